@@ -0,0 +1,8 @@
+// Generates the gRPC service for `--features grpc` builds (see src/grpc.rs);
+// `protoc` must be installed for those.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/relayer.proto");
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/relayer.proto")?;
+    Ok(())
+}
@@ -0,0 +1,82 @@
+//! `api-gateway --all-in-one [<dir>]` (`--features all-in-one`): the whole
+//! relayer in one process on port 3001, to try it without ScyllaDB, Docker
+//! or a terminal per service:
+//!
+//! - the gateway in test mode on the `memory` store (see `store`): health
+//!   and the transaction routes, nothing kept past the process
+//! - the signaling server (the `signaling-server` crate) at `/signaling`
+//! - the wasm endpoint app from `<dir>` (`../ws-tx-endpoint`) at `/`,
+//!   served as `serve-web` serves it, its `config.js` pointing it at the
+//!   two above
+//!
+//! The app is built separately (`wasm-pack build --target web` in
+//! `ws-tx-endpoint`); until it is, the API and signaling run alone.
+//!
+//! ```sh
+//! cd api-gateway && cargo run --features all-in-one -- --all-in-one
+//! ```
+
+use std::collections::HashMap;
+use std::path::Path;
+use tracing::{info, warn};
+
+use crate::store::{self, Backend};
+use crate::web::{self, Bundle, Policy, RuntimeConfig};
+
+const PORT: u16 = 3001;
+/// Where the app is from `cargo run` in `api-gateway/`.
+const DEFAULT_APP_DIR: &str = "../ws-tx-endpoint";
+/// The app's pages and worker; the rest of its folder is source.
+const APP_FILES: &[&str] = &["index.html", "widget.html", "tx-worker.js"];
+
+/// The app's files as its Dockerfile assembles them: the pages, the
+/// worker and `wasm-pack`'s `pkg/`.
+fn app_files(dir: &Path) -> Result<HashMap<String, Vec<u8>>, Box<dyn std::error::Error>> {
+    let pkg = dir.join("pkg");
+    if !pkg.is_dir() {
+        return Err(format!("no pkg/ in {}; run `wasm-pack build --target web` there", dir.display()).into());
+    }
+    let mut files = HashMap::new();
+    web::read_dir(dir, &pkg, &mut files)?;
+    for name in APP_FILES {
+        files.insert(name.to_string(), std::fs::read(dir.join(name))?);
+    }
+    Ok(files)
+}
+
+pub async fn serve(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let app_dir = args
+        .iter()
+        .skip_while(|arg| *arg != "--all-in-one")
+        .nth(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map_or(DEFAULT_APP_DIR, String::as_str);
+    let origin = format!("localhost:{}", PORT);
+    warn!("All-in-one dev mode: transactions are kept in memory, and only the transaction routes are served");
+
+    let mut signaling_config = signaling_server::Config::from_env();
+    // Its endpoint status lookups find nothing here, and fail open
+    signaling_config.api_gateway = format!("http://{}", origin);
+    let signaling = signaling_server::AppState::new(signaling_config)?;
+    tokio::spawn(signaling.clone().run_reload());
+
+    let mut app = store::standalone_router(store::standalone(Backend::Memory)?)
+        .nest("/signaling", signaling_server::router(signaling));
+
+    let config = RuntimeConfig {
+        api_gateway: None,
+        signaling_server: Some(format!("ws://{}/signaling", origin)),
+        same_origin_api: true,
+    };
+    let policy = Policy::from_env(&config);
+    match app_files(Path::new(app_dir)).and_then(|files| Bundle::from_files(files, &policy, &config, app_dir)) {
+        Ok(bundle) => app = app.fallback_service(web::router(bundle)),
+        Err(e) => warn!("Not serving the endpoint app: {}", e),
+    }
+
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", PORT)).await?;
+    info!("🚀 Relayer running on http://{}/", origin);
+    info!("📡 Signaling on ws://{}/signaling", origin);
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
+    Ok(())
+}
@@ -0,0 +1,249 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use scylla::frame::response::result::Row;
+use scylla::Session;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use tx_core::{Money, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::db;
+use crate::endpoints::{self, EndpointStatus};
+use crate::funding::DepositRequest;
+use crate::repository::Repository;
+use crate::{endpoint_stats_as_of, lwt_applied, stats_error, stored_money, AppState};
+
+/// Holdings in assets other than the native one. Native balances come from
+/// the transaction ledger; every other asset is tracked here as signed
+/// entries per endpoint, and only moves through deposits and swaps.
+/// Amounts are exact minor units of the asset, written as major units.
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct AssetBalance {
+    pub asset: String,
+    #[serde(serialize_with = "tx_core::money::as_major::serialize")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub balance: Money,
+    /// Locked by swaps that have not settled yet.
+    #[serde(serialize_with = "tx_core::money::as_major::serialize")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub held: Money,
+}
+
+impl AssetBalance {
+    fn empty(asset: &str) -> Self {
+        AssetBalance { asset: asset.to_string(), balance: Money::zero(asset), held: Money::zero(asset) }
+    }
+}
+
+fn db_error(e: impl std::fmt::Display) -> StatusCode {
+    error!("Asset ledger query failed: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// Records a ledger entry. Entries are keyed by `entry_id`, so writing the
+/// same entry again is a no-op.
+pub async fn record_entry(session: &Session, endpoint_id: &str, entry_id: Uuid, delta: &Money) -> Result<(), StatusCode> {
+    session
+        .query(
+            "INSERT INTO transactions.asset_ledger (endpoint_id, asset, entry_id, delta_minor, at) VALUES (?, ?, ?, ?, ?)",
+            (endpoint_id, delta.currency(), entry_id, delta.minor(), Utc::now().timestamp_millis()),
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+/// How long a hold claim lasts if its holder never releases it.
+const HOLD_CLAIM_TTL_SECS: i32 = 10;
+const HOLD_CLAIM_ATTEMPTS: u32 = 20;
+const HOLD_CLAIM_RETRY: Duration = Duration::from_millis(50);
+
+/// Places a hold for `amount` only if the endpoint has that much available,
+/// 422 if not. The check and the hold run under a per-endpoint, per-asset
+/// claim, so concurrent holds on one account are serialized; 409 if the
+/// claim stays taken. Endpoints the ledger doesn't know aren't checked.
+pub async fn hold_if_available(
+    session: &Repository,
+    endpoint_id: &str,
+    hold_id: Uuid,
+    amount: &Money,
+) -> Result<(), StatusCode> {
+    let asset = amount.currency();
+    let holder = claim_holds(session, endpoint_id, asset).await?;
+    let held = async {
+        if let Some(available) = available(session, endpoint_id, asset).await? {
+            if available.checked_sub(amount).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?.is_negative() {
+                info!("{} has {} available, needs {}", endpoint_id, available, amount);
+                return Err(StatusCode::UNPROCESSABLE_ENTITY);
+            }
+        }
+        place_hold(session, endpoint_id, hold_id, amount).await
+    }
+    .await;
+
+    // Left to expire if this fails
+    if let Err(e) = session
+        .query(
+            "DELETE FROM transactions.asset_hold_claims WHERE endpoint_id = ? AND asset = ? IF holder = ?",
+            (endpoint_id, asset, holder),
+        )
+        .await
+    {
+        warn!("Failed to release the hold claim on {} {}: {}", endpoint_id, asset, e);
+    }
+    held
+}
+
+async fn claim_holds(session: &Session, endpoint_id: &str, asset: &str) -> Result<Uuid, StatusCode> {
+    let holder = Uuid::new_v4();
+    for _ in 0..HOLD_CLAIM_ATTEMPTS {
+        let claimed = session
+            .query(
+                "INSERT INTO transactions.asset_hold_claims (endpoint_id, asset, holder) VALUES (?, ?, ?)
+                 IF NOT EXISTS USING TTL ?",
+                (endpoint_id, asset, holder, HOLD_CLAIM_TTL_SECS),
+            )
+            .await
+            .map_err(db_error)?;
+        if lwt_applied(claimed) {
+            return Ok(holder);
+        }
+        tokio::time::sleep(HOLD_CLAIM_RETRY).await;
+    }
+    warn!("Holds on {} {} stayed claimed", endpoint_id, asset);
+    Err(StatusCode::CONFLICT)
+}
+
+async fn place_hold(session: &Session, endpoint_id: &str, hold_id: Uuid, amount: &Money) -> Result<(), StatusCode> {
+    session
+        .query(
+            "INSERT INTO transactions.asset_holds (endpoint_id, asset, hold_id, amount_minor) VALUES (?, ?, ?, ?)",
+            (endpoint_id, amount.currency(), hold_id, amount.minor()),
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+pub async fn release_hold(session: &Session, endpoint_id: &str, asset: &str, hold_id: Uuid) -> Result<(), StatusCode> {
+    session
+        .query(
+            "DELETE FROM transactions.asset_holds WHERE endpoint_id = ? AND asset = ? AND hold_id = ?",
+            (endpoint_id, asset, hold_id),
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(())
+}
+
+/// Adds up `(asset, double, minor)` rows by asset, into `field` of each
+/// asset's balance.
+fn add_rows(
+    balances: &mut BTreeMap<String, AssetBalance>,
+    rows: Vec<Row>,
+    field: fn(&mut AssetBalance) -> &mut Money,
+) -> Result<(), StatusCode> {
+    for row in rows {
+        let (asset, major, minor) = row.into_typed::<(String, Option<f64>, Option<i64>)>().map_err(db_error)?;
+        let amount = stored_money(minor, major, &asset).map_err(db_error)?;
+        let balance = balances.entry(asset.clone()).or_insert_with(|| AssetBalance::empty(&asset));
+        let total = field(balance);
+        *total = total.checked_add(&amount).map_err(db_error)?;
+    }
+    Ok(())
+}
+
+/// `SELECT asset, <columns>` from `table` for `endpoint_id`, and `asset`
+/// if given.
+async fn select(
+    session: &Session,
+    columns: &str,
+    table: &str,
+    endpoint_id: &str,
+    asset: Option<&str>,
+) -> Result<Vec<Row>, StatusCode> {
+    let query = format!("SELECT asset, {} FROM transactions.{} WHERE endpoint_id = ?", columns, table);
+    let result = match asset {
+        Some(asset) => session.query(db::idempotent(query + " AND asset = ?"), (endpoint_id, asset)).await,
+        None => session.query(db::idempotent(query), (endpoint_id,)).await,
+    };
+    Ok(result.map_err(db_error)?.rows.unwrap_or_default())
+}
+
+/// `endpoint_id`'s balances, of `asset` only if given.
+async fn balances(session: &Session, endpoint_id: &str, asset: Option<&str>) -> Result<BTreeMap<String, AssetBalance>, StatusCode> {
+    let mut balances = BTreeMap::new();
+    let entries = select(session, "delta, delta_minor", "asset_ledger", endpoint_id, asset).await?;
+    add_rows(&mut balances, entries, |balance| &mut balance.balance)?;
+    let holds = select(session, "amount, amount_minor", "asset_holds", endpoint_id, asset).await?;
+    add_rows(&mut balances, holds, |balance| &mut balance.held)?;
+    Ok(balances)
+}
+
+async fn balance_of(session: &Session, endpoint_id: &str, asset: &str) -> Result<AssetBalance, StatusCode> {
+    Ok(balances(session, endpoint_id, Some(asset))
+        .await?
+        .remove(asset)
+        .unwrap_or_else(|| AssetBalance::empty(asset)))
+}
+
+/// What `endpoint_id` can still commit of `asset`: its balance less open
+/// holds. `None` for the native asset of an endpoint the gateway has no
+/// record of, whose balance it can't know.
+pub async fn available(session: &Repository, endpoint_id: &str, asset: &str) -> Result<Option<Money>, StatusCode> {
+    let mut balance = balance_of(session, endpoint_id, asset).await?;
+    if asset == NATIVE_ASSET {
+        let Some(endpoint) = endpoints::load_endpoint(session, endpoint_id).await? else {
+            return Ok(None);
+        };
+        balance.balance = endpoint_stats_as_of(session, endpoint_id, None)
+            .await?
+            .balance_from(&endpoint.initial_balance)
+            .map_err(stats_error)?;
+    }
+    balance.balance.checked_sub(&balance.held).map(Some).map_err(stats_error)
+}
+
+/// `GET /api/endpoints/:id/assets`
+pub async fn get_assets(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+) -> Result<Json<Vec<AssetBalance>>, StatusCode> {
+    let balances = balances(&state.session, &endpoint_id, None).await?;
+    Ok(Json(balances.into_values().collect()))
+}
+
+/// `POST /api/endpoints/:id/assets/:asset/deposits`: credits a non-native
+/// asset, the counterpart of `funding::create_deposit`.
+pub async fn create_asset_deposit(
+    State(state): State<AppState>,
+    Path((endpoint_id, asset)): Path<(String, String)>,
+    Json(request): Json<DepositRequest>,
+) -> Result<(StatusCode, Json<AssetBalance>), StatusCode> {
+    if asset == NATIVE_ASSET || asset.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let amount = Money::from_major(request.amount, &asset).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if amount.is_negative() || amount.is_zero() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    if let Some(endpoint) = endpoints::load_endpoint(&state.session, &endpoint_id).await? {
+        if endpoint.status == EndpointStatus::Closed {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+
+    record_entry(&state.session, &endpoint_id, Uuid::new_v4(), &amount).await?;
+    let balance = balance_of(&state.session, &endpoint_id, &asset).await?;
+
+    info!("✅ Deposited {} to {}", amount, endpoint_id);
+    Ok((StatusCode::CREATED, Json(balance)))
+}
@@ -0,0 +1,110 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::db;
+use crate::{pii, timestamp_from_millis, AppState};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: String,
+    pub entity_id: String,
+    pub action: String,
+    pub actor: String,
+    pub details: Option<String>,
+    pub at: DateTime<Utc>,
+}
+
+/// Appends an entry to the audit trail of `entity_id` (a transaction id,
+/// endpoint id, ...). `actor` identifies the reviewer or subsystem, and
+/// is the tenant its details are encrypted for.
+pub async fn record(
+    session: &Session,
+    entity_id: &str,
+    action: &str,
+    actor: &str,
+    details: Option<String>,
+) -> Result<(), StatusCode> {
+    let details = pii::seal_opt(session, actor, details.as_deref()).await?;
+    session
+        .query(
+            "INSERT INTO transactions.audit_log (entity_id, at, id, action, actor, details)
+             VALUES (?, ?, ?, ?, ?, ?)",
+            (
+                entity_id,
+                Utc::now().timestamp_millis(),
+                Uuid::new_v4(),
+                action,
+                actor,
+                details,
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to write audit entry for {}: {}", entity_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(())
+}
+
+pub async fn load_trail(session: &Session, entity_id: &str) -> Result<Vec<AuditEntry>, StatusCode> {
+    let rows = session
+        .query(
+            db::idempotent("SELECT entity_id, at, id, action, actor, details
+             FROM transactions.audit_log WHERE entity_id = ?"),
+            (entity_id,),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut entries = Vec::new();
+    if let Some(rows) = rows.rows {
+        for row in rows {
+            if let Ok((entity_id, at, id, action, actor, details)) =
+                row.into_typed::<(String, i64, Uuid, String, String, Option<String>)>() {
+                let details = pii::open_opt(session, &actor, details).await?;
+                entries.push(AuditEntry {
+                    id: id.to_string(),
+                    entity_id,
+                    action,
+                    actor,
+                    details,
+                    at: timestamp_from_millis(at),
+                });
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// Replaces an entry's details, keeping who did what when. For erasing
+/// personal data an entry quoted.
+pub async fn redact(session: &Session, entry: &AuditEntry, details: &str) -> Result<(), StatusCode> {
+    let id = Uuid::parse_str(&entry.id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    session
+        .query(
+            db::idempotent("UPDATE transactions.audit_log SET details = ? WHERE entity_id = ? AND at = ? AND id = ?"),
+            (details, &entry.entity_id, entry.at.timestamp_millis(), id),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to redact audit entry {} of {}: {}", entry.id, entry.entity_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(())
+}
+
+pub async fn get_audit_trail(
+    State(state): State<AppState>,
+    Path(entity_id): Path<String>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    load_trail(&state.session, &entity_id).await.map(Json)
+}
@@ -0,0 +1,413 @@
+//! Bearer-token authentication. Once `JWT_SECRET` is set, read through
+//! the secrets provider so it rotates like the others, every route outside
+//! a few public ones needs an HS256 JWT in `Authorization: Bearer`. The
+//! WebSocket and event-stream routes also take it as `?access_token=`,
+//! since browsers can't set headers on those. Without the secret the
+//! gateway stays open, as before, and says so at startup.
+//!
+//! Tokens carry `roles` (`reader`, `writer`, `admin`), each including the
+//! ones before it. Reads need `reader` and any other method `writer`.
+//! Operator actions need `admin`: reviews, suspensions, breaker
+//! overrides, dispute escalations and rulings, batching settings,
+//! personal data erasure, key-usage reports and `/api/admin`. When
+//! set, `JWT_ISSUER` and `JWT_AUDIENCE` must match the token's `iss` and
+//! `aud`.
+//!
+//! Credentials that fail to verify are counted, delayed and eventually
+//! locked out per client IP and third party, see `lockout`.
+//!
+//! Callers on the mTLS listener are already authenticated and pass as
+//! writers. A request with `X-API-Key` is a third party's and is checked
+//! against its consents instead, see `consents`.
+//!
+//! `POST /api/auth/token` issues test tokens when `AUTH_DEV_TOKENS=true`;
+//! it is 404 otherwise.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::consents;
+use crate::lockout;
+use crate::mtls::PeerIdentity;
+use crate::ratelimit;
+use crate::secrets::{Secret, Secrets};
+use crate::AppState;
+
+const SECRET_NAME: &str = "JWT_SECRET";
+const DEFAULT_TOKEN_TTL_SECS: i64 = 3600;
+/// Longest a dev token may live.
+const MAX_TOKEN_TTL_SECS: i64 = 86_400;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Reader,
+    Writer,
+    Admin,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    pub exp: i64,
+    #[serde(default)]
+    pub iat: i64,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+}
+
+impl Claims {
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.iter().any(|granted| *granted >= role)
+    }
+}
+
+#[derive(Clone)]
+pub struct Auth {
+    issuer: Option<String>,
+    audience: Option<String>,
+    dev_tokens: bool,
+}
+
+impl Auth {
+    pub async fn from_env(secrets: &Secrets) -> Result<Self, Box<dyn std::error::Error>> {
+        let auth = Auth {
+            issuer: std::env::var("JWT_ISSUER").ok().filter(|v| !v.is_empty()),
+            audience: std::env::var("JWT_AUDIENCE").ok().filter(|v| !v.is_empty()),
+            dev_tokens: std::env::var("AUTH_DEV_TOKENS").is_ok_and(|v| v == "true"),
+        };
+        if secrets.get(SECRET_NAME).await?.is_some() {
+            info!("JWT authentication on");
+            if auth.dev_tokens {
+                warn!("AUTH_DEV_TOKENS is on: anyone can issue tokens at /api/auth/token");
+            }
+        } else {
+            warn!("{} not set: gateway routes are unauthenticated", SECRET_NAME);
+        }
+        Ok(auth)
+    }
+
+    fn validation(&self) -> Validation {
+        let mut validation = Validation::new(Algorithm::HS256);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        validation
+    }
+}
+
+/// The role a request needs, `None` for public routes.
+fn required_role(method: &Method, path: &str) -> Option<Role> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["health"] | ["leader"] | ["api", ".well-known", "keys"] | ["api", "receipts", "key"] | ["api", "auth", "token"] => {
+            None
+        }
+        // Authenticated by the provider's webhook secret
+        ["api", "settlements", _, "confirmation"] => None,
+        // The embedded client apps' pages
+        #[cfg(feature = "embed-web")]
+        _ if crate::embedded::serves(path) => None,
+        // Queries only; there are no mutations
+        ["api", "graphql", ..] => Some(Role::Reader),
+        ["api", "admin", ..] => Some(Role::Admin),
+        ["api", "review-queue", _, "approve" | "reject"]
+        | ["api", "endpoints", _, "suspend" | "activate" | "close" | "personal-data" | "key" | "key-usage"]
+        | ["api", "endpoints", _, "breaker", "override"]
+        | ["api", "disputes", _, "escalate" | "resolve"] => Some(Role::Admin),
+        ["api", "rooms", _, "batching"] if method == Method::PUT => Some(Role::Admin),
+        // Credit funds; writers ask through `top-up-requests` instead.
+        // Provisioning sets an initial balance and registers signing keys.
+        ["api", "endpoints", _, "deposits"] | ["api", "endpoints", _, "assets", _, "deposits"]
+            if method == Method::POST =>
+        {
+            Some(Role::Admin)
+        }
+        ["api", "endpoints"] if method == Method::POST => Some(Role::Admin),
+        _ if method == Method::GET || method == Method::HEAD || method == Method::OPTIONS => Some(Role::Reader),
+        _ => Some(Role::Writer),
+    }
+}
+
+/// Routes browsers open without being able to set headers.
+const QUERY_TOKEN_PATHS: &[&str] = &["/api/ws", "/api/graphql/ws", "/api/transactions/stream"];
+
+fn bearer_token(request: &Request) -> Option<String> {
+    if let Some(value) = request.headers().get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) {
+        return value.strip_prefix("Bearer ").map(|token| token.trim().to_string());
+    }
+    if !QUERY_TOKEN_PATHS.contains(&request.uri().path()) {
+        return None;
+    }
+    request
+        .uri()
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("access_token="))
+        .map(str::to_string)
+}
+
+fn unauthorized(reason: &str) -> Response {
+    (StatusCode::UNAUTHORIZED, [(header::WWW_AUTHENTICATE, "Bearer")], reason.to_string()).into_response()
+}
+
+async fn secret(secrets: &Secrets) -> Result<Option<Secret>, StatusCode> {
+    secrets.get(SECRET_NAME).await.map_err(|e| {
+        warn!("Reading {} failed: {}", SECRET_NAME, e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })
+}
+
+/// Checks the bearer token against the route's role and hands the
+/// `Claims` on to handlers.
+pub async fn authenticate(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let ip = ratelimit::request_ip(&request);
+    let Some(role) = required_role(request.method(), request.uri().path()) else {
+        if request.uri().path() == "/api/auth/token" {
+            if let Err(locked) = lockout::check(&lockout::keys(ip.as_deref(), None)) {
+                return locked.into_response();
+            }
+        }
+        return next.run(request).await;
+    };
+    if let Some(api_key) = request.headers().get(consents::API_KEY_HEADER) {
+        let api_key = api_key.to_str().unwrap_or_default().to_string();
+        let keys = lockout::keys(ip.as_deref(), consents::key_owner(&api_key));
+        if let Err(locked) = lockout::check(&keys) {
+            return locked.into_response();
+        }
+        return match consents::authorize(&state.session, request.method(), request.uri(), &api_key).await {
+            Ok(access) => {
+                lockout::succeeded(&keys);
+                request.extensions_mut().insert(access);
+                next.run(request).await
+            }
+            Err(StatusCode::UNAUTHORIZED) => {
+                lockout::failed(&keys).await;
+                StatusCode::UNAUTHORIZED.into_response()
+            }
+            // The key is the third party's; it just can't read this
+            Err(StatusCode::FORBIDDEN) => {
+                lockout::succeeded(&keys);
+                StatusCode::FORBIDDEN.into_response()
+            }
+            Err(status) => status.into_response(),
+        };
+    }
+    if let Some(PeerIdentity(peer)) = request.extensions().get::<PeerIdentity>() {
+        if role <= Role::Writer {
+            debug!("{} {} from relay {}", request.method(), request.uri().path(), peer);
+            return next.run(request).await;
+        }
+    }
+    match check_token(&state, bearer_token(&request).as_deref(), ip.as_deref(), role).await {
+        Ok(Some(claims)) => {
+            request.extensions_mut().insert(claims);
+            next.run(request).await
+        }
+        Ok(None) => next.run(request).await,
+        Err(response) => response,
+    }
+}
+
+/// The claims of a bearer `token` that carries `role`, or `None` while
+/// the gateway is open. Failures count towards `ip`'s lockout. Shared
+/// with the gRPC service, which reads the token from its metadata.
+pub async fn check_token(
+    state: &AppState,
+    token: Option<&str>,
+    ip: Option<&str>,
+    role: Role,
+) -> Result<Option<Claims>, Response> {
+    let key = match secret(&state.secrets).await {
+        Ok(Some(key)) => key,
+        Ok(None) => return Ok(None),
+        Err(status) => return Err(status.into_response()),
+    };
+
+    let Some(token) = token else {
+        return Err(unauthorized("Bearer token required"));
+    };
+    let keys = lockout::keys(ip, None);
+    lockout::check(&keys).map_err(IntoResponse::into_response)?;
+    let claims = match jsonwebtoken::decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(key.expose().as_bytes()),
+        &state.auth.validation(),
+    ) {
+        Ok(data) => data.claims,
+        Err(e) => {
+            debug!("Rejected token: {}", e);
+            lockout::failed(&keys).await;
+            return Err(unauthorized("Invalid or expired token"));
+        }
+    };
+    lockout::succeeded(&keys);
+    if !claims.has_role(role) {
+        return Err((StatusCode::FORBIDDEN, format!("{:?} role required", role).to_lowercase()).into_response());
+    }
+    Ok(Some(claims))
+}
+
+/// Who is acting on an operator route: the token's subject, or the
+/// `header_name` callers named themselves in while the gateway is open.
+pub fn actor(claims: Option<&Claims>, headers: &HeaderMap, header_name: &str) -> Result<String, StatusCode> {
+    if let Some(claims) = claims {
+        return Ok(claims.sub.clone());
+    }
+    headers
+        .get(header_name)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| !v.trim().is_empty())
+        .map(str::to_string)
+        .ok_or(StatusCode::UNAUTHORIZED)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TokenRequest {
+    pub sub: String,
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    pub ttl_secs: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TokenResponse {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// `POST /api/auth/token`: a signed token for whoever asks, for trying
+/// the gateway out. Roles default to `reader`.
+pub async fn issue_token(
+    State(state): State<AppState>,
+    Json(request): Json<TokenRequest>,
+) -> Result<Json<TokenResponse>, StatusCode> {
+    if !state.auth.dev_tokens {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let key = secret(&state.secrets).await?.ok_or(StatusCode::SERVICE_UNAVAILABLE)?;
+    if request.sub.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = Utc::now();
+    let ttl = request.ttl_secs.unwrap_or(DEFAULT_TOKEN_TTL_SECS).clamp(1, MAX_TOKEN_TTL_SECS);
+    let expires_at = now + Duration::seconds(ttl);
+    let roles = if request.roles.is_empty() { vec![Role::Reader] } else { request.roles };
+    let claims = Claims {
+        sub: request.sub,
+        iss: state.auth.issuer.clone(),
+        aud: state.auth.audience.clone(),
+        exp: expires_at.timestamp(),
+        iat: now.timestamp(),
+        roles,
+    };
+    let token = jsonwebtoken::encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(key.expose().as_bytes()))
+        .map_err(|e| {
+            warn!("Token signing failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Issued a dev token for {} ({:?})", claims.sub, claims.roles);
+    Ok(Json(TokenResponse { token, expires_at }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn claims(roles: Vec<Role>) -> Claims {
+        Claims { sub: "ops".into(), iss: None, aud: None, exp: 0, iat: 0, roles }
+    }
+
+    fn get(uri: &str) -> Request {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn roles_include_the_ones_before_them() {
+        let admin = claims(vec![Role::Admin]);
+        assert!(admin.has_role(Role::Reader) && admin.has_role(Role::Writer) && admin.has_role(Role::Admin));
+        let writer = claims(vec![Role::Writer]);
+        assert!(writer.has_role(Role::Reader) && writer.has_role(Role::Writer));
+        assert!(!writer.has_role(Role::Admin));
+        assert!(!claims(Vec::new()).has_role(Role::Reader));
+    }
+
+    #[test]
+    fn reads_need_reader_and_writes_writer() {
+        assert_eq!(required_role(&Method::GET, "/api/transactions"), Some(Role::Reader));
+        assert_eq!(required_role(&Method::OPTIONS, "/api/transactions"), Some(Role::Reader));
+        assert_eq!(required_role(&Method::POST, "/api/transactions"), Some(Role::Writer));
+        assert_eq!(required_role(&Method::DELETE, "/api/endpoints/alice/consents/1"), Some(Role::Writer));
+        assert_eq!(required_role(&Method::POST, "/api/graphql"), Some(Role::Reader));
+    }
+
+    #[test]
+    fn public_routes_need_nothing() {
+        for path in ["/health", "/leader", "/api/.well-known/keys", "/api/receipts/key", "/api/settlements/s1/confirmation"] {
+            assert_eq!(required_role(&Method::GET, path), None, "{}", path);
+        }
+        assert_eq!(required_role(&Method::POST, "/api/auth/token"), None);
+    }
+
+    #[test]
+    fn operator_actions_need_admin() {
+        for (method, path) in [
+            (Method::GET, "/api/admin/reports"),
+            (Method::POST, "/api/review-queue/1/approve"),
+            (Method::POST, "/api/endpoints/alice/suspend"),
+            (Method::DELETE, "/api/endpoints/alice/personal-data"),
+            (Method::PUT, "/api/endpoints/alice/key"),
+            (Method::GET, "/api/endpoints/alice/key-usage"),
+            (Method::POST, "/api/endpoints/alice/breaker/override"),
+            (Method::POST, "/api/disputes/d1/resolve"),
+            (Method::PUT, "/api/rooms/r1/batching"),
+        ] {
+            assert_eq!(required_role(&method, path), Some(Role::Admin), "{} {}", method, path);
+        }
+        assert_eq!(required_role(&Method::GET, "/api/rooms/r1/batching"), Some(Role::Reader));
+    }
+
+    #[test]
+    fn query_tokens_only_on_browser_streams() {
+        assert_eq!(bearer_token(&get("/api/ws?access_token=abc")).as_deref(), Some("abc"));
+        assert_eq!(bearer_token(&get("/api/transactions/stream?x=1&access_token=abc")).as_deref(), Some("abc"));
+        assert_eq!(bearer_token(&get("/api/graphql/ws?access_token=abc")).as_deref(), Some("abc"));
+        assert_eq!(bearer_token(&get("/api/transactions?access_token=abc")), None);
+        assert_eq!(bearer_token(&get("/api/admin/reports?access_token=abc")), None);
+
+        let mut request = get("/api/transactions");
+        request.headers_mut().insert(header::AUTHORIZATION, "Bearer xyz".parse().unwrap());
+        assert_eq!(bearer_token(&request).as_deref(), Some("xyz"));
+    }
+
+    #[test]
+    fn only_admins_credit_funds() {
+        for path in ["/api/endpoints", "/api/endpoints/alice/deposits", "/api/endpoints/alice/assets/EUR/deposits"] {
+            assert_eq!(required_role(&Method::POST, path), Some(Role::Admin), "{}", path);
+        }
+        assert_eq!(required_role(&Method::POST, "/api/endpoints/alice/top-up-requests"), Some(Role::Writer));
+        assert_eq!(required_role(&Method::POST, "/api/admin/top-up-requests/1/approve"), Some(Role::Admin));
+        assert_eq!(required_role(&Method::GET, "/api/endpoints/alice/assets"), Some(Role::Reader));
+    }
+}
@@ -0,0 +1,359 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use tracing::{error, info};
+use tx_core::{Money, MoneyError, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::db;
+use crate::repository::Repository;
+use crate::{
+    insert_transaction, load_transaction, lwt_applied, signatures, timestamp_from_millis, AppState, Transaction,
+    TransactionKind, TransactionStatus,
+};
+
+/// Bounds on a room's batch window.
+const MIN_WINDOW_MS: i64 = 1_000;
+const MAX_WINDOW_MS: i64 = 3_600_000;
+
+/// Upper bound on the transactions settled in one window.
+const MAX_BATCH_TRANSACTIONS: usize = 10_000;
+
+/// Batch settlement for chatty rooms. Instead of every endpoint recording
+/// each transfer, the signaling server buffers a batching room's
+/// transactions and posts them at the window boundary. The gateway nets
+/// them per pair of endpoints and records only one transfer per pair, plus
+/// a report of the window listing what each transfer settled.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct RoomBatching {
+    pub room_id: String,
+    /// `None` when the room settles transactions one by one.
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub window_ms: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct UpdateBatchingRequest {
+    pub window_ms: Option<i64>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BatchRequest {
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+    pub transactions: Vec<Transaction>,
+}
+
+/// One net transfer of a window.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct PairSettlement {
+    pub transaction_id: String,
+    pub from_endpoint: String,
+    pub to_endpoint: String,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub amount: Money,
+    /// Ids of the room transactions this transfer settles, both directions.
+    pub settles: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct BatchReport {
+    pub batch_id: String,
+    pub room_id: String,
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+    pub transaction_count: i32,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub gross_volume: Money,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub net_volume: Money,
+    pub settlements: Vec<PairSettlement>,
+    pub settled_at: DateTime<Utc>,
+}
+
+fn db_error(e: impl std::fmt::Display) -> StatusCode {
+    error!("Batch settlement query failed: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+async fn load_window(session: &Session, room_id: &str) -> Result<Option<i64>, StatusCode> {
+    Ok(session
+        .query(
+            db::idempotent("SELECT batch_window_ms FROM transactions.room_settings WHERE room_id = ?"),
+            (room_id,),
+        )
+        .await
+        .map_err(db_error)?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(Option<i64>,)>().ok())
+        .and_then(|(window_ms,)| window_ms))
+}
+
+/// `GET /api/rooms/:id/batching`
+pub async fn get_batching(
+    State(state): State<AppState>,
+    Path(room_id): Path<String>,
+) -> Result<Json<RoomBatching>, StatusCode> {
+    let window_ms = load_window(&state.session, &room_id).await?;
+    Ok(Json(RoomBatching { room_id, window_ms }))
+}
+
+/// `PUT /api/rooms/:id/batching`: sets the window, or turns batching off
+/// with a `null` one. Takes effect at the room's next window.
+pub async fn update_batching(
+    State(state): State<AppState>,
+    Path(room_id): Path<String>,
+    Json(request): Json<UpdateBatchingRequest>,
+) -> Result<Json<RoomBatching>, StatusCode> {
+    if let Some(window_ms) = request.window_ms {
+        if !(MIN_WINDOW_MS..=MAX_WINDOW_MS).contains(&window_ms) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+
+    state
+        .session
+        .query(
+            "INSERT INTO transactions.room_settings (room_id, batch_window_ms, updated_at) VALUES (?, ?, ?)",
+            (&room_id, request.window_ms, Utc::now().timestamp_millis()),
+        )
+        .await
+        .map_err(db_error)?;
+
+    info!("Room {} batch window set to {:?}ms", room_id, request.window_ms);
+    Ok(Json(RoomBatching { room_id, window_ms: request.window_ms }))
+}
+
+fn validate(request: &BatchRequest) -> Result<(), StatusCode> {
+    if request.window_end <= request.window_start || request.transactions.len() > MAX_BATCH_TRANSACTIONS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut ids = HashSet::new();
+    for tx in &request.transactions {
+        Uuid::parse_str(&tx.id).map_err(|_| StatusCode::BAD_REQUEST)?;
+        if !ids.insert(tx.id.as_str())
+            || tx.kind != TransactionKind::Transfer
+            || tx.from_endpoint == tx.to_endpoint
+            || tx.amount.is_negative()
+            || tx.amount.is_zero()
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    Ok(())
+}
+
+/// Nets the window's transactions per unordered pair of endpoints. Pairs
+/// that cancel out exactly get no transfer; their transactions only count
+/// towards the gross volume.
+fn net_per_pair(transactions: &[Transaction]) -> Result<Vec<PairSettlement>, MoneyError> {
+    let mut pairs: BTreeMap<(&str, &str), (Money, Vec<String>)> = BTreeMap::new();
+    for tx in transactions {
+        let (from, to) = (tx.from_endpoint.as_str(), tx.to_endpoint.as_str());
+        // Keyed low to high; positive means low pays high
+        let (key, signed) = if from < to {
+            ((from, to), tx.amount.clone())
+        } else {
+            ((to, from), tx.amount.checked_neg()?)
+        };
+        let entry = pairs.entry(key).or_insert_with(|| (Money::zero(NATIVE_ASSET), Vec::new()));
+        entry.0 = entry.0.checked_add(&signed)?;
+        entry.1.push(tx.id.clone());
+    }
+
+    let mut settlements = Vec::new();
+    for ((low, high), (net, settles)) in pairs {
+        if net.is_zero() {
+            continue;
+        }
+        let (from, to, amount) = if net.is_negative() { (high, low, net.checked_neg()?) } else { (low, high, net) };
+        settlements.push(PairSettlement {
+            transaction_id: Uuid::new_v4().to_string(),
+            from_endpoint: from.to_string(),
+            to_endpoint: to.to_string(),
+            amount,
+            settles,
+        });
+    }
+    Ok(settlements)
+}
+
+fn total<'a>(amounts: impl IntoIterator<Item = &'a Money>) -> Result<Money, MoneyError> {
+    amounts.into_iter().try_fold(Money::zero(NATIVE_ASSET), |total, amount| total.checked_add(amount))
+}
+
+type ReportRow = (Uuid, i64, i64, i32, f64, f64, String, i64);
+
+fn report_from_row(room_id: &str, row: ReportRow) -> Result<BatchReport, StatusCode> {
+    let (batch_id, window_start, window_end, transaction_count, gross_volume, net_volume, settlements, settled_at) =
+        row;
+    Ok(BatchReport {
+        batch_id: batch_id.to_string(),
+        room_id: room_id.to_string(),
+        window_start: timestamp_from_millis(window_start),
+        window_end: timestamp_from_millis(window_end),
+        transaction_count,
+        gross_volume: Money::from_major(gross_volume, NATIVE_ASSET).map_err(db_error)?,
+        net_volume: Money::from_major(net_volume, NATIVE_ASSET).map_err(db_error)?,
+        settlements: serde_json::from_str(&settlements).map_err(db_error)?,
+        settled_at: timestamp_from_millis(settled_at),
+    })
+}
+
+async fn load_report(session: &Session, room_id: &str, window_start: i64) -> Result<Option<BatchReport>, StatusCode> {
+    let row = session
+        .query(
+            db::idempotent("SELECT batch_id, window_start, window_end, transaction_count, gross_volume, net_volume, settlements, settled_at
+             FROM transactions.batch_reports WHERE room_id = ? AND window_start = ?"),
+            (room_id, window_start),
+        )
+        .await
+        .map_err(db_error)?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<ReportRow>().ok());
+    row.map(|row| report_from_row(room_id, row)).transpose()
+}
+
+/// Records the report's transfers that aren't in the log yet.
+async fn record_transfers(session: &Repository, report: &BatchReport) -> Result<(), StatusCode> {
+    for settlement in &report.settlements {
+        let id = Uuid::parse_str(&settlement.transaction_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        if load_transaction(session, id).await?.is_some() {
+            continue;
+        }
+        let transfer = Transaction {
+            id: settlement.transaction_id.clone(),
+            from_endpoint: settlement.from_endpoint.clone(),
+            to_endpoint: settlement.to_endpoint.clone(),
+            amount: settlement.amount.clone(),
+            timestamp: report.window_end,
+            signature: String::new(),
+            status: TransactionStatus::Confirmed,
+            kind: TransactionKind::Transfer,
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: None,
+            public_key: None,
+        };
+        insert_transaction(session, &transfer).await?;
+    }
+    Ok(())
+}
+
+/// `POST /api/rooms/:id/batches`: settles one window of a batching room.
+/// The report is written first and claims the window; posting the same
+/// window again returns its report (200) after recording any transfer a
+/// failed earlier attempt left out.
+pub async fn settle_batch(
+    State(state): State<AppState>,
+    Path(room_id): Path<String>,
+    Json(request): Json<BatchRequest>,
+) -> Result<(StatusCode, Json<BatchReport>), StatusCode> {
+    // Not gated on the room's current setting: a window opened before
+    // batching was turned off still has to settle
+    validate(&request)?;
+    let window_start = request.window_start.timestamp_millis();
+
+    if let Some(report) = load_report(&state.session, &room_id, window_start).await? {
+        record_transfers(&state.session, &report).await?;
+        return Ok((StatusCode::OK, Json(report)));
+    }
+
+    for tx in &request.transactions {
+        signatures::check(&state.session, tx).await?;
+    }
+
+    let settlements = net_per_pair(&request.transactions).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let report = BatchReport {
+        batch_id: Uuid::new_v4().to_string(),
+        room_id: room_id.clone(),
+        window_start: request.window_start,
+        window_end: request.window_end,
+        transaction_count: request.transactions.len() as i32,
+        gross_volume: total(request.transactions.iter().map(|tx| &tx.amount))
+            .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?,
+        net_volume: total(settlements.iter().map(|settlement| &settlement.amount))
+            .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?,
+        settlements,
+        settled_at: Utc::now(),
+    };
+
+    let claimed = state
+        .session
+        .query(
+            "INSERT INTO transactions.batch_reports
+                 (room_id, window_start, batch_id, window_end, transaction_count, gross_volume, net_volume, settlements, settled_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            (
+                &room_id,
+                window_start,
+                Uuid::parse_str(&report.batch_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+                report.window_end.timestamp_millis(),
+                report.transaction_count,
+                report.gross_volume.to_major(),
+                report.net_volume.to_major(),
+                serde_json::to_string(&report.settlements).map_err(db_error)?,
+                report.settled_at.timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(db_error)?;
+    if !lwt_applied(claimed) {
+        // Raced with another post of the same window
+        let report = load_report(&state.session, &room_id, window_start).await?.ok_or(StatusCode::CONFLICT)?;
+        record_transfers(&state.session, &report).await?;
+        return Ok((StatusCode::OK, Json(report)));
+    }
+
+    record_transfers(&state.session, &report).await?;
+    info!(
+        "📦 Room {} settled {} transactions as {} transfers ({} gross, {} net)",
+        room_id,
+        report.transaction_count,
+        report.settlements.len(),
+        report.gross_volume,
+        report.net_volume
+    );
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+/// `GET /api/rooms/:id/batches?limit=N`: settlement reports, newest first.
+pub async fn list_batches(
+    State(state): State<AppState>,
+    Path(room_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<BatchReport>>, StatusCode> {
+    let limit: i32 = params.get("limit").and_then(|l| l.parse().ok()).unwrap_or(50).clamp(1, 500);
+    let rows = state
+        .session
+        .query(
+            db::idempotent("SELECT batch_id, window_start, window_end, transaction_count, gross_volume, net_volume, settlements, settled_at
+             FROM transactions.batch_reports WHERE room_id = ? LIMIT ?"),
+            (&room_id, limit),
+        )
+        .await
+        .map_err(db_error)?;
+
+    rows.rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<ReportRow>().ok())
+        .map(|row| report_from_row(&room_id, row))
+        .collect::<Result<Vec<_>, _>>()
+        .map(Json)
+}
@@ -0,0 +1,99 @@
+//! Streaming payment channels run between the peers; the gateway only sees
+//! the final signed update (see `tx_core::ChannelUpdate`) and records its
+//! settlement transfer. A channel settles once, whichever side closes it.
+
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Utc;
+use scylla::Session;
+use tracing::{error, info, warn};
+use tx_core::ChannelUpdate;
+use uuid::Uuid;
+
+use crate::{audit, circuit_breaker, ingest_transaction, lwt_applied, AppState};
+
+async fn claim(session: &Session, channel_id: Uuid, update: &ChannelUpdate) -> Result<bool, StatusCode> {
+    let result = session
+        .query(
+            "INSERT INTO transactions.payment_channels (channel_id, payer, payee, reserved, paid, settled_at)
+             VALUES (?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            (
+                channel_id,
+                &update.payer,
+                &update.payee,
+                update.reserved,
+                update.paid,
+                Utc::now().timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to claim settlement of channel {}: {}", channel_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(lwt_applied(result))
+}
+
+/// `POST /api/channels/settle`: records the final update's transfer.
+/// 409 if the channel was already settled.
+pub async fn settle_channel(
+    State(state): State<AppState>,
+    Json(update): Json<ChannelUpdate>,
+) -> Response {
+    let Ok(channel_id) = Uuid::parse_str(&update.channel_id) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    if update.paid <= 0.0 {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+    let Ok(settlement) = update.settlement() else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    if !update.verify() {
+        warn!("Channel {} settlement has an invalid payer signature", channel_id);
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    if let Err(cooling_down) = circuit_breaker::check(&state.session, &update.payer).await {
+        return cooling_down;
+    }
+
+    match claim(&state.session, channel_id, &update).await {
+        Ok(true) => {}
+        Ok(false) => return StatusCode::CONFLICT.into_response(),
+        Err(status) => return status.into_response(),
+    }
+
+    let created = match ingest_transaction(&state, settlement).await {
+        Ok(created) => created,
+        Err(status) => {
+            // Refused settlements can be retried once the cause is fixed
+            if let Err(e) = state
+                .session
+                .query("DELETE FROM transactions.payment_channels WHERE channel_id = ?", (channel_id,))
+                .await
+            {
+                error!("Failed to release claim on channel {}: {}", channel_id, e);
+            }
+            return status.into_response();
+        }
+    };
+
+    if let Err(status) = audit::record(
+        &state.session,
+        &update.channel_id,
+        "channel.settled",
+        &update.payer,
+        Some(format!("paid {:.2} of {:.2} reserved to {}", update.paid, update.reserved, update.payee)),
+    )
+    .await
+    {
+        return status.into_response();
+    }
+
+    info!("🌊 Channel {} settled for {:.2}", channel_id, update.paid);
+    created.into_response()
+}
@@ -0,0 +1,217 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use chrono::{DateTime, Duration, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::auth::{self, Claims};
+use crate::db;
+use crate::error::ApiError;
+use crate::sends;
+use crate::{audit, timestamp_from_millis, AppState};
+
+const STATE_COOLDOWN: &str = "cooldown";
+const STATE_OVERRIDE: &str = "override";
+
+/// Thresholds, each overridable through the environment:
+///
+/// - `CIRCUIT_BREAKER_MULTIPLIER` (10): trip when the current window's send
+///   count exceeds this multiple of the endpoint's historical rate
+/// - `CIRCUIT_BREAKER_WINDOW_SECS` (3600): length of the current window
+/// - `CIRCUIT_BREAKER_BASELINE_DAYS` (7): history used for the baseline rate
+/// - `CIRCUIT_BREAKER_MIN_SENDS` (10): never trip below this many sends
+/// - `CIRCUIT_BREAKER_COOLDOWN_SECS` (900): how long sends stay rejected
+pub struct BreakerConfig {
+    pub multiplier: f64,
+    pub window: Duration,
+    pub baseline: Duration,
+    pub min_sends: usize,
+    pub cooldown: Duration,
+}
+
+impl BreakerConfig {
+    pub fn from_env() -> Self {
+        fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+            std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        BreakerConfig {
+            multiplier: env_or("CIRCUIT_BREAKER_MULTIPLIER", 10.0),
+            window: Duration::seconds(env_or("CIRCUIT_BREAKER_WINDOW_SECS", 3600)),
+            baseline: Duration::days(env_or("CIRCUIT_BREAKER_BASELINE_DAYS", 7)),
+            min_sends: env_or("CIRCUIT_BREAKER_MIN_SENDS", 10),
+            cooldown: Duration::seconds(env_or("CIRCUIT_BREAKER_COOLDOWN_SECS", 900)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BreakerState {
+    pub endpoint_id: String,
+    pub state: String,
+    pub until: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+/// Response returned while an endpoint is cooling down.
+pub struct CoolingDown(BreakerState);
+
+impl IntoResponse for CoolingDown {
+    fn into_response(self) -> Response {
+        let retry_after = (self.0.until - Utc::now()).num_seconds().max(1);
+        ApiError::RateLimited {
+            message: format!(
+                "Sending from {} is paused until {} after an unusual spike in activity",
+                self.0.endpoint_id,
+                self.0.until.to_rfc3339()
+            ),
+            retry_after_secs: retry_after as u64,
+            details: Some(serde_json::json!({
+                "until": self.0.until,
+                "reason": self.0.reason,
+            })),
+        }
+        .into_response()
+    }
+}
+
+async fn load_state(session: &Session, endpoint_id: &str) -> Result<Option<BreakerState>, StatusCode> {
+    let rows = session
+        .query(
+            db::idempotent("SELECT state, until, reason FROM transactions.endpoint_breakers WHERE endpoint_id = ?"),
+            (endpoint_id,),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to load breaker state for {}: {}", endpoint_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let state = rows
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(String, i64, Option<String>)>().ok())
+        .map(|(state, until, reason)| BreakerState {
+            endpoint_id: endpoint_id.to_string(),
+            state,
+            until: timestamp_from_millis(until),
+            reason,
+        })
+        // Rows carry a TTL, this only guards against clock skew
+        .filter(|state| state.until > Utc::now());
+    Ok(state)
+}
+
+async fn save_state(session: &Session, state: &BreakerState) -> Result<(), StatusCode> {
+    let ttl = (state.until - Utc::now()).num_seconds().max(1) as i32;
+    session
+        .query(
+            "INSERT INTO transactions.endpoint_breakers (endpoint_id, state, until, reason)
+             VALUES (?, ?, ?, ?) USING TTL ?",
+            (
+                &state.endpoint_id,
+                &state.state,
+                state.until.timestamp_millis(),
+                &state.reason,
+                ttl,
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to save breaker state for {}: {}", state.endpoint_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(())
+}
+
+async fn send_count_since(session: &Session, endpoint_id: &str, since: DateTime<Utc>) -> usize {
+    sends::amounts_since(session, endpoint_id, since.timestamp_millis())
+        .await
+        .map(|amounts| amounts.len())
+        .unwrap_or(0)
+}
+
+/// Rejects sends from endpoints in cool-down, and trips the breaker when the
+/// current window's send rate spikes past the configured multiple of the
+/// endpoint's baseline.
+pub async fn check(session: &Session, endpoint_id: &str) -> Result<(), Response> {
+    match load_state(session, endpoint_id).await.map_err(IntoResponse::into_response)? {
+        Some(state) if state.state == STATE_COOLDOWN => return Err(CoolingDown(state).into_response()),
+        Some(_) => return Ok(()),
+        None => {}
+    }
+
+    let config = BreakerConfig::from_env();
+    let now = Utc::now();
+    // Count this send as part of the current window
+    let current = send_count_since(session, endpoint_id, now - config.window).await + 1;
+    if current < config.min_sends {
+        return Ok(());
+    }
+
+    let history = send_count_since(session, endpoint_id, now - config.baseline).await + 1 - current;
+    let windows = config.baseline.num_seconds() as f64 / config.window.num_seconds().max(1) as f64;
+    // Treat endpoints without history as sending once per window
+    let baseline_rate = (history as f64 / windows).max(1.0);
+    if (current as f64) <= baseline_rate * config.multiplier {
+        return Ok(());
+    }
+
+    let state = BreakerState {
+        endpoint_id: endpoint_id.to_string(),
+        state: STATE_COOLDOWN.to_string(),
+        until: now + config.cooldown,
+        reason: Some(format!(
+            "{} sends in the last {}s against a baseline of {:.1}",
+            current,
+            config.window.num_seconds(),
+            baseline_rate
+        )),
+    };
+    save_state(session, &state).await.map_err(IntoResponse::into_response)?;
+    audit::record(session, endpoint_id, "circuit_breaker.tripped", "circuit-breaker", state.reason.clone())
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    warn!("⚡ Circuit breaker tripped for {}: {:?}", endpoint_id, state.reason);
+    Err(CoolingDown(state).into_response())
+}
+
+pub async fn get_breaker(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+) -> Result<Json<BreakerState>, StatusCode> {
+    load_state(&state.session, &endpoint_id)
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// Admin override: lifts a cool-down and exempts the endpoint from
+/// re-tripping for one window so the spike that caused it can drain.
+pub async fn override_breaker(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+) -> Result<Json<BreakerState>, StatusCode> {
+    let admin = auth::actor(claims.as_deref(), &headers, "x-admin")?;
+
+    let config = BreakerConfig::from_env();
+    let breaker = BreakerState {
+        endpoint_id: endpoint_id.clone(),
+        state: STATE_OVERRIDE.to_string(),
+        until: Utc::now() + config.window,
+        reason: Some(format!("overridden by {}", admin)),
+    };
+    save_state(&state.session, &breaker).await?;
+    audit::record(&state.session, &endpoint_id, "circuit_breaker.override", &admin, None).await?;
+
+    info!("Circuit breaker for {} overridden by {}", endpoint_id, admin);
+    Ok(Json(breaker))
+}
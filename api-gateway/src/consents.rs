@@ -0,0 +1,541 @@
+//! Consented data sharing, Open Banking style. An operator registers a
+//! third party with `POST /api/admin/third-parties` and hands it the API
+//! key that comes back, once. An endpoint's owner then grants that third
+//! party read access to some of its data with `POST
+//! /api/endpoints/:id/consents`, for some `scopes` and for at most
+//! `MAX_CONSENT_DAYS` (90), and can revoke it at any time with `DELETE
+//! /api/endpoints/:id/consents/:consent_id`.
+//!
+//! A request carrying `X-API-Key` is the third party's, whatever else it
+//! carries, and may only make the reads its scopes cover, on an endpoint
+//! with an active consent to it:
+//!
+//! - `transactions`: `GET /api/transactions?endpoint=:id`
+//! - `balance`: `GET /api/endpoints/:id/balance` and `/stats`
+//! - `statements`: `GET /api/endpoints/:id/statement`
+//!
+//! Those handlers check again, with `confine`, that what they serve is
+//! the endpoint and scope the consent covers.
+//!
+//! Grants and revocations go to the endpoint's audit trail and are
+//! published as `consent.granted` and `consent.revoked` events, partitioned
+//! by `consents:<endpoint id>`. They aren't part of the ledger's event log,
+//! so the relay doesn't retry them: a publish that fails is logged and the
+//! audit trail stays the record. Consents that run out publish nothing.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::Json,
+    Extension,
+};
+use chrono::{DateTime, Duration, Utc};
+use rand_core::{OsRng, RngCore};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::{self, Claims, Role};
+use crate::db;
+use crate::events::Event;
+use crate::{audit, lwt_applied, timestamp_from_millis, AppState};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+const DEFAULT_CONSENT_DAYS: i64 = 90;
+const MAX_CONSENT_DAYS: i64 = 90;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Transactions,
+    Balance,
+    Statements,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Transactions => "transactions",
+            Scope::Balance => "balance",
+            Scope::Statements => "statements",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transactions" => Ok(Scope::Transactions),
+            "balance" => Ok(Scope::Balance),
+            "statements" => Ok(Scope::Statements),
+            other => Err(format!("Unknown consent scope: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "lowercase")]
+pub enum ConsentStatus {
+    Active,
+    Expired,
+    Revoked,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Consent {
+    pub consent_id: String,
+    pub endpoint_id: String,
+    pub third_party_id: String,
+    pub scopes: Vec<Scope>,
+    pub granted_by: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub status: ConsentStatus,
+}
+
+impl Consent {
+    fn allows(&self, third_party_id: Uuid, scope: Scope, now: DateTime<Utc>) -> bool {
+        self.third_party_id == third_party_id.to_string()
+            && self.revoked_at.is_none()
+            && self.expires_at > now
+            && self.scopes.contains(&scope)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ThirdParty {
+    pub third_party_id: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    /// Only in the response that registers the third party; the gateway
+    /// keeps a hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+/// The third party a request was let through for, and what its consent
+/// covers. Read handlers hand it to `confine`.
+#[derive(Clone, Debug)]
+pub struct ThirdPartyAccess {
+    pub third_party_id: Uuid,
+    pub endpoint_id: String,
+    pub scope: Scope,
+}
+
+/// Refuses (403) a third party's read of anything but the endpoint and
+/// scope it was let through for, as the handler serving it reads them,
+/// so a handler can't be made to answer for more than `authorize`
+/// checked. Requests without `access` aren't a third party's.
+pub fn confine(access: Option<&ThirdPartyAccess>, endpoint_id: Option<&str>, scope: Scope) -> Result<(), StatusCode> {
+    let Some(access) = access else {
+        return Ok(());
+    };
+    if endpoint_id != Some(access.endpoint_id.as_str()) || scope != access.scope {
+        warn!(
+            "Third party {} let through for {} on {} but read {} on {:?}",
+            access.third_party_id, access.scope, access.endpoint_id, scope, endpoint_id
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(())
+}
+
+fn db_error<E: fmt::Display>(endpoint_id: &str) -> impl Fn(E) -> StatusCode + '_ {
+    move |e| {
+        error!("Consent query for {} failed: {}", endpoint_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+fn key_hash(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ThirdPartyRequest {
+    pub name: String,
+}
+
+/// `POST /api/admin/third-parties`: registers a third party and returns
+/// its API key, `<third_party_id>.<secret>`.
+pub async fn create_third_party(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    claims: Option<Extension<Claims>>,
+    Json(request): Json<ThirdPartyRequest>,
+) -> Result<(StatusCode, Json<ThirdParty>), StatusCode> {
+    let name = request.name.trim().to_string();
+    if name.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let actor = auth::actor(claims.as_deref(), &headers, "x-admin")?;
+
+    let third_party_id = Uuid::new_v4();
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let secret = hex::encode(secret);
+    let created_at = Utc::now();
+    state
+        .session
+        .query(
+            db::idempotent(
+                "INSERT INTO transactions.third_parties (third_party_id, name, key_hash, created_at) VALUES (?, ?, ?, ?)",
+            ),
+            (third_party_id, &name, key_hash(&secret), created_at.timestamp_millis()),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to register third party {}: {}", name, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let id = third_party_id.to_string();
+    audit::record(&state.session, &id, "third_party.registered", &actor, Some(name.clone())).await?;
+    info!("Registered third party {} ({})", name, id);
+    Ok((
+        StatusCode::CREATED,
+        Json(ThirdParty {
+            api_key: Some(format!("{}.{}", id, secret)),
+            third_party_id: id,
+            name,
+            created_at,
+        }),
+    ))
+}
+
+/// The third party an API key belongs to, `None` if it belongs to none.
+/// The third party an API key names, whether or not the key is its.
+pub fn key_owner(api_key: &str) -> Option<Uuid> {
+    let (id, _) = api_key.trim().split_once('.')?;
+    Uuid::parse_str(id).ok()
+}
+
+async fn verify_key(session: &Session, api_key: &str) -> Result<Option<Uuid>, StatusCode> {
+    let Some((_, secret)) = api_key.trim().split_once('.') else {
+        return Ok(None);
+    };
+    let Some(third_party_id) = key_owner(api_key) else {
+        return Ok(None);
+    };
+    let stored = session
+        .query(
+            db::idempotent("SELECT key_hash FROM transactions.third_parties WHERE third_party_id = ?"),
+            (third_party_id,),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to load third party {}: {}", third_party_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(Option<String>,)>().ok())
+        .and_then(|(hash,)| hash);
+    Ok(stored.filter(|hash| *hash == key_hash(secret)).map(|_| third_party_id))
+}
+
+/// The endpoint and scope a third party's request reads, `None` for
+/// anything consents don't cover.
+fn requested_scope(method: &Method, uri: &Uri) -> Option<(String, Scope)> {
+    if method != Method::GET {
+        return None;
+    }
+    let segments: Vec<&str> = uri.path().trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        ["api", "transactions"] => {
+            let Query(params) = Query::<HashMap<String, String>>::try_from_uri(uri).ok()?;
+            let endpoint = params.get("endpoint")?.clone();
+            Some((endpoint, Scope::Transactions))
+        }
+        ["api", "endpoints", id, "balance" | "stats"] => Some((id.to_string(), Scope::Balance)),
+        ["api", "endpoints", id, "statement"] => Some((id.to_string(), Scope::Statements)),
+        _ => None,
+    }
+}
+
+/// Checks a third party's request: a known key, a read consents cover and
+/// an active consent for it. Fails with 401 for the key, 403 otherwise.
+pub async fn authorize(
+    session: &Session,
+    method: &Method,
+    uri: &Uri,
+    api_key: &str,
+) -> Result<ThirdPartyAccess, StatusCode> {
+    let third_party_id = verify_key(session, api_key).await?.ok_or(StatusCode::UNAUTHORIZED)?;
+    let (endpoint_id, scope) = requested_scope(method, uri).ok_or(StatusCode::FORBIDDEN)?;
+
+    let now = Utc::now();
+    let consented = load_consents(session, &endpoint_id, None)
+        .await?
+        .iter()
+        .any(|consent| consent.allows(third_party_id, scope, now));
+    if !consented {
+        info!("Third party {} has no {} consent for {}", third_party_id, scope, endpoint_id);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok(ThirdPartyAccess {
+        third_party_id,
+        endpoint_id,
+        scope,
+    })
+}
+
+type ConsentRow = (Uuid, Option<Uuid>, Option<String>, Option<String>, Option<i64>, Option<i64>, Option<i64>);
+
+fn consent_from_row(endpoint_id: &str, row: ConsentRow, now: DateTime<Utc>) -> Result<Consent, String> {
+    let (consent_id, third_party_id, scopes, granted_by, created_at, expires_at, revoked_at) = row;
+    let scopes = scopes
+        .unwrap_or_default()
+        .split(',')
+        .filter(|scope| !scope.is_empty())
+        .map(Scope::from_str)
+        .collect::<Result<Vec<_>, _>>()?;
+    let expires_at = timestamp_from_millis(expires_at.unwrap_or_default());
+    let revoked_at = revoked_at.map(timestamp_from_millis);
+    let status = match revoked_at {
+        Some(_) => ConsentStatus::Revoked,
+        None if expires_at <= now => ConsentStatus::Expired,
+        None => ConsentStatus::Active,
+    };
+    Ok(Consent {
+        consent_id: consent_id.to_string(),
+        endpoint_id: endpoint_id.to_string(),
+        third_party_id: third_party_id.map(|id| id.to_string()).unwrap_or_default(),
+        scopes,
+        granted_by: granted_by.unwrap_or_default(),
+        created_at: timestamp_from_millis(created_at.unwrap_or_default()),
+        expires_at,
+        revoked_at,
+        status,
+    })
+}
+
+async fn load_consents(session: &Session, endpoint_id: &str, consent_id: Option<Uuid>) -> Result<Vec<Consent>, StatusCode> {
+    let select = "SELECT consent_id, third_party_id, scopes, granted_by, created_at, expires_at, revoked_at \
+                  FROM transactions.consents WHERE endpoint_id = ?";
+    let result = match consent_id {
+        Some(consent_id) => {
+            session
+                .query(db::idempotent(format!("{} AND consent_id = ?", select)), (endpoint_id, consent_id))
+                .await
+        }
+        None => session.query(db::idempotent(select), (endpoint_id,)).await,
+    }
+    .map_err(db_error(endpoint_id))?;
+
+    let now = Utc::now();
+    let mut consents = Vec::new();
+    for row in result.rows.unwrap_or_default() {
+        let consent = row
+            .into_typed::<ConsentRow>()
+            .map_err(|e| e.to_string())
+            .and_then(|row| consent_from_row(endpoint_id, row, now))
+            .map_err(db_error(endpoint_id))?;
+        consents.push(consent);
+    }
+    Ok(consents)
+}
+
+/// Who is acting for `endpoint_id`: the token's subject when it is the
+/// endpoint itself or an admin, anyone while the gateway is open.
+fn owner(claims: Option<&Claims>, endpoint_id: &str) -> Result<String, StatusCode> {
+    match claims {
+        Some(claims) if claims.sub == endpoint_id || claims.has_role(Role::Admin) => Ok(claims.sub.clone()),
+        Some(_) => Err(StatusCode::FORBIDDEN),
+        None => Ok(endpoint_id.to_string()),
+    }
+}
+
+/// Publishes a grant or revocation, logging rather than failing if the
+/// publisher is down.
+async fn publish(state: &AppState, event_type: &str, consent: &Consent, at: DateTime<Utc>) {
+    let event = Event {
+        partition_key: format!("consents:{}", consent.endpoint_id),
+        offset: at.timestamp_millis(),
+        event_id: format!("{}:{}", consent.consent_id, event_type),
+        event_type: event_type.to_string(),
+        transaction_id: String::new(),
+        tx_hash: String::new(),
+        payload: serde_json::to_value(consent).unwrap_or_default(),
+        at,
+    };
+    if let Err(e) = state.events.publish(&event).await {
+        warn!("Failed to publish {} for consent {}: {}", event_type, consent.consent_id, e);
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ConsentRequest {
+    pub third_party_id: String,
+    pub scopes: Vec<Scope>,
+    /// Days until the consent runs out, `DEFAULT_CONSENT_DAYS` if absent.
+    pub valid_days: Option<i64>,
+}
+
+/// `POST /api/endpoints/:id/consents`
+pub async fn grant_consent(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    claims: Option<Extension<Claims>>,
+    Json(request): Json<ConsentRequest>,
+) -> Result<(StatusCode, Json<Consent>), StatusCode> {
+    let granted_by = owner(claims.as_deref(), &endpoint_id)?;
+    let third_party_id = Uuid::parse_str(&request.third_party_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let valid_days = request.valid_days.unwrap_or(DEFAULT_CONSENT_DAYS);
+    if request.scopes.is_empty() || !(1..=MAX_CONSENT_DAYS).contains(&valid_days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let registered = state
+        .session
+        .query(
+            db::idempotent("SELECT third_party_id FROM transactions.third_parties WHERE third_party_id = ?"),
+            (third_party_id,),
+        )
+        .await
+        .map_err(db_error(&endpoint_id))?
+        .rows
+        .is_some_and(|rows| !rows.is_empty());
+    if !registered {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let mut scopes = Vec::new();
+    for scope in request.scopes {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    let consent_id = Uuid::new_v4();
+    let created_at = Utc::now();
+    let consent = Consent {
+        consent_id: consent_id.to_string(),
+        endpoint_id: endpoint_id.clone(),
+        third_party_id: third_party_id.to_string(),
+        scopes,
+        granted_by,
+        created_at,
+        expires_at: created_at + Duration::days(valid_days),
+        revoked_at: None,
+        status: ConsentStatus::Active,
+    };
+    let scopes = consent.scopes.iter().map(Scope::as_str).collect::<Vec<_>>().join(",");
+    state
+        .session
+        .query(
+            db::idempotent(
+                "INSERT INTO transactions.consents \
+                 (endpoint_id, consent_id, third_party_id, scopes, granted_by, created_at, expires_at) \
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            ),
+            (
+                &endpoint_id,
+                consent_id,
+                third_party_id,
+                &scopes,
+                &consent.granted_by,
+                created_at.timestamp_millis(),
+                consent.expires_at.timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(db_error(&endpoint_id))?;
+
+    let details = format!("{} to {} ({}) until {}", consent.consent_id, third_party_id, scopes, consent.expires_at);
+    audit::record(&state.session, &endpoint_id, "consent.granted", &consent.granted_by, Some(details)).await?;
+    publish(&state, "consent.granted", &consent, created_at).await;
+    info!("{} granted third party {} {} access", endpoint_id, third_party_id, scopes);
+    Ok((StatusCode::CREATED, Json(consent)))
+}
+
+/// `GET /api/endpoints/:id/consents`: the endpoint's consents, including
+/// expired and revoked ones.
+pub async fn list_consents(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    claims: Option<Extension<Claims>>,
+) -> Result<Json<Vec<Consent>>, StatusCode> {
+    owner(claims.as_deref(), &endpoint_id)?;
+    let mut consents = load_consents(&state.session, &endpoint_id, None).await?;
+    consents.sort_by_key(|consent| std::cmp::Reverse(consent.created_at));
+    Ok(Json(consents))
+}
+
+/// `DELETE /api/endpoints/:id/consents/:consent_id`: revokes a consent.
+/// The third party loses access with its next request.
+pub async fn revoke_consent(
+    State(state): State<AppState>,
+    Path((endpoint_id, consent_id)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+) -> Result<Json<Consent>, StatusCode> {
+    let revoked_by = owner(claims.as_deref(), &endpoint_id)?;
+    let consent_id = Uuid::parse_str(&consent_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let consent = load_consents(&state.session, &endpoint_id, Some(consent_id))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    Ok(Json(revoke(&state, consent, &revoked_by).await?))
+}
+
+/// Revokes every consent of `endpoint_id` still in force, returning how
+/// many there were.
+pub async fn revoke_all(state: &AppState, endpoint_id: &str, revoked_by: &str) -> Result<usize, StatusCode> {
+    let now = Utc::now();
+    let mut revoked = 0;
+    for consent in load_consents(&state.session, endpoint_id, None).await? {
+        if consent.revoked_at.is_none() && consent.expires_at > now {
+            revoke(state, consent, revoked_by).await?;
+            revoked += 1;
+        }
+    }
+    Ok(revoked)
+}
+
+async fn revoke(state: &AppState, mut consent: Consent, revoked_by: &str) -> Result<Consent, StatusCode> {
+    if consent.revoked_at.is_some() {
+        return Ok(consent);
+    }
+    let endpoint_id = consent.endpoint_id.clone();
+    let consent_id = Uuid::parse_str(&consent.consent_id).map_err(db_error(&endpoint_id))?;
+
+    let revoked_at = Utc::now();
+    let result = state
+        .session
+        .query(
+            "UPDATE transactions.consents SET revoked_at = ? WHERE endpoint_id = ? AND consent_id = ? IF revoked_at = null",
+            (revoked_at.timestamp_millis(), &endpoint_id, consent_id),
+        )
+        .await
+        .map_err(db_error(&endpoint_id))?;
+    if !lwt_applied(result) {
+        // Revoked concurrently; that revocation published its own event
+        let consent = load_consents(&state.session, &endpoint_id, Some(consent_id)).await?.into_iter().next();
+        return consent.ok_or(StatusCode::NOT_FOUND);
+    }
+    consent.revoked_at = Some(revoked_at);
+    consent.status = ConsentStatus::Revoked;
+
+    let details = format!("{} from {}", consent.consent_id, consent.third_party_id);
+    audit::record(&state.session, &endpoint_id, "consent.revoked", revoked_by, Some(details)).await?;
+    publish(state, "consent.revoked", &consent, revoked_at).await;
+    info!("{} revoked consent {}", endpoint_id, consent_id);
+    Ok(consent)
+}
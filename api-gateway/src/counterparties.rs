@@ -0,0 +1,164 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::db;
+use crate::AppState;
+
+const BLOCK_LIST: &str = "block";
+const ALLOW_LIST: &str = "allow";
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct CounterpartyLists {
+    pub blocked: Vec<String>,
+    pub allowed: Vec<String>,
+}
+
+impl CounterpartyLists {
+    /// An empty allowlist means "allow everyone not blocked".
+    pub fn accepts(&self, counterparty: &str) -> bool {
+        if self.blocked.iter().any(|p| p == counterparty) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|p| p == counterparty)
+    }
+}
+
+pub async fn load_lists(session: &Session, endpoint_id: &str) -> Result<CounterpartyLists, StatusCode> {
+    let rows = session
+        .query(
+            db::idempotent("SELECT list, counterparty FROM transactions.counterparty_lists WHERE endpoint_id = ?"),
+            (endpoint_id,),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to load counterparty lists for {}: {}", endpoint_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut lists = CounterpartyLists::default();
+    if let Some(rows) = rows.rows {
+        for row in rows {
+            if let Ok((list, counterparty)) = row.into_typed::<(String, String)>() {
+                match list.as_str() {
+                    BLOCK_LIST => lists.blocked.push(counterparty),
+                    ALLOW_LIST => lists.allowed.push(counterparty),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(lists)
+}
+
+/// Drops both of the endpoint's lists, returning how many entries they had.
+pub async fn clear_lists(session: &Session, endpoint_id: &str) -> Result<usize, StatusCode> {
+    let lists = load_lists(session, endpoint_id).await?;
+    session
+        .query(
+            db::idempotent("DELETE FROM transactions.counterparty_lists WHERE endpoint_id = ?"),
+            (endpoint_id,),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to clear counterparty lists for {}: {}", endpoint_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(lists.blocked.len() + lists.allowed.len())
+}
+
+/// Ingest-side check: the receiver must accept transfers from the sender.
+pub async fn check_receiver_accepts(
+    session: &Session,
+    from_endpoint: &str,
+    to_endpoint: &str,
+) -> Result<(), StatusCode> {
+    if load_lists(session, to_endpoint).await?.accepts(from_endpoint) {
+        Ok(())
+    } else {
+        info!("{} does not accept transfers from {}", to_endpoint, from_endpoint);
+        Err(StatusCode::FORBIDDEN)
+    }
+}
+
+fn list_name(list: &str) -> Result<&'static str, StatusCode> {
+    match list {
+        "blocked" | "block" => Ok(BLOCK_LIST),
+        "allowed" | "allow" => Ok(ALLOW_LIST),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+pub async fn get_lists(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+) -> Result<Json<CounterpartyLists>, StatusCode> {
+    load_lists(&state.session, &endpoint_id).await.map(Json)
+}
+
+pub async fn add_counterparty(
+    State(state): State<AppState>,
+    Path((endpoint_id, list, counterparty)): Path<(String, String, String)>,
+) -> Result<Json<CounterpartyLists>, StatusCode> {
+    let list = list_name(&list)?;
+    if counterparty == endpoint_id {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    // A peer is on at most one list
+    for other in [BLOCK_LIST, ALLOW_LIST].into_iter().filter(|l| *l != list) {
+        remove(&state.session, &endpoint_id, other, &counterparty).await?;
+    }
+
+    state
+        .session
+        .query(
+            "INSERT INTO transactions.counterparty_lists (endpoint_id, list, counterparty, created_at)
+             VALUES (?, ?, ?, ?)",
+            (&endpoint_id, list, &counterparty, Utc::now().timestamp_millis()),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to update counterparty list: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("{} added {} to its {} list", endpoint_id, counterparty, list);
+    load_lists(&state.session, &endpoint_id).await.map(Json)
+}
+
+pub async fn remove_counterparty(
+    State(state): State<AppState>,
+    Path((endpoint_id, list, counterparty)): Path<(String, String, String)>,
+) -> Result<Json<CounterpartyLists>, StatusCode> {
+    let list = list_name(&list)?;
+    remove(&state.session, &endpoint_id, list, &counterparty).await?;
+    load_lists(&state.session, &endpoint_id).await.map(Json)
+}
+
+async fn remove(
+    session: &Session,
+    endpoint_id: &str,
+    list: &str,
+    counterparty: &str,
+) -> Result<(), StatusCode> {
+    session
+        .query(
+            "DELETE FROM transactions.counterparty_lists
+             WHERE endpoint_id = ? AND list = ? AND counterparty = ?",
+            (endpoint_id, list, counterparty),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to update counterparty list: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(())
+}
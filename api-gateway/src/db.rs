@@ -0,0 +1,114 @@
+//! How queries behave when Scylla is slow or a node fails. Every query
+//! gets the session's default execution profile:
+//!
+//! - a request timeout, `SCYLLA_REQUEST_TIMEOUT_MS` (5000)
+//! - up to `SCYLLA_MAX_RETRIES` (2) retries on another node, for
+//!   idempotent statements only
+//! - speculative execution: if a replica hasn't answered an idempotent
+//!   statement after `SCYLLA_SPECULATIVE_DELAY_MS` (100), the query is also
+//!   sent to the next one, up to `SCYLLA_SPECULATIVE_MAX` (1) extra times
+//!
+//! The driver treats statements as non-idempotent unless told otherwise,
+//! so reads and idempotent writes go through `idempotent` (or `scan`, for
+//! reads of a whole table or log). LWTs, counters and anything else that
+//! must not run twice stay plain strings and are never retried.
+
+use scylla::execution_profile::ExecutionProfile;
+use scylla::query::Query;
+use scylla::retry_policy::{QueryInfo, RetryDecision, RetryPolicy, RetrySession};
+use scylla::speculative_execution::SimpleSpeculativeExecutionPolicy;
+use scylla::transport::errors::{DbError, QueryError};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Full scans outlive the request timeout; they're for startup
+/// migrations, rebuilds and snapshots, not the request path.
+pub const SCAN_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub fn execution_profile() -> ExecutionProfile {
+    let speculative_max = env_u64("SCYLLA_SPECULATIVE_MAX", 1);
+    ExecutionProfile::builder()
+        .request_timeout(Some(Duration::from_millis(env_u64("SCYLLA_REQUEST_TIMEOUT_MS", 5000))))
+        .retry_policy(Box::new(BoundedRetryPolicy {
+            max_retries: env_u64("SCYLLA_MAX_RETRIES", 2) as usize,
+        }))
+        .speculative_execution_policy((speculative_max > 0).then(|| {
+            Arc::new(SimpleSpeculativeExecutionPolicy {
+                max_retry_count: speculative_max as usize,
+                retry_interval: Duration::from_millis(env_u64("SCYLLA_SPECULATIVE_DELAY_MS", 100)),
+            }) as _
+        }))
+        .build()
+}
+
+/// A statement safe to run more than once: retried and sent speculatively.
+pub fn idempotent(cql: impl Into<String>) -> Query {
+    let mut query = Query::new(cql.into());
+    query.set_is_idempotent(true);
+    query
+}
+
+/// An idempotent read of a whole table or log, with a longer timeout.
+pub fn scan(cql: impl Into<String>) -> Query {
+    let mut query = idempotent(cql);
+    query.set_request_timeout(Some(SCAN_TIMEOUT));
+    query
+}
+
+/// Retries idempotent statements on the next node after errors a retry can
+/// fix, at most `max_retries` times. Everything else fails straight away.
+#[derive(Debug)]
+pub struct BoundedRetryPolicy {
+    max_retries: usize,
+}
+
+impl RetryPolicy for BoundedRetryPolicy {
+    fn new_session(&self) -> Box<dyn RetrySession> {
+        Box::new(BoundedRetrySession { max_retries: self.max_retries, retries: 0 })
+    }
+
+    fn clone_boxed(&self) -> Box<dyn RetryPolicy> {
+        Box::new(BoundedRetryPolicy { max_retries: self.max_retries })
+    }
+}
+
+struct BoundedRetrySession {
+    max_retries: usize,
+    retries: usize,
+}
+
+/// Whether `error` is one a retry, here or by the client, can fix.
+pub fn transient(error: &QueryError) -> bool {
+    match error {
+        QueryError::DbError(error, _) => matches!(
+            error,
+            DbError::Overloaded
+                | DbError::IsBootstrapping
+                | DbError::Unavailable { .. }
+                | DbError::ReadTimeout { .. }
+                | DbError::WriteTimeout { .. }
+                | DbError::ServerError
+                | DbError::TruncateError
+        ),
+        QueryError::IoError(_) | QueryError::TimeoutError | QueryError::UnableToAllocStreamId => true,
+        _ => false,
+    }
+}
+
+impl RetrySession for BoundedRetrySession {
+    fn decide_should_retry(&mut self, query_info: QueryInfo) -> RetryDecision {
+        if !query_info.is_idempotent || self.retries >= self.max_retries || !transient(query_info.error) {
+            return RetryDecision::DontRetry;
+        }
+        self.retries += 1;
+        RetryDecision::RetryNextNode(None)
+    }
+
+    fn reset(&mut self) {
+        self.retries = 0;
+    }
+}
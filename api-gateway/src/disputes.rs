@@ -0,0 +1,657 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::auth::{self, Claims};
+use crate::db;
+use crate::events::{self, EventKind};
+use crate::repository::Repository;
+use crate::{
+    audit, insert_transaction, load_transaction, pii, timestamp_from_millis, transaction_from_row,
+    AppState, Transaction, TransactionKind, TransactionStatus, TxRow, TX_COLUMNS,
+};
+
+/// Evidence files are stored inline with the entry, so keep them small.
+const MAX_ATTACHMENT_BYTES: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum DisputeStatus {
+    #[serde(rename = "open")]
+    Open,
+    #[serde(rename = "under_review")]
+    UnderReview,
+    #[serde(rename = "refunded")]
+    Refunded,
+    #[serde(rename = "rejected")]
+    Rejected,
+}
+
+impl DisputeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DisputeStatus::Open => "open",
+            DisputeStatus::UnderReview => "under_review",
+            DisputeStatus::Refunded => "refunded",
+            DisputeStatus::Rejected => "rejected",
+        }
+    }
+
+    fn is_final(&self) -> bool {
+        matches!(self, DisputeStatus::Refunded | DisputeStatus::Rejected)
+    }
+}
+
+impl fmt::Display for DisputeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DisputeStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "open" => Ok(DisputeStatus::Open),
+            "under_review" => Ok(DisputeStatus::UnderReview),
+            "refunded" => Ok(DisputeStatus::Refunded),
+            "rejected" => Ok(DisputeStatus::Rejected),
+            other => Err(format!("Unknown dispute status: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Dispute {
+    pub transaction_id: String,
+    pub opened_by: String,
+    pub counterparty: String,
+    pub reason: String,
+    pub status: DisputeStatus,
+    pub opened_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct AttachmentInfo {
+    pub evidence_id: String,
+    pub name: String,
+    pub content_type: String,
+    pub size: usize,
+}
+
+/// One entry of a dispute's timeline: either evidence submitted by a party
+/// or a status change recorded in the audit log.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct TimelineEntry {
+    pub at: DateTime<Utc>,
+    pub kind: String,
+    pub actor: String,
+    pub message: Option<String>,
+    pub attachment: Option<AttachmentInfo>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct DisputeDetail {
+    #[serde(flatten)]
+    pub dispute: Dispute,
+    pub timeline: Vec<TimelineEntry>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct OpenDisputeRequest {
+    pub opened_by: String,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AttachmentUpload {
+    pub name: String,
+    pub content_type: String,
+    /// Base64-encoded file contents.
+    pub data: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EvidenceRequest {
+    pub party: String,
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default)]
+    pub attachment: Option<AttachmentUpload>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ResolveRequest {
+    /// `refund` or `reject`.
+    pub outcome: String,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+type DisputeRow = (Uuid, String, String, String, String, i64, i64);
+type EvidenceRow = (i64, Uuid, String, Option<String>, Option<String>, Option<String>, Option<Vec<u8>>);
+
+const DISPUTE_COLUMNS: &str =
+    "transaction_id, opened_by, counterparty, reason, status, opened_at, updated_at";
+
+fn dispute_from_row(row: DisputeRow) -> Result<Dispute, String> {
+    let (transaction_id, opened_by, counterparty, reason, status, opened_at, updated_at) = row;
+    Ok(Dispute {
+        transaction_id: transaction_id.to_string(),
+        opened_by,
+        counterparty,
+        reason,
+        status: status.parse()?,
+        opened_at: timestamp_from_millis(opened_at),
+        updated_at: timestamp_from_millis(updated_at),
+    })
+}
+
+pub async fn load_dispute(session: &Session, tx_id: Uuid) -> Result<Option<Dispute>, StatusCode> {
+    let rows = session
+        .query(
+            db::idempotent(format!("SELECT {} FROM transactions.disputes WHERE transaction_id = ?", DISPUTE_COLUMNS)),
+            (tx_id,),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to load dispute {}: {}", tx_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    match rows.rows.and_then(|rows| rows.into_iter().next()) {
+        Some(row) => {
+            let row = row
+                .into_typed::<DisputeRow>()
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            let mut dispute = dispute_from_row(row).map_err(|e| {
+                error!("Corrupt dispute {}: {}", tx_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            dispute.reason = pii::open(session, &dispute.opened_by, dispute.reason).await?;
+            Ok(Some(dispute))
+        }
+        None => Ok(None),
+    }
+}
+
+async fn set_status(session: &Session, tx_id: Uuid, status: DisputeStatus) -> Result<(), StatusCode> {
+    session
+        .query(
+            "UPDATE transactions.disputes SET status = ?, updated_at = ? WHERE transaction_id = ?",
+            (status.as_str(), Utc::now().timestamp_millis(), tx_id),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to update dispute {}: {}", tx_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(())
+}
+
+/// Parties that have submitted at least one piece of evidence.
+async fn evidence_parties(session: &Session, tx_id: Uuid) -> Result<Vec<String>, StatusCode> {
+    let rows = session
+        .query(
+            db::idempotent("SELECT party FROM transactions.dispute_evidence WHERE transaction_id = ?"),
+            (tx_id,),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut parties = Vec::new();
+    if let Some(rows) = rows.rows {
+        for row in rows {
+            if let Ok((party,)) = row.into_typed::<(String,)>() {
+                if !parties.contains(&party) {
+                    parties.push(party);
+                }
+            }
+        }
+    }
+    Ok(parties)
+}
+
+fn parse_id(id: &str) -> Result<Uuid, StatusCode> {
+    Uuid::parse_str(id).map_err(|_| StatusCode::BAD_REQUEST)
+}
+
+pub async fn open_dispute(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<OpenDisputeRequest>,
+) -> Result<(StatusCode, Json<Dispute>), StatusCode> {
+    let tx_id = parse_id(&id)?;
+    let transaction = load_transaction(&state.session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if transaction.kind != TransactionKind::Transfer || request.reason.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let counterparty = if request.opened_by == transaction.from_endpoint {
+        transaction.to_endpoint.clone()
+    } else if request.opened_by == transaction.to_endpoint {
+        transaction.from_endpoint.clone()
+    } else {
+        return Err(StatusCode::FORBIDDEN);
+    };
+    if load_dispute(&state.session, tx_id).await?.is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let now = Utc::now();
+    let reason = pii::seal(&state.session, &request.opened_by, &request.reason).await?;
+    let dispute = Dispute {
+        transaction_id: id.clone(),
+        opened_by: request.opened_by,
+        counterparty,
+        reason: request.reason,
+        status: DisputeStatus::Open,
+        opened_at: now,
+        updated_at: now,
+    };
+
+    state
+        .session
+        .query(
+            format!("INSERT INTO transactions.disputes ({}) VALUES (?, ?, ?, ?, ?, ?, ?)", DISPUTE_COLUMNS),
+            (
+                tx_id,
+                &dispute.opened_by,
+                &dispute.counterparty,
+                &reason,
+                dispute.status.as_str(),
+                now.timestamp_millis(),
+                now.timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to open dispute {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    audit::record(&state.session, &id, "dispute.opened", &dispute.opened_by, Some(dispute.reason.clone())).await?;
+    let payload = serde_json::to_value(&dispute).unwrap_or_default();
+    events::append_with(&state.session, EventKind::Disputed, &transaction, payload, now).await?;
+
+    info!("Dispute opened on {} by {}", id, dispute.opened_by);
+    Ok((StatusCode::CREATED, Json(dispute)))
+}
+
+pub async fn list_disputes(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<Dispute>>, StatusCode> {
+    let rows = match params.get("status") {
+        Some(status) => {
+            let status: DisputeStatus = status.parse().map_err(|_| StatusCode::BAD_REQUEST)?;
+            state
+                .session
+                .query(
+                    db::idempotent(format!("SELECT {} FROM transactions.disputes WHERE status = ? ALLOW FILTERING", DISPUTE_COLUMNS)),
+                    (status.as_str(),),
+                )
+                .await
+        }
+        None => {
+            state
+                .session
+                .query(db::idempotent(format!("SELECT {} FROM transactions.disputes", DISPUTE_COLUMNS)), &[])
+                .await
+        }
+    }
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut disputes = Vec::new();
+    if let Some(rows) = rows.rows {
+        for row in rows {
+            if let Ok(row) = row.into_typed::<DisputeRow>() {
+                match dispute_from_row(row) {
+                    Ok(mut dispute) => {
+                        dispute.reason = pii::open(&state.session, &dispute.opened_by, dispute.reason).await?;
+                        disputes.push(dispute);
+                    }
+                    Err(e) => warn!("Skipping dispute: {}", e),
+                }
+            }
+        }
+    }
+
+    disputes.sort_by_key(|dispute| std::cmp::Reverse(dispute.updated_at));
+    Ok(Json(disputes))
+}
+
+pub async fn get_dispute(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<DisputeDetail>, StatusCode> {
+    let tx_id = parse_id(&id)?;
+    let dispute = load_dispute(&state.session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let rows = state
+        .session
+        .query(
+            db::idempotent("SELECT at, id, party, message, attachment_name, attachment_type, attachment
+             FROM transactions.dispute_evidence WHERE transaction_id = ?"),
+            (tx_id,),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut timeline = Vec::new();
+    if let Some(rows) = rows.rows {
+        for row in rows {
+            if let Ok((at, evidence_id, party, message, name, content_type, data)) =
+                row.into_typed::<EvidenceRow>() {
+                let session = &state.session;
+                let message = pii::open_opt(session, &party, message).await?;
+                let name = pii::open_opt(session, &party, name).await?;
+                let content_type = pii::open_opt(session, &party, content_type).await?;
+                let attachment = match (name, content_type, data) {
+                    (Some(name), Some(content_type), Some(data)) => Some(AttachmentInfo {
+                        evidence_id: evidence_id.to_string(),
+                        name,
+                        content_type,
+                        size: data.len(),
+                    }),
+                    _ => None,
+                };
+                timeline.push(TimelineEntry {
+                    at: timestamp_from_millis(at),
+                    kind: "evidence".to_string(),
+                    actor: party,
+                    message,
+                    attachment,
+                });
+            }
+        }
+    }
+
+    for entry in audit::load_trail(&state.session, &id).await? {
+        if entry.action.starts_with("dispute.") {
+            timeline.push(TimelineEntry {
+                at: entry.at,
+                kind: entry.action,
+                actor: entry.actor,
+                message: entry.details,
+                attachment: None,
+            });
+        }
+    }
+
+    timeline.sort_by_key(|entry| entry.at);
+    Ok(Json(DisputeDetail { dispute, timeline }))
+}
+
+pub async fn add_evidence(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<EvidenceRequest>,
+) -> Result<(StatusCode, Json<TimelineEntry>), StatusCode> {
+    let tx_id = parse_id(&id)?;
+    let dispute = load_dispute(&state.session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if request.party != dispute.opened_by && request.party != dispute.counterparty {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if dispute.status.is_final() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let message = request.message.filter(|m| !m.trim().is_empty());
+    let attachment = match request.attachment {
+        Some(upload) => {
+            let data = BASE64.decode(upload.data.as_bytes()).map_err(|_| StatusCode::BAD_REQUEST)?;
+            if data.len() > MAX_ATTACHMENT_BYTES {
+                return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            }
+            Some((upload.name, upload.content_type, data))
+        }
+        None => None,
+    };
+    if message.is_none() && attachment.is_none() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let at = Utc::now();
+    let evidence_id = Uuid::new_v4();
+    let (name, content_type, data) = match &attachment {
+        Some((name, content_type, data)) => (Some(name.as_str()), Some(content_type.as_str()), Some(data.as_slice())),
+        None => (None, None, None),
+    };
+    let session = &state.session;
+    let sealed_message = pii::seal_opt(session, &request.party, message.as_deref()).await?;
+    let name = pii::seal_opt(session, &request.party, name).await?;
+    let content_type = pii::seal_opt(session, &request.party, content_type).await?;
+
+    state
+        .session
+        .query(
+            "INSERT INTO transactions.dispute_evidence (transaction_id, at, id, party, message, attachment_name, attachment_type, attachment)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            (tx_id, at.timestamp_millis(), evidence_id, &request.party, &sealed_message, name, content_type, data),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to store evidence for {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Evidence added to dispute {} by {}", id, request.party);
+    Ok((
+        StatusCode::CREATED,
+        Json(TimelineEntry {
+            at,
+            kind: "evidence".to_string(),
+            actor: request.party,
+            message,
+            attachment: attachment.map(|(name, content_type, data)| AttachmentInfo {
+                evidence_id: evidence_id.to_string(),
+                name,
+                content_type,
+                size: data.len(),
+            }),
+        }),
+    ))
+}
+
+pub async fn get_attachment(
+    State(state): State<AppState>,
+    Path((id, evidence_id)): Path<(String, String)>,
+) -> Result<Response, StatusCode> {
+    let tx_id = parse_id(&id)?;
+    let evidence_id = parse_id(&evidence_id)?;
+
+    let rows = state
+        .session
+        .query(
+            db::idempotent("SELECT party, attachment_name, attachment_type, attachment
+             FROM transactions.dispute_evidence WHERE transaction_id = ? AND id = ? ALLOW FILTERING"),
+            (tx_id, evidence_id),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (party, name, content_type, data) = rows
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(String, Option<String>, Option<String>, Option<Vec<u8>>)>().ok())
+        .and_then(|(party, name, content_type, data)| Some((party, name?, content_type?, data?)))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let name = pii::open(&state.session, &party, name).await?;
+    let content_type = pii::open(&state.session, &party, content_type).await?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", name.replace('"', ""))),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+/// Moves an open dispute to review. The disputing party must have
+/// submitted evidence first.
+pub async fn escalate_dispute(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+) -> Result<Json<Dispute>, StatusCode> {
+    let reviewer = auth::actor(claims.as_deref(), &headers, "x-reviewer")?;
+    let tx_id = parse_id(&id)?;
+    let mut dispute = load_dispute(&state.session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if dispute.status != DisputeStatus::Open {
+        return Err(StatusCode::CONFLICT);
+    }
+    if !evidence_parties(&state.session, tx_id).await?.contains(&dispute.opened_by) {
+        return Err(StatusCode::PRECONDITION_FAILED);
+    }
+
+    set_status(&state.session, tx_id, DisputeStatus::UnderReview).await?;
+    audit::record(&state.session, &id, "dispute.under_review", &reviewer, None).await?;
+
+    dispute.status = DisputeStatus::UnderReview;
+    dispute.updated_at = Utc::now();
+    Ok(Json(dispute))
+}
+
+/// Closes a dispute. Refunds are only granted from review, after both
+/// parties had the chance to respond; rejections are allowed at any point.
+pub async fn resolve_dispute(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+    Json(request): Json<ResolveRequest>,
+) -> Result<Json<Dispute>, StatusCode> {
+    let reviewer = auth::actor(claims.as_deref(), &headers, "x-reviewer")?;
+    let tx_id = parse_id(&id)?;
+    let mut dispute = load_dispute(&state.session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let outcome = match request.outcome.as_str() {
+        "refund" => DisputeStatus::Refunded,
+        "reject" => DisputeStatus::Rejected,
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+    if dispute.status.is_final() {
+        return Err(StatusCode::CONFLICT);
+    }
+    if outcome == DisputeStatus::Refunded && dispute.status != DisputeStatus::UnderReview {
+        return Err(StatusCode::PRECONDITION_FAILED);
+    }
+
+    set_status(&state.session, tx_id, outcome).await?;
+    audit::record(&state.session, &id, &format!("dispute.{}", outcome), &reviewer, request.note).await?;
+
+    if outcome == DisputeStatus::Refunded {
+        let chargeback = create_chargeback(&state.session, tx_id).await?;
+        audit::record(
+            &state.session,
+            &id,
+            "dispute.chargeback",
+            &reviewer,
+            Some(format!("compensating transaction {}", chargeback.id)),
+        )
+        .await?;
+    }
+
+    info!("Dispute {} resolved as {} by {}", id, outcome, reviewer);
+    dispute.status = outcome;
+    dispute.updated_at = Utc::now();
+    Ok(Json(dispute))
+}
+
+/// Records the compensating entry for a refunded dispute: the original
+/// amount flows back from the receiver to the sender, linked through
+/// `parent_tx_id`.
+async fn create_chargeback(session: &Repository, tx_id: Uuid) -> Result<Transaction, StatusCode> {
+    let original = load_transaction(session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let chargeback = Transaction {
+        id: Uuid::new_v4().to_string(),
+        from_endpoint: original.to_endpoint,
+        to_endpoint: original.from_endpoint,
+        amount: original.amount,
+        timestamp: Utc::now(),
+        signature: String::new(),
+        status: TransactionStatus::Confirmed,
+        kind: TransactionKind::Chargeback,
+        risk_score: None,
+        parent_tx_id: Some(original.id),
+        sequence: None,
+        public_key: None,
+    };
+    insert_transaction(session, &chargeback).await?;
+
+    info!("↩️ Chargeback {} created for {}", chargeback.id, tx_id);
+    Ok(chargeback)
+}
+
+/// Chargebacks involving an endpoint, so clients can apply reversals
+/// decided on the gateway.
+pub async fn get_endpoint_chargebacks(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+) -> Result<Json<Vec<Transaction>>, StatusCode> {
+    let mut chargebacks = Vec::new();
+    for column in ["from_endpoint", "to_endpoint"] {
+        let rows = state
+            .session
+            .query(
+                db::idempotent(format!(
+                    "SELECT {} FROM transactions.tx_log WHERE {} = ? AND kind = ? ALLOW FILTERING",
+                    TX_COLUMNS, column
+                )),
+                (&endpoint_id, TransactionKind::Chargeback.as_str()),
+            )
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        if let Some(rows) = rows.rows {
+            for row in rows {
+                if let Ok(row) = row.into_typed::<TxRow>() {
+                    match transaction_from_row(row) {
+                        Ok(tx) => chargebacks.push(tx),
+                        Err(e) => warn!("Skipping transaction: {}", e),
+                    }
+                }
+            }
+        }
+    }
+
+    chargebacks.sort_by_key(|chargeback| std::cmp::Reverse(chargeback.timestamp));
+    Ok(Json(chargebacks))
+}
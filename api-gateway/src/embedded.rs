@@ -0,0 +1,126 @@
+//! The client apps built into the gateway binary (`--features embed-web`),
+//! so one container serves the API, both endpoint apps and the dashboard.
+//! Each app is embedded from its folder under `api-gateway/web/`, filled
+//! by the build (see `Dockerfile.embedded`) before compiling, and mounted at a
+//! base path:
+//!
+//! - `web/endpoint`: `ws-tx-endpoint`, its pages and `pkg/`, at
+//!   `WEB_ENDPOINT_PATH` (`/endpoint`)
+//! - `web/wrtc`: `wrtc-tx-endpoint`, at `WEB_WRTC_PATH` (`/wrtc`)
+//! - `web/dashboard`: `tx-status`'s `dist/`, at `WEB_DASHBOARD_PATH`
+//!   (`/dashboard`)
+//!
+//! An app whose folder has no `index.html` isn't mounted. Apps are served
+//! like `api-gateway serve-web` serves them (CSP, SRI, hashed names, see
+//! `web`), from the router's fallback so the API routes always win, and
+//! their pages need no credentials. `config.js` points them at this
+//! gateway unless `WEB_API_GATEWAY` says otherwise.
+
+use axum::{
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use rust_embed::RustEmbed;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use tracing::{info, warn};
+
+use crate::web::{Bundle, Policy, RuntimeConfig};
+use crate::AppState;
+
+#[derive(RustEmbed)]
+#[folder = "web/endpoint/"]
+struct Endpoint;
+
+#[derive(RustEmbed)]
+#[folder = "web/wrtc/"]
+struct Wrtc;
+
+#[derive(RustEmbed)]
+#[folder = "web/dashboard/"]
+struct Dashboard;
+
+struct App {
+    /// `/`, or a path without the trailing `/`.
+    base: String,
+    bundle: Bundle,
+}
+
+static APPS: LazyLock<Vec<App>> = LazyLock::new(|| {
+    let config = RuntimeConfig::from_env(true);
+    let policy = Policy::from_env(&config);
+    [
+        ("endpoint", "WEB_ENDPOINT_PATH", files::<Endpoint>()),
+        ("wrtc", "WEB_WRTC_PATH", files::<Wrtc>()),
+        ("dashboard", "WEB_DASHBOARD_PATH", files::<Dashboard>()),
+    ]
+    .into_iter()
+    .filter_map(|(name, base_var, files)| {
+        if files.is_empty() {
+            return None;
+        }
+        let base = std::env::var(base_var).unwrap_or_else(|_| format!("/{}", name));
+        let base = format!("/{}", base.trim_matches('/'));
+        match Bundle::from_files(files, &policy, &config, &format!("embedded {}", name)) {
+            Ok(bundle) => {
+                info!("Serving the {} app at {}/", name, base);
+                Some(App { base, bundle })
+            }
+            Err(e) => {
+                warn!("Not serving the {} app: {}", name, e);
+                None
+            }
+        }
+    })
+    .collect()
+});
+
+/// The embedded files, skipping dotfiles such as the placeholders that
+/// keep the folders in git.
+fn files<E: RustEmbed>() -> HashMap<String, Vec<u8>> {
+    E::iter()
+        .filter(|path| !path.split('/').any(|segment| segment.starts_with('.')))
+        .filter_map(|path| E::get(&path).map(|file| (path.to_string(), file.data.into_owned())))
+        .collect()
+}
+
+/// The app `path` is in, and the path within it. The API keeps its paths
+/// even if an app is mounted at `/`.
+fn app_for(path: &str) -> Option<(&'static App, &str)> {
+    if path.starts_with("/api/") || path == "/health" || path == "/leader" {
+        return None;
+    }
+    APPS.iter().find_map(|app| {
+        let base = app.base.trim_end_matches('/');
+        path.strip_prefix(base)
+            .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+            .map(|rest| (app, rest))
+    })
+}
+
+/// Whether `path` is an app's, for `auth`: app pages are public.
+pub fn serves(path: &str) -> bool {
+    app_for(path).is_some()
+}
+
+/// Loads the apps and serves them from `router`'s fallback.
+pub fn mount(router: Router<AppState>) -> Router<AppState> {
+    if APPS.is_empty() {
+        warn!("Built with embed-web, but no app was embedded");
+    }
+    router.fallback(serve)
+}
+
+async fn serve(method: Method, uri: Uri, headers: HeaderMap) -> Response {
+    let Some((app, rest)) = app_for(uri.path()) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    // The apps load their files relative to the page
+    if rest.is_empty() {
+        let query = uri.query().map(|q| format!("?{}", q)).unwrap_or_default();
+        let location = format!("{}/{}", app.base.trim_end_matches('/'), query);
+        return (StatusCode::PERMANENT_REDIRECT, [(header::LOCATION, location)]).into_response();
+    }
+    app.bundle.response(rest, &method, &headers)
+}
@@ -0,0 +1,367 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use tracing::{error, info};
+
+use crate::db;
+use crate::repository::{Repository, Statement};
+use crate::sends;
+use crate::signatures;
+use crate::{lwt_applied, stored_money, timestamp_from_millis, AppState};
+use tx_core::{Money, NATIVE_ASSET};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum EndpointStatus {
+    #[serde(rename = "active")]
+    Active,
+    #[serde(rename = "suspended")]
+    Suspended,
+    #[serde(rename = "closed")]
+    Closed,
+}
+
+impl EndpointStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EndpointStatus::Active => "active",
+            EndpointStatus::Suspended => "suspended",
+            EndpointStatus::Closed => "closed",
+        }
+    }
+}
+
+impl fmt::Display for EndpointStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EndpointStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(EndpointStatus::Active),
+            "suspended" => Ok(EndpointStatus::Suspended),
+            "closed" => Ok(EndpointStatus::Closed),
+            other => Err(format!("Unknown endpoint status: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Endpoint {
+    pub id: String,
+    pub status: EndpointStatus,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub initial_balance: Money,
+    #[serde(default, with = "tx_core::money::as_major_opt")]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub max_transaction_amount: Option<Money>,
+    #[serde(default, with = "tx_core::money::as_major_opt")]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub daily_send_limit: Option<Money>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CreateEndpointRequest {
+    pub id: String,
+    #[serde(default = "no_balance", with = "tx_core::money::as_major")]
+    pub initial_balance: Money,
+    #[serde(default, with = "tx_core::money::as_major_opt")]
+    pub max_transaction_amount: Option<Money>,
+    #[serde(default, with = "tx_core::money::as_major_opt")]
+    pub daily_send_limit: Option<Money>,
+    /// Hex Ed25519 key the endpoint signs transfers with, registered to it
+    /// on creation.
+    #[serde(default)]
+    pub public_key: Option<String>,
+}
+
+fn no_balance() -> Money {
+    Money::zero(NATIVE_ASSET)
+}
+
+pub async fn load_endpoint(session: &Repository, id: &str) -> Result<Option<Endpoint>, StatusCode> {
+    let rows = session
+        .execute(Statement::EndpointById, (id,))
+        .await
+        .map_err(|e| {
+            error!("Failed to load endpoint {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    if let Some(rows) = rows.rows {
+        if let Some(row) = rows.into_iter().next() {
+            if let Ok(row) = row.into_typed::<EndpointRow>() {
+                return endpoint_from_row(row).map(Some);
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+pub const ENDPOINT_COLUMNS: &str = "id, status, initial_balance, initial_balance_minor, max_transaction_amount, \
+     max_transaction_minor, daily_send_limit, daily_send_limit_minor, created_at, updated_at";
+
+type EndpointRow = (
+    String,
+    String,
+    Option<f64>,
+    Option<i64>,
+    Option<f64>,
+    Option<i64>,
+    Option<f64>,
+    Option<i64>,
+    i64,
+    i64,
+);
+
+fn endpoint_from_row(row: EndpointRow) -> Result<Endpoint, StatusCode> {
+    let (id, status, initial_major, initial_minor, max_major, max_minor, limit_major, limit_minor, created_at, updated_at) =
+        row;
+    let invalid = |e: &dyn fmt::Display| {
+        error!("Endpoint {}: {}", id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let limit = |minor: Option<i64>, major: Option<f64>| {
+        (minor.is_some() || major.is_some())
+            .then(|| stored_money(minor, major, NATIVE_ASSET))
+            .transpose()
+            .map_err(|e| invalid(&e))
+    };
+    Ok(Endpoint {
+        status: status.parse().map_err(|e: String| invalid(&e))?,
+        initial_balance: stored_money(initial_minor, initial_major, NATIVE_ASSET).map_err(|e| invalid(&e))?,
+        max_transaction_amount: limit(max_minor, max_major)?,
+        daily_send_limit: limit(limit_minor, limit_major)?,
+        id,
+        created_at: timestamp_from_millis(created_at),
+        updated_at: timestamp_from_millis(updated_at),
+    })
+}
+
+/// Every registered endpoint, by id. The registry is small enough to
+/// scan.
+pub async fn list_endpoints(session: &Session) -> Result<Vec<Endpoint>, StatusCode> {
+    let rows = session
+        .query(
+            db::scan(format!("SELECT {} FROM transactions.endpoints", ENDPOINT_COLUMNS)),
+            &[],
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to list endpoints: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    let mut endpoints = rows
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<EndpointRow>().ok())
+        .map(endpoint_from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+    endpoints.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(endpoints)
+}
+
+/// Endpoints that have never been provisioned are allowed to transact unless
+/// `REQUIRE_PROVISIONED_ENDPOINTS` is set, so existing deployments keep working.
+fn require_provisioned() -> bool {
+    std::env::var("REQUIRE_PROVISIONED_ENDPOINTS")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
+}
+
+/// Ingest-side enforcement of endpoint lifecycle and limits.
+pub async fn check_transaction_allowed(
+    session: &Repository,
+    from_endpoint: &str,
+    to_endpoint: &str,
+    amount: &Money,
+) -> Result<(), StatusCode> {
+    match load_endpoint(session, from_endpoint).await? {
+        Some(sender) => {
+            if sender.status != EndpointStatus::Active {
+                info!("Rejecting send from {} endpoint {}", sender.status, sender.id);
+                return Err(StatusCode::FORBIDDEN);
+            }
+            if let Some(max) = sender.max_transaction_amount {
+                if max.checked_sub(amount).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?.is_negative() {
+                    return Err(StatusCode::UNPROCESSABLE_ENTITY);
+                }
+            }
+            if let Some(limit) = sender.daily_send_limit {
+                let remaining = sent_today(session, from_endpoint)
+                    .await?
+                    .checked_add(amount)
+                    .and_then(|total| limit.checked_sub(&total))
+                    .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+                if remaining.is_negative() {
+                    return Err(StatusCode::UNPROCESSABLE_ENTITY);
+                }
+            }
+        }
+        None if require_provisioned() => return Err(StatusCode::FORBIDDEN),
+        None => {}
+    }
+
+    match load_endpoint(session, to_endpoint).await? {
+        Some(receiver) if receiver.status == EndpointStatus::Closed => Err(StatusCode::FORBIDDEN),
+        None if require_provisioned() => Err(StatusCode::FORBIDDEN),
+        _ => Ok(()),
+    }
+}
+
+async fn sent_today(session: &Session, endpoint_id: &str) -> Result<Money, StatusCode> {
+    let start_of_day = Utc::now()
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .map(|dt| dt.and_utc().timestamp_millis())
+        .unwrap_or_default();
+
+    let amounts = sends::amounts_since(session, endpoint_id, start_of_day).await.map_err(|e| {
+        error!("Failed to read today's sends for {}: {}", endpoint_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let mut total = Money::zero(NATIVE_ASSET);
+    for amount in amounts {
+        total = total.checked_add(&amount).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    }
+    Ok(total)
+}
+
+pub async fn create_endpoint(
+    State(state): State<AppState>,
+    Json(request): Json<CreateEndpointRequest>,
+) -> Result<(StatusCode, Json<Endpoint>), StatusCode> {
+    let negative = |amount: &Option<Money>| amount.as_ref().is_some_and(Money::is_negative);
+    if request.id.trim().is_empty()
+        || request.initial_balance.is_negative()
+        || negative(&request.max_transaction_amount)
+        || negative(&request.daily_send_limit)
+        || request.public_key.as_deref().is_some_and(|key| !signatures::well_formed(key))
+    {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let now = Utc::now();
+    let endpoint = Endpoint {
+        id: request.id,
+        status: EndpointStatus::Active,
+        initial_balance: request.initial_balance,
+        max_transaction_amount: request.max_transaction_amount,
+        daily_send_limit: request.daily_send_limit,
+        created_at: now,
+        updated_at: now,
+    };
+
+    // Conditional, so of two concurrent creates only one takes the id
+    let result = state
+        .session
+        .query(
+            "INSERT INTO transactions.endpoints (id, status, initial_balance_minor, max_transaction_minor, daily_send_limit_minor,
+                created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            (
+                &endpoint.id,
+                endpoint.status.as_str(),
+                endpoint.initial_balance.minor(),
+                endpoint.max_transaction_amount.as_ref().map(Money::minor),
+                endpoint.daily_send_limit.as_ref().map(Money::minor),
+                now.timestamp_millis(),
+                now.timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to provision endpoint: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !lwt_applied(result) {
+        return Err(StatusCode::CONFLICT);
+    }
+    if let Some(public_key) = &request.public_key {
+        signatures::register_key(&state.session, &endpoint.id, public_key).await?;
+    }
+
+    info!("✅ Endpoint {} provisioned", endpoint.id);
+    Ok((StatusCode::CREATED, Json(endpoint)))
+}
+
+pub async fn get_endpoint(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Endpoint>, StatusCode> {
+    load_endpoint(&state.session, &id)
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+pub async fn suspend_endpoint(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Endpoint>, StatusCode> {
+    transition(&state.session, &id, EndpointStatus::Suspended).await
+}
+
+pub async fn activate_endpoint(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Endpoint>, StatusCode> {
+    transition(&state.session, &id, EndpointStatus::Active).await
+}
+
+pub async fn close_endpoint(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Endpoint>, StatusCode> {
+    transition(&state.session, &id, EndpointStatus::Closed).await
+}
+
+async fn transition(
+    session: &Repository,
+    id: &str,
+    target: EndpointStatus,
+) -> Result<Json<Endpoint>, StatusCode> {
+    let mut endpoint = load_endpoint(session, id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    // Closed is terminal
+    if endpoint.status == EndpointStatus::Closed && target != EndpointStatus::Closed {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let now = Utc::now();
+    session
+        .query(
+            "UPDATE transactions.endpoints SET status = ?, updated_at = ? WHERE id = ?",
+            (target.as_str(), now.timestamp_millis(), id),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to update endpoint {}: {}", id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Endpoint {} is now {}", id, target);
+    endpoint.status = target;
+    endpoint.updated_at = now;
+    Ok(Json(endpoint))
+}
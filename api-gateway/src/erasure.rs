@@ -0,0 +1,254 @@
+//! Erasure of an endpoint's personal data, for GDPR requests: `DELETE
+//! /api/endpoints/:id/personal-data` (admin). Endpoints carry no names or
+//! profile; the endpoint id stays, as the ledger's pseudonym for whoever
+//! holds it, and what is erased is the free text and account details
+//! attached to it:
+//!
+//! - the reasons of disputes it opened, and the messages and attachments
+//!   of evidence it submitted
+//! - remittance information on payment instructions to or from it, and
+//!   its reasons for declining them
+//! - the payout destinations of its settlements
+//! - its counterparty block and allow lists
+//! - audit details it wrote on those disputes and payment files
+//!
+//! Its consents are revoked, which tells the third parties holding them.
+//!
+//! Transactions, balances and projections hold ids and amounts only and
+//! are kept, as is the event log: its payloads are hash-chained into
+//! receipts and snapshots, so rewriting them would break every proof over
+//! the endpoint's partition. Dispute and settlement events there keep the
+//! text as first recorded, as part of the ledger's retained record.
+//!
+//! Each erasure is recorded with what it touched, and can be repeated, e.g.
+//! for data recorded after it.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::auth::{self, Claims};
+use crate::db;
+use crate::payment_files::InstructionStatus;
+use crate::{audit, consents, counterparties, AppState};
+
+/// What erased free text reads as.
+pub const ERASED: &str = "[erased]";
+
+#[derive(Clone, Debug, Default, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ErasureReport {
+    pub endpoint_id: String,
+    pub erased_at: DateTime<Utc>,
+    pub erased_by: String,
+    pub disputes: usize,
+    pub evidence: usize,
+    pub payment_instructions: usize,
+    pub settlements: usize,
+    pub counterparty_entries: usize,
+    pub audit_entries: usize,
+    pub consents_revoked: usize,
+}
+
+fn db_error<E: fmt::Display>(endpoint_id: &str) -> impl Fn(E) -> StatusCode + '_ {
+    move |e| {
+        error!("Erasure for {} failed: {}", endpoint_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// `DELETE /api/endpoints/:id/personal-data`
+pub async fn erase_personal_data(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    headers: HeaderMap,
+    claims: Option<Extension<Claims>>,
+) -> Result<Json<ErasureReport>, StatusCode> {
+    let erased_by = auth::actor(claims.as_deref(), &headers, "x-admin")?;
+    let session = &state.session;
+    let mut report = ErasureReport {
+        endpoint_id: endpoint_id.clone(),
+        erased_at: Utc::now(),
+        erased_by: erased_by.clone(),
+        ..ErasureReport::default()
+    };
+
+    // Audit entries quoting its text live under these
+    let mut entities = BTreeSet::new();
+    erase_disputes(session, &endpoint_id, &mut report, &mut entities).await?;
+    erase_payment_instructions(session, &endpoint_id, &mut report, &mut entities).await?;
+    erase_settlements(session, &endpoint_id, &mut report).await?;
+
+    report.counterparty_entries = counterparties::clear_lists(session, &endpoint_id).await?;
+    for entity_id in &entities {
+        for entry in audit::load_trail(session, entity_id).await? {
+            if entry.actor == endpoint_id && entry.details.as_deref().is_some_and(|details| details != ERASED) {
+                audit::redact(session, &entry, ERASED).await?;
+                report.audit_entries += 1;
+            }
+        }
+    }
+    report.consents_revoked = consents::revoke_all(&state, &endpoint_id, &erased_by).await?;
+
+    let body = serde_json::to_string(&report).map_err(db_error(&endpoint_id))?;
+    session
+        .query(
+            db::idempotent(
+                "INSERT INTO transactions.erasures (endpoint_id, erased_at, erased_by, report) VALUES (?, ?, ?, ?)",
+            ),
+            (&endpoint_id, report.erased_at.timestamp_millis(), &erased_by, &body),
+        )
+        .await
+        .map_err(db_error(&endpoint_id))?;
+    audit::record(session, &endpoint_id, "personal_data.erased", &erased_by, Some(body)).await?;
+
+    info!("Erased personal data of {} for {}", endpoint_id, erased_by);
+    Ok(Json(report))
+}
+
+async fn dispute_ids(session: &Session, endpoint_id: &str, column: &str) -> Result<Vec<Uuid>, StatusCode> {
+    let result = session
+        .query(
+            db::idempotent(format!(
+                "SELECT transaction_id FROM transactions.disputes WHERE {} = ? ALLOW FILTERING",
+                column
+            )),
+            (endpoint_id,),
+        )
+        .await
+        .map_err(db_error(endpoint_id))?;
+    Ok(result
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(Uuid,)>().ok())
+        .map(|(id,)| id)
+        .collect())
+}
+
+async fn erase_disputes(
+    session: &Session,
+    endpoint_id: &str,
+    report: &mut ErasureReport,
+    entities: &mut BTreeSet<String>,
+) -> Result<(), StatusCode> {
+    let opened = dispute_ids(session, endpoint_id, "opened_by").await?;
+    for transaction_id in &opened {
+        session
+            .query(
+                db::idempotent("UPDATE transactions.disputes SET reason = ? WHERE transaction_id = ?"),
+                (ERASED, transaction_id),
+            )
+            .await
+            .map_err(db_error(endpoint_id))?;
+        report.disputes += 1;
+    }
+
+    let mut disputes = opened;
+    disputes.extend(dispute_ids(session, endpoint_id, "counterparty").await?);
+    for transaction_id in disputes {
+        entities.insert(transaction_id.to_string());
+        let result = session
+            .query(
+                db::idempotent(
+                    "SELECT at, id FROM transactions.dispute_evidence \
+                     WHERE transaction_id = ? AND party = ? ALLOW FILTERING",
+                ),
+                (transaction_id, endpoint_id),
+            )
+            .await
+            .map_err(db_error(endpoint_id))?;
+        let rows = result.rows.unwrap_or_default().into_iter().filter_map(|row| row.into_typed::<(i64, Uuid)>().ok());
+        for (at, id) in rows {
+            session
+                .query(
+                    db::idempotent(
+                        "UPDATE transactions.dispute_evidence \
+                         SET message = ?, attachment_name = null, attachment_type = null, attachment = null \
+                         WHERE transaction_id = ? AND at = ? AND id = ?",
+                    ),
+                    (ERASED, transaction_id, at, id),
+                )
+                .await
+                .map_err(db_error(endpoint_id))?;
+            report.evidence += 1;
+        }
+    }
+    Ok(())
+}
+
+async fn erase_payment_instructions(
+    session: &Session,
+    endpoint_id: &str,
+    report: &mut ErasureReport,
+    entities: &mut BTreeSet<String>,
+) -> Result<(), StatusCode> {
+    for column in ["from_endpoint", "to_endpoint"] {
+        let result = session
+            .query(
+                db::idempotent(format!(
+                    "SELECT file_id, position, status, reason FROM transactions.payment_instructions \
+                     WHERE {} = ? ALLOW FILTERING",
+                    column
+                )),
+                (endpoint_id,),
+            )
+            .await
+            .map_err(db_error(endpoint_id))?;
+        let rows = result
+            .rows
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|row| row.into_typed::<(Uuid, i32, String, Option<String>)>().ok());
+        for (file_id, position, status, reason) in rows {
+            // Declines are the sender's words, other reasons the gateway's
+            let declined = column == "from_endpoint" && status == InstructionStatus::Declined.as_str();
+            let reason = if declined { Some(ERASED.to_string()) } else { reason };
+            session
+                .query(
+                    db::idempotent(
+                        "UPDATE transactions.payment_instructions SET remittance = null, reason = ? \
+                         WHERE file_id = ? AND position = ?",
+                    ),
+                    (reason, file_id, position),
+                )
+                .await
+                .map_err(db_error(endpoint_id))?;
+            entities.insert(file_id.to_string());
+            report.payment_instructions += 1;
+        }
+    }
+    Ok(())
+}
+
+async fn erase_settlements(session: &Session, endpoint_id: &str, report: &mut ErasureReport) -> Result<(), StatusCode> {
+    let result = session
+        .query(
+            db::idempotent("SELECT transaction_id FROM transactions.settlements WHERE endpoint_id = ? ALLOW FILTERING"),
+            (endpoint_id,),
+        )
+        .await
+        .map_err(db_error(endpoint_id))?;
+    let rows = result.rows.unwrap_or_default().into_iter().filter_map(|row| row.into_typed::<(Uuid,)>().ok());
+    for (transaction_id,) in rows {
+        session
+            .query(
+                db::idempotent("UPDATE transactions.settlements SET destination = ? WHERE transaction_id = ?"),
+                (ERASED, transaction_id),
+            )
+            .await
+            .map_err(db_error(endpoint_id))?;
+        report.settlements += 1;
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! Error responses. Every failure the gateway answers with is a JSON body
+//! clients can act on:
+//!
+//! `{"code": "not_found", "message": "...", "details": ..., "request_id": "..."}`
+//!
+//! `code` is stable and machine-readable, `message` is for people and may
+//! change, `details` carries what a particular error knows (the limit that
+//! was hit, the field that was wrong) and `request_id` matches the
+//! `X-Request-Id` response header and the gateway's logs for the request.
+//!
+//! Handlers return `ApiError`, which keeps database, parse and validation
+//! failures apart. Those still returning a bare `StatusCode`, and axum's
+//! own extractor rejections, are turned into the same body by
+//! `request_ids`, which also stamps every error with its request id.
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Json, Response},
+};
+use scylla::transport::errors::QueryError;
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use tracing::{error, info_span, Instrument};
+use uuid::Uuid;
+
+use crate::db;
+use crate::validation::FieldError;
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+/// Longest client-supplied request id kept; longer ones are replaced.
+const MAX_REQUEST_ID_LEN: usize = 128;
+/// Largest plain-text error body carried over as a message.
+const MAX_MESSAGE_BYTES: usize = 4096;
+
+/// What a failed request gets back.
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    #[cfg_attr(feature = "ts", ts(type = "unknown"))]
+    pub details: Option<Value>,
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ApiError {
+    /// A well-formed request the gateway's rules refuse: 422, with the
+    /// fields at fault in `details` where known.
+    Validation { message: String, details: Option<Value> },
+    /// An id, parameter or body that doesn't parse.
+    Parse(String),
+    NotFound(String),
+    Conflict(String),
+    RateLimited { message: String, retry_after_secs: u64, details: Option<Value> },
+    /// A failed query; transient failures are worth retrying.
+    Database(QueryError),
+    /// A status from code that doesn't say more.
+    Status(StatusCode),
+}
+
+impl ApiError {
+    pub fn validation(message: impl Into<String>) -> Self {
+        ApiError::Validation { message: message.into(), details: None }
+    }
+
+    /// A validation failure listing each field at fault.
+    pub fn invalid_fields(message: &str, fields: Vec<FieldError>) -> Self {
+        let names: Vec<&str> = fields.iter().map(|field| field.field.as_str()).collect();
+        ApiError::Validation {
+            message: format!("{}: {}", message, names.join(", ")),
+            details: Some(serde_json::json!({ "fields": fields })),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ApiError::Validation { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::Parse(_) => StatusCode::BAD_REQUEST,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Database(e) if db::transient(e) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            ApiError::Status(status) => *status,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Validation { .. } => "validation_failed",
+            ApiError::Parse(_) => "malformed_request",
+            ApiError::Database(e) if db::transient(e) => "database_unavailable",
+            ApiError::Database(_) => "database_error",
+            _ => status_code(self.status()),
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        let (message, details) = match self {
+            ApiError::Validation { message, details } | ApiError::RateLimited { message, details, .. } => {
+                (message.clone(), details.clone())
+            }
+            ApiError::Parse(message) | ApiError::NotFound(message) | ApiError::Conflict(message) => {
+                (message.clone(), None)
+            }
+            // What failed is for the logs, not the client
+            ApiError::Database(_) | ApiError::Status(_) => (reason(self.status()), None),
+        };
+        ErrorBody { code: self.code().to_string(), message, details, request_id: None }
+    }
+}
+
+/// The code of an error known only by its status.
+fn status_code(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "bad_request",
+        StatusCode::UNAUTHORIZED => "unauthorized",
+        StatusCode::FORBIDDEN => "forbidden",
+        StatusCode::NOT_FOUND => "not_found",
+        StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+        StatusCode::CONFLICT => "conflict",
+        StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+        StatusCode::UNPROCESSABLE_ENTITY => "unprocessable_entity",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+        StatusCode::SERVICE_UNAVAILABLE => "service_unavailable",
+        StatusCode::BAD_GATEWAY | StatusCode::GATEWAY_TIMEOUT => "upstream_error",
+        status if status.is_client_error() => "client_error",
+        _ => "internal_error",
+    }
+}
+
+fn reason(status: StatusCode) -> String {
+    status.canonical_reason().unwrap_or("Error").to_string()
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Database(e) => write!(f, "database error: {}", e),
+            _ => write!(f, "{}: {}", self.code(), self.body().message),
+        }
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        ApiError::Status(status)
+    }
+}
+
+/// Logged here, as the client only learns that the database failed.
+impl From<QueryError> for ApiError {
+    fn from(e: QueryError) -> Self {
+        error!("Database query error: {}", e);
+        ApiError::Database(e)
+    }
+}
+
+/// For code still returning bare status codes.
+impl From<ApiError> for StatusCode {
+    fn from(e: ApiError) -> Self {
+        e.status()
+    }
+}
+
+impl From<uuid::Error> for ApiError {
+    fn from(e: uuid::Error) -> Self {
+        ApiError::Parse(format!("invalid id: {}", e))
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(e: serde_json::Error) -> Self {
+        ApiError::Parse(e.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = self.body();
+        let mut response = (self.status(), Json(body.clone())).into_response();
+        if let ApiError::RateLimited { retry_after_secs, .. } = self {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+        }
+        // For `request_ids` to stamp
+        response.extensions_mut().insert(body);
+        response
+    }
+}
+
+fn request_id(request: &Request) -> String {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Middleware giving every request an id, the client's `X-Request-Id` or a
+/// new one, echoed in the response and logged with everything the request
+/// does. Error responses get the JSON body above with the id filled in.
+pub async fn request_ids(request: Request, next: Next) -> Response {
+    let id = request_id(&request);
+    let span = info_span!("request", request_id = %id, method = %request.method(), path = %request.uri().path());
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    if !response.status().is_client_error() && !response.status().is_server_error() {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let mut error = match parts.extensions.remove::<ErrorBody>() {
+        Some(error) => error,
+        None => {
+            let is_json = parts
+                .headers
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.starts_with("application/json"));
+            // Replayed and otherwise already structured bodies go out as they are
+            if is_json {
+                return Response::from_parts(parts, body);
+            }
+            let mut error = ApiError::Status(parts.status).body();
+            let text = axum::body::to_bytes(body, MAX_MESSAGE_BYTES).await.unwrap_or_default();
+            let text = String::from_utf8_lossy(&text).trim().to_string();
+            if !text.is_empty() {
+                error.message = text;
+            }
+            error
+        }
+    };
+    error.request_id = Some(id);
+    let mut response = (parts.status, Json(error)).into_response();
+    for (name, value) in &parts.headers {
+        if name != header::CONTENT_TYPE && name != header::CONTENT_LENGTH {
+            response.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    response
+}
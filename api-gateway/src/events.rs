@@ -0,0 +1,676 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::hotspots;
+use crate::{lwt_applied, timestamp_from_millis, AppState, Transaction};
+
+const RELAY_INTERVAL: Duration = Duration::from_secs(1);
+const MAX_OFFSET_ATTEMPTS: usize = 20;
+/// Events per query when reading a partition through.
+const PAGE_SIZE: i32 = 1000;
+
+/// The ledger's write model: every change is one of these, appended to the
+/// event log first and then applied to the projections (see `projections`).
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum EventKind {
+    #[serde(rename = "transaction.created")]
+    TransactionCreated,
+    #[serde(rename = "transaction.status_changed")]
+    StatusChanged,
+    #[serde(rename = "settlement.updated")]
+    Settled,
+    #[serde(rename = "dispute.opened")]
+    Disputed,
+    /// A chargeback entry reversing an earlier transfer.
+    #[serde(rename = "transaction.reversed")]
+    Reversed,
+}
+
+impl EventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::TransactionCreated => "transaction.created",
+            EventKind::StatusChanged => "transaction.status_changed",
+            EventKind::Settled => "settlement.updated",
+            EventKind::Disputed => "dispute.opened",
+            EventKind::Reversed => "transaction.reversed",
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transaction.created" => Ok(EventKind::TransactionCreated),
+            "transaction.status_changed" => Ok(EventKind::StatusChanged),
+            "settlement.updated" => Ok(EventKind::Settled),
+            "dispute.opened" => Ok(EventKind::Disputed),
+            "transaction.reversed" => Ok(EventKind::Reversed),
+            other => Err(format!("Unknown event type: {}", other)),
+        }
+    }
+}
+
+/// A ledger change as seen by downstream consumers. Events are partitioned
+/// by the sending endpoint; `offset` increases by one per event within a
+/// partition, and `tx_hash` identifies the transaction's immutable content,
+/// so consumers can deduplicate and resume after an outage.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Event {
+    pub partition_key: String,
+    pub offset: i64,
+    pub event_id: String,
+    pub event_type: String,
+    pub transaction_id: String,
+    pub tx_hash: String,
+    pub payload: serde_json::Value,
+    pub at: DateTime<Utc>,
+}
+
+#[async_trait]
+pub trait EventPublisher: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Delivers one event. Returning an error stops the partition at this
+    /// offset; the relay retries it on the next pass.
+    async fn publish(&self, event: &Event) -> Result<(), String>;
+}
+
+/// Writes events to the log only. Used when no broker is configured.
+pub struct LogEventPublisher;
+
+#[async_trait]
+impl EventPublisher for LogEventPublisher {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    async fn publish(&self, event: &Event) -> Result<(), String> {
+        debug!("Event {}#{} {} for {}", event.partition_key, event.offset, event.event_type, event.transaction_id);
+        Ok(())
+    }
+}
+
+/// Posts each event to an HTTP endpoint, e.g. a Kafka REST proxy or a NATS
+/// bridge.
+pub struct WebhookEventPublisher {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[async_trait]
+impl EventPublisher for WebhookEventPublisher {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn publish(&self, event: &Event) -> Result<(), String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .header("Idempotency-Key", format!("{}:{}", event.partition_key, event.offset))
+            .json(event)
+            .send()
+            .await
+            .map_err(|e| format!("Event webhook failed: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Event webhook returned {}", response.status()));
+        }
+        Ok(())
+    }
+}
+
+/// Selects the publisher from `EVENT_PUBLISHER` (`log` or `webhook`).
+pub fn publisher_from_env() -> Arc<dyn EventPublisher> {
+    match std::env::var("EVENT_PUBLISHER").as_deref() {
+        Ok("webhook") => match std::env::var("EVENT_WEBHOOK_URL") {
+            Ok(url) => {
+                info!("Publishing events to {}", url);
+                Arc::new(WebhookEventPublisher {
+                    client: reqwest::Client::new(),
+                    url,
+                })
+            }
+            Err(_) => {
+                warn!("EVENT_WEBHOOK_URL not set, falling back to log event publisher");
+                Arc::new(LogEventPublisher)
+            }
+        },
+        _ => Arc::new(LogEventPublisher),
+    }
+}
+
+/// SHA-256 over the fields that never change after a transaction is created.
+pub fn transaction_hash(tx: &Transaction) -> String {
+    let canonical = serde_json::json!({
+        "id": tx.id,
+        "from": tx.from_endpoint,
+        "to": tx.to_endpoint,
+        "amount": tx.amount.to_major(),
+        "timestamp": tx.timestamp.timestamp_millis(),
+        "kind": tx.kind.as_str(),
+    });
+    Sha256::digest(canonical.to_string().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+async fn next_offset(session: &Session, partition_key: &str) -> Result<i64, String> {
+    for _ in 0..MAX_OFFSET_ATTEMPTS {
+        let current = session
+            .query(
+                db::idempotent("SELECT last_offset FROM transactions.event_offsets WHERE partition_key = ?"),
+                (partition_key,),
+            )
+            .await
+            .map_err(|e| e.to_string())?
+            .rows
+            .and_then(|rows| rows.into_iter().next())
+            .and_then(|row| row.into_typed::<(i64,)>().ok())
+            .map(|(last,)| last);
+
+        let claimed = match current {
+            None => session
+                .query(
+                    "INSERT INTO transactions.event_offsets (partition_key, last_offset)
+                     VALUES (?, 1) IF NOT EXISTS",
+                    (partition_key,),
+                )
+                .await,
+            Some(last) => session
+                .query(
+                    "UPDATE transactions.event_offsets SET last_offset = ?
+                     WHERE partition_key = ? IF last_offset = ?",
+                    (last + 1, partition_key, last),
+                )
+                .await,
+        }
+        .map_err(|e| e.to_string())?;
+
+        if lwt_applied(claimed) {
+            return Ok(current.unwrap_or(0) + 1);
+        }
+    }
+    Err(format!("Contention allocating offset for {}", partition_key))
+}
+
+/// Appends an event for `tx` to its sender's partition, carrying the
+/// transaction itself. The relay publishes it asynchronously, so callers
+/// never wait on the broker.
+pub async fn append(session: &Session, kind: EventKind, tx: &Transaction) -> Result<Event, StatusCode> {
+    let payload = serde_json::to_value(tx).unwrap_or_default();
+    append_with(session, kind, tx, payload, Utc::now()).await
+}
+
+/// Like `append`, with an explicit payload (e.g. a settlement or dispute
+/// record about `tx`) and event time.
+pub async fn append_with(
+    session: &Session,
+    kind: EventKind,
+    tx: &Transaction,
+    payload: serde_json::Value,
+    at: DateTime<Utc>,
+) -> Result<Event, StatusCode> {
+    let partition_key = tx.from_endpoint.clone();
+    let offset = next_offset(session, &partition_key).await.map_err(|e| {
+        error!("Failed to allocate event offset: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let event = new_event(kind, tx, partition_key, offset, payload, at);
+    write(session, &event, tx).await?;
+    Ok(event)
+}
+
+fn new_event(
+    kind: EventKind,
+    tx: &Transaction,
+    partition_key: String,
+    offset: i64,
+    payload: serde_json::Value,
+    at: DateTime<Utc>,
+) -> Event {
+    Event {
+        partition_key,
+        offset,
+        event_id: Uuid::new_v4().to_string(),
+        event_type: kind.as_str().to_string(),
+        transaction_id: tx.id.clone(),
+        tx_hash: transaction_hash(tx),
+        payload,
+        at,
+    }
+}
+
+/// Like `append`, at most once per transaction and `kind`: a retry of a
+/// request that already appended it (e.g. one that failed while
+/// projecting) gets the recorded event back instead of a second one.
+/// A different transaction reusing the id is a 409, not a replay.
+/// `transactions.events_by_transaction` points at the offset the first
+/// attempt claimed, before the event itself is written there.
+pub async fn append_once(session: &Session, kind: EventKind, tx: &Transaction) -> Result<Event, StatusCode> {
+    let db_error = |e: String| {
+        error!("Failed to append event for {}: {}", tx.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let mut slot = None;
+    for _ in 0..MAX_OFFSET_ATTEMPTS {
+        if let Some((partition_key, offset)) = recorded_offset(session, kind, &tx.id).await.map_err(db_error)? {
+            if let Some(event) = load_event(session, &partition_key, offset).await.map_err(db_error)? {
+                if !records(&event, tx) {
+                    warn!("{} for {} already appended for a different transaction", kind, tx.id);
+                    return Err(StatusCode::CONFLICT);
+                }
+                info!("{} for {} already appended at {}#{}", kind, tx.id, partition_key, offset);
+                return Ok(event);
+            }
+            // The attempt that claimed the offset died before writing to it
+            slot = Some((partition_key, offset));
+            break;
+        }
+
+        let partition_key = tx.from_endpoint.clone();
+        let offset = next_offset(session, &partition_key).await.map_err(db_error)?;
+        let claimed = session
+            .query(
+                "INSERT INTO transactions.events_by_transaction (transaction_id, event_type, partition_key, event_offset)
+                 VALUES (?, ?, ?, ?) IF NOT EXISTS",
+                (&tx.id, kind.as_str(), &partition_key, offset),
+            )
+            .await
+            .map_err(|e| db_error(e.to_string()))?;
+        if lwt_applied(claimed) {
+            slot = Some((partition_key, offset));
+            break;
+        }
+        // A concurrent attempt got there first and this offset stays a gap;
+        // the next pass finds its claim
+    }
+    let (partition_key, offset) = slot.ok_or_else(|| db_error(format!("Contention appending {} for {}", kind, tx.id)))?;
+
+    let payload = serde_json::to_value(tx).unwrap_or_default();
+    let event = new_event(kind, tx, partition_key, offset, payload, Utc::now());
+    write(session, &event, tx).await?;
+    Ok(event)
+}
+
+/// Whether `event` was appended for `tx`, not just for its id.
+fn records(event: &Event, tx: &Transaction) -> bool {
+    event.tx_hash == transaction_hash(tx)
+}
+
+async fn recorded_offset(
+    session: &Session,
+    kind: EventKind,
+    transaction_id: &str,
+) -> Result<Option<(String, i64)>, String> {
+    Ok(session
+        .query(
+            db::idempotent(
+                "SELECT partition_key, event_offset FROM transactions.events_by_transaction
+                 WHERE transaction_id = ? AND event_type = ?",
+            ),
+            (transaction_id, kind.as_str()),
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(String, i64)>().ok()))
+}
+
+async fn load_event(session: &Session, partition_key: &str, offset: i64) -> Result<Option<Event>, String> {
+    Ok(session
+        .query(
+            db::idempotent(format!(
+                "SELECT {} FROM transactions.events WHERE partition_key = ? AND event_offset = ?",
+                EVENT_COLUMNS
+            )),
+            (partition_key, offset),
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<EventRow>().ok())
+        .map(event_from_row))
+}
+
+async fn write(session: &Session, event: &Event, tx: &Transaction) -> Result<(), StatusCode> {
+    session
+        .query(
+            "INSERT INTO transactions.events (partition_key, event_offset, event_id, event_type, transaction_id, counterparty, tx_hash, payload, at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            (
+                &event.partition_key,
+                event.offset,
+                Uuid::parse_str(&event.event_id).unwrap_or_default(),
+                &event.event_type,
+                &event.transaction_id,
+                &tx.to_endpoint,
+                &event.tx_hash,
+                event.payload.to_string(),
+                event.at.timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to append event for {}: {}", tx.id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    hotspots::record(&event.partition_key);
+    Ok(())
+}
+
+type EventRow = (String, i64, Uuid, String, String, String, String, i64);
+
+const EVENT_COLUMNS: &str =
+    "partition_key, event_offset, event_id, event_type, transaction_id, tx_hash, payload, at";
+
+fn event_from_row(row: EventRow) -> Event {
+    let (partition_key, event_offset, event_id, event_type, transaction_id, tx_hash, payload, at) = row;
+    Event {
+        partition_key,
+        offset: event_offset,
+        event_id: event_id.to_string(),
+        event_type,
+        transaction_id,
+        tx_hash,
+        payload: serde_json::from_str(&payload).unwrap_or_default(),
+        at: timestamp_from_millis(at),
+    }
+}
+
+pub async fn load_events(
+    session: &Session,
+    partition_key: Option<&str>,
+    after_offset: i64,
+    limit: i32,
+) -> Result<Vec<Event>, String> {
+    let result = match partition_key {
+        Some(key) => {
+            session
+                .query(
+                    db::scan(format!(
+                        "SELECT {} FROM transactions.events WHERE partition_key = ? AND event_offset > ? LIMIT ?",
+                        EVENT_COLUMNS
+                    )),
+                    (key, after_offset, limit),
+                )
+                .await
+        }
+        None => {
+            session
+                .query(
+                    db::scan(format!(
+                        "SELECT {} FROM transactions.events WHERE event_offset > ? LIMIT ? ALLOW FILTERING",
+                        EVENT_COLUMNS
+                    )),
+                    (after_offset, limit),
+                )
+                .await
+        }
+    }
+    .map_err(|e| e.to_string())?;
+
+    let mut events = Vec::new();
+    if let Some(rows) = result.rows {
+        for row in rows {
+            if let Ok(row) = row.into_typed::<EventRow>() {
+                events.push(event_from_row(row));
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Every partition and the last offset handed out in it.
+pub async fn partitions(session: &Session) -> Result<BTreeMap<String, i64>, String> {
+    let rows = session
+        .query(db::idempotent("SELECT partition_key, last_offset FROM transactions.event_offsets"), &[])
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(rows
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(String, i64)>().ok())
+        .collect())
+}
+
+/// A partition's events after `after_offset`, in offset order, read a
+/// page at a time.
+pub async fn load_partition(session: &Session, partition_key: &str, after_offset: i64) -> Result<Vec<Event>, String> {
+    let mut events = Vec::new();
+    let mut after = after_offset;
+    loop {
+        let page = load_events(session, Some(partition_key), after, PAGE_SIZE).await?;
+        let done = page.len() < PAGE_SIZE as usize;
+        if let Some(last) = page.last() {
+            after = last.offset;
+        }
+        events.extend(page);
+        if done {
+            return Ok(events);
+        }
+    }
+}
+
+/// Events recorded at or before `as_of`, optionally only those where
+/// `endpoint` is the sender or the counterparty.
+pub async fn load_events_until(
+    session: &Session,
+    endpoint: Option<&str>,
+    as_of: DateTime<Utc>,
+) -> Result<Vec<Event>, String> {
+    let at = as_of.timestamp_millis();
+    let results = match endpoint {
+        Some(endpoint) => vec![
+            session
+                .query(
+                    db::scan(format!(
+                        "SELECT {} FROM transactions.events WHERE partition_key = ? AND at <= ? ALLOW FILTERING",
+                        EVENT_COLUMNS
+                    )),
+                    (endpoint, at),
+                )
+                .await,
+            session
+                .query(
+                    db::scan(format!(
+                        "SELECT {} FROM transactions.events WHERE counterparty = ? AND at <= ? ALLOW FILTERING",
+                        EVENT_COLUMNS
+                    )),
+                    (endpoint, at),
+                )
+                .await,
+        ],
+        None => vec![
+            session
+                .query(
+                    db::scan(format!("SELECT {} FROM transactions.events WHERE at <= ? ALLOW FILTERING", EVENT_COLUMNS)),
+                    (at,),
+                )
+                .await,
+        ],
+    };
+
+    let mut events = Vec::new();
+    for result in results {
+        if let Some(rows) = result.map_err(|e| e.to_string())?.rows {
+            for row in rows {
+                if let Ok(row) = row.into_typed::<EventRow>() {
+                    events.push(event_from_row(row));
+                }
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Replay API: `GET /api/events?after_offset=N[&partition_key=K][&limit=L]`.
+/// Offsets are per partition, so exact resumption needs `partition_key`;
+/// without it, every partition's events past `after_offset` are returned.
+pub async fn get_events(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<Event>>, StatusCode> {
+    let after_offset = params
+        .get("after_offset")
+        .map(|o| o.parse::<i64>().map_err(|_| StatusCode::BAD_REQUEST))
+        .transpose()?
+        .unwrap_or(0);
+    let limit = params
+        .get("limit")
+        .and_then(|l| l.parse::<i32>().ok())
+        .unwrap_or(500);
+
+    let mut events = load_events(
+        &state.session,
+        params.get("partition_key").map(String::as_str),
+        after_offset,
+        limit,
+    )
+    .await
+    .map_err(|e| {
+        error!("Event replay failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    events.sort_by_key(|event| (event.at, event.offset));
+    Ok(Json(events))
+}
+
+async fn publish_partition(
+    session: &Session,
+    publisher: &dyn EventPublisher,
+    partition_key: &str,
+) -> Result<(), String> {
+    let published = session
+        .query(
+            db::idempotent("SELECT published_offset FROM transactions.event_cursors WHERE partition_key = ?"),
+            (partition_key,),
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(i64,)>().ok())
+        .map(|(offset,)| offset)
+        .unwrap_or(0);
+
+    for event in load_events(session, Some(partition_key), published, 100).await? {
+        publisher.publish(&event).await?;
+        session
+            .query(
+                "UPDATE transactions.event_cursors SET published_offset = ? WHERE partition_key = ?",
+                (event.offset, partition_key),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Background task delivering appended events to the configured publisher
+/// in offset order. A publisher outage just pauses delivery; the cursor
+/// table records how far each partition got.
+pub async fn run_relay(state: AppState) {
+    info!("Event relay started with {} publisher", state.events.name());
+    loop {
+        // Only the leader publishes; standbys keep polling so they pick up
+        // from the cursors as soon as they take over
+        if !state.leadership.is_leader() {
+            tokio::time::sleep(RELAY_INTERVAL).await;
+            continue;
+        }
+
+        match state
+            .session
+            .query(db::idempotent("SELECT DISTINCT partition_key FROM transactions.events"), &[])
+            .await
+        {
+            Ok(result) => {
+                for row in result.rows.unwrap_or_default() {
+                    let Ok((partition_key,)) = row.into_typed::<(String,)>() else {
+                        continue;
+                    };
+                    if let Err(e) = publish_partition(&state.session, state.events.as_ref(), &partition_key).await {
+                        warn!("Publishing partition {} paused: {}", partition_key, e);
+                    }
+                }
+            }
+            Err(e) => warn!("Event relay scan failed: {}", e),
+        }
+        tokio::time::sleep(RELAY_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TransactionKind, TransactionStatus};
+    use tx_core::{Money, NATIVE_ASSET};
+
+    fn transaction(amount: f64) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4().to_string(),
+            from_endpoint: "alice".into(),
+            to_endpoint: "bob".into(),
+            amount: Money::from_major(amount, NATIVE_ASSET).unwrap(),
+            timestamp: Utc::now(),
+            signature: String::new(),
+            status: TransactionStatus::Pending,
+            kind: TransactionKind::Transfer,
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: None,
+            public_key: None,
+        }
+    }
+
+    #[test]
+    fn events_record_only_the_transaction_they_were_appended_for() {
+        let tx = transaction(10.0);
+        let event = new_event(EventKind::TransactionCreated, &tx, "alice".into(), 1, serde_json::Value::Null, Utc::now());
+        assert!(records(&event, &tx));
+
+        // A retry the gateway scored differently is still the same transfer
+        let mut rescored = tx.clone();
+        rescored.status = TransactionStatus::Held;
+        rescored.risk_score = Some(80);
+        assert!(records(&event, &rescored));
+
+        let mut reused = transaction(99.0);
+        reused.id = tx.id.clone();
+        reused.timestamp = tx.timestamp;
+        assert!(!records(&event, &reused));
+    }
+}
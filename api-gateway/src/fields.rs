@@ -0,0 +1,360 @@
+//! Sparse field selection for transaction lists. `?fields=id,amount,status`
+//! reads only those `tx_log` columns and returns objects with only those
+//! keys, so pollers that need a few fields don't pay for the rest. Names
+//! are the transaction's JSON keys; an unknown one is a 400. Without
+//! `fields`, lists return whole transactions as before.
+//!
+//! `id` and `timestamp` are always read, since pages are ordered and
+//! cursored by them, but only returned if asked for.
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use scylla::frame::response::result::{CqlValue, Row};
+use scylla::Session;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::db;
+use crate::pagination::{Cursor, Positioned};
+use crate::{timestamp_from_millis, Transaction, TransactionKind, TransactionStatus};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxField {
+    Id,
+    FromEndpoint,
+    ToEndpoint,
+    Amount,
+    Timestamp,
+    Signature,
+    Status,
+    Kind,
+    RiskScore,
+    ParentTxId,
+    Sequence,
+}
+
+impl TxField {
+    /// JSON key and `tx_log` column, which share a name.
+    pub fn name(self) -> &'static str {
+        match self {
+            TxField::Id => "id",
+            TxField::FromEndpoint => "from_endpoint",
+            TxField::ToEndpoint => "to_endpoint",
+            TxField::Amount => "amount",
+            TxField::Timestamp => "timestamp",
+            TxField::Signature => "signature",
+            TxField::Status => "status",
+            TxField::Kind => "kind",
+            TxField::RiskScore => "risk_score",
+            TxField::ParentTxId => "parent_tx_id",
+            TxField::Sequence => "sequence",
+        }
+    }
+}
+
+impl std::str::FromStr for TxField {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(TxField::Id),
+            "from_endpoint" => Ok(TxField::FromEndpoint),
+            "to_endpoint" => Ok(TxField::ToEndpoint),
+            "amount" => Ok(TxField::Amount),
+            "timestamp" => Ok(TxField::Timestamp),
+            "signature" => Ok(TxField::Signature),
+            "status" => Ok(TxField::Status),
+            "kind" => Ok(TxField::Kind),
+            "risk_score" => Ok(TxField::RiskScore),
+            "parent_tx_id" => Ok(TxField::ParentTxId),
+            "sequence" => Ok(TxField::Sequence),
+            other => Err(format!("Unknown field: {}", other)),
+        }
+    }
+}
+
+/// The fields a request asked for, in no particular order.
+#[derive(Clone, Debug)]
+pub struct Projection {
+    fields: Vec<TxField>,
+}
+
+impl Projection {
+    /// `None` without `fields`; 400 if it names an unknown field or none.
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Option<Self>, StatusCode> {
+        let Some(list) = params.get("fields") else {
+            return Ok(None);
+        };
+        let mut fields = Vec::new();
+        for name in list.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            let field = name.parse::<TxField>().map_err(|_| StatusCode::BAD_REQUEST)?;
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+        if fields.is_empty() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(Some(Projection { fields }))
+    }
+
+    fn wants(&self, field: TxField) -> bool {
+        self.fields.contains(&field)
+    }
+
+    /// Columns to select: the ordering key, then whatever else was asked.
+    fn columns(&self) -> Vec<TxField> {
+        let mut columns = vec![TxField::Id, TxField::Timestamp];
+        for &field in &self.fields {
+            if !columns.contains(&field) {
+                columns.push(field);
+            }
+        }
+        columns
+    }
+
+    fn select(&self) -> String {
+        self.columns().iter().map(|field| field.name()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// For views that already hold whole transactions, such as the event
+    /// log replay behind `as_of`.
+    pub fn apply(&self, transaction: &Transaction) -> SparseTransaction {
+        let want = |field| self.wants(field);
+        SparseTransaction {
+            position: Cursor::of(transaction),
+            id: want(TxField::Id).then(|| transaction.id.clone()),
+            from_endpoint: want(TxField::FromEndpoint).then(|| transaction.from_endpoint.clone()),
+            to_endpoint: want(TxField::ToEndpoint).then(|| transaction.to_endpoint.clone()),
+            amount: want(TxField::Amount).then(|| transaction.amount.to_major()),
+            timestamp: want(TxField::Timestamp).then_some(transaction.timestamp),
+            signature: want(TxField::Signature).then(|| transaction.signature.clone()),
+            status: want(TxField::Status).then_some(transaction.status),
+            kind: want(TxField::Kind).then_some(transaction.kind),
+            risk_score: want(TxField::RiskScore).then_some(transaction.risk_score),
+            parent_tx_id: want(TxField::ParentTxId).then(|| transaction.parent_tx_id.clone()),
+            sequence: want(TxField::Sequence).then_some(transaction.sequence),
+        }
+    }
+
+    /// Decodes a row selected with `select`, columns in `columns` order.
+    fn decode(&self, row: Row) -> Result<SparseTransaction, String> {
+        let mut sparse = SparseTransaction::default();
+        let mut id = None;
+        let mut timestamp = None;
+        for (field, value) in self.columns().into_iter().zip(row.columns) {
+            match field {
+                TxField::Id => id = value.as_ref().and_then(CqlValue::as_uuid),
+                TxField::Timestamp => timestamp = value.as_ref().and_then(CqlValue::as_bigint),
+                TxField::FromEndpoint => sparse.from_endpoint = Some(text(value)?),
+                TxField::ToEndpoint => sparse.to_endpoint = Some(text(value)?),
+                TxField::Amount => {
+                    sparse.amount = Some(value.as_ref().and_then(CqlValue::as_double).ok_or("amount is not a double")?)
+                }
+                TxField::Signature => sparse.signature = Some(text(value)?),
+                TxField::Status => sparse.status = Some(text(value)?.parse()?),
+                // Rows written before `kind` existed are plain transfers
+                TxField::Kind => {
+                    sparse.kind = Some(optional_text(value).map(|kind| kind.parse()).transpose()?.unwrap_or_default())
+                }
+                TxField::RiskScore => {
+                    sparse.risk_score = Some(
+                        value
+                            .as_ref()
+                            .and_then(CqlValue::as_int)
+                            .map(|score| score.clamp(0, 100) as u8),
+                    )
+                }
+                TxField::ParentTxId => {
+                    sparse.parent_tx_id = Some(value.as_ref().and_then(CqlValue::as_uuid).map(|id| id.to_string()))
+                }
+                TxField::Sequence => {
+                    sparse.sequence = Some(value.as_ref().and_then(CqlValue::as_bigint).map(|sequence| sequence as u64))
+                }
+            }
+        }
+
+        let id = id.ok_or("id is not a uuid")?;
+        let timestamp = timestamp_from_millis(timestamp.ok_or("timestamp is not a bigint")?);
+        sparse.position = Some(Cursor { timestamp: timestamp.timestamp_millis(), id });
+        sparse.id = self.wants(TxField::Id).then(|| id.to_string());
+        sparse.timestamp = self.wants(TxField::Timestamp).then_some(timestamp);
+        Ok(sparse)
+    }
+}
+
+fn optional_text(value: Option<CqlValue>) -> Option<String> {
+    match value {
+        Some(CqlValue::Text(text)) | Some(CqlValue::Ascii(text)) => Some(text),
+        _ => None,
+    }
+}
+
+fn text(value: Option<CqlValue>) -> Result<String, String> {
+    optional_text(value).ok_or_else(|| "expected a text column".to_string())
+}
+
+/// A transaction with only the requested fields. Nullable fields that were
+/// asked for come out as `null` when unset, the rest are left out.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct SparseTransaction {
+    #[serde(skip)]
+    position: Option<Cursor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_endpoint: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<TransactionStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<TransactionKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub risk_score: Option<Option<u8>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_tx_id: Option<Option<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sequence: Option<Option<u64>>,
+}
+
+impl Positioned for SparseTransaction {
+    fn position(&self) -> Option<Cursor> {
+        self.position
+    }
+}
+
+/// A list response: whole transactions, or projections of them when the
+/// request named `fields`.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum TransactionList {
+    Full(Vec<Transaction>),
+    Sparse(Vec<SparseTransaction>),
+}
+
+/// The projected transactions with `ids`, in that order; ids not in
+/// `tx_log` are left out.
+pub async fn load(session: &Session, projection: &Projection, ids: &[Uuid]) -> Result<Vec<SparseTransaction>, String> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rows = session
+        .query(
+            db::idempotent(format!("SELECT {} FROM transactions.tx_log WHERE id IN ?", projection.select())),
+            (ids.to_vec(),),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut by_id = HashMap::new();
+    for row in rows.rows.unwrap_or_default() {
+        match projection.decode(row) {
+            Ok(sparse) => {
+                if let Some(position) = sparse.position {
+                    by_id.insert(position.id, sparse);
+                }
+            }
+            Err(e) => warn!("Skipping transaction: {}", e),
+        }
+    }
+    Ok(ids.iter().filter_map(|id| by_id.remove(id)).collect())
+}
+
+/// Projected transactions in `status`, oldest first.
+pub async fn load_with_status(
+    session: &Session,
+    projection: &Projection,
+    status: TransactionStatus,
+) -> Result<Vec<SparseTransaction>, String> {
+    let rows = session
+        .query(
+            db::idempotent(format!(
+                "SELECT {} FROM transactions.tx_log WHERE status = ? ALLOW FILTERING",
+                projection.select()
+            )),
+            (status.as_str(),),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut transactions: Vec<SparseTransaction> = rows
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| projection.decode(row).map_err(|e| warn!("Skipping transaction: {}", e)).ok())
+        .collect();
+    transactions.sort_by_key(|sparse| sparse.position);
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tx_core::{Money, NATIVE_ASSET};
+
+    fn projection(fields: &str) -> Result<Option<Projection>, StatusCode> {
+        Projection::from_params(&HashMap::from([("fields".to_string(), fields.to_string())]))
+    }
+
+    #[test]
+    fn fields_are_parsed_and_deduplicated() {
+        assert!(Projection::from_params(&HashMap::new()).unwrap().is_none());
+        let projection = projection(" amount, status ,amount,").unwrap().unwrap();
+        assert_eq!(projection.fields, [TxField::Amount, TxField::Status]);
+        assert_eq!(projection.select(), "id, timestamp, amount, status");
+    }
+
+    #[test]
+    fn unknown_or_missing_fields_are_rejected() {
+        assert_eq!(projection("amount,public_key").err(), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(projection(" , ").err(), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn only_requested_keys_are_returned() {
+        let transaction = Transaction {
+            id: Uuid::new_v4().to_string(),
+            from_endpoint: "alice".into(),
+            to_endpoint: "bob".into(),
+            amount: Money::from_major(12.5, NATIVE_ASSET).unwrap(),
+            timestamp: timestamp_from_millis(1_700_000_000_000),
+            signature: String::new(),
+            status: TransactionStatus::Confirmed,
+            kind: TransactionKind::Transfer,
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: None,
+            public_key: None,
+        };
+        let sparse = projection("amount,risk_score").unwrap().unwrap().apply(&transaction);
+        assert_eq!(sparse.position(), Cursor::of(&transaction));
+        assert_eq!(serde_json::to_value(&sparse).unwrap(), serde_json::json!({ "amount": 12.5, "risk_score": null }));
+    }
+
+    #[test]
+    fn rows_decode_in_column_order() {
+        let id = Uuid::new_v4();
+        let row = Row {
+            columns: vec![
+                Some(CqlValue::Uuid(id)),
+                Some(CqlValue::BigInt(1_700_000_000_000)),
+                Some(CqlValue::Text("confirmed".into())),
+                None,
+            ],
+        };
+        let sparse = projection("status,kind").unwrap().unwrap().decode(row).unwrap();
+        assert_eq!(sparse.position(), Some(Cursor { timestamp: 1_700_000_000_000, id }));
+        assert_eq!(sparse.id, None);
+        assert_eq!(sparse.status, Some(TransactionStatus::Confirmed));
+        assert_eq!(sparse.kind, Some(TransactionKind::Transfer));
+    }
+}
@@ -0,0 +1,308 @@
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use tracing::{error, info, warn};
+use tx_core::{Money, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::audit;
+use crate::auth::{self, Claims};
+use crate::db;
+use crate::endpoints::{self, EndpointStatus};
+use crate::{
+    insert_transaction, lwt_applied, timestamp_from_millis, AppState, Transaction, TransactionKind,
+    TransactionStatus,
+};
+
+/// Source account recorded on deposit entries.
+pub const FUNDING_ACCOUNT: &str = "system:funding";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DepositRequest {
+    pub amount: f64,
+}
+
+impl DepositRequest {
+    fn amount(&self) -> Result<Money, StatusCode> {
+        let amount = Money::from_major(self.amount, NATIVE_ASSET).map_err(|_| StatusCode::BAD_REQUEST)?;
+        if amount.is_negative() || amount.is_zero() {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        Ok(amount)
+    }
+}
+
+/// Credits an endpoint by recording a confirmed `deposit` entry in the log.
+/// Admin only; endpoints ask for funds with `request_top_up`.
+pub async fn create_deposit(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    Json(request): Json<DepositRequest>,
+) -> Result<(StatusCode, Json<Transaction>), StatusCode> {
+    let amount = request.amount()?;
+    check_open(&state, &endpoint_id).await?;
+    let deposit = deposit(&state, endpoint_id, amount).await?;
+    Ok((StatusCode::CREATED, Json(deposit)))
+}
+
+async fn check_open(state: &AppState, endpoint_id: &str) -> Result<(), StatusCode> {
+    if let Some(endpoint) = endpoints::load_endpoint(&state.session, endpoint_id).await? {
+        if endpoint.status == EndpointStatus::Closed {
+            return Err(StatusCode::FORBIDDEN);
+        }
+    }
+    Ok(())
+}
+
+async fn deposit(state: &AppState, endpoint_id: String, amount: Money) -> Result<Transaction, StatusCode> {
+    let deposit = Transaction {
+        id: Uuid::new_v4().to_string(),
+        from_endpoint: FUNDING_ACCOUNT.to_string(),
+        to_endpoint: endpoint_id,
+        amount,
+        timestamp: Utc::now(),
+        signature: String::new(),
+        status: TransactionStatus::Confirmed,
+        kind: TransactionKind::Deposit,
+        risk_score: None,
+        parent_tx_id: None,
+        sequence: None,
+        public_key: None,
+    };
+
+    insert_transaction(&state.session, &deposit).await?;
+
+    info!("✅ Deposited {} to {}", deposit.amount, deposit.to_endpoint);
+    Ok(deposit)
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TopUpStatus {
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "approved")]
+    Approved,
+    #[serde(rename = "rejected")]
+    Rejected,
+}
+
+impl TopUpStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TopUpStatus::Pending => "pending",
+            TopUpStatus::Approved => "approved",
+            TopUpStatus::Rejected => "rejected",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "pending" => Some(TopUpStatus::Pending),
+            "approved" => Some(TopUpStatus::Approved),
+            "rejected" => Some(TopUpStatus::Rejected),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TopUpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct TopUpRequest {
+    pub id: Uuid,
+    pub endpoint_id: String,
+    #[serde(with = "tx_core::money::as_major")]
+    pub amount: Money,
+    pub status: TopUpStatus,
+    pub requested_at: DateTime<Utc>,
+    pub decided_at: Option<DateTime<Utc>>,
+    pub decided_by: Option<String>,
+    /// The `deposit` entry an approval recorded.
+    pub deposit_id: Option<String>,
+}
+
+const TOP_UP_COLUMNS: &str = "id, endpoint_id, amount_minor, status, requested_at, decided_at, decided_by, deposit_id";
+
+type TopUpRow = (Uuid, String, i64, String, i64, Option<i64>, Option<String>, Option<String>);
+
+fn top_up_from_row(row: TopUpRow) -> Option<TopUpRequest> {
+    let (id, endpoint_id, amount_minor, status, requested_at, decided_at, decided_by, deposit_id) = row;
+    Some(TopUpRequest {
+        id,
+        endpoint_id,
+        amount: Money::new(amount_minor, NATIVE_ASSET),
+        status: TopUpStatus::parse(&status)?,
+        requested_at: timestamp_from_millis(requested_at),
+        decided_at: decided_at.map(timestamp_from_millis),
+        decided_by,
+        deposit_id,
+    })
+}
+
+fn db_error(e: impl fmt::Display) -> StatusCode {
+    error!("Top-up request query failed: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+async fn load_top_up(session: &Session, id: Uuid) -> Result<Option<TopUpRequest>, StatusCode> {
+    let rows = session
+        .query(
+            db::idempotent(format!("SELECT {} FROM transactions.top_up_requests WHERE id = ?", TOP_UP_COLUMNS)),
+            (id,),
+        )
+        .await
+        .map_err(db_error)?;
+    Ok(rows
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<TopUpRow>().ok())
+        .and_then(top_up_from_row))
+}
+
+/// `POST /api/endpoints/:id/top-up-requests`: asks for funds. Nothing is
+/// credited until an admin approves the request.
+pub async fn request_top_up(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    Json(request): Json<DepositRequest>,
+) -> Result<(StatusCode, Json<TopUpRequest>), StatusCode> {
+    let amount = request.amount()?;
+    check_open(&state, &endpoint_id).await?;
+
+    let top_up = TopUpRequest {
+        id: Uuid::new_v4(),
+        endpoint_id,
+        amount,
+        status: TopUpStatus::Pending,
+        requested_at: Utc::now(),
+        decided_at: None,
+        decided_by: None,
+        deposit_id: None,
+    };
+    state
+        .session
+        .query(
+            "INSERT INTO transactions.top_up_requests (id, endpoint_id, amount_minor, status, requested_at)
+             VALUES (?, ?, ?, ?, ?)",
+            (
+                top_up.id,
+                &top_up.endpoint_id,
+                top_up.amount.minor(),
+                top_up.status.as_str(),
+                top_up.requested_at.timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(db_error)?;
+
+    info!("Top-up of {} requested for {}", top_up.amount, top_up.endpoint_id);
+    Ok((StatusCode::CREATED, Json(top_up)))
+}
+
+/// `GET /api/admin/top-up-requests`: pending requests, oldest first.
+pub async fn list_top_ups(State(state): State<AppState>) -> Result<Json<Vec<TopUpRequest>>, StatusCode> {
+    let rows = state
+        .session
+        .query(
+            db::idempotent(format!(
+                "SELECT {} FROM transactions.top_up_requests WHERE status = ? ALLOW FILTERING",
+                TOP_UP_COLUMNS
+            )),
+            (TopUpStatus::Pending.as_str(),),
+        )
+        .await
+        .map_err(db_error)?;
+
+    let mut pending = Vec::new();
+    for row in rows.rows.unwrap_or_default() {
+        match row.into_typed::<TopUpRow>().ok().and_then(top_up_from_row) {
+            Some(top_up) => pending.push(top_up),
+            None => warn!("Skipping unreadable top-up request"),
+        }
+    }
+    pending.sort_by_key(|top_up| top_up.requested_at);
+    Ok(Json(pending))
+}
+
+/// `POST /api/admin/top-up-requests/:id/approve`: records the deposit.
+pub async fn approve_top_up(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+) -> Result<Json<TopUpRequest>, StatusCode> {
+    let reviewer = auth::actor(claims.as_deref(), &headers, "x-reviewer")?;
+    let mut top_up = decide(&state, id, &reviewer, TopUpStatus::Approved).await?;
+
+    let deposit = deposit(&state, top_up.endpoint_id.clone(), top_up.amount.clone()).await?;
+    state
+        .session
+        .query(
+            "UPDATE transactions.top_up_requests SET deposit_id = ? WHERE id = ?",
+            (&deposit.id, id),
+        )
+        .await
+        .map_err(db_error)?;
+    top_up.deposit_id = Some(deposit.id);
+    Ok(Json(top_up))
+}
+
+/// `POST /api/admin/top-up-requests/:id/reject`
+pub async fn reject_top_up(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+) -> Result<Json<TopUpRequest>, StatusCode> {
+    let reviewer = auth::actor(claims.as_deref(), &headers, "x-reviewer")?;
+    decide(&state, id, &reviewer, TopUpStatus::Rejected).await.map(Json)
+}
+
+/// Moves a pending request to `outcome`. Conditional on it still being
+/// pending, so a request is approved, and credited, at most once.
+async fn decide(
+    state: &AppState,
+    id: Uuid,
+    reviewer: &str,
+    outcome: TopUpStatus,
+) -> Result<TopUpRequest, StatusCode> {
+    let mut top_up = load_top_up(&state.session, id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    if top_up.status != TopUpStatus::Pending {
+        return Err(StatusCode::CONFLICT);
+    }
+    if outcome == TopUpStatus::Approved {
+        check_open(state, &top_up.endpoint_id).await?;
+    }
+
+    let now = Utc::now();
+    let result = state
+        .session
+        .query(
+            "UPDATE transactions.top_up_requests SET status = ?, decided_at = ?, decided_by = ?
+             WHERE id = ? IF status = ?",
+            (outcome.as_str(), now.timestamp_millis(), reviewer, id, TopUpStatus::Pending.as_str()),
+        )
+        .await
+        .map_err(db_error)?;
+    if !lwt_applied(result) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    top_up.status = outcome;
+    top_up.decided_at = Some(now);
+    top_up.decided_by = Some(reviewer.to_string());
+    audit::record(&state.session, &id.to_string(), &format!("top-up.{}", outcome), reviewer, None).await?;
+    info!("Top-up request {} {} by {}", id, outcome, reviewer);
+    Ok(top_up)
+}
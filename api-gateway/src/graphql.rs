@@ -0,0 +1,562 @@
+//! GraphQL over the same reads as the REST API, so a dashboard can fetch
+//! what it shows in one request: `POST /api/graphql` for queries and
+//! `/api/graphql/ws` (graphql-transport-ws) for subscriptions.
+//!
+//! ```graphql
+//! {
+//!   stats { total_transactions total_volume }
+//!   transactions(limit: 20) { items { id amount status } next_cursor }
+//!   endpoint(id: "alice") {
+//!     status balance
+//!     transactions(filter: { status: ["held"], min_amount: 100 }) { items { id to_endpoint } }
+//!   }
+//! }
+//! ```
+//!
+//! Field and argument names are snake_case, matching the REST bodies and
+//! the live stream, so results from either can be mixed. Amounts are the
+//! `Amount` scalar: exact minor units inside the gateway, written as a
+//! number of major units as in REST, and read from one or from an exact
+//! decimal string (`"12.50"`). Subscriptions follow the live feed and take
+//! `resume`, the last update `id` seen, to first replay what a
+//! reconnecting client missed. A subscriber that falls behind, or whose
+//! token is no longer buffered, is ended and should refetch.
+//!
+//! Transaction lists and subscriptions take a `filter` (statuses, a time
+//! range, an amount range). The timeline has no index on those, so a
+//! filtered page is found by walking it: a page that stops short of
+//! `limit` with a `next_cursor` ran out of scan budget, not of matches.
+
+use async_graphql::{
+    Context, EmptyMutation, Error, ErrorExtensions, InputObject, InputValueError, InputValueResult, Object, Result,
+    Scalar, ScalarType, Schema, SimpleObject, Subscription, Value, ID,
+};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use tokio_stream::{Stream, StreamExt};
+use tracing::error;
+use tx_core::Money;
+use uuid::Uuid;
+
+use crate::endpoints::{self, Endpoint, EndpointStatus};
+use crate::error::ApiError;
+use crate::events::EventKind;
+use crate::pagination::{self, Cursor, Page};
+use crate::{timeline, AppState, EndpointStats, Transaction, TransactionStats, TransactionStatus};
+
+/// Deep enough for a chargeback's parent's parent, not for abuse.
+const MAX_DEPTH: usize = 8;
+/// Transactions a filtered page reads at most before returning short.
+const MAX_SCANNED: usize = 5000;
+
+pub type GatewaySchema = Schema<QueryRoot, EmptyMutation, SubscriptionRoot>;
+
+pub fn schema(state: AppState) -> GatewaySchema {
+    Schema::build(QueryRoot, EmptyMutation, SubscriptionRoot)
+        .data(state)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+/// The REST error's message, with its code as the `code` extension.
+fn status_error(error: impl Into<ApiError>) -> Error {
+    let body = error.into().body();
+    Error::new(body.message).extend_with(|_, extensions| extensions.set("code", body.code))
+}
+
+fn parse_id(id: &str) -> Result<Uuid> {
+    Uuid::parse_str(id).map_err(|_| Error::new("Invalid transaction id"))
+}
+
+/// An amount in the native asset; see the module docs.
+pub struct Amount(Money);
+
+#[Scalar]
+impl ScalarType for Amount {
+    fn parse(value: Value) -> InputValueResult<Self> {
+        let json = value.into_json().map_err(InputValueError::custom)?;
+        tx_core::money::as_major::deserialize(json).map(Amount).map_err(InputValueError::custom)
+    }
+
+    fn to_value(&self) -> Value {
+        Value::from(self.0.to_major())
+    }
+}
+
+/// Which transactions a list or subscription returns; every field set
+/// must match.
+#[derive(InputObject, Default)]
+#[graphql(rename_fields = "snake_case")]
+pub struct TransactionFilter {
+    /// Any of these statuses.
+    status: Option<Vec<String>>,
+    /// At or after.
+    since: Option<DateTime<Utc>>,
+    /// Before.
+    until: Option<DateTime<Utc>>,
+    min_amount: Option<Amount>,
+    max_amount: Option<Amount>,
+}
+
+/// A `TransactionFilter` with its statuses parsed.
+struct Criteria {
+    statuses: Option<Vec<TransactionStatus>>,
+    since: Option<DateTime<Utc>>,
+    until: Option<DateTime<Utc>>,
+    min_amount: Option<Money>,
+    max_amount: Option<Money>,
+}
+
+impl TransactionFilter {
+    fn criteria(self) -> Result<Criteria> {
+        let statuses = self
+            .status
+            .map(|statuses| statuses.iter().map(|status| status.parse()).collect::<Result<Vec<_>, _>>())
+            .transpose()
+            .map_err(Error::new)?;
+        Ok(Criteria {
+            statuses,
+            since: self.since,
+            until: self.until,
+            min_amount: self.min_amount.map(|Amount(min)| min),
+            max_amount: self.max_amount.map(|Amount(max)| max),
+        })
+    }
+}
+
+impl Criteria {
+    fn admits(&self, transaction: &Transaction) -> bool {
+        self.statuses.as_ref().is_none_or(|statuses| statuses.contains(&transaction.status))
+            && self.since.is_none_or(|since| transaction.timestamp >= since)
+            && self.until.is_none_or(|until| transaction.timestamp < until)
+            && self.min_amount.as_ref().is_none_or(|min| transaction.amount >= *min)
+            && self.max_amount.as_ref().is_none_or(|max| transaction.amount <= *max)
+    }
+}
+
+/// Sorts before every transaction in the millisecond of `time`.
+fn bound(time: DateTime<Utc>) -> Cursor {
+    Cursor { timestamp: time.timestamp_millis(), id: Uuid::nil() }
+}
+
+async fn load_page(state: &AppState, endpoint: Option<&str>, page: &Page, limit: i32) -> Result<Vec<Transaction>> {
+    let ids = timeline::page(&state.session, endpoint, page, limit)
+        .await
+        .map_err(|e| {
+            error!("Database query error: {}", e);
+            status_error(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+    crate::load_transactions(&state.session, &ids).await.map_err(status_error)
+}
+
+/// A page of `endpoint`'s (or everyone's) transactions matching `filter`,
+/// newest first.
+async fn transaction_page(
+    state: &AppState,
+    endpoint: Option<&str>,
+    before: Option<&str>,
+    after: Option<&str>,
+    limit: Option<i32>,
+    filter: Option<TransactionFilter>,
+) -> Result<TransactionPage> {
+    let page = Page::from_cursors(before, after).map_err(|_| Error::new("Invalid cursor"))?;
+    let limit = pagination::clamp_limit(limit);
+    if let Some(filter) = filter {
+        return filtered_page(state, endpoint, page, limit, filter.criteria()?).await;
+    }
+    let transactions = load_page(state, endpoint, &page, limit).await?;
+    let next_cursor = pagination::next_cursor(&page, limit, &transactions).map(|cursor| cursor.encode());
+    Ok(TransactionPage {
+        items: transactions.into_iter().map(TransactionNode).collect(),
+        next_cursor,
+    })
+}
+
+/// Walks the timeline from the page's cursor, clamped to the filter's time
+/// range, collecting matches until `limit`, the end of the range, or
+/// `MAX_SCANNED`. `after` pages walk toward newer history and are returned
+/// newest first like the rest.
+async fn filtered_page(
+    state: &AppState,
+    endpoint: Option<&str>,
+    page: Page,
+    limit: i32,
+    criteria: Criteria,
+) -> Result<TransactionPage> {
+    let forward = matches!(page, Page::After(_));
+    let since = criteria.since.map(bound);
+    let until = criteria.until.map(bound);
+    // Where the walk resumes from, and the bound it stops at
+    let (mut from, stop) = match &page {
+        Page::After(cursor) => (Some(since.map_or(*cursor, |since| since.max(*cursor))), until),
+        Page::Before(cursor) => (Some(until.map_or(*cursor, |until| until.min(*cursor))), since),
+        Page::Latest => (until, since),
+    };
+    let batch_size = limit.max(pagination::DEFAULT_LIMIT);
+
+    let mut items = Vec::new();
+    let mut scanned = 0;
+    let mut more = true;
+    while more && items.len() < limit as usize && scanned < MAX_SCANNED {
+        let batch_page = match from {
+            Some(cursor) if forward => Page::After(cursor),
+            Some(cursor) => Page::Before(cursor),
+            None => Page::Latest,
+        };
+        let previous = from;
+        let mut batch = load_page(state, endpoint, &batch_page, batch_size).await?;
+        more = batch.len() == batch_size as usize;
+        if forward {
+            batch.reverse();
+        }
+        let mut batch = batch.into_iter().peekable();
+        while let Some(transaction) = batch.next() {
+            let Some(position) = Cursor::of(&transaction) else {
+                continue;
+            };
+            let past_stop = stop.is_some_and(|stop| if forward { position >= stop } else { position < stop });
+            if past_stop {
+                more = false;
+                break;
+            }
+            scanned += 1;
+            from = Some(position);
+            if criteria.admits(&transaction) {
+                items.push(transaction);
+            }
+            if items.len() == limit as usize || scanned == MAX_SCANNED {
+                more |= batch.peek().is_some();
+                break;
+            }
+        }
+        // Nothing readable in a full batch: don't walk in place
+        if from == previous {
+            break;
+        }
+    }
+
+    let next_cursor = if forward {
+        items.reverse();
+        pagination::next_cursor(&page, limit, &items)
+    } else {
+        from.filter(|_| more)
+    };
+    Ok(TransactionPage {
+        items: items.into_iter().map(TransactionNode).collect(),
+        next_cursor: next_cursor.map(|cursor| cursor.encode()),
+    })
+}
+
+pub struct QueryRoot;
+
+#[Object(rename_fields = "snake_case", rename_args = "snake_case")]
+impl QueryRoot {
+    async fn transaction(&self, ctx: &Context<'_>, id: ID) -> Result<Option<TransactionNode>> {
+        let state = ctx.data::<AppState>()?;
+        let transaction = crate::load_transaction(&state.session, parse_id(&id)?).await.map_err(status_error)?;
+        Ok(transaction.map(TransactionNode))
+    }
+
+    /// Newest first; `before`/`after` take a `next_cursor` as in REST.
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        endpoint: Option<String>,
+        before: Option<String>,
+        after: Option<String>,
+        limit: Option<i32>,
+        filter: Option<TransactionFilter>,
+    ) -> Result<TransactionPage> {
+        let state = ctx.data::<AppState>()?;
+        transaction_page(state, endpoint.as_deref(), before.as_deref(), after.as_deref(), limit, filter).await
+    }
+
+    /// A registered endpoint.
+    async fn endpoint(&self, ctx: &Context<'_>, id: String) -> Result<Option<Endpoint>> {
+        let state = ctx.data::<AppState>()?;
+        endpoints::load_endpoint(&state.session, &id).await.map_err(status_error)
+    }
+
+    /// Registered endpoints by id, those in `status` if given.
+    async fn endpoints(&self, ctx: &Context<'_>, status: Option<String>) -> Result<Vec<Endpoint>> {
+        let status = status.map(|status| status.parse::<EndpointStatus>()).transpose().map_err(Error::new)?;
+        let state = ctx.data::<AppState>()?;
+        let endpoints = endpoints::list_endpoints(&state.session).await.map_err(status_error)?;
+        Ok(endpoints
+            .into_iter()
+            .filter(|endpoint| status.is_none_or(|status| endpoint.status == status))
+            .collect())
+    }
+
+    async fn stats(&self, ctx: &Context<'_>) -> Result<TransactionStats> {
+        let state = ctx.data::<AppState>()?;
+        let stats = crate::compute_stats(&state.session).await.map_err(status_error)?;
+        Ok(state.stats_privacy.totals(stats))
+    }
+
+    /// Stats for any endpoint id, registered or not.
+    async fn endpoint_stats(
+        &self,
+        ctx: &Context<'_>,
+        endpoint_id: String,
+        as_of: Option<DateTime<Utc>>,
+    ) -> Result<EndpointStats> {
+        let state = ctx.data::<AppState>()?;
+        crate::published_endpoint_stats(state, &endpoint_id, as_of).await.map_err(status_error)
+    }
+}
+
+pub struct TransactionNode(Transaction);
+
+#[Object(name = "Transaction", rename_fields = "snake_case")]
+impl TransactionNode {
+    async fn id(&self) -> ID {
+        ID(self.0.id.clone())
+    }
+
+    async fn from_endpoint(&self) -> &str {
+        &self.0.from_endpoint
+    }
+
+    async fn to_endpoint(&self) -> &str {
+        &self.0.to_endpoint
+    }
+
+    async fn amount(&self) -> Amount {
+        Amount(self.0.amount.clone())
+    }
+
+    async fn timestamp(&self) -> DateTime<Utc> {
+        self.0.timestamp
+    }
+
+    async fn signature(&self) -> &str {
+        &self.0.signature
+    }
+
+    async fn status(&self) -> &'static str {
+        self.0.status.as_str()
+    }
+
+    async fn kind(&self) -> &'static str {
+        self.0.kind.as_str()
+    }
+
+    async fn risk_score(&self) -> Option<u8> {
+        self.0.risk_score
+    }
+
+    async fn parent_tx_id(&self) -> Option<&str> {
+        self.0.parent_tx_id.as_deref()
+    }
+
+    async fn sequence(&self) -> Option<u64> {
+        self.0.sequence
+    }
+
+    /// The transfer a chargeback reverses, or the net transfer a netted
+    /// payment settled through.
+    async fn parent(&self, ctx: &Context<'_>) -> Result<Option<TransactionNode>> {
+        let Some(parent_tx_id) = &self.0.parent_tx_id else {
+            return Ok(None);
+        };
+        let state = ctx.data::<AppState>()?;
+        let parent = crate::load_transaction(&state.session, parse_id(parent_tx_id)?)
+            .await
+            .map_err(status_error)?;
+        Ok(parent.map(TransactionNode))
+    }
+
+    /// The registered endpoint that sent it, if registered.
+    async fn sender(&self, ctx: &Context<'_>) -> Result<Option<Endpoint>> {
+        let state = ctx.data::<AppState>()?;
+        endpoints::load_endpoint(&state.session, &self.0.from_endpoint).await.map_err(status_error)
+    }
+
+    /// The registered endpoint it was sent to, if registered.
+    async fn recipient(&self, ctx: &Context<'_>) -> Result<Option<Endpoint>> {
+        let state = ctx.data::<AppState>()?;
+        endpoints::load_endpoint(&state.session, &self.0.to_endpoint).await.map_err(status_error)
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "snake_case")]
+pub struct TransactionPage {
+    items: Vec<TransactionNode>,
+    /// `before` for the next (older) page, if there may be one.
+    next_cursor: Option<String>,
+}
+
+#[Object(rename_fields = "snake_case")]
+impl TransactionStats {
+    async fn total_transactions(&self) -> i64 {
+        self.total_transactions
+    }
+
+    async fn total_volume(&self) -> Amount {
+        Amount(self.total_volume.clone())
+    }
+
+    async fn average_transaction(&self) -> Amount {
+        Amount(self.average_transaction.clone())
+    }
+
+    async fn endpoints(&self) -> &Vec<EndpointStats> {
+        &self.endpoints
+    }
+}
+
+#[Object(rename_fields = "snake_case")]
+impl EndpointStats {
+    async fn endpoint_id(&self) -> &str {
+        &self.endpoint_id
+    }
+
+    async fn transaction_count(&self) -> i64 {
+        self.transaction_count
+    }
+
+    async fn total_sent(&self) -> Amount {
+        Amount(self.total_sent.clone())
+    }
+
+    async fn total_received(&self) -> Amount {
+        Amount(self.total_received.clone())
+    }
+
+    async fn balance_change(&self) -> Amount {
+        Amount(self.balance_change.clone())
+    }
+}
+
+#[Object(rename_fields = "snake_case", rename_args = "snake_case")]
+impl Endpoint {
+    async fn id(&self) -> &str {
+        &self.id
+    }
+
+    async fn status(&self) -> &'static str {
+        self.status.as_str()
+    }
+
+    async fn initial_balance(&self) -> Amount {
+        Amount(self.initial_balance.clone())
+    }
+
+    async fn max_transaction_amount(&self) -> Option<Amount> {
+        self.max_transaction_amount.clone().map(Amount)
+    }
+
+    async fn daily_send_limit(&self) -> Option<Amount> {
+        self.daily_send_limit.clone().map(Amount)
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+
+    /// As `GET /api/endpoints/:id/balance`, now unless `as_of` is given.
+    async fn balance(&self, ctx: &Context<'_>, as_of: Option<DateTime<Utc>>) -> Result<Amount> {
+        let state = ctx.data::<AppState>()?;
+        let balance = crate::endpoint_balance(&state.session, self.id.clone(), as_of.unwrap_or_else(Utc::now))
+            .await
+            .map_err(status_error)?;
+        Ok(Amount(balance.balance))
+    }
+
+    async fn stats(&self, ctx: &Context<'_>, as_of: Option<DateTime<Utc>>) -> Result<EndpointStats> {
+        let state = ctx.data::<AppState>()?;
+        crate::published_endpoint_stats(state, &self.id, as_of).await.map_err(status_error)
+    }
+
+    /// Its recent transactions, sent or received, newest first.
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        before: Option<String>,
+        after: Option<String>,
+        limit: Option<i32>,
+        filter: Option<TransactionFilter>,
+    ) -> Result<TransactionPage> {
+        let state = ctx.data::<AppState>()?;
+        transaction_page(state, Some(&self.id), before.as_deref(), after.as_deref(), limit, filter).await
+    }
+}
+
+#[derive(SimpleObject)]
+#[graphql(rename_fields = "snake_case")]
+pub struct TransactionUpdate {
+    /// `transaction.created`, `transaction.reversed` or
+    /// `transaction.status_changed`, as on the SSE stream.
+    event: &'static str,
+    /// `<partition>:<offset>` of the event; what `resume` takes.
+    id: String,
+    transaction: TransactionNode,
+}
+
+/// Live updates of the kinds `wanted` admits, involving `endpoint` if set
+/// and matching `filter`, after `resume` if given.
+fn live_updates(
+    state: &AppState,
+    endpoint: Option<String>,
+    filter: Option<TransactionFilter>,
+    resume: Option<String>,
+    wanted: fn(EventKind) -> bool,
+) -> Result<impl Stream<Item = TransactionUpdate>> {
+    let criteria = filter.unwrap_or_default().criteria()?;
+    let updates = state
+        .live
+        .updates(resume.as_deref())
+        // Lagged: end the subscription rather than skip silently
+        .map_while(|update| update.ok())
+        .filter_map(move |update| {
+            let transaction = &update.transaction;
+            if !wanted(update.kind)
+                || endpoint.as_ref().is_some_and(|endpoint| !transaction.involves(endpoint))
+                || !criteria.admits(transaction)
+            {
+                return None;
+            }
+            Some(TransactionUpdate {
+                event: update.kind.as_str(),
+                id: update.event_id.clone(),
+                transaction: TransactionNode(transaction.clone()),
+            })
+        });
+    Ok(updates)
+}
+
+pub struct SubscriptionRoot;
+
+#[Subscription(rename_fields = "snake_case", rename_args = "snake_case")]
+impl SubscriptionRoot {
+    /// New transfers and chargebacks.
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        endpoint: Option<String>,
+        filter: Option<TransactionFilter>,
+        resume: Option<String>,
+    ) -> Result<impl Stream<Item = TransactionUpdate>> {
+        let state = ctx.data::<AppState>()?;
+        live_updates(state, endpoint, filter, resume, |kind| {
+            matches!(kind, EventKind::TransactionCreated | EventKind::Reversed)
+        })
+    }
+
+    async fn status_changes(
+        &self,
+        ctx: &Context<'_>,
+        endpoint: Option<String>,
+        filter: Option<TransactionFilter>,
+        resume: Option<String>,
+    ) -> Result<impl Stream<Item = TransactionUpdate>> {
+        let state = ctx.data::<AppState>()?;
+        live_updates(state, endpoint, filter, resume, |kind| kind == EventKind::StatusChanged)
+    }
+}
@@ -0,0 +1,282 @@
+//! The gRPC API (`--features grpc`, `proto/relayer.proto`), served on
+//! `GRPC_PORT` (50051) next to the REST API for backend consumers that
+//! want typed, streaming access. It runs the same code as REST: a create
+//! goes through `POST /api/transactions` (validation, signatures, risk,
+//! limits, idempotency), reads use the same store, timeline and stats.
+//! Each call needs the role its REST route would, from a bearer token in
+//! the `authorization` metadata, and failed tokens count towards the
+//! caller's lockout. Third-party API keys are REST only.
+
+use axum::{
+    body::to_bytes,
+    extract::State,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    Json,
+};
+use serde::Deserialize;
+use std::pin::Pin;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+use tx_core::{Money, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::auth::{self, Role};
+use crate::error::ApiError;
+use crate::idempotency;
+use crate::pagination::{self, Cursor, Page};
+use crate::timeline;
+use crate::{AppState, Transaction};
+
+pub mod proto {
+    tonic::include_proto!("relayer.v1");
+}
+
+use proto::relayer_server::{Relayer, RelayerServer};
+
+/// Transactions read per timeline page while streaming a list.
+const PAGE_SIZE: i32 = pagination::DEFAULT_LIMIT;
+
+fn status_from(status: StatusCode, message: String) -> Status {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Status::invalid_argument(message),
+        StatusCode::UNAUTHORIZED => Status::unauthenticated(message),
+        StatusCode::FORBIDDEN => Status::permission_denied(message),
+        StatusCode::NOT_FOUND => Status::not_found(message),
+        StatusCode::CONFLICT => Status::already_exists(message),
+        StatusCode::TOO_MANY_REQUESTS => Status::resource_exhausted(message),
+        StatusCode::SERVICE_UNAVAILABLE => Status::unavailable(message),
+        _ => Status::internal(message),
+    }
+}
+
+impl From<ApiError> for Status {
+    fn from(e: ApiError) -> Self {
+        status_from(e.status(), e.body().message)
+    }
+}
+
+/// The JSON body of a REST `response`, or its error as a status.
+async fn json_from(response: axum::response::Response) -> Result<serde_json::Value, Status> {
+    let status = response.status();
+    let body = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| Status::internal(format!("Unreadable response: {}", e)))?;
+    let value: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+    if status.is_success() {
+        return Ok(value);
+    }
+    let message = match value.get("message").and_then(serde_json::Value::as_str) {
+        Some(message) => message.to_string(),
+        None => String::from_utf8_lossy(&body).into_owned(),
+    };
+    Err(status_from(status, message))
+}
+
+impl From<Transaction> for proto::Transaction {
+    fn from(tx: Transaction) -> Self {
+        proto::Transaction {
+            id: tx.id,
+            from_endpoint: tx.from_endpoint,
+            to_endpoint: tx.to_endpoint,
+            amount: tx.amount.to_major(),
+            timestamp: tx.timestamp.to_rfc3339(),
+            signature: tx.signature,
+            status: tx.status.to_string(),
+            kind: tx.kind.to_string(),
+            risk_score: tx.risk_score.map(u32::from),
+            parent_tx_id: tx.parent_tx_id,
+            sequence: tx.sequence,
+            public_key: tx.public_key,
+        }
+    }
+}
+
+fn transaction_from(tx: proto::Transaction) -> Result<Transaction, Status> {
+    let timestamp = chrono::DateTime::parse_from_rfc3339(&tx.timestamp)
+        .map_err(|e| Status::invalid_argument(format!("timestamp: {}", e)))?;
+    let amount = Money::from_major(tx.amount, NATIVE_ASSET)
+        .map_err(|e| Status::invalid_argument(format!("amount: {}", e)))?;
+    Ok(Transaction {
+        id: tx.id,
+        from_endpoint: tx.from_endpoint,
+        to_endpoint: tx.to_endpoint,
+        amount,
+        timestamp: timestamp.with_timezone(&chrono::Utc),
+        signature: tx.signature,
+        status: tx.status.parse().map_err(Status::invalid_argument)?,
+        kind: if tx.kind.is_empty() { Default::default() } else { tx.kind.parse().map_err(Status::invalid_argument)? },
+        risk_score: None,
+        parent_tx_id: tx.parent_tx_id,
+        sequence: tx.sequence,
+        public_key: tx.public_key,
+    })
+}
+
+/// `IngestResponse` as the REST API sends it.
+#[derive(Deserialize)]
+struct Created {
+    id: String,
+    risk_score: u8,
+    risk_reasons: Vec<String>,
+    held: bool,
+    tags: Vec<String>,
+}
+
+pub struct Service {
+    state: AppState,
+}
+
+impl Service {
+    async fn authorize<T>(&self, request: &Request<T>, role: Role) -> Result<(), Status> {
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        let ip = request.remote_addr().map(|addr| addr.ip().to_string());
+        match auth::check_token(&self.state, token, ip.as_deref(), role).await {
+            Ok(_) => Ok(()),
+            Err(response) => Err(json_from(response).await.err().unwrap_or_else(|| Status::unauthenticated(""))),
+        }
+    }
+}
+
+#[tonic::async_trait]
+impl Relayer for Service {
+    type ListTransactionsStream = Pin<Box<dyn Stream<Item = Result<proto::Transaction, Status>> + Send>>;
+
+    async fn create_transaction(
+        &self,
+        request: Request<proto::CreateTransactionRequest>,
+    ) -> Result<Response<proto::CreateTransactionResponse>, Status> {
+        self.authorize(&request, Role::Writer).await?;
+        let request = request.into_inner();
+        let transaction = request.transaction.ok_or_else(|| Status::invalid_argument("transaction is required"))?;
+        let transaction = transaction_from(transaction)?;
+
+        let mut headers = HeaderMap::new();
+        if !request.idempotency_key.is_empty() {
+            let key = HeaderValue::from_str(&request.idempotency_key)
+                .map_err(|_| Status::invalid_argument("idempotency_key is not a valid header value"))?;
+            headers.insert(idempotency::HEADER, key);
+        }
+        let response = crate::create_transaction(State(self.state.clone()), headers, Json(transaction)).await;
+        let created: Created = serde_json::from_value(json_from(response).await?)
+            .map_err(|e| Status::internal(format!("Unexpected create response: {}", e)))?;
+
+        Ok(Response::new(proto::CreateTransactionResponse {
+            id: created.id,
+            risk_score: u32::from(created.risk_score),
+            risk_reasons: created.risk_reasons,
+            held: created.held,
+            tags: created.tags,
+        }))
+    }
+
+    async fn get_transaction(
+        &self,
+        request: Request<proto::GetTransactionRequest>,
+    ) -> Result<Response<proto::Transaction>, Status> {
+        self.authorize(&request, Role::Reader).await?;
+        let id = request.into_inner().id;
+        let tx_id = Uuid::parse_str(&id).map_err(ApiError::from)?;
+        let transaction = self
+            .state
+            .store
+            .get(tx_id)
+            .await?
+            .ok_or_else(|| Status::not_found(format!("Transaction {} not found", id)))?;
+        Ok(Response::new(transaction.into()))
+    }
+
+    async fn list_transactions(
+        &self,
+        request: Request<proto::ListTransactionsRequest>,
+    ) -> Result<Response<Self::ListTransactionsStream>, Status> {
+        self.authorize(&request, Role::Reader).await?;
+        let request = request.into_inner();
+        let limit = request.limit as usize;
+        let state = self.state.clone();
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(PAGE_SIZE as usize);
+        tokio::spawn(async move {
+            let mut page = Page::Latest;
+            let mut sent = 0;
+            loop {
+                let page_size = match limit {
+                    0 => PAGE_SIZE,
+                    limit => PAGE_SIZE.min(i32::try_from(limit - sent).unwrap_or(i32::MAX)),
+                };
+                let ids = match timeline::page(&state.session, request.endpoint.as_deref(), &page, page_size).await {
+                    Ok(ids) => ids,
+                    Err(e) => {
+                        error!("Database query error: {}", e);
+                        let _ = sender.send(Err(Status::internal("Failed to list transactions"))).await;
+                        return;
+                    }
+                };
+                let transactions = match crate::load_transactions(&state.session, &ids).await {
+                    Ok(transactions) => transactions,
+                    Err(e) => {
+                        let _ = sender.send(Err(e.into())).await;
+                        return;
+                    }
+                };
+                let next = transactions.last().and_then(Cursor::of);
+                for transaction in transactions {
+                    // The caller hung up
+                    if sender.send(Ok(transaction.into())).await.is_err() {
+                        return;
+                    }
+                    sent += 1;
+                }
+                match next {
+                    Some(cursor) if ids.len() == page_size as usize && (limit == 0 || sent < limit) => {
+                        page = Page::Before(cursor);
+                    }
+                    _ => return,
+                }
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn get_stats(&self, request: Request<proto::GetStatsRequest>) -> Result<Response<proto::Stats>, Status> {
+        self.authorize(&request, Role::Reader).await?;
+        let stats = crate::compute_stats(&self.state.session)
+            .await
+            .map_err(|status| status_from(status, "Failed to compute stats".to_string()))?;
+        let stats = self.state.stats_privacy.totals(stats);
+        Ok(Response::new(proto::Stats {
+            total_transactions: stats.total_transactions,
+            total_volume: stats.total_volume.to_major(),
+            average_transaction: stats.average_transaction.to_major(),
+            endpoints: stats
+                .endpoints
+                .into_iter()
+                .map(|endpoint| proto::EndpointStats {
+                    endpoint_id: endpoint.endpoint_id,
+                    transaction_count: endpoint.transaction_count,
+                    total_sent: endpoint.total_sent.to_major(),
+                    total_received: endpoint.total_received.to_major(),
+                    balance_change: endpoint.balance_change.to_major(),
+                })
+                .collect(),
+        }))
+    }
+}
+
+pub async fn serve(state: AppState) {
+    let port: u16 = std::env::var("GRPC_PORT").ok().and_then(|v| v.parse().ok()).unwrap_or(50051);
+    let addr = std::net::SocketAddr::from(([0, 0, 0, 0], port));
+    info!("🚀 gRPC API running on {}", addr);
+    if let Err(e) = tonic::transport::Server::builder()
+        .add_service(RelayerServer::new(Service { state }))
+        .serve(addr)
+        .await
+    {
+        error!("gRPC server failed: {}", e);
+    }
+}
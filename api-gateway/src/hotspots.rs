@@ -0,0 +1,148 @@
+//! Write-rate tracking for event-log partitions. Every event is written to
+//! its sender's partition, so one endpoint sending far more than the rest
+//! concentrates load on the replicas owning that partition. Each append is
+//! counted in `HOTSPOT_BUCKET_SECS` (10) buckets; when a bucket closes, any
+//! partition with at least `HOTSPOT_MIN_WRITES` (50) writes and
+//! `HOTSPOT_SHARE` (0.5) of the bucket's writes is logged as hot. The last
+//! `HOTSPOT_WINDOW_BUCKETS` (6) buckets back `GET /api/admin/partitions/hot`.
+//!
+//! Counts are per gateway instance: each sees only the writes it made.
+
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{LazyLock, Mutex};
+use tracing::warn;
+
+const DEFAULT_LIMIT: usize = 10;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+struct Bucket {
+    start: i64,
+    total: u64,
+    writes: HashMap<String, u64>,
+}
+
+struct Tracker {
+    bucket_secs: i64,
+    window: usize,
+    min_writes: u64,
+    share: f64,
+    buckets: VecDeque<Bucket>,
+}
+
+static TRACKER: LazyLock<Mutex<Tracker>> = LazyLock::new(|| {
+    Mutex::new(Tracker {
+        bucket_secs: env_or("HOTSPOT_BUCKET_SECS", 10i64).max(1),
+        window: env_or("HOTSPOT_WINDOW_BUCKETS", 6usize).max(1),
+        min_writes: env_or("HOTSPOT_MIN_WRITES", 50),
+        share: env_or("HOTSPOT_SHARE", 0.5),
+        buckets: VecDeque::new(),
+    })
+});
+
+impl Tracker {
+    fn record(&mut self, partition_key: &str, now: i64) {
+        let start = now - now.rem_euclid(self.bucket_secs);
+        if self.buckets.back().is_none_or(|bucket| bucket.start != start) {
+            if let Some(closed) = self.buckets.back() {
+                self.report(closed);
+            }
+            self.buckets.push_back(Bucket { start, total: 0, writes: HashMap::new() });
+            while self.buckets.len() > self.window {
+                self.buckets.pop_front();
+            }
+        }
+
+        let bucket = self.buckets.back_mut().expect("bucket just pushed");
+        bucket.total += 1;
+        *bucket.writes.entry(partition_key.to_string()).or_default() += 1;
+    }
+
+    fn report(&self, bucket: &Bucket) {
+        for (partition_key, &writes) in &bucket.writes {
+            let share = writes as f64 / bucket.total as f64;
+            if writes >= self.min_writes && share >= self.share {
+                warn!(
+                    partition_key = %partition_key,
+                    writes,
+                    bucket_writes = bucket.total,
+                    share,
+                    writes_per_sec = writes as f64 / self.bucket_secs as f64,
+                    bucket_start = bucket.start,
+                    "Hot event-log partition"
+                );
+            }
+        }
+    }
+
+    fn window_secs(&self, now: i64) -> i64 {
+        let oldest = self.buckets.front().map_or(now, |bucket| bucket.start);
+        (now - oldest).max(self.bucket_secs)
+    }
+}
+
+/// Counts one write to `partition_key`.
+pub fn record(partition_key: &str) {
+    TRACKER.lock().unwrap().record(partition_key, Utc::now().timestamp());
+}
+
+#[derive(Serialize)]
+pub struct PartitionRate {
+    pub partition_key: String,
+    pub writes: u64,
+    pub writes_per_sec: f64,
+    /// Fraction of all writes in the window.
+    pub share: f64,
+}
+
+#[derive(Serialize)]
+pub struct HotPartitions {
+    pub window_secs: i64,
+    pub total_writes: u64,
+    pub partitions: Vec<PartitionRate>,
+}
+
+/// `GET /api/admin/partitions/hot[?limit=N]`: the busiest partitions over
+/// the tracking window, busiest first.
+pub async fn get_hot_partitions(
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<HotPartitions>, StatusCode> {
+    let limit = match params.get("limit") {
+        Some(limit) => limit.parse::<usize>().map_err(|_| StatusCode::BAD_REQUEST)?,
+        None => DEFAULT_LIMIT,
+    };
+
+    let tracker = TRACKER.lock().unwrap();
+    let window_secs = tracker.window_secs(Utc::now().timestamp());
+    let mut writes: HashMap<&str, u64> = HashMap::new();
+    let mut total_writes = 0;
+    for bucket in &tracker.buckets {
+        total_writes += bucket.total;
+        for (partition_key, count) in &bucket.writes {
+            *writes.entry(partition_key.as_str()).or_default() += count;
+        }
+    }
+
+    let mut partitions: Vec<PartitionRate> = writes
+        .into_iter()
+        .map(|(partition_key, writes)| PartitionRate {
+            partition_key: partition_key.to_string(),
+            writes,
+            writes_per_sec: writes as f64 / window_secs as f64,
+            share: writes as f64 / total_writes as f64,
+        })
+        .collect();
+    partitions.sort_by(|a, b| b.writes.cmp(&a.writes).then_with(|| a.partition_key.cmp(&b.partition_key)));
+    partitions.truncate(limit);
+
+    Ok(Json(HotPartitions { window_secs, total_writes, partitions }))
+}
@@ -0,0 +1,296 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use chrono::Utc;
+use scylla::Session;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use crate::db;
+use crate::lwt_applied;
+
+/// `Idempotency-Key` for `POST /api/transactions`. The first request with a
+/// key claims it; its response is stored and replayed to any retry with the
+/// same key and body, so a transaction relayed more than once is recorded
+/// once. Keys are scoped to the sender and kept for `IDEMPOTENCY_TTL_SECS`
+/// (a day). A claim still unanswered after `IDEMPOTENCY_IN_PROGRESS_SECS`
+/// (60), its gateway presumably gone, is taken over by the next retry.
+/// Storing or releasing the outcome is conditional on still holding the
+/// claim, so it stays ordered with the claim's own LWT.
+pub const HEADER: &str = "idempotency-key";
+
+/// Marks a response as a replay of the stored one.
+const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+const MAX_KEY_LEN: usize = 255;
+
+/// Stored responses are small JSON bodies; anything bigger isn't kept.
+const MAX_STORED_BODY: usize = 64 * 1024;
+
+fn ttl() -> i32 {
+    std::env::var("IDEMPOTENCY_TTL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(86_400)
+}
+
+/// How long a claim may go unanswered before a retry takes it over.
+fn in_progress_millis() -> i64 {
+    std::env::var("IDEMPOTENCY_IN_PROGRESS_SECS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(60)
+        .saturating_mul(1000)
+}
+
+fn db_error(e: impl std::fmt::Display) -> StatusCode {
+    error!("Idempotency query failed: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// The request's key, if it sent one. 400 if it's empty or too long.
+pub fn key_from(headers: &HeaderMap) -> Result<Option<String>, StatusCode> {
+    let Some(value) = headers.get(HEADER) else {
+        return Ok(None);
+    };
+    let key = value.to_str().map_err(|_| StatusCode::BAD_REQUEST)?.trim();
+    if key.is_empty() || key.len() > MAX_KEY_LEN {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(Some(key.to_string()))
+}
+
+/// What a retry must repeat exactly to be replayed.
+pub fn request_hash<T: Serialize>(request: &T) -> String {
+    hex::encode(Sha256::digest(serde_json::to_vec(request).unwrap_or_default()))
+}
+
+/// A key held by the request handling it, for `complete`.
+pub struct Claimed {
+    key: String,
+    request_hash: String,
+    claimed_at: i64,
+}
+
+pub enum Claim {
+    /// First use of the key, or a takeover of a stale claim; the caller
+    /// handles the request and completes it.
+    New(Claimed),
+    /// The key's stored response.
+    Replay(Response),
+}
+
+type StoredRow = (String, Option<i32>, Option<String>, Option<Vec<u8>>, Option<i64>);
+
+/// Claims `key` for `scope` (the sender), or finds what it was used for.
+/// 422 if it was used with a different request, 409 while the first one is
+/// still being handled.
+pub async fn claim(session: &Session, scope: &str, key: &str, request_hash: &str) -> Result<Claim, StatusCode> {
+    let claimed = Claimed {
+        key: format!("{}:{}", scope, key),
+        request_hash: request_hash.to_string(),
+        claimed_at: Utc::now().timestamp_millis(),
+    };
+    let inserted = session
+        .query(
+            "INSERT INTO transactions.idempotency_keys (key, request_hash, claimed_at) VALUES (?, ?, ?)
+             IF NOT EXISTS USING TTL ?",
+            (&claimed.key, request_hash, claimed.claimed_at, ttl()),
+        )
+        .await
+        .map_err(db_error)?;
+    if lwt_applied(inserted) {
+        return Ok(Claim::New(claimed));
+    }
+
+    let stored = session
+        .query(
+            db::idempotent(
+                "SELECT request_hash, status, content_type, body, claimed_at
+                 FROM transactions.idempotency_keys WHERE key = ?",
+            ),
+            (&claimed.key,),
+        )
+        .await
+        .map_err(db_error)?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<StoredRow>().ok());
+
+    match found(stored, request_hash, claimed.claimed_at, in_progress_millis())? {
+        Found::Replay(status, content_type, body) => {
+            let mut response = (status, body).into_response();
+            if let Some(content_type) = content_type.and_then(|c| HeaderValue::from_str(&c).ok()) {
+                response.headers_mut().insert(header::CONTENT_TYPE, content_type);
+            }
+            response.headers_mut().insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+            Ok(Claim::Replay(response))
+        }
+        Found::Stale(previous) => take_over(session, claimed, previous).await,
+    }
+}
+
+/// What a key someone already claimed means for a request with
+/// `request_hash` at `now`.
+#[derive(Debug, PartialEq)]
+enum Found {
+    /// The stored response: status, content type and body.
+    Replay(StatusCode, Option<String>, Vec<u8>),
+    /// An unanswered claim made at the given time, old enough to take over.
+    Stale(Option<i64>),
+}
+
+fn found(stored: Option<StoredRow>, request_hash: &str, now: i64, in_progress: i64) -> Result<Found, StatusCode> {
+    match stored {
+        Some((stored_hash, ..)) if stored_hash != request_hash => Err(StatusCode::UNPROCESSABLE_ENTITY),
+        Some((_, Some(status), content_type, body, _)) => {
+            let status = u16::try_from(status)
+                .ok()
+                .and_then(|status| StatusCode::from_u16(status).ok())
+                .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+            Ok(Found::Replay(status, content_type, body.unwrap_or_default()))
+        }
+        Some((_, None, _, _, previous)) if previous.is_none_or(|previous| now - previous > in_progress) => {
+            Ok(Found::Stale(previous))
+        }
+        Some(_) => Err(StatusCode::CONFLICT),
+        // Expired between the two queries; the client can simply retry
+        None => Err(StatusCode::CONFLICT),
+    }
+}
+
+/// Takes over a claim made at `previous` that was never answered. Only
+/// one retry wins; the rest still see 409.
+async fn take_over(session: &Session, claimed: Claimed, previous: Option<i64>) -> Result<Claim, StatusCode> {
+    let taken = session
+        .query(
+            "UPDATE transactions.idempotency_keys USING TTL ? SET request_hash = ?, claimed_at = ?
+             WHERE key = ? IF status = null AND claimed_at = ?",
+            (ttl(), &claimed.request_hash, claimed.claimed_at, &claimed.key, previous),
+        )
+        .await
+        .map_err(db_error)?;
+    if !lwt_applied(taken) {
+        return Err(StatusCode::CONFLICT);
+    }
+    warn!("Idempotency key {} taken over from a claim that was never answered", claimed.key);
+    Ok(Claim::New(claimed))
+}
+
+/// Stores `response` as the key's outcome and returns it. Outcomes a retry
+/// could change (server errors, cool-downs) release the key instead.
+/// Either only while `claimed` still holds the key.
+pub async fn complete(session: &Session, claimed: Claimed, response: Response) -> Response {
+    let Claimed { key, request_hash, claimed_at } = claimed;
+    let status = response.status();
+    if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+        match session
+            .query(
+                "DELETE FROM transactions.idempotency_keys WHERE key = ? IF request_hash = ? AND claimed_at = ?",
+                (&key, &request_hash, claimed_at),
+            )
+            .await
+        {
+            Ok(released) => {
+                if !lwt_applied(released) {
+                    warn!("Idempotency key {} was taken over; not released", key)
+                }
+            }
+            Err(e) => warn!("Failed to release idempotency key {}: {}", key, e),
+        }
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_STORED_BODY).await {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Response for idempotency key {} not stored: {}", key, e);
+            return (StatusCode::INTERNAL_SERVER_ERROR).into_response();
+        }
+    };
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|c| c.to_str().ok())
+        .map(str::to_string);
+
+    match session
+        .query(
+            "UPDATE transactions.idempotency_keys USING TTL ?
+             SET request_hash = ?, status = ?, content_type = ?, body = ?
+             WHERE key = ? IF request_hash = ? AND claimed_at = ?",
+            (
+                ttl(),
+                &request_hash,
+                i32::from(status.as_u16()),
+                content_type,
+                body.to_vec(),
+                &key,
+                &request_hash,
+                claimed_at,
+            ),
+        )
+        .await
+    {
+        Ok(stored) => {
+            // Whoever took it over stores their own outcome
+            if !lwt_applied(stored) {
+                warn!("Idempotency key {} was taken over; response not stored", key)
+            }
+        }
+        // The request went through; a retry will take the key over after
+        // IDEMPOTENCY_IN_PROGRESS_SECS and record nothing new
+        Err(e) => warn!("Failed to store response for idempotency key {}: {}", key, e),
+    }
+    Response::from_parts(parts, Body::from(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IN_PROGRESS: i64 = 60_000;
+
+    fn row(hash: &str, status: Option<i32>, claimed_at: Option<i64>) -> Option<StoredRow> {
+        Some((hash.to_string(), status, Some("application/json".into()), Some(b"{}".to_vec()), claimed_at))
+    }
+
+    #[test]
+    fn answered_keys_replay_for_the_same_request() {
+        assert_eq!(
+            found(row("h", Some(201), Some(0)), "h", 1, IN_PROGRESS),
+            Ok(Found::Replay(StatusCode::CREATED, Some("application/json".into()), b"{}".to_vec()))
+        );
+    }
+
+    #[test]
+    fn a_different_request_is_unprocessable() {
+        assert_eq!(found(row("h", Some(201), Some(0)), "other", 1, IN_PROGRESS), Err(StatusCode::UNPROCESSABLE_ENTITY));
+        assert_eq!(found(row("h", None, Some(0)), "other", 1, IN_PROGRESS), Err(StatusCode::UNPROCESSABLE_ENTITY));
+    }
+
+    #[test]
+    fn unanswered_claims_are_taken_over_once_stale() {
+        assert_eq!(found(row("h", None, Some(0)), "h", IN_PROGRESS, IN_PROGRESS), Err(StatusCode::CONFLICT));
+        assert_eq!(found(row("h", None, Some(0)), "h", IN_PROGRESS + 1, IN_PROGRESS), Ok(Found::Stale(Some(0))));
+        // Claimed before `claimed_at` was recorded
+        assert_eq!(found(row("h", None, None), "h", 1, IN_PROGRESS), Ok(Found::Stale(None)));
+    }
+
+    #[test]
+    fn expired_keys_ask_for_a_retry() {
+        assert_eq!(found(None, "h", 1, IN_PROGRESS), Err(StatusCode::CONFLICT));
+    }
+
+    #[test]
+    fn request_hashes_cover_the_whole_request() {
+        let a = serde_json::json!({ "id": "1", "amount": 5.0 });
+        let b = serde_json::json!({ "id": "1", "amount": 6.0 });
+        assert_eq!(request_hash(&a), request_hash(&a.clone()));
+        assert_ne!(request_hash(&a), request_hash(&b));
+    }
+}
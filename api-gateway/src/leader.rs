@@ -0,0 +1,159 @@
+use axum::{extract::State, http::StatusCode, response::Json};
+use chrono::{DateTime, Duration, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::{lwt_applied, timestamp_from_millis, AppState};
+
+const LEASE_NAME: &str = "gateway";
+
+/// Leader election for running several gateways side by side. Every
+/// instance serves the API; only the lease holder runs the background work
+/// (event relay, projection snapshots). The lease is a row written with a
+/// TTL through LWT, renewed every third of `LEADER_LEASE_SECS` (15), so a
+/// standby takes over within one lease period of the leader dying.
+#[derive(Clone)]
+pub struct Leadership {
+    pub instance_id: String,
+    is_leader: Arc<AtomicBool>,
+}
+
+impl Leadership {
+    /// `GATEWAY_INSTANCE_ID`, falling back to the pod/host name.
+    pub fn from_env() -> Self {
+        let instance_id = std::env::var("GATEWAY_INSTANCE_ID")
+            .or_else(|_| std::env::var("HOSTNAME"))
+            .unwrap_or_else(|_| Uuid::new_v4().to_string());
+        Leadership {
+            instance_id,
+            is_leader: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.is_leader.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LeaderStatus {
+    pub instance_id: String,
+    pub is_leader: bool,
+    pub leader: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn lease_duration() -> Duration {
+    Duration::seconds(
+        std::env::var("LEADER_LEASE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(15),
+    )
+}
+
+pub async fn init_schema(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS transactions.leader_leases (
+                 name TEXT PRIMARY KEY,
+                 holder TEXT,
+                 expires_at BIGINT
+             )",
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Renews the lease if we hold it, otherwise tries to take it over once
+/// the previous holder's row has expired.
+async fn try_hold(session: &Session, instance_id: &str, leading: bool) -> Result<bool, String> {
+    let lease = lease_duration();
+    let ttl = lease.num_seconds().max(1) as i32;
+    let expires_at = (Utc::now() + lease).timestamp_millis();
+
+    if leading {
+        let renewed = session
+            .query(
+                "UPDATE transactions.leader_leases USING TTL ? SET holder = ?, expires_at = ?
+                 WHERE name = ? IF holder = ?",
+                (ttl, instance_id, expires_at, LEASE_NAME, instance_id),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        if lwt_applied(renewed) {
+            return Ok(true);
+        }
+    }
+
+    let acquired = session
+        .query(
+            "INSERT INTO transactions.leader_leases (name, holder, expires_at)
+             VALUES (?, ?, ?) IF NOT EXISTS USING TTL ?",
+            (LEASE_NAME, instance_id, expires_at, ttl),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(lwt_applied(acquired))
+}
+
+pub async fn run_election(state: AppState) {
+    let leadership = &state.leadership;
+    let interval = (lease_duration() / 3).to_std().unwrap_or(std::time::Duration::from_secs(5));
+    info!("Instance {} joining leader election", leadership.instance_id);
+
+    loop {
+        let leading = leadership.is_leader();
+        let now_leading = match try_hold(&state.session, &leadership.instance_id, leading).await {
+            Ok(held) => held,
+            Err(e) => {
+                // Without a renewal we can't be sure the lease is still ours
+                error!("Leader lease check failed: {}", e);
+                false
+            }
+        };
+
+        if now_leading != leading {
+            leadership.is_leader.store(now_leading, Ordering::Relaxed);
+            if now_leading {
+                info!("👑 Instance {} is now the leader", leadership.instance_id);
+            } else {
+                warn!("Instance {} lost leadership", leadership.instance_id);
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// `GET /leader`: this instance's role and the current lease holder.
+pub async fn get_leader(State(state): State<AppState>) -> Result<Json<LeaderStatus>, StatusCode> {
+    let rows = state
+        .session
+        .query(
+            db::idempotent("SELECT holder, expires_at FROM transactions.leader_leases WHERE name = ?"),
+            (LEASE_NAME,),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to read leader lease: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let lease = rows
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(String, i64)>().ok());
+
+    Ok(Json(LeaderStatus {
+        instance_id: state.leadership.instance_id.clone(),
+        is_leader: state.leadership.is_leader(),
+        leader: lease.as_ref().map(|(holder, _)| holder.clone()),
+        expires_at: lease.map(|(_, expires_at)| timestamp_from_millis(expires_at)),
+    }))
+}
@@ -0,0 +1,241 @@
+//! Live transactions over server-sent events: `GET /api/transactions/stream`
+//! pushes each transaction as it is created, reversed or changes status.
+//! Every instance tails the shared event log, so a subscriber sees changes
+//! made through any gateway, and only while someone is subscribed.
+//!
+//! Updates from the last `RESUME_RETENTION` (5 minutes, at most
+//! `RESUME_BUFFER_SIZE`) are kept so a subscriber that reconnects can
+//! resume after the last event id it saw instead of refetching: SSE
+//! clients send it as `Last-Event-ID` (browsers do on reconnect) or
+//! `?resume=`, the push socket and GraphQL subscriptions take `resume`.
+//! The tail keeps running for that long after the last subscriber leaves.
+//! The buffer is per instance, so a token from another gateway, or one
+//! older than the buffer, can't be resumed from and gets `lagged` instead.
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event as SseEvent, KeepAlive, Sse},
+};
+use scylla::Session;
+use axum::http::HeaderMap;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, warn};
+
+use crate::events::{self, EventKind};
+use crate::{AppState, Transaction};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+/// Updates buffered per subscriber before it is told it lagged.
+const CHANNEL_CAPACITY: usize = 1024;
+const BATCH_SIZE: i32 = 100;
+const RESUME_RETENTION: Duration = Duration::from_secs(300);
+const RESUME_BUFFER_SIZE: usize = 10_000;
+
+#[derive(Clone, Debug)]
+pub struct LiveUpdate {
+    pub kind: EventKind,
+    /// `<partition>:<offset>` of the event, unique per update.
+    pub event_id: String,
+    pub transaction: Transaction,
+}
+
+#[derive(Default)]
+struct Recent {
+    updates: VecDeque<(Instant, Arc<LiveUpdate>)>,
+    /// When someone was last subscribed, while the tail runs.
+    last_subscribed: Option<Instant>,
+}
+
+#[derive(Clone)]
+pub struct LiveFeed {
+    sender: broadcast::Sender<Arc<LiveUpdate>>,
+    recent: Arc<Mutex<Recent>>,
+}
+
+impl Default for LiveFeed {
+    fn default() -> Self {
+        LiveFeed {
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+            recent: Arc::default(),
+        }
+    }
+}
+
+pub type Updates = std::pin::Pin<Box<dyn Stream<Item = Result<Arc<LiveUpdate>, BroadcastStreamRecvError>> + Send>>;
+
+impl LiveFeed {
+    /// Live updates, preceded by those after the event id `resume` if it
+    /// is given. A token that isn't buffered yields `Lagged(0)` first, so
+    /// the subscriber refetches. Counts as a subscriber, so the tail runs
+    /// while the stream lives.
+    pub fn updates(&self, resume: Option<&str>) -> Updates {
+        // Subscribing under the lock `publish` holds means nothing falls
+        // between the backlog and the live updates
+        let recent = self.recent.lock().unwrap();
+        let receiver = self.sender.subscribe();
+        let backlog: Vec<_> = match resume {
+            None => Vec::new(),
+            Some(token) => match recent.updates.iter().position(|(_, update)| update.event_id == token) {
+                Some(seen) => recent.updates.iter().skip(seen + 1).map(|(_, update)| Ok(update.clone())).collect(),
+                None => vec![Err(BroadcastStreamRecvError::Lagged(0))],
+            },
+        };
+        drop(recent);
+        Box::pin(tokio_stream::iter(backlog).chain(BroadcastStream::new(receiver)))
+    }
+
+    fn publish(&self, update: LiveUpdate) {
+        let update = Arc::new(update);
+        let now = Instant::now();
+        let mut recent = self.recent.lock().unwrap();
+        recent.updates.push_back((now, update.clone()));
+        while recent.updates.len() > RESUME_BUFFER_SIZE
+            || recent.updates.front().is_some_and(|(at, _)| now - *at > RESUME_RETENTION)
+        {
+            recent.updates.pop_front();
+        }
+        // Nobody listening right now is fine; it stays buffered
+        let _ = self.sender.send(update);
+    }
+
+    /// Whether the tail should run: someone is subscribed, or was recently
+    /// enough to resume. When it stops, the buffer is dropped, since the
+    /// next run starts from the log's end and resuming across that gap
+    /// would silently skip events.
+    fn wanted(&self) -> bool {
+        let mut recent = self.recent.lock().unwrap();
+        let now = Instant::now();
+        if self.sender.receiver_count() > 0 {
+            recent.last_subscribed = Some(now);
+            return true;
+        }
+        if recent.last_subscribed.is_some_and(|at| now - at <= RESUME_RETENTION) {
+            return true;
+        }
+        recent.last_subscribed = None;
+        recent.updates.clear();
+        false
+    }
+}
+
+/// The resume token from `Last-Event-ID` or `?resume=`.
+pub fn resume_token(headers: &HeaderMap, params: &HashMap<String, String>) -> Option<String> {
+    headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| params.get("resume").cloned())
+        .filter(|token| !token.is_empty())
+}
+
+/// Each partition's last allocated offset.
+async fn latest_offsets(session: &Session) -> Result<HashMap<String, i64>, String> {
+    Ok(events::partitions(session).await?.into_iter().collect())
+}
+
+/// Background task feeding `LiveFeed` from the event log. Idle without
+/// subscribers (recent ones included); the first one starts it from the
+/// log's current end.
+pub async fn run_live_tail(state: AppState) {
+    let mut seen: HashMap<String, i64> = HashMap::new();
+    let mut tailing = false;
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        if !state.live.wanted() {
+            tailing = false;
+            continue;
+        }
+
+        let latest = match latest_offsets(&state.session).await {
+            Ok(latest) => latest,
+            Err(e) => {
+                warn!("Live feed offset scan failed: {}", e);
+                continue;
+            }
+        };
+        if !tailing {
+            seen = latest;
+            tailing = true;
+            continue;
+        }
+
+        for (partition_key, last_offset) in latest {
+            let from = seen.get(&partition_key).copied().unwrap_or(0);
+            if last_offset <= from {
+                continue;
+            }
+            let events = match events::load_events(&state.session, Some(&partition_key), from, BATCH_SIZE).await {
+                Ok(events) => events,
+                Err(e) => {
+                    warn!("Live feed read of {} failed: {}", partition_key, e);
+                    continue;
+                }
+            };
+            for event in events {
+                seen.insert(partition_key.clone(), event.offset);
+                let kind = match event.event_type.parse() {
+                    Ok(kind @ (EventKind::TransactionCreated | EventKind::Reversed | EventKind::StatusChanged)) => kind,
+                    _ => continue,
+                };
+                let Ok(transaction) = serde_json::from_value::<Transaction>(event.payload) else {
+                    continue;
+                };
+                state.live.publish(LiveUpdate {
+                    kind,
+                    event_id: format!("{}:{}", event.partition_key, event.offset),
+                    transaction,
+                });
+            }
+        }
+    }
+}
+
+/// `GET /api/transactions/stream[?endpoint=<id>&resume=<event id>]`. Each
+/// SSE event is named after the change (`transaction.created`,
+/// `transaction.reversed`, `transaction.status_changed`), carries the
+/// transaction as JSON and has the event id as its SSE id. A subscriber
+/// too slow to keep up, or resuming from a token no longer buffered, gets
+/// `lagged` with the number of updates it missed (0 if unknown) and
+/// should refetch. Comments keep the connection alive through idle proxies.
+pub async fn stream_transactions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Sse<impl Stream<Item = Result<SseEvent, Infallible>>> {
+    let endpoint = params.get("endpoint").cloned();
+    let resume = resume_token(&headers, &params);
+    debug!(
+        "Live subscriber{}{}",
+        endpoint.as_deref().map(|e| format!(" for {}", e)).unwrap_or_default(),
+        resume.as_deref().map(|token| format!(" resuming after {}", token)).unwrap_or_default()
+    );
+
+    let updates = state.live.updates(resume.as_deref()).filter_map(move |update| match update {
+        Ok(update) => {
+            let transaction = &update.transaction;
+            if let Some(endpoint) = &endpoint {
+                if transaction.from_endpoint != *endpoint && transaction.to_endpoint != *endpoint {
+                    return None;
+                }
+            }
+            let event = SseEvent::default()
+                .event(update.kind.as_str())
+                .id(update.event_id.clone())
+                .json_data(transaction)
+                .ok()?;
+            Some(Ok(event))
+        }
+        Err(BroadcastStreamRecvError::Lagged(missed)) => {
+            Some(Ok(SseEvent::default().event("lagged").data(missed.to_string())))
+        }
+    });
+
+    Sse::new(updates).keep_alive(KeepAlive::new().interval(HEARTBEAT_INTERVAL).text("heartbeat"))
+}
@@ -0,0 +1,318 @@
+//! Brute-force protection for the gateway's credentials. A bearer token
+//! that doesn't verify, or a third-party API key that doesn't match,
+//! counts as a failure against the client IP and, for an API key, against
+//! the third party it names. Past `AUTH_FREE_FAILURES` (3) failures, each
+//! refusal is held back before it is sent, from `AUTH_DELAY_BASE_MS` (250)
+//! doubling up to `AUTH_DELAY_MAX_MS` (8000). `AUTH_LOCKOUT_FAILURES` (10)
+//! within `AUTH_FAILURE_WINDOW_SECS` (900) lock the key out for
+//! `AUTH_LOCKOUT_SECS` (900): its requests get `429` with `Retry-After`
+//! before their credentials are looked at, and a locked-out IP can't have
+//! dev tokens issued. A success clears the key's failures.
+//!
+//! Failures are counted per minute, and a minute reaching
+//! `AUTH_FAILURE_ALERT_PER_MIN` (100) is logged as a spike. `GET
+//! /api/admin/lockouts` reports the counts, the last hour by minute and
+//! every key with failures; `DELETE /api/admin/lockouts/:key` lifts a
+//! lockout early, on the audit trail of the key.
+//!
+//! The client IP is the one the rate limits use: the peer address, or
+//! the `X-Forwarded-For` hop the trusted proxies appended, never one the
+//! client wrote (see `ratelimit`). IPv6 clients are counted per /64, which
+//! a single host can rotate through freely.
+//!
+//! Like the rate limits, failures are counted per gateway instance.
+
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::{LazyLock, Mutex};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::auth::{self, Claims};
+use crate::error::ApiError;
+use crate::{audit, timestamp_from_millis, AppState};
+
+/// Minutes of failure counts kept for the report.
+const MINUTES_KEPT: usize = 60;
+/// Keys tracked before those with expired failures are dropped.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+struct Failures {
+    count: u32,
+    first_at: DateTime<Utc>,
+    last_at: DateTime<Utc>,
+    locked_until: Option<DateTime<Utc>>,
+}
+
+struct Tracker {
+    free_failures: u32,
+    delay_base: std::time::Duration,
+    delay_max: std::time::Duration,
+    lockout_failures: u32,
+    window: Duration,
+    lockout: Duration,
+    alert_per_min: u64,
+    keys: HashMap<String, Failures>,
+    /// Start of each minute, with its failures; newest last.
+    minutes: VecDeque<(DateTime<Utc>, u64)>,
+    failures: u64,
+    lockouts: u64,
+    refused: u64,
+}
+
+static TRACKER: LazyLock<Mutex<Tracker>> = LazyLock::new(|| {
+    let tracker = Tracker {
+        free_failures: env_or("AUTH_FREE_FAILURES", 3),
+        delay_base: std::time::Duration::from_millis(env_or("AUTH_DELAY_BASE_MS", 250)),
+        delay_max: std::time::Duration::from_millis(env_or("AUTH_DELAY_MAX_MS", 8000)),
+        lockout_failures: env_or("AUTH_LOCKOUT_FAILURES", 10u32).max(1),
+        window: Duration::seconds(env_or("AUTH_FAILURE_WINDOW_SECS", 900)),
+        lockout: Duration::seconds(env_or("AUTH_LOCKOUT_SECS", 900)),
+        alert_per_min: env_or("AUTH_FAILURE_ALERT_PER_MIN", 100),
+        keys: HashMap::new(),
+        minutes: VecDeque::new(),
+        failures: 0,
+        lockouts: 0,
+        refused: 0,
+    };
+    info!(
+        "Auth lockout after {} failures in {}s, for {}s",
+        tracker.lockout_failures,
+        tracker.window.num_seconds(),
+        tracker.lockout.num_seconds()
+    );
+    Mutex::new(tracker)
+});
+
+impl Tracker {
+    fn locked_until(&self, key: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.keys.get(key).and_then(|failures| failures.locked_until).filter(|until| *until > now)
+    }
+
+    /// Counts a failure against `key` and says how long to hold the
+    /// refusal back.
+    fn fail(&mut self, key: &str, now: DateTime<Utc>) -> std::time::Duration {
+        if self.keys.len() >= SWEEP_THRESHOLD && !self.keys.contains_key(key) {
+            let window = self.window;
+            self.keys.retain(|_, failures| {
+                failures.locked_until.is_some_and(|until| until > now) || now - failures.first_at < window
+            });
+        }
+        let failures = self.keys.entry(key.to_string()).or_insert(Failures {
+            count: 0,
+            first_at: now,
+            last_at: now,
+            locked_until: None,
+        });
+        if now - failures.first_at >= self.window && failures.locked_until.is_none_or(|until| until <= now) {
+            *failures = Failures { count: 0, first_at: now, last_at: now, locked_until: None };
+        }
+        failures.count += 1;
+        failures.last_at = now;
+
+        if failures.count >= self.lockout_failures && failures.locked_until.is_none_or(|until| until <= now) {
+            let until = now + self.lockout;
+            failures.locked_until = Some(until);
+            self.lockouts += 1;
+            warn!(
+                key = %key,
+                failures = failures.count,
+                until = %until.to_rfc3339(),
+                "Locked out after failed authentication"
+            );
+        }
+
+        let beyond = failures.count.saturating_sub(self.free_failures);
+        if beyond == 0 {
+            return std::time::Duration::ZERO;
+        }
+        self.delay_base.saturating_mul(1u32 << (beyond - 1).min(16)).min(self.delay_max)
+    }
+
+    fn count_minute(&mut self, now: DateTime<Utc>) {
+        self.failures += 1;
+        let minute = timestamp_from_millis((now.timestamp() - now.timestamp().rem_euclid(60)) * 1000);
+        if self.minutes.back().is_none_or(|(start, _)| *start != minute) {
+            self.minutes.push_back((minute, 0));
+            while self.minutes.len() > MINUTES_KEPT {
+                self.minutes.pop_front();
+            }
+        }
+        let (start, failures) = self.minutes.back_mut().expect("minute just pushed");
+        *failures += 1;
+        if *failures == self.alert_per_min {
+            warn!(failures = *failures, minute = %start.to_rfc3339(), "Spike in failed authentication");
+        }
+    }
+}
+
+/// The lockout key for a client IP.
+fn ip_key(ip: &str) -> String {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) if ip.to_ipv4_mapped().is_none() => {
+            let prefix = Ipv6Addr::from(u128::from(ip) & !(u128::MAX >> 64));
+            format!("ip:{}/64", prefix)
+        }
+        Ok(IpAddr::V6(ip)) => format!("ip:{}", ip.to_canonical()),
+        Ok(ip) => format!("ip:{}", ip),
+        Err(_) => format!("ip:{}", ip),
+    }
+}
+
+/// The keys a request's failures count against: its client IP (as
+/// `ratelimit::request_ip` gives it) and the third party its API key
+/// names.
+pub fn keys(ip: Option<&str>, third_party: Option<Uuid>) -> Vec<String> {
+    let mut keys: Vec<String> = ip.map(ip_key).into_iter().collect();
+    keys.extend(third_party.map(|id| format!("third-party:{}", id)));
+    keys
+}
+
+/// Refuses the request with `429` while any of `keys` is locked out.
+pub fn check(keys: &[String]) -> Result<(), ApiError> {
+    let now = Utc::now();
+    let mut tracker = TRACKER.lock().unwrap();
+    let Some(until) = keys.iter().filter_map(|key| tracker.locked_until(key, now)).max() else {
+        return Ok(());
+    };
+    tracker.refused += 1;
+    Err(ApiError::RateLimited {
+        message: "Too many failed authentication attempts".to_string(),
+        retry_after_secs: (until - now).num_seconds().max(1) as u64,
+        details: Some(serde_json::json!({ "locked_until": until })),
+    })
+}
+
+/// Counts a failed authentication against `keys`, then waits out the
+/// delay the most failed of them has earned.
+pub async fn failed(keys: &[String]) {
+    let now = Utc::now();
+    let delay = {
+        let mut tracker = TRACKER.lock().unwrap();
+        tracker.count_minute(now);
+        keys.iter().map(|key| tracker.fail(key, now)).max().unwrap_or_default()
+    };
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+}
+
+pub fn succeeded(keys: &[String]) {
+    let mut tracker = TRACKER.lock().unwrap();
+    for key in keys {
+        if tracker.locked_until(key, Utc::now()).is_none() {
+            tracker.keys.remove(key);
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct KeyFailures {
+    pub key: String,
+    pub failures: u32,
+    pub first_at: DateTime<Utc>,
+    pub last_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct MinuteFailures {
+    pub minute: DateTime<Utc>,
+    pub failures: u64,
+}
+
+#[derive(Serialize)]
+pub struct LockoutReport {
+    /// Since startup.
+    pub failures: u64,
+    pub lockouts: u64,
+    /// Requests refused while locked out.
+    pub refused: u64,
+    pub alert_per_min: u64,
+    pub minutes: Vec<MinuteFailures>,
+    /// Locked out first, then by failures.
+    pub keys: Vec<KeyFailures>,
+}
+
+/// `GET /api/admin/lockouts`: this instance's failed authentications and
+/// lockouts.
+pub async fn get_lockouts() -> Json<LockoutReport> {
+    let now = Utc::now();
+    let tracker = TRACKER.lock().unwrap();
+    let mut keys: Vec<KeyFailures> = tracker
+        .keys
+        .iter()
+        .filter(|(_, failures)| {
+            failures.locked_until.is_some_and(|until| until > now) || now - failures.first_at < tracker.window
+        })
+        .map(|(key, failures)| KeyFailures {
+            key: key.clone(),
+            failures: failures.count,
+            first_at: failures.first_at,
+            last_at: failures.last_at,
+            locked_until: failures.locked_until.filter(|until| *until > now),
+        })
+        .collect();
+    keys.sort_by(|a, b| {
+        b.locked_until
+            .is_some()
+            .cmp(&a.locked_until.is_some())
+            .then_with(|| b.failures.cmp(&a.failures))
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    Json(LockoutReport {
+        failures: tracker.failures,
+        lockouts: tracker.lockouts,
+        refused: tracker.refused,
+        alert_per_min: tracker.alert_per_min,
+        minutes: tracker
+            .minutes
+            .iter()
+            .map(|(minute, failures)| MinuteFailures { minute: *minute, failures: *failures })
+            .collect(),
+        keys,
+    })
+}
+
+/// `DELETE /api/admin/lockouts/:key`: forgets a key's failures on this
+/// instance, lifting its lockout. 404 if it has none.
+pub async fn delete_lockout(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+) -> Result<StatusCode, StatusCode> {
+    let admin = auth::actor(claims.as_deref(), &headers, "x-admin")?;
+    if TRACKER.lock().unwrap().keys.remove(&key).is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    audit::record(&state.session, &key, "lockout.lifted", &admin, None).await?;
+
+    info!("Lockout of {} lifted by {}", key, admin);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv6_clients_share_their_64() {
+        assert_eq!(ip_key("2001:db8:1:2:aaaa::1"), ip_key("2001:db8:1:2:bbbb::2"));
+        assert_ne!(ip_key("2001:db8:1:2::1"), ip_key("2001:db8:1:3::1"));
+        assert_eq!(ip_key("::ffff:1.2.3.4"), "ip:1.2.3.4");
+        assert_eq!(ip_key("1.2.3.4"), "ip:1.2.3.4");
+    }
+}
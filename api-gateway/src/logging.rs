@@ -0,0 +1,177 @@
+//! The tracing filter, adjustable at runtime. The gateway logs with
+//! `DEFAULT_DIRECTIVES`; during an incident `PUT /api/admin/log-level`
+//! with `{"directives": "scylla=debug", "duration_secs": 600}` adds
+//! directives on top of them (a later directive for the same target
+//! wins), and they revert by themselves after `duration_secs`
+//! (`LOG_LEVEL_REVERT_SECS`, 900, at most `LOG_LEVEL_MAX_SECS`, 86400).
+//! `GET` shows what is in effect and `DELETE` reverts early. Changes are
+//! on the audit trail of `log-level`.
+//!
+//! Like the rate limits, the filter is per gateway instance: the change
+//! applies to the instance that served the request.
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::{LazyLock, Mutex, OnceLock};
+use tracing::{info, warn};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Registry};
+
+use crate::auth::{self, Claims};
+use crate::error::ApiError;
+use crate::{audit, AppState};
+
+pub const DEFAULT_DIRECTIVES: &str = "api_gateway=debug,info";
+/// The audit trail changes are recorded on.
+const AUDIT_ENTITY: &str = "log-level";
+
+static HANDLE: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+static OVERRIDE: LazyLock<Mutex<Overrides>> = LazyLock::new(|| Mutex::new(Overrides::default()));
+
+#[derive(Default)]
+struct Overrides {
+    current: Option<Override>,
+    /// Bumped on every change, so a revert timer only undoes its own.
+    generation: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Override {
+    pub directives: String,
+    pub set_by: String,
+    pub set_at: DateTime<Utc>,
+    pub revert_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LogLevel {
+    /// The filter in effect.
+    pub filter: String,
+    pub default: &'static str,
+    #[serde(rename = "override")]
+    pub current: Option<Override>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogLevelRequest {
+    pub directives: String,
+    pub duration_secs: Option<i64>,
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Installs the global subscriber; call once, first thing.
+pub fn init() {
+    let (filter, handle) = reload::Layer::new(EnvFilter::new(DEFAULT_DIRECTIVES));
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    let _ = HANDLE.set(handle);
+}
+
+fn apply(filter: EnvFilter) -> Result<(), ApiError> {
+    let handle = HANDLE.get().ok_or(ApiError::Status(StatusCode::SERVICE_UNAVAILABLE))?;
+    handle.reload(filter).map_err(|e| {
+        warn!("Failed to reload the log filter: {}", e);
+        ApiError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+    })
+}
+
+fn filter_with(directives: &str) -> Result<EnvFilter, ApiError> {
+    EnvFilter::try_new(format!("{},{}", DEFAULT_DIRECTIVES, directives))
+        .map_err(|e| ApiError::validation(format!("Invalid directives: {}", e)))
+}
+
+fn current() -> LogLevel {
+    let overrides = OVERRIDE.lock().unwrap();
+    let filter = match &overrides.current {
+        Some(current) => format!("{},{}", DEFAULT_DIRECTIVES, current.directives),
+        None => DEFAULT_DIRECTIVES.to_string(),
+    };
+    LogLevel { filter, default: DEFAULT_DIRECTIVES, current: overrides.current.clone() }
+}
+
+/// Drops the override if it is still the one from `generation` (any, if
+/// `None`). Whether there was one to drop.
+fn revert(generation: Option<u64>) -> Result<bool, ApiError> {
+    let mut overrides = OVERRIDE.lock().unwrap();
+    if overrides.current.is_none() || generation.is_some_and(|generation| generation != overrides.generation) {
+        return Ok(false);
+    }
+    apply(EnvFilter::new(DEFAULT_DIRECTIVES))?;
+    overrides.current = None;
+    overrides.generation += 1;
+    info!("Log filter reverted to {}", DEFAULT_DIRECTIVES);
+    Ok(true)
+}
+
+/// `GET /api/admin/log-level`
+pub async fn get_log_level() -> Json<LogLevel> {
+    Json(current())
+}
+
+/// `PUT /api/admin/log-level`: adds `directives` until `duration_secs`
+/// from now, replacing any earlier change.
+pub async fn put_log_level(
+    State(state): State<AppState>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+    Json(request): Json<LogLevelRequest>,
+) -> Result<Json<LogLevel>, ApiError> {
+    let admin = auth::actor(claims.as_deref(), &headers, "x-admin")?;
+    let directives = request.directives.trim().to_string();
+    if directives.is_empty() {
+        return Err(ApiError::validation("directives is required"));
+    }
+    let max_secs = env_or("LOG_LEVEL_MAX_SECS", 86_400);
+    let duration_secs = request.duration_secs.unwrap_or_else(|| env_or("LOG_LEVEL_REVERT_SECS", 900));
+    if !(1..=max_secs).contains(&duration_secs) {
+        return Err(ApiError::validation(format!("duration_secs must be between 1 and {}", max_secs)));
+    }
+    let filter = filter_with(&directives)?;
+
+    let now = Utc::now();
+    let generation = {
+        let mut overrides = OVERRIDE.lock().unwrap();
+        apply(filter)?;
+        overrides.generation += 1;
+        overrides.current = Some(Override {
+            directives: directives.clone(),
+            set_by: admin.clone(),
+            set_at: now,
+            revert_at: now + Duration::seconds(duration_secs),
+        });
+        overrides.generation
+    };
+    tokio::spawn(async move {
+        tokio::time::sleep(std::time::Duration::from_secs(duration_secs as u64)).await;
+        let _ = revert(Some(generation));
+    });
+
+    let details = format!("{} for {}s", directives, duration_secs);
+    audit::record(&state.session, AUDIT_ENTITY, "log_level.changed", &admin, Some(details)).await?;
+    info!("Log filter changed by {}: +{} for {}s", admin, directives, duration_secs);
+    Ok(Json(current()))
+}
+
+/// `DELETE /api/admin/log-level`: reverts to the defaults now.
+pub async fn delete_log_level(
+    State(state): State<AppState>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+) -> Result<StatusCode, ApiError> {
+    let admin = auth::actor(claims.as_deref(), &headers, "x-admin")?;
+    if !revert(None)? {
+        return Err(ApiError::NotFound("No log level override is in effect".to_string()));
+    }
+    audit::record(&state.session, AUDIT_ENTITY, "log_level.reverted", &admin, None).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,104 +1,516 @@
+use async_graphql_axum::{GraphQL, GraphQLSubscription};
 use axum::{
     extract::{Query, State},
-    http::{StatusCode, Method},
-    response::Json,
-    routing::{get, post},
-    Router,
+    http::{HeaderMap, StatusCode, Method},
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, patch, post, put},
+    Extension, Router,
 };
+use chrono::{DateTime, Utc};
+use openssl::ssl::{SslContext, SslContextBuilder, SslFiletype, SslMethod, SslVerifyMode, SslVersion};
 use scylla::{Session, SessionBuilder};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 use tower_http::cors::{CorsLayer, Any};
-use tracing::{info, error};
+use tracing::{info, error, warn};
 use uuid::Uuid;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Transaction {
+#[cfg(feature = "all-in-one")]
+mod all_in_one;
+mod assets;
+mod audit;
+mod auth;
+mod batching;
+mod channels;
+mod circuit_breaker;
+mod consents;
+mod counterparties;
+mod db;
+mod disputes;
+#[cfg(feature = "embed-web")]
+mod embedded;
+mod endpoints;
+mod erasure;
+mod error;
+mod events;
+mod fields;
+mod funding;
+mod graphql;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod hotspots;
+mod idempotency;
+mod leader;
+mod live;
+mod lockout;
+mod logging;
+mod migrations;
+mod mtls;
+mod netting;
+mod pagination;
+mod payment_files;
+mod pii;
+mod privacy;
+mod projections;
+mod push;
+mod ratelimit;
+mod receipts;
+mod reporting;
+mod repository;
+mod review;
+mod risk;
+mod screening;
+mod secrets;
+mod sends;
+mod sequence;
+mod service_keys;
+mod settlement;
+mod signatures;
+mod snapshots;
+mod statements;
+mod store;
+mod swaps;
+mod timeline;
+mod tls;
+mod transforms;
+#[cfg(feature = "ts")]
+mod typescript;
+mod validation;
+mod web;
+
+// Shared with the endpoints, see tx-core
+pub use tx_core::{Transaction, TransactionKind, TransactionStatus};
+use tx_core::{Money, MoneyError, NATIVE_ASSET};
+use error::ApiError;
+use repository::Statement;
+
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct IngestResponse {
     pub id: String,
-    pub from_endpoint: String,
-    pub to_endpoint: String,
-    pub amount: f64,
-    pub timestamp: i64,
-    pub signature: String,
-    pub status: String,
+    pub risk_score: u8,
+    #[cfg_attr(feature = "ts", ts(type = "Array<string>"))]
+    pub risk_reasons: Vec<&'static str>,
+    /// Whether receivers should hold the payment for manual accept.
+    pub held: bool,
+    /// From the ingest transform, if one is configured.
+    pub tags: Vec<String>,
 }
 
+// Amounts are summed as `Money` and still served as plain numbers
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 pub struct TransactionStats {
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
     pub total_transactions: i64,
-    pub total_volume: f64,
-    pub average_transaction: f64,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub total_volume: Money,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub average_transaction: Money,
     pub endpoints: Vec<EndpointStats>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
 pub struct EndpointStats {
     pub endpoint_id: String,
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
     pub transaction_count: i64,
-    pub total_sent: f64,
-    pub total_received: f64,
-    pub balance_change: f64,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub total_sent: Money,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub total_received: Money,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub balance_change: Money,
+}
+
+impl EndpointStats {
+    fn empty(endpoint_id: &str) -> Self {
+        EndpointStats {
+            endpoint_id: endpoint_id.to_string(),
+            transaction_count: 0,
+            total_sent: Money::zero(NATIVE_ASSET),
+            total_received: Money::zero(NATIVE_ASSET),
+            balance_change: Money::zero(NATIVE_ASSET),
+        }
+    }
+
+    /// The endpoint's balance given what it started with.
+    fn balance_from(&self, initial_balance: &Money) -> Result<Money, MoneyError> {
+        initial_balance.checked_add(&self.balance_change)
+    }
+
+    /// Folds one log entry into the stats. A chargeback runs from the
+    /// original receiver back to the original sender and undoes the
+    /// transfer's totals rather than counting as a transfer of its own.
+    fn apply(&mut self, from_endpoint: &str, to_endpoint: &str, amount: &Money, kind: TransactionKind) -> Result<(), MoneyError> {
+        // Already counted through the net transfer they belong to
+        if kind == TransactionKind::Netted {
+            return Ok(());
+        }
+
+        if kind == TransactionKind::Chargeback {
+            if from_endpoint == self.endpoint_id {
+                self.total_received = self.total_received.checked_sub(amount)?;
+                self.balance_change = self.balance_change.checked_sub(amount)?;
+            }
+            if to_endpoint == self.endpoint_id {
+                self.total_sent = self.total_sent.checked_sub(amount)?;
+                self.balance_change = self.balance_change.checked_add(amount)?;
+            }
+            return Ok(());
+        }
+
+        self.transaction_count += 1;
+
+        if from_endpoint == self.endpoint_id {
+            self.total_sent = self.total_sent.checked_add(amount)?;
+            self.balance_change = self.balance_change.checked_sub(amount)?;
+        }
+
+        if to_endpoint == self.endpoint_id {
+            self.total_received = self.total_received.checked_add(amount)?;
+            self.balance_change = self.balance_change.checked_add(amount)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone)]
 pub struct AppState {
-    session: Session,
+    /// Derefs to the `Session` for statements not prepared yet
+    session: repository::Repository,
+    settlement: Arc<dyn settlement::SettlementProvider>,
+    screening: Arc<dyn screening::ScreeningProvider>,
+    events: Arc<dyn events::EventPublisher>,
+    store: Arc<dyn store::TransactionStore>,
+    leadership: leader::Leadership,
+    keys: service_keys::ServiceKeys,
+    secrets: secrets::Secrets,
+    live: live::LiveFeed,
+    transforms: transforms::Transforms,
+    auth: auth::Auth,
+    reporting: reporting::ReportingRules,
+    stats_privacy: privacy::StatsPrivacy,
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_env_filter("api_gateway=debug,info")
-        .init();
+    logging::init();
+
+    // `api-gateway verify-receipt <receipt.json> [<history.json>]`
+    // checks a receipt offline and exits
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("verify-receipt") {
+        receipts::verify_offline(&args[2..])?;
+        return Ok(());
+    }
+
+    // `api-gateway export-types [<file.d.ts>]` writes the TypeScript
+    // definitions and exits (`--features ts` builds only)
+    #[cfg(feature = "ts")]
+    if args.get(1).map(String::as_str) == Some("export-types") {
+        let path = args.get(2).map(String::as_str).unwrap_or(typescript::DEFAULT_PATH);
+        typescript::export(std::path::Path::new(path))?;
+        info!("Wrote TypeScript definitions to {}", path);
+        return Ok(());
+    }
+
+    // `api-gateway serve-web <dir>` serves the built wasm app with a strict
+    // CSP and hashed, integrity-pinned assets instead of the gateway
+    if args.get(1).map(String::as_str) == Some("serve-web") {
+        return web::serve(&args[2..]).await;
+    }
+
+    // `api-gateway --all-in-one [<dir>]` runs the gateway on an in-memory
+    // store, the signaling server and the wasm app on one port
+    if args.iter().any(|arg| arg == "--all-in-one") {
+        #[cfg(feature = "all-in-one")]
+        return all_in_one::serve(&args).await;
+        #[cfg(not(feature = "all-in-one"))]
+        return Err("--all-in-one needs a build with `--features all-in-one`".into());
+    }
 
     info!("Starting API Gateway...");
 
+    // `STORAGE_BACKEND=sqlite|memory` runs the transaction routes alone,
+    // without a database server
+    let backend = store::Backend::from_env()?;
+    if backend != store::Backend::Scylla {
+        return store::serve_standalone(store::standalone(backend)?).await;
+    }
+
+    let secrets = secrets::Secrets::from_env()?;
+
     // Connect to ScyllaDB with retry logic
-    let session = connect_to_scylla().await?;
+    let session = connect_to_scylla(&secrets).await?;
+
+    // `api-gateway --migrate-only [--dry-run]` brings the schema up to date
+    // (or lists what that would apply) and exits
+    let migrate_only = args.iter().any(|arg| arg == "--migrate-only");
+    if migrate_only && args.iter().any(|arg| arg == "--dry-run") {
+        migrations::dry_run(&session).await?;
+        return Ok(());
+    }
     
     // Initialize database schema
-    init_database(&session).await?;
+    init_database(&session, migrate_only).await?;
+    if migrate_only {
+        info!("✅ Schema migrated");
+        return Ok(());
+    }
+    pii::init(&secrets).await?;
+    let session = repository::Repository::prepare(session).await?;
+
+    // `api-gateway rebuild-projections [--full]` rebuilds the read models
+    // from the latest snapshot (or the whole log) and exits
+    if args.get(1).map(String::as_str) == Some("rebuild-projections") {
+        let full = args.iter().any(|arg| arg == "--full");
+        snapshots::rebuild(&session, full).await?;
+        return Ok(());
+    }
+
+    // `api-gateway rotate-keys` schedules the next service key and exits
+    if args.get(1).map(String::as_str) == Some("rotate-keys") {
+        service_keys::rotate(&session).await?;
+        return Ok(());
+    }
+
+    // `api-gateway rotate-pii-keys [--new-data-keys]` rewraps the personal
+    // data keys under the current master key and exits
+    if args.get(1).map(String::as_str) == Some("rotate-pii-keys") {
+        let new_data_keys = args.iter().any(|arg| arg == "--new-data-keys");
+        pii::rotate(&session, new_data_keys).await?;
+        return Ok(());
+    }
 
-    let state = AppState { session };
+    let keys = service_keys::ServiceKeys::load(&session, &secrets).await?;
+    let public_tls = tls::public_from_env()?;
+    let internal_tls = mtls::InternalTls::from_env()?;
+    let mtls_enforced = internal_tls.is_some();
+    let auth = auth::Auth::from_env(&secrets).await?;
+    let stats_privacy = privacy::StatsPrivacy::from_env(&secrets).await?;
+    let state = AppState {
+        store: Arc::new(store::ScyllaStore::new(session.clone())),
+        session,
+        settlement: settlement::provider_from_env(),
+        screening: screening::provider_from_env(),
+        events: events::publisher_from_env(),
+        leadership: leader::Leadership::from_env(),
+        keys,
+        secrets,
+        live: live::LiveFeed::default(),
+        transforms: transforms::Transforms::from_env()?,
+        auth,
+        reporting: reporting::ReportingRules::from_env()?,
+        stats_privacy,
+    };
+
+    tokio::spawn(leader::run_election(state.clone()));
+    tokio::spawn(events::run_relay(state.clone()));
+    tokio::spawn(snapshots::run_snapshots(state.clone()));
+    tokio::spawn(swaps::run_swap_expiry(state.clone()));
+    tokio::spawn(state.secrets.clone().run_refresh());
+    tokio::spawn(service_keys::run_key_refresh(state.clone()));
+    tokio::spawn(live::run_live_tail(state.clone()));
+    tokio::spawn(timeline::run_maintenance(state.clone()));
+    tokio::spawn(state.transforms.clone().run_reload());
+    tokio::spawn(reporting::run_reporting(state.clone()));
+    #[cfg(feature = "grpc")]
+    tokio::spawn(grpc::serve(state.clone()));
+
+    let schema = graphql::schema(state.clone());
 
     // Build our application with routes
     let app = Router::new()
         .route("/api/transactions", get(get_transactions))
         .route("/api/transactions", post(create_transaction))
+        .route("/api/transactions/net", post(netting::create_net_transaction))
+        .route("/api/transactions/stream", get(live::stream_transactions))
+        .route("/api/ws", get(push::connect))
+        .route_service("/api/graphql", GraphQL::new(schema.clone()))
+        .route_service("/api/graphql/ws", GraphQLSubscription::new(schema))
         .route("/api/transactions/:id", get(get_transaction_by_id))
+        .route("/api/transactions/:id/status", patch(patch_transaction_status))
+        .route("/api/transactions/:id/receipt", get(receipts::get_receipt))
+        .route("/api/receipts/key", get(receipts::get_receipt_key))
+        .route("/api/.well-known/keys", get(service_keys::get_keys))
         .route("/api/stats", get(get_stats))
+        .route("/api/endpoints", post(endpoints::create_endpoint))
+        .route("/api/endpoints/:id", get(endpoints::get_endpoint))
+        .route("/api/endpoints/:id/suspend", post(endpoints::suspend_endpoint))
+        .route("/api/endpoints/:id/activate", post(endpoints::activate_endpoint))
+        .route("/api/endpoints/:id/close", post(endpoints::close_endpoint))
+        .route("/api/endpoints/:id/personal-data", delete(erasure::erase_personal_data))
+        .route("/api/endpoints/:id/deposits", post(funding::create_deposit))
+        .route("/api/endpoints/:id/top-up-requests", post(funding::request_top_up))
+        .route("/api/admin/top-up-requests", get(funding::list_top_ups))
+        .route("/api/admin/top-up-requests/:id/approve", post(funding::approve_top_up))
+        .route("/api/admin/top-up-requests/:id/reject", post(funding::reject_top_up))
+        .route("/api/endpoints/:id/assets", get(assets::get_assets))
+        .route("/api/endpoints/:id/assets/:asset/deposits", post(assets::create_asset_deposit))
+        .route("/api/endpoints/:id/counterparties", get(counterparties::get_lists))
+        .route("/api/endpoints/:id/chargebacks", get(disputes::get_endpoint_chargebacks))
+        .route("/api/endpoints/:id/sequence", get(sequence::get_sequence))
+        .route("/api/endpoints/:id/breaker", get(circuit_breaker::get_breaker))
+        .route("/api/endpoints/:id/breaker/override", post(circuit_breaker::override_breaker))
+        .route(
+            "/api/endpoints/:id/counterparties/:list/:peer",
+            put(counterparties::add_counterparty).delete(counterparties::remove_counterparty),
+        )
+        .route("/api/endpoints/:id/withdrawals", post(settlement::create_withdrawal))
+        .route("/api/settlements/:id", get(settlement::get_settlement))
+        .route("/api/settlements/:id/confirmation", post(settlement::confirm_settlement))
+        .route("/api/channels/settle", post(channels::settle_channel))
+        .route("/api/swaps/hold", post(swaps::hold_swap))
+        .route("/api/swaps/:id", get(swaps::get_swap))
+        .route("/api/rooms/:id/batching", get(batching::get_batching))
+        .route("/api/rooms/:id/batching", put(batching::update_batching))
+        .route("/api/rooms/:id/batches", get(batching::list_batches))
+        .route(
+            "/api/rooms/:id/batches",
+            post(batching::settle_batch)
+                .route_layer(axum::middleware::from_fn_with_state(mtls_enforced, mtls::require_peer)),
+        )
+        .route("/api/transactions/:id/dispute", post(disputes::open_dispute))
+        .route("/api/disputes", get(disputes::list_disputes))
+        .route("/api/disputes/:id", get(disputes::get_dispute))
+        .route("/api/disputes/:id/evidence", post(disputes::add_evidence))
+        .route("/api/disputes/:id/evidence/:evidence_id/attachment", get(disputes::get_attachment))
+        .route("/api/disputes/:id/escalate", post(disputes::escalate_dispute))
+        .route("/api/disputes/:id/resolve", post(disputes::resolve_dispute))
+        .route("/api/review-queue", get(review::get_review_queue))
+        .route("/api/review-queue/:id/approve", post(review::approve_transaction))
+        .route("/api/review-queue/:id/reject", post(review::reject_transaction))
+        .route("/api/audit/:entity_id", get(audit::get_audit_trail))
+        .route("/api/events", get(events::get_events))
+        .route("/api/admin/partitions/hot", get(hotspots::get_hot_partitions))
+        .route("/api/admin/rate-limits", get(ratelimit::get_rate_limits))
+        .route("/api/admin/lockouts", get(lockout::get_lockouts))
+        .route("/api/admin/lockouts/:key", delete(lockout::delete_lockout))
+        .route(
+            "/api/admin/log-level",
+            get(logging::get_log_level).put(logging::put_log_level).delete(logging::delete_log_level),
+        )
+        .route("/api/admin/reporting-rules", get(reporting::get_rules))
+        .route("/api/admin/reports", get(reporting::list_reports))
+        .route("/api/admin/reports", post(reporting::generate_report))
+        .route("/api/admin/reports/:rule/:period_start", get(reporting::get_report))
+        .route("/api/admin/statements", get(repository::get_statements))
         .route("/api/endpoints/:id/stats", get(get_endpoint_stats))
+        .route("/api/endpoints/:id/balance", get(get_endpoint_balance))
+        .route("/api/endpoints/:id/statement", get(statements::get_statement))
+        .route("/api/endpoints/:id/key", put(signatures::put_key))
+        .route("/api/endpoints/:id/key-usage", get(signatures::get_key_usage))
+        .route("/api/endpoints/:id/consents", get(consents::list_consents))
+        .route("/api/endpoints/:id/consents", post(consents::grant_consent))
+        .route("/api/endpoints/:id/consents/:consent_id", delete(consents::revoke_consent))
+        .route("/api/admin/third-parties", post(consents::create_third_party))
+        .route("/api/endpoints/:id/payment-instructions", get(payment_files::get_queue))
+        .route(
+            "/api/endpoints/:id/payment-instructions/:tx_id/decline",
+            post(payment_files::decline_instruction),
+        )
+        .route("/api/payment-files", post(payment_files::import_file))
+        .route("/api/payment-files/:id", get(payment_files::get_file))
+        .route("/leader", get(leader::get_leader))
         .route("/health", get(health_check))
+        .route("/api/auth/token", post(auth::issue_token));
+    // `--features embed-web` builds also serve the client apps
+    #[cfg(feature = "embed-web")]
+    let app = embedded::mount(app);
+    let app = app
+        .layer(axum::middleware::from_fn_with_state(state.clone(), auth::authenticate))
+        .layer(ratelimit::RateLimitLayer::from_env())
+        .layer(axum::middleware::from_fn(error::request_ids))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
-                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+                .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE])
                 .allow_headers(Any)
+                .expose_headers([
+                    axum::http::header::LINK,
+                    pagination::NEXT_CURSOR_HEADER,
+                    axum::http::HeaderName::from_static(error::REQUEST_ID_HEADER),
+                ])
         )
         .with_state(state);
 
+    if let Some(internal_tls) = internal_tls {
+        tokio::spawn(internal_tls.config.clone().run_reload());
+        tokio::spawn(mtls::serve(app.clone(), internal_tls));
+    }
+
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await?;
-    info!("🚀 API Gateway running on http://0.0.0.0:3001");
-    
-    axum::serve(listener, app).await?;
+    match public_tls {
+        Some(config) => {
+            info!("🚀 API Gateway running on https://0.0.0.0:3001");
+            tokio::spawn(config.clone().run_reload());
+            tls::serve(listener, app, config, tls::any_client).await;
+        }
+        None => {
+            info!("🚀 API Gateway running on http://0.0.0.0:3001");
+            axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
+        }
+    }
     Ok(())
 }
 
-async fn connect_to_scylla() -> Result<Session, Box<dyn std::error::Error>> {
+async fn connect_to_scylla(secrets: &secrets::Secrets) -> Result<Session, Box<dyn std::error::Error>> {
+    // Comma-separated contact points, e.g. several nodes of a managed cluster
     let scylla_host = std::env::var("SCYLLA_HOST").unwrap_or_else(|_| "127.0.0.1:9042".to_string());
     info!("Connecting to ScyllaDB at {}", scylla_host);
 
-    let session = SessionBuilder::new()
-        .known_node(&scylla_host)
-        .build()
-        .await?;
+    let mut builder = SessionBuilder::new()
+        .known_nodes(scylla_host.split(',').map(str::trim).filter(|host| !host.is_empty()))
+        .ssl_context(scylla_ssl_context()?)
+        .default_execution_profile_handle(db::execution_profile().into_handle());
+    if let Some(username) = secrets.get("SCYLLA_USERNAME").await? {
+        let password = secrets.get("SCYLLA_PASSWORD").await?.ok_or("SCYLLA_USERNAME is set but SCYLLA_PASSWORD isn't")?;
+        builder = builder.user(username.expose(), password.expose());
+    }
+    let session = builder.build().await?;
 
     info!("✅ Connected to ScyllaDB");
     Ok(session)
 }
 
-async fn init_database(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+/// TLS to Scylla, with `SCYLLA_TLS=true`. The server certificate must
+/// chain to `SCYLLA_CA` (the system roots if unset); `SCYLLA_CLIENT_CERT`
+/// and `SCYLLA_CLIENT_KEY` (PEM) are presented to clusters that require
+/// client certificates.
+fn scylla_ssl_context() -> Result<Option<SslContext>, Box<dyn std::error::Error>> {
+    if std::env::var("SCYLLA_TLS").as_deref() != Ok("true") {
+        return Ok(None);
+    }
+
+    let mut context = SslContextBuilder::new(SslMethod::tls())?;
+    context.set_min_proto_version(Some(SslVersion::TLS1_2))?;
+    context.set_verify(SslVerifyMode::PEER);
+    match std::env::var("SCYLLA_CA") {
+        Ok(ca) => context.set_ca_file(ca)?,
+        Err(_) => context.set_default_verify_paths()?,
+    }
+    if let Ok(cert) = std::env::var("SCYLLA_CLIENT_CERT") {
+        let key = std::env::var("SCYLLA_CLIENT_KEY").map_err(|_| "SCYLLA_CLIENT_CERT is set but SCYLLA_CLIENT_KEY isn't")?;
+        context.set_certificate_chain_file(cert)?;
+        context.set_private_key_file(key, SslFiletype::PEM)?;
+        context.check_private_key()?;
+    }
+    info!("Connecting to ScyllaDB over TLS");
+    Ok(Some(context.build()))
+}
+
+/// Creates the keyspace and the migrations' own tables, then applies the
+/// migrations (with `migrate` set, even if `MIGRATE_ON_START=false`).
+async fn init_database(session: &Session, migrate: bool) -> Result<(), Box<dyn std::error::Error>> {
     info!("Initializing database schema...");
 
     // Create keyspace
@@ -113,297 +525,583 @@ async fn init_database(session: &Session) -> Result<(), Box<dyn std::error::Erro
         )
         .await?;
 
-    // Create transactions table
-    session
-        .query(
-            "CREATE TABLE IF NOT EXISTS transactions.tx_log (
-                 id UUID PRIMARY KEY,
-                 from_endpoint TEXT,
-                 to_endpoint TEXT,
-                 amount DOUBLE,
-                 timestamp BIGINT,
-                 signature TEXT,
-                 status TEXT
-             )",
-            &[],
-        )
-        .await?;
+    // What the migrations need to run; every table is created by them
+    migrations::init_schema(session).await?;
+    leader::init_schema(session).await?;
+    migrations::run(session, migrate).await?;
 
-    // Create index for timestamp-based queries
-    session
-        .query(
-            "CREATE INDEX IF NOT EXISTS tx_timestamp_idx 
-             ON transactions.tx_log (timestamp)",
-            &[],
-        )
-        .await?;
+    migrate_transaction_statuses(session).await?;
+    projections::backfill(session).await?;
+    projections::migrate_minor_units(session).await?;
+    projections::migrate_totals(session).await?;
+    timeline::configure(session).await?;
+    timeline::backfill(session).await?;
+    sends::backfill(session).await?;
 
     info!("✅ Database schema initialized");
     Ok(())
 }
 
+async fn migrate_transaction_statuses(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    let rows = session
+        .query(db::scan("SELECT id, status FROM transactions.tx_log"), &[])
+        .await?;
+
+    let mut migrated = 0;
+    if let Some(rows) = rows.rows {
+        for row in rows {
+            let Ok((id, status)) = row.into_typed::<(Uuid, String)>() else {
+                continue;
+            };
+            if status.parse::<TransactionStatus>().is_ok() {
+                continue;
+            }
+
+            match TransactionStatus::from_legacy(&status) {
+                Some(canonical) => {
+                    session
+                        .query(
+                            "UPDATE transactions.tx_log SET status = ? WHERE id = ?",
+                            (canonical.as_str(), id),
+                        )
+                        .await?;
+                    migrated += 1;
+                }
+                None => warn!("Transaction {} has unrecognized status '{}'", id, status),
+            }
+        }
+    }
+
+    if migrated > 0 {
+        info!("Normalized status on {} transactions", migrated);
+    }
+    Ok(())
+}
+
+const TX_COLUMNS: &str =
+    "id, from_endpoint, to_endpoint, amount, amount_minor, timestamp, signature, status, kind, risk_score, parent_tx_id, sequence";
+
+type TxRow =
+    (Uuid, String, String, f64, Option<i64>, i64, String, String, Option<String>, Option<i32>, Option<Uuid>, Option<i64>);
+
+fn transaction_from_row(row: TxRow) -> Result<Transaction, String> {
+    let (id, from_endpoint, to_endpoint, amount, amount_minor, timestamp, signature, status, kind, risk_score, parent_tx_id, sequence) =
+        row;
+    // Rows written before `amount_minor` existed only have the double
+    let amount = match amount_minor {
+        Some(minor) => Money::new(minor, NATIVE_ASSET),
+        None => Money::from_major(amount, NATIVE_ASSET).map_err(|e| e.to_string())?,
+    };
+    Ok(Transaction {
+        id: id.to_string(),
+        from_endpoint,
+        to_endpoint,
+        amount,
+        timestamp: timestamp_from_millis(timestamp),
+        signature,
+        status: status.parse()?,
+        // Rows written before `kind` existed are plain transfers
+        kind: kind.as_deref().map(str::parse).transpose()?.unwrap_or_default(),
+        risk_score: risk_score.map(|score| score.clamp(0, 100) as u8),
+        parent_tx_id: parent_tx_id.map(|id| id.to_string()),
+        sequence: sequence.map(|sequence| sequence as u64),
+        public_key: None,
+    })
+}
+
+/// Writes go to the event log first; `tx_log` and the endpoint ledger are
+/// projections of it (see `projections`).
+async fn record_event(session: &Session, kind: events::EventKind, transaction: &Transaction) -> Result<(), StatusCode> {
+    let event = events::append(session, kind, transaction).await?;
+    project(session, &event, transaction).await
+}
+
+async fn project(session: &Session, event: &events::Event, transaction: &Transaction) -> Result<(), StatusCode> {
+    projections::apply(session, event).await.map_err(|e| {
+        error!("Failed to project event for {}: {}", transaction.id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn insert_transaction(session: &Session, transaction: &Transaction) -> Result<(), StatusCode> {
+    Uuid::parse_str(&transaction.id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if let Some(parent_tx_id) = &transaction.parent_tx_id {
+        Uuid::parse_str(parent_tx_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    }
+
+    // Once per transaction, so a retry after a failed projection re-applies
+    // the event it already appended instead of appending another
+    let event = events::append_once(session, projections::creation_event(transaction), transaction).await?;
+    project(session, &event, transaction).await
+}
+
+async fn load_transaction(session: &repository::Repository, tx_id: Uuid) -> Result<Option<Transaction>, ApiError> {
+    let rows = session.execute(Statement::TransactionById, (tx_id,)).await?;
+
+    if let Some(rows) = rows.rows {
+        if let Some(row) = rows.into_iter().next() {
+            if let Ok(row) = row.into_typed::<TxRow>() {
+                return transaction_from_row(row).map(Some).map_err(|e| {
+                    error!("Transaction {}: {}", tx_id, e);
+                    ApiError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+                });
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Moves a transaction to `status`, 409 if its lifecycle doesn't allow
+/// that. Already being there is a no-op, so repeated updates are fine.
+async fn update_transaction_status(
+    session: &repository::Repository,
+    id: &str,
+    status: TransactionStatus,
+) -> Result<Transaction, StatusCode> {
+    let tx_id = Uuid::parse_str(id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut transaction = load_transaction(session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if transaction.status == status {
+        return Ok(transaction);
+    }
+    transaction.status = transaction.status.transition_to(status).map_err(|e| {
+        warn!("Transaction {}: {}", id, e);
+        StatusCode::CONFLICT
+    })?;
+    record_event(session, events::EventKind::StatusChanged, &transaction).await?;
+    Ok(transaction)
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusUpdate {
+    status: TransactionStatus,
+}
+
+/// `PATCH /api/transactions/:id/status` with `{"status": ...}`, for
+/// endpoints reporting delivery progress. Holds are placed by ingest and
+/// lifted through the review queue, never here.
+async fn patch_transaction_status(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(update): Json<StatusUpdate>,
+) -> Result<Json<Transaction>, ApiError> {
+    if update.status == TransactionStatus::Held {
+        return Err(ApiError::validation("Holds are placed by the gateway, not reported"));
+    }
+    let tx_id = Uuid::parse_str(&id)?;
+    let current = load_transaction(&state.session, tx_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Transaction {} not found", id)))?;
+    if current.status == TransactionStatus::Held {
+        return Err(ApiError::Conflict(format!("Transaction {} is held for review", id)));
+    }
+
+    let transaction = update_transaction_status(&state.session, &id, update.status).await?;
+    info!("Transaction {} is now {}", id, transaction.status);
+    Ok(Json(transaction))
+}
+
+/// Whether a conditional (`IF ...`) statement took effect, read from the
+/// `[applied]` column Scylla returns first.
+fn lwt_applied(result: scylla::QueryResult) -> bool {
+    matches!(
+        result
+            .rows
+            .and_then(|rows| rows.into_iter().next())
+            .and_then(|row| row.columns.into_iter().next().flatten()),
+        Some(scylla::frame::response::result::CqlValue::Boolean(true))
+    )
+}
+
+/// Funding and settlement entries use `system:` pseudo-endpoints.
+fn is_system_account(endpoint_id: &str) -> bool {
+    endpoint_id.starts_with("system:")
+}
+
+/// An amount stored as minor units in a `_minor` column, read from the
+/// double column it replaced for rows written before it existed.
+fn stored_money(minor: Option<i64>, major: Option<f64>, currency: &str) -> Result<Money, MoneyError> {
+    match (minor, major) {
+        (Some(minor), _) => Ok(Money::new(minor, currency)),
+        (None, Some(major)) => Money::from_major(major, currency),
+        (None, None) => Ok(Money::zero(currency)),
+    }
+}
+
+/// Timestamps are stored as BIGINT milliseconds since the epoch (UTC).
+fn timestamp_from_millis(millis: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(millis).unwrap_or_default()
+}
+
 async fn health_check() -> Json<serde_json::Value> {
     Json(serde_json::json!({
         "status": "healthy",
-        "timestamp": chrono::Utc::now().to_rfc3339(),
+        "timestamp": Utc::now().to_rfc3339(),
         "service": "api-gateway"
     }))
 }
 
+/// `GET /api/transactions`: newest first, `limit` (100, at most 1000) per
+/// page, paged with `before`/`after` cursors (see `pagination`).
+/// `GET /api/transactions[?endpoint=&as_of=&before=&after=&limit=&fields=]`
 async fn get_transactions(
     State(state): State<AppState>,
+    access: Option<Extension<consents::ThirdPartyAccess>>,
     Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<Vec<Transaction>>, StatusCode> {
-    let limit = params
-        .get("limit")
-        .and_then(|l| l.parse::<i32>().ok())
-        .unwrap_or(100);
-
+) -> Result<(HeaderMap, Json<fields::TransactionList>), ApiError> {
+    let limit = pagination::limit_from(&params);
+    let page = pagination::Page::from_params(&params)?;
+    let projection = fields::Projection::from_params(&params)?;
     let endpoint = params.get("endpoint");
+    consents::confine(access.as_deref(), endpoint.map(String::as_str), consents::Scope::Transactions)?;
 
-    let (query, values): (String, Vec<scylla::frame::value::Value>) = if let Some(ep) = endpoint {
-        (
-            "SELECT id, from_endpoint, to_endpoint, amount, timestamp, signature, status 
-             FROM transactions.tx_log WHERE from_endpoint = ? OR to_endpoint = ? LIMIT ? ALLOW FILTERING".to_string(),
-            vec![ep.clone().into(), ep.clone().into(), limit.into()]
-        )
-    } else {
-        (
-            "SELECT id, from_endpoint, to_endpoint, amount, timestamp, signature, status 
-             FROM transactions.tx_log LIMIT ?".to_string(),
-            vec![limit.into()]
-        )
-    };
+    // Historical view: replay the event log instead of reading tx_log
+    if let Some(as_of) = parse_as_of(&params)? {
+        let transactions = projections::transactions_as_of(&state.session, endpoint.map(String::as_str), as_of)
+            .await
+            .map_err(|e| {
+                error!("Failed to reconstruct transactions as of {}: {}", as_of, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        let transactions = pagination::paginate(transactions, &page, limit);
+        let headers = pagination::headers("/api/transactions", &params, &page, limit, &transactions);
+        let list = match projection {
+            Some(projection) => fields::TransactionList::Sparse(transactions.iter().map(|tx| projection.apply(tx)).collect()),
+            None => fields::TransactionList::Full(transactions),
+        };
+        return Ok((headers, Json(list)));
+    }
 
-    let rows = state
-        .session
-        .query(query, values)
+    let ids = timeline::page(&state.session, endpoint.map(String::as_str), &page, limit)
         .await
         .map_err(|e| {
             error!("Database query error: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         })?;
+    let (headers, list) = match projection {
+        Some(projection) => {
+            let transactions = fields::load(&state.session, &projection, &ids).await.map_err(|e| {
+                error!("Database query error: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            let headers = pagination::headers("/api/transactions", &params, &page, limit, &transactions);
+            (headers, fields::TransactionList::Sparse(transactions))
+        }
+        None => {
+            let transactions = load_transactions(&state.session, &ids).await?;
+            let headers = pagination::headers("/api/transactions", &params, &page, limit, &transactions);
+            (headers, fields::TransactionList::Full(transactions))
+        }
+    };
+    Ok((headers, Json(list)))
+}
 
-    let mut transactions = Vec::new();
-    
-    if let Some(rows) = rows.rows {
-        for row in rows {
-            if let Ok((id, from_endpoint, to_endpoint, amount, timestamp, signature, status)) = 
-                row.into_typed::<(Uuid, String, String, f64, i64, String, String)>() {
-                transactions.push(Transaction {
-                    id: id.to_string(),
-                    from_endpoint,
-                    to_endpoint,
-                    amount,
-                    timestamp,
-                    signature,
-                    status,
-                });
+/// The transactions with `ids`, in that order; ids not in `tx_log` are left out.
+async fn load_transactions(session: &repository::Repository, ids: &[Uuid]) -> Result<Vec<Transaction>, ApiError> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rows = session.execute(Statement::TransactionsByIds, (ids.to_vec(),)).await?;
+
+    let mut by_id = HashMap::new();
+    for row in rows.rows.unwrap_or_default() {
+        if let Ok(row) = row.into_typed::<TxRow>() {
+            match transaction_from_row(row) {
+                Ok(tx) => {
+                    by_id.insert(tx.id.clone(), tx);
+                }
+                Err(e) => warn!("Skipping transaction: {}", e),
             }
         }
     }
-
-    // Sort by timestamp descending (newest first)
-    transactions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-
-    Ok(Json(transactions))
+    Ok(ids.iter().filter_map(|id| by_id.remove(&id.to_string())).collect())
 }
 
 async fn get_transaction_by_id(
     State(state): State<AppState>,
     axum::extract::Path(id): axum::extract::Path<String>,
-) -> Result<Json<Transaction>, StatusCode> {
-    let tx_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
-
-    let rows = state
-        .session
-        .query(
-            "SELECT id, from_endpoint, to_endpoint, amount, timestamp, signature, status 
-             FROM transactions.tx_log WHERE id = ?",
-            (tx_id,),
-        )
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<Json<Transaction>, ApiError> {
+    let tx_id = Uuid::parse_str(&id)?;
 
-    if let Some(rows) = rows.rows {
-        if let Some(row) = rows.into_iter().next() {
-            if let Ok((id, from_endpoint, to_endpoint, amount, timestamp, signature, status)) = 
-                row.into_typed::<(Uuid, String, String, f64, i64, String, String)>() {
-                return Ok(Json(Transaction {
-                    id: id.to_string(),
-                    from_endpoint,
-                    to_endpoint,
-                    amount,
-                    timestamp,
-                    signature,
-                    status,
-                }));
-            }
-        }
-    }
-
-    Err(StatusCode::NOT_FOUND)
+    state
+        .store
+        .get(tx_id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Transaction {} not found", id)))
 }
 
+/// `POST /api/transactions`. With an `Idempotency-Key` header, a retry
+/// gets the first attempt's response instead of a second recording.
 async fn create_transaction(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(transaction): Json<Transaction>,
-) -> Result<StatusCode, StatusCode> {
-    let tx_id = Uuid::parse_str(&transaction.id)
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
+) -> Response {
+    let key = match idempotency::key_from(&headers) {
+        Ok(Some(key)) => key,
+        Ok(None) => return create_once(&state, transaction).await,
+        Err(status) => return status.into_response(),
+    };
 
-    state
-        .session
-        .query(
-            "INSERT INTO transactions.tx_log (id, from_endpoint, to_endpoint, amount, timestamp, signature, status)
-             VALUES (?, ?, ?, ?, ?, ?, ?)",
-            (
-                tx_id,
-                transaction.from_endpoint,
-                transaction.to_endpoint,
-                transaction.amount,
-                transaction.timestamp,
-                transaction.signature,
-                transaction.status,
-            ),
-        )
-        .await
-        .map_err(|e| {
-            error!("Failed to insert transaction: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    let sender = transaction.from_endpoint.clone();
+    let request_hash = idempotency::request_hash(&transaction);
+    let claimed = match idempotency::claim(&state.session, &sender, &key, &request_hash).await {
+        Ok(idempotency::Claim::New(claimed)) => claimed,
+        Ok(idempotency::Claim::Replay(response)) => return response,
+        Err(status) => return status.into_response(),
+    };
 
-    info!("✅ Transaction {} created", transaction.id);
-    Ok(StatusCode::CREATED)
+    let response = create_once(&state, transaction).await;
+    idempotency::complete(&state.session, claimed, response).await
 }
 
-async fn get_stats(
-    State(state): State<AppState>,
-) -> Result<Json<TransactionStats>, StatusCode> {
-    // Get total transaction count and volume
-    let total_rows = state
-        .session
-        .query(
-            "SELECT COUNT(*), SUM(amount) FROM transactions.tx_log",
-            &[],
-        )
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+async fn create_once(state: &AppState, transaction: Transaction) -> Response {
+    // Cool-downs carry a descriptive body, so they short-circuit here
+    if let Err(cooling_down) =
+        circuit_breaker::check(&state.session, &transaction.from_endpoint).await
+    {
+        return cooling_down;
+    }
 
-    let (total_transactions, total_volume) = if let Some(rows) = total_rows.rows {
-        if let Some(row) = rows.into_iter().next() {
-            if let Ok((count, sum)) = row.into_typed::<(i64, Option<f64>)>() {
-                (count, sum.unwrap_or(0.0))
-            } else {
-                (0, 0.0)
-            }
-        } else {
-            (0, 0.0)
+    ingest_transaction(state, transaction).await.into_response()
+}
+
+async fn ingest_transaction(
+    state: &AppState,
+    mut transaction: Transaction,
+) -> Result<(StatusCode, Json<IngestResponse>), ApiError> {
+    // Deposits, withdrawals and chargebacks are only created by the gateway's
+    // own flows, and every transfer starts its lifecycle here; later statuses
+    // are reported through PATCH /api/transactions/:id/status
+    validation::transfer(&transaction)?;
+
+    // Set when this transfer releases an imported payment instruction
+    let instruction = payment_files::matching_instruction(&state.session, &transaction).await?;
+
+    signatures::check_sender(&state.session, &transaction).await?;
+
+    endpoints::check_transaction_allowed(
+        &state.session,
+        &transaction.from_endpoint,
+        &transaction.to_endpoint,
+        &transaction.amount,
+    )
+    .await?;
+
+    counterparties::check_receiver_accepts(
+        &state.session,
+        &transaction.from_endpoint,
+        &transaction.to_endpoint,
+    )
+    .await?;
+
+    let screening = screening::screen(state.screening.as_ref(), &transaction).await;
+    audit::record(
+        &state.session,
+        &transaction.id,
+        screening.outcome.audit_action(),
+        state.screening.name(),
+        screening.reason.clone(),
+    )
+    .await?;
+
+    if screening.outcome == screening::ScreeningOutcome::Block {
+        warn!("Transaction {} blocked by screening", transaction.id);
+        return Err(StatusCode::FORBIDDEN.into());
+    }
+
+    let mut assessment = risk::assess(&state.session, &transaction).await;
+    let transform = match state.transforms.apply(&transaction, assessment.score, &assessment.reasons).await {
+        Ok(transform) => transform,
+        Err(e) => {
+            warn!("Ingest transform failed on {}: {}", transaction.id, e);
+            audit::record(&state.session, &transaction.id, "transform.failed", "transform", Some(e)).await?;
+            transforms::TransformOutcome::default()
         }
-    } else {
-        (0, 0.0)
     };
+    if let Some(score) = transform.risk_score {
+        assessment.score = score;
+        assessment.reasons.push("adjusted by transform");
+    }
+    if !transform.tags.is_empty() {
+        let details = format!("tags: {}", transform.tags.join(", "));
+        audit::record(&state.session, &transaction.id, "transform.tagged", "transform", Some(details)).await?;
+    }
 
-    let average_transaction = if total_transactions > 0 {
-        total_volume / total_transactions as f64
-    } else {
-        0.0
-    };
+    let flagged = screening.outcome == screening::ScreeningOutcome::Flag;
+    let held = flagged || transform.review || assessment.score >= risk::hold_threshold();
+    transaction.risk_score = Some(assessment.score);
+    if held {
+        transaction.status = TransactionStatus::Held;
+    }
 
-    // Get endpoint statistics
-    let endpoint_rows = state
-        .session
-        .query(
-            "SELECT from_endpoint, to_endpoint, amount FROM transactions.tx_log",
-            &[],
-        )
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Last check before recording: a reused sequence means the sender
+    // already promised these funds elsewhere. The claim names this
+    // transaction, so retrying it after a failure below claims it again
+    let sequence = transaction.sequence.map(|s| s as i64);
+    sequence::claim(&state.session, &transaction.from_endpoint, sequence, &transaction.id).await?;
 
-    let mut endpoint_map: HashMap<String, EndpointStats> = HashMap::new();
+    insert_transaction(&state.session, &transaction).await?;
 
-    if let Some(rows) = endpoint_rows.rows {
-        for row in rows {
-            if let Ok((from_endpoint, to_endpoint, amount)) = 
-                row.into_typed::<(String, String, f64)>() {
-                
-                // Update sender stats
-                let sender_stats = endpoint_map.entry(from_endpoint.clone()).or_insert(EndpointStats {
-                    endpoint_id: from_endpoint.clone(),
-                    transaction_count: 0,
-                    total_sent: 0.0,
-                    total_received: 0.0,
-                    balance_change: 0.0,
-                });
-                sender_stats.transaction_count += 1;
-                sender_stats.total_sent += amount;
-                sender_stats.balance_change -= amount;
-
-                // Update receiver stats
-                let receiver_stats = endpoint_map.entry(to_endpoint.clone()).or_insert(EndpointStats {
-                    endpoint_id: to_endpoint.clone(),
-                    transaction_count: 0,
-                    total_sent: 0.0,
-                    total_received: 0.0,
-                    balance_change: 0.0,
-                });
-                receiver_stats.total_received += amount;
-                receiver_stats.balance_change += amount;
-            }
-        }
+    if let Some(instruction) = &instruction {
+        payment_files::release(&state.session, &transaction, instruction).await?;
     }
 
-    let endpoints: Vec<EndpointStats> = endpoint_map.into_values().collect();
+    if held {
+        let (actor, details) = if flagged {
+            ("screening", screening.reason.clone())
+        } else if transform.review {
+            ("transform", transform.review_reason.clone())
+        } else {
+            ("risk-engine", Some(assessment.reasons.join(", ")))
+        };
+        audit::record(&state.session, &transaction.id, "review.held", actor, details).await?;
+    }
+
+    info!("✅ Transaction {} created (risk {})", transaction.id, assessment.score);
+    Ok((
+        StatusCode::CREATED,
+        Json(IngestResponse {
+            id: transaction.id,
+            risk_score: assessment.score,
+            risk_reasons: assessment.reasons,
+            held,
+            tags: transform.tags,
+        }),
+    ))
+}
 
-    Ok(Json(TransactionStats {
+async fn get_stats(
+    State(state): State<AppState>,
+) -> Result<Json<TransactionStats>, StatusCode> {
+    let stats = compute_stats(&state.session).await?;
+    Ok(Json(state.stats_privacy.totals(stats)))
+}
+
+async fn compute_stats(session: &repository::Repository) -> Result<TransactionStats, StatusCode> {
+    let (total_transactions, total_volume, endpoints) = projections::totals(session).await.map_err(|e| {
+        error!("Failed to read stats totals: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let average_transaction = if total_transactions > 0 {
+        total_volume.checked_div(total_transactions).map_err(stats_error)?
+    } else {
+        Money::zero(NATIVE_ASSET)
+    };
+
+    // System accounts aren't real endpoints
+    let endpoints: Vec<EndpointStats> =
+        endpoints.into_iter().filter(|stats| !is_system_account(&stats.endpoint_id)).collect();
+
+    Ok(TransactionStats {
         total_transactions,
         total_volume,
         average_transaction,
         endpoints,
-    }))
+    })
 }
 
+fn stats_error(e: MoneyError) -> StatusCode {
+    error!("Stats aggregation failed: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+/// `GET /api/endpoints/:id/stats[?as_of=<RFC 3339>]`
 async fn get_endpoint_stats(
     State(state): State<AppState>,
+    access: Option<Extension<consents::ThirdPartyAccess>>,
     axum::extract::Path(endpoint_id): axum::extract::Path<String>,
-) -> Result<Json<EndpointStats>, StatusCode> {
-    let rows = state
-        .session
-        .query(
-            "SELECT from_endpoint, to_endpoint, amount FROM transactions.tx_log 
-             WHERE from_endpoint = ? OR to_endpoint = ? ALLOW FILTERING",
-            (&endpoint_id, &endpoint_id),
-        )
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut stats = EndpointStats {
-        endpoint_id: endpoint_id.clone(),
-        transaction_count: 0,
-        total_sent: 0.0,
-        total_received: 0.0,
-        balance_change: 0.0,
-    };
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<EndpointStats>, ApiError> {
+    consents::confine(access.as_deref(), Some(&endpoint_id), consents::Scope::Balance)?;
+    let as_of = parse_as_of(&params)?;
+    Ok(Json(published_endpoint_stats(&state, &endpoint_id, as_of).await?))
+}
 
-    if let Some(rows) = rows.rows {
-        for row in rows {
-            if let Ok((from_endpoint, to_endpoint, amount)) = 
-                row.into_typed::<(String, String, f64)>() {
-                
-                stats.transaction_count += 1;
-                
-                if from_endpoint == endpoint_id {
-                    stats.total_sent += amount;
-                    stats.balance_change -= amount;
-                }
-                
-                if to_endpoint == endpoint_id {
-                    stats.total_received += amount;
-                    stats.balance_change += amount;
-                }
-            }
-        }
-    }
+/// `endpoint_id`'s stats as the stats API publishes them (see `privacy`).
+async fn published_endpoint_stats(
+    state: &AppState,
+    endpoint_id: &str,
+    as_of: Option<DateTime<Utc>>,
+) -> Result<EndpointStats, ApiError> {
+    let stats = endpoint_stats_as_of(&state.session, endpoint_id, as_of).await?;
+    state
+        .stats_privacy
+        .endpoint(stats)
+        .ok_or_else(|| ApiError::NotFound(format!("Stats for {} are withheld", endpoint_id)))
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct EndpointBalance {
+    pub endpoint_id: String,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub balance: Money,
+    pub as_of: DateTime<Utc>,
+}
+
+/// `GET /api/endpoints/:id/balance[?as_of=<RFC 3339>]`: initial balance
+/// plus every ledger movement up to `as_of` (default: now). A registered
+/// endpoint's initial balance only counts from its registration onwards.
+async fn get_endpoint_balance(
+    State(state): State<AppState>,
+    access: Option<Extension<consents::ThirdPartyAccess>>,
+    axum::extract::Path(endpoint_id): axum::extract::Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<EndpointBalance>, ApiError> {
+    consents::confine(access.as_deref(), Some(&endpoint_id), consents::Scope::Balance)?;
+    let as_of = parse_as_of(&params)?.unwrap_or_else(Utc::now);
+    Ok(Json(endpoint_balance(&state.session, endpoint_id, as_of).await?))
+}
+
+async fn endpoint_balance(session: &repository::Repository, endpoint_id: String, as_of: DateTime<Utc>) -> Result<EndpointBalance, StatusCode> {
+    let initial_balance = endpoints::load_endpoint(session, &endpoint_id)
+        .await?
+        .filter(|endpoint| endpoint.created_at <= as_of)
+        .map_or_else(|| Money::zero(NATIVE_ASSET), |endpoint| endpoint.initial_balance);
+    let stats = endpoint_stats_as_of(session, &endpoint_id, Some(as_of)).await?;
+
+    Ok(EndpointBalance {
+        endpoint_id,
+        balance: stats.balance_from(&initial_balance).map_err(stats_error)?,
+        as_of,
+    })
+}
+
+/// Reads the optional `as_of` query parameter (RFC 3339).
+fn parse_as_of(params: &HashMap<String, String>) -> Result<Option<DateTime<Utc>>, ApiError> {
+    params
+        .get("as_of")
+        .map(|as_of| {
+            DateTime::parse_from_rfc3339(as_of)
+                .map(|at| at.with_timezone(&Utc))
+                .map_err(|e| ApiError::Parse(format!("as_of is not an RFC 3339 timestamp: {}", e)))
+        })
+        .transpose()
+}
+
+async fn compute_endpoint_stats(
+    session: &repository::Repository,
+    endpoint_id: &str,
+) -> Result<EndpointStats, StatusCode> {
+    endpoint_stats_as_of(session, endpoint_id, None).await
+}
 
-    Ok(Json(stats))
+async fn endpoint_stats_as_of(
+    session: &repository::Repository,
+    endpoint_id: &str,
+    as_of: Option<DateTime<Utc>>,
+) -> Result<EndpointStats, StatusCode> {
+    projections::endpoint_stats(session, endpoint_id, as_of)
+        .await
+        .map_err(|e| {
+            error!("Failed to read stats for {}: {}", endpoint_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
 }
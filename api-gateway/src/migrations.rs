@@ -0,0 +1,340 @@
+//! Versioned schema changes. Every table is created and changed by a CQL
+//! file in `api-gateway/migrations/`, listed in `MIGRATIONS` in version
+//! order, starting from the baseline (0) the modules used to create at
+//! startup. Only the keyspace, `schema_migrations` itself and the lease
+//! table the runner takes are set up in code. Each runs once, and is recorded in
+//! `transactions.schema_migrations` with the SHA-256 of its file. An
+//! applied migration whose file has since changed stops startup: fix
+//! forward with a new migration instead of editing an old one.
+//!
+//! Gateways apply pending migrations at startup, one instance at a time
+//! under the `migrations` lease. To roll a change out ahead of a deploy,
+//! run `api-gateway --migrate-only` (`--dry-run` to list what it would
+//! apply without touching the schema) and start the gateways with
+//! `MIGRATE_ON_START=false`, so one that finds migrations pending refuses
+//! to start instead of applying them.
+
+use chrono::{Duration, Utc};
+use scylla::Session;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::{info, warn};
+
+use crate::leader::Leadership;
+use crate::{db, lwt_applied};
+
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub cql: &'static str,
+}
+
+/// In version order; append only.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 0,
+        name: "baseline",
+        cql: include_str!("../migrations/0000_baseline.cql"),
+    },
+    Migration {
+        version: 1,
+        name: "drop_tx_timestamp_idx",
+        cql: include_str!("../migrations/0001_drop_tx_timestamp_idx.cql"),
+    },
+    Migration {
+        version: 2,
+        name: "idempotency_claimed_at",
+        cql: include_str!("../migrations/0002_idempotency_claimed_at.cql"),
+    },
+    Migration {
+        version: 3,
+        name: "transaction_event_claims",
+        cql: include_str!("../migrations/0003_transaction_event_claims.cql"),
+    },
+    Migration {
+        version: 4,
+        name: "ledger_totals",
+        cql: include_str!("../migrations/0004_ledger_totals.cql"),
+    },
+    Migration {
+        version: 5,
+        name: "snapshot_state_version",
+        cql: include_str!("../migrations/0005_snapshot_state_version.cql"),
+    },
+    Migration {
+        version: 6,
+        name: "money_minor_columns",
+        cql: include_str!("../migrations/0006_money_minor_columns.cql"),
+    },
+    Migration {
+        version: 7,
+        name: "top_up_requests",
+        cql: include_str!("../migrations/0007_top_up_requests.cql"),
+    },
+    Migration {
+        version: 8,
+        name: "asset_hold_claims",
+        cql: include_str!("../migrations/0008_asset_hold_claims.cql"),
+    },
+];
+
+const LEASE_NAME: &str = "migrations";
+/// How long the lease outlives an instance that died mid-migration.
+const LEASE_SECS: i64 = 600;
+const LEASE_POLL: std::time::Duration = std::time::Duration::from_secs(2);
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        hex::encode(Sha256::digest(self.cql.as_bytes()))
+    }
+
+    /// The file's statements, without `--` comment lines.
+    pub fn statements(&self) -> Vec<String> {
+        let cql: String = self
+            .cql
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("--"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        cql.split(';').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect()
+    }
+}
+
+pub async fn init_schema(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    session
+        .query(
+            "CREATE TABLE IF NOT EXISTS transactions.schema_migrations (
+                 version INT PRIMARY KEY,
+                 name TEXT,
+                 checksum TEXT,
+                 applied_at BIGINT,
+                 applied_by TEXT,
+                 duration_ms BIGINT
+             )",
+            &[],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Applied versions, with the checksum each was applied with.
+async fn applied(session: &Session) -> Result<HashMap<i32, String>, Box<dyn std::error::Error>> {
+    let rows = session
+        .query(db::idempotent("SELECT version, checksum FROM transactions.schema_migrations"), &[])
+        .await?;
+    Ok(rows
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(i32, String)>().ok())
+        .collect())
+}
+
+/// The migrations not applied yet, after checking the applied ones still
+/// match their files.
+fn pending(applied: &HashMap<i32, String>) -> Result<Vec<&'static Migration>, String> {
+    for (version, checksum) in applied {
+        match MIGRATIONS.iter().find(|m| m.version == *version) {
+            Some(migration) if migration.checksum() != *checksum => {
+                return Err(format!(
+                    "Migration {} ({}) was changed after it was applied: checksum {}, applied as {}",
+                    version,
+                    migration.name,
+                    migration.checksum(),
+                    checksum
+                ));
+            }
+            Some(_) => {}
+            // Applied by a newer build, e.g. during a rolling deploy
+            None => warn!("Schema is at migration {}, which this build doesn't know", version),
+        }
+    }
+    Ok(MIGRATIONS.iter().filter(|m| !applied.contains_key(&m.version)).collect())
+}
+
+async fn take_lease(session: &Session, holder: &str) -> Result<(), Box<dyn std::error::Error>> {
+    loop {
+        let expires_at = (Utc::now() + Duration::seconds(LEASE_SECS)).timestamp_millis();
+        let taken = session
+            .query(
+                "INSERT INTO transactions.leader_leases (name, holder, expires_at)
+                 VALUES (?, ?, ?) IF NOT EXISTS USING TTL ?",
+                (LEASE_NAME, holder, expires_at, LEASE_SECS as i32),
+            )
+            .await?;
+        if lwt_applied(taken) {
+            return Ok(());
+        }
+        info!("Waiting for another instance to finish migrating");
+        tokio::time::sleep(LEASE_POLL).await;
+    }
+}
+
+async fn release_lease(session: &Session, holder: &str) {
+    if let Err(e) = session
+        .query(
+            "DELETE FROM transactions.leader_leases WHERE name = ? IF holder = ?",
+            (LEASE_NAME, holder),
+        )
+        .await
+    {
+        warn!("Failed to release the migrations lease: {}", e);
+    }
+}
+
+/// Applies the pending migrations, or with `MIGRATE_ON_START=false` (and
+/// `apply` unset) fails if there are any.
+pub async fn run(session: &Session, apply: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let apply = apply || std::env::var("MIGRATE_ON_START").as_deref() != Ok("false");
+    if !apply {
+        let pending = pending(&applied(session).await?)?;
+        if let Some(first) = pending.first() {
+            return Err(format!(
+                "{} migrations pending, from {} ({}); run `api-gateway --migrate-only` first",
+                pending.len(),
+                first.version,
+                first.name
+            )
+            .into());
+        }
+        return Ok(());
+    }
+
+    let holder = Leadership::from_env().instance_id;
+    take_lease(session, &holder).await?;
+    let result = apply_pending(session, &holder).await;
+    release_lease(session, &holder).await;
+    result
+}
+
+/// The keyspace, table and column an `ALTER TABLE ... ADD` adds.
+fn added_column(statement: &str) -> Option<(&str, &str, &str)> {
+    let words: Vec<&str> = statement.split_whitespace().collect();
+    let [alter, table, name, add, column, ..] = words.as_slice() else {
+        return None;
+    };
+    let keywords = [(alter, "alter"), (table, "table"), (add, "add")];
+    if !keywords.iter().all(|(word, keyword)| word.eq_ignore_ascii_case(keyword)) {
+        return None;
+    }
+    let (keyspace, table) = name.split_once('.')?;
+    Some((keyspace, table, column))
+}
+
+async fn column_exists(
+    session: &Session,
+    keyspace: &str,
+    table: &str,
+    column: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let rows = session
+        .query(
+            db::idempotent(
+                "SELECT column_name FROM system_schema.columns
+                 WHERE keyspace_name = ? AND table_name = ? AND column_name = ?",
+            ),
+            (keyspace, table, column),
+        )
+        .await?;
+    Ok(rows.rows.is_some_and(|rows| !rows.is_empty()))
+}
+
+async fn apply_pending(session: &Session, holder: &str) -> Result<(), Box<dyn std::error::Error>> {
+    // Read under the lease, so migrations another instance just applied
+    // aren't run again
+    let pending = pending(&applied(session).await?)?;
+    for migration in pending {
+        info!("Applying migration {} ({})", migration.version, migration.name);
+        let started = Utc::now();
+        for statement in migration.statements() {
+            // So the baseline can run on a database an older build set up,
+            // and a migration that failed half way can run again
+            if let Some((keyspace, table, column)) = added_column(&statement) {
+                if column_exists(session, keyspace, table, column).await? {
+                    info!("Migration {}: {}.{}.{} already exists", migration.version, keyspace, table, column);
+                    continue;
+                }
+            }
+            session.query(statement.as_str(), &[]).await.map_err(|e| {
+                format!("Migration {} ({}) failed: {}", migration.version, migration.name, e)
+            })?;
+        }
+        let duration_ms = (Utc::now() - started).num_milliseconds();
+        session
+            .query(
+                "INSERT INTO transactions.schema_migrations
+                     (version, name, checksum, applied_at, applied_by, duration_ms)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+                (
+                    migration.version,
+                    migration.name,
+                    migration.checksum(),
+                    Utc::now().timestamp_millis(),
+                    holder,
+                    duration_ms,
+                ),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// `api-gateway --migrate-only --dry-run`: prints the migrations that
+/// would be applied, and their statements, without changing anything.
+pub async fn dry_run(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    let exists = session
+        .query(
+            db::idempotent(
+                "SELECT table_name FROM system_schema.tables
+                 WHERE keyspace_name = 'transactions' AND table_name = 'schema_migrations'",
+            ),
+            &[],
+        )
+        .await?
+        .rows
+        .is_some_and(|rows| !rows.is_empty());
+    let applied = if exists { applied(session).await? } else { HashMap::new() };
+    let pending = pending(&applied)?;
+
+    println!("{} applied, {} pending", applied.len(), pending.len());
+    for migration in pending {
+        println!("\n-- {} {} (sha256 {})", migration.version, migration.name, migration.checksum());
+        for statement in migration.statements() {
+            println!("{};", statement);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_unique_and_in_order() {
+        assert!(MIGRATIONS.windows(2).all(|pair| pair[0].version < pair[1].version));
+        assert_eq!(MIGRATIONS[0].version, 0);
+    }
+
+    #[test]
+    fn statements_skip_comments() {
+        let baseline = &MIGRATIONS[0];
+        let statements = baseline.statements();
+        assert!(statements.iter().all(|statement| !statement.contains("--")));
+        assert!(statements[0].starts_with("CREATE TABLE IF NOT EXISTS transactions.tx_log"));
+    }
+
+    #[test]
+    fn finds_added_columns() {
+        assert_eq!(
+            added_column("ALTER TABLE transactions.tx_log ADD kind TEXT"),
+            Some(("transactions", "tx_log", "kind"))
+        );
+        assert_eq!(
+            added_column("alter table transactions.endpoint_ledger\n    add total_count BIGINT STATIC"),
+            Some(("transactions", "endpoint_ledger", "total_count"))
+        );
+        assert_eq!(added_column("DROP INDEX IF EXISTS transactions.tx_timestamp_idx"), None);
+        assert_eq!(added_column("CREATE TABLE IF NOT EXISTS transactions.swaps (swap_id UUID PRIMARY KEY)"), None);
+    }
+}
@@ -0,0 +1,193 @@
+//! Mutual TLS for internal callers. Relays (the signaling servers) reach
+//! the gateway on `MTLS_PORT` (3443) and present a certificate from the
+//! internal CA whose SPIFFE ID (`spiffe://<trust domain>/<service>`, a URI
+//! SAN) is in `MTLS_ALLOWED_IDS`. The public listener keeps serving
+//! browsers; routes only relays may call refuse it once mTLS is set up.
+//!
+//! - MTLS_CERT, MTLS_KEY: the gateway's certificate chain and PKCS#8 key (PEM)
+//! - MTLS_CA: the internal CA bundle client certificates must chain to
+//! - MTLS_ALLOWED_IDS: comma-separated SPIFFE IDs allowed to connect
+//!
+//! The certificate, key and CA are reloaded as they rotate, like the public
+//! listener's (see `tls`).
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Extension, Router,
+};
+use rustls::{
+    client::danger::HandshakeSignatureValid,
+    pki_types::{CertificateDer, UnixTime},
+    server::{
+        danger::{ClientCertVerified, ClientCertVerifier},
+        WebPkiClientVerifier,
+    },
+    DigitallySignedStruct, DistinguishedName, RootCertStore, ServerConfig, SignatureScheme,
+};
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+use x509_parser::extensions::GeneralName;
+
+use crate::tls::{self, Reloading};
+
+/// The verified SPIFFE ID of the caller, on requests that came in over mTLS.
+#[derive(Clone, Debug)]
+pub struct PeerIdentity(pub String);
+
+pub struct InternalTls {
+    pub port: u16,
+    pub config: Reloading<ServerConfig>,
+}
+
+impl InternalTls {
+    /// `None` unless `MTLS_CERT` is set; an incomplete setup is an error
+    /// rather than an internal listener that accepts anyone.
+    pub fn from_env() -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let Ok(cert_path) = std::env::var("MTLS_CERT") else {
+            return Ok(None);
+        };
+        let key_path = std::env::var("MTLS_KEY").map_err(|_| "MTLS_CERT is set but MTLS_KEY isn't")?;
+        let ca_path = std::env::var("MTLS_CA").map_err(|_| "MTLS_CERT is set but MTLS_CA isn't")?;
+        let allowed: HashSet<String> = std::env::var("MTLS_ALLOWED_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .collect();
+        if allowed.is_empty() {
+            return Err("MTLS_ALLOWED_IDS must list the SPIFFE IDs allowed to connect".into());
+        }
+        if let Some(id) = allowed.iter().find(|id| !id.starts_with("spiffe://")) {
+            return Err(format!("Not a SPIFFE ID: {}", id).into());
+        }
+
+        let (cert_path, key_path, ca_path) = (PathBuf::from(cert_path), PathBuf::from(key_path), PathBuf::from(ca_path));
+        let files = vec![cert_path.clone(), key_path.clone(), ca_path.clone()];
+        let config = Reloading::new("mTLS certificate", files, move || {
+            server_config(&cert_path, &key_path, &ca_path, &allowed).map_err(|e| e.to_string())
+        })?;
+
+        let port = std::env::var("MTLS_PORT").ok().and_then(|v| v.parse().ok()).unwrap_or(3443);
+        Ok(Some(InternalTls { port, config }))
+    }
+}
+
+fn server_config(
+    cert_path: &Path,
+    key_path: &Path,
+    ca_path: &Path,
+    allowed: &HashSet<String>,
+) -> Result<ServerConfig, Box<dyn std::error::Error>> {
+    let mut roots = RootCertStore::empty();
+    for ca in tls::certs(ca_path)? {
+        roots.add(ca)?;
+    }
+
+    let provider = tls::provider();
+    let verifier = Arc::new(SpiffeClientVerifier {
+        inner: WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?,
+        allowed: allowed.clone(),
+    });
+    Ok(ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(tls::certs(cert_path)?, tls::private_key(key_path)?)?)
+}
+
+/// The URI SAN that names the certificate's holder, if it has one.
+fn spiffe_id(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let san = cert.subject_alternative_name().ok()??;
+    san.value.general_names.iter().find_map(|name| match name {
+        GeneralName::URI(uri) if uri.starts_with("spiffe://") => Some(uri.to_string()),
+        _ => None,
+    })
+}
+
+/// Chain validation against the internal CA, then the SPIFFE ID against the
+/// allow list, so an unlisted service fails the handshake.
+#[derive(Debug)]
+struct SpiffeClientVerifier {
+    inner: Arc<dyn ClientCertVerifier>,
+    allowed: HashSet<String>,
+}
+
+impl ClientCertVerifier for SpiffeClientVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.inner.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.inner.verify_client_cert(end_entity, intermediates, now)?;
+        match spiffe_id(end_entity) {
+            Some(id) if self.allowed.contains(&id) => Ok(ClientCertVerified::assertion()),
+            Some(id) => Err(rustls::Error::General(format!("SPIFFE ID {} is not allowed", id))),
+            None => Err(rustls::Error::General("client certificate has no SPIFFE ID".to_string())),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Serves `app` on the mTLS port. Each connection's requests carry the
+/// caller's `PeerIdentity`.
+pub async fn serve(app: Router, internal: InternalTls) {
+    let listener = match TcpListener::bind(("0.0.0.0", internal.port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            warn!("mTLS listener failed to bind port {}: {}", internal.port, e);
+            return;
+        }
+    };
+    info!("🔒 Internal mTLS listener on port {}", internal.port);
+
+    tls::serve(listener, app, internal.config, |connection, app| {
+        // The verifier has already checked it against the allow list
+        let identity = connection.peer_certificates().and_then(|certs| certs.first()).and_then(spiffe_id)?;
+        debug!("mTLS connection as {}", identity);
+        Some(app.clone().layer(Extension(PeerIdentity(identity))))
+    })
+    .await
+}
+
+/// Route layer for calls only relays make: once mTLS is configured they're
+/// refused (403) unless they came in over it.
+pub async fn require_peer(State(enforced): State<bool>, request: Request, next: Next) -> Response {
+    if enforced && request.extensions().get::<PeerIdentity>().is_none() {
+        return (StatusCode::FORBIDDEN, "mTLS required").into_response();
+    }
+    next.run(request).await
+}
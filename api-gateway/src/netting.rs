@@ -0,0 +1,102 @@
+use axum::{
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use serde::Deserialize;
+use std::collections::HashSet;
+use tracing::{error, info};
+use tx_core::{Money, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::{
+    circuit_breaker, ingest_transaction, insert_transaction, AppState, Transaction, TransactionKind,
+    TransactionStatus,
+};
+
+/// Upper bound on the micro-payments folded into one net transfer.
+const MAX_COMPONENTS: usize = 1000;
+
+/// A net transfer plus the micro-payments it settles.
+///
+/// Endpoints in netting mode accumulate small payments to the same peer
+/// locally and settle them as one transfer. The net goes through the usual
+/// ingest checks; the components are stored as `netted` children of it so
+/// the individual payments stay auditable without being counted twice.
+#[derive(Clone, Debug, Deserialize)]
+pub struct NetTransactionRequest {
+    pub transaction: Transaction,
+    pub components: Vec<Transaction>,
+}
+
+fn validate(request: &NetTransactionRequest) -> Result<(), StatusCode> {
+    let net = &request.transaction;
+    if request.components.is_empty() || request.components.len() > MAX_COMPONENTS {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let mut ids = HashSet::new();
+    let mut total = Money::zero(NATIVE_ASSET);
+    for component in &request.components {
+        Uuid::parse_str(&component.id).map_err(|_| StatusCode::BAD_REQUEST)?;
+        if component.id == net.id
+            || !ids.insert(component.id.as_str())
+            || component.from_endpoint != net.from_endpoint
+            || component.to_endpoint != net.to_endpoint
+            || component.amount.is_negative()
+            || component.amount.is_zero()
+            // Signed by the same key as the net, which ingest checks against the sender
+            || component.public_key != net.public_key
+            || (component.public_key.is_some() && !tx_core::verify_transaction(component))
+        {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+        total = total.checked_add(&component.amount).map_err(|_| StatusCode::BAD_REQUEST)?;
+    }
+
+    // Exact: components are whole minor units, like the net
+    if total != net.amount {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    Ok(())
+}
+
+/// `POST /api/transactions/net`
+pub async fn create_net_transaction(
+    State(state): State<AppState>,
+    Json(request): Json<NetTransactionRequest>,
+) -> Response {
+    if let Err(status) = validate(&request) {
+        return status.into_response();
+    }
+    if let Err(cooling_down) =
+        circuit_breaker::check(&state.session, &request.transaction.from_endpoint).await
+    {
+        return cooling_down;
+    }
+
+    let net_id = request.transaction.id.clone();
+    let net_status = request.transaction.status;
+    let (status, response) = match ingest_transaction(&state, request.transaction).await {
+        Ok(created) => created,
+        Err(status) => return status.into_response(),
+    };
+
+    // Components follow the net's outcome; later status changes are
+    // tracked on the net itself
+    let component_status = if response.held { TransactionStatus::Held } else { net_status };
+    for mut component in request.components {
+        component.kind = TransactionKind::Netted;
+        component.parent_tx_id = Some(net_id.clone());
+        component.status = component_status;
+        component.risk_score = None;
+        component.sequence = None;
+        if let Err(status) = insert_transaction(&state.session, &component).await {
+            error!("Failed to record component {} of net {}", component.id, net_id);
+            return status.into_response();
+        }
+    }
+
+    info!("🧮 Net transaction {} settled its components", net_id);
+    (status, response).into_response()
+}
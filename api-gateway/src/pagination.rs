@@ -0,0 +1,244 @@
+//! Cursor pagination for `GET /api/transactions`. Pages are newest first,
+//! ordered by `(timestamp, id)` so transactions sharing a millisecond keep
+//! a stable order. A cursor is an opaque token naming one transaction's
+//! position; `before=<cursor>` pages toward older history, `after=<cursor>`
+//! toward newer. The body stays a plain array; the `Link` header (RFC 8288)
+//! carries the `next` (older) and `prev` (newer) pages, and `Next-Cursor`
+//! the token for the next one.
+
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::Transaction;
+
+pub const NEXT_CURSOR_HEADER: HeaderName = HeaderName::from_static("next-cursor");
+
+pub const DEFAULT_LIMIT: i32 = 100;
+pub const MAX_LIMIT: i32 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cursor {
+    pub timestamp: i64,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    pub fn of(transaction: &Transaction) -> Option<Self> {
+        Some(Cursor {
+            timestamp: transaction.timestamp.timestamp_millis(),
+            id: Uuid::parse_str(&transaction.id).ok()?,
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.timestamp, self.id))
+    }
+
+    fn decode(token: &str) -> Option<Self> {
+        let decoded = String::from_utf8(URL_SAFE_NO_PAD.decode(token).ok()?).ok()?;
+        let (timestamp, id) = decoded.split_once(':')?;
+        Some(Cursor {
+            timestamp: timestamp.parse().ok()?,
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+/// Anything listed in timeline order.
+pub trait Positioned {
+    fn position(&self) -> Option<Cursor>;
+}
+
+impl Positioned for Transaction {
+    fn position(&self) -> Option<Cursor> {
+        Cursor::of(self)
+    }
+}
+
+pub enum Page {
+    Latest,
+    /// Older than the cursor.
+    Before(Cursor),
+    /// Newer than the cursor.
+    After(Cursor),
+}
+
+impl Page {
+    /// 400 for an unreadable cursor or both `before` and `after`.
+    pub fn from_params(params: &HashMap<String, String>) -> Result<Self, StatusCode> {
+        Page::from_cursors(params.get("before").map(String::as_str), params.get("after").map(String::as_str))
+    }
+
+    pub fn from_cursors(before: Option<&str>, after: Option<&str>) -> Result<Self, StatusCode> {
+        let cursor = |token: &str| Cursor::decode(token).ok_or(StatusCode::BAD_REQUEST);
+        match (before, after) {
+            (None, None) => Ok(Page::Latest),
+            (Some(before), None) => Ok(Page::Before(cursor(before)?)),
+            (None, Some(after)) => Ok(Page::After(cursor(after)?)),
+            (Some(_), Some(_)) => Err(StatusCode::BAD_REQUEST),
+        }
+    }
+
+    /// Whether a transaction at `position` belongs on this page.
+    pub fn admits(&self, position: Cursor) -> bool {
+        match self {
+            Page::Latest => true,
+            Page::Before(cursor) => position < *cursor,
+            Page::After(cursor) => position > *cursor,
+        }
+    }
+}
+
+pub fn limit_from(params: &HashMap<String, String>) -> i32 {
+    clamp_limit(params.get("limit").and_then(|l| l.parse::<i32>().ok()))
+}
+
+pub fn clamp_limit(limit: Option<i32>) -> i32 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// Pages an in-memory list, for views not served from the timeline.
+pub fn paginate(transactions: Vec<Transaction>, page: &Page, limit: i32) -> Vec<Transaction> {
+    let mut positioned: Vec<(Cursor, Transaction)> = transactions
+        .into_iter()
+        .filter_map(|transaction| Some((Cursor::of(&transaction)?, transaction)))
+        .filter(|(position, _)| page.admits(*position))
+        .collect();
+    positioned.sort_by_key(|(position, _)| std::cmp::Reverse(*position));
+
+    let limit = limit as usize;
+    let range = match page {
+        // The page adjoining the cursor is the oldest of the newer ones
+        Page::After(_) => positioned.len().saturating_sub(limit)..positioned.len(),
+        _ => 0..positioned.len().min(limit),
+    };
+    positioned.drain(range).map(|(_, transaction)| transaction).collect()
+}
+
+/// Where the page after `page_items` (toward older history) starts, if
+/// there may be one.
+pub fn next_cursor<T: Positioned>(page: &Page, limit: i32, page_items: &[T]) -> Option<Cursor> {
+    let full = page_items.len() >= limit as usize;
+    page_items
+        .last()
+        .filter(|_| full || matches!(page, Page::After(_)))
+        .and_then(Positioned::position)
+}
+
+/// `Link` and `Next-Cursor` for a page of `page_items` (newest first) served
+/// at `path` with `params`.
+pub fn headers<T: Positioned>(path: &str, params: &HashMap<String, String>, page: &Page, limit: i32, page_items: &[T]) -> HeaderMap {
+    let full = page_items.len() >= limit as usize;
+    let next = next_cursor(page, limit, page_items);
+    let prev = page_items
+        .first()
+        .filter(|_| matches!(page, Page::Before(_)) || (full && matches!(page, Page::After(_))))
+        .and_then(Positioned::position);
+
+    let link = |direction: &str, cursor: Cursor, rel: &str| {
+        let mut query: Vec<(&str, String)> = params
+            .iter()
+            .filter(|(name, _)| !matches!(name.as_str(), "before" | "after"))
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        query.sort();
+        query.push((direction, cursor.encode()));
+        let mut url = reqwest::Url::parse("http://gateway").expect("static URL");
+        url.set_path(path);
+        url.query_pairs_mut().extend_pairs(query);
+        format!("<{}?{}>; rel=\"{}\"", url.path(), url.query().unwrap_or_default(), rel)
+    };
+
+    let mut links = Vec::new();
+    let mut headers = HeaderMap::new();
+    if let Some(cursor) = next {
+        links.push(link("before", cursor, "next"));
+        if let Ok(value) = HeaderValue::from_str(&cursor.encode()) {
+            headers.insert(NEXT_CURSOR_HEADER, value);
+        }
+    }
+    if let Some(cursor) = prev {
+        links.push(link("after", cursor, "prev"));
+    }
+    if !links.is_empty() {
+        if let Ok(value) = HeaderValue::from_str(&links.join(", ")) {
+            headers.insert(header::LINK, value);
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TransactionKind, TransactionStatus};
+    use chrono::{TimeZone, Utc};
+    use tx_core::{Money, NATIVE_ASSET};
+
+    fn transaction(millis: i64) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4().to_string(),
+            from_endpoint: "alice".into(),
+            to_endpoint: "bob".into(),
+            amount: Money::new(1, NATIVE_ASSET),
+            timestamp: Utc.timestamp_millis_opt(millis).unwrap(),
+            signature: String::new(),
+            status: TransactionStatus::Confirmed,
+            kind: TransactionKind::Transfer,
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: None,
+            public_key: None,
+        }
+    }
+
+    fn millis(page: &[Transaction]) -> Vec<i64> {
+        page.iter().map(|tx| tx.timestamp.timestamp_millis()).collect()
+    }
+
+    #[test]
+    fn cursors_round_trip() {
+        let cursor = Cursor::of(&transaction(1_700_000_000_123)).unwrap();
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert_eq!(Cursor::decode("not a cursor"), None);
+    }
+
+    #[test]
+    fn before_and_after_are_exclusive_of_each_other() {
+        let token = Cursor::of(&transaction(1)).unwrap().encode();
+        assert!(matches!(Page::from_cursors(None, None), Ok(Page::Latest)));
+        assert!(matches!(Page::from_cursors(Some(&token), None), Ok(Page::Before(_))));
+        assert!(matches!(Page::from_cursors(None, Some(&token)), Ok(Page::After(_))));
+        assert_eq!(Page::from_cursors(Some(&token), Some(&token)).err(), Some(StatusCode::BAD_REQUEST));
+        assert_eq!(Page::from_cursors(Some("?"), None).err(), Some(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn pages_walk_the_timeline_newest_first() {
+        let all: Vec<Transaction> = (1..=5).map(transaction).collect();
+
+        let latest = paginate(all.clone(), &Page::Latest, 2);
+        assert_eq!(millis(&latest), [5, 4]);
+        let next = next_cursor(&Page::Latest, 2, &latest).unwrap();
+
+        let older = paginate(all.clone(), &Page::Before(next), 2);
+        assert_eq!(millis(&older), [3, 2]);
+
+        let newer = paginate(all.clone(), &Page::After(Cursor::of(&older[1]).unwrap()), 2);
+        assert_eq!(millis(&newer), [4, 3]);
+
+        let last = paginate(all, &Page::Before(Cursor::of(&older[1]).unwrap()), 2);
+        assert_eq!(millis(&last), [1]);
+        assert_eq!(next_cursor(&Page::Before(next), 2, &last), None);
+    }
+
+    #[test]
+    fn limits_are_clamped() {
+        assert_eq!(clamp_limit(None), DEFAULT_LIMIT);
+        assert_eq!(clamp_limit(Some(0)), 1);
+        assert_eq!(clamp_limit(Some(MAX_LIMIT + 1)), MAX_LIMIT);
+    }
+}
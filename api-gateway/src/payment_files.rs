@@ -0,0 +1,830 @@
+//! ISO 20022 payment initiation: `POST /api/payment-files` takes a
+//! `pain.001` customer credit transfer file and queues each transfer as an
+//! instruction for its sender. The gateway can't sign for an endpoint, so
+//! nothing moves until the sender picks its queue up from `GET
+//! /api/endpoints/:id/payment-instructions`, signs each instruction as a
+//! transfer under the instruction's `transaction_id` and posts it as usual.
+//! Ingest then marks the instruction released. Senders may decline an
+//! instruction instead.
+//!
+//! The debtor and creditor accounts name endpoints by `Othr/Id`; IBANs are
+//! not endpoints and are rejected. A file whose counts or control sums
+//! don't add up, that isn't a credit transfer or that reuses a `MsgId` is
+//! refused whole. Otherwise each transfer is checked on its own (currency,
+//! amount, accounts, endpoint status) and the ones that fail are recorded
+//! as rejected with a reason.
+//!
+//! `GET /api/payment-files/:id` reports on a file and its instructions, as
+//! JSON or, with `?format=pain002`, as a `pain.002` status report.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use tracing::{error, info, warn};
+use tx_core::{Money, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::db;
+use crate::endpoints::{self, EndpointStatus};
+use crate::repository::Repository;
+use crate::statements::escape;
+use crate::{audit, is_system_account, lwt_applied, pii, timestamp_from_millis, AppState, Transaction};
+
+/// Transfers accepted in one file.
+const MAX_INSTRUCTIONS: usize = 1000;
+/// `pain.002` additional information is `Max105Text`.
+const REASON_LENGTH: usize = 105;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum InstructionStatus {
+    /// Waiting for the sender to sign it.
+    #[serde(rename = "queued")]
+    Queued,
+    /// Signed and ingested as a transfer.
+    #[serde(rename = "released")]
+    Released,
+    /// Turned down by the sender.
+    #[serde(rename = "declined")]
+    Declined,
+    /// Failed the gateway's checks on import.
+    #[serde(rename = "rejected")]
+    Rejected,
+}
+
+impl InstructionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InstructionStatus::Queued => "queued",
+            InstructionStatus::Released => "released",
+            InstructionStatus::Declined => "declined",
+            InstructionStatus::Rejected => "rejected",
+        }
+    }
+
+    /// ISO 20022 transaction status code.
+    fn iso_code(&self) -> &'static str {
+        match self {
+            InstructionStatus::Queued => "PDNG",
+            InstructionStatus::Released => "ACSP",
+            InstructionStatus::Declined | InstructionStatus::Rejected => "RJCT",
+        }
+    }
+}
+
+impl fmt::Display for InstructionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for InstructionStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(InstructionStatus::Queued),
+            "released" => Ok(InstructionStatus::Released),
+            "declined" => Ok(InstructionStatus::Declined),
+            "rejected" => Ok(InstructionStatus::Rejected),
+            other => Err(format!("Unknown instruction status: {}", other)),
+        }
+    }
+}
+
+/// One credit transfer from a file. Senders sign it as a transfer with
+/// `transaction_id` as its id.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct PaymentInstruction {
+    pub transaction_id: String,
+    pub file_id: String,
+    pub payment_info_id: String,
+    pub end_to_end_id: String,
+    pub from_endpoint: String,
+    pub to_endpoint: String,
+    #[serde(with = "tx_core::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub amount: Money,
+    pub requested_date: Option<String>,
+    pub remittance: Option<String>,
+    pub status: InstructionStatus,
+    pub reason: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct PaymentFileReport {
+    pub file_id: String,
+    pub message_id: String,
+    pub submitted_by: String,
+    pub received_at: DateTime<Utc>,
+    pub queued: usize,
+    pub released: usize,
+    pub declined: usize,
+    pub rejected: usize,
+    pub instructions: Vec<PaymentInstruction>,
+}
+
+// The parts of pain.001 the gateway reads; everything else is ignored.
+
+#[derive(Debug, Deserialize)]
+struct Document {
+    #[serde(rename = "CstmrCdtTrfInitn")]
+    initiation: Initiation,
+}
+
+#[derive(Debug, Deserialize)]
+struct Initiation {
+    #[serde(rename = "GrpHdr")]
+    header: GroupHeader,
+    #[serde(rename = "PmtInf", default)]
+    payments: Vec<PaymentInformation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GroupHeader {
+    #[serde(rename = "MsgId")]
+    message_id: String,
+    #[serde(rename = "NbOfTxs")]
+    count: String,
+    #[serde(rename = "CtrlSum")]
+    control_sum: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PaymentInformation {
+    #[serde(rename = "PmtInfId")]
+    id: String,
+    #[serde(rename = "PmtMtd")]
+    method: String,
+    #[serde(rename = "NbOfTxs")]
+    count: Option<String>,
+    #[serde(rename = "CtrlSum")]
+    control_sum: Option<String>,
+    #[serde(rename = "ReqdExctnDt")]
+    requested_date: Option<RequestedDate>,
+    #[serde(rename = "DbtrAcct")]
+    debtor_account: Account,
+    #[serde(rename = "CdtTrfTxInf", default)]
+    transfers: Vec<CreditTransfer>,
+}
+
+/// A bare date up to `pain.001.001.03`, `Dt` or `DtTm` since.
+#[derive(Debug, Deserialize)]
+struct RequestedDate {
+    #[serde(rename = "$text")]
+    text: Option<String>,
+    #[serde(rename = "Dt")]
+    date: Option<String>,
+    #[serde(rename = "DtTm")]
+    date_time: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Account {
+    #[serde(rename = "Id")]
+    id: AccountId,
+}
+
+#[derive(Debug, Deserialize)]
+struct AccountId {
+    #[serde(rename = "Othr")]
+    other: Option<OtherId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OtherId {
+    #[serde(rename = "Id")]
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreditTransfer {
+    #[serde(rename = "PmtId")]
+    payment_id: PaymentId,
+    #[serde(rename = "Amt")]
+    amount: AmountChoice,
+    #[serde(rename = "CdtrAcct")]
+    creditor_account: Option<Account>,
+    #[serde(rename = "RmtInf")]
+    remittance: Option<Remittance>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PaymentId {
+    #[serde(rename = "EndToEndId")]
+    end_to_end_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AmountChoice {
+    #[serde(rename = "InstdAmt")]
+    instructed: Option<InstructedAmount>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InstructedAmount {
+    #[serde(rename = "@Ccy")]
+    currency: String,
+    #[serde(rename = "$text")]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Remittance {
+    #[serde(rename = "Ustrd", default)]
+    unstructured: Vec<String>,
+}
+
+fn account_endpoint(account: Option<&Account>) -> Option<String> {
+    account
+        .and_then(|account| account.id.other.as_ref())
+        .map(|other| other.id.trim().to_string())
+        .filter(|id| !id.is_empty())
+}
+
+/// Decimal amounts as the file writes them, whatever their currency.
+fn parse_amount(value: &str) -> Result<Money, String> {
+    value.trim().parse::<Money>().map_err(|_| format!("Invalid amount: {}", value.trim()))
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    value.trim().parse().map_err(|_| format!("Invalid NbOfTxs: {}", value.trim()))
+}
+
+fn check_totals(what: &str, count: Option<&str>, control_sum: Option<&str>, amounts: &[Money]) -> Result<(), String> {
+    if let Some(count) = count {
+        if parse_count(count)? != amounts.len() {
+            return Err(format!("{} NbOfTxs is {}, but it has {} transfers", what, count.trim(), amounts.len()));
+        }
+    }
+    if let Some(control_sum) = control_sum {
+        let expected = parse_amount(control_sum)?;
+        let total = amounts.iter().map(Money::minor).try_fold(0i64, i64::checked_add);
+        if total != Some(expected.minor()) {
+            return Err(format!("{} CtrlSum {} doesn't match its transfers", what, control_sum.trim()));
+        }
+    }
+    Ok(())
+}
+
+/// File-level checks; any failure refuses the whole file.
+fn validate(document: &Document) -> Result<(), String> {
+    let initiation = &document.initiation;
+    if initiation.header.message_id.trim().is_empty() {
+        return Err("MsgId is empty".to_string());
+    }
+    if initiation.payments.is_empty() {
+        return Err("No PmtInf".to_string());
+    }
+
+    let mut all = Vec::new();
+    for payment in &initiation.payments {
+        if payment.method.trim() != "TRF" {
+            return Err(format!("PmtInf {} is not a credit transfer (PmtMtd {})", payment.id, payment.method.trim()));
+        }
+        let amounts = payment
+            .transfers
+            .iter()
+            .map(|transfer| match &transfer.amount.instructed {
+                Some(instructed) => parse_amount(&instructed.value),
+                None => Err(format!("{} has no InstdAmt", transfer.payment_id.end_to_end_id.trim())),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        check_totals(
+            &format!("PmtInf {}", payment.id.trim()),
+            payment.count.as_deref(),
+            payment.control_sum.as_deref(),
+            &amounts,
+        )?;
+        all.extend(amounts);
+    }
+
+    if all.is_empty() || all.len() > MAX_INSTRUCTIONS {
+        return Err(format!("Files carry 1 to {} transfers", MAX_INSTRUCTIONS));
+    }
+    check_totals("GrpHdr", Some(initiation.header.count.as_str()), initiation.header.control_sum.as_deref(), &all)
+}
+
+/// Per-transfer checks: `Err` is the reason it's rejected.
+async fn check_instruction(session: &Repository, instruction: &PaymentInstruction, currency: &str) -> Result<Result<(), String>, StatusCode> {
+    if currency != NATIVE_ASSET {
+        return Ok(Err(format!("Only {} transfers can be initiated, not {}", NATIVE_ASSET, currency)));
+    }
+    if instruction.amount.minor() <= 0 {
+        return Ok(Err("Amount must be positive".to_string()));
+    }
+    if instruction.end_to_end_id.is_empty() {
+        return Ok(Err("EndToEndId is empty".to_string()));
+    }
+    if instruction.from_endpoint.is_empty() {
+        return Ok(Err("Debtor account has no Othr/Id endpoint".to_string()));
+    }
+    if instruction.to_endpoint.is_empty() {
+        return Ok(Err("Creditor account has no Othr/Id endpoint".to_string()));
+    }
+    if instruction.from_endpoint == instruction.to_endpoint {
+        return Ok(Err("Debtor and creditor are the same endpoint".to_string()));
+    }
+    if is_system_account(&instruction.from_endpoint) || is_system_account(&instruction.to_endpoint) {
+        return Ok(Err("System accounts can't take part in initiated transfers".to_string()));
+    }
+    if let Some(sender) = endpoints::load_endpoint(session, &instruction.from_endpoint).await? {
+        if sender.status != EndpointStatus::Active {
+            return Ok(Err(format!("Debtor endpoint is {}", sender.status)));
+        }
+    }
+    if let Some(receiver) = endpoints::load_endpoint(session, &instruction.to_endpoint).await? {
+        if receiver.status == EndpointStatus::Closed {
+            return Ok(Err("Creditor endpoint is closed".to_string()));
+        }
+    }
+    Ok(Ok(()))
+}
+
+fn db_error<E: fmt::Display>(file_id: Uuid) -> impl Fn(E) -> StatusCode {
+    move |e| {
+        error!("Payment file {} query failed: {}", file_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+/// `POST /api/payment-files` with a `pain.001` document as the body.
+pub async fn import_file(
+    State(state): State<AppState>,
+    claims: Option<Extension<Claims>>,
+    body: String,
+) -> Response {
+    let document: Document = match quick_xml::de::from_str(&body) {
+        Ok(document) => document,
+        Err(e) => return (StatusCode::BAD_REQUEST, format!("Not a pain.001 document: {}", e)).into_response(),
+    };
+    if let Err(reason) = validate(&document) {
+        return (StatusCode::UNPROCESSABLE_ENTITY, reason).into_response();
+    }
+    let submitted_by = claims.map(|Extension(claims)| claims.sub).unwrap_or_else(|| "anonymous".to_string());
+
+    match import(&state.session, document, submitted_by).await {
+        Ok(report) => (StatusCode::CREATED, Json(report)).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+async fn import(session: &Repository, document: Document, submitted_by: String) -> Result<PaymentFileReport, StatusCode> {
+    let file_id = Uuid::new_v4();
+    let message_id = document.initiation.header.message_id.trim().to_string();
+    let claimed = session
+        .query(
+            "INSERT INTO transactions.payment_file_messages (message_id, file_id) VALUES (?, ?) IF NOT EXISTS",
+            (&message_id, file_id),
+        )
+        .await
+        .map_err(db_error(file_id))?;
+    if !lwt_applied(claimed) {
+        info!("Refusing payment file {}: MsgId already imported", message_id);
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let now = Utc::now();
+    session
+        .query(
+            db::idempotent(
+                "INSERT INTO transactions.payment_files (file_id, message_id, submitted_by, received_at) VALUES (?, ?, ?, ?)",
+            ),
+            (file_id, &message_id, &submitted_by, now.timestamp_millis()),
+        )
+        .await
+        .map_err(db_error(file_id))?;
+
+    let mut instructions = Vec::new();
+    for payment in document.initiation.payments {
+        let from_endpoint = account_endpoint(Some(&payment.debtor_account)).unwrap_or_default();
+        let requested_date = payment
+            .requested_date
+            .and_then(|date| date.date.or(date.date_time).or(date.text))
+            .map(|date| date.trim().to_string());
+        for transfer in payment.transfers {
+            // Present and parseable, `validate` saw to that
+            let Some(instructed) = transfer.amount.instructed else { continue };
+            let amount = parse_amount(&instructed.value).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+            let remittance = transfer
+                .remittance
+                .map(|remittance| remittance.unstructured.join(" ").trim().to_string())
+                .filter(|text| !text.is_empty());
+            let mut instruction = PaymentInstruction {
+                transaction_id: Uuid::new_v4().to_string(),
+                file_id: file_id.to_string(),
+                payment_info_id: payment.id.trim().to_string(),
+                end_to_end_id: transfer.payment_id.end_to_end_id.trim().to_string(),
+                from_endpoint: from_endpoint.clone(),
+                to_endpoint: account_endpoint(transfer.creditor_account.as_ref()).unwrap_or_default(),
+                amount: Money::new(amount.minor(), NATIVE_ASSET),
+                requested_date: requested_date.clone(),
+                remittance,
+                status: InstructionStatus::Queued,
+                reason: None,
+                updated_at: now,
+            };
+            if let Err(reason) = check_instruction(session, &instruction, instructed.currency.trim()).await? {
+                instruction.status = InstructionStatus::Rejected;
+                instruction.reason = Some(reason);
+            }
+            instructions.push(instruction);
+        }
+    }
+
+    for (position, instruction) in instructions.iter().enumerate() {
+        store_instruction(session, file_id, position as i32, instruction).await?;
+    }
+
+    let report = report(file_id, message_id, submitted_by.clone(), now, instructions);
+    audit::record(
+        session,
+        &file_id.to_string(),
+        "payment_file.imported",
+        &submitted_by,
+        Some(format!("MsgId {}: {} queued, {} rejected", report.message_id, report.queued, report.rejected)),
+    )
+    .await?;
+    info!("📥 Payment file {} imported: {} queued, {} rejected", report.message_id, report.queued, report.rejected);
+    Ok(report)
+}
+
+async fn store_instruction(session: &Session, file_id: Uuid, position: i32, instruction: &PaymentInstruction) -> Result<(), StatusCode> {
+    let transaction_id = Uuid::parse_str(&instruction.transaction_id).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let remittance = pii::seal_opt(session, &instruction.from_endpoint, instruction.remittance.as_deref()).await?;
+    session
+        .query(
+            db::idempotent(
+                "INSERT INTO transactions.payment_instructions (file_id, position, transaction_id, payment_info_id, end_to_end_id,
+                 from_endpoint, to_endpoint, amount_minor, requested_date, remittance, status, reason, updated_at)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            ),
+            (
+                file_id,
+                position,
+                transaction_id,
+                &instruction.payment_info_id,
+                &instruction.end_to_end_id,
+                &instruction.from_endpoint,
+                &instruction.to_endpoint,
+                instruction.amount.minor(),
+                &instruction.requested_date,
+                &remittance,
+                instruction.status.as_str(),
+                &instruction.reason,
+                instruction.updated_at.timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(db_error(file_id))?;
+
+    if instruction.status == InstructionStatus::Queued {
+        session
+            .query(
+                db::idempotent(
+                    "INSERT INTO transactions.payment_queue (from_endpoint, transaction_id, file_id, position, to_endpoint, amount_minor)
+                     VALUES (?, ?, ?, ?, ?, ?)",
+                ),
+                (
+                    &instruction.from_endpoint,
+                    transaction_id,
+                    file_id,
+                    position,
+                    &instruction.to_endpoint,
+                    instruction.amount.minor(),
+                ),
+            )
+            .await
+            .map_err(db_error(file_id))?;
+    }
+    Ok(())
+}
+
+fn report(
+    file_id: Uuid,
+    message_id: String,
+    submitted_by: String,
+    received_at: DateTime<Utc>,
+    instructions: Vec<PaymentInstruction>,
+) -> PaymentFileReport {
+    let count = |status| instructions.iter().filter(|i| i.status == status).count();
+    PaymentFileReport {
+        file_id: file_id.to_string(),
+        message_id,
+        submitted_by,
+        received_at,
+        queued: count(InstructionStatus::Queued),
+        released: count(InstructionStatus::Released),
+        declined: count(InstructionStatus::Declined),
+        rejected: count(InstructionStatus::Rejected),
+        instructions,
+    }
+}
+
+type InstructionRow = (
+    Uuid,
+    Uuid,
+    String,
+    String,
+    String,
+    String,
+    i64,
+    Option<String>,
+    Option<String>,
+    String,
+    Option<String>,
+    i64,
+);
+
+const INSTRUCTION_COLUMNS: &str = "file_id, transaction_id, payment_info_id, end_to_end_id, from_endpoint, to_endpoint,
+     amount_minor, requested_date, remittance, status, reason, updated_at";
+
+fn instruction_from_row(row: InstructionRow) -> Result<PaymentInstruction, String> {
+    let (
+        file_id,
+        transaction_id,
+        payment_info_id,
+        end_to_end_id,
+        from_endpoint,
+        to_endpoint,
+        amount,
+        requested_date,
+        remittance,
+        status,
+        reason,
+        updated_at,
+    ) = row;
+    Ok(PaymentInstruction {
+        transaction_id: transaction_id.to_string(),
+        file_id: file_id.to_string(),
+        payment_info_id,
+        end_to_end_id,
+        from_endpoint,
+        to_endpoint,
+        amount: Money::new(amount, NATIVE_ASSET),
+        requested_date,
+        remittance,
+        status: status.parse()?,
+        reason,
+        updated_at: timestamp_from_millis(updated_at),
+    })
+}
+
+async fn load_instructions(session: &Session, file_id: Uuid, position: Option<i32>) -> Result<Vec<PaymentInstruction>, StatusCode> {
+    let select = format!("SELECT {} FROM transactions.payment_instructions WHERE file_id = ?", INSTRUCTION_COLUMNS);
+    let result = match position {
+        Some(position) => session.query(db::idempotent(format!("{} AND position = ?", select)), (file_id, position)).await,
+        None => session.query(db::idempotent(select), (file_id,)).await,
+    }
+    .map_err(db_error(file_id))?;
+
+    let mut instructions = Vec::new();
+    for row in result.rows.unwrap_or_default() {
+        let mut instruction = row
+            .into_typed::<InstructionRow>()
+            .map_err(|e| e.to_string())
+            .and_then(instruction_from_row)
+            .map_err(db_error(file_id))?;
+        instruction.remittance = pii::open_opt(session, &instruction.from_endpoint, instruction.remittance).await?;
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+/// `GET /api/payment-files/:id[?format=pain002]`
+pub async fn get_file(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    let file_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let row = state
+        .session
+        .query(
+            db::idempotent("SELECT message_id, submitted_by, received_at FROM transactions.payment_files WHERE file_id = ?"),
+            (file_id,),
+        )
+        .await
+        .map_err(db_error(file_id))?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let (message_id, submitted_by, received_at) =
+        row.into_typed::<(String, String, i64)>().map_err(db_error(file_id))?;
+
+    let instructions = load_instructions(&state.session, file_id, None).await?;
+    let report = report(file_id, message_id, submitted_by, timestamp_from_millis(received_at), instructions);
+
+    match params.get("format").map(String::as_str) {
+        None | Some("json") => Ok(Json(report).into_response()),
+        Some("pain002") => Ok(([(header::CONTENT_TYPE, "application/xml")], pain002(&report)).into_response()),
+        Some(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// The report as a `pain.002.001.10` customer payment status report.
+fn pain002(report: &PaymentFileReport) -> String {
+    let time = |at: &DateTime<Utc>| at.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let total = report.instructions.len();
+    let refused = report.declined + report.rejected;
+    let group_status = if refused == total {
+        "RJCT"
+    } else if refused > 0 {
+        "PART"
+    } else if report.released == total {
+        "ACSP"
+    } else {
+        "ACTC"
+    };
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<Document xmlns=\"urn:iso:std:iso:20022:tech:xsd:pain.002.001.10\">\n<CstmrPmtStsRpt>\n");
+    out.push_str(&format!(
+        "<GrpHdr><MsgId>{}</MsgId><CreDtTm>{}</CreDtTm></GrpHdr>\n",
+        Uuid::new_v4().simple(),
+        time(&Utc::now())
+    ));
+    out.push_str(&format!(
+        "<OrgnlGrpInfAndSts><OrgnlMsgId>{}</OrgnlMsgId><OrgnlMsgNmId>pain.001</OrgnlMsgNmId><OrgnlNbOfTxs>{}</OrgnlNbOfTxs><GrpSts>{}</GrpSts></OrgnlGrpInfAndSts>\n",
+        escape(&report.message_id),
+        total,
+        group_status
+    ));
+
+    let mut payment_info_ids: Vec<&str> = Vec::new();
+    for instruction in &report.instructions {
+        if !payment_info_ids.contains(&instruction.payment_info_id.as_str()) {
+            payment_info_ids.push(&instruction.payment_info_id);
+        }
+    }
+    for payment_info_id in payment_info_ids {
+        out.push_str(&format!("<OrgnlPmtInfAndSts><OrgnlPmtInfId>{}</OrgnlPmtInfId>\n", escape(payment_info_id)));
+        for instruction in report.instructions.iter().filter(|i| i.payment_info_id == payment_info_id) {
+            let reason = instruction
+                .reason
+                .as_deref()
+                .map(|reason| {
+                    let reason: String = reason.chars().take(REASON_LENGTH).collect();
+                    format!("<StsRsnInf><AddtlInf>{}</AddtlInf></StsRsnInf>", escape(&reason))
+                })
+                .unwrap_or_default();
+            out.push_str(&format!(
+                "<TxInfAndSts><OrgnlEndToEndId>{}</OrgnlEndToEndId><TxSts>{}</TxSts>{}</TxInfAndSts>\n",
+                escape(&instruction.end_to_end_id),
+                instruction.status.iso_code(),
+                reason
+            ));
+        }
+        out.push_str("</OrgnlPmtInfAndSts>\n");
+    }
+    out.push_str("</CstmrPmtStsRpt>\n</Document>\n");
+    out
+}
+
+/// A queued instruction as its sender's queue holds it.
+pub struct Queued {
+    transaction_id: Uuid,
+    file_id: Uuid,
+    position: i32,
+    to_endpoint: String,
+    amount_minor: i64,
+}
+
+async fn load_queue(session: &Session, from_endpoint: &str, transaction_id: Option<Uuid>) -> Result<Vec<Queued>, StatusCode> {
+    let select = "SELECT transaction_id, file_id, position, to_endpoint, amount_minor FROM transactions.payment_queue
+                  WHERE from_endpoint = ?";
+    let result = match transaction_id {
+        Some(id) => session.query(db::idempotent(format!("{} AND transaction_id = ?", select)), (from_endpoint, id)).await,
+        None => session.query(db::idempotent(select), (from_endpoint,)).await,
+    }
+    .map_err(|e| {
+        error!("Failed to load {}'s payment queue: {}", from_endpoint, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(result
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(Uuid, Uuid, i32, String, i64)>().ok())
+        .map(|(transaction_id, file_id, position, to_endpoint, amount_minor)| Queued {
+            transaction_id,
+            file_id,
+            position,
+            to_endpoint,
+            amount_minor,
+        })
+        .collect())
+}
+
+/// `GET /api/endpoints/:id/payment-instructions`: what the endpoint has
+/// yet to sign, oldest file first.
+pub async fn get_queue(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+) -> Result<Json<Vec<PaymentInstruction>>, StatusCode> {
+    let mut instructions = Vec::new();
+    for queued in load_queue(&state.session, &endpoint_id, None).await? {
+        instructions.extend(load_instructions(&state.session, queued.file_id, Some(queued.position)).await?);
+    }
+    instructions.retain(|instruction| instruction.status == InstructionStatus::Queued);
+    instructions.sort_by_key(|instruction| instruction.updated_at);
+    Ok(Json(instructions))
+}
+
+async fn settle(session: &Session, from_endpoint: &str, queued: &Queued, status: InstructionStatus, reason: Option<String>) -> Result<(), StatusCode> {
+    session
+        .query(
+            db::idempotent(
+                "UPDATE transactions.payment_instructions SET status = ?, reason = ?, updated_at = ? WHERE file_id = ? AND position = ?",
+            ),
+            (status.as_str(), reason, Utc::now().timestamp_millis(), queued.file_id, queued.position),
+        )
+        .await
+        .map_err(db_error(queued.file_id))?;
+    session
+        .query(
+            db::idempotent("DELETE FROM transactions.payment_queue WHERE from_endpoint = ? AND transaction_id = ?"),
+            (from_endpoint, queued.transaction_id),
+        )
+        .await
+        .map_err(db_error(queued.file_id))?;
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DeclineRequest {
+    pub reason: Option<String>,
+}
+
+/// `POST /api/endpoints/:id/payment-instructions/:tx_id/decline`
+pub async fn decline_instruction(
+    State(state): State<AppState>,
+    Path((endpoint_id, tx_id)): Path<(String, String)>,
+    claims: Option<Extension<Claims>>,
+    request: Option<Json<DeclineRequest>>,
+) -> Result<StatusCode, StatusCode> {
+    let transaction_id = Uuid::parse_str(&tx_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let queued = load_queue(&state.session, &endpoint_id, Some(transaction_id))
+        .await?
+        .into_iter()
+        .next()
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let reason = request
+        .and_then(|Json(request)| request.reason)
+        .map(|reason| reason.trim().to_string())
+        .filter(|reason| !reason.is_empty());
+
+    settle(&state.session, &endpoint_id, &queued, InstructionStatus::Declined, reason.clone()).await?;
+    let actor = claims.map(|Extension(claims)| claims.sub).unwrap_or_else(|| endpoint_id.clone());
+    let details = match reason {
+        Some(reason) => format!("{}: {}", tx_id, reason),
+        None => tx_id,
+    };
+    audit::record(&state.session, &queued.file_id.to_string(), "payment_instruction.declined", &actor, Some(details)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Whether `transaction` fulfils one of its sender's queued instructions,
+/// checked before it's recorded. A transfer under an instruction's id must
+/// pay what the instruction says.
+pub async fn matching_instruction(session: &Session, transaction: &Transaction) -> Result<Option<Queued>, StatusCode> {
+    let Ok(transaction_id) = Uuid::parse_str(&transaction.id) else {
+        return Ok(None);
+    };
+    let Some(queued) = load_queue(session, &transaction.from_endpoint, Some(transaction_id)).await?.into_iter().next() else {
+        return Ok(None);
+    };
+    if queued.to_endpoint != transaction.to_endpoint || queued.amount_minor != transaction.amount.minor() {
+        warn!("Transaction {} doesn't match its payment instruction", transaction.id);
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(Some(queued))
+}
+
+/// Marks the instruction a recorded transfer fulfilled as released.
+pub async fn release(session: &Session, transaction: &Transaction, queued: &Queued) -> Result<(), StatusCode> {
+    settle(session, &transaction.from_endpoint, queued, InstructionStatus::Released, None).await?;
+    audit::record(
+        session,
+        &queued.file_id.to_string(),
+        "payment_instruction.released",
+        &transaction.from_endpoint,
+        Some(transaction.id.clone()),
+    )
+    .await
+}
@@ -0,0 +1,342 @@
+//! Encryption at rest for the free text and account details the gateway
+//! stores about endpoints: dispute reasons, evidence messages and
+//! attachment names and types, remittance information, settlement
+//! destinations and audit details.
+//!
+//! Envelope encryption: each tenant (the endpoint or actor the text is
+//! about) has AES-256-GCM data keys, stored in `pii_data_keys` wrapped by
+//! the master key `PII_MASTER_KEY` (32 bytes of base64, read through the
+//! secrets provider). Values are sealed under the tenant's newest data key
+//! as `pii:v1:<version>:<base64 nonce and ciphertext>`, bound to the tenant
+//! so they can't be moved to another's row. The repository functions seal
+//! on write and open on read; values without the prefix, written before
+//! encryption was turned on or by erasure, read as they are. Without a
+//! master key values are stored in the clear, and sealed ones can't be
+//! read.
+//!
+//! `api-gateway rotate-pii-keys` rewraps every data key under the current
+//! master key. To replace the master key, move it to
+//! `PII_MASTER_KEY_PREVIOUS`, set the new one, restart the gateways and
+//! run the rotation; the previous key can be dropped after. With
+//! `--new-data-keys` the rotation also gives every tenant a new data key,
+//! which seals from then on; older versions are kept to read what they
+//! sealed.
+//!
+//! Event payloads are hash-chained into receipts and snapshots and stay in
+//! the clear, including the dispute and settlement events quoting the
+//! text above.
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use rand_core::{OsRng, RngCore};
+use scylla::Session;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use zeroize::Zeroizing;
+
+use crate::audit;
+use crate::db;
+use crate::lwt_applied;
+use crate::secrets::Secrets;
+
+const PREFIX: &str = "pii:v1:";
+const NONCE_BYTES: usize = 12;
+/// How long a tenant's newest data key version is trusted before it is
+/// reread, to pick up versions added by a rotation.
+const LATEST_TTL: Duration = Duration::from_secs(300);
+
+type DataKey = Arc<Zeroizing<[u8; 32]>>;
+
+struct MasterKey {
+    id: String,
+    key: Zeroizing<[u8; 32]>,
+}
+
+impl MasterKey {
+    fn parse(name: &str, value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let bytes = Zeroizing::new(BASE64.decode(value.trim())?);
+        let key = Zeroizing::new(
+            <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| format!("{} must be 32 bytes of base64", name))?,
+        );
+        let id = hex::encode(&Sha256::digest(key.as_slice())[..8]);
+        Ok(MasterKey { id, key })
+    }
+}
+
+struct Pii {
+    /// The current master key first.
+    masters: Vec<MasterKey>,
+    keys: RwLock<HashMap<(String, i32), DataKey>>,
+    latest: RwLock<HashMap<String, (i32, Instant)>>,
+}
+
+static PII: OnceLock<Pii> = OnceLock::new();
+
+/// Reads the master keys. Called once at startup, before any value is
+/// sealed or opened.
+pub async fn init(secrets: &Secrets) -> Result<(), Box<dyn std::error::Error>> {
+    let mut masters = Vec::new();
+    if let Some(value) = secrets.get("PII_MASTER_KEY").await? {
+        masters.push(MasterKey::parse("PII_MASTER_KEY", value.expose())?);
+    }
+    if let Some(value) = secrets.get("PII_MASTER_KEY_PREVIOUS").await? {
+        if masters.is_empty() {
+            return Err("PII_MASTER_KEY_PREVIOUS is set but PII_MASTER_KEY isn't".into());
+        }
+        masters.push(MasterKey::parse("PII_MASTER_KEY_PREVIOUS", value.expose())?);
+    }
+    match masters.first() {
+        Some(master) => info!("PII encryption with master key {}", master.id),
+        None => warn!("PII_MASTER_KEY is not set; personal data is stored unencrypted"),
+    }
+    let _ = PII.set(Pii { masters, keys: RwLock::default(), latest: RwLock::default() });
+    Ok(())
+}
+
+fn encrypt(key: &[u8; 32], plaintext: &[u8], aad: &[u8]) -> Result<String, String> {
+    let mut nonce = [0u8; NONCE_BYTES];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .encrypt(Nonce::from_slice(&nonce), Payload { msg: plaintext, aad })
+        .map_err(|_| "encryption failed".to_string())?;
+    let mut sealed = nonce.to_vec();
+    sealed.extend(ciphertext);
+    Ok(BASE64.encode(sealed))
+}
+
+fn decrypt(key: &[u8; 32], sealed: &str, aad: &[u8]) -> Result<Zeroizing<Vec<u8>>, String> {
+    let sealed = BASE64.decode(sealed).map_err(|e| e.to_string())?;
+    if sealed.len() < NONCE_BYTES {
+        return Err("truncated ciphertext".to_string());
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_BYTES);
+    Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad })
+        .map(Zeroizing::new)
+        .map_err(|_| "decryption failed".to_string())
+}
+
+/// What a data key is wrapped for, so it can't be swapped for another.
+fn wrap_aad(tenant: &str, version: i32) -> Vec<u8> {
+    format!("{}:{}", tenant, version).into_bytes()
+}
+
+impl Pii {
+    fn current(&self) -> Option<&MasterKey> {
+        self.masters.first()
+    }
+
+    fn unwrap_key(&self, tenant: &str, version: i32, wrapped: &str, master_key_id: &str) -> Result<DataKey, String> {
+        let master = self
+            .masters
+            .iter()
+            .find(|master| master.id == master_key_id)
+            .ok_or_else(|| format!("master key {} is not configured", master_key_id))?;
+        let bytes = decrypt(&master.key, wrapped, &wrap_aad(tenant, version))?;
+        let key = <[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| "data key is not 32 bytes".to_string())?;
+        Ok(Arc::new(Zeroizing::new(key)))
+    }
+
+    async fn data_key(&self, session: &Session, tenant: &str, version: i32) -> Result<DataKey, String> {
+        let cache_key = (tenant.to_string(), version);
+        if let Some(key) = self.keys.read().unwrap().get(&cache_key) {
+            return Ok(key.clone());
+        }
+        let result = session
+            .query(
+                db::idempotent(
+                    "SELECT wrapped_key, master_key_id FROM transactions.pii_data_keys \
+                     WHERE tenant = ? AND version = ?",
+                ),
+                (tenant, version),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        let (wrapped, master_key_id) = result
+            .rows
+            .and_then(|rows| rows.into_iter().next())
+            .and_then(|row| row.into_typed::<(String, String)>().ok())
+            .ok_or_else(|| format!("data key {} is missing", version))?;
+        let key = self.unwrap_key(tenant, version, &wrapped, &master_key_id)?;
+        self.keys.write().unwrap().insert(cache_key, key.clone());
+        Ok(key)
+    }
+
+    /// Adds data key `version`, unless another instance got there first.
+    async fn create_data_key(&self, session: &Session, tenant: &str, version: i32) -> Result<(), String> {
+        let master = self.current().ok_or("no master key")?;
+        let key = Zeroizing::new({
+            let mut key = [0u8; 32];
+            OsRng.fill_bytes(&mut key);
+            key
+        });
+        let wrapped = encrypt(&master.key, key.as_slice(), &wrap_aad(tenant, version))?;
+        let result = session
+            .query(
+                "INSERT INTO transactions.pii_data_keys (tenant, version, wrapped_key, master_key_id, created_at)
+                 VALUES (?, ?, ?, ?, ?) IF NOT EXISTS",
+                (tenant, version, &wrapped, &master.id, Utc::now().timestamp_millis()),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        if lwt_applied(result) {
+            info!("PII data key {} for {}", version, tenant);
+        }
+        Ok(())
+    }
+
+    async fn newest_version(&self, session: &Session, tenant: &str) -> Result<Option<i32>, String> {
+        let result = session
+            .query(
+                db::idempotent("SELECT version FROM transactions.pii_data_keys WHERE tenant = ? LIMIT 1"),
+                (tenant,),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(result
+            .rows
+            .and_then(|rows| rows.into_iter().next())
+            .and_then(|row| row.into_typed::<(i32,)>().ok())
+            .map(|(version,)| version))
+    }
+
+    /// The version new values for `tenant` are sealed under, creating the
+    /// tenant's first data key if it has none.
+    async fn latest_version(&self, session: &Session, tenant: &str) -> Result<i32, String> {
+        if let Some((version, read_at)) = self.latest.read().unwrap().get(tenant) {
+            if read_at.elapsed() < LATEST_TTL {
+                return Ok(*version);
+            }
+        }
+        let version = match self.newest_version(session, tenant).await? {
+            Some(version) => version,
+            None => {
+                self.create_data_key(session, tenant, 1).await?;
+                self.newest_version(session, tenant).await?.ok_or("data key not created")?
+            }
+        };
+        self.latest.write().unwrap().insert(tenant.to_string(), (version, Instant::now()));
+        Ok(version)
+    }
+
+    async fn seal(&self, session: &Session, tenant: &str, plaintext: &str) -> Result<String, String> {
+        let version = self.latest_version(session, tenant).await?;
+        let key = self.data_key(session, tenant, version).await?;
+        let sealed = encrypt(&key, plaintext.as_bytes(), tenant.as_bytes())?;
+        Ok(format!("{}{}:{}", PREFIX, version, sealed))
+    }
+
+    async fn open(&self, session: &Session, tenant: &str, sealed: &str) -> Result<String, String> {
+        let (version, sealed) = sealed.split_once(':').ok_or("malformed value")?;
+        let version = version.parse().map_err(|_| "malformed key version")?;
+        let key = self.data_key(session, tenant, version).await?;
+        let plaintext = decrypt(&key, sealed, tenant.as_bytes())?;
+        String::from_utf8(plaintext.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+fn enabled() -> Option<&'static Pii> {
+    PII.get().filter(|pii| pii.current().is_some())
+}
+
+/// `plaintext` as stored for `tenant`.
+pub async fn seal(session: &Session, tenant: &str, plaintext: &str) -> Result<String, StatusCode> {
+    let Some(pii) = enabled() else {
+        return Ok(plaintext.to_string());
+    };
+    pii.seal(session, tenant, plaintext).await.map_err(|e| {
+        error!("Failed to encrypt personal data of {}: {}", tenant, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+pub async fn seal_opt(session: &Session, tenant: &str, plaintext: Option<&str>) -> Result<Option<String>, StatusCode> {
+    match plaintext {
+        Some(plaintext) => seal(session, tenant, plaintext).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The plaintext of a value stored for `tenant`.
+pub async fn open(session: &Session, tenant: &str, stored: String) -> Result<String, StatusCode> {
+    let Some(sealed) = stored.strip_prefix(PREFIX) else {
+        return Ok(stored);
+    };
+    let pii = enabled().ok_or_else(|| {
+        error!("Personal data of {} is encrypted but PII_MASTER_KEY is not set", tenant);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    pii.open(session, tenant, sealed).await.map_err(|e| {
+        error!("Failed to decrypt personal data of {}: {}", tenant, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+pub async fn open_opt(session: &Session, tenant: &str, stored: Option<String>) -> Result<Option<String>, StatusCode> {
+    match stored {
+        Some(stored) => open(session, tenant, stored).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// `api-gateway rotate-pii-keys [--new-data-keys]`: rewraps every data
+/// key under the current master key, and with `new_data_keys` adds a new
+/// version for every tenant.
+pub async fn rotate(session: &Session, new_data_keys: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let pii = enabled().ok_or("PII_MASTER_KEY is not set")?;
+    let master = pii.current().ok_or("no master key")?;
+    let result = session
+        .query(
+            db::idempotent("SELECT tenant, version, wrapped_key, master_key_id FROM transactions.pii_data_keys"),
+            &[],
+        )
+        .await?;
+    let rows = result
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(String, i32, String, String)>().ok());
+
+    let mut newest: HashMap<String, i32> = HashMap::new();
+    let mut rewrapped = 0;
+    for (tenant, version, wrapped, master_key_id) in rows {
+        let entry = newest.entry(tenant.clone()).or_insert(version);
+        *entry = (*entry).max(version);
+        if master_key_id == master.id {
+            continue;
+        }
+        let key = pii.unwrap_key(&tenant, version, &wrapped, &master_key_id)?;
+        let wrapped = encrypt(&master.key, key.as_slice(), &wrap_aad(&tenant, version))?;
+        session
+            .query(
+                "UPDATE transactions.pii_data_keys SET wrapped_key = ?, master_key_id = ? \
+                 WHERE tenant = ? AND version = ?",
+                (&wrapped, &master.id, &tenant, version),
+            )
+            .await?;
+        rewrapped += 1;
+    }
+    info!("Rewrapped {} PII data keys under master key {}", rewrapped, master.id);
+    let details = format!("{} data keys rewrapped under master key {}", rewrapped, master.id);
+    audit::record(session, "pii-keys", "pii_key.rewrapped", "gateway", Some(details))
+        .await
+        .map_err(|status| format!("Failed to audit PII key rotation: {}", status))?;
+
+    if new_data_keys {
+        for (tenant, version) in &newest {
+            pii.create_data_key(session, tenant, version + 1).await?;
+            let details = format!("{} v{}", tenant, version + 1);
+            audit::record(session, "pii-keys", "pii_key.created", "gateway", Some(details))
+                .await
+                .map_err(|status| format!("Failed to audit PII key rotation: {}", status))?;
+        }
+        info!("Added PII data keys for {} tenants", newest.len());
+    }
+    Ok(())
+}
@@ -0,0 +1,142 @@
+//! Privacy protection for deployments that publish their stats. When it
+//! is turned on, `GET /api/stats`, `GET /api/endpoints/:id/stats` and
+//! their GraphQL counterparts serve noisy aggregates with small endpoints
+//! withheld, so no single payment can be read back out of them. Balances,
+//! statements and the ledger itself stay exact and are guarded by roles.
+//!
+//! - `PUBLIC_STATS_MIN_COUNT` (0, off): endpoints with fewer transactions
+//!   are left out of `/api/stats`, and their own stats are 404
+//! - `PUBLIC_STATS_EPSILON` (unset, off): adds Laplace noise with this
+//!   privacy budget, split evenly across the figures published for one
+//!   endpoint (count, sent, received) and across the totals (count,
+//!   volume). Lower is more private and noisier.
+//! - `PUBLIC_STATS_AMOUNT_BOUND` (1000): the largest payment, in major
+//!   units, the noise hides; bigger ones stand out more than epsilon says
+//! - `PUBLIC_STATS_NOISE_SEED`, read through the secrets provider: keys
+//!   the noise. The same figure gets the same noise until it changes, so
+//!   asking again and averaging doesn't remove it. Without a seed each
+//!   instance picks its own at startup; set it so every instance agrees.
+//!
+//! Derived figures are computed from the noisy ones: a balance change is
+//! received minus sent, the average is volume over count.
+
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::info;
+use tx_core::{Money, NATIVE_ASSET};
+use zeroize::Zeroizing;
+
+use crate::secrets::Secrets;
+use crate::{EndpointStats, TransactionStats};
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+struct Noise {
+    epsilon: f64,
+    /// `PUBLIC_STATS_AMOUNT_BOUND` in minor units.
+    amount_bound: f64,
+    seed: Zeroizing<Vec<u8>>,
+}
+
+impl Noise {
+    /// A Laplace sample of `scale` for `value`, the same every time for
+    /// the same figure.
+    fn laplace(&self, figure: &str, subject: &str, value: i64, scale: f64) -> f64 {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seed.as_slice());
+        for part in [figure, subject, &value.to_string()] {
+            hasher.update(part.as_bytes());
+            hasher.update([0]);
+        }
+        let digest = hasher.finalize();
+        let bits = u64::from_be_bytes(digest[..8].try_into().unwrap()) >> 11;
+        // Uniform in (-0.5, 0.5), never exactly 0.5 so the log stays finite
+        let u = (bits as f64 + 0.5) / (1u64 << 53) as f64 - 0.5;
+        -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+    }
+
+    fn count(&self, figure: &str, subject: &str, value: i64, figures: f64) -> i64 {
+        let noisy = value as f64 + self.laplace(figure, subject, value, figures / self.epsilon);
+        noisy.round().max(0.0) as i64
+    }
+
+    fn amount(&self, figure: &str, subject: &str, value: &Money, figures: f64) -> Money {
+        let scale = self.amount_bound * figures / self.epsilon;
+        let noisy = value.minor() as f64 + self.laplace(figure, subject, value.minor(), scale);
+        Money::new(noisy.round().max(0.0) as i64, value.currency())
+    }
+}
+
+#[derive(Clone)]
+pub struct StatsPrivacy {
+    min_count: i64,
+    noise: Option<Arc<Noise>>,
+}
+
+impl StatsPrivacy {
+    pub async fn from_env(secrets: &Secrets) -> Result<Self, Box<dyn std::error::Error>> {
+        let min_count: i64 = env_or("PUBLIC_STATS_MIN_COUNT", 0);
+        let noise = match std::env::var("PUBLIC_STATS_EPSILON").ok() {
+            Some(epsilon) => {
+                let epsilon: f64 = epsilon.parse().map_err(|_| "PUBLIC_STATS_EPSILON must be a number")?;
+                if !epsilon.is_finite() || epsilon <= 0.0 {
+                    return Err("PUBLIC_STATS_EPSILON must be positive".into());
+                }
+                let bound = Money::from_major(env_or("PUBLIC_STATS_AMOUNT_BOUND", 1000.0), NATIVE_ASSET)?;
+                let seed = match secrets.get("PUBLIC_STATS_NOISE_SEED").await? {
+                    Some(seed) => Zeroizing::new(seed.expose().as_bytes().to_vec()),
+                    None => {
+                        let mut seed = Zeroizing::new(vec![0u8; 32]);
+                        OsRng.fill_bytes(&mut seed);
+                        seed
+                    }
+                };
+                info!("Public stats noise: epsilon {}, amount bound {}", epsilon, bound.to_major());
+                Some(Arc::new(Noise { epsilon, amount_bound: bound.minor().max(1) as f64, seed }))
+            }
+            None => None,
+        };
+        if min_count > 0 {
+            info!("Public stats withhold endpoints under {} transactions", min_count);
+        }
+        Ok(StatsPrivacy { min_count, noise })
+    }
+
+    /// `stats` as published, or `None` if the endpoint is withheld.
+    pub fn endpoint(&self, stats: EndpointStats) -> Option<EndpointStats> {
+        if stats.transaction_count < self.min_count {
+            return None;
+        }
+        let Some(noise) = &self.noise else {
+            return Some(stats);
+        };
+        let id = &stats.endpoint_id;
+        let total_sent = noise.amount("sent", id, &stats.total_sent, 3.0);
+        let total_received = noise.amount("received", id, &stats.total_received, 3.0);
+        let balance_change = Money::new(total_received.minor() - total_sent.minor(), total_sent.currency());
+        Some(EndpointStats {
+            transaction_count: noise.count("count", id, stats.transaction_count, 3.0),
+            endpoint_id: stats.endpoint_id,
+            total_sent,
+            total_received,
+            balance_change,
+        })
+    }
+
+    pub fn totals(&self, stats: TransactionStats) -> TransactionStats {
+        let endpoints = stats.endpoints.into_iter().filter_map(|endpoint| self.endpoint(endpoint)).collect();
+        let Some(noise) = &self.noise else {
+            return TransactionStats { endpoints, ..stats };
+        };
+        let total_transactions = noise.count("count", "", stats.total_transactions, 2.0);
+        let total_volume = noise.amount("volume", "", &stats.total_volume, 2.0);
+        let average_transaction = match total_transactions {
+            0 => Money::zero(total_volume.currency()),
+            count => Money::new(total_volume.minor() / count, total_volume.currency()),
+        };
+        TransactionStats { total_transactions, total_volume, average_transaction, endpoints }
+    }
+}
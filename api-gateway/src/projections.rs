@@ -0,0 +1,517 @@
+//! Read models derived from the event log. Nothing here is written except by
+//! `apply`, so every table can be dropped and rebuilt from `events` at any
+//! time.
+//!
+//! - `tx_log`: latest state of each transaction
+//! - `endpoint_ledger`: one row per endpoint per balance-affecting event,
+//!   holding that event's contribution to the endpoint's stats. Summing a
+//!   partition up to a point in time gives the stats as of that time.
+//! - the ledger's static `total_*` columns: each endpoint's stats, and
+//!   `stats_*` its share of the overall count and volume as a sender, so
+//!   `/api/stats` reads a row per endpoint instead of the whole log. Each
+//!   ledger row is written in one conditional batch with the totals it
+//!   adds up to, so they count it exactly once however often an event is
+//!   replayed.
+//! - the transaction timeline, for paging through history (see `timeline`)
+//! - `tx_sends`, each sender's transfers by day (see `sends`)
+
+use chrono::{DateTime, Utc};
+use scylla::batch::Batch;
+use scylla::Session;
+use std::collections::{HashMap, HashSet};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::db;
+use crate::events::{self, Event, EventKind};
+use crate::repository::{Repository, Statement};
+use crate::sends;
+use crate::snapshots::ProjectionSnapshot;
+use crate::timeline;
+use crate::{lwt_applied, transaction_from_row, EndpointStats, Transaction, TransactionKind, TX_COLUMNS};
+use tx_core::{Money, NATIVE_ASSET};
+
+/// How often a ledger write retries when other rows keep moving the
+/// endpoint's totals on first.
+const MAX_TOTALS_ATTEMPTS: usize = 20;
+
+async fn upsert_transaction(session: &Session, transaction: &Transaction) -> Result<(), String> {
+    let tx_id = Uuid::parse_str(&transaction.id).map_err(|e| e.to_string())?;
+    let parent_tx_id = transaction
+        .parent_tx_id
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+
+    session
+        .query(
+            db::idempotent(
+                "INSERT INTO transactions.tx_log (id, from_endpoint, to_endpoint, amount, amount_minor, timestamp, signature, status, kind, risk_score, parent_tx_id, sequence)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            ),
+            (
+                tx_id,
+                &transaction.from_endpoint,
+                &transaction.to_endpoint,
+                transaction.amount.to_major(),
+                transaction.amount.minor(),
+                transaction.timestamp.timestamp_millis(),
+                &transaction.signature,
+                transaction.status.as_str(),
+                transaction.kind.as_str(),
+                transaction.risk_score.map(i32::from),
+                parent_tx_id,
+                transaction.sequence.map(|sequence| sequence as i64),
+            ),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    timeline::insert(
+        session,
+        tx_id,
+        &transaction.from_endpoint,
+        &transaction.to_endpoint,
+        transaction.timestamp.timestamp_millis(),
+    )
+    .await?;
+    sends::insert(
+        session,
+        tx_id,
+        &transaction.from_endpoint,
+        &transaction.to_endpoint,
+        transaction.timestamp.timestamp_millis(),
+        &transaction.amount,
+    )
+    .await
+}
+
+/// Writes the ledger rows for a transaction entering the log. The row's
+/// deltas are exactly what folding the transaction into empty stats yields,
+/// so the projection agrees with `EndpointStats::apply`.
+async fn record_ledger(session: &Session, event: &Event, transaction: &Transaction) -> Result<(), String> {
+    let event_id = Uuid::parse_str(&event.event_id).map_err(|e| e.to_string())?;
+    let mut parties = vec![transaction.from_endpoint.as_str()];
+    if transaction.to_endpoint != transaction.from_endpoint {
+        parties.push(transaction.to_endpoint.as_str());
+    }
+
+    for endpoint_id in parties {
+        let mut delta = EndpointStats::empty(endpoint_id);
+        delta
+            .apply(&transaction.from_endpoint, &transaction.to_endpoint, &transaction.amount, transaction.kind)
+            .map_err(|e| e.to_string())?;
+        let contribution = if endpoint_id == transaction.from_endpoint {
+            stats_contribution(transaction.kind, &transaction.amount)
+        } else {
+            (0, 0)
+        };
+        add_ledger_row(session, endpoint_id, event.at, event_id, &transaction.id, &delta, contribution).await?;
+    }
+    Ok(())
+}
+
+/// What a transaction adds to the overall count and volume, in minor
+/// units: a chargeback takes its amount back off the volume rather than
+/// counting as a transaction, and netted legs count through their net
+/// transfer.
+fn stats_contribution(kind: TransactionKind, amount: &Money) -> (i64, i64) {
+    match kind {
+        TransactionKind::Netted => (0, 0),
+        TransactionKind::Chargeback => (0, -amount.minor()),
+        _ => (1, amount.minor()),
+    }
+}
+
+/// An endpoint's totals as its ledger partition's static columns hold
+/// them, and the version they were written at (`None` before the first).
+#[derive(Default)]
+struct LedgerTotals {
+    version: Option<i64>,
+    transaction_count: i64,
+    sent_minor: i64,
+    received_minor: i64,
+    balance_minor: i64,
+    stats_count: i64,
+    stats_volume_minor: i64,
+}
+
+async fn load_ledger_totals(session: &Session, endpoint_id: &str) -> Result<LedgerTotals, String> {
+    let row = session
+        .query(
+            db::idempotent(
+                "SELECT totals_version, total_count, total_sent_minor, total_received_minor, total_balance_minor,
+                        stats_count, stats_volume_minor
+                 FROM transactions.endpoint_ledger WHERE endpoint_id = ? LIMIT 1",
+            ),
+            (endpoint_id,),
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .rows
+        .and_then(|rows| rows.into_iter().next());
+    let Some(row) = row else {
+        return Ok(LedgerTotals::default());
+    };
+    type TotalsRow = (Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<i64>);
+    let (version, count, sent, received, balance, stats_count, stats_volume) =
+        row.into_typed::<TotalsRow>().map_err(|e| e.to_string())?;
+    Ok(LedgerTotals {
+        version,
+        transaction_count: count.unwrap_or(0),
+        sent_minor: sent.unwrap_or(0),
+        received_minor: received.unwrap_or(0),
+        balance_minor: balance.unwrap_or(0),
+        stats_count: stats_count.unwrap_or(0),
+        stats_volume_minor: stats_volume.unwrap_or(0),
+    })
+}
+
+async fn ledger_row_exists(
+    session: &Session,
+    endpoint_id: &str,
+    at: DateTime<Utc>,
+    event_id: Uuid,
+) -> Result<bool, String> {
+    let result = session
+        .query(
+            db::idempotent(
+                "SELECT event_id FROM transactions.endpoint_ledger WHERE endpoint_id = ? AND at = ? AND event_id = ?",
+            ),
+            (endpoint_id, at.timestamp_millis(), event_id),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result.rows.is_some_and(|rows| !rows.is_empty()))
+}
+
+/// Writes a ledger row, and adds `delta` and the overall `contribution`
+/// (see `stats_contribution`) to the endpoint's totals in the same
+/// conditional batch: both land or neither does, so a replayed event
+/// leaves the totals as they were. Whether the row is new.
+async fn add_ledger_row(
+    session: &Session,
+    endpoint_id: &str,
+    at: DateTime<Utc>,
+    event_id: Uuid,
+    transaction_id: &str,
+    delta: &EndpointStats,
+    contribution: (i64, i64),
+) -> Result<bool, String> {
+    let mut batch = Batch::default();
+    batch.append_statement(
+        "INSERT INTO transactions.endpoint_ledger (endpoint_id, at, event_id, transaction_id, count_delta, sent_delta, received_delta, balance_delta, sent_minor, received_minor, balance_minor)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+    );
+    batch.append_statement(
+        "UPDATE transactions.endpoint_ledger
+         SET total_count = ?, total_sent_minor = ?, total_received_minor = ?, total_balance_minor = ?,
+             stats_count = ?, stats_volume_minor = ?, totals_version = ?
+         WHERE endpoint_id = ? IF totals_version = ?",
+    );
+    let row = (
+        endpoint_id,
+        at.timestamp_millis(),
+        event_id,
+        transaction_id,
+        delta.transaction_count,
+        delta.total_sent.to_major(),
+        delta.total_received.to_major(),
+        delta.balance_change.to_major(),
+        delta.total_sent.minor(),
+        delta.total_received.minor(),
+        delta.balance_change.minor(),
+    );
+
+    for _ in 0..MAX_TOTALS_ATTEMPTS {
+        let totals = load_ledger_totals(session, endpoint_id).await?;
+        let next_version = totals.version.unwrap_or(0) + 1;
+        let update = (
+            totals.transaction_count + delta.transaction_count,
+            totals.sent_minor + delta.total_sent.minor(),
+            totals.received_minor + delta.total_received.minor(),
+            totals.balance_minor + delta.balance_change.minor(),
+            totals.stats_count + contribution.0,
+            totals.stats_volume_minor + contribution.1,
+            next_version,
+            endpoint_id,
+            totals.version,
+        );
+        let result = session.batch(&batch, (row, update)).await.map_err(|e| e.to_string())?;
+        if lwt_applied(result) {
+            return Ok(true);
+        }
+        if ledger_row_exists(session, endpoint_id, at, event_id).await? {
+            // Applied before; the totals have it
+            return Ok(false);
+        }
+        // Another row moved the totals on first; add to where they are now
+    }
+    Err(format!("Contention updating the totals of {}", endpoint_id))
+}
+
+/// Folds one event into the read models. Idempotent: replaying an event
+/// rewrites the same rows.
+pub async fn apply(session: &Session, event: &Event) -> Result<(), String> {
+    let kind: EventKind = event.event_type.parse()?;
+    match kind {
+        EventKind::TransactionCreated | EventKind::Reversed => {
+            let transaction: Transaction =
+                serde_json::from_value(event.payload.clone()).map_err(|e| e.to_string())?;
+            upsert_transaction(session, &transaction).await?;
+            record_ledger(session, event, &transaction).await
+        }
+        EventKind::StatusChanged => {
+            let transaction: Transaction =
+                serde_json::from_value(event.payload.clone()).map_err(|e| e.to_string())?;
+            upsert_transaction(session, &transaction).await
+        }
+        // Recorded for audit and downstream consumers, no balance effect
+        EventKind::Settled | EventKind::Disputed => Ok(()),
+    }
+}
+
+/// Endpoint stats from the ledger, optionally as of a point in time.
+pub async fn endpoint_stats(
+    session: &Repository,
+    endpoint_id: &str,
+    as_of: Option<DateTime<Utc>>,
+) -> Result<EndpointStats, String> {
+    let result = match as_of {
+        Some(as_of) => {
+            session
+                .execute(Statement::LedgerTotalsAsOf, (endpoint_id, as_of.timestamp_millis()))
+                .await
+        }
+        None => session.execute(Statement::LedgerTotals, (endpoint_id,)).await,
+    }
+    .map_err(|e| e.to_string())?;
+
+    let mut stats = EndpointStats::empty(endpoint_id);
+    if let Some((count, sent, received, balance)) = result
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(Option<i64>, Option<i64>, Option<i64>, Option<i64>)>().ok())
+    {
+        stats.transaction_count = count.unwrap_or(0);
+        stats.total_sent = Money::new(sent.unwrap_or(0), NATIVE_ASSET);
+        stats.total_received = Money::new(received.unwrap_or(0), NATIVE_ASSET);
+        stats.balance_change = Money::new(balance.unwrap_or(0), NATIVE_ASSET);
+    }
+    Ok(stats)
+}
+
+/// The overall transaction count and volume, and every endpoint's stats,
+/// from the totals: a row per endpoint, however long the log.
+pub async fn totals(session: &Repository) -> Result<(i64, Money, Vec<EndpointStats>), String> {
+    let rows = session
+        .execute(Statement::EndpointTotals, ())
+        .await
+        .map_err(|e| e.to_string())?;
+    let (mut count, mut volume) = (0, 0);
+    let mut endpoints = Vec::new();
+    for row in rows.rows.unwrap_or_default() {
+        type TotalsRow = (String, Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<i64>);
+        let (endpoint_id, transaction_count, sent, received, balance, stats_count, stats_volume) =
+            row.into_typed::<TotalsRow>().map_err(|e| e.to_string())?;
+        count += stats_count.unwrap_or(0);
+        volume += stats_volume.unwrap_or(0);
+        let mut stats = EndpointStats::empty(&endpoint_id);
+        stats.transaction_count = transaction_count.unwrap_or(0);
+        stats.total_sent = Money::new(sent.unwrap_or(0), NATIVE_ASSET);
+        stats.total_received = Money::new(received.unwrap_or(0), NATIVE_ASSET);
+        stats.balance_change = Money::new(balance.unwrap_or(0), NATIVE_ASSET);
+        endpoints.push(stats);
+    }
+    Ok((count, Money::new(volume, NATIVE_ASSET), endpoints))
+}
+
+/// Reconstructs every transaction as it stood at `as_of` by replaying the
+/// log up to that moment, optionally limited to one endpoint's transfers.
+/// Newest first, like `GET /api/transactions`.
+pub async fn transactions_as_of(
+    session: &Session,
+    endpoint: Option<&str>,
+    as_of: DateTime<Utc>,
+) -> Result<Vec<Transaction>, String> {
+    let mut log = events::load_events_until(session, endpoint, as_of).await?;
+    log.sort_by(|a, b| (&a.partition_key, a.offset).cmp(&(&b.partition_key, b.offset)));
+
+    let mut transactions: HashMap<String, Transaction> = HashMap::new();
+    for event in log {
+        match event.event_type.parse() {
+            Ok(EventKind::TransactionCreated) | Ok(EventKind::Reversed) | Ok(EventKind::StatusChanged) => {}
+            _ => continue,
+        }
+        match serde_json::from_value::<Transaction>(event.payload) {
+            Ok(transaction) => {
+                transactions.insert(transaction.id.clone(), transaction);
+            }
+            Err(e) => error!("Unreadable payload in event {}#{}: {}", event.partition_key, event.offset, e),
+        }
+    }
+
+    let mut transactions: Vec<Transaction> = transactions.into_values().collect();
+    transactions.sort_by_key(|transaction| std::cmp::Reverse(transaction.timestamp));
+    Ok(transactions)
+}
+
+/// Resets the read models to a snapshot's state: each endpoint's ledger
+/// starts from one opening row carrying its totals at the snapshot.
+pub async fn restore(session: &Session, snapshot: &ProjectionSnapshot) -> Result<(), String> {
+    let snapshot_id = Uuid::parse_str(&snapshot.snapshot_id).map_err(|e| e.to_string())?;
+    truncate_ledger(session).await?;
+
+    let mut contributions: HashMap<&str, (i64, i64)> = HashMap::new();
+    for transaction in snapshot.transactions.values() {
+        upsert_transaction(session, transaction).await?;
+        let (count, volume) = stats_contribution(transaction.kind, &transaction.amount);
+        let contribution = contributions.entry(transaction.from_endpoint.as_str()).or_default();
+        contribution.0 += count;
+        contribution.1 += volume;
+    }
+
+    let transaction_id = format!("snapshot:{}", snapshot.snapshot_id);
+    for (endpoint_id, stats) in &snapshot.endpoints {
+        let contribution = contributions.remove(endpoint_id.as_str()).unwrap_or_default();
+        add_ledger_row(session, endpoint_id, snapshot.taken_at, snapshot_id, &transaction_id, stats, contribution)
+            .await?;
+    }
+    for (endpoint_id, contribution) in contributions {
+        let stats = EndpointStats::empty(endpoint_id);
+        add_ledger_row(session, endpoint_id, snapshot.taken_at, snapshot_id, &transaction_id, &stats, contribution)
+            .await?;
+    }
+    Ok(())
+}
+
+/// The ledger, and with it the totals.
+async fn truncate_ledger(session: &Session) -> Result<(), String> {
+    session
+        .query("TRUNCATE transactions.endpoint_ledger", &[])
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Drops the ledger and replays the whole event log into the read models.
+pub async fn rebuild(session: &Session) -> Result<usize, String> {
+    truncate_ledger(session).await?;
+
+    // Each transaction's events share its sender's partition, so partition
+    // order is enough to apply them in the order they happened
+    let mut replayed = 0;
+    for partition_key in events::partitions(session).await?.keys() {
+        let partition = events::load_partition(session, partition_key, 0).await?;
+        for event in &partition {
+            if let Err(e) = apply(session, event).await {
+                error!("Skipping event {}#{} during rebuild: {}", event.partition_key, event.offset, e);
+            }
+        }
+        replayed += partition.len();
+    }
+    Ok(replayed)
+}
+
+/// One-off migration for ledgers written before the event log was the
+/// source of truth: every `tx_log` row without a creation event gets one,
+/// dated at the transaction's own timestamp, and the projections are rebuilt.
+/// Does nothing once the ledger has rows.
+pub async fn backfill(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    let existing = session
+        .query(db::idempotent("SELECT endpoint_id FROM transactions.endpoint_ledger LIMIT 1"), &[])
+        .await?;
+    if existing.rows.map(|rows| !rows.is_empty()).unwrap_or(false) {
+        return Ok(());
+    }
+
+    let mut recorded: HashSet<String> = HashSet::new();
+    for partition_key in events::partitions(session).await?.keys() {
+        recorded.extend(
+            events::load_partition(session, partition_key, 0)
+                .await?
+                .into_iter()
+                .filter(|event| {
+                    matches!(
+                        event.event_type.parse(),
+                        Ok(EventKind::TransactionCreated) | Ok(EventKind::Reversed)
+                    )
+                })
+                .map(|event| event.transaction_id),
+        );
+    }
+
+    let rows = session
+        .query(db::scan(format!("SELECT {} FROM transactions.tx_log", TX_COLUMNS)), &[])
+        .await?;
+    let mut backfilled = 0;
+    if let Some(rows) = rows.rows {
+        for row in rows {
+            let Ok(transaction) = row.into_typed().map_err(|e| e.to_string()).and_then(transaction_from_row) else {
+                continue;
+            };
+            if recorded.contains(&transaction.id) {
+                continue;
+            }
+            let payload = serde_json::to_value(&transaction)?;
+            events::append_with(session, creation_event(&transaction), &transaction, payload, transaction.timestamp)
+                .await
+                .map_err(|status| format!("Backfilling {} failed: {}", transaction.id, status))?;
+            backfilled += 1;
+        }
+    }
+
+    let replayed = rebuild(session).await?;
+    info!("Backfilled {} transactions, replayed {} events into projections", backfilled, replayed);
+    Ok(())
+}
+
+/// One-off migration for ledgers written before amounts were kept in
+/// minor units: if any row lacks them, the projections are rebuilt from
+/// the log, which fills in `tx_log.amount_minor` as well.
+pub async fn migrate_minor_units(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    let unmigrated = session
+        .query(db::scan("SELECT balance_minor FROM transactions.endpoint_ledger"), &[])
+        .await?
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .any(|row| matches!(row.into_typed::<(Option<i64>,)>(), Ok((None,))));
+    if !unmigrated {
+        return Ok(());
+    }
+
+    let replayed = rebuild(session).await?;
+    info!("Rebuilt projections in minor units, replayed {} events", replayed);
+    Ok(())
+}
+
+/// One-off migration for ledgers written before the totals were kept on
+/// the ledger: if any endpoint's partition has no `totals_version`, the
+/// projections are rebuilt from the log, which adds every event once.
+pub async fn migrate_totals(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    let uncounted = session
+        .query(db::scan("SELECT DISTINCT endpoint_id, totals_version FROM transactions.endpoint_ledger"), &[])
+        .await?
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .any(|row| matches!(row.into_typed::<(String, Option<i64>)>(), Ok((_, None))));
+    if !uncounted {
+        return Ok(());
+    }
+
+    let replayed = rebuild(session).await?;
+    info!("Moved the stats totals onto the ledger, replayed {} events", replayed);
+    Ok(())
+}
+
+/// Chargebacks enter the log as reversals of their parent, everything else
+/// as a plain creation.
+pub fn creation_event(transaction: &Transaction) -> EventKind {
+    match transaction.kind {
+        TransactionKind::Chargeback => EventKind::Reversed,
+        _ => EventKind::TransactionCreated,
+    }
+}
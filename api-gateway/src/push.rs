@@ -0,0 +1,235 @@
+//! WebSocket push for dashboards: `GET /api/ws[?resume=<event id>]`
+//! upgrades and streams the same live updates as the SSE feed, plus the
+//! change each one makes to `/api/stats`, so a client can keep its
+//! aggregates current without polling. With `resume`, the last `id` it
+//! saw, a reconnecting client first gets what it missed (see `live`).
+//!
+//! Messages are JSON objects tagged by `type`. A new connection gets
+//! everything; the client narrows it with
+//!
+//! ```json
+//! {"type": "subscribe", "endpoints": ["alice"], "min_amount": 100.0, "max_amount": null}
+//! ```
+//!
+//! The thresholds are major units, as numbers or exact decimal strings
+//! (`"100.00"`), and compare exactly against the transaction's amount.
+//!
+//! Each `subscribe` replaces the previous filter and is answered with
+//! `subscribed`; `unsubscribe` stops transaction and stats messages.
+//! The server sends:
+//!
+//! - `transaction`: the change (`transaction.created`, ...), the event id
+//!   and the transaction, if it involves a subscribed endpoint and its
+//!   amount is within the thresholds
+//! - `stats_delta`: what a new transfer or chargeback adds to the totals
+//!   and to each involved endpoint's stats. Totals are global, so deltas
+//!   ignore the amount thresholds; endpoint entries follow the endpoint
+//!   filter
+//! - `lagged`: the client fell behind by `missed` updates (0 if unknown,
+//!   as for a `resume` token no longer buffered) and should refetch
+//!   `/api/transactions` and `/api/stats`
+//! - `error`: a message the server couldn't read
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
+use tokio_stream::StreamExt;
+use tracing::debug;
+
+use crate::events::EventKind;
+use crate::live::LiveUpdate;
+use crate::{is_system_account, AppState, EndpointStats, Transaction, TransactionKind};
+use tx_core::{Money, NATIVE_ASSET};
+
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Subscription {
+    /// Only transactions involving one of these; all if empty.
+    #[serde(default)]
+    pub endpoints: Vec<String>,
+    #[serde(default, with = "tx_core::money::as_major_opt")]
+    pub min_amount: Option<Money>,
+    #[serde(default, with = "tx_core::money::as_major_opt")]
+    pub max_amount: Option<Money>,
+}
+
+impl Subscription {
+    fn follows(&self, endpoint_id: &str) -> bool {
+        self.endpoints.is_empty() || self.endpoints.iter().any(|e| e == endpoint_id)
+    }
+
+    fn admits(&self, transaction: &Transaction) -> bool {
+        (self.endpoints.is_empty() || self.endpoints.iter().any(|e| transaction.involves(e)))
+            && self.min_amount.as_ref().is_none_or(|min| transaction.amount >= *min)
+            && self.max_amount.as_ref().is_none_or(|max| transaction.amount <= *max)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Subscribe(Subscription),
+    Unsubscribe,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    Subscribed {
+        subscription: Option<&'a Subscription>,
+    },
+    Transaction {
+        event: &'static str,
+        id: &'a str,
+        transaction: &'a Transaction,
+    },
+    StatsDelta {
+        id: &'a str,
+        total_transactions: i64,
+        #[serde(with = "tx_core::money::as_major")]
+        total_volume: Money,
+        endpoints: Vec<EndpointStats>,
+    },
+    Lagged {
+        missed: u64,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// What `update` adds to `/api/stats`, mirroring its aggregation: netted
+/// payments count through their net transfer, a chargeback takes its
+/// amount back off the volume, and status changes change nothing.
+fn stats_delta(update: &LiveUpdate) -> Option<(i64, Money, Vec<EndpointStats>)> {
+    if !matches!(update.kind, EventKind::TransactionCreated | EventKind::Reversed) {
+        return None;
+    }
+    let transaction = &update.transaction;
+    if transaction.kind == TransactionKind::Netted {
+        return None;
+    }
+    let amount = &transaction.amount;
+    let (count, volume) = if transaction.kind == TransactionKind::Chargeback {
+        (0, Money::zero(NATIVE_ASSET).checked_sub(amount).ok()?)
+    } else {
+        (1, amount.clone())
+    };
+
+    let mut endpoints: Vec<EndpointStats> = Vec::new();
+    for endpoint_id in [&transaction.from_endpoint, &transaction.to_endpoint] {
+        if is_system_account(endpoint_id) || endpoints.iter().any(|s| s.endpoint_id == *endpoint_id) {
+            continue;
+        }
+        let mut stats = EndpointStats::empty(endpoint_id);
+        stats
+            .apply(&transaction.from_endpoint, &transaction.to_endpoint, amount, transaction.kind)
+            .ok()?;
+        endpoints.push(stats);
+    }
+    Some((count, volume, endpoints))
+}
+
+/// `GET /api/ws[?resume=<event id>]`
+pub async fn connect(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let resume = params.get("resume").cloned().filter(|token| !token.is_empty());
+    upgrade.on_upgrade(move |socket| serve(state, socket, resume))
+}
+
+async fn send(socket: &mut WebSocket, message: &ServerMessage<'_>) -> bool {
+    match serde_json::to_string(message) {
+        Ok(text) => socket.send(Message::Text(text)).await.is_ok(),
+        Err(_) => true,
+    }
+}
+
+async fn serve(state: AppState, mut socket: WebSocket, resume: Option<String>) {
+    let mut updates = state.live.updates(resume.as_deref());
+    // `None` after `unsubscribe`
+    let mut subscription = Some(Subscription::default());
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    debug!("Push client connected");
+
+    loop {
+        tokio::select! {
+            incoming = socket.recv() => {
+                let text = match incoming {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | None | Some(Err(_)) => break,
+                    Some(Ok(_)) => continue,
+                };
+                let reply = match serde_json::from_str::<ClientMessage>(&text) {
+                    Ok(ClientMessage::Subscribe(requested)) => {
+                        subscription = Some(requested);
+                        ServerMessage::Subscribed { subscription: subscription.as_ref() }
+                    }
+                    Ok(ClientMessage::Unsubscribe) => {
+                        subscription = None;
+                        ServerMessage::Subscribed { subscription: None }
+                    }
+                    Err(e) => ServerMessage::Error { message: format!("Invalid message: {}", e) },
+                };
+                if !send(&mut socket, &reply).await {
+                    break;
+                }
+            }
+            update = updates.next() => {
+                let update = match update {
+                    Some(Ok(update)) => update,
+                    Some(Err(BroadcastStreamRecvError::Lagged(missed))) => {
+                        if !send(&mut socket, &ServerMessage::Lagged { missed }).await {
+                            break;
+                        }
+                        continue;
+                    }
+                    None => break,
+                };
+                let Some(subscription) = &subscription else {
+                    continue;
+                };
+
+                if subscription.admits(&update.transaction) {
+                    let message = ServerMessage::Transaction {
+                        event: update.kind.as_str(),
+                        id: &update.event_id,
+                        transaction: &update.transaction,
+                    };
+                    if !send(&mut socket, &message).await {
+                        break;
+                    }
+                }
+                if let Some((total_transactions, total_volume, mut endpoints)) = stats_delta(&update) {
+                    endpoints.retain(|stats| subscription.follows(&stats.endpoint_id));
+                    let message = ServerMessage::StatsDelta {
+                        id: &update.event_id,
+                        total_transactions,
+                        total_volume,
+                        endpoints,
+                    };
+                    if !send(&mut socket, &message).await {
+                        break;
+                    }
+                }
+            }
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    debug!("Push client disconnected");
+}
@@ -0,0 +1,360 @@
+//! Token-bucket rate limiting, as a tower layer in front of every route.
+//! Each client IP gets `RATE_LIMIT_IP_PER_SEC` (50) requests a second with
+//! bursts of up to `RATE_LIMIT_IP_BURST` (100), and each sending endpoint
+//! `RATE_LIMIT_ENDPOINT_PER_SEC` (10) transfers a second with bursts of
+//! `RATE_LIMIT_ENDPOINT_BURST` (20) through `POST /api/transactions`. A
+//! rate of 0 turns that limit off. Requests over a limit get `429` with
+//! `Retry-After`.
+//!
+//! The client IP is the peer address. Behind proxies, set
+//! `RATE_LIMIT_TRUSTED_HOPS` to how many there are and it is the
+//! `X-Forwarded-For` hop that many from the right, the one the outermost
+//! proxy appended; hops left of it are the client's to write and are
+//! ignored. `RATE_LIMIT_TRUST_FORWARDED=true` is one trusted hop. A
+//! request with fewer hops than that, or one that isn't an IP, falls back
+//! to the peer address. The endpoint is the transfer's `from_endpoint`, read before its signature is
+//! checked, so a client spoofing someone else's transfers spends their
+//! budget; the IP limit, checked first, bounds how fast.
+//!
+//! Buckets are per gateway instance. Throttled requests are logged and
+//! counted, and `GET /api/admin/rate-limits` reports the counts and the
+//! keys throttled most since their buckets were last idle.
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Query, Request},
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower::{Layer, Service};
+use tracing::{info, warn};
+
+use crate::error::ApiError;
+
+const DEFAULT_LIMIT: usize = 10;
+/// Largest transfer body read for its sender; bigger ones are refused
+/// by the handler anyway.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+/// Buckets kept before idle ones are dropped.
+const SWEEP_THRESHOLD: usize = 10_000;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Limit {
+    pub per_sec: f64,
+    pub burst: f64,
+}
+
+impl Limit {
+    fn from_env(prefix: &str, per_sec: f64, burst: f64) -> Option<Limit> {
+        let per_sec: f64 = env_or(&format!("{}_PER_SEC", prefix), per_sec);
+        let burst: f64 = env_or(&format!("{}_BURST", prefix), burst);
+        (per_sec > 0.0).then_some(Limit { per_sec, burst: burst.max(1.0) })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    throttled: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: Limit, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_sec).min(limit.burst);
+        self.updated = now;
+    }
+
+    /// Takes a token, or says how long until one is available.
+    fn take(&mut self, limit: Limit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        self.throttled += 1;
+        Err(Duration::from_secs_f64((1.0 - self.tokens) / limit.per_sec))
+    }
+}
+
+/// The buckets of one kind of key, with their throttle count.
+struct Limiter {
+    kind: &'static str,
+    limit: Limit,
+    buckets: Mutex<HashMap<String, Bucket>>,
+    throttled: AtomicU64,
+}
+
+impl Limiter {
+    fn new(kind: &'static str, limit: Limit) -> Self {
+        Limiter {
+            kind,
+            limit,
+            buckets: Mutex::new(HashMap::new()),
+            throttled: AtomicU64::new(0),
+        }
+    }
+
+    fn check(&self, key: &str) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() >= SWEEP_THRESHOLD && !buckets.contains_key(key) {
+            // A full bucket is as good as a new one
+            let limit = self.limit;
+            buckets.retain(|_, bucket| {
+                bucket.refill(limit, now);
+                bucket.tokens < limit.burst
+            });
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.limit.burst,
+            updated: now,
+            throttled: 0,
+        });
+        let result = bucket.take(self.limit, now);
+        if result.is_err() {
+            self.throttled.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn report(&self, limit: usize) -> LimiterReport {
+        let buckets = self.buckets.lock().unwrap();
+        let mut top: Vec<ThrottledKey> = buckets
+            .iter()
+            .filter(|(_, bucket)| bucket.throttled > 0)
+            .map(|(key, bucket)| ThrottledKey { key: key.clone(), throttled: bucket.throttled })
+            .collect();
+        top.sort_by(|a, b| b.throttled.cmp(&a.throttled).then_with(|| a.key.cmp(&b.key)));
+        top.truncate(limit);
+        LimiterReport {
+            limit: self.limit,
+            tracked_keys: buckets.len(),
+            throttled: self.throttled.load(Ordering::Relaxed),
+            top,
+        }
+    }
+}
+
+struct Limiters {
+    /// Proxies in front of the gateway that append to `X-Forwarded-For`.
+    trusted_hops: usize,
+    ip: Option<Limiter>,
+    endpoint: Option<Limiter>,
+}
+
+static LIMITERS: LazyLock<Limiters> = LazyLock::new(|| {
+    let limiters = Limiters {
+        trusted_hops: env_or(
+            "RATE_LIMIT_TRUSTED_HOPS",
+            usize::from(std::env::var("RATE_LIMIT_TRUST_FORWARDED").is_ok_and(|v| v == "true")),
+        ),
+        ip: Limit::from_env("RATE_LIMIT_IP", 50.0, 100.0).map(|limit| Limiter::new("ip", limit)),
+        endpoint: Limit::from_env("RATE_LIMIT_ENDPOINT", 10.0, 20.0).map(|limit| Limiter::new("endpoint", limit)),
+    };
+    for (kind, limiter) in [("IP", &limiters.ip), ("endpoint", &limiters.endpoint)] {
+        match limiter {
+            Some(Limiter { limit, .. }) => info!("Rate limit per {}: {}/s, burst {}", kind, limit.per_sec, limit.burst),
+            None => info!("Rate limit per {} off", kind),
+        }
+    }
+    limiters
+});
+
+fn too_many_requests(limiter: &Limiter, key: &str, retry_after: Duration) -> Response {
+    let secs = retry_after.as_secs_f64().ceil().max(1.0) as u64;
+    warn!(kind = limiter.kind, key = %key, retry_after_secs = secs, "Rate limited");
+    ApiError::RateLimited {
+        message: format!("Rate limit exceeded for this {}", limiter.kind),
+        retry_after_secs: secs,
+        details: Some(serde_json::json!({
+            "limit": limiter.kind,
+            "per_sec": limiter.limit.per_sec,
+            "burst": limiter.limit.burst,
+        })),
+    }
+    .into_response()
+}
+
+/// The `X-Forwarded-For` hop `trusted_hops` from the right: what the
+/// outermost trusted proxy saw as its peer.
+fn forwarded_ip(forwarded_for: &str, trusted_hops: usize) -> Option<IpAddr> {
+    let hop = forwarded_for.rsplit(',').nth(trusted_hops.checked_sub(1)?)?;
+    hop.trim().parse().ok()
+}
+
+fn client_ip(request: &Request, trusted_hops: usize) -> Option<String> {
+    peer_ip(request.headers(), request.extensions().get::<ConnectInfo<SocketAddr>>(), trusted_hops)
+}
+
+fn peer_ip(headers: &HeaderMap, peer: Option<&ConnectInfo<SocketAddr>>, trusted_hops: usize) -> Option<String> {
+    let forwarded = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .map(|v| v.to_str().ok())
+        .collect::<Option<Vec<_>>>()
+        .and_then(|values| forwarded_ip(&values.join(","), trusted_hops));
+    if let Some(forwarded) = forwarded {
+        return Some(forwarded.to_string());
+    }
+    peer.map(|ConnectInfo(addr)| addr.ip().to_string())
+}
+
+/// The client IP, as the IP limit sees it.
+pub fn request_ip(request: &Request) -> Option<String> {
+    client_ip(request, LIMITERS.trusted_hops)
+}
+
+/// `request_ip` for handlers, which see the headers and peer address
+/// rather than the request.
+pub fn handler_ip(headers: &HeaderMap, peer: Option<&ConnectInfo<SocketAddr>>) -> Option<String> {
+    peer_ip(headers, peer, LIMITERS.trusted_hops)
+}
+
+#[derive(Deserialize)]
+struct Sender {
+    from_endpoint: String,
+}
+
+/// Checks the request against its limits, handing it back (its body
+/// reread if the sender was needed) or the response refusing it.
+async fn admit(request: Request) -> Result<Request, Response> {
+    let limiters = &*LIMITERS;
+    if request.uri().path() == "/health" {
+        return Ok(request);
+    }
+    if let Some(limiter) = &limiters.ip {
+        if let Some(ip) = client_ip(&request, limiters.trusted_hops) {
+            limiter.check(&ip).map_err(|retry_after| too_many_requests(limiter, &ip, retry_after))?;
+        }
+    }
+
+    let Some(limiter) = &limiters.endpoint else {
+        return Ok(request);
+    };
+    if request.method() != Method::POST || request.uri().path() != "/api/transactions" {
+        return Ok(request);
+    }
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE.into_response())?;
+    // Malformed bodies are the handler's to refuse
+    if let Ok(Sender { from_endpoint }) = serde_json::from_slice(&bytes) {
+        limiter
+            .check(&from_endpoint)
+            .map_err(|retry_after| too_many_requests(limiter, &from_endpoint, retry_after))?;
+    }
+    Ok(Request::from_parts(parts, Body::from(bytes)))
+}
+
+#[derive(Clone)]
+pub struct RateLimitLayer;
+
+impl RateLimitLayer {
+    /// Reads the limits now rather than on the first request.
+    pub fn from_env() -> Self {
+        LazyLock::force(&LIMITERS);
+        RateLimitLayer
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // The ready service goes with this call; the clone waits for the next
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            match admit(request).await {
+                Ok(request) => inner.call(request).await,
+                Err(response) => Ok(response),
+            }
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ThrottledKey {
+    pub key: String,
+    pub throttled: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct LimiterReport {
+    pub limit: Limit,
+    pub tracked_keys: usize,
+    /// Requests refused since startup.
+    pub throttled: u64,
+    pub top: Vec<ThrottledKey>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RateLimitReport {
+    pub ip: Option<LimiterReport>,
+    pub endpoint: Option<LimiterReport>,
+}
+
+/// `GET /api/admin/rate-limits[?limit=N]`: this instance's throttle counts
+/// and its `N` (10) most throttled IPs and endpoints.
+pub async fn get_rate_limits(Query(params): Query<HashMap<String, String>>) -> Json<RateLimitReport> {
+    let limit = params.get("limit").and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_LIMIT);
+    let limiters = &*LIMITERS;
+    Json(RateLimitReport {
+        ip: limiters.ip.as_ref().map(|limiter| limiter.report(limit)),
+        endpoint: limiters.endpoint.as_ref().map(|limiter| limiter.report(limit)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarded_ip_counts_trusted_hops_from_the_right() {
+        let header = "6.6.6.6, 1.2.3.4, 10.0.0.1";
+        assert_eq!(forwarded_ip(header, 0), None);
+        assert_eq!(forwarded_ip(header, 1), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(forwarded_ip(header, 2), Some("1.2.3.4".parse().unwrap()));
+        assert_eq!(forwarded_ip(header, 4), None);
+        assert_eq!(forwarded_ip("not-an-ip", 1), None);
+    }
+}
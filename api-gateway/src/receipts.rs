@@ -0,0 +1,175 @@
+//! Signed settlement receipts. Once a transaction is confirmed or failed
+//! the gateway signs its hash, status and the time of issue with its
+//! service key, so the outcome can be proven later without asking the
+//! gateway again. Once a projection snapshot's checkpoint covers the
+//! transaction, the receipt also carries a proof of its inclusion.
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::Utc;
+use scylla::Session;
+use serde::Serialize;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::{load_transaction, snapshots, timestamp_from_millis, AppState, Transaction};
+use tx_core::{transaction_hash, verify_receipt, KeySet, Receipt};
+
+fn sign(state: &AppState, receipt: &mut Receipt) {
+    let (public_key, signature) = state.keys.sign(&receipt.signing_bytes());
+    receipt.public_key = public_key;
+    receipt.signature = signature;
+}
+
+fn db_error(e: impl std::fmt::Display) -> StatusCode {
+    error!("Receipt query failed: {}", e);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+async fn load_receipt(session: &Session, tx_id: Uuid) -> Result<Option<Receipt>, StatusCode> {
+    let row = session
+        .query(
+            db::idempotent("SELECT transaction_hash, status, issued_at, signature, public_key, inclusion
+             FROM transactions.receipts WHERE tx_id = ?"),
+            (tx_id,),
+        )
+        .await
+        .map_err(db_error)?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(String, String, i64, String, String, Option<String>)>().ok());
+
+    Ok(row.and_then(|(transaction_hash, status, issued_at, signature, public_key, inclusion)| {
+        Some(Receipt {
+            transaction_id: tx_id.to_string(),
+            transaction_hash,
+            status: status.parse().ok()?,
+            issued_at: timestamp_from_millis(issued_at),
+            signature,
+            public_key,
+            inclusion: inclusion.and_then(|json| serde_json::from_str(&json).ok()),
+        })
+    }))
+}
+
+/// `GET /api/transactions/:id/receipt`
+///
+/// 409 while the transaction is still pending or held for review. A
+/// receipt is reissued if the transaction's status has changed since, the
+/// receipt key has been rotated, or a checkpoint has come to cover it.
+pub async fn get_receipt(
+    State(state): State<AppState>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<Receipt>, StatusCode> {
+    let tx_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let tx = load_transaction(&state.session, tx_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    if !tx.status.is_final() {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let stored = load_receipt(&state.session, tx_id)
+        .await?
+        .filter(|receipt| receipt.matches(&tx) && receipt.public_key == state.keys.public_key());
+    if let Some(receipt) = stored.as_ref().filter(|receipt| receipt.inclusion.is_some()) {
+        return Ok(Json(receipt.clone()));
+    }
+
+    let inclusion = snapshots::inclusion_proof(&state.session, &tx).await.unwrap_or_else(|e| {
+        warn!("No inclusion proof for {}: {}", tx.id, e);
+        None
+    });
+    if let (Some(receipt), None) = (stored, &inclusion) {
+        return Ok(Json(receipt));
+    }
+
+    let mut receipt = Receipt {
+        transaction_id: tx.id.clone(),
+        transaction_hash: transaction_hash(&tx),
+        status: tx.status,
+        // Millisecond precision, as signed
+        issued_at: timestamp_from_millis(Utc::now().timestamp_millis()),
+        signature: String::new(),
+        public_key: String::new(),
+        inclusion,
+    };
+    sign(&state, &mut receipt);
+
+    state
+        .session
+        .query(
+            "INSERT INTO transactions.receipts (tx_id, transaction_hash, status, issued_at, signature, public_key, inclusion)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            (
+                tx_id,
+                &receipt.transaction_hash,
+                receipt.status.as_str(),
+                receipt.issued_at.timestamp_millis(),
+                &receipt.signature,
+                &receipt.public_key,
+                receipt
+                    .inclusion
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()
+                    .map_err(db_error)?,
+            ),
+        )
+        .await
+        .map_err(db_error)?;
+
+    Ok(Json(receipt))
+}
+
+#[derive(Serialize)]
+pub struct ReceiptKey {
+    pub public_key: String,
+}
+
+/// `GET /api/receipts/key`: the key receipts are signed with now. Prefer
+/// `/api/.well-known/keys`, which also lists the keys around a rotation.
+pub async fn get_receipt_key(State(state): State<AppState>) -> Json<ReceiptKey> {
+    Json(ReceiptKey { public_key: state.keys.public_key() })
+}
+
+/// `api-gateway verify-receipt <receipt.json> [<history.json>]`: checks a
+/// receipt's signature, its key against the key set saved at `GATEWAY_KEYS`
+/// (or the single key in `RECEIPT_PUBLIC_KEY`), its inclusion
+/// proof, and its hash against the transaction in `history.json` (one
+/// transaction or a list, e.g. saved from `/api/transactions`). Needs no
+/// database or network; fails unless every check that could run passed.
+pub fn verify_offline(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.first().ok_or("Usage: api-gateway verify-receipt <receipt.json> [<history.json>]")?;
+    let receipt: Receipt = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+    let history: Vec<Transaction> = match args.get(1) {
+        Some(history_path) => {
+            let json = std::fs::read_to_string(history_path)?;
+            serde_json::from_str(&json).or_else(|_| serde_json::from_str(&json).map(|tx| vec![tx]))?
+        }
+        None => Vec::new(),
+    };
+    let trusted_keys = match std::env::var("GATEWAY_KEYS") {
+        Ok(keys_path) => Some(serde_json::from_str::<KeySet>(&std::fs::read_to_string(keys_path)?)?),
+        Err(_) => match std::env::var("RECEIPT_PUBLIC_KEY") {
+            Ok(public_key) => Some(KeySet::single(&public_key).ok_or("RECEIPT_PUBLIC_KEY must be 32 bytes of hex")?),
+            Err(_) => None,
+        },
+    };
+
+    let report = verify_receipt(&receipt, trusted_keys.as_ref(), &history);
+    println!(
+        "Receipt for transaction {} ({} as of {})",
+        receipt.transaction_id,
+        receipt.status.as_str(),
+        receipt.issued_at.to_rfc3339()
+    );
+    println!("  signature:   {}", report.signature);
+    println!("  gateway key: {}", report.key);
+    println!("  inclusion:   {}", report.inclusion);
+    println!("  transaction: {}", report.transaction);
+
+    if !report.is_valid() {
+        return Err("Receipt did not verify".into());
+    }
+    Ok(())
+}
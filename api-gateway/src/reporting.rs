@@ -0,0 +1,618 @@
+//! Threshold reports for money-transfer regulators. `REPORTING_RULES_FILE`
+//! names a JSON array of rules, each reporting one jurisdiction's
+//! threshold over a period:
+//!
+//! ```json
+//! [{ "id": "us-ctr", "jurisdiction": "US", "threshold": 10000, "period": "daily", "basis": "aggregate" }]
+//! ```
+//!
+//! With `basis` `single` (the default), a party is reported for each
+//! transaction of at least `threshold`. With `aggregate`, a party is
+//! reported when what it sent, or what it received, over the period adds
+//! up to `threshold`. Periods are UTC days, ISO weeks or calendar months.
+//! Transfers, deposits and withdrawals count unless they failed or
+//! expired; system accounts are never reported.
+//!
+//! Once a period closes the leader generates its report for every rule,
+//! checking every `REPORTING_INTERVAL_SECS` (600). Reports are stored as
+//! generated and never replaced. Earlier periods, e.g. for a rule added
+//! later, are generated with `POST /api/admin/reports`. `GET
+//! /api/admin/reports/:rule/:period` serves a report as JSON, CSV
+//! (`?format=csv`) or XML (`?format=xml`).
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
+    Extension,
+};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, SecondsFormat, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use std::collections::{btree_map::Entry, BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use tx_core::{Money, TransactionKind, TransactionStatus, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::auth::{self, Claims};
+use crate::db;
+use crate::pagination::{Cursor, Page, MAX_LIMIT};
+use crate::repository::Repository;
+use crate::statements::{decimal, escape};
+use crate::{audit, is_system_account, lwt_applied, timeline, timestamp_from_millis, AppState, Transaction};
+
+const DEFAULT_INTERVAL_SECS: u64 = 600;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "lowercase")]
+pub enum ReportingPeriod {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl ReportingPeriod {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportingPeriod::Daily => "daily",
+            ReportingPeriod::Weekly => "weekly",
+            ReportingPeriod::Monthly => "monthly",
+        }
+    }
+
+    /// Start of the period `date` falls in.
+    fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            ReportingPeriod::Daily => date,
+            ReportingPeriod::Weekly => date - Duration::days(date.weekday().num_days_from_monday() as i64),
+            ReportingPeriod::Monthly => date.with_day(1).unwrap_or(date),
+        }
+    }
+
+    fn end_of(&self, start: NaiveDate) -> NaiveDate {
+        match self {
+            ReportingPeriod::Daily => start + Duration::days(1),
+            ReportingPeriod::Weekly => start + Duration::days(7),
+            ReportingPeriod::Monthly => start.checked_add_months(Months::new(1)).unwrap_or(start),
+        }
+    }
+
+    /// Start of the last period that has closed by `now`.
+    fn last_closed(&self, now: DateTime<Utc>) -> NaiveDate {
+        let current = self.start_of(now.date_naive());
+        self.start_of(current - Duration::days(1))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "lowercase")]
+pub enum ReportingBasis {
+    #[default]
+    Single,
+    Aggregate,
+}
+
+impl ReportingBasis {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportingBasis::Single => "single",
+            ReportingBasis::Aggregate => "aggregate",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ReportingRule {
+    pub id: String,
+    pub jurisdiction: String,
+    #[serde(with = "tx_core::money::as_major")]
+    pub threshold: Money,
+    pub period: ReportingPeriod,
+    #[serde(default)]
+    pub basis: ReportingBasis,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+#[serde(rename_all = "lowercase")]
+pub enum ReportDirection {
+    Sent,
+    Received,
+}
+
+impl ReportDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReportDirection::Sent => "sent",
+            ReportDirection::Received => "received",
+        }
+    }
+}
+
+impl fmt::Display for ReportDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ReportDirection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sent" => Ok(ReportDirection::Sent),
+            "received" => Ok(ReportDirection::Received),
+            other => Err(format!("Unknown direction: {}", other)),
+        }
+    }
+}
+
+/// One party reported for one direction: a single transaction, or the
+/// period's total under `aggregate`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ReportEntry {
+    pub endpoint_id: String,
+    pub direction: ReportDirection,
+    #[serde(with = "tx_core::money::as_major")]
+    pub total: Money,
+    pub transaction_ids: Vec<String>,
+    pub first_at: DateTime<Utc>,
+    pub last_at: DateTime<Utc>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct RegulatoryReport {
+    pub report_id: String,
+    pub rule: ReportingRule,
+    pub period_start: NaiveDate,
+    /// Exclusive.
+    pub period_end: NaiveDate,
+    pub generated_at: DateTime<Utc>,
+    pub generated_by: String,
+    pub entries: Vec<ReportEntry>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ReportSummary {
+    pub report_id: String,
+    pub rule_id: String,
+    pub jurisdiction: String,
+    pub period_start: NaiveDate,
+    pub period_end: NaiveDate,
+    pub generated_at: DateTime<Utc>,
+    pub entry_count: i32,
+}
+
+/// The configured rules, by id.
+#[derive(Clone, Default)]
+pub struct ReportingRules {
+    rules: Arc<BTreeMap<String, ReportingRule>>,
+}
+
+impl ReportingRules {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let Ok(path) = std::env::var("REPORTING_RULES_FILE") else {
+            return Ok(ReportingRules::default());
+        };
+        let path = PathBuf::from(path);
+        let text = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let list: Vec<ReportingRule> =
+            serde_json::from_str(&text).map_err(|e| format!("Invalid reporting rules in {}: {}", path.display(), e))?;
+
+        let mut rules = BTreeMap::new();
+        for rule in list {
+            if rule.id.trim().is_empty() || rule.threshold.minor() <= 0 {
+                return Err(format!("Reporting rule {:?} needs an id and a positive threshold", rule.id).into());
+            }
+            info!(
+                "Reporting rule {}: {} {} {}, {}",
+                rule.id,
+                rule.jurisdiction,
+                rule.period.as_str(),
+                decimal(rule.threshold.minor(), 2),
+                rule.basis.as_str()
+            );
+            if rules.insert(rule.id.clone(), rule).is_some() {
+                return Err("Reporting rule ids must be unique".into());
+            }
+        }
+        Ok(ReportingRules { rules: Arc::new(rules) })
+    }
+
+    fn get(&self, id: &str) -> Option<&ReportingRule> {
+        self.rules.get(id)
+    }
+}
+
+fn db_error<E: fmt::Display>(rule_id: &str) -> impl Fn(E) -> StatusCode + '_ {
+    move |e| {
+        error!("Regulatory report query for {} failed: {}", rule_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+/// Every transaction timestamped in `[start, end)`, walking the timeline
+/// back from `end`.
+async fn transactions_between(
+    session: &Repository,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<Transaction>, String> {
+    let mut page = Page::Before(Cursor {
+        timestamp: end.timestamp_millis(),
+        id: Uuid::nil(),
+    });
+    let mut transactions = Vec::new();
+    loop {
+        let ids = timeline::page(session, None, &page, MAX_LIMIT).await?;
+        let batch = crate::load_transactions(session, &ids).await.map_err(|status| status.to_string())?;
+        let Some(oldest) = batch.iter().filter_map(Cursor::of).min() else {
+            return Ok(transactions);
+        };
+        let done = ids.len() < MAX_LIMIT as usize || batch.iter().any(|tx| tx.timestamp < start);
+        transactions.extend(batch.into_iter().filter(|tx| tx.timestamp >= start && tx.timestamp < end));
+        if done {
+            return Ok(transactions);
+        }
+        page = Page::Before(oldest);
+    }
+}
+
+fn reportable(transaction: &Transaction) -> bool {
+    matches!(
+        transaction.kind,
+        TransactionKind::Transfer | TransactionKind::Deposit | TransactionKind::Withdrawal
+    ) && !matches!(transaction.status, TransactionStatus::Failed | TransactionStatus::Expired)
+}
+
+/// The rule's entries for `transactions`, by party and direction.
+fn entries(rule: &ReportingRule, transactions: &[Transaction]) -> Vec<ReportEntry> {
+    let threshold = rule.threshold.minor();
+    let mut singles = Vec::new();
+    let mut totals: BTreeMap<(String, ReportDirection), ReportEntry> = BTreeMap::new();
+    for transaction in transactions.iter().filter(|tx| reportable(tx)) {
+        let amount = &transaction.amount;
+        let sides = [
+            (&transaction.from_endpoint, ReportDirection::Sent),
+            (&transaction.to_endpoint, ReportDirection::Received),
+        ];
+        for (endpoint_id, direction) in sides {
+            if is_system_account(endpoint_id) {
+                continue;
+            }
+            let entry = ReportEntry {
+                endpoint_id: endpoint_id.clone(),
+                direction,
+                total: Money::new(amount.minor(), NATIVE_ASSET),
+                transaction_ids: vec![transaction.id.clone()],
+                first_at: transaction.timestamp,
+                last_at: transaction.timestamp,
+            };
+            match rule.basis {
+                ReportingBasis::Single if amount.minor() >= threshold => singles.push(entry),
+                ReportingBasis::Single => {}
+                ReportingBasis::Aggregate => {
+                    let total = match totals.entry((endpoint_id.clone(), direction)) {
+                        Entry::Vacant(vacant) => {
+                            vacant.insert(entry);
+                            continue;
+                        }
+                        Entry::Occupied(occupied) => occupied.into_mut(),
+                    };
+                    total.total = Money::new(total.total.minor().saturating_add(amount.minor()), NATIVE_ASSET);
+                    total.transaction_ids.push(transaction.id.clone());
+                    total.first_at = total.first_at.min(transaction.timestamp);
+                    total.last_at = total.last_at.max(transaction.timestamp);
+                }
+            }
+        }
+    }
+
+    let mut entries: Vec<ReportEntry> = match rule.basis {
+        ReportingBasis::Single => singles,
+        ReportingBasis::Aggregate => totals.into_values().filter(|entry| entry.total.minor() >= threshold).collect(),
+    };
+    entries.sort_by(|a, b| (&a.endpoint_id, a.direction, a.first_at).cmp(&(&b.endpoint_id, b.direction, b.first_at)));
+    entries
+}
+
+/// Generates and stores the rule's report for the period starting at
+/// `period_start`, unless one is stored already (409).
+async fn generate(
+    session: &Repository,
+    rule: &ReportingRule,
+    period_start: NaiveDate,
+    generated_by: &str,
+) -> Result<RegulatoryReport, StatusCode> {
+    let period_end = rule.period.end_of(period_start);
+    let transactions = transactions_between(session, midnight(period_start), midnight(period_end))
+        .await
+        .map_err(db_error(&rule.id))?;
+    let report_id = Uuid::new_v4();
+    let report = RegulatoryReport {
+        report_id: report_id.to_string(),
+        rule: rule.clone(),
+        period_start,
+        period_end,
+        generated_at: Utc::now(),
+        generated_by: generated_by.to_string(),
+        entries: entries(rule, &transactions),
+    };
+
+    let body = serde_json::to_string(&report).map_err(db_error(&rule.id))?;
+    let stored = session
+        .query(
+            "INSERT INTO transactions.regulatory_reports \
+             (rule_id, period_start, report_id, generated_at, generated_by, entry_count, report) \
+             VALUES (?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            (
+                &rule.id,
+                period_start.to_string(),
+                report_id,
+                report.generated_at.timestamp_millis(),
+                generated_by,
+                report.entries.len() as i32,
+                body,
+            ),
+        )
+        .await
+        .map_err(db_error(&rule.id))?;
+    if !lwt_applied(stored) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    let details = format!("{} ({} entries)", period_start, report.entries.len());
+    audit::record(session, &rule.id, "regulatory_report.generated", generated_by, Some(details)).await?;
+    info!("Generated {} report for {}: {} entries", rule.id, period_start, report.entries.len());
+    Ok(report)
+}
+
+/// Background task generating each rule's report once its period closes,
+/// on the leader.
+pub async fn run_reporting(state: AppState) {
+    if state.reporting.rules.is_empty() {
+        return;
+    }
+    let interval = std::time::Duration::from_secs(
+        std::env::var("REPORTING_INTERVAL_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(DEFAULT_INTERVAL_SECS),
+    );
+    loop {
+        tokio::time::sleep(interval).await;
+        if !state.leadership.is_leader() {
+            continue;
+        }
+        for rule in state.reporting.rules.values() {
+            let period_start = rule.period.last_closed(Utc::now());
+            if !matches!(is_stored(&state.session, &rule.id, period_start).await, Ok(false)) {
+                continue;
+            }
+            match generate(&state.session, rule, period_start, "reporting").await {
+                Ok(_) | Err(StatusCode::CONFLICT) => {}
+                Err(status) => warn!("Generating the {} report for {} failed: {}", rule.id, period_start, status),
+            }
+        }
+    }
+}
+
+type SummaryRow = (String, Option<Uuid>, Option<i64>, Option<i32>);
+
+fn summary_from_row(rule: &ReportingRule, row: SummaryRow) -> Option<ReportSummary> {
+    let (period_start, report_id, generated_at, entry_count) = row;
+    let period_start = NaiveDate::from_str(&period_start).ok()?;
+    Some(ReportSummary {
+        report_id: report_id.map(|id| id.to_string()).unwrap_or_default(),
+        rule_id: rule.id.clone(),
+        jurisdiction: rule.jurisdiction.clone(),
+        period_start,
+        period_end: rule.period.end_of(period_start),
+        generated_at: timestamp_from_millis(generated_at.unwrap_or_default()),
+        entry_count: entry_count.unwrap_or_default(),
+    })
+}
+
+async fn is_stored(session: &Session, rule_id: &str, period_start: NaiveDate) -> Result<bool, StatusCode> {
+    let result = session
+        .query(
+            db::idempotent(
+                "SELECT report_id FROM transactions.regulatory_reports WHERE rule_id = ? AND period_start = ?",
+            ),
+            (rule_id, period_start.to_string()),
+        )
+        .await
+        .map_err(db_error(rule_id))?;
+    Ok(result.rows.is_some_and(|rows| !rows.is_empty()))
+}
+
+/// `GET /api/admin/reporting-rules`
+pub async fn get_rules(State(state): State<AppState>) -> Json<Vec<ReportingRule>> {
+    Json(state.reporting.rules.values().cloned().collect())
+}
+
+/// `GET /api/admin/reports[?rule=]`: the stored reports, newest period
+/// first per rule.
+pub async fn list_reports(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<ReportSummary>>, StatusCode> {
+    let rules: Vec<&ReportingRule> = match params.get("rule") {
+        Some(id) => vec![state.reporting.get(id).ok_or(StatusCode::NOT_FOUND)?],
+        None => state.reporting.rules.values().collect(),
+    };
+
+    let mut summaries = Vec::new();
+    for rule in rules {
+        let result = state
+            .session
+            .query(
+                db::idempotent(
+                    "SELECT period_start, report_id, generated_at, entry_count FROM transactions.regulatory_reports \
+                     WHERE rule_id = ?",
+                ),
+                (&rule.id,),
+            )
+            .await
+            .map_err(db_error(&rule.id))?;
+        summaries.extend(
+            result
+                .rows
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|row| row.into_typed::<SummaryRow>().ok())
+                .filter_map(|row| summary_from_row(rule, row)),
+        );
+    }
+    Ok(Json(summaries))
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct GenerateRequest {
+    pub rule: String,
+    pub period_start: NaiveDate,
+}
+
+fn period_start(rule: &ReportingRule, date: NaiveDate) -> Result<NaiveDate, StatusCode> {
+    // Periods that haven't closed would be reported incomplete
+    if rule.period.start_of(date) != date || midnight(rule.period.end_of(date)) > Utc::now() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    Ok(date)
+}
+
+/// `POST /api/admin/reports`: generates a closed period's report that
+/// isn't stored yet.
+pub async fn generate_report(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    claims: Option<Extension<Claims>>,
+    Json(request): Json<GenerateRequest>,
+) -> Result<(StatusCode, Json<RegulatoryReport>), StatusCode> {
+    let rule = state.reporting.get(&request.rule).ok_or(StatusCode::NOT_FOUND)?;
+    let period_start = period_start(rule, request.period_start)?;
+    let admin = auth::actor(claims.as_deref(), &headers, "x-admin")?;
+    let report = generate(&state.session, rule, period_start, &admin).await?;
+    Ok((StatusCode::CREATED, Json(report)))
+}
+
+/// `GET /api/admin/reports/:rule/:period_start[?format=json|csv|xml]`
+pub async fn get_report(
+    State(state): State<AppState>,
+    Path((rule_id, period_start)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    let period_start = NaiveDate::from_str(&period_start).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let body = state
+        .session
+        .query(
+            db::idempotent("SELECT report FROM transactions.regulatory_reports WHERE rule_id = ? AND period_start = ?"),
+            (&rule_id, period_start.to_string()),
+        )
+        .await
+        .map_err(db_error(&rule_id))?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .ok_or(StatusCode::NOT_FOUND)?
+        .into_typed::<(String,)>()
+        .map_err(db_error(&rule_id))?
+        .0;
+    let report: RegulatoryReport = serde_json::from_str(&body).map_err(db_error(&rule_id))?;
+
+    match params.get("format").map(String::as_str) {
+        None | Some("json") => Ok(Json(report).into_response()),
+        Some("csv") => Ok(([(header::CONTENT_TYPE, "text/csv")], csv(&report)).into_response()),
+        Some("xml") => Ok(([(header::CONTENT_TYPE, "application/xml")], xml(&report)).into_response()),
+        Some(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+pub fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// One row per entry; a total's transaction ids are joined with `;`.
+fn csv(report: &RegulatoryReport) -> String {
+    let mut out = String::from(concat!(
+        "jurisdiction,rule,period_start,period_end,endpoint_id,direction,",
+        "total,currency,transaction_count,first_at,last_at,transaction_ids\r\n",
+    ));
+    for entry in &report.entries {
+        let fields = [
+            csv_field(&report.rule.jurisdiction),
+            csv_field(&report.rule.id),
+            report.period_start.to_string(),
+            report.period_end.to_string(),
+            csv_field(&entry.endpoint_id),
+            entry.direction.to_string(),
+            decimal(entry.total.minor(), 6),
+            entry.total.currency().to_string(),
+            entry.transaction_ids.len().to_string(),
+            timestamp(entry.first_at),
+            timestamp(entry.last_at),
+            csv_field(&entry.transaction_ids.join(";")),
+        ];
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+fn xml(report: &RegulatoryReport) -> String {
+    let rule = &report.rule;
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<ThresholdReport id=\"{id}\" generated=\"{generated}\">\n\
+         <Rule id=\"{rule}\" jurisdiction=\"{jurisdiction}\" period=\"{period}\" basis=\"{basis}\">\
+         <Threshold currency=\"{currency}\">{threshold}</Threshold></Rule>\n\
+         <Period start=\"{start}\" end=\"{end}\"/>\n<Entries count=\"{count}\">\n",
+        id = escape(&report.report_id),
+        generated = timestamp(report.generated_at),
+        rule = escape(&rule.id),
+        jurisdiction = escape(&rule.jurisdiction),
+        period = rule.period.as_str(),
+        basis = rule.basis.as_str(),
+        currency = escape(rule.threshold.currency()),
+        threshold = decimal(rule.threshold.minor(), 6),
+        start = report.period_start,
+        end = report.period_end,
+        count = report.entries.len(),
+    ));
+    for entry in &report.entries {
+        out.push_str(&format!(
+            "<Entry endpoint=\"{endpoint}\" direction=\"{direction}\">\
+             <Total currency=\"{currency}\">{total}</Total><First>{first}</First><Last>{last}</Last>\n",
+            endpoint = escape(&entry.endpoint_id),
+            direction = entry.direction,
+            currency = escape(entry.total.currency()),
+            total = decimal(entry.total.minor(), 6),
+            first = timestamp(entry.first_at),
+            last = timestamp(entry.last_at),
+        ));
+        for id in &entry.transaction_ids {
+            out.push_str(&format!("<Transaction id=\"{}\"/>\n", escape(id)));
+        }
+        out.push_str("</Entry>\n");
+    }
+    out.push_str("</Entries>\n</ThresholdReport>\n");
+    out
+}
@@ -0,0 +1,230 @@
+//! Prepared statements for the request path. The catalog below is
+//! prepared once at startup, so a statement that no longer matches the
+//! schema fails the deploy instead of the first request, and Scylla
+//! parses each statement once instead of on every query. Values are
+//! always bound, never spliced into the CQL.
+//!
+//! Every execution is timed per statement: `GET /api/admin/statements`
+//! reports counts, errors and latency since startup, and executions slower
+//! than `SCYLLA_SLOW_STATEMENT_MS` (500) are logged.
+//!
+//! `Repository` derefs to the session, for statements not in the catalog
+//! yet: schema setup, LWTs and the less travelled paths.
+
+use axum::{extract::State, response::Json};
+use scylla::prepared_statement::PreparedStatement;
+use scylla::serialize::row::SerializeRow;
+use scylla::transport::errors::QueryError;
+use scylla::{QueryResult, Session};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::endpoints::ENDPOINT_COLUMNS;
+use crate::{db, AppState, TX_COLUMNS};
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Upper bounds of the latency buckets, in milliseconds; slower
+/// executions land in a last, open bucket.
+const BUCKETS_MS: [u64; 12] = [1, 2, 5, 10, 20, 50, 100, 200, 500, 1000, 2000, 5000];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Statement {
+    TransactionById,
+    TransactionsByIds,
+    /// Every endpoint's totals and share of the overall count and volume,
+    /// for `/api/stats`.
+    EndpointTotals,
+    EndpointById,
+    LedgerTotals,
+    LedgerTotalsAsOf,
+}
+
+impl Statement {
+    pub const ALL: [Statement; 6] = [
+        Statement::TransactionById,
+        Statement::TransactionsByIds,
+        Statement::EndpointTotals,
+        Statement::EndpointById,
+        Statement::LedgerTotals,
+        Statement::LedgerTotalsAsOf,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Statement::TransactionById => "transaction_by_id",
+            Statement::TransactionsByIds => "transactions_by_ids",
+            Statement::EndpointTotals => "endpoint_totals",
+            Statement::EndpointById => "endpoint_by_id",
+            Statement::LedgerTotals => "ledger_totals",
+            Statement::LedgerTotalsAsOf => "ledger_totals_as_of",
+        }
+    }
+
+    fn cql(&self) -> String {
+        const LEDGER_TOTALS: &str = "SELECT SUM(count_delta), SUM(sent_minor), SUM(received_minor), SUM(balance_minor)
+             FROM transactions.endpoint_ledger WHERE endpoint_id = ?";
+        match self {
+            Statement::TransactionById => format!("SELECT {} FROM transactions.tx_log WHERE id = ?", TX_COLUMNS),
+            Statement::TransactionsByIds => format!("SELECT {} FROM transactions.tx_log WHERE id IN ?", TX_COLUMNS),
+            Statement::EndpointTotals => "SELECT DISTINCT endpoint_id, total_count, total_sent_minor,
+                    total_received_minor, total_balance_minor, stats_count, stats_volume_minor
+             FROM transactions.endpoint_ledger"
+                .to_string(),
+            Statement::EndpointById => format!("SELECT {} FROM transactions.endpoints WHERE id = ?", ENDPOINT_COLUMNS),
+            Statement::LedgerTotals => LEDGER_TOTALS.to_string(),
+            Statement::LedgerTotalsAsOf => format!("{} AND at <= ?", LEDGER_TOTALS),
+        }
+    }
+
+    /// Reads of a whole table, with `db::scan`'s longer timeout.
+    fn is_scan(&self) -> bool {
+        matches!(self, Statement::EndpointTotals)
+    }
+}
+
+#[derive(Default)]
+struct Latency {
+    executions: u64,
+    errors: u64,
+    total: Duration,
+    max: Duration,
+    buckets: [u64; BUCKETS_MS.len() + 1],
+}
+
+impl Latency {
+    fn record(&mut self, elapsed: Duration, failed: bool) {
+        self.executions += 1;
+        if failed {
+            self.errors += 1;
+        }
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        let ms = elapsed.as_millis() as u64;
+        let bucket = BUCKETS_MS.iter().position(|bound| ms < *bound).unwrap_or(BUCKETS_MS.len());
+        self.buckets[bucket] += 1;
+    }
+
+    /// The bucket bound below which `quantile` of executions finished;
+    /// the maximum for the open bucket.
+    fn quantile_ms(&self, quantile: f64) -> f64 {
+        let rank = (self.executions as f64 * quantile).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return match BUCKETS_MS.get(i) {
+                    Some(bound) => *bound as f64,
+                    None => millis(self.max),
+                };
+            }
+        }
+        millis(self.max)
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The session, with the catalog prepared on it.
+#[derive(Clone)]
+pub struct Repository {
+    session: Arc<Session>,
+    statements: Arc<HashMap<Statement, PreparedStatement>>,
+    latency: Arc<Mutex<HashMap<Statement, Latency>>>,
+    slow: Duration,
+}
+
+impl Repository {
+    /// Prepares the catalog; the schema has to be in place.
+    pub async fn prepare(session: Session) -> Result<Self, QueryError> {
+        let mut statements = HashMap::new();
+        for statement in Statement::ALL {
+            let mut prepared = session.prepare(statement.cql()).await?;
+            // Everything in the catalog is a read
+            prepared.set_is_idempotent(true);
+            if statement.is_scan() {
+                prepared.set_request_timeout(Some(db::SCAN_TIMEOUT));
+            }
+            statements.insert(statement, prepared);
+        }
+        info!("Prepared {} statements", statements.len());
+        Ok(Repository {
+            session: Arc::new(session),
+            statements: Arc::new(statements),
+            latency: Arc::default(),
+            slow: Duration::from_millis(env_u64("SCYLLA_SLOW_STATEMENT_MS", 500)),
+        })
+    }
+
+    pub async fn execute(&self, statement: Statement, values: impl SerializeRow) -> Result<QueryResult, QueryError> {
+        let prepared = &self.statements[&statement];
+        let started = Instant::now();
+        let result = self.session.execute(prepared, values).await;
+        let elapsed = started.elapsed();
+
+        if elapsed >= self.slow {
+            warn!(statement = statement.name(), elapsed_ms = millis(elapsed), "Slow statement");
+        }
+        self.latency
+            .lock()
+            .unwrap()
+            .entry(statement)
+            .or_default()
+            .record(elapsed, result.is_err());
+        result
+    }
+
+    pub fn latency(&self) -> Vec<StatementLatency> {
+        let latency = self.latency.lock().unwrap();
+        Statement::ALL
+            .into_iter()
+            .map(|statement| {
+                let stats = latency.get(&statement);
+                let executions = stats.map_or(0, |s| s.executions);
+                StatementLatency {
+                    statement: statement.name(),
+                    executions,
+                    errors: stats.map_or(0, |s| s.errors),
+                    mean_ms: stats.filter(|_| executions > 0).map_or(0.0, |s| millis(s.total) / executions as f64),
+                    p50_ms: stats.filter(|_| executions > 0).map_or(0.0, |s| s.quantile_ms(0.5)),
+                    p99_ms: stats.filter(|_| executions > 0).map_or(0.0, |s| s.quantile_ms(0.99)),
+                    max_ms: stats.map_or(0.0, |s| millis(s.max)),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Deref for Repository {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.session
+    }
+}
+
+/// Percentiles are the upper bound of the bucket they fall in.
+#[derive(Serialize)]
+pub struct StatementLatency {
+    pub statement: &'static str,
+    pub executions: u64,
+    pub errors: u64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+/// `GET /api/admin/statements`: latency of each prepared statement on
+/// this instance since it started.
+pub async fn get_statements(State(state): State<AppState>) -> Json<Vec<StatementLatency>> {
+    Json(state.session.latency())
+}
@@ -0,0 +1,114 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+    Extension,
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::audit;
+use crate::auth::{self, Claims};
+use crate::fields::{self, Projection, TransactionList};
+use crate::{
+    load_transaction, update_transaction_status, transaction_from_row, AppState, Transaction,
+    TransactionStatus, TxRow, TX_COLUMNS,
+};
+
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct ReviewDecision {
+    pub note: Option<String>,
+}
+
+/// Held transactions awaiting a reviewer, oldest first. Takes `fields`
+/// like `GET /api/transactions`.
+pub async fn get_review_queue(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<TransactionList>, StatusCode> {
+    if let Some(projection) = Projection::from_params(&params)? {
+        let queue = fields::load_with_status(&state.session, &projection, TransactionStatus::Held)
+            .await
+            .map_err(|e| {
+                error!("Database query error: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+        return Ok(Json(TransactionList::Sparse(queue)));
+    }
+
+    let rows = state
+        .session
+        .query(
+            db::idempotent(format!("SELECT {} FROM transactions.tx_log WHERE status = ? ALLOW FILTERING", TX_COLUMNS)),
+            (TransactionStatus::Held.as_str(),),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let mut queue = Vec::new();
+    if let Some(rows) = rows.rows {
+        for row in rows {
+            if let Ok(row) = row.into_typed::<TxRow>() {
+                match transaction_from_row(row) {
+                    Ok(tx) => queue.push(tx),
+                    Err(e) => warn!("Skipping transaction: {}", e),
+                }
+            }
+        }
+    }
+
+    queue.sort_by_key(|transaction| transaction.timestamp);
+    Ok(Json(TransactionList::Full(queue)))
+}
+
+pub async fn approve_transaction(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+    decision: Option<Json<ReviewDecision>>,
+) -> Result<Json<Transaction>, StatusCode> {
+    let reviewer = auth::actor(claims.as_deref(), &headers, "x-reviewer")?;
+    decide(&state, &id, reviewer, decision, TransactionStatus::Confirmed).await
+}
+
+pub async fn reject_transaction(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    claims: Option<Extension<Claims>>,
+    headers: HeaderMap,
+    decision: Option<Json<ReviewDecision>>,
+) -> Result<Json<Transaction>, StatusCode> {
+    let reviewer = auth::actor(claims.as_deref(), &headers, "x-reviewer")?;
+    decide(&state, &id, reviewer, decision, TransactionStatus::Failed).await
+}
+
+async fn decide(
+    state: &AppState,
+    id: &str,
+    reviewer: String,
+    decision: Option<Json<ReviewDecision>>,
+    outcome: TransactionStatus,
+) -> Result<Json<Transaction>, StatusCode> {
+    let tx_id = Uuid::parse_str(id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut transaction = load_transaction(&state.session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if transaction.status != TransactionStatus::Held {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    update_transaction_status(&state.session, id, outcome).await?;
+    transaction.status = outcome;
+
+    let action = if outcome == TransactionStatus::Confirmed { "review.approved" } else { "review.rejected" };
+    let note = decision.and_then(|Json(d)| d.note);
+    audit::record(&state.session, id, action, &reviewer, note).await?;
+
+    info!("Transaction {} {} by {}", id, outcome, reviewer);
+    Ok(Json(transaction))
+}
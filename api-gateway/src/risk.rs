@@ -0,0 +1,95 @@
+use chrono::{Duration, Utc};
+use scylla::Session;
+use serde::Serialize;
+use tracing::debug;
+
+use crate::db;
+use crate::endpoints;
+use crate::repository::Repository;
+use crate::sends;
+use crate::Transaction;
+
+const DEFAULT_HOLD_THRESHOLD: u8 = 70;
+
+/// Score at or above which receivers should hold a payment for manual accept.
+/// Configurable via `RISK_HOLD_THRESHOLD`.
+pub fn hold_threshold() -> u8 {
+    std::env::var("RISK_HOLD_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_HOLD_THRESHOLD)
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct RiskAssessment {
+    pub score: u8,
+    pub reasons: Vec<&'static str>,
+}
+
+/// Rules engine run on ingest. Each rule adds to a 0-100 score.
+pub async fn assess(session: &Repository, tx: &Transaction) -> RiskAssessment {
+    let mut score: u32 = 0;
+    let mut reasons = Vec::new();
+
+    let amount = tx.amount.to_major();
+    if amount >= 10_000.0 {
+        score += 40;
+        reasons.push("very large amount");
+    } else if amount >= 1_000.0 {
+        score += 20;
+        reasons.push("large amount");
+    } else if amount >= 500.0 {
+        score += 10;
+        reasons.push("elevated amount");
+    }
+
+    if amount >= 1_000.0 && amount % 100.0 == 0.0 {
+        score += 5;
+        reasons.push("round amount");
+    }
+
+    let recent_sends = recent_send_count(session, &tx.from_endpoint).await;
+    if recent_sends > 20 {
+        score += 30;
+        reasons.push("high send velocity");
+    } else if recent_sends > 5 {
+        score += 15;
+        reasons.push("elevated send velocity");
+    }
+
+    if !has_prior_transfer(session, &tx.from_endpoint, &tx.to_endpoint).await {
+        score += 15;
+        reasons.push("new counterparty");
+    }
+
+    if matches!(endpoints::load_endpoint(session, &tx.from_endpoint).await, Ok(None)) {
+        score += 10;
+        reasons.push("unprovisioned sender");
+    }
+
+    let score = score.min(100) as u8;
+    debug!("Risk score {} for transaction {}: {:?}", score, tx.id, reasons);
+    RiskAssessment { score, reasons }
+}
+
+async fn recent_send_count(session: &Session, endpoint_id: &str) -> usize {
+    let since = (Utc::now() - Duration::hours(1)).timestamp_millis();
+    sends::amounts_since(session, endpoint_id, since)
+        .await
+        .map(|amounts| amounts.len())
+        .unwrap_or(0)
+}
+
+async fn has_prior_transfer(session: &Session, from: &str, to: &str) -> bool {
+    session
+        .query(
+            db::idempotent("SELECT id FROM transactions.tx_log
+             WHERE from_endpoint = ? AND to_endpoint = ? LIMIT 1 ALLOW FILTERING"),
+            (from, to),
+        )
+        .await
+        .ok()
+        .and_then(|result| result.rows)
+        .map(|rows| !rows.is_empty())
+        .unwrap_or(false)
+}
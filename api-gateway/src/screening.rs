@@ -0,0 +1,201 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::Transaction;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ScreeningOutcome {
+    #[serde(rename = "clear")]
+    Clear,
+    #[serde(rename = "flag")]
+    Flag,
+    #[serde(rename = "block")]
+    Block,
+}
+
+impl ScreeningOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScreeningOutcome::Clear => "clear",
+            ScreeningOutcome::Flag => "flag",
+            ScreeningOutcome::Block => "block",
+        }
+    }
+
+    /// Audit log action recorded for this outcome.
+    pub fn audit_action(&self) -> &'static str {
+        match self {
+            ScreeningOutcome::Clear => "screening.clear",
+            ScreeningOutcome::Flag => "screening.flagged",
+            ScreeningOutcome::Block => "screening.blocked",
+        }
+    }
+}
+
+impl fmt::Display for ScreeningOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScreeningResult {
+    pub outcome: ScreeningOutcome,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl ScreeningResult {
+    fn clear() -> Self {
+        ScreeningResult { outcome: ScreeningOutcome::Clear, reason: None }
+    }
+}
+
+#[async_trait]
+pub trait ScreeningProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Screens both parties of a transaction before it is recorded.
+    async fn screen(&self, transaction: &Transaction) -> Result<ScreeningResult, String>;
+}
+
+/// Screens against lists loaded at startup: `SCREENING_DENYLIST` entries are
+/// blocked, `SCREENING_WATCHLIST` entries are flagged for review. Both take a
+/// comma-separated list of endpoint ids; `SCREENING_DENYLIST_FILE` adds
+/// denylist entries from a file, one per line.
+pub struct LocalDenylistProvider {
+    denylist: HashSet<String>,
+    watchlist: HashSet<String>,
+}
+
+impl LocalDenylistProvider {
+    pub fn from_env() -> Self {
+        let mut denylist = parse_list(&std::env::var("SCREENING_DENYLIST").unwrap_or_default());
+        if let Ok(path) = std::env::var("SCREENING_DENYLIST_FILE") {
+            match std::fs::read_to_string(&path) {
+                Ok(contents) => denylist.extend(
+                    contents
+                        .lines()
+                        .map(str::trim)
+                        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                        .map(str::to_string),
+                ),
+                Err(e) => warn!("Failed to read screening denylist {}: {}", path, e),
+            }
+        }
+        let watchlist = parse_list(&std::env::var("SCREENING_WATCHLIST").unwrap_or_default());
+
+        info!(
+            "Local screening: {} denylisted, {} watchlisted endpoints",
+            denylist.len(),
+            watchlist.len()
+        );
+        LocalDenylistProvider { denylist, watchlist }
+    }
+}
+
+fn parse_list(value: &str) -> HashSet<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+#[async_trait]
+impl ScreeningProvider for LocalDenylistProvider {
+    fn name(&self) -> &'static str {
+        "local-denylist"
+    }
+
+    async fn screen(&self, transaction: &Transaction) -> Result<ScreeningResult, String> {
+        let parties = [&transaction.from_endpoint, &transaction.to_endpoint];
+
+        if let Some(party) = parties.iter().find(|p| self.denylist.contains(p.as_str())) {
+            return Ok(ScreeningResult {
+                outcome: ScreeningOutcome::Block,
+                reason: Some(format!("{} is denylisted", party)),
+            });
+        }
+        if let Some(party) = parties.iter().find(|p| self.watchlist.contains(p.as_str())) {
+            return Ok(ScreeningResult {
+                outcome: ScreeningOutcome::Flag,
+                reason: Some(format!("{} is watchlisted", party)),
+            });
+        }
+        Ok(ScreeningResult::clear())
+    }
+}
+
+/// Posts each transaction to an external screening service, which answers
+/// with a `ScreeningResult` body.
+pub struct HttpScreeningProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[async_trait]
+impl ScreeningProvider for HttpScreeningProvider {
+    fn name(&self) -> &'static str {
+        "http"
+    }
+
+    async fn screen(&self, transaction: &Transaction) -> Result<ScreeningResult, String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(transaction)
+            .send()
+            .await
+            .map_err(|e| format!("Screening request failed: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Screening service returned {}", response.status()));
+        }
+
+        response
+            .json::<ScreeningResult>()
+            .await
+            .map_err(|e| format!("Invalid screening response: {}", e))
+    }
+}
+
+/// Selects the provider from `SCREENING_PROVIDER` (`local` or `http`).
+pub fn provider_from_env() -> Arc<dyn ScreeningProvider> {
+    match std::env::var("SCREENING_PROVIDER").as_deref() {
+        Ok("http") => match std::env::var("SCREENING_URL") {
+            Ok(url) => {
+                info!("Using HTTP screening provider at {}", url);
+                Arc::new(HttpScreeningProvider {
+                    client: reqwest::Client::new(),
+                    url,
+                })
+            }
+            Err(_) => {
+                warn!("SCREENING_URL not set, falling back to local denylist screening");
+                Arc::new(LocalDenylistProvider::from_env())
+            }
+        },
+        _ => Arc::new(LocalDenylistProvider::from_env()),
+    }
+}
+
+/// Runs the provider, flagging the transaction for review if screening
+/// itself fails so an outage never lets a payment through unscreened.
+pub async fn screen(provider: &dyn ScreeningProvider, transaction: &Transaction) -> ScreeningResult {
+    match provider.screen(transaction).await {
+        Ok(result) => result,
+        Err(e) => {
+            warn!("Screening of {} failed: {}", transaction.id, e);
+            ScreeningResult {
+                outcome: ScreeningOutcome::Flag,
+                reason: Some(format!("screening unavailable: {}", e)),
+            }
+        }
+    }
+}
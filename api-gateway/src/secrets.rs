@@ -0,0 +1,236 @@
+//! Credentials the gateway holds: Scylla auth (`SCYLLA_USERNAME`,
+//! `SCYLLA_PASSWORD`), the settlement callback secret
+//! (`SETTLEMENT_WEBHOOK_SECRET`) and a pinned service key
+//! (`GATEWAY_SIGNING_KEY`). They're read through a `SecretsProvider`
+//! chosen by `SECRETS_PROVIDER`:
+//!
+//! - `env` (default): environment variables of the same name
+//! - `file`: one file per secret in `SECRETS_DIR` (`/run/secrets`), as
+//!   mounted by Docker or Kubernetes
+//! - `vault`: keys of one Vault KV v2 secret, `VAULT_SECRET_PATH`
+//!   (`secret/data/p2p-relayer`) at `VAULT_ADDR`, read with `VAULT_TOKEN`
+//!   or the token in `VAULT_TOKEN_FILE` (reread on every call, for agents
+//!   that renew it)
+//!
+//! Values are cached and refetched every `SECRETS_REFRESH_SECS` (60), so a
+//! rotated secret takes effect without a restart; a failed refresh keeps
+//! the cached value. Scylla credentials are only read when connecting.
+//! Values are zeroed in memory when dropped.
+
+use async_trait::async_trait;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+/// A secret value. Zeroed when dropped and redacted from `Debug`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(Zeroizing<String>);
+
+impl Secret {
+    pub fn new(value: String) -> Self {
+        Secret(Zeroizing::new(value))
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(***)")
+    }
+}
+
+#[async_trait]
+pub trait SecretsProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// The current value of `name`, or `None` if the backend doesn't have it.
+    async fn fetch(&self, name: &str) -> Result<Option<Secret>, String>;
+}
+
+pub struct EnvSecretsProvider;
+
+#[async_trait]
+impl SecretsProvider for EnvSecretsProvider {
+    fn name(&self) -> &'static str {
+        "env"
+    }
+
+    async fn fetch(&self, name: &str) -> Result<Option<Secret>, String> {
+        Ok(std::env::var(name).ok().map(Secret::new))
+    }
+}
+
+/// Reads `<dir>/<NAME>`, without its trailing newline.
+pub struct FileSecretsProvider {
+    dir: PathBuf,
+}
+
+#[async_trait]
+impl SecretsProvider for FileSecretsProvider {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn fetch(&self, name: &str) -> Result<Option<Secret>, String> {
+        match tokio::fs::read_to_string(self.dir.join(name)).await {
+            Ok(contents) => {
+                let contents = Zeroizing::new(contents);
+                Ok(Some(Secret::new(contents.trim_end_matches(['\r', '\n']).to_string())))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Reading secret {} failed: {}", name, e)),
+        }
+    }
+}
+
+pub struct VaultSecretsProvider {
+    client: reqwest::Client,
+    url: String,
+    token: Option<Secret>,
+    token_file: Option<PathBuf>,
+}
+
+impl VaultSecretsProvider {
+    async fn token(&self) -> Result<Secret, String> {
+        if let Some(path) = &self.token_file {
+            let token = Zeroizing::new(
+                tokio::fs::read_to_string(path)
+                    .await
+                    .map_err(|e| format!("Reading VAULT_TOKEN_FILE failed: {}", e))?,
+            );
+            return Ok(Secret::new(token.trim().to_string()));
+        }
+        self.token.clone().ok_or_else(|| "No Vault token".to_string())
+    }
+}
+
+#[async_trait]
+impl SecretsProvider for VaultSecretsProvider {
+    fn name(&self) -> &'static str {
+        "vault"
+    }
+
+    async fn fetch(&self, name: &str) -> Result<Option<Secret>, String> {
+        let response = self
+            .client
+            .get(&self.url)
+            .header("X-Vault-Token", self.token().await?.expose())
+            .send()
+            .await
+            .map_err(|e| format!("Vault request failed: {}", e))?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !response.status().is_success() {
+            return Err(format!("Vault returned {}", response.status()));
+        }
+
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid Vault response: {}", e))?;
+        Ok(body
+            .pointer(&format!("/data/data/{}", name))
+            .and_then(Value::as_str)
+            .map(|value| Secret::new(value.to_string())))
+    }
+}
+
+/// Selects the provider from `SECRETS_PROVIDER`. Unlike the other
+/// providers, a misconfigured one is an error: falling back would quietly
+/// read credentials from somewhere else.
+pub fn provider_from_env() -> Result<Arc<dyn SecretsProvider>, Box<dyn std::error::Error>> {
+    match std::env::var("SECRETS_PROVIDER").as_deref() {
+        Ok("file") => {
+            let dir = std::env::var("SECRETS_DIR").unwrap_or_else(|_| "/run/secrets".to_string());
+            info!("Reading secrets from files in {}", dir);
+            Ok(Arc::new(FileSecretsProvider { dir: dir.into() }))
+        }
+        Ok("vault") => {
+            let addr = std::env::var("VAULT_ADDR").map_err(|_| "SECRETS_PROVIDER=vault needs VAULT_ADDR")?;
+            let path = std::env::var("VAULT_SECRET_PATH").unwrap_or_else(|_| "secret/data/p2p-relayer".to_string());
+            let token = std::env::var("VAULT_TOKEN").ok().map(Secret::new);
+            let token_file = std::env::var("VAULT_TOKEN_FILE").ok().map(PathBuf::from);
+            if token.is_none() && token_file.is_none() {
+                return Err("SECRETS_PROVIDER=vault needs VAULT_TOKEN or VAULT_TOKEN_FILE".into());
+            }
+            let url = format!("{}/v1/{}", addr.trim_end_matches('/'), path.trim_start_matches('/'));
+            info!("Reading secrets from Vault at {}", url);
+            Ok(Arc::new(VaultSecretsProvider {
+                client: reqwest::Client::new(),
+                url,
+                token,
+                token_file,
+            }))
+        }
+        Ok("env") | Err(_) => Ok(Arc::new(EnvSecretsProvider)),
+        Ok(other) => Err(format!("Unknown SECRETS_PROVIDER {}", other).into()),
+    }
+}
+
+fn refresh_interval() -> std::time::Duration {
+    std::time::Duration::from_secs(
+        std::env::var("SECRETS_REFRESH_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(60),
+    )
+}
+
+/// Cached secrets, shared by every request.
+#[derive(Clone)]
+pub struct Secrets {
+    provider: Arc<dyn SecretsProvider>,
+    cache: Arc<RwLock<HashMap<String, Option<Secret>>>>,
+}
+
+impl Secrets {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Secrets {
+            provider: provider_from_env()?,
+            cache: Arc::new(RwLock::new(HashMap::new())),
+        })
+    }
+
+    /// `name`, fetched on first use and cached from then on, absence
+    /// included.
+    pub async fn get(&self, name: &str) -> Result<Option<Secret>, String> {
+        if let Some(cached) = self.cache.read().unwrap().get(name) {
+            return Ok(cached.clone());
+        }
+        let value = self.provider.fetch(name).await?;
+        self.cache.write().unwrap().insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Refetches every cached secret.
+    async fn refresh(&self) {
+        let names: Vec<String> = self.cache.read().unwrap().keys().cloned().collect();
+        for name in names {
+            match self.provider.fetch(&name).await {
+                Ok(value) => {
+                    let previous = self.cache.write().unwrap().insert(name.clone(), value.clone());
+                    if previous.is_some_and(|previous| previous != value) {
+                        info!("Secret {} changed", name);
+                    }
+                }
+                Err(e) => warn!("Refreshing secret {} from {} failed: {}", name, self.provider.name(), e),
+            }
+        }
+    }
+
+    pub async fn run_refresh(self) {
+        let interval = refresh_interval();
+        loop {
+            tokio::time::sleep(interval).await;
+            self.refresh().await;
+        }
+    }
+}
@@ -0,0 +1,107 @@
+//! Each endpoint's outgoing transfers, partitioned by sender and UTC day
+//! and clustered newest first: `tx_sends((from_endpoint, day), timestamp,
+//! id)`. `tx_log` is keyed by id alone, so asking it for a sender's recent
+//! transfers filters the whole table; here a time range reads one
+//! partition per day it covers. Daily limits, the risk score's velocity
+//! check and the circuit breaker read from here.
+//!
+//! The projections write it next to `tx_log` and the timeline, so it holds
+//! the same transactions; `backfill` fills it from `tx_log` once.
+
+use chrono::Utc;
+use scylla::Session;
+use tracing::info;
+use tx_core::{Money, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::db;
+
+const DAY_MS: i64 = 86_400_000;
+
+/// Start of the UTC day `timestamp` falls in, in milliseconds.
+fn day_of(timestamp: i64) -> i64 {
+    timestamp - timestamp.rem_euclid(DAY_MS)
+}
+
+pub async fn insert(
+    session: &Session,
+    tx_id: Uuid,
+    from_endpoint: &str,
+    to_endpoint: &str,
+    timestamp: i64,
+    amount: &Money,
+) -> Result<(), String> {
+    session
+        .query(
+            db::idempotent(
+                "INSERT INTO transactions.tx_sends (from_endpoint, day, timestamp, id, to_endpoint, amount, amount_minor)
+                 VALUES (?, ?, ?, ?, ?, ?, ?)",
+            ),
+            (from_endpoint, day_of(timestamp), timestamp, tx_id, to_endpoint, amount.to_major(), amount.minor()),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The amounts `from_endpoint` sent at or after `since` (milliseconds),
+/// newest first.
+pub async fn amounts_since(session: &Session, from_endpoint: &str, since: i64) -> Result<Vec<Money>, String> {
+    // Timestamps may run a little ahead of our clock, so start a day out
+    let mut day = day_of(Utc::now().timestamp_millis().max(since)) + DAY_MS;
+    let mut amounts = Vec::new();
+    while day >= day_of(since) {
+        let rows = session
+            .query(
+                db::idempotent(
+                    "SELECT amount, amount_minor FROM transactions.tx_sends
+                     WHERE from_endpoint = ? AND day = ? AND timestamp >= ?",
+                ),
+                (from_endpoint, day, since),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        for row in rows.rows.unwrap_or_default() {
+            let (amount, amount_minor) = row.into_typed::<(f64, Option<i64>)>().map_err(|e| e.to_string())?;
+            amounts.push(match amount_minor {
+                Some(minor) => Money::new(minor, NATIVE_ASSET),
+                None => Money::from_major(amount, NATIVE_ASSET).map_err(|e| e.to_string())?,
+            });
+        }
+        day -= DAY_MS;
+    }
+    Ok(amounts)
+}
+
+/// One-off migration for deployments that predate `tx_sends`: copies every
+/// `tx_log` row in. Does nothing once the table has rows.
+pub async fn backfill(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    let existing = session
+        .query(db::idempotent("SELECT from_endpoint FROM transactions.tx_sends LIMIT 1"), &[])
+        .await?;
+    if existing.rows.map(|rows| !rows.is_empty()).unwrap_or(false) {
+        return Ok(());
+    }
+
+    let rows = session
+        .query(
+            db::scan("SELECT id, from_endpoint, to_endpoint, timestamp, amount, amount_minor FROM transactions.tx_log"),
+            &[],
+        )
+        .await?;
+    let mut backfilled = 0;
+    for row in rows.rows.unwrap_or_default() {
+        let (id, from_endpoint, to_endpoint, timestamp, amount, amount_minor) =
+            row.into_typed::<(Uuid, String, String, i64, f64, Option<i64>)>()?;
+        let amount = match amount_minor {
+            Some(minor) => Money::new(minor, NATIVE_ASSET),
+            None => Money::from_major(amount, NATIVE_ASSET)?,
+        };
+        insert(session, id, &from_endpoint, &to_endpoint, timestamp, &amount).await?;
+        backfilled += 1;
+    }
+    if backfilled > 0 {
+        info!("Backfilled {} transactions into tx_sends", backfilled);
+    }
+    Ok(())
+}
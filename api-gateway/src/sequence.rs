@@ -0,0 +1,121 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::db;
+use crate::{lwt_applied, AppState};
+
+/// Each sender numbers its transfers; the gateway only accepts a sequence
+/// number greater than the last one it recorded for that sender. Two devices
+/// (or the WebSocket and WebRTC paths) racing to spend the same funds end up
+/// claiming the same number, and all but the first are refused. The
+/// claim records which transaction made it (`claimed_by`), so a retry of
+/// that transaction is not taken for a second spend.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SequenceState {
+    pub endpoint_id: String,
+    pub last_sequence: i64,
+}
+
+/// Transfers without a sequence number are accepted unless
+/// `REQUIRE_TX_SEQUENCE` is set, so older clients keep working.
+fn require_sequence() -> bool {
+    std::env::var("REQUIRE_TX_SEQUENCE")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
+}
+
+/// Atomically advances the sender's sequence to `sequence` on behalf of
+/// `transaction_id`, refusing with 409 if it does not exceed the last
+/// claimed one, unless that claim was this transaction's own.
+pub async fn claim(
+    session: &Session,
+    endpoint_id: &str,
+    sequence: Option<i64>,
+    transaction_id: &str,
+) -> Result<(), StatusCode> {
+    let Some(sequence) = sequence else {
+        return if require_sequence() { Err(StatusCode::BAD_REQUEST) } else { Ok(()) };
+    };
+    if sequence < 1 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let db_error = |e| {
+        error!("Failed to claim sequence for {}: {}", endpoint_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+
+    let inserted = session
+        .query(
+            "INSERT INTO transactions.endpoint_sequences (endpoint_id, last_sequence, claimed_by)
+             VALUES (?, ?, ?) IF NOT EXISTS",
+            (endpoint_id, sequence, transaction_id),
+        )
+        .await
+        .map_err(db_error)?;
+    if lwt_applied(inserted) {
+        return Ok(());
+    }
+
+    let advanced = session
+        .query(
+            "UPDATE transactions.endpoint_sequences SET last_sequence = ?, claimed_by = ?
+             WHERE endpoint_id = ? IF last_sequence < ?",
+            (sequence, transaction_id, endpoint_id, sequence),
+        )
+        .await
+        .map_err(db_error)?;
+    if lwt_applied(advanced) {
+        return Ok(());
+    }
+
+    let current = session
+        .query(
+            db::idempotent(
+                "SELECT last_sequence, claimed_by FROM transactions.endpoint_sequences WHERE endpoint_id = ?",
+            ),
+            (endpoint_id,),
+        )
+        .await
+        .map_err(db_error)?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(i64, Option<String>)>().ok());
+    if let Some((last, Some(claimed_by))) = current {
+        if last == sequence && claimed_by == transaction_id {
+            return Ok(());
+        }
+    }
+
+    warn!("Sequence {} from {} already used, possible double spend", sequence, endpoint_id);
+    Err(StatusCode::CONFLICT)
+}
+
+pub async fn get_sequence(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+) -> Result<Json<SequenceState>, StatusCode> {
+    let rows = state
+        .session
+        .query(
+            db::idempotent("SELECT last_sequence FROM transactions.endpoint_sequences WHERE endpoint_id = ?"),
+            (&endpoint_id,),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let last_sequence = rows
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(i64,)>().ok())
+        .map(|(last,)| last)
+        .unwrap_or(0);
+
+    Ok(Json(SequenceState { endpoint_id, last_sequence }))
+}
@@ -0,0 +1,310 @@
+//! The gateway's service identity: the ed25519 keys it signs receipts
+//! with, published as a key set at `/api/.well-known/keys`. Receipts are
+//! all they sign; settlement webhooks are checked against their own
+//! shared secret (`SETTLEMENT_WEBHOOK_SECRET`), not these keys.
+//!
+//! Rotation (`api-gateway rotate-keys`) adds a key that starts signing
+//! `KEY_ROTATION_OVERLAP_SECS` (a day) later and retires the current one
+//! the same period after that. Clients that refresh the key set at least
+//! once per overlap always know a key before it signs, and every instance
+//! picks up the new key from the database well before it takes over.
+
+use axum::{extract::State, http::header, response::IntoResponse, Json};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signer, SigningKey};
+use rand_core::OsRng;
+use scylla::Session;
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+use tx_core::{key_id, KeySet, PublishedKey};
+use zeroize::Zeroizing;
+
+use crate::audit;
+use crate::db;
+use crate::secrets::Secrets;
+use crate::{lwt_applied, timestamp_from_millis, AppState};
+
+/// Where the first generated key was kept before keys could be rotated.
+const LEGACY_KEY_NAME: &str = "receipts";
+
+/// The audit trail key creation and rotation are recorded on.
+const AUDIT_ENTITY: &str = "service-keys";
+
+const KEY_REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+#[derive(Clone)]
+struct ServiceKey {
+    kid: String,
+    key: SigningKey,
+    not_before: DateTime<Utc>,
+    expires_at: Option<DateTime<Utc>>,
+}
+
+/// The keyring, shared by every request. `GATEWAY_SIGNING_KEY`, read
+/// through the secrets provider, pins a single key and rotation is then
+/// by replacing the secret. Without it keys live in the database, shared
+/// by every gateway instance; anyone with database access can then sign
+/// as the gateway.
+#[derive(Clone)]
+pub struct ServiceKeys {
+    ring: Arc<RwLock<Vec<ServiceKey>>>,
+    pinned: bool,
+}
+
+impl ServiceKeys {
+    pub async fn load(session: &Session, secrets: &Secrets) -> Result<Self, Box<dyn std::error::Error>> {
+        if let Some(key) = pinned_key(secrets).await? {
+            info!("Service key {} (pinned)", key.kid);
+            return Ok(ServiceKeys { ring: Arc::new(RwLock::new(vec![key])), pinned: true });
+        }
+
+        bootstrap(session).await?;
+        let keys = ServiceKeys { ring: Arc::new(RwLock::new(Vec::new())), pinned: false };
+        keys.refresh(session, secrets).await?;
+        info!("Service key {}", keys.current().kid);
+        Ok(keys)
+    }
+
+    /// Rereads the keyring, picking up keys rotated in elsewhere. A pinned
+    /// key replaced in the secret store signs from now on; the one it
+    /// replaces stays published until the gateway restarts.
+    pub async fn refresh(&self, session: &Session, secrets: &Secrets) -> Result<(), Box<dyn std::error::Error>> {
+        if self.pinned {
+            let Some(key) = pinned_key(secrets).await? else {
+                return Ok(());
+            };
+            let mut ring = self.ring.write().unwrap();
+            if ring.iter().any(|existing| existing.kid == key.kid) {
+                return Ok(());
+            }
+            let now = Utc::now();
+            for existing in ring.iter_mut() {
+                existing.expires_at.get_or_insert(now);
+            }
+            info!("Service key {} (pinned) replaces the previous one", key.kid);
+            ring.push(ServiceKey { not_before: now, ..key });
+            return Ok(());
+        }
+        let ring = read_ring(session).await?;
+        if ring.is_empty() {
+            return Err("No service keys in the database".into());
+        }
+        *self.ring.write().unwrap() = ring;
+        Ok(())
+    }
+
+    /// The key that signs now: the newest to have taken over. Its
+    /// predecessors retire only after it does.
+    fn current(&self) -> ServiceKey {
+        let now = Utc::now();
+        let ring = self.ring.read().unwrap();
+        ring.iter()
+            .filter(|key| key.not_before <= now)
+            .max_by_key(|key| key.not_before)
+            .or_else(|| ring.first())
+            .cloned()
+            .expect("keyring is never empty")
+    }
+
+    pub fn public_key(&self) -> String {
+        hex::encode(self.current().key.verifying_key().to_bytes())
+    }
+
+    /// Signs `bytes` with the current key; returns it and the signature, in hex.
+    pub fn sign(&self, bytes: &[u8]) -> (String, String) {
+        let current = self.current();
+        (
+            hex::encode(current.key.verifying_key().to_bytes()),
+            hex::encode(current.key.sign(bytes).to_bytes()),
+        )
+    }
+
+    pub fn key_set(&self) -> KeySet {
+        let mut keys: Vec<PublishedKey> = self
+            .ring
+            .read()
+            .unwrap()
+            .iter()
+            .map(|key| {
+                PublishedKey::ed25519(
+                    key.kid.clone(),
+                    &key.key.verifying_key().to_bytes(),
+                    (key.not_before > DateTime::<Utc>::UNIX_EPOCH).then_some(key.not_before),
+                    key.expires_at,
+                )
+            })
+            .collect();
+        keys.sort_by_key(|key| std::cmp::Reverse(key.nbf.unwrap_or(0)));
+        KeySet { keys }
+    }
+}
+
+/// The key pinned by `GATEWAY_SIGNING_KEY` (hex ed25519 seed;
+/// `RECEIPT_SIGNING_KEY` is still read), if any.
+async fn pinned_key(secrets: &Secrets) -> Result<Option<ServiceKey>, Box<dyn std::error::Error>> {
+    let seed = match secrets.get("GATEWAY_SIGNING_KEY").await? {
+        Some(seed) => seed,
+        None => match secrets.get("RECEIPT_SIGNING_KEY").await? {
+            Some(seed) => seed,
+            None => return Ok(None),
+        },
+    };
+    let bytes = Zeroizing::new(hex::decode(seed.expose().trim())?);
+    let seed = Zeroizing::new(<[u8; 32]>::try_from(bytes.as_slice()).map_err(|_| "GATEWAY_SIGNING_KEY must be 32 bytes of hex")?);
+    let key = SigningKey::from_bytes(&seed);
+    Ok(Some(ServiceKey {
+        kid: key_id(&key.verifying_key().to_bytes()),
+        key,
+        not_before: DateTime::<Utc>::UNIX_EPOCH,
+        expires_at: None,
+    }))
+}
+
+fn overlap() -> Duration {
+    Duration::seconds(
+        std::env::var("KEY_ROTATION_OVERLAP_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(86_400),
+    )
+}
+
+async fn read_ring(session: &Session) -> Result<Vec<ServiceKey>, Box<dyn std::error::Error>> {
+    let rows = session
+        .query(db::idempotent("SELECT kid, secret_key, not_before, expires_at FROM transactions.gateway_keys"), &[])
+        .await?;
+
+    let mut ring = Vec::new();
+    for row in rows.rows.unwrap_or_default() {
+        let (kid, seed, not_before, expires_at) = row.into_typed::<(String, String, i64, Option<i64>)>()?;
+        let Some(seed) = hex::decode(&seed).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok()) else {
+            warn!("Skipping malformed service key {}", kid);
+            continue;
+        };
+        ring.push(ServiceKey {
+            kid,
+            key: SigningKey::from_bytes(&seed),
+            not_before: timestamp_from_millis(not_before),
+            expires_at: expires_at.map(timestamp_from_millis),
+        });
+    }
+    Ok(ring)
+}
+
+async fn insert_key(session: &Session, key: &ServiceKey) -> Result<bool, Box<dyn std::error::Error>> {
+    let result = session
+        .query(
+            "INSERT INTO transactions.gateway_keys (kid, secret_key, not_before, expires_at)
+             VALUES (?, ?, ?, ?) IF NOT EXISTS",
+            (
+                &key.kid,
+                hex::encode(key.key.to_bytes()),
+                key.not_before.timestamp_millis(),
+                key.expires_at.map(|t| t.timestamp_millis()),
+            ),
+        )
+        .await?;
+    Ok(lwt_applied(result))
+}
+
+/// Seeds an empty keyring with the key receipts were signed with before
+/// rotation, generating it first on a fresh database. Concurrent instances
+/// agree on it through the LWT on `service_keys`.
+async fn bootstrap(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    if !read_ring(session).await?.is_empty() {
+        return Ok(());
+    }
+
+    let generated = SigningKey::generate(&mut OsRng);
+    let result = session
+        .query(
+            "INSERT INTO transactions.service_keys (name, secret_key) VALUES (?, ?) IF NOT EXISTS",
+            (LEGACY_KEY_NAME, hex::encode(generated.to_bytes())),
+        )
+        .await?;
+    if lwt_applied(result) {
+        warn!("GATEWAY_SIGNING_KEY not set, generated a service key and stored it in the database");
+        let kid = key_id(&generated.verifying_key().to_bytes());
+        record(session, "service_key.generated", format!("{} signs from now", kid)).await?;
+    }
+
+    let (seed,) = session
+        .query(db::idempotent("SELECT secret_key FROM transactions.service_keys WHERE name = ?"), (LEGACY_KEY_NAME,))
+        .await?
+        .single_row_typed::<(String,)>()?;
+    let seed = <[u8; 32]>::try_from(hex::decode(seed)?).map_err(|_| "Stored service key is malformed")?;
+    let key = SigningKey::from_bytes(&seed);
+    insert_key(
+        session,
+        &ServiceKey {
+            kid: key_id(&key.verifying_key().to_bytes()),
+            key,
+            not_before: DateTime::<Utc>::UNIX_EPOCH,
+            expires_at: None,
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// `api-gateway rotate-keys`: adds the next key and schedules the switch.
+pub async fn rotate(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    bootstrap(session).await?;
+    let overlap = overlap();
+    let activates_at = Utc::now() + overlap;
+    let retires_at = activates_at + overlap;
+
+    let key = SigningKey::generate(&mut OsRng);
+    let next = ServiceKey {
+        kid: key_id(&key.verifying_key().to_bytes()),
+        key,
+        not_before: activates_at,
+        expires_at: None,
+    };
+    if !insert_key(session, &next).await? {
+        return Err(format!("Service key {} already exists", next.kid).into());
+    }
+
+    // Everything signing now, including keys scheduled by an earlier
+    // rotation that hasn't completed, hands over to the new one
+    for key in read_ring(session).await? {
+        if key.kid == next.kid || key.expires_at.is_some_and(|expires_at| expires_at <= retires_at) {
+            continue;
+        }
+        session
+            .query(
+                "UPDATE transactions.gateway_keys SET expires_at = ? WHERE kid = ?",
+                (retires_at.timestamp_millis(), &key.kid),
+            )
+            .await?;
+        info!("Service key {} retires at {}", key.kid, retires_at.to_rfc3339());
+        record(session, "service_key.retiring", format!("{} retires at {}", key.kid, retires_at.to_rfc3339())).await?;
+    }
+
+    info!("Service key {} signs from {}", next.kid, activates_at.to_rfc3339());
+    record(session, "service_key.scheduled", format!("{} signs from {}", next.kid, activates_at.to_rfc3339())).await?;
+    Ok(())
+}
+
+async fn record(session: &Session, action: &str, details: String) -> Result<(), Box<dyn std::error::Error>> {
+    audit::record(session, AUDIT_ENTITY, action, "gateway", Some(details))
+        .await
+        .map_err(|status| format!("Failed to audit {}: {}", action, status).into())
+}
+
+/// Every instance rereads the keyring, so a rotation reaches them all
+/// long before the new key takes over.
+pub async fn run_key_refresh(state: AppState) {
+    loop {
+        tokio::time::sleep(KEY_REFRESH_INTERVAL).await;
+        if let Err(e) = state.keys.refresh(&state.session, &state.secrets).await {
+            warn!("Service key refresh failed: {}", e);
+        }
+    }
+}
+
+/// `GET /api/.well-known/keys`: every key the gateway signs, has signed,
+/// or is about to sign with.
+pub async fn get_keys(State(state): State<AppState>) -> impl IntoResponse {
+    ([(header::CACHE_CONTROL, "public, max-age=300")], Json(state.keys.key_set()))
+}
@@ -0,0 +1,443 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::endpoints;
+use crate::error::ApiError;
+use crate::events::{self, EventKind};
+use crate::lockout;
+use crate::ratelimit;
+use crate::repository::Repository;
+use crate::{
+    compute_endpoint_stats, insert_transaction, load_transaction, pii, stats_error, timestamp_from_millis,
+    update_transaction_status, AppState, Transaction, TransactionKind, TransactionStatus,
+};
+use tx_core::{Money, NATIVE_ASSET};
+
+/// Destination account recorded on withdrawal entries.
+pub const SETTLEMENT_ACCOUNT: &str = "system:settlement";
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum SettlementStatus {
+    #[serde(rename = "initiated")]
+    Initiated,
+    #[serde(rename = "sent")]
+    Sent,
+    #[serde(rename = "confirmed")]
+    Confirmed,
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+impl SettlementStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SettlementStatus::Initiated => "initiated",
+            SettlementStatus::Sent => "sent",
+            SettlementStatus::Confirmed => "confirmed",
+            SettlementStatus::Failed => "failed",
+        }
+    }
+
+    fn is_final(&self) -> bool {
+        matches!(self, SettlementStatus::Confirmed | SettlementStatus::Failed)
+    }
+}
+
+impl fmt::Display for SettlementStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SettlementStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "initiated" => Ok(SettlementStatus::Initiated),
+            "sent" => Ok(SettlementStatus::Sent),
+            "confirmed" => Ok(SettlementStatus::Confirmed),
+            "failed" => Ok(SettlementStatus::Failed),
+            other => Err(format!("Unknown settlement status: {}", other)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Settlement {
+    pub transaction_id: String,
+    pub endpoint_id: String,
+    pub amount: f64,
+    pub destination: String,
+    pub status: SettlementStatus,
+    pub provider: String,
+    pub provider_reference: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Result of handing a settlement to a provider.
+pub struct ProviderUpdate {
+    pub status: SettlementStatus,
+    pub reference: Option<String>,
+}
+
+#[async_trait]
+pub trait SettlementProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Submits the payout. Providers that confirm asynchronously return
+    /// `Sent` and report the final outcome through the confirmation webhook.
+    async fn initiate(&self, settlement: &Settlement) -> Result<ProviderUpdate, String>;
+}
+
+/// Confirms every payout immediately. Used for local development.
+pub struct StubSettlementProvider;
+
+#[async_trait]
+impl SettlementProvider for StubSettlementProvider {
+    fn name(&self) -> &'static str {
+        "stub"
+    }
+
+    async fn initiate(&self, settlement: &Settlement) -> Result<ProviderUpdate, String> {
+        Ok(ProviderUpdate {
+            status: SettlementStatus::Confirmed,
+            reference: Some(format!("stub-{}", settlement.transaction_id)),
+        })
+    }
+}
+
+/// Posts payouts to an external service, which later calls back
+/// `POST /api/settlements/:id/confirmation` with the final status.
+pub struct WebhookSettlementProvider {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[async_trait]
+impl SettlementProvider for WebhookSettlementProvider {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn initiate(&self, settlement: &Settlement) -> Result<ProviderUpdate, String> {
+        let response = self
+            .client
+            .post(&self.url)
+            .json(settlement)
+            .send()
+            .await
+            .map_err(|e| format!("Settlement webhook failed: {}", e))?;
+
+        if !response.status().is_success() {
+            return Err(format!("Settlement webhook returned {}", response.status()));
+        }
+
+        Ok(ProviderUpdate {
+            status: SettlementStatus::Sent,
+            reference: None,
+        })
+    }
+}
+
+/// Selects the provider from `SETTLEMENT_PROVIDER` (`stub` or `webhook`).
+pub fn provider_from_env() -> Arc<dyn SettlementProvider> {
+    match std::env::var("SETTLEMENT_PROVIDER").as_deref() {
+        Ok("webhook") => match std::env::var("SETTLEMENT_WEBHOOK_URL") {
+            Ok(url) => {
+                info!("Using webhook settlement provider at {}", url);
+                Arc::new(WebhookSettlementProvider {
+                    client: reqwest::Client::new(),
+                    url,
+                })
+            }
+            Err(_) => {
+                warn!("SETTLEMENT_WEBHOOK_URL not set, falling back to stub settlement provider");
+                Arc::new(StubSettlementProvider)
+            }
+        },
+        _ => Arc::new(StubSettlementProvider),
+    }
+}
+
+async fn save_settlement(session: &Repository, settlement: &Settlement) -> Result<(), StatusCode> {
+    let tx_id = Uuid::parse_str(&settlement.transaction_id)
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let destination = pii::seal(session, &settlement.endpoint_id, &settlement.destination).await?;
+
+    session
+        .query(
+            "INSERT INTO transactions.settlements (transaction_id, endpoint_id, amount, destination, status, provider, provider_reference, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            (
+                tx_id,
+                &settlement.endpoint_id,
+                settlement.amount,
+                &destination,
+                settlement.status.as_str(),
+                &settlement.provider,
+                &settlement.provider_reference,
+                settlement.updated_at.timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to save settlement {}: {}", settlement.transaction_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    // Every settlement state lands in the withdrawal's event stream
+    if let Some(withdrawal) = load_transaction(session, tx_id).await? {
+        let payload = serde_json::to_value(settlement).unwrap_or_default();
+        events::append_with(session, EventKind::Settled, &withdrawal, payload, settlement.updated_at).await?;
+    }
+    Ok(())
+}
+
+async fn load_settlement(session: &Session, tx_id: Uuid) -> Result<Option<Settlement>, StatusCode> {
+    let rows = session
+        .query(
+            db::idempotent("SELECT transaction_id, endpoint_id, amount, destination, status, provider, provider_reference, updated_at
+             FROM transactions.settlements WHERE transaction_id = ?"),
+            (tx_id,),
+        )
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    if let Some(rows) = rows.rows {
+        if let Some(row) = rows.into_iter().next() {
+            if let Ok((transaction_id, endpoint_id, amount, destination, status, provider, provider_reference, updated_at)) =
+                row.into_typed::<(Uuid, String, f64, String, String, String, Option<String>, i64)>() {
+                let status = status.parse().map_err(|e| {
+                    error!("Settlement {}: {}", transaction_id, e);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+                let destination = pii::open(session, &endpoint_id, destination).await?;
+                return Ok(Some(Settlement {
+                    transaction_id: transaction_id.to_string(),
+                    endpoint_id,
+                    amount,
+                    destination,
+                    status,
+                    provider,
+                    provider_reference,
+                    updated_at: timestamp_from_millis(updated_at),
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Moves a settlement to `status` and mirrors final outcomes onto the
+/// withdrawal transaction.
+async fn apply_update(
+    session: &Repository,
+    settlement: &mut Settlement,
+    update: ProviderUpdate,
+) -> Result<(), StatusCode> {
+    settlement.status = update.status;
+    if update.reference.is_some() {
+        settlement.provider_reference = update.reference;
+    }
+    settlement.updated_at = Utc::now();
+    save_settlement(session, settlement).await?;
+
+    let tx_status = match settlement.status {
+        SettlementStatus::Confirmed => Some(TransactionStatus::Confirmed),
+        SettlementStatus::Failed => Some(TransactionStatus::Failed),
+        _ => None,
+    };
+    if let Some(tx_status) = tx_status {
+        update_transaction_status(session, &settlement.transaction_id, tx_status).await?;
+    }
+
+    info!("Settlement {} is now {}", settlement.transaction_id, settlement.status);
+    Ok(())
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct WithdrawalRequest {
+    pub amount: f64,
+    pub destination: String,
+}
+
+pub async fn create_withdrawal(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    Json(request): Json<WithdrawalRequest>,
+) -> Result<(StatusCode, Json<Settlement>), StatusCode> {
+    let requested = Money::from_major(request.amount, NATIVE_ASSET).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if requested.is_negative() || requested.is_zero() || request.destination.trim().is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    endpoints::check_transaction_allowed(&state.session, &endpoint_id, SETTLEMENT_ACCOUNT, &requested).await?;
+
+    // Withdrawals may only draw on funds the ledger knows about
+    let initial_balance = endpoints::load_endpoint(&state.session, &endpoint_id)
+        .await?
+        .map_or_else(|| Money::zero(NATIVE_ASSET), |ep| ep.initial_balance);
+    let balance = compute_endpoint_stats(&state.session, &endpoint_id)
+        .await?
+        .balance_from(&initial_balance)
+        .map_err(stats_error)?;
+    if balance.checked_sub(&requested).map_err(stats_error)?.is_negative() {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let withdrawal = Transaction {
+        id: Uuid::new_v4().to_string(),
+        from_endpoint: endpoint_id.clone(),
+        to_endpoint: SETTLEMENT_ACCOUNT.to_string(),
+        amount: requested,
+        timestamp: Utc::now(),
+        signature: String::new(),
+        status: TransactionStatus::Pending,
+        kind: TransactionKind::Withdrawal,
+        risk_score: None,
+        parent_tx_id: None,
+        sequence: None,
+        public_key: None,
+    };
+    insert_transaction(&state.session, &withdrawal).await?;
+
+    let mut settlement = Settlement {
+        transaction_id: withdrawal.id.clone(),
+        endpoint_id,
+        amount: request.amount,
+        destination: request.destination,
+        status: SettlementStatus::Initiated,
+        provider: state.settlement.name().to_string(),
+        provider_reference: None,
+        updated_at: Utc::now(),
+    };
+    save_settlement(&state.session, &settlement).await?;
+
+    let update = match state.settlement.initiate(&settlement).await {
+        Ok(update) => update,
+        Err(e) => {
+            error!("Settlement {} failed: {}", settlement.transaction_id, e);
+            ProviderUpdate {
+                status: SettlementStatus::Failed,
+                reference: None,
+            }
+        }
+    };
+    apply_update(&state.session, &mut settlement, update).await?;
+
+    Ok((StatusCode::CREATED, Json(settlement)))
+}
+
+pub async fn get_settlement(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Settlement>, StatusCode> {
+    let tx_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    load_settlement(&state.session, tx_id)
+        .await?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SettlementConfirmation {
+    pub status: SettlementStatus,
+    pub reference: Option<String>,
+}
+
+/// Compares digests rather than the secrets, so neither their contents nor
+/// their lengths show in the timing.
+fn secret_matches(provided: &str, expected: &str) -> bool {
+    Sha256::digest(provided.as_bytes()).ct_eq(&Sha256::digest(expected.as_bytes())).into()
+}
+
+/// Callback used by asynchronous providers to report the final outcome.
+/// Requests must carry `X-Settlement-Secret` matching `SETTLEMENT_WEBHOOK_SECRET`;
+/// a wrong secret counts against the caller's IP like a bad token (see
+/// `lockout`).
+pub async fn confirm_settlement(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(confirmation): Json<SettlementConfirmation>,
+) -> Result<Json<Settlement>, ApiError> {
+    let keys = lockout::keys(ratelimit::handler_ip(&headers, peer.as_ref()).as_deref(), None);
+    lockout::check(&keys)?;
+
+    let expected = state
+        .secrets
+        .get("SETTLEMENT_WEBHOOK_SECRET")
+        .await
+        .map_err(|e| {
+            error!("Settlement webhook secret unavailable: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let provided = headers
+        .get("x-settlement-secret")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !secret_matches(provided, expected.expose()) {
+        lockout::failed(&keys).await;
+        return Err(StatusCode::UNAUTHORIZED.into());
+    }
+    lockout::succeeded(&keys);
+
+    if !confirmation.status.is_final() {
+        return Err(StatusCode::BAD_REQUEST.into());
+    }
+
+    let tx_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let mut settlement = load_settlement(&state.session, tx_id)
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    if settlement.status.is_final() {
+        return Err(StatusCode::CONFLICT.into());
+    }
+
+    apply_update(
+        &state.session,
+        &mut settlement,
+        ProviderUpdate {
+            status: confirmation.status,
+            reference: confirmation.reference,
+        },
+    )
+    .await?;
+
+    Ok(Json(settlement))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhook_secrets_must_match_exactly() {
+        assert!(secret_matches("s3cret", "s3cret"));
+        assert!(!secret_matches("s3cre", "s3cret"));
+        assert!(!secret_matches("s3cret ", "s3cret"));
+        assert!(!secret_matches("", "s3cret"));
+    }
+}
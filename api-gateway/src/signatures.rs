@@ -0,0 +1,257 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{error, warn};
+use tx_core::verify_transaction;
+
+use crate::audit::{self, AuditEntry};
+use crate::db;
+use crate::endpoints;
+use crate::reporting::csv_field;
+use crate::{lwt_applied, AppState, Transaction};
+
+/// The subsystem key events are recorded by.
+const KEY_ACTOR: &str = "signatures";
+
+/// Transfers without a public key are accepted unless
+/// `REQUIRE_TX_SIGNATURES` is set, so older clients keep working.
+pub fn require_signatures() -> bool {
+    std::env::var("REQUIRE_TX_SIGNATURES")
+        .map(|v| v == "true" || v == "1")
+        .unwrap_or(false)
+}
+
+/// Verifies a transfer's signature and that it was made with the sender's
+/// key, the one registered for it by `register_key`. Transfers signed with
+/// any other key are refused with 401.
+pub async fn check(session: &Session, transaction: &Transaction) -> Result<(), StatusCode> {
+    if transaction.public_key.is_some() && !verify_transaction(transaction) {
+        warn!("Transaction {} has an invalid signature", transaction.id);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    check_sender(session, transaction).await
+}
+
+/// `check` for a transfer whose signature `validation::transfer` already
+/// verified: only that the key is the sender's. Once the sender has a key
+/// registered, an unsigned transfer is refused whatever
+/// `REQUIRE_TX_SIGNATURES` says.
+pub async fn check_sender(session: &Session, transaction: &Transaction) -> Result<(), StatusCode> {
+    let Some(public_key) = transaction.public_key.as_deref() else {
+        if require_signatures() {
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        if pinned_key(session, &transaction.from_endpoint).await?.is_some() {
+            warn!("Transaction {} is unsigned but {} has a key registered", transaction.id, transaction.from_endpoint);
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+        return Ok(());
+    };
+
+    check_key(session, &transaction.from_endpoint, public_key).await.inspect_err(|status| {
+        if *status == StatusCode::UNAUTHORIZED {
+            warn!(
+                "Transaction {} signed with a key not registered to {}",
+                transaction.id, transaction.from_endpoint
+            );
+        }
+    })
+}
+
+/// Refuses with 401 any key other than the one registered to
+/// `endpoint_id`, including every key of an endpoint with none registered.
+/// Refused keys go on the endpoint's audit trail.
+pub async fn check_key(session: &Session, endpoint_id: &str, public_key: &str) -> Result<(), StatusCode> {
+    if pinned_key(session, endpoint_id).await?.as_deref() == Some(public_key) {
+        return Ok(());
+    }
+    audit::record(session, endpoint_id, "key.rejected", KEY_ACTOR, Some(public_key.to_string())).await?;
+    Err(StatusCode::UNAUTHORIZED)
+}
+
+/// Whether `public_key` is a hex-encoded 32-byte Ed25519 key.
+pub fn well_formed(public_key: &str) -> bool {
+    hex::decode(public_key).is_ok_and(|bytes| bytes.len() == 32)
+}
+
+/// Pins `public_key` to `endpoint_id` unless it already has a key,
+/// returning whether it did. Keys are only registered when an endpoint is
+/// provisioned or by an admin, never by whoever first signs as it.
+pub async fn register_key(
+    session: &Session,
+    endpoint_id: &str,
+    public_key: &str,
+) -> Result<bool, StatusCode> {
+    let result = session
+        .query(
+            "INSERT INTO transactions.endpoint_keys (endpoint_id, public_key) VALUES (?, ?) IF NOT EXISTS",
+            (endpoint_id, public_key),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to register key for {}: {}", endpoint_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    if !lwt_applied(result) {
+        return Ok(false);
+    }
+    audit::record(session, endpoint_id, "key.registered", KEY_ACTOR, Some(public_key.to_string())).await?;
+    Ok(true)
+}
+
+#[derive(Deserialize)]
+pub struct RegisterKeyRequest {
+    pub public_key: String,
+}
+
+/// `PUT /api/endpoints/:id/key` (admin): registers the key for an endpoint
+/// provisioned without one. An endpoint's key is never replaced; offering a
+/// different one is a 409.
+pub async fn put_key(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    Json(request): Json<RegisterKeyRequest>,
+) -> Result<StatusCode, StatusCode> {
+    if !well_formed(&request.public_key) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if endpoints::load_endpoint(&state.session, &endpoint_id).await?.is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if register_key(&state.session, &endpoint_id, &request.public_key).await? {
+        return Ok(StatusCode::CREATED);
+    }
+    match pinned_key(&state.session, &endpoint_id).await? {
+        Some(pinned) if pinned == request.public_key => Ok(StatusCode::OK),
+        _ => Err(StatusCode::CONFLICT),
+    }
+}
+
+async fn pinned_key(session: &Session, endpoint_id: &str) -> Result<Option<String>, StatusCode> {
+    let rows = session
+        .query(
+            db::idempotent("SELECT public_key FROM transactions.endpoint_keys WHERE endpoint_id = ?"),
+            (endpoint_id,),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to load key for {}: {}", endpoint_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+    Ok(rows
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(String,)>().ok())
+        .map(|(public_key,)| public_key))
+}
+
+#[derive(Serialize)]
+pub struct KeyUsageReport {
+    pub endpoint_id: String,
+    pub public_key: Option<String>,
+    /// `key.*` entries of the endpoint's audit trail, newest first.
+    pub events: Vec<AuditEntry>,
+    pub generated_at: DateTime<Utc>,
+}
+
+/// `GET /api/endpoints/:id/key-usage?format=<json|csv>`: the key pinned to
+/// an endpoint and every registration and refused key on record, for
+/// incident forensics. Signing and decryption are logged by the client,
+/// which never hands its keys to the gateway.
+pub async fn get_key_usage(
+    State(state): State<AppState>,
+    Path(endpoint_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    let events = audit::load_trail(&state.session, &endpoint_id)
+        .await?
+        .into_iter()
+        .filter(|entry| entry.action.starts_with("key."))
+        .collect();
+    let report = KeyUsageReport {
+        public_key: pinned_key(&state.session, &endpoint_id).await?,
+        endpoint_id,
+        events,
+        generated_at: Utc::now(),
+    };
+
+    match params.get("format").map(String::as_str) {
+        None | Some("json") => Ok(Json(report).into_response()),
+        Some("csv") => {
+            let filename = format!(
+                "key-usage-{}.csv",
+                report.endpoint_id.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "_")
+            );
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "text/csv".to_string()),
+                    (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+                ],
+                csv(&report),
+            )
+                .into_response())
+        }
+        Some(_) => Err(StatusCode::BAD_REQUEST),
+    }
+}
+
+fn csv(report: &KeyUsageReport) -> String {
+    let mut out = String::from("endpoint_id,at,action,actor,public_key\r\n");
+    for entry in &report.events {
+        let fields = [
+            csv_field(&report.endpoint_id),
+            entry.at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            csv_field(&entry.action),
+            csv_field(&entry.actor),
+            csv_field(entry.details.as_deref().unwrap_or("")),
+        ];
+        out.push_str(&fields.join(","));
+        out.push_str("\r\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_32_hex_bytes() {
+        assert!(well_formed(&"ab".repeat(32)));
+        assert!(!well_formed(&"ab".repeat(31)));
+        assert!(!well_formed(&"ab".repeat(33)));
+        assert!(!well_formed(&"zz".repeat(32)));
+        assert!(!well_formed(""));
+    }
+
+    #[test]
+    fn csv_has_a_row_per_key_event() {
+        let at = DateTime::parse_from_rfc3339("2026-01-02T03:04:05Z").unwrap().with_timezone(&Utc);
+        let report = KeyUsageReport {
+            endpoint_id: "alice".into(),
+            public_key: Some("ab".repeat(32)),
+            events: vec![AuditEntry {
+                id: "1".into(),
+                entity_id: "alice".into(),
+                action: "key.rejected".into(),
+                actor: KEY_ACTOR.into(),
+                details: Some("cd".repeat(32)),
+                at,
+            }],
+            generated_at: at,
+        };
+        assert_eq!(
+            csv(&report),
+            format!(
+                "endpoint_id,at,action,actor,public_key\r\nalice,2026-01-02T03:04:05.000Z,key.rejected,signatures,{}\r\n",
+                "cd".repeat(32)
+            )
+        );
+    }
+}
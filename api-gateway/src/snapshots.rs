@@ -0,0 +1,465 @@
+use chrono::{DateTime, Utc};
+use scylla::Session;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::events::{self, Event, EventKind};
+use tx_core::{ChainLink, InclusionProof};
+use crate::projections;
+use crate::{timestamp_from_millis, AppState, EndpointStats, Transaction};
+
+const SNAPSHOT_SCOPE: &str = "ledger";
+/// The encoding `state_hash` is over: 1 since amounts are held as exact
+/// minor units. Snapshots from before carry no version.
+const STATE_VERSION: i32 = 1;
+
+/// How often the gateway snapshots its projections,
+/// `PROJECTION_SNAPSHOT_INTERVAL_SECS` (3600).
+fn snapshot_interval() -> Duration {
+    let secs = std::env::var("PROJECTION_SNAPSHOT_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(3600);
+    Duration::from_secs(secs)
+}
+
+/// The projections' state after applying every event up to `offsets`.
+///
+/// `root` is the checkpoint root of the event log at those offsets: a hash
+/// chain per partition over each event, combined across partitions. A
+/// snapshot is only trusted while its chain heads still hash to the root,
+/// the log still holds the last event it covers in each partition, and
+/// its own contents still hash to `state_hash`. The next snapshot extends
+/// the heads over the events after `offsets`, so only those are read.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProjectionSnapshot {
+    pub snapshot_id: String,
+    pub taken_at: DateTime<Utc>,
+    pub offsets: BTreeMap<String, i64>,
+    pub endpoints: BTreeMap<String, EndpointStats>,
+    pub transactions: BTreeMap<String, Transaction>,
+    /// Each partition's chain head at `offsets`; `root` is over these.
+    pub chains: BTreeMap<String, String>,
+    pub root: String,
+    pub state_hash: String,
+}
+
+fn hex(digest: impl AsRef<[u8]>) -> String {
+    digest.as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn chain_link(event: &Event) -> ChainLink {
+    ChainLink {
+        offset: event.offset,
+        event_id: event.event_id.clone(),
+        event_type: event.event_type.clone(),
+        tx_hash: event.tx_hash.clone(),
+        payload: event.payload.to_string(),
+    }
+}
+
+fn state_hash(endpoints: &BTreeMap<String, EndpointStats>, transactions: &BTreeMap<String, Transaction>) -> String {
+    let state = serde_json::json!({ "endpoints": endpoints, "transactions": transactions });
+    hex(Sha256::digest(state.to_string().as_bytes()))
+}
+
+/// `state_hash` over the JSON a snapshot was stored as, for snapshots
+/// taken before `STATE_VERSION`.
+fn stored_state_hash(endpoints: &str, transactions: &BTreeMap<String, serde_json::Value>) -> Result<String, String> {
+    let endpoints: serde_json::Value = serde_json::from_str(endpoints).map_err(|e| e.to_string())?;
+    let state = serde_json::json!({ "endpoints": endpoints, "transactions": transactions });
+    Ok(hex(Sha256::digest(state.to_string().as_bytes())))
+}
+
+/// Folds one event into snapshot state, mirroring `projections::apply`.
+fn fold(snapshot: &mut ProjectionSnapshot, event: &Event) {
+    match event.event_type.parse() {
+        Ok(EventKind::TransactionCreated) | Ok(EventKind::Reversed) | Ok(EventKind::StatusChanged) => {}
+        _ => return,
+    }
+    let transaction: Transaction = match serde_json::from_value(event.payload.clone()) {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            error!("Unreadable payload in event {}#{}: {}", event.partition_key, event.offset, e);
+            return;
+        }
+    };
+
+    if event.event_type != EventKind::StatusChanged.as_str() {
+        let mut parties = vec![transaction.from_endpoint.clone()];
+        if transaction.to_endpoint != transaction.from_endpoint {
+            parties.push(transaction.to_endpoint.clone());
+        }
+        for endpoint_id in parties {
+            let applied = snapshot
+                .endpoints
+                .entry(endpoint_id.clone())
+                .or_insert_with(|| EndpointStats::empty(&endpoint_id))
+                .apply(&transaction.from_endpoint, &transaction.to_endpoint, &transaction.amount, transaction.kind);
+            if let Err(e) = applied {
+                error!("Stats for {} at event {}#{}: {}", endpoint_id, event.partition_key, event.offset, e);
+            }
+        }
+    }
+    snapshot.transactions.insert(transaction.id.clone(), transaction);
+}
+
+/// The events past the snapshot's offsets, partition by partition, read
+/// from each partition's tail only. Fails if a partition the snapshot
+/// covers no longer reaches its offset there.
+async fn load_tail(
+    session: &Session,
+    partitions: &BTreeMap<String, i64>,
+    snapshot: &ProjectionSnapshot,
+) -> Result<Vec<Event>, String> {
+    let mut tail = Vec::new();
+    for partition_key in partitions.keys() {
+        let Some(last) = snapshot.offsets.get(partition_key).copied() else {
+            tail.extend(events::load_partition(session, partition_key, 0).await?);
+            continue;
+        };
+        // From the last covered event on, to check it is still there
+        let mut partition = events::load_partition(session, partition_key, last - 1).await?.into_iter();
+        if partition.next().map(|event| event.offset) != Some(last) {
+            return Err(format!("event {}#{} covered by the snapshot is missing", partition_key, last));
+        }
+        tail.extend(partition);
+    }
+    Ok(tail)
+}
+
+/// The latest snapshot, and whether its state hash was just moved to
+/// `STATE_VERSION`: a snapshot from before is checked against the JSON it
+/// was stored as, and its `state_hash` replaced by the current encoding's
+/// if that matches, so `verify` treats both alike.
+async fn load_latest(session: &Session) -> Result<Option<(ProjectionSnapshot, bool)>, String> {
+    let row = session
+        .query(
+            db::idempotent("SELECT snapshot_id, taken_at, offsets, endpoints, chains, root, state_hash, state_version
+             FROM transactions.projection_snapshots WHERE scope = ? LIMIT 1"),
+            (SNAPSHOT_SCOPE,),
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .rows
+        .and_then(|rows| rows.into_iter().next());
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let (snapshot_id, taken_at, offsets, endpoints_json, chains, root, stored_hash, state_version) = row
+        .into_typed::<(Uuid, i64, String, String, Option<String>, String, String, Option<i32>)>()
+        .map_err(|e| e.to_string())?;
+
+    let rows = session
+        .query(
+            db::scan("SELECT transaction_id, payload FROM transactions.projection_snapshot_transactions WHERE snapshot_id = ?"),
+            (snapshot_id,),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut transactions = BTreeMap::new();
+    let mut payloads = BTreeMap::new();
+    for row in rows.rows.unwrap_or_default() {
+        let (transaction_id, payload) = row.into_typed::<(String, String)>().map_err(|e| e.to_string())?;
+        let payload: serde_json::Value = serde_json::from_str(&payload).map_err(|e| e.to_string())?;
+        let transaction = serde_json::from_value(payload.clone()).map_err(|e| e.to_string())?;
+        transactions.insert(transaction_id.clone(), transaction);
+        payloads.insert(transaction_id, payload);
+    }
+
+    let endpoints: BTreeMap<String, EndpointStats> = serde_json::from_str(&endpoints_json).map_err(|e| e.to_string())?;
+    let rehashed = state_version.is_none_or(|version| version < STATE_VERSION);
+    let current_hash = if rehashed {
+        if stored_state_hash(&endpoints_json, &payloads)? != stored_hash {
+            return Err(format!("snapshot {} contents do not match its state hash", snapshot_id));
+        }
+        state_hash(&endpoints, &transactions)
+    } else {
+        stored_hash
+    };
+
+    let snapshot = ProjectionSnapshot {
+        snapshot_id: snapshot_id.to_string(),
+        taken_at: timestamp_from_millis(taken_at),
+        offsets: serde_json::from_str(&offsets).map_err(|e| e.to_string())?,
+        endpoints,
+        transactions,
+        // Empty for snapshots taken before chain heads were kept
+        chains: chains
+            .map(|chains| serde_json::from_str(&chains))
+            .transpose()
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default(),
+        root,
+        state_hash: current_hash,
+    };
+    Ok(Some((snapshot, rehashed)))
+}
+
+/// Records a snapshot's state hash in the current encoding.
+async fn store_state_hash(session: &Session, snapshot: &ProjectionSnapshot) -> Result<(), String> {
+    let snapshot_id = Uuid::parse_str(&snapshot.snapshot_id).map_err(|e| e.to_string())?;
+    session
+        .query(
+            "UPDATE transactions.projection_snapshots SET state_hash = ?, state_version = ?
+             WHERE scope = ? AND taken_at = ? AND snapshot_id = ?",
+            (
+                &snapshot.state_hash,
+                STATE_VERSION,
+                SNAPSHOT_SCOPE,
+                snapshot.taken_at.timestamp_millis(),
+                snapshot_id,
+            ),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Checks a snapshot against its own contents, and its chain heads against
+/// its checkpoint root and the partitions in the log. The events before
+/// the heads are not re-read; a full rebuild replays them.
+fn verify(snapshot: &ProjectionSnapshot, partitions: &BTreeMap<String, i64>) -> Result<(), String> {
+    if state_hash(&snapshot.endpoints, &snapshot.transactions) != snapshot.state_hash {
+        return Err(format!("snapshot {} contents do not match its state hash", snapshot.snapshot_id));
+    }
+    if !snapshot.offsets.keys().eq(snapshot.chains.keys()) {
+        return Err(format!("snapshot {} has no chain heads for its offsets", snapshot.snapshot_id));
+    }
+    if tx_core::checkpoint_root(&snapshot.chains) != snapshot.root {
+        return Err(format!("chain heads of snapshot {} do not match its checkpoint root", snapshot.snapshot_id));
+    }
+    for (partition_key, last) in &snapshot.offsets {
+        if partitions.get(partition_key).is_none_or(|allocated| allocated < last) {
+            return Err(format!(
+                "event log of {} no longer reaches snapshot {}",
+                partition_key, snapshot.snapshot_id
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The latest snapshot that still verifies, if any, and the events after it.
+async fn latest_verified(
+    session: &Session,
+    partitions: &BTreeMap<String, i64>,
+) -> Option<(ProjectionSnapshot, Vec<Event>)> {
+    let (snapshot, rehashed) = match load_latest(session).await {
+        Ok(Some(latest)) => latest,
+        Ok(None) => return None,
+        Err(e) => {
+            warn!("Failed to load projection snapshot: {}", e);
+            return None;
+        }
+    };
+    match verify(&snapshot, partitions) {
+        Ok(()) => {}
+        Err(e) => {
+            error!("Ignoring projection snapshot: {}", e);
+            return None;
+        }
+    }
+    if rehashed {
+        match store_state_hash(session, &snapshot).await {
+            Ok(()) => info!("Projection snapshot {} rehashed to state version {}", snapshot.snapshot_id, STATE_VERSION),
+            Err(e) => warn!("Failed to rehash projection snapshot {}: {}", snapshot.snapshot_id, e),
+        }
+    }
+    match load_tail(session, partitions, &snapshot).await {
+        Ok(tail) => Some((snapshot, tail)),
+        Err(e) => {
+            error!("Ignoring projection snapshot: {}", e);
+            None
+        }
+    }
+}
+
+/// Takes a new snapshot from the latest verified one plus the event tail,
+/// extending its chain heads over the tail.
+pub async fn take_snapshot(session: &Session) -> Result<ProjectionSnapshot, String> {
+    let partitions = events::partitions(session).await?;
+    let (mut snapshot, tail) = match latest_verified(session, &partitions).await {
+        Some(latest) => latest,
+        None => {
+            let empty = ProjectionSnapshot {
+                snapshot_id: String::new(),
+                taken_at: DateTime::default(),
+                offsets: BTreeMap::new(),
+                endpoints: BTreeMap::new(),
+                transactions: BTreeMap::new(),
+                chains: BTreeMap::new(),
+                root: String::new(),
+                state_hash: String::new(),
+            };
+            let log = load_tail(session, &partitions, &empty).await?;
+            (empty, log)
+        }
+    };
+
+    for event in tail {
+        fold(&mut snapshot, &event);
+        let chain = snapshot.chains.entry(event.partition_key.clone()).or_default();
+        *chain = chain_link(&event).extend(chain);
+        snapshot.offsets.insert(event.partition_key.clone(), event.offset);
+        snapshot.taken_at = snapshot.taken_at.max(event.at);
+    }
+
+    let snapshot_id = Uuid::new_v4();
+    snapshot.snapshot_id = snapshot_id.to_string();
+    snapshot.root = tx_core::checkpoint_root(&snapshot.chains);
+    snapshot.state_hash = state_hash(&snapshot.endpoints, &snapshot.transactions);
+
+    for (transaction_id, transaction) in &snapshot.transactions {
+        let payload = serde_json::to_string(transaction).map_err(|e| e.to_string())?;
+        session
+            .query(
+                "INSERT INTO transactions.projection_snapshot_transactions (snapshot_id, transaction_id, payload)
+                 VALUES (?, ?, ?)",
+                (snapshot_id, transaction_id, payload),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    // Written last, so a snapshot is never visible before its transactions
+    session
+        .query(
+            "INSERT INTO transactions.projection_snapshots
+             (scope, taken_at, snapshot_id, offsets, endpoints, chains, root, state_hash, state_version)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            (
+                SNAPSHOT_SCOPE,
+                snapshot.taken_at.timestamp_millis(),
+                snapshot_id,
+                serde_json::to_string(&snapshot.offsets).map_err(|e| e.to_string())?,
+                serde_json::to_string(&snapshot.endpoints).map_err(|e| e.to_string())?,
+                serde_json::to_string(&snapshot.chains).map_err(|e| e.to_string())?,
+                &snapshot.root,
+                &snapshot.state_hash,
+                STATE_VERSION,
+            ),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    info!(
+        "Projection snapshot {} covers {} transactions, root {}",
+        snapshot.snapshot_id,
+        snapshot.transactions.len(),
+        snapshot.root
+    );
+    Ok(snapshot)
+}
+
+/// Proof that `tx` is covered by the latest snapshot's checkpoint. None
+/// until a snapshot covers it, or if the latest predates kept chain heads.
+pub async fn inclusion_proof(session: &Session, tx: &Transaction) -> Result<Option<InclusionProof>, String> {
+    let row = session
+        .query(
+            db::idempotent("SELECT snapshot_id, offsets, chains, root FROM transactions.projection_snapshots WHERE scope = ? LIMIT 1"),
+            (SNAPSHOT_SCOPE,),
+        )
+        .await
+        .map_err(|e| e.to_string())?
+        .rows
+        .and_then(|rows| rows.into_iter().next());
+    let Some(row) = row else {
+        return Ok(None);
+    };
+    let (snapshot_id, offsets, chains, root) = row
+        .into_typed::<(Uuid, String, Option<String>, String)>()
+        .map_err(|e| e.to_string())?;
+    let Some(chains) = chains else {
+        return Ok(None);
+    };
+    let offsets: BTreeMap<String, i64> = serde_json::from_str(&offsets).map_err(|e| e.to_string())?;
+    let mut chains: BTreeMap<String, String> = serde_json::from_str(&chains).map_err(|e| e.to_string())?;
+
+    // Events live in their sender's partition
+    let partition_key = tx.from_endpoint.clone();
+    let Some(last) = offsets.get(&partition_key).copied() else {
+        return Ok(None);
+    };
+    let mut partition = events::load_events(session, Some(&partition_key), 0, i32::MAX).await?;
+    partition.retain(|event| event.offset <= last);
+    partition.sort_by_key(|event| event.offset);
+
+    let Some(index) = partition.iter().position(|event| {
+        event.transaction_id == tx.id && event.event_type == EventKind::TransactionCreated.as_str()
+    }) else {
+        return Ok(None);
+    };
+    let prev_chain = partition[..index]
+        .iter()
+        .fold(String::new(), |chain, event| chain_link(event).extend(&chain));
+    let links: Vec<ChainLink> = partition[index..].iter().map(chain_link).collect();
+
+    let head = links.iter().fold(prev_chain.clone(), |chain, link| link.extend(&chain));
+    if chains.remove(&partition_key).as_ref() != Some(&head) {
+        return Err(format!(
+            "event log of {} no longer matches snapshot {}",
+            partition_key, snapshot_id
+        ));
+    }
+
+    Ok(Some(InclusionProof {
+        snapshot_id: snapshot_id.to_string(),
+        root,
+        partition_key,
+        prev_chain,
+        links,
+        other_chains: chains,
+    }))
+}
+
+pub async fn run_snapshots(state: AppState) {
+    let interval = snapshot_interval();
+    loop {
+        tokio::time::sleep(interval).await;
+        if !state.leadership.is_leader() {
+            continue;
+        }
+        if let Err(e) = take_snapshot(&state.session).await {
+            warn!("Projection snapshot failed: {}", e);
+        }
+    }
+}
+
+/// Rebuilds the projections from the latest verified snapshot plus the
+/// event tail, or from the whole log when `full` is set or no snapshot
+/// verifies.
+///
+/// Restoring a snapshot collapses each endpoint's history up to the
+/// snapshot into a single ledger row, so `as_of` queries earlier than the
+/// snapshot only resolve at that granularity. Use a full rebuild when
+/// exact history is needed.
+pub async fn rebuild(session: &Session, full: bool) -> Result<(), String> {
+    let partitions = events::partitions(session).await?;
+    let latest = if full { None } else { latest_verified(session, &partitions).await };
+    let Some((snapshot, tail)) = latest else {
+        let replayed = projections::rebuild(session).await?;
+        info!("Rebuilt projections from {} events", replayed);
+        return Ok(());
+    };
+
+    projections::restore(session, &snapshot).await?;
+    for event in &tail {
+        if let Err(e) = projections::apply(session, event).await {
+            error!("Skipping event {}#{} during rebuild: {}", event.partition_key, event.offset, e);
+        }
+    }
+
+    info!(
+        "Rebuilt projections from snapshot {} plus {} events",
+        snapshot.snapshot_id,
+        tail.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,344 @@
+//! Account statements for accounting software: `GET
+//! /api/endpoints/:id/statement?format=<ofx|qif|camt053>&from=&to=` (RFC
+//! 3339, default the 30 days up to now) renders an endpoint's ledger over
+//! the period as a file to import.
+//!
+//! - `ofx`: OFX 2.2 bank statement (`STMTRS`), closing balance as
+//!   `LEDGERBAL`
+//! - `qif`: QIF `!Type:Bank` records. QIF has no balances; import it into
+//!   an account opened at the statement's opening balance
+//! - `camt053`: ISO 20022 `camt.053.001.08` with `OPBD` and `CLBD` balances
+//!
+//! Entries are the endpoint's ledger movements as its balance counts them:
+//! a chargeback is booked against the side it debits, and netted payments
+//! are left out because their net transfer carries the funds.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use std::collections::HashMap;
+use tracing::error;
+use tx_core::money::DECIMALS;
+use tx_core::{Money, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::consents::{self, Scope, ThirdPartyAccess};
+use crate::{projections, AppState, TransactionKind};
+
+const DEFAULT_PERIOD_DAYS: i64 = 30;
+/// Longest period one statement may cover; each one replays the log.
+const MAX_PERIOD_DAYS: i64 = 366;
+/// Fraction digits ISO 20022 amounts allow.
+const CAMT_FRACTION_DIGITS: u32 = 5;
+/// OFX `NAME` is at most 32 characters.
+const OFX_NAME_LENGTH: usize = 32;
+/// ISO 20022 references are `Max35Text`.
+const CAMT_REFERENCE_LENGTH: usize = 35;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Ofx,
+    Qif,
+    Camt053,
+}
+
+impl std::str::FromStr for Format {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ofx" => Ok(Format::Ofx),
+            "qif" => Ok(Format::Qif),
+            "camt053" | "camt.053" => Ok(Format::Camt053),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Format {
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Ofx => "application/x-ofx",
+            Format::Qif => "application/qif",
+            Format::Camt053 => "application/xml",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Ofx => "ofx",
+            Format::Qif => "qif",
+            Format::Camt053 => "xml",
+        }
+    }
+}
+
+/// One ledger movement; `minor` is signed from the endpoint's side.
+struct Entry {
+    id: String,
+    at: DateTime<Utc>,
+    counterparty: String,
+    kind: TransactionKind,
+    minor: i64,
+}
+
+struct Statement {
+    endpoint_id: String,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    opening: Money,
+    closing: Money,
+    /// Oldest first.
+    entries: Vec<Entry>,
+}
+
+pub async fn get_statement(
+    State(state): State<AppState>,
+    access: Option<Extension<ThirdPartyAccess>>,
+    Path(endpoint_id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, StatusCode> {
+    consents::confine(access.as_deref(), Some(&endpoint_id), Scope::Statements)?;
+    let format: Format = params
+        .get("format")
+        .map_or(Ok(Format::Ofx), |format| format.parse())
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let to = parse_time(&params, "to")?.unwrap_or_else(Utc::now);
+    let from = parse_time(&params, "from")?.unwrap_or(to - Duration::days(DEFAULT_PERIOD_DAYS));
+    if from >= to || to - from > Duration::days(MAX_PERIOD_DAYS) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let statement = statement(&state, endpoint_id, from, to).await?;
+    let body = match format {
+        Format::Ofx => ofx(&statement),
+        Format::Qif => qif(&statement),
+        Format::Camt053 => camt053(&statement),
+    };
+    let filename = format!(
+        "{}-{}-{}.{}",
+        statement.endpoint_id.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-' && c != '_', "_"),
+        from.format("%Y%m%d"),
+        to.format("%Y%m%d"),
+        format.extension()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn parse_time(params: &HashMap<String, String>, name: &str) -> Result<Option<DateTime<Utc>>, StatusCode> {
+    params
+        .get(name)
+        .map(|value| {
+            DateTime::parse_from_rfc3339(value)
+                .map(|at| at.with_timezone(&Utc))
+                .map_err(|_| StatusCode::BAD_REQUEST)
+        })
+        .transpose()
+}
+
+async fn statement(state: &AppState, endpoint_id: String, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<Statement, StatusCode> {
+    // Balances count ledger rows up to and including their instant
+    let opening = crate::endpoint_balance(&state.session, endpoint_id.clone(), from - Duration::milliseconds(1)).await?;
+    let closing = crate::endpoint_balance(&state.session, endpoint_id.clone(), to).await?;
+
+    let transactions = projections::transactions_as_of(&state.session, Some(&endpoint_id), to)
+        .await
+        .map_err(|e| {
+            error!("Failed to load transactions for {}'s statement: {}", endpoint_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let mut entries = Vec::new();
+    for transaction in transactions.into_iter().rev() {
+        if transaction.timestamp < from
+            || transaction.kind == TransactionKind::Netted
+            || transaction.from_endpoint == transaction.to_endpoint
+        {
+            continue;
+        }
+        let amount = transaction.amount.minor();
+        let (counterparty, minor) = if transaction.from_endpoint == endpoint_id {
+            (transaction.to_endpoint, -amount)
+        } else {
+            (transaction.from_endpoint, amount)
+        };
+        entries.push(Entry { id: transaction.id, at: transaction.timestamp, counterparty, kind: transaction.kind, minor });
+    }
+
+    Ok(Statement { endpoint_id, from, to, opening: opening.balance, closing: closing.balance, entries })
+}
+
+fn ofx(statement: &Statement) -> String {
+    let date = |at: &DateTime<Utc>| at.format("%Y%m%d%H%M%S%.3f[0:GMT]").to_string();
+    let status = "<STATUS><CODE>0</CODE><SEVERITY>INFO</SEVERITY></STATUS>";
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n");
+    out.push_str("<?OFX OFXHEADER=\"200\" VERSION=\"220\" SECURITY=\"NONE\" OLDFILEUID=\"NONE\" NEWFILEUID=\"NONE\"?>\n");
+    out.push_str("<OFX>\n");
+    out.push_str(&format!(
+        "<SIGNONMSGSRSV1><SONRS>{}<DTSERVER>{}</DTSERVER><LANGUAGE>ENG</LANGUAGE></SONRS></SIGNONMSGSRSV1>\n",
+        status,
+        date(&Utc::now())
+    ));
+    out.push_str(&format!("<BANKMSGSRSV1><STMTTRNRS><TRNUID>{}</TRNUID>{}<STMTRS>\n", Uuid::new_v4(), status));
+    out.push_str(&format!("<CURDEF>{}</CURDEF>\n", NATIVE_ASSET));
+    out.push_str(&format!(
+        "<BANKACCTFROM><BANKID>p2p-relayer</BANKID><ACCTID>{}</ACCTID><ACCTTYPE>CHECKING</ACCTTYPE></BANKACCTFROM>\n",
+        escape(&statement.endpoint_id)
+    ));
+    out.push_str(&format!(
+        "<BANKTRANLIST><DTSTART>{}</DTSTART><DTEND>{}</DTEND>\n",
+        date(&statement.from),
+        date(&statement.to)
+    ));
+    for entry in &statement.entries {
+        let kind = match (entry.kind, entry.minor >= 0) {
+            (TransactionKind::Deposit, _) => "DEP",
+            (_, true) => "CREDIT",
+            (_, false) => "DEBIT",
+        };
+        out.push_str(&format!(
+            "<STMTTRN><TRNTYPE>{}</TRNTYPE><DTPOSTED>{}</DTPOSTED><TRNAMT>{}</TRNAMT><FITID>{}</FITID><NAME>{}</NAME><MEMO>{}</MEMO></STMTTRN>\n",
+            kind,
+            date(&entry.at),
+            decimal(entry.minor, DECIMALS),
+            escape(&entry.id),
+            escape(&entry.counterparty.chars().take(OFX_NAME_LENGTH).collect::<String>()),
+            escape(&memo(entry))
+        ));
+    }
+    out.push_str("</BANKTRANLIST>\n");
+    out.push_str(&format!(
+        "<LEDGERBAL><BALAMT>{}</BALAMT><DTASOF>{}</DTASOF></LEDGERBAL>\n",
+        decimal(statement.closing.minor(), DECIMALS),
+        date(&statement.to)
+    ));
+    out.push_str("</STMTRS></STMTTRNRS></BANKMSGSRSV1>\n</OFX>\n");
+    out
+}
+
+fn qif(statement: &Statement) -> String {
+    let mut out = String::from("!Type:Bank\n");
+    for entry in &statement.entries {
+        out.push_str(&format!("D{}\n", entry.at.format("%m/%d/%Y")));
+        out.push_str(&format!("T{}\n", decimal(entry.minor, DECIMALS)));
+        out.push_str(&format!("N{}\n", single_line(&entry.id)));
+        out.push_str(&format!("P{}\n", single_line(&entry.counterparty)));
+        out.push_str(&format!("M{}\n", memo(entry)));
+        out.push_str("^\n");
+    }
+    out
+}
+
+fn camt053(statement: &Statement) -> String {
+    let now = Utc::now();
+    let time = |at: &DateTime<Utc>| at.to_rfc3339_opts(SecondsFormat::Millis, true);
+    let amount = |minor: i64| {
+        let indicator = if minor < 0 { "DBIT" } else { "CRDT" };
+        (format!("<Amt Ccy=\"{}\">{}</Amt>", NATIVE_ASSET, decimal(minor.abs(), CAMT_FRACTION_DIGITS)), indicator)
+    };
+    let balance = |code: &str, money: &Money, at: &DateTime<Utc>| {
+        let (amt, indicator) = amount(money.minor());
+        format!(
+            "<Bal><Tp><CdOrPrtry><Cd>{}</Cd></CdOrPrtry></Tp>{}<CdtDbtInd>{}</CdtDbtInd><Dt><DtTm>{}</DtTm></Dt></Bal>\n",
+            code,
+            amt,
+            indicator,
+            time(at)
+        )
+    };
+
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<Document xmlns=\"urn:iso:std:iso:20022:tech:xsd:camt.053.001.08\">\n<BkToCstmrStmt>\n");
+    out.push_str(&format!(
+        "<GrpHdr><MsgId>{}</MsgId><CreDtTm>{}</CreDtTm></GrpHdr>\n",
+        Uuid::new_v4().simple(),
+        time(&now)
+    ));
+    out.push_str(&format!(
+        "<Stmt>\n<Id>{}</Id><CreDtTm>{}</CreDtTm><FrToDt><FrDtTm>{}</FrDtTm><ToDtTm>{}</ToDtTm></FrToDt>\n",
+        Uuid::new_v4().simple(),
+        time(&now),
+        time(&statement.from),
+        time(&statement.to)
+    ));
+    out.push_str(&format!(
+        "<Acct><Id><Othr><Id>{}</Id></Othr></Id><Ccy>{}</Ccy></Acct>\n",
+        escape(&reference(&statement.endpoint_id)),
+        NATIVE_ASSET
+    ));
+    out.push_str(&balance("OPBD", &statement.opening, &statement.from));
+    out.push_str(&balance("CLBD", &statement.closing, &statement.to));
+    out.push_str(&format!(
+        "<TxsSummry><TtlNtries><NbOfNtries>{}</NbOfNtries></TtlNtries></TxsSummry>\n",
+        statement.entries.len()
+    ));
+    for entry in &statement.entries {
+        let (amt, indicator) = amount(entry.minor);
+        let reference = escape(&reference(&entry.id));
+        let party = if entry.minor < 0 { "Cdtr" } else { "Dbtr" };
+        out.push_str(&format!(
+            "<Ntry><NtryRef>{reference}</NtryRef>{amt}<CdtDbtInd>{indicator}</CdtDbtInd><Sts><Cd>BOOK</Cd></Sts>\
+             <BookgDt><DtTm>{at}</DtTm></BookgDt><ValDt><DtTm>{at}</DtTm></ValDt><AcctSvcrRef>{reference}</AcctSvcrRef>\
+             <BkTxCd><Prtry><Cd>{kind}</Cd></Prtry></BkTxCd><NtryDtls><TxDtls><Refs><EndToEndId>{reference}</EndToEndId></Refs>\
+             <RltdPties><{party}><Pty><Nm>{name}</Nm></Pty></{party}></RltdPties></TxDtls></NtryDtls></Ntry>\n",
+            at = time(&entry.at),
+            kind = entry.kind.as_str(),
+            name = escape(&entry.counterparty),
+        ));
+    }
+    out.push_str("</Stmt>\n</BkToCstmrStmt>\n</Document>\n");
+    out
+}
+
+fn memo(entry: &Entry) -> String {
+    let direction = if entry.minor < 0 { "to" } else { "from" };
+    single_line(&format!("{} {} {}", entry.kind.as_str(), direction, entry.counterparty))
+}
+
+/// `minor` in major units with 2 to `max_fraction` fraction digits,
+/// rounded half away from zero past that.
+pub fn decimal(minor: i64, max_fraction: u32) -> String {
+    let mut minor = minor as i128;
+    if max_fraction < DECIMALS {
+        let step = 10i128.pow(DECIMALS - max_fraction);
+        minor = (minor + minor.signum() * step / 2) / step * step;
+    }
+    let per_major = 10u128.pow(DECIMALS);
+    let sign = if minor < 0 { "-" } else { "" };
+    let abs = minor.unsigned_abs();
+    let fraction = format!("{:0width$}", abs % per_major, width = DECIMALS as usize);
+    format!("{}{}.{:0<2}", sign, abs / per_major, fraction.trim_end_matches('0'))
+}
+
+/// Ids as ISO 20022 references: hyphens dropped, so UUIDs fit, and cut
+/// to the 35 characters allowed.
+fn reference(id: &str) -> String {
+    id.chars().filter(|c| *c != '-').take(CAMT_REFERENCE_LENGTH).collect()
+}
+
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
+}
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
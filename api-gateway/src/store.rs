@@ -0,0 +1,403 @@
+//! Where transactions are kept, behind `TransactionStore`, picked with
+//! `STORAGE_BACKEND`:
+//!
+//! - `scylla` (the default): the event log and its projections in
+//!   ScyllaDB, and every route the gateway has.
+//! - `sqlite`: one file, `SQLITE_PATH` (`gateway.db`), for local
+//!   development.
+//! - `memory`: nothing kept past the process, for tests.
+//!
+//! With `sqlite` or `memory` the gateway needs no database server and runs
+//! in test mode: `serve_standalone` serves health and the transaction
+//! routes (create, read, list, status updates) from the store, with
+//! creation validated but not risk-scored, screened or limited. The ledger,
+//! settlement, disputes and the rest are built on ScyllaDB and aren't
+//! served there.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, Query, State},
+    http::{Method, StatusCode},
+    response::Json,
+    routing::{get, patch},
+    Router,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use tower_http::cors::{Any, CorsLayer};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::error::{self, ApiError};
+use crate::events::EventKind;
+use crate::pagination;
+use crate::repository::Repository;
+use crate::validation;
+use crate::{health_check, IngestResponse, Transaction, TransactionStatus};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Scylla,
+    Sqlite,
+    Memory,
+}
+
+impl Backend {
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("STORAGE_BACKEND").as_deref() {
+            Err(_) | Ok("scylla") => Ok(Backend::Scylla),
+            Ok("sqlite") => Ok(Backend::Sqlite),
+            Ok("memory") => Ok(Backend::Memory),
+            Ok(other) => Err(format!("Unknown STORAGE_BACKEND {:?} (scylla, sqlite or memory)", other)),
+        }
+    }
+}
+
+#[async_trait]
+pub trait TransactionStore: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Records a new transaction; 409 if its id is taken.
+    async fn insert(&self, transaction: &Transaction) -> Result<(), ApiError>;
+
+    async fn get(&self, id: Uuid) -> Result<Option<Transaction>, ApiError>;
+
+    /// The newest `limit`, only those `endpoint` sent or received if given.
+    async fn list(&self, endpoint: Option<&str>, limit: usize) -> Result<Vec<Transaction>, ApiError>;
+
+    /// Records the status `transaction` has moved to.
+    async fn update_status(&self, transaction: &Transaction) -> Result<(), ApiError>;
+}
+
+/// The event log and its projections; see `projections`.
+pub struct ScyllaStore {
+    session: Repository,
+}
+
+impl ScyllaStore {
+    pub fn new(session: Repository) -> Self {
+        ScyllaStore { session }
+    }
+}
+
+#[async_trait]
+impl TransactionStore for ScyllaStore {
+    fn name(&self) -> &'static str {
+        "scylla"
+    }
+
+    async fn insert(&self, transaction: &Transaction) -> Result<(), ApiError> {
+        crate::insert_transaction(&self.session, transaction).await.map_err(ApiError::from)
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Option<Transaction>, ApiError> {
+        crate::load_transaction(&self.session, id).await
+    }
+
+    async fn list(&self, endpoint: Option<&str>, limit: usize) -> Result<Vec<Transaction>, ApiError> {
+        let limit = i32::try_from(limit).unwrap_or(i32::MAX);
+        let ids = crate::timeline::page(&self.session, endpoint, &pagination::Page::Latest, limit)
+            .await
+            .map_err(|e| {
+                error!("Database query error: {}", e);
+                ApiError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+            })?;
+        crate::load_transactions(&self.session, &ids).await
+    }
+
+    async fn update_status(&self, transaction: &Transaction) -> Result<(), ApiError> {
+        crate::record_event(&self.session, EventKind::StatusChanged, transaction).await.map_err(ApiError::from)
+    }
+}
+
+/// Newest first, as every store lists.
+fn newest_first(transactions: &mut [Transaction]) {
+    transactions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| b.id.cmp(&a.id)));
+}
+
+#[derive(Default)]
+pub struct MemoryStore {
+    transactions: RwLock<HashMap<String, Transaction>>,
+}
+
+#[async_trait]
+impl TransactionStore for MemoryStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn insert(&self, transaction: &Transaction) -> Result<(), ApiError> {
+        let mut transactions = self.transactions.write().unwrap();
+        if transactions.contains_key(&transaction.id) {
+            return Err(ApiError::Conflict(format!("Transaction {} already exists", transaction.id)));
+        }
+        transactions.insert(transaction.id.clone(), transaction.clone());
+        Ok(())
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Option<Transaction>, ApiError> {
+        Ok(self.transactions.read().unwrap().get(&id.to_string()).cloned())
+    }
+
+    async fn list(&self, endpoint: Option<&str>, limit: usize) -> Result<Vec<Transaction>, ApiError> {
+        let mut transactions: Vec<Transaction> = self
+            .transactions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|tx| endpoint.is_none_or(|endpoint| tx.from_endpoint == endpoint || tx.to_endpoint == endpoint))
+            .cloned()
+            .collect();
+        newest_first(&mut transactions);
+        transactions.truncate(limit);
+        Ok(transactions)
+    }
+
+    async fn update_status(&self, transaction: &Transaction) -> Result<(), ApiError> {
+        match self.transactions.write().unwrap().get_mut(&transaction.id) {
+            Some(stored) => {
+                stored.status = transaction.status;
+                Ok(())
+            }
+            None => Err(ApiError::NotFound(format!("Transaction {} not found", transaction.id))),
+        }
+    }
+}
+
+/// Each transaction as JSON, next to the columns it is looked up by.
+/// rusqlite blocks, so every query runs on the blocking pool.
+pub struct SqliteStore {
+    connection: Arc<Mutex<Connection>>,
+}
+
+fn sqlite_error(e: impl std::fmt::Display) -> ApiError {
+    error!("SQLite error: {}", e);
+    ApiError::Status(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self, rusqlite::Error> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS transactions (
+                 id TEXT PRIMARY KEY,
+                 from_endpoint TEXT NOT NULL,
+                 to_endpoint TEXT NOT NULL,
+                 timestamp INTEGER NOT NULL,
+                 body TEXT NOT NULL
+             );
+             CREATE INDEX IF NOT EXISTS transactions_from ON transactions (from_endpoint, timestamp);
+             CREATE INDEX IF NOT EXISTS transactions_to ON transactions (to_endpoint, timestamp);",
+        )?;
+        Ok(SqliteStore { connection: Arc::new(Mutex::new(connection)) })
+    }
+
+    async fn run<T: Send + 'static>(
+        &self,
+        query: impl FnOnce(&Connection) -> Result<T, ApiError> + Send + 'static,
+    ) -> Result<T, ApiError> {
+        let connection = self.connection.clone();
+        tokio::task::spawn_blocking(move || query(&connection.lock().unwrap()))
+            .await
+            .map_err(sqlite_error)?
+    }
+}
+
+fn from_json(body: String) -> Result<Transaction, ApiError> {
+    serde_json::from_str(&body).map_err(sqlite_error)
+}
+
+#[async_trait]
+impl TransactionStore for SqliteStore {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn insert(&self, transaction: &Transaction) -> Result<(), ApiError> {
+        let transaction = transaction.clone();
+        let body = serde_json::to_string(&transaction).map_err(sqlite_error)?;
+        self.run(move |connection| {
+            let inserted = connection
+                .execute(
+                    "INSERT OR IGNORE INTO transactions (id, from_endpoint, to_endpoint, timestamp, body)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        transaction.id,
+                        transaction.from_endpoint,
+                        transaction.to_endpoint,
+                        transaction.timestamp.timestamp_millis(),
+                        body
+                    ],
+                )
+                .map_err(sqlite_error)?;
+            if inserted == 0 {
+                return Err(ApiError::Conflict(format!("Transaction {} already exists", transaction.id)));
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Option<Transaction>, ApiError> {
+        self.run(move |connection| {
+            connection
+                .query_row("SELECT body FROM transactions WHERE id = ?1", params![id.to_string()], |row| row.get(0))
+                .optional()
+                .map_err(sqlite_error)?
+                .map(from_json)
+                .transpose()
+        })
+        .await
+    }
+
+    async fn list(&self, endpoint: Option<&str>, limit: usize) -> Result<Vec<Transaction>, ApiError> {
+        let endpoint = endpoint.map(str::to_string);
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        self.run(move |connection| {
+            let mut statement = connection
+                .prepare(
+                    "SELECT body FROM transactions
+                     WHERE ?1 IS NULL OR from_endpoint = ?1 OR to_endpoint = ?1
+                     ORDER BY timestamp DESC, id DESC LIMIT ?2",
+                )
+                .map_err(sqlite_error)?;
+            let bodies = statement
+                .query_map(params![endpoint, limit], |row| row.get::<_, String>(0))
+                .map_err(sqlite_error)?;
+            let transactions: Result<Vec<Transaction>, ApiError> =
+                bodies.map(|body| from_json(body.map_err(sqlite_error)?)).collect();
+            transactions
+        })
+        .await
+    }
+
+    async fn update_status(&self, transaction: &Transaction) -> Result<(), ApiError> {
+        let id = transaction.id.clone();
+        let body = serde_json::to_string(transaction).map_err(sqlite_error)?;
+        self.run(move |connection| {
+            let updated = connection
+                .execute("UPDATE transactions SET body = ?1 WHERE id = ?2", params![body, id])
+                .map_err(sqlite_error)?;
+            if updated == 0 {
+                return Err(ApiError::NotFound(format!("Transaction {} not found", id)));
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+/// The store for a test-mode backend.
+pub fn standalone(backend: Backend) -> Result<Arc<dyn TransactionStore>, Box<dyn std::error::Error>> {
+    match backend {
+        Backend::Sqlite => {
+            let path = std::env::var("SQLITE_PATH").unwrap_or_else(|_| "gateway.db".to_string());
+            info!("Keeping transactions in SQLite at {}", path);
+            Ok(Arc::new(SqliteStore::open(&path)?))
+        }
+        Backend::Memory => {
+            warn!("Keeping transactions in memory: they are lost on restart");
+            Ok(Arc::new(MemoryStore::default()))
+        }
+        Backend::Scylla => Err("ScyllaDB is not a standalone backend".into()),
+    }
+}
+
+type Store = Arc<dyn TransactionStore>;
+
+/// `POST /api/transactions` in test mode: validated and recorded, with
+/// nothing else checked.
+async fn create_transaction(
+    State(store): State<Store>,
+    Json(transaction): Json<Transaction>,
+) -> Result<(StatusCode, Json<IngestResponse>), ApiError> {
+    validation::transfer(&transaction)?;
+    store.insert(&transaction).await?;
+
+    info!("✅ Transaction {} created", transaction.id);
+    Ok((
+        StatusCode::CREATED,
+        Json(IngestResponse {
+            id: transaction.id,
+            risk_score: 0,
+            risk_reasons: Vec::new(),
+            held: false,
+            tags: Vec::new(),
+        }),
+    ))
+}
+
+/// `GET /api/transactions[?endpoint=&limit=]` in test mode: the newest
+/// page only, without cursors.
+async fn get_transactions(
+    State(store): State<Store>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<Transaction>>, ApiError> {
+    let limit = pagination::limit_from(&params) as usize;
+    let endpoint = params.get("endpoint").map(String::as_str);
+    store.list(endpoint, limit).await.map(Json)
+}
+
+async fn get_transaction(State(store): State<Store>, Path(id): Path<String>) -> Result<Json<Transaction>, ApiError> {
+    store
+        .get(Uuid::parse_str(&id)?)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound(format!("Transaction {} not found", id)))
+}
+
+async fn patch_transaction_status(
+    State(store): State<Store>,
+    Path(id): Path<String>,
+    Json(update): Json<crate::StatusUpdate>,
+) -> Result<Json<Transaction>, ApiError> {
+    if update.status == TransactionStatus::Held {
+        return Err(ApiError::validation("Holds are placed by the gateway, not reported"));
+    }
+    let mut transaction = store
+        .get(Uuid::parse_str(&id)?)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Transaction {} not found", id)))?;
+    if transaction.status == update.status {
+        return Ok(Json(transaction));
+    }
+    transaction.status = transaction
+        .status
+        .transition_to(update.status)
+        .map_err(|e| ApiError::Conflict(format!("Transaction {}: {}", id, e)))?;
+    store.update_status(&transaction).await?;
+
+    info!("Transaction {} is now {}", id, transaction.status);
+    Ok(Json(transaction))
+}
+
+/// Test mode: the transaction routes over `store`, unauthenticated, on
+/// the usual port.
+/// Health and the transaction routes, served from `store`.
+pub fn standalone_router(store: Store) -> Router {
+    Router::new()
+        .route("/api/transactions", get(get_transactions).post(create_transaction))
+        .route("/api/transactions/:id", get(get_transaction))
+        .route("/api/transactions/:id/status", patch(patch_transaction_status))
+        .route("/health", get(health_check))
+        .layer(axum::middleware::from_fn(error::request_ids))
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods([Method::GET, Method::POST, Method::PATCH])
+                .allow_headers(Any)
+                .expose_headers([axum::http::HeaderName::from_static(error::REQUEST_ID_HEADER)]),
+        )
+        .with_state(store)
+}
+
+pub async fn serve_standalone(store: Store) -> Result<(), Box<dyn std::error::Error>> {
+    warn!("Test mode on {}: only health and the transaction routes are served", store.name());
+    let app = standalone_router(store);
+
+    let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await?;
+    info!("🚀 API Gateway running on http://0.0.0.0:3001 (test mode)");
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
+    Ok(())
+}
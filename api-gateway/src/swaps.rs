@@ -0,0 +1,352 @@
+//! Atomic swaps are a two-phase commit run by the gateway. Each party
+//! posts its signed `SwapCommitment`, which locks its leg as a hold; once
+//! both holds are in, the swap moves to `committing` and both legs settle.
+//! Swaps still `prepared` at expiry roll back and release their holds.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::Utc;
+use scylla::Session;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use tx_core::{SwapCommitment, SwapLeg, SwapState, SwapStatus, SwapTerms, NATIVE_ASSET};
+use uuid::Uuid;
+
+use crate::db;
+use crate::repository::Repository;
+use crate::{
+    assets, audit, endpoints, insert_transaction, load_transaction, lwt_applied, signatures, AppState, Transaction,
+    TransactionKind, TransactionStatus,
+};
+
+const SWAP_SWEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+struct SwapRecord {
+    terms: SwapTerms,
+    status: SwapStatus,
+    offer_tx_id: Uuid,
+    ask_tx_id: Uuid,
+}
+
+impl SwapRecord {
+    /// Each leg settles under a fixed id, which also names its hold, so
+    /// settling twice is harmless.
+    fn legs(&self) -> [(&SwapLeg, Uuid); 2] {
+        [(&self.terms.offer, self.offer_tx_id), (&self.terms.ask, self.ask_tx_id)]
+    }
+}
+
+fn db_error<E: std::fmt::Display>(swap_id: Uuid) -> impl Fn(E) -> StatusCode {
+    move |e| {
+        error!("Swap {} query failed: {}", swap_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+}
+
+async fn load_swap(session: &Session, swap_id: Uuid) -> Result<Option<SwapRecord>, StatusCode> {
+    let row = session
+        .query(
+            db::idempotent("SELECT terms, status, offer_tx_id, ask_tx_id FROM transactions.swaps WHERE swap_id = ?"),
+            (swap_id,),
+        )
+        .await
+        .map_err(db_error(swap_id))?
+        .rows
+        .and_then(|rows| rows.into_iter().next())
+        .and_then(|row| row.into_typed::<(String, String, Uuid, Uuid)>().ok());
+
+    let Some((terms, status, offer_tx_id, ask_tx_id)) = row else {
+        return Ok(None);
+    };
+    let terms = serde_json::from_str(&terms).map_err(|e| {
+        error!("Swap {} has unreadable terms: {}", swap_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let status = status.parse().map_err(|e| {
+        error!("Swap {}: {}", swap_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    Ok(Some(SwapRecord { terms, status, offer_tx_id, ask_tx_id }))
+}
+
+async fn committed_parties(session: &Session, swap_id: Uuid) -> Result<Vec<String>, StatusCode> {
+    Ok(session
+        .query(
+            db::idempotent("SELECT party FROM transactions.swap_commitments WHERE swap_id = ?"),
+            (swap_id,),
+        )
+        .await
+        .map_err(db_error(swap_id))?
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(String,)>().ok())
+        .map(|(party,)| party)
+        .collect())
+}
+
+async fn swap_state(session: &Session, swap_id: Uuid) -> Result<SwapState, StatusCode> {
+    let record = load_swap(session, swap_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    Ok(SwapState {
+        terms: record.terms,
+        status: record.status,
+        held_by: committed_parties(session, swap_id).await?,
+    })
+}
+
+async fn transition(session: &Session, swap_id: Uuid, from: SwapStatus, to: SwapStatus) -> Result<bool, StatusCode> {
+    let result = session
+        .query(
+            "UPDATE transactions.swaps SET status = ?, updated_at = ? WHERE swap_id = ? IF status = ?",
+            (to.as_str(), Utc::now().timestamp_millis(), swap_id, from.as_str()),
+        )
+        .await
+        .map_err(db_error(swap_id))?;
+    Ok(lwt_applied(result))
+}
+
+async fn release_holds(session: &Session, record: &SwapRecord) -> Result<(), StatusCode> {
+    for (leg, hold_id) in record.legs() {
+        assets::release_hold(session, &leg.from_endpoint, &leg.asset, hold_id).await?;
+    }
+    Ok(())
+}
+
+async fn settle_leg(session: &Repository, leg: &SwapLeg, tx_id: Uuid, swap_id: Uuid) -> Result<(), StatusCode> {
+    if leg.asset != NATIVE_ASSET {
+        let debit = leg.amount.checked_neg().map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+        assets::record_entry(session, &leg.from_endpoint, tx_id, &debit).await?;
+        return assets::record_entry(session, &leg.to_endpoint, tx_id, &leg.amount).await;
+    }
+
+    if load_transaction(session, tx_id).await?.is_some() {
+        return Ok(());
+    }
+    let transfer = Transaction {
+        id: tx_id.to_string(),
+        from_endpoint: leg.from_endpoint.clone(),
+        to_endpoint: leg.to_endpoint.clone(),
+        amount: leg.amount.clone(),
+        timestamp: Utc::now(),
+        signature: String::new(),
+        status: TransactionStatus::Confirmed,
+        kind: TransactionKind::Transfer,
+        risk_score: None,
+        parent_tx_id: None,
+        sequence: None,
+        public_key: None,
+    };
+    insert_transaction(session, &transfer).await?;
+    info!("Swap {} leg {} settled as transaction {}", swap_id, leg.from_endpoint, tx_id);
+    Ok(())
+}
+
+/// Second phase. Only runs once the swap is `committing`, and repeats
+/// safely until it reaches `settled`.
+async fn roll_forward(session: &Repository, swap_id: Uuid) -> Result<(), StatusCode> {
+    let record = load_swap(session, swap_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    if record.status != SwapStatus::Committing {
+        return Ok(());
+    }
+
+    for (leg, tx_id) in record.legs() {
+        settle_leg(session, leg, tx_id, swap_id).await?;
+    }
+    release_holds(session, &record).await?;
+
+    if transition(session, swap_id, SwapStatus::Committing, SwapStatus::Settled).await? {
+        audit::record(
+            session,
+            &record.terms.swap_id,
+            "swap.settled",
+            "gateway",
+            Some(format!(
+                "{} {} <-> {} {}",
+                record.terms.maker(),
+                record.terms.offer.amount,
+                record.terms.taker(),
+                record.terms.ask.amount
+            )),
+        )
+        .await?;
+        info!("🔁 Swap {} settled", swap_id);
+    }
+    Ok(())
+}
+
+async fn roll_back(session: &Session, swap_id: Uuid) -> Result<(), StatusCode> {
+    if !transition(session, swap_id, SwapStatus::Prepared, SwapStatus::RolledBack).await? {
+        return Ok(());
+    }
+    if let Some(record) = load_swap(session, swap_id).await? {
+        release_holds(session, &record).await?;
+    }
+    audit::record(session, &swap_id.to_string(), "swap.rolled_back", "gateway", Some("expired".to_string())).await?;
+    info!("↩️ Swap {} expired and rolled back", swap_id);
+    Ok(())
+}
+
+/// Creates the swap on the first commitment; later ones must carry the
+/// same terms.
+async fn open_swap(session: &Session, swap_id: Uuid, terms: &SwapTerms) -> Result<SwapRecord, StatusCode> {
+    let serialized = serde_json::to_string(terms).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    session
+        .query(
+            "INSERT INTO transactions.swaps (swap_id, terms, status, expires_at, offer_tx_id, ask_tx_id, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?) IF NOT EXISTS",
+            (
+                swap_id,
+                serialized,
+                SwapStatus::Prepared.as_str(),
+                terms.expires_at.timestamp_millis(),
+                Uuid::new_v4(),
+                Uuid::new_v4(),
+                Utc::now().timestamp_millis(),
+            ),
+        )
+        .await
+        .map_err(db_error(swap_id))?;
+
+    let record = load_swap(session, swap_id).await?.ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    if &record.terms != terms {
+        warn!("Swap {} committed to with different terms", swap_id);
+        return Err(StatusCode::CONFLICT);
+    }
+    Ok(record)
+}
+
+/// `POST /api/swaps/hold`: records a party's commitment and locks its
+/// leg. The second commitment triggers settlement. Responds with the
+/// swap's state; 410 once expired, 422 if the leg isn't covered.
+pub async fn hold_swap(
+    State(state): State<AppState>,
+    Json(commitment): Json<SwapCommitment>,
+) -> Result<Json<SwapState>, StatusCode> {
+    let swap_id = Uuid::parse_str(&commitment.terms.swap_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    if !commitment.verify() {
+        warn!("Swap {} commitment by {} does not verify", swap_id, commitment.party);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    signatures::check_key(&state.session, &commitment.party, &commitment.public_key).await?;
+
+    let record = open_swap(&state.session, swap_id, &commitment.terms).await?;
+    let committed = committed_parties(&state.session, swap_id).await?;
+    if record.status != SwapStatus::Prepared || committed.contains(&commitment.party) {
+        return swap_state(&state.session, swap_id).await.map(Json);
+    }
+    if commitment.terms.expires_at <= Utc::now() {
+        return Err(StatusCode::GONE);
+    }
+
+    let Some((leg, hold_id)) = record.legs().into_iter().find(|(leg, _)| leg.from_endpoint == commitment.party) else {
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    if leg.asset == NATIVE_ASSET {
+        endpoints::check_transaction_allowed(&state.session, &leg.from_endpoint, &leg.to_endpoint, &leg.amount)
+            .await?;
+    }
+    assets::hold_if_available(&state.session, &leg.from_endpoint, hold_id, &leg.amount)
+        .await
+        .inspect_err(|status| {
+            if *status == StatusCode::UNPROCESSABLE_ENTITY {
+                info!("Swap {}: {} can't cover {}", swap_id, leg.from_endpoint, leg.amount);
+            }
+        })?;
+    let serialized = serde_json::to_string(&commitment).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    state
+        .session
+        .query(
+            "INSERT INTO transactions.swap_commitments (swap_id, party, commitment) VALUES (?, ?, ?)",
+            (swap_id, &commitment.party, serialized),
+        )
+        .await
+        .map_err(db_error(swap_id))?;
+    audit::record(
+        &state.session,
+        &commitment.terms.swap_id,
+        "swap.held",
+        &commitment.party,
+        Some(leg.amount.to_string()),
+    )
+    .await?;
+
+    // The sweeper may have rolled the swap back while the hold was written
+    if load_swap(&state.session, swap_id).await?.map(|r| r.status) == Some(SwapStatus::RolledBack) {
+        assets::release_hold(&state.session, &leg.from_endpoint, &leg.asset, hold_id).await?;
+        return Err(StatusCode::GONE);
+    }
+
+    let committed = committed_parties(&state.session, swap_id).await?;
+    let terms = &record.terms;
+    let both_held = [terms.maker(), terms.taker()]
+        .iter()
+        .all(|party| committed.iter().any(|c| c == party));
+    if both_held
+        && terms.expires_at > Utc::now()
+        && transition(&state.session, swap_id, SwapStatus::Prepared, SwapStatus::Committing).await?
+    {
+        roll_forward(&state.session, swap_id).await?;
+    }
+
+    swap_state(&state.session, swap_id).await.map(Json)
+}
+
+/// `GET /api/swaps/:id`
+pub async fn get_swap(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<SwapState>, StatusCode> {
+    let swap_id = Uuid::parse_str(&id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    swap_state(&state.session, swap_id).await.map(Json)
+}
+
+async fn swaps_with_status(session: &Session, status: SwapStatus) -> Result<Vec<(Uuid, i64)>, StatusCode> {
+    Ok(session
+        .query(
+            db::idempotent("SELECT swap_id, expires_at FROM transactions.swaps WHERE status = ? ALLOW FILTERING"),
+            (status.as_str(),),
+        )
+        .await
+        .map_err(|e| {
+            error!("Failed to list {} swaps: {}", status, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(Uuid, i64)>().ok())
+        .collect())
+}
+
+async fn sweep(session: &Repository) -> Result<(), StatusCode> {
+    // A crash between the commit decision and settlement leaves swaps
+    // `committing`; those always finish
+    for (swap_id, _) in swaps_with_status(session, SwapStatus::Committing).await? {
+        roll_forward(session, swap_id).await?;
+    }
+
+    let now = Utc::now().timestamp_millis();
+    for (swap_id, expires_at) in swaps_with_status(session, SwapStatus::Prepared).await? {
+        if expires_at <= now {
+            roll_back(session, swap_id).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Leader-only loop that finishes interrupted commits and rolls back
+/// expired swaps.
+pub async fn run_swap_expiry(state: AppState) {
+    loop {
+        tokio::time::sleep(SWAP_SWEEP_INTERVAL).await;
+        if !state.leadership.is_leader() {
+            continue;
+        }
+        if let Err(status) = sweep(&state.session).await {
+            warn!("Swap sweep failed: {}", status);
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! The transaction timeline: ids newest first, per endpoint and for all
+//! transactions (`ALL_SCOPE`), for paging through history. It is a
+//! projection like the others (see `projections`), kept in time buckets so
+//! no partition grows without bound: rows live in
+//! `tx_timeline_rows((scope, bucket_ms, bucket_start), timestamp, id)`, and
+//! `tx_timeline_buckets` lists each scope's non-empty buckets.
+//!
+//! The bucket size is a scheme recorded in `tx_timeline_schemes`, set by
+//! `TIMELINE_BUCKET_SECS`:
+//!
+//! - unset: keep the current scheme (one day for a new deployment)
+//! - a number of seconds: switch to that size on startup
+//! - `auto`: the leader picks the largest of hour, 6 hours, day, week and
+//!   30 days that keeps a day's worth of all-transaction rows times the
+//!   bucket size under `TIMELINE_TARGET_ROWS` (100000) per bucket, and
+//!   re-evaluates every `TIMELINE_TUNE_INTERVAL_SECS` (21600)
+//!
+//! Changing the size never moves data on the write path. New rows go to
+//! the new scheme while reads merge every scheme still on record, so
+//! history stays complete throughout. `TIMELINE_BUCKET_TRANSITION_SECS`
+//! (3600) after a switch, once every instance writes the new scheme, the
+//! leader copies the old scheme's rows into new buckets and drops it.
+
+use chrono::Utc;
+use scylla::Session;
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::db;
+use crate::pagination::Page;
+use crate::AppState;
+
+/// The timeline scope holding every transaction.
+pub const ALL_SCOPE: &str = "*";
+
+const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 24 * HOUR_MS;
+const DEFAULT_BUCKET_MS: i64 = DAY_MS;
+const MIN_BUCKET_MS: i64 = 60_000;
+/// Sizes `auto` chooses from.
+const AUTO_BUCKETS_MS: [i64; 5] = [HOUR_MS, 6 * HOUR_MS, DAY_MS, 7 * DAY_MS, 30 * DAY_MS];
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60);
+/// Bucket listings are read this many at a time while paging.
+const DIRECTORY_CHUNK: i32 = 64;
+
+/// The scheme this instance writes. Refreshed from the schemes table, so
+/// instances converge within `MAINTENANCE_INTERVAL` of a switch.
+static CURRENT_BUCKET_MS: AtomicI64 = AtomicI64::new(DEFAULT_BUCKET_MS);
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+enum BucketConfig {
+    Keep,
+    Fixed(i64),
+    Auto,
+}
+
+fn bucket_config() -> BucketConfig {
+    match std::env::var("TIMELINE_BUCKET_SECS").as_deref() {
+        Err(_) => BucketConfig::Keep,
+        Ok("auto") => BucketConfig::Auto,
+        Ok(secs) => match secs.parse::<i64>() {
+            Ok(secs) => BucketConfig::Fixed((secs * 1000).max(MIN_BUCKET_MS)),
+            Err(_) => {
+                warn!("Ignoring invalid TIMELINE_BUCKET_SECS {}", secs);
+                BucketConfig::Keep
+            }
+        },
+    }
+}
+
+struct Scheme {
+    bucket_ms: i64,
+    since: i64,
+    superseded_at: Option<i64>,
+}
+
+async fn load_schemes(session: &Session) -> Result<Vec<Scheme>, String> {
+    let rows = session
+        .query(db::idempotent("SELECT bucket_ms, since, superseded_at FROM transactions.tx_timeline_schemes"), &[])
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(rows
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(i64, i64, Option<i64>)>().ok())
+        .map(|(bucket_ms, since, superseded_at)| Scheme { bucket_ms, since, superseded_at })
+        .collect())
+}
+
+/// The newest scheme not superseded yet.
+fn current(schemes: &[Scheme]) -> Option<i64> {
+    schemes
+        .iter()
+        .filter(|scheme| scheme.superseded_at.is_none())
+        .max_by_key(|scheme| scheme.since)
+        .map(|scheme| scheme.bucket_ms)
+}
+
+/// Makes `bucket_ms` the scheme new rows are written to. The others stay
+/// readable until rebucketed.
+async fn switch(session: &Session, schemes: &[Scheme], bucket_ms: i64) -> Result<(), String> {
+    let now = Utc::now().timestamp_millis();
+    session
+        .query(
+            "INSERT INTO transactions.tx_timeline_schemes (bucket_ms, since, superseded_at) VALUES (?, ?, null)",
+            (bucket_ms, now),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    for scheme in schemes.iter().filter(|s| s.bucket_ms != bucket_ms && s.superseded_at.is_none()) {
+        session
+            .query(
+                "UPDATE transactions.tx_timeline_schemes SET superseded_at = ? WHERE bucket_ms = ?",
+                (now, scheme.bucket_ms),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    CURRENT_BUCKET_MS.store(bucket_ms, Ordering::Relaxed);
+    info!("Timeline buckets are now {}s", bucket_ms / 1000);
+    Ok(())
+}
+
+/// Applies `TIMELINE_BUCKET_SECS` on startup.
+pub async fn configure(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    let schemes = load_schemes(session).await?;
+    let existing = current(&schemes);
+    let wanted = match (bucket_config(), existing) {
+        (BucketConfig::Fixed(bucket_ms), _) => bucket_ms,
+        (_, Some(bucket_ms)) => bucket_ms,
+        (_, None) => DEFAULT_BUCKET_MS,
+    };
+    if existing == Some(wanted) {
+        CURRENT_BUCKET_MS.store(wanted, Ordering::Relaxed);
+    } else {
+        switch(session, &schemes, wanted).await?;
+    }
+    Ok(())
+}
+
+async fn insert_row(session: &Session, scope: &str, bucket_ms: i64, timestamp: i64, tx_id: Uuid) -> Result<(), String> {
+    let bucket_start = timestamp - timestamp.rem_euclid(bucket_ms);
+    session
+        .query(
+            db::idempotent(
+                "INSERT INTO transactions.tx_timeline_rows (scope, bucket_ms, bucket_start, timestamp, id) VALUES (?, ?, ?, ?, ?)",
+            ),
+            (scope, bucket_ms, bucket_start, timestamp, tx_id),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    session
+        .query(
+            db::idempotent("INSERT INTO transactions.tx_timeline_buckets (scope, bucket_ms, bucket_start) VALUES (?, ?, ?)"),
+            (scope, bucket_ms, bucket_start),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Adds a transaction under all transactions and each of its endpoints.
+pub async fn insert(
+    session: &Session,
+    tx_id: Uuid,
+    from_endpoint: &str,
+    to_endpoint: &str,
+    timestamp: i64,
+) -> Result<(), String> {
+    let bucket_ms = CURRENT_BUCKET_MS.load(Ordering::Relaxed);
+    let mut scopes = vec![ALL_SCOPE, from_endpoint];
+    if to_endpoint != from_endpoint {
+        scopes.push(to_endpoint);
+    }
+    for scope in scopes {
+        insert_row(session, scope, bucket_ms, timestamp, tx_id).await?;
+    }
+    Ok(())
+}
+
+/// Up to `limit` `(timestamp, id)` rows of one scheme for `page`, in page
+/// order: newest first, or oldest first for `Page::After`. Walks the
+/// scheme's buckets from the cursor outwards until the page is full.
+async fn scheme_page(
+    session: &Session,
+    scope: &str,
+    bucket_ms: i64,
+    page: &Page,
+    limit: i32,
+) -> Result<Vec<(i64, Uuid)>, String> {
+    let ascending = matches!(page, Page::After(_));
+    let (directory, rows) = match page {
+        Page::Latest => ("", ""),
+        Page::Before(_) => ("AND bucket_start <= ?", "AND (timestamp, id) < (?, ?)"),
+        Page::After(_) => ("AND bucket_start >= ?", "AND (timestamp, id) > (?, ?)"),
+    };
+    let order = if ascending {
+        (" ORDER BY bucket_start ASC", " ORDER BY timestamp ASC, id ASC")
+    } else {
+        ("", "")
+    };
+    let cursor = match page {
+        Page::Latest => None,
+        Page::Before(cursor) | Page::After(cursor) => Some(*cursor),
+    };
+
+    let mut collected: Vec<(i64, Uuid)> = Vec::new();
+    // The next directory chunk starts past the last bucket read
+    let mut resume: Option<i64> = cursor.map(|cursor| cursor.timestamp - cursor.timestamp.rem_euclid(bucket_ms));
+    let mut first_chunk = true;
+    loop {
+        let bound = if first_chunk {
+            directory.to_string()
+        } else if ascending {
+            "AND bucket_start > ?".to_string()
+        } else {
+            "AND bucket_start < ?".to_string()
+        };
+        let cql = format!(
+            "SELECT bucket_start FROM transactions.tx_timeline_buckets WHERE scope = ? AND bucket_ms = ? {}{} LIMIT ?",
+            bound, order.0
+        );
+        let result = match resume.filter(|_| !bound.is_empty()) {
+            Some(start) => session.query(db::idempotent(cql), (scope, bucket_ms, start, DIRECTORY_CHUNK)).await,
+            None => session.query(db::idempotent(cql), (scope, bucket_ms, DIRECTORY_CHUNK)).await,
+        }
+        .map_err(|e| e.to_string())?;
+        let starts: Vec<i64> = result
+            .rows
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|row| row.into_typed::<(i64,)>().ok())
+            .map(|(start,)| start)
+            .collect();
+
+        for &bucket_start in &starts {
+            let remaining = limit - collected.len() as i32;
+            let cql = format!(
+                "SELECT timestamp, id FROM transactions.tx_timeline_rows WHERE scope = ? AND bucket_ms = ? AND bucket_start = ? {}{} LIMIT ?",
+                if cursor.is_some() { rows } else { "" },
+                order.1
+            );
+            let result = match cursor {
+                Some(cursor) => {
+                    session
+                        .query(
+                            db::idempotent(cql),
+                            (scope, bucket_ms, bucket_start, cursor.timestamp, cursor.id, remaining),
+                        )
+                        .await
+                }
+                None => session.query(db::idempotent(cql), (scope, bucket_ms, bucket_start, remaining)).await,
+            }
+            .map_err(|e| e.to_string())?;
+            collected.extend(
+                result
+                    .rows
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter_map(|row| row.into_typed::<(i64, Uuid)>().ok()),
+            );
+            if collected.len() as i32 >= limit {
+                return Ok(collected);
+            }
+        }
+
+        if starts.len() < DIRECTORY_CHUNK as usize {
+            return Ok(collected);
+        }
+        resume = starts.last().copied();
+        first_chunk = false;
+    }
+}
+
+/// Ids of one page of transactions, optionally one endpoint's, newest
+/// first. Merges every bucket scheme on record, so pages stay complete
+/// while a size change is in transition.
+pub async fn page(session: &Session, endpoint: Option<&str>, page: &Page, limit: i32) -> Result<Vec<Uuid>, String> {
+    let scope = endpoint.unwrap_or(ALL_SCOPE);
+    let mut bucket_sizes: Vec<i64> = load_schemes(session).await?.iter().map(|s| s.bucket_ms).collect();
+    let current = CURRENT_BUCKET_MS.load(Ordering::Relaxed);
+    if !bucket_sizes.contains(&current) {
+        bucket_sizes.push(current);
+    }
+
+    let mut rows = Vec::new();
+    for bucket_ms in bucket_sizes {
+        rows.extend(scheme_page(session, scope, bucket_ms, page, limit).await?);
+    }
+
+    // Rows being rebucketed are briefly in two schemes
+    let mut seen = HashSet::new();
+    rows.retain(|(_, id)| seen.insert(*id));
+    rows.sort_by(|a, b| b.cmp(a));
+    let ids = rows.into_iter().map(|(_, id)| id);
+    Ok(if matches!(page, Page::After(_)) {
+        // The page adjoining the cursor is the oldest of the newer ones
+        let mut ids: Vec<Uuid> = ids.collect();
+        let skip = ids.len().saturating_sub(limit as usize);
+        ids.drain(..skip);
+        ids
+    } else {
+        ids.take(limit as usize).collect()
+    })
+}
+
+/// One-off migration filling the timeline from `tx_log`, for deployments
+/// that predate it. Does nothing once the timeline has rows.
+pub async fn backfill(session: &Session) -> Result<(), Box<dyn std::error::Error>> {
+    let existing = session
+        .query(db::idempotent("SELECT scope FROM transactions.tx_timeline_buckets LIMIT 1"), &[])
+        .await?;
+    if existing.rows.map(|rows| !rows.is_empty()).unwrap_or(false) {
+        return Ok(());
+    }
+
+    let rows = session
+        .query(db::scan("SELECT id, from_endpoint, to_endpoint, timestamp FROM transactions.tx_log"), &[])
+        .await?;
+    let mut backfilled = 0;
+    for row in rows.rows.unwrap_or_default() {
+        let (id, from_endpoint, to_endpoint, timestamp) = row.into_typed::<(Uuid, String, String, i64)>()?;
+        insert(session, id, &from_endpoint, &to_endpoint, timestamp).await?;
+        backfilled += 1;
+    }
+    if backfilled > 0 {
+        info!("Backfilled {} transactions into the timeline", backfilled);
+    }
+    Ok(())
+}
+
+/// Moves every row of a superseded scheme into the current one, then
+/// forgets the scheme. Rows are copied before they are deleted, so a
+/// failure part way just leaves duplicates that reads drop.
+async fn rebucket(session: &Session, from_ms: i64, to_ms: i64) -> Result<usize, String> {
+    let partitions = session
+        .query(db::scan("SELECT DISTINCT scope, bucket_ms FROM transactions.tx_timeline_buckets"), &[])
+        .await
+        .map_err(|e| e.to_string())?;
+    let scopes: Vec<String> = partitions
+        .rows
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|row| row.into_typed::<(String, i64)>().ok())
+        .filter(|(_, bucket_ms)| *bucket_ms == from_ms)
+        .map(|(scope, _)| scope)
+        .collect();
+
+    let mut moved = 0;
+    for scope in scopes {
+        let buckets = session
+            .query(
+                db::scan("SELECT bucket_start FROM transactions.tx_timeline_buckets WHERE scope = ? AND bucket_ms = ?"),
+                (&scope, from_ms),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        for (bucket_start,) in buckets.rows.unwrap_or_default().into_iter().filter_map(|row| row.into_typed::<(i64,)>().ok()) {
+            let rows = session
+                .query(
+                    db::scan(
+                        "SELECT timestamp, id FROM transactions.tx_timeline_rows WHERE scope = ? AND bucket_ms = ? AND bucket_start = ?",
+                    ),
+                    (&scope, from_ms, bucket_start),
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+            for (timestamp, id) in rows.rows.unwrap_or_default().into_iter().filter_map(|row| row.into_typed::<(i64, Uuid)>().ok()) {
+                insert_row(session, &scope, to_ms, timestamp, id).await?;
+                moved += 1;
+            }
+            session
+                .query(
+                    db::idempotent("DELETE FROM transactions.tx_timeline_rows WHERE scope = ? AND bucket_ms = ? AND bucket_start = ?"),
+                    (&scope, from_ms, bucket_start),
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+            session
+                .query(
+                    db::idempotent("DELETE FROM transactions.tx_timeline_buckets WHERE scope = ? AND bucket_ms = ? AND bucket_start = ?"),
+                    (&scope, from_ms, bucket_start),
+                )
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+    }
+
+    session
+        .query("DELETE FROM transactions.tx_timeline_schemes WHERE bucket_ms = ?", (from_ms,))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(moved)
+}
+
+/// Rows per millisecond across all transactions over the last day.
+async fn recent_rate(session: &Session, bucket_ms: i64) -> Result<f64, String> {
+    let now = Utc::now().timestamp_millis();
+    let since = now - DAY_MS;
+    let buckets = session
+        .query(
+            db::idempotent(
+                "SELECT bucket_start FROM transactions.tx_timeline_buckets WHERE scope = ? AND bucket_ms = ? AND bucket_start >= ?",
+            ),
+            (ALL_SCOPE, bucket_ms, since - since.rem_euclid(bucket_ms)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut count = 0;
+    for (bucket_start,) in buckets.rows.unwrap_or_default().into_iter().filter_map(|row| row.into_typed::<(i64,)>().ok()) {
+        let result = session
+            .query(
+                db::idempotent(
+                    "SELECT COUNT(*) FROM transactions.tx_timeline_rows WHERE scope = ? AND bucket_ms = ? AND bucket_start = ? AND timestamp >= ?",
+                ),
+                (ALL_SCOPE, bucket_ms, bucket_start, since),
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some((rows,)) = result.rows.and_then(|rows| rows.into_iter().next()).and_then(|row| row.into_typed::<(i64,)>().ok()) {
+            count += rows;
+        }
+    }
+    Ok(count as f64 / DAY_MS as f64)
+}
+
+/// The largest automatic size keeping a bucket under the target row count.
+fn auto_bucket_ms(rate: f64) -> i64 {
+    let target = env_u64("TIMELINE_TARGET_ROWS", 100_000) as f64;
+    AUTO_BUCKETS_MS
+        .iter()
+        .rev()
+        .copied()
+        .find(|&bucket_ms| rate * bucket_ms as f64 <= target)
+        .unwrap_or(AUTO_BUCKETS_MS[0])
+}
+
+async fn maintain(session: &Session, leader: bool, tune: bool) -> Result<(), String> {
+    let schemes = load_schemes(session).await?;
+    let Some(current_ms) = current(&schemes) else {
+        return Ok(());
+    };
+    CURRENT_BUCKET_MS.store(current_ms, Ordering::Relaxed);
+    if !leader {
+        return Ok(());
+    }
+
+    if tune {
+        let rate = recent_rate(session, current_ms).await?;
+        let wanted = auto_bucket_ms(rate);
+        if wanted != current_ms {
+            info!("Tuning timeline buckets from {}s to {}s for {:.0} transactions a day", current_ms / 1000, wanted / 1000, rate * DAY_MS as f64);
+            return switch(session, &schemes, wanted).await;
+        }
+    }
+
+    let transition = env_u64("TIMELINE_BUCKET_TRANSITION_SECS", 3600) as i64 * 1000;
+    let now = Utc::now().timestamp_millis();
+    for scheme in &schemes {
+        if scheme.superseded_at.is_some_and(|at| at + transition <= now) {
+            let moved = rebucket(session, scheme.bucket_ms, current_ms).await?;
+            info!("Rebucketed {} timeline rows from {}s to {}s buckets", moved, scheme.bucket_ms / 1000, current_ms / 1000);
+        }
+    }
+    Ok(())
+}
+
+/// Background task keeping this instance on the current scheme; on the
+/// leader, also tunes the size (`auto`) and retires superseded schemes.
+pub async fn run_maintenance(state: AppState) {
+    let auto = matches!(bucket_config(), BucketConfig::Auto);
+    let tune_interval = Duration::from_secs(env_u64("TIMELINE_TUNE_INTERVAL_SECS", 21_600));
+    let mut last_tuned: Option<tokio::time::Instant> = None;
+    loop {
+        tokio::time::sleep(MAINTENANCE_INTERVAL).await;
+        let leader = state.leadership.is_leader();
+        let tune = auto && leader && last_tuned.is_none_or(|at| at.elapsed() >= tune_interval);
+        if tune {
+            last_tuned = Some(tokio::time::Instant::now());
+        }
+        if let Err(e) = maintain(&state.session, leader, tune).await {
+            warn!("Timeline maintenance failed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! TLS for the gateway's listeners. With `TLS_CERT` and `TLS_KEY` (PEM
+//! chain and PKCS#8 key) set, the public port serves HTTPS and WSS instead
+//! of plain HTTP; the internal mTLS listener is in `mtls`.
+//!
+//! Certificates are reread as they rotate on disk: every
+//! `TLS_RELOAD_SECS` (30) the files are checked, and when one has changed
+//! the listener's config is rebuilt for new connections. A set that doesn't
+//! load, e.g. a key written after its certificate, is logged and the
+//! current one stays in use until the files change again.
+
+use axum::{extract::ConnectInfo, Extension, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use rustls::{
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer},
+    ServerConfig, ServerConnection,
+};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+
+type Build<T> = dyn Fn() -> Result<T, String> + Send + Sync;
+
+/// A value built from files on disk and rebuilt when any of them changes.
+pub struct Reloading<T> {
+    what: &'static str,
+    files: Arc<[PathBuf]>,
+    build: Arc<Build<T>>,
+    current: Arc<RwLock<Arc<T>>>,
+    modified: Arc<Mutex<Vec<Option<SystemTime>>>>,
+}
+
+impl<T> Clone for Reloading<T> {
+    fn clone(&self) -> Self {
+        Reloading {
+            what: self.what,
+            files: self.files.clone(),
+            build: self.build.clone(),
+            current: self.current.clone(),
+            modified: self.modified.clone(),
+        }
+    }
+}
+
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files.iter().map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok()).collect()
+}
+
+impl<T: Send + Sync + 'static> Reloading<T> {
+    pub fn new(
+        what: &'static str,
+        files: Vec<PathBuf>,
+        build: impl Fn() -> Result<T, String> + Send + Sync + 'static,
+    ) -> Result<Self, String> {
+        let stamps = modified(&files);
+        let value = build()?;
+        Ok(Reloading {
+            what,
+            files: files.into(),
+            build: Arc::new(build),
+            current: Arc::new(RwLock::new(Arc::new(value))),
+            modified: Arc::new(Mutex::new(stamps)),
+        })
+    }
+
+    pub fn current(&self) -> Arc<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Rebuilds the value whenever its files change.
+    pub async fn run_reload(self) {
+        let interval = Duration::from_secs(std::env::var("TLS_RELOAD_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(30));
+        loop {
+            tokio::time::sleep(interval).await;
+            let stamps = modified(&self.files);
+            if *self.modified.lock().unwrap() == stamps {
+                continue;
+            }
+            match (self.build)() {
+                Ok(value) => {
+                    info!("🔄 Reloaded the {}", self.what);
+                    *self.current.write().unwrap() = Arc::new(value);
+                }
+                Err(e) => warn!("Keeping the current {}: {}", self.what, e),
+            }
+            // Not retried until the files change again
+            *self.modified.lock().unwrap() = stamps;
+        }
+    }
+}
+
+pub fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+pub fn certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid certificate in {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificate in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+pub fn private_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("Invalid key in {}: {}", path.display(), e))?
+        .ok_or_else(|| format!("No private key in {}", path.display()))
+}
+
+/// The public listener's config, `None` unless `TLS_CERT` is set.
+pub fn public_from_env() -> Result<Option<Reloading<ServerConfig>>, Box<dyn std::error::Error>> {
+    let Ok(cert_path) = std::env::var("TLS_CERT") else {
+        return Ok(None);
+    };
+    let key_path = std::env::var("TLS_KEY").map_err(|_| "TLS_CERT is set but TLS_KEY isn't")?;
+    let (cert_path, key_path) = (PathBuf::from(cert_path), PathBuf::from(key_path));
+
+    let files = vec![cert_path.clone(), key_path.clone()];
+    let config = Reloading::new("TLS certificate", files, move || {
+        let mut config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_single_cert(certs(&cert_path)?, private_key(&key_path)?)
+            .map_err(|e| e.to_string())?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    })?;
+    Ok(Some(config))
+}
+
+/// Serves `app` over TLS on `listener`, with the config current when each
+/// connection arrives. `route` picks the router for a connection from its
+/// handshake, or `None` to drop it. Requests carry the peer address as
+/// `ConnectInfo`, as over plain HTTP.
+pub async fn serve<F>(listener: TcpListener, app: Router, config: Reloading<ServerConfig>, route: F)
+where
+    F: Fn(&ServerConnection, &Router) -> Option<Router> + Clone + Send + 'static,
+{
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("TLS accept failed: {}", e);
+                continue;
+            }
+        };
+        let acceptor = TlsAcceptor::from(config.current());
+        let app = app.clone();
+        let route = route.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+            };
+            let Some(app) = route(stream.get_ref().1, &app) else {
+                return;
+            };
+            let app = app.layer(Extension(ConnectInfo(addr)));
+
+            let service = TowerToHyperService::new(app);
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("TLS connection from {} ended: {}", addr, e);
+            }
+        });
+    }
+}
+
+/// Every connection gets `app` as is.
+pub fn any_client(_: &ServerConnection, app: &Router) -> Option<Router> {
+    Some(app.clone())
+}
@@ -0,0 +1,205 @@
+//! Operator scripts run on every transfer as it's ingested, after risk
+//! scoring and before the hold decision, to tag it, send it to review or
+//! adjust its risk score. Scripts are Rhai, read from `TRANSFORM_SCRIPT`;
+//! without one, ingest is unchanged. The file is checked every
+//! `TRANSFORM_RELOAD_SECS` (5) and recompiled when it changes. A script
+//! that doesn't compile is logged and the previous one stays in use. At
+//! startup it's fatal.
+//!
+//! A script sees the transfer as the constant `tx` (`id`, `from`, `to`,
+//! `amount`, `sequence`) and the risk engine's `risk_reasons`, and may set:
+//!
+//! ```rhai
+//! if tx.amount > 5000.0 && tx.from.starts_with("treasury-") {
+//!     tags.push("treasury");
+//!     risk_score -= 20;
+//! }
+//! if tx.to == "escrow-7" {
+//!     review = true;
+//!     review_reason = "escrow-7 payouts are checked by hand";
+//! }
+//! ```
+//!
+//! Scripts are sandboxed. They can't touch files, the network or the
+//! gateway's state, only these variables. Each run is capped at
+//! `TRANSFORM_MAX_OPERATIONS` (100,000) operations and
+//! `TRANSFORM_TIMEOUT_MS` (50), and strings, arrays and maps are capped in
+//! size. A run that errors or hits a limit leaves the transfer as the
+//! risk engine scored it, and is recorded in its audit trail.
+
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, info, warn};
+
+use crate::Transaction;
+
+const MAX_STRING_SIZE: usize = 1_024;
+const MAX_COLLECTION_SIZE: usize = 256;
+const MAX_CALL_LEVELS: usize = 32;
+/// Tags kept from one run; the rest are ignored.
+const MAX_TAGS: usize = 16;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// What a script asked for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransformOutcome {
+    pub tags: Vec<String>,
+    /// Changed from what the risk engine gave, clamped to 0-100.
+    pub risk_score: Option<u8>,
+    pub review: bool,
+    pub review_reason: Option<String>,
+}
+
+struct Script {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    ast: AST,
+}
+
+/// The current script, if any, shared by every request.
+#[derive(Clone)]
+pub struct Transforms {
+    script: Arc<RwLock<Option<Arc<Script>>>>,
+    max_operations: u64,
+    timeout: Duration,
+}
+
+impl Transforms {
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let transforms = Transforms {
+            script: Arc::default(),
+            max_operations: env_or("TRANSFORM_MAX_OPERATIONS", 100_000),
+            timeout: Duration::from_millis(env_or("TRANSFORM_TIMEOUT_MS", 50)),
+        };
+        if let Ok(path) = std::env::var("TRANSFORM_SCRIPT") {
+            let script = load(PathBuf::from(&path))?;
+            info!("Running ingest transform {}", path);
+            *transforms.script.write().unwrap() = Some(Arc::new(script));
+        }
+        Ok(transforms)
+    }
+
+    /// Runs the script over `tx`; an empty outcome without one. `Err`
+    /// says why the run failed.
+    pub async fn apply(&self, tx: &Transaction, risk_score: u8, risk_reasons: &[&str]) -> Result<TransformOutcome, String> {
+        let Some(script) = self.script.read().unwrap().clone() else {
+            return Ok(TransformOutcome::default());
+        };
+        let engine = self.engine();
+        let mut scope = scope(tx, risk_score, risk_reasons);
+        // Scripts are bounded, but still too slow for the async workers
+        tokio::task::spawn_blocking(move || -> Result<TransformOutcome, String> {
+            engine.run_ast_with_scope(&mut scope, &script.ast).map_err(|e| e.to_string())?;
+            Ok(outcome(&scope, risk_score))
+        })
+        .await
+        .map_err(|e| format!("transform panicked: {}", e))?
+    }
+
+    /// A fresh engine per run, so the deadline is the run's own.
+    fn engine(&self) -> Engine {
+        let mut engine = sandboxed();
+        engine.set_max_operations(self.max_operations);
+        let timeout = self.timeout;
+        let started = Instant::now();
+        engine.on_progress(move |_| (started.elapsed() > timeout).then_some(Dynamic::UNIT));
+        engine
+    }
+
+    /// Recompiles the script whenever its file changes.
+    pub async fn run_reload(self) {
+        let interval = Duration::from_secs(env_or("TRANSFORM_RELOAD_SECS", 5));
+        loop {
+            tokio::time::sleep(interval).await;
+            let Some(current) = self.script.read().unwrap().clone() else {
+                return;
+            };
+            let modified = std::fs::metadata(&current.path).and_then(|m| m.modified()).ok();
+            if modified.is_none() || modified == current.modified {
+                continue;
+            }
+            match load(current.path.clone()) {
+                Ok(script) => {
+                    info!("Reloaded ingest transform {}", current.path.display());
+                    *self.script.write().unwrap() = Some(Arc::new(script));
+                }
+                Err(e) => {
+                    warn!("Keeping the previous ingest transform: {}", e);
+                    // Not retried until the file changes again
+                    *self.script.write().unwrap() = Some(Arc::new(Script {
+                        path: current.path.clone(),
+                        modified,
+                        ast: current.ast.clone(),
+                    }));
+                }
+            }
+        }
+    }
+}
+
+/// No I/O is registered on a Rhai engine to begin with; this takes away
+/// `eval` and `print` too, and caps what one run may allocate.
+fn sandboxed() -> Engine {
+    let mut engine = Engine::new();
+    engine.disable_symbol("eval");
+    engine.on_print(|_| {});
+    engine.on_debug(|text, _, _| debug!("Ingest transform: {}", text));
+    engine.set_max_string_size(MAX_STRING_SIZE);
+    engine.set_max_array_size(MAX_COLLECTION_SIZE);
+    engine.set_max_map_size(MAX_COLLECTION_SIZE);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+    engine
+}
+
+fn load(path: PathBuf) -> Result<Script, String> {
+    let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+    let source = std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let ast = sandboxed()
+        .compile(&source)
+        .map_err(|e| format!("Invalid ingest transform {}: {}", path.display(), e))?;
+    Ok(Script { path, modified, ast })
+}
+
+fn scope(tx: &Transaction, risk_score: u8, risk_reasons: &[&str]) -> Scope<'static> {
+    let mut fields = Map::new();
+    fields.insert("id".into(), tx.id.clone().into());
+    fields.insert("from".into(), tx.from_endpoint.clone().into());
+    fields.insert("to".into(), tx.to_endpoint.clone().into());
+    fields.insert("amount".into(), tx.amount.to_major().into());
+    fields.insert("sequence".into(), tx.sequence.map_or(Dynamic::UNIT, |s| (s as i64).into()));
+    let reasons: Array = risk_reasons.iter().map(|reason| reason.to_string().into()).collect();
+
+    let mut scope = Scope::new();
+    scope.push_constant("tx", fields);
+    scope.push_constant("risk_reasons", reasons);
+    scope.push("risk_score", risk_score as i64);
+    scope.push("tags", Array::new());
+    scope.push("review", false);
+    scope.push("review_reason", String::new());
+    scope
+}
+
+/// Reads back what the script set, ignoring values of the wrong type.
+fn outcome(scope: &Scope, risk_score: u8) -> TransformOutcome {
+    let tags = scope
+        .get_value::<Array>("tags")
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|tag| tag.into_string().ok())
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .take(MAX_TAGS)
+        .collect();
+    let score = scope.get_value::<i64>("risk_score").map(|score| score.clamp(0, 100) as u8);
+    TransformOutcome {
+        tags,
+        risk_score: score.filter(|score| *score != risk_score),
+        review: scope.get_value::<bool>("review").unwrap_or(false),
+        review_reason: scope.get_value::<String>("review_reason").filter(|reason| !reason.trim().is_empty()),
+    }
+}
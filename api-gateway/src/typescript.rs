@@ -0,0 +1,116 @@
+//! TypeScript definitions of everything the relayer puts on the wire: the
+//! tx-core types the endpoints and signaling servers exchange, and the
+//! gateway's REST payloads. Built with `--features ts`:
+//!
+//! `api-gateway export-types [relayer-types/index.d.ts]`
+//!
+//! Regenerate whenever one of these types changes; `relayer-types` is
+//! published from the result.
+
+use std::path::Path;
+
+use ts_rs::TS;
+use tx_core::{
+    AckResult, ChainLink, ChannelUpdate, IceCandidate, InclusionProof, KeySet, Money, PublishedKey, Quote,
+    QuoteRequest, Receipt, RoomInfo, Side, SignalingMessage, SwapCommitment, SwapLeg, SwapState, SwapStatus,
+    SwapTerms, Transaction, TransactionKind, TransactionStatus,
+};
+
+use crate::assets::AssetBalance;
+use crate::batching::{BatchReport, PairSettlement, RoomBatching};
+use crate::consents::{Consent, ConsentStatus, Scope, ThirdParty};
+use crate::counterparties::CounterpartyLists;
+use crate::disputes::{AttachmentInfo, Dispute, DisputeDetail, DisputeStatus, TimelineEntry};
+use crate::endpoints::{Endpoint, EndpointStatus};
+use crate::erasure::ErasureReport;
+use crate::error::ErrorBody;
+use crate::payment_files::{InstructionStatus, PaymentFileReport, PaymentInstruction};
+use crate::reporting::{
+    RegulatoryReport, ReportDirection, ReportEntry, ReportSummary, ReportingBasis, ReportingPeriod, ReportingRule,
+};
+use crate::settlement::{Settlement, SettlementStatus};
+use crate::validation::FieldError;
+use crate::{EndpointBalance, EndpointStats, IngestResponse, TransactionStats};
+
+pub const DEFAULT_PATH: &str = "relayer-types/index.d.ts";
+
+/// Referenced types are all declared in the same file, so order doesn't
+/// matter; this one groups them by where they appear.
+fn declarations() -> Vec<String> {
+    vec![
+        // Transactions and receipts
+        Transaction::decl(),
+        TransactionKind::decl(),
+        TransactionStatus::decl(),
+        Money::decl(),
+        Receipt::decl(),
+        InclusionProof::decl(),
+        ChainLink::decl(),
+        KeySet::decl(),
+        PublishedKey::decl(),
+        // Signaling
+        SignalingMessage::decl(),
+        RoomInfo::decl(),
+        AckResult::decl(),
+        IceCandidate::decl(),
+        ChannelUpdate::decl(),
+        QuoteRequest::decl(),
+        Quote::decl(),
+        Side::decl(),
+        SwapCommitment::decl(),
+        SwapTerms::decl(),
+        SwapLeg::decl(),
+        SwapState::decl(),
+        SwapStatus::decl(),
+        // REST
+        ErrorBody::decl(),
+        FieldError::decl(),
+        IngestResponse::decl(),
+        TransactionStats::decl(),
+        EndpointStats::decl(),
+        EndpointBalance::decl(),
+        Endpoint::decl(),
+        EndpointStatus::decl(),
+        AssetBalance::decl(),
+        CounterpartyLists::decl(),
+        Dispute::decl(),
+        DisputeStatus::decl(),
+        DisputeDetail::decl(),
+        TimelineEntry::decl(),
+        AttachmentInfo::decl(),
+        Settlement::decl(),
+        SettlementStatus::decl(),
+        RoomBatching::decl(),
+        BatchReport::decl(),
+        PairSettlement::decl(),
+        PaymentInstruction::decl(),
+        InstructionStatus::decl(),
+        PaymentFileReport::decl(),
+        Consent::decl(),
+        ConsentStatus::decl(),
+        Scope::decl(),
+        ThirdParty::decl(),
+        ReportingRule::decl(),
+        ReportingPeriod::decl(),
+        ReportingBasis::decl(),
+        ReportDirection::decl(),
+        ReportEntry::decl(),
+        RegulatoryReport::decl(),
+        ReportSummary::decl(),
+        ErasureReport::decl(),
+    ]
+}
+
+pub fn export(path: &Path) -> std::io::Result<()> {
+    let mut out = String::from("// Generated by `api-gateway export-types`. Do not edit.\n");
+    out.push_str(&format!("// Signaling protocol version {}.\n", tx_core::PROTOCOL_VERSION));
+    for declaration in declarations() {
+        out.push_str("\nexport ");
+        out.push_str(&declaration);
+        out.push('\n');
+    }
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, out)
+}
@@ -0,0 +1,115 @@
+//! Checks on transfers submitted to `POST /api/transactions`, before
+//! anything is looked up or recorded. Every problem found is reported at
+//! once, as `422` with one entry per field in `details.fields`:
+//!
+//! - `id` is a UUID
+//! - `from_endpoint` and `to_endpoint` are present and differ
+//! - `amount` is positive; amounts that aren't exact native-asset money don't parse
+//! - `timestamp` is at most `TX_MAX_CLOCK_SKEW_SECS` (300) ahead of the
+//!   gateway and at most `TX_MAX_AGE_SECS` (a week, for endpoints sending
+//!   from an offline outbox) behind it
+//! - `signature` is present, and verifies under `public_key` when there
+//!   is one; `public_key` itself is required with `REQUIRE_TX_SIGNATURES`
+//! - it is a pending transfer: other kinds, statuses and parents only
+//!   come from the gateway's own flows
+//!
+//! Whether the key belongs to the sender is checked after, in `signatures`.
+
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use tx_core::verify_transaction;
+use uuid::Uuid;
+
+use crate::error::ApiError;
+use crate::signatures::require_signatures;
+use crate::{Transaction, TransactionKind, TransactionStatus};
+
+/// Longest endpoint id accepted.
+const MAX_ENDPOINT_ID_LEN: usize = 128;
+
+/// `name` in seconds, clamped to what a `Duration` holds.
+fn env_secs(name: &str, default: i64) -> Duration {
+    let secs: i64 = std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+    Duration::try_seconds(secs.max(0)).unwrap_or(Duration::MAX)
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct Problems(Vec<FieldError>);
+
+impl Problems {
+    fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(FieldError { field: field.to_string(), message: message.into() });
+    }
+
+    fn endpoint(&mut self, field: &str, endpoint_id: &str) {
+        if endpoint_id.trim().is_empty() {
+            self.add(field, "is required");
+        } else if endpoint_id.len() > MAX_ENDPOINT_ID_LEN {
+            self.add(field, format!("must be at most {} characters", MAX_ENDPOINT_ID_LEN));
+        }
+    }
+
+    fn into_result(self) -> Result<(), ApiError> {
+        if self.0.is_empty() {
+            return Ok(());
+        }
+        Err(ApiError::invalid_fields("Invalid transaction", self.0))
+    }
+}
+
+pub fn transfer(transaction: &Transaction) -> Result<(), ApiError> {
+    let mut problems = Problems::default();
+
+    if Uuid::parse_str(&transaction.id).is_err() {
+        problems.add("id", "must be a UUID");
+    }
+
+    problems.endpoint("from_endpoint", &transaction.from_endpoint);
+    problems.endpoint("to_endpoint", &transaction.to_endpoint);
+    if !transaction.from_endpoint.trim().is_empty() && transaction.from_endpoint == transaction.to_endpoint {
+        problems.add("to_endpoint", "must differ from from_endpoint");
+    }
+
+    if transaction.amount.is_negative() || transaction.amount.is_zero() {
+        problems.add("amount", "must be greater than 0");
+    }
+
+    // A bound past the end of time is no bound
+    let now = Utc::now();
+    let latest = now.checked_add_signed(env_secs("TX_MAX_CLOCK_SKEW_SECS", 300));
+    let earliest = now.checked_sub_signed(env_secs("TX_MAX_AGE_SECS", 7 * 86_400));
+    if latest.is_some_and(|latest| transaction.timestamp > latest) {
+        problems.add("timestamp", "is in the future");
+    } else if earliest.is_some_and(|earliest| transaction.timestamp < earliest) {
+        problems.add("timestamp", "is too old");
+    }
+
+    if transaction.signature.trim().is_empty() {
+        problems.add("signature", "is required");
+    } else if transaction.public_key.is_some() {
+        if !verify_transaction(transaction) {
+            problems.add("signature", "does not verify under public_key");
+        }
+    } else if require_signatures() {
+        problems.add("public_key", "is required");
+    }
+
+    if transaction.kind != TransactionKind::Transfer {
+        problems.add("kind", "must be transfer");
+    }
+    if transaction.parent_tx_id.is_some() {
+        problems.add("parent_tx_id", "must not be set");
+    }
+    if transaction.status != TransactionStatus::Pending {
+        problems.add("status", "must be pending");
+    }
+
+    problems.into_result()
+}
@@ -0,0 +1,426 @@
+//! `api-gateway serve-web <dir>`: serves a built wasm app (the
+//! `ws-tx-endpoint` bundle: its pages, `tx-worker.js` and the wasm-pack
+//! `pkg/`) in place of a plain static server, locked down against script
+//! injection.
+//!
+//! The bundle is read once at startup. Files below the top level that
+//! pages load by URL get content-hashed names (`pkg/app_bg.<hash>.wasm`,
+//! `pkg/app.<hash>.js`), the references to them are rewritten, and they
+//! are served `immutable` for a year; the old names are not served. Top
+//! level files keep their names, since the wasm itself names the worker,
+//! and are revalidated on every load by `ETag`.
+//!
+//! Every page gets a strict Content Security Policy: scripts only from
+//! this origin or inline blocks whose hashes it lists, wasm compilation
+//! allowed, connections only to this origin and `WEB_CONNECT_SRC`
+//! (`http://localhost:3001 ws://localhost:8080`, what the app falls back
+//! to), no plugins, forms or `<base>`. Pages can't be framed, except
+//! `widget.html` by `WEB_FRAME_ANCESTORS` (`*`; set it to the sites that
+//! embed the widget). The glue module is pinned by subresource integrity
+//! (an import map and a `modulepreload`) and fetches the wasm with an
+//! `integrity` of its own, so a tampered file is refused rather than run.
+//!
+//! Each page also loads `config.js`, which sets `window.RELAYER_CONFIG`
+//! from `WEB_API_GATEWAY` and `WEB_SIGNALING_SERVER` (both allowed to
+//! connect), so one build of an app can be pointed anywhere; unset, the
+//! app keeps its own defaults.
+//!
+//! Listens on `WEB_PORT` (8000). `--features embed-web` builds also serve
+//! the same way from the gateway itself, see `embedded`.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::State,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use sha2::{Digest, Sha256, Sha384};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+const IMMUTABLE: &str = "public, max-age=31536000, immutable";
+const REVALIDATE: &str = "no-cache";
+/// The one page meant to be embedded in other sites.
+const WIDGET_PAGE: &str = "widget.html";
+
+/// Where the apps find the gateway and the signaling server.
+pub struct RuntimeConfig {
+    pub api_gateway: Option<String>,
+    pub signaling_server: Option<String>,
+    /// Without `api_gateway`, point the apps at the origin serving them.
+    pub same_origin_api: bool,
+}
+
+impl RuntimeConfig {
+    pub fn from_env(same_origin_api: bool) -> Self {
+        let var = |name| std::env::var(name).ok().filter(|v: &String| !v.is_empty());
+        RuntimeConfig {
+            api_gateway: var("WEB_API_GATEWAY"),
+            signaling_server: var("WEB_SIGNALING_SERVER"),
+            same_origin_api,
+        }
+    }
+
+    fn script(&self) -> String {
+        let mut entries = Vec::new();
+        match &self.api_gateway {
+            Some(url) => entries.push(format!("apiGateway: {}", serde_json::Value::from(url.as_str()))),
+            None if self.same_origin_api => entries.push("apiGateway: window.location.origin".to_string()),
+            None => {}
+        }
+        if let Some(url) = &self.signaling_server {
+            entries.push(format!("signalingServer: {}", serde_json::Value::from(url.as_str())));
+        }
+        format!(
+            "// Written by the gateway when it serves the app\nwindow.RELAYER_CONFIG = Object.freeze({{ {} }});\n",
+            entries.join(", ")
+        )
+    }
+}
+
+pub struct Policy {
+    connect_src: String,
+    frame_ancestors: String,
+}
+
+impl Policy {
+    pub fn from_env(config: &RuntimeConfig) -> Self {
+        let mut connect_src = std::env::var("WEB_CONNECT_SRC")
+            .unwrap_or_else(|_| "http://localhost:3001 ws://localhost:8080".to_string());
+        for url in config.api_gateway.iter().chain(&config.signaling_server) {
+            connect_src.push(' ');
+            connect_src.push_str(url);
+        }
+        Policy {
+            connect_src,
+            frame_ancestors: std::env::var("WEB_FRAME_ANCESTORS").unwrap_or_else(|_| "*".to_string()),
+        }
+    }
+
+    /// The policy for `path`, allowing the inline scripts and styles
+    /// hashed in `scripts` and `styles`.
+    fn header(&self, path: &str, scripts: &[String], styles: &[String]) -> String {
+        let frame_ancestors = if path == WIDGET_PAGE { self.frame_ancestors.as_str() } else { "'none'" };
+        let script_src = ["'self'", "'wasm-unsafe-eval'"].into_iter().chain(scripts.iter().map(String::as_str));
+        let style_src = std::iter::once("'self'").chain(styles.iter().map(String::as_str));
+        [
+            "default-src 'none'".to_string(),
+            format!("script-src {}", script_src.collect::<Vec<_>>().join(" ")),
+            format!("style-src {}", style_src.collect::<Vec<_>>().join(" ")),
+            // Dioxus renders `style` attributes
+            "style-src-attr 'unsafe-inline'".to_string(),
+            format!("connect-src 'self' {}", self.connect_src),
+            "worker-src 'self'".to_string(),
+            "img-src 'self' data:".to_string(),
+            "font-src 'self'".to_string(),
+            "object-src 'none'".to_string(),
+            "base-uri 'none'".to_string(),
+            "form-action 'none'".to_string(),
+            format!("frame-ancestors {}", frame_ancestors),
+        ]
+        .join("; ")
+    }
+}
+
+struct Asset {
+    body: Bytes,
+    content_type: &'static str,
+    cache_control: &'static str,
+    etag: String,
+    csp: String,
+}
+
+/// An app as served, by path without the leading `/`.
+pub struct Bundle {
+    assets: HashMap<String, Asset>,
+}
+
+/// Adds the files under `dir` to `files`, by path relative to `root`.
+pub fn read_dir(root: &Path, dir: &Path, files: &mut HashMap<String, Vec<u8>>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_dir(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let name = relative.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            files.insert(name, std::fs::read(&path)?);
+        }
+    }
+    Ok(())
+}
+
+fn content_type(path: &str) -> &'static str {
+    match path.rsplit_once('.').map(|(_, extension)| extension) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("wasm") => "application/wasm",
+        Some("css") => "text/css; charset=utf-8",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        _ => "application/octet-stream",
+    }
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("")
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit_once('/').map(|(_, name)| name).unwrap_or(path)
+}
+
+/// `pkg/app_bg.wasm` becomes `pkg/app_bg.<hash>.wasm`.
+fn hashed_path(path: &str, body: &[u8]) -> String {
+    let hash = &hex::encode(Sha256::digest(body))[..16];
+    match path.rsplit_once('.') {
+        Some((stem, extension)) if !stem.ends_with('/') => format!("{}.{}.{}", stem, hash, extension),
+        _ => format!("{}.{}", path, hash),
+    }
+}
+
+fn integrity(body: &[u8]) -> String {
+    format!("sha384-{}", BASE64.encode(Sha384::digest(body)))
+}
+
+/// CSP hash sources for the contents of each inline `<tag>` in `html`.
+fn inline_hashes(html: &str, tag: &str) -> Vec<String> {
+    let (open, close) = (format!("<{}", tag), format!("</{}>", tag));
+    let mut hashes = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find(&open) {
+        let Some(tag_end) = rest[start..].find('>').map(|i| start + i + 1) else {
+            break;
+        };
+        let Some(end) = rest[tag_end..].find(&close).map(|i| tag_end + i) else {
+            break;
+        };
+        if !rest[start..tag_end].contains("src=") {
+            hashes.push(format!("'sha256-{}'", BASE64.encode(Sha256::digest(&rest.as_bytes()[tag_end..end]))));
+        }
+        rest = &rest[end + close.len()..];
+    }
+    hashes
+}
+
+impl Bundle {
+    pub fn load(root: &Path, policy: &Policy, config: &RuntimeConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut files = HashMap::new();
+        read_dir(root, root, &mut files)?;
+        Self::from_files(files, policy, config, &root.display().to_string())
+    }
+
+    /// `source` names where `files` came from, for the logs.
+    pub fn from_files(
+        mut files: HashMap<String, Vec<u8>>,
+        policy: &Policy,
+        config: &RuntimeConfig,
+        source: &str,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        if !files.contains_key("index.html") {
+            return Err(format!("No index.html in {}", source).into());
+        }
+        inject_config(&mut files, config);
+
+        // The wasm, then the glue modules that fetch it, get hashed names
+        let mut renamed: HashMap<String, String> = HashMap::new();
+        let mut integrities: HashMap<String, String> = HashMap::new();
+        for (path, body) in files.iter().filter(|(path, _)| path.ends_with(".wasm")) {
+            let hashed = hashed_path(path, body);
+            integrities.insert(hashed.clone(), integrity(body));
+            renamed.insert(path.clone(), hashed);
+        }
+        let wasm: Vec<(String, String)> = renamed.clone().into_iter().collect();
+        let glue: Vec<String> =
+            files.keys().filter(|path| path.contains('/') && path.ends_with(".js")).cloned().collect();
+        for path in glue {
+            let Ok(mut text) = String::from_utf8(files[&path].clone()) else {
+                continue;
+            };
+            let mut fetches_wasm = false;
+            for (wasm_path, hashed) in wasm.iter().filter(|(wasm_path, _)| parent(wasm_path) == parent(&path)) {
+                let (name, hashed_name) = (file_name(wasm_path), file_name(hashed));
+                let url = format!("new URL('{}', import.meta.url)", name);
+                if text.contains(&url) {
+                    let pinned = format!(
+                        "fetch(new URL('{}', import.meta.url), {{ integrity: '{}' }})",
+                        hashed_name, integrities[hashed]
+                    );
+                    text = text.replace(&url, &pinned);
+                } else if text.contains(name) {
+                    warn!("{} loads {} without a URL we recognise; its integrity is not pinned", path, name);
+                    text = text.replace(name, hashed_name);
+                } else {
+                    continue;
+                }
+                fetches_wasm = true;
+            }
+            if fetches_wasm {
+                let body = text.into_bytes();
+                let hashed = hashed_path(&path, &body);
+                integrities.insert(hashed.clone(), integrity(&body));
+                renamed.insert(path.clone(), hashed);
+                files.insert(path, body);
+            }
+        }
+
+        let mut assets = HashMap::new();
+        for (path, body) in files {
+            if let Some(hashed) = renamed.get(&path) {
+                let csp = policy.header(hashed, &[], &[]);
+                assets.insert(hashed.clone(), Asset::new(hashed, body, IMMUTABLE, csp));
+                continue;
+            }
+            let top_level = !path.contains('/');
+            let text = if top_level { String::from_utf8(body.clone()).ok() } else { None };
+            let Some(mut text) = text else {
+                let csp = policy.header(&path, &[], &[]);
+                assets.insert(path.clone(), Asset::new(&path, body, REVALIDATE, csp));
+                continue;
+            };
+
+            // Point the pages and the worker at the hashed names
+            let mut pinned = Vec::new();
+            for (original, hashed) in &renamed {
+                if text.contains(original.as_str()) {
+                    text = text.replace(original.as_str(), hashed);
+                    if hashed.ends_with(".js") {
+                        pinned.push((hashed, &integrities[hashed]));
+                    }
+                }
+            }
+            let csp = if path.ends_with(".html") {
+                if !pinned.is_empty() {
+                    text = text.replacen("</head>", &pin_modules(&pinned), 1);
+                }
+                policy.header(&path, &inline_hashes(&text, "script"), &inline_hashes(&text, "style"))
+            } else {
+                policy.header(&path, &[], &[])
+            };
+            assets.insert(path.clone(), Asset::new(&path, text.into_bytes(), REVALIDATE, csp));
+        }
+
+        info!("Serving {} files from {} ({} with hashed names)", assets.len(), source, renamed.len());
+        Ok(Bundle { assets })
+    }
+
+    /// The response for `path` within the app, `index.html` for a
+    /// directory.
+    pub fn response(&self, path: &str, method: &Method, headers: &HeaderMap) -> Response {
+        if method != Method::GET && method != Method::HEAD {
+            return StatusCode::METHOD_NOT_ALLOWED.into_response();
+        }
+        let mut path = path.trim_start_matches('/').to_string();
+        if path.is_empty() || path.ends_with('/') {
+            path.push_str("index.html");
+        }
+        match self.assets.get(&path) {
+            Some(asset) => asset.response(method, headers),
+            None => StatusCode::NOT_FOUND.into_response(),
+        }
+    }
+}
+
+/// Adds `config.js` and loads it from every top-level page, ahead of
+/// the page's own scripts.
+fn inject_config(files: &mut HashMap<String, Vec<u8>>, config: &RuntimeConfig) {
+    for (path, body) in files.iter_mut() {
+        if path.contains('/') || !path.ends_with(".html") {
+            continue;
+        }
+        let Ok(text) = std::str::from_utf8(body) else {
+            continue;
+        };
+        if text.contains("</head>") {
+            *body = text.replacen("</head>", "    <script src=\"./config.js\"></script>\n</head>", 1).into_bytes();
+        }
+    }
+    files.insert("config.js".to_string(), config.script().into_bytes());
+}
+
+/// An import map and `modulepreload` links giving the integrity of each
+/// module, followed by `</head>`. Import maps must come before the page's
+/// module scripts.
+fn pin_modules(modules: &[(&String, &String)]) -> String {
+    let map = serde_json::json!({
+        "integrity": modules
+            .iter()
+            .map(|(path, integrity)| (format!("./{}", path), serde_json::Value::from(integrity.as_str())))
+            .collect::<serde_json::Map<_, _>>(),
+    });
+    let mut head = format!("    <script type=\"importmap\">{}</script>\n", map);
+    for (path, integrity) in modules {
+        head.push_str(&format!(
+            "    <link rel=\"modulepreload\" href=\"./{}\" integrity=\"{}\" crossorigin=\"anonymous\">\n",
+            path, integrity
+        ));
+    }
+    head.push_str("</head>");
+    head
+}
+
+impl Asset {
+    fn new(path: &str, body: Vec<u8>, cache_control: &'static str, csp: String) -> Self {
+        Asset {
+            etag: format!("\"{}\"", &hex::encode(Sha256::digest(&body))[..32]),
+            body: Bytes::from(body),
+            content_type: content_type(path),
+            cache_control,
+            csp,
+        }
+    }
+
+    fn response(&self, method: &Method, headers: &HeaderMap) -> Response {
+        let not_modified = headers
+            .get(header::IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|tags| tags.split(',').any(|tag| tag.trim() == self.etag || tag.trim() == "*"));
+        let (status, body) = if not_modified {
+            (StatusCode::NOT_MODIFIED, Body::empty())
+        } else if method == Method::HEAD {
+            (StatusCode::OK, Body::empty())
+        } else {
+            (StatusCode::OK, Body::from(self.body.clone()))
+        };
+        let mut response = (status, body).into_response();
+        let headers = response.headers_mut();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(self.content_type));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(self.cache_control));
+        if let Ok(etag) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, etag);
+        }
+        if let Ok(csp) = HeaderValue::from_str(&self.csp) {
+            headers.insert(header::CONTENT_SECURITY_POLICY, csp);
+        }
+        headers.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+        headers.insert(header::REFERRER_POLICY, HeaderValue::from_static("no-referrer"));
+        response
+    }
+}
+
+async fn serve_asset(State(bundle): State<Arc<Bundle>>, method: Method, uri: Uri, headers: HeaderMap) -> Response {
+    bundle.response(uri.path(), &method, &headers)
+}
+
+/// Serves `bundle` at every path.
+pub fn router(bundle: Bundle) -> Router {
+    Router::new().fallback(serve_asset).with_state(Arc::new(bundle))
+}
+
+pub async fn serve(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let root = args.first().ok_or("Usage: api-gateway serve-web <dir>")?;
+    let config = RuntimeConfig::from_env(false);
+    let bundle = Bundle::load(Path::new(root), &Policy::from_env(&config), &config)?;
+    let port: u16 = std::env::var("WEB_PORT").ok().and_then(|v| v.parse().ok()).unwrap_or(8000);
+    let app = router(bundle);
+
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+    info!("🌐 Serving {} on http://0.0.0.0:{}", root, port);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
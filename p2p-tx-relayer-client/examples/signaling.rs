@@ -0,0 +1,29 @@
+//! Joins a signaling room as a native peer and prints what arrives.
+//!
+//! `cargo run --example signaling --features signaling -- [room]`
+
+use p2p_tx_relayer_client::SignalingClient;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let server = std::env::var("SIGNALING_SERVER").unwrap_or_else(|_| "ws://localhost:8080".to_string());
+    let room = std::env::args().nth(1).unwrap_or_else(|| "default".to_string());
+
+    let peer_id = format!("rust-{}", std::process::id());
+    let mut signaling = SignalingClient::connect(&server, peer_id, &[]).await?;
+    println!("protocol version {}", signaling.negotiated().protocol_version);
+    for info in signaling.rooms().await? {
+        println!("room {} ({} peers)", info.room_id, info.peer_count);
+    }
+    signaling.join(&room).await?;
+
+    while let Some(message) = signaling.next_message().await? {
+        match (&message.message_type[..], &message.transaction) {
+            ("transaction", Some(transaction)) => {
+                println!("transaction {} {} from {}", transaction.id, transaction.amount, transaction.from_endpoint)
+            }
+            (message_type, _) => println!("{} from {}", message_type, message.from_peer.as_deref().unwrap_or("server")),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,45 @@
+//! Signs a transfer and submits it to a local gateway.
+//!
+//! `cargo run --example submit -- alice bob 12.50`
+
+use chrono::Utc;
+use ed25519_dalek::{Signer, SigningKey};
+use p2p_tx_relayer_client::{canonical_bytes, Client, Money, Transaction, TransactionKind, TransactionStatus};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let from = args.next().unwrap_or_else(|| "alice".to_string());
+    let to = args.next().unwrap_or_else(|| "bob".to_string());
+    let amount: Money = args.next().as_deref().unwrap_or("1").parse()?;
+    let gateway = std::env::var("API_GATEWAY_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+
+    // A throwaway key; a real sender keeps one and registers it
+    let key = SigningKey::from_bytes(&rand::random::<[u8; 32]>());
+    let mut transaction = Transaction {
+        id: uuid::Uuid::new_v4().to_string(),
+        from_endpoint: from,
+        to_endpoint: to,
+        amount,
+        timestamp: Utc::now(),
+        signature: String::new(),
+        status: TransactionStatus::Pending,
+        kind: TransactionKind::Transfer,
+        risk_score: None,
+        parent_tx_id: None,
+        sequence: Some(Utc::now().timestamp_millis() as u64),
+        public_key: Some(hex::encode(key.verifying_key().as_bytes())),
+    };
+    let signature = key.sign(&canonical_bytes(&transaction));
+    transaction.signature = hex::encode(signature.to_bytes());
+
+    let client = Client::builder(gateway).build()?;
+    let response = client.submit(&transaction).await?;
+    println!(
+        "{} recorded, risk {}{}",
+        response.id,
+        response.risk_score,
+        if response.held { " (held for review)" } else { "" }
+    );
+    Ok(())
+}
@@ -0,0 +1,49 @@
+//! Prints an endpoint's (or everyone's) transactions as they change,
+//! resuming across restarts from the id saved in `watch.last-event-id`.
+//!
+//! `cargo run --example watch -- [endpoint]`
+
+use futures_util::StreamExt;
+use p2p_tx_relayer_client::{Client, LiveEvent, WatchOptions};
+
+const LAST_EVENT_FILE: &str = "watch.last-event-id";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let gateway = std::env::var("API_GATEWAY_URL").unwrap_or_else(|_| "http://localhost:3000".to_string());
+    let client = Client::builder(gateway).build()?;
+
+    let mut options = WatchOptions::new();
+    if let Some(endpoint) = std::env::args().nth(1) {
+        options = options.endpoint(endpoint);
+    }
+    if let Ok(last_event_id) = std::fs::read_to_string(LAST_EVENT_FILE) {
+        options = options.resume(last_event_id.trim());
+    }
+
+    let mut events = client.watch(options);
+    while let Some(event) = events.next().await {
+        match event {
+            Ok(LiveEvent::Transaction { change, id, transaction }) => {
+                println!(
+                    "{} {} {} -> {} {} [{}]",
+                    change,
+                    transaction.id,
+                    transaction.from_endpoint,
+                    transaction.to_endpoint,
+                    transaction.amount,
+                    transaction.status.as_str()
+                );
+                std::fs::write(LAST_EVENT_FILE, id)?;
+            }
+            Ok(LiveEvent::Lagged { missed }) => {
+                // Too far behind to replay; start over from a fresh page
+                eprintln!("Missed {} events, refetching", missed);
+                let page = client.transactions(&Default::default()).await?;
+                println!("{} most recent transactions reloaded", page.items.len());
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    Ok(())
+}
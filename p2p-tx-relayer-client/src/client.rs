@@ -0,0 +1,297 @@
+use std::time::Duration;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::stream::{BoxStream, StreamExt};
+use reqwest::{RequestBuilder, Response};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tx_core::{Receipt, Transaction, TransactionStatus};
+
+use crate::models::{EndpointBalance, EndpointStats, IngestResponse, Page, PaymentInstruction, TransactionStats};
+use crate::stream::{self, LiveEvent, WatchOptions};
+use crate::Error;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub struct ClientBuilder {
+    base_url: String,
+    timeout: Duration,
+    user_agent: String,
+    http: Option<reqwest::Client>,
+    token: Option<BearerToken>,
+}
+
+/// Redacted from `Debug`.
+#[derive(Clone)]
+struct BearerToken(String);
+
+impl std::fmt::Debug for BearerToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BearerToken(***)")
+    }
+}
+
+impl ClientBuilder {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        ClientBuilder {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            timeout: DEFAULT_TIMEOUT,
+            user_agent: concat!("p2p-tx-relayer-client/", env!("CARGO_PKG_VERSION")).to_string(),
+            http: None,
+            token: None,
+        }
+    }
+
+    /// Per request; the live stream isn't cut off by it.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Shares a configured `reqwest::Client` (proxies, TLS roots, pool).
+    /// `user_agent` is then left to it.
+    pub fn http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = Some(http);
+        self
+    }
+
+    /// Sent as `Authorization: Bearer` on every request, for gateways
+    /// that require a JWT.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(BearerToken(token.into()));
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let http = match self.http {
+            Some(http) => http,
+            None => reqwest::Client::builder().user_agent(self.user_agent).build()?,
+        };
+        Ok(Client { http, base_url: self.base_url, timeout: self.timeout, token: self.token })
+    }
+}
+
+/// Filters and position for `Client::transactions`.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionQuery {
+    endpoint: Option<String>,
+    before: Option<String>,
+    after: Option<String>,
+    limit: Option<u32>,
+    as_of: Option<DateTime<Utc>>,
+}
+
+impl TransactionQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only transactions `endpoint_id` sent or received.
+    pub fn endpoint(mut self, endpoint_id: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint_id.into());
+        self
+    }
+
+    /// Older than a page's `next_cursor`.
+    pub fn before(mut self, cursor: impl Into<String>) -> Self {
+        self.before = Some(cursor.into());
+        self
+    }
+
+    /// Newer than a cursor, for catching up from a known position.
+    pub fn after(mut self, cursor: impl Into<String>) -> Self {
+        self.after = Some(cursor.into());
+        self
+    }
+
+    /// The gateway clamps this to its own maximum.
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Transactions as they stood at `as_of`.
+    pub fn as_of(mut self, as_of: DateTime<Utc>) -> Self {
+        self.as_of = Some(as_of);
+        self
+    }
+
+    fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params = Vec::new();
+        if let Some(endpoint) = &self.endpoint {
+            params.push(("endpoint", endpoint.clone()));
+        }
+        if let Some(before) = &self.before {
+            params.push(("before", before.clone()));
+        }
+        if let Some(after) = &self.after {
+            params.push(("after", after.clone()));
+        }
+        if let Some(limit) = self.limit {
+            params.push(("limit", limit.to_string()));
+        }
+        if let Some(as_of) = self.as_of {
+            params.push(("as_of", rfc3339(as_of)));
+        }
+        params
+    }
+}
+
+fn rfc3339(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// A handle on one gateway. Cheap to clone; clones share the connection
+/// pool.
+#[derive(Clone, Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    timeout: Duration,
+    token: Option<BearerToken>,
+}
+
+impl Client {
+    pub fn builder(base_url: impl Into<String>) -> ClientBuilder {
+        ClientBuilder::new(base_url)
+    }
+
+    pub(crate) fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub(crate) fn http(&self) -> &reqwest::Client {
+        &self.http
+    }
+
+    pub(crate) fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        match &self.token {
+            Some(BearerToken(token)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        check(self.authorize(request).timeout(self.timeout).send().await?).await
+    }
+
+    async fn get_json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// `None` for 404s, which these lookups use for "no such thing".
+    async fn get_optional<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<Option<T>, Error> {
+        match self.get_json(request).await {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::Api { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Records a signed transaction. Keyed by its id, so resubmitting
+    /// after a timeout returns the first answer instead of a duplicate.
+    pub async fn submit(&self, transaction: &Transaction) -> Result<IngestResponse, Error> {
+        let request = self
+            .http
+            .post(self.url("/api/transactions"))
+            .header("idempotency-key", &transaction.id)
+            .json(transaction);
+        self.get_json(request).await
+    }
+
+    pub async fn transaction(&self, id: &str) -> Result<Option<Transaction>, Error> {
+        self.get_optional(self.http.get(self.url(&format!("/api/transactions/{}", id)))).await
+    }
+
+    pub async fn transactions(&self, query: &TransactionQuery) -> Result<Page<Transaction>, Error> {
+        let response = self.send(self.http.get(self.url("/api/transactions")).query(&query.params())).await?;
+        let next_cursor = response
+            .headers()
+            .get("next-cursor")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        Ok(Page { items: response.json().await?, next_cursor })
+    }
+
+    /// For operators: moves a transaction along, e.g. releasing a held
+    /// one. The gateway rejects transitions its state machine doesn't
+    /// allow with 409.
+    pub async fn set_status(&self, id: &str, status: TransactionStatus) -> Result<(), Error> {
+        let request = self
+            .http
+            .patch(self.url(&format!("/api/transactions/{}/status", id)))
+            .json(&json!({ "status": status }));
+        self.send(request).await.map(|_| ())
+    }
+
+    /// The gateway's signed receipt; `None` while the transaction is
+    /// unknown or not yet settled.
+    pub async fn receipt(&self, id: &str) -> Result<Option<Receipt>, Error> {
+        let request = self.http.get(self.url(&format!("/api/transactions/{}/receipt", id)));
+        match self.get_optional(request).await {
+            Err(Error::Api { status: 409, .. }) => Ok(None),
+            result => result,
+        }
+    }
+
+    pub async fn stats(&self) -> Result<TransactionStats, Error> {
+        self.get_json(self.http.get(self.url("/api/stats"))).await
+    }
+
+    pub async fn endpoint_stats(&self, endpoint_id: &str, as_of: Option<DateTime<Utc>>) -> Result<EndpointStats, Error> {
+        let mut request = self.http.get(self.url(&format!("/api/endpoints/{}/stats", endpoint_id)));
+        if let Some(as_of) = as_of {
+            request = request.query(&[("as_of", rfc3339(as_of))]);
+        }
+        self.get_json(request).await
+    }
+
+    /// Now unless `as_of` is given.
+    pub async fn balance(&self, endpoint_id: &str, as_of: Option<DateTime<Utc>>) -> Result<EndpointBalance, Error> {
+        let mut request = self.http.get(self.url(&format!("/api/endpoints/{}/balance", endpoint_id)));
+        if let Some(as_of) = as_of {
+            request = request.query(&[("as_of", rfc3339(as_of))]);
+        }
+        self.get_json(request).await
+    }
+
+    /// Payment instructions imported for `endpoint_id` that it has yet to
+    /// sign. Submitting the signed transfer releases one.
+    pub async fn payment_instructions(&self, endpoint_id: &str) -> Result<Vec<PaymentInstruction>, Error> {
+        self.get_json(self.http.get(self.url(&format!("/api/endpoints/{}/payment-instructions", endpoint_id)))).await
+    }
+
+    /// Turns down a queued payment instruction instead of signing it.
+    pub async fn decline_payment_instruction(
+        &self,
+        endpoint_id: &str,
+        transaction_id: &str,
+        reason: Option<&str>,
+    ) -> Result<(), Error> {
+        let request = self
+            .http
+            .post(self.url(&format!("/api/endpoints/{}/payment-instructions/{}/decline", endpoint_id, transaction_id)))
+            .json(&json!({ "reason": reason }));
+        self.send(request).await.map(|_| ())
+    }
+
+    /// Follows the live stream, reconnecting as needed; see `WatchOptions`.
+    pub fn watch(&self, options: WatchOptions) -> BoxStream<'static, Result<LiveEvent, Error>> {
+        stream::watch(self.clone(), options).boxed()
+    }
+}
+
+/// Turns error statuses into `Error::Api` with the body as message.
+pub(crate) async fn check(response: Response) -> Result<Response, Error> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let message = response.text().await.unwrap_or_default();
+    Err(Error::Api { status: status.as_u16(), message: message.trim().to_string() })
+}
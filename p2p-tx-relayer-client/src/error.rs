@@ -0,0 +1,64 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The request didn't complete: connection, TLS or timeout.
+    Transport(reqwest::Error),
+    /// The gateway answered with an error status.
+    Api { status: u16, message: String },
+    /// A response body that isn't what the gateway sends.
+    Decode(String),
+    #[cfg(feature = "signaling")]
+    Signaling(String),
+}
+
+impl Error {
+    /// The HTTP status, for `Api` errors.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            Error::Api { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Whether retrying the same request may succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::Transport(_) => true,
+            Error::Api { status, .. } => *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(e) => write!(f, "Request failed: {}", e),
+            Error::Api { status, message } if message.is_empty() => write!(f, "Gateway returned HTTP {}", status),
+            Error::Api { status, message } => write!(f, "Gateway returned HTTP {}: {}", status, message),
+            Error::Decode(e) => write!(f, "Invalid response: {}", e),
+            #[cfg(feature = "signaling")]
+            Error::Signaling(e) => write!(f, "Signaling failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            Error::Decode(e.to_string())
+        } else {
+            Error::Transport(e)
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! Client for the relayer's api-gateway, and optionally its signaling
+//! server, for Rust services that submit or watch transactions.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), p2p_tx_relayer_client::Error> {
+//! use futures_util::StreamExt;
+//! use p2p_tx_relayer_client::{Client, LiveEvent, WatchOptions};
+//!
+//! let client = Client::builder("http://localhost:3000").build()?;
+//! println!("{} transactions", client.stats().await?.total_transactions);
+//!
+//! let mut events = client.watch(WatchOptions::new().endpoint("alice"));
+//! while let Some(event) = events.next().await {
+//!     if let LiveEvent::Transaction { change, transaction, .. } = event? {
+//!         println!("{} {}", change, transaction.id);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Transactions, receipts and signaling messages are `tx-core`'s types,
+//! re-exported here, so they sign and verify exactly as the endpoints'
+//! do. The `signaling` feature adds `SignalingClient`.
+
+mod client;
+mod error;
+mod models;
+#[cfg(feature = "signaling")]
+mod signaling;
+mod stream;
+
+pub use client::{Client, ClientBuilder, TransactionQuery};
+pub use error::Error;
+pub use models::{EndpointBalance, EndpointStats, IngestResponse, Page, PaymentInstruction, TransactionStats};
+#[cfg(feature = "signaling")]
+pub use signaling::SignalingClient;
+pub use stream::{Change, LiveEvent, WatchOptions};
+
+pub use tx_core::{
+    canonical_bytes, plugin, verify_receipt, verify_transaction, Capabilities, Capability, Money, Plugin, Receipt,
+    SignalingMessage, Transaction, TransactionKind, TransactionStatus,
+};
@@ -0,0 +1,80 @@
+//! Response bodies of the gateway's REST API. Amounts are major units on
+//! the wire and `Money` here, as in the gateway.
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use tx_core::Money;
+
+/// `POST /api/transactions`'s answer.
+#[derive(Clone, Debug, Deserialize)]
+pub struct IngestResponse {
+    pub id: String,
+    pub risk_score: u8,
+    pub risk_reasons: Vec<String>,
+    /// Whether receivers should hold the payment for manual accept.
+    pub held: bool,
+    /// Set by the gateway's ingest transform; gateways without one send
+    /// none.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct TransactionStats {
+    pub total_transactions: i64,
+    #[serde(with = "tx_core::money::as_major")]
+    pub total_volume: Money,
+    #[serde(with = "tx_core::money::as_major")]
+    pub average_transaction: Money,
+    pub endpoints: Vec<EndpointStats>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EndpointStats {
+    pub endpoint_id: String,
+    pub transaction_count: i64,
+    #[serde(with = "tx_core::money::as_major")]
+    pub total_sent: Money,
+    #[serde(with = "tx_core::money::as_major")]
+    pub total_received: Money,
+    #[serde(with = "tx_core::money::as_major")]
+    pub balance_change: Money,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct EndpointBalance {
+    pub endpoint_id: String,
+    #[serde(with = "tx_core::money::as_major")]
+    pub balance: Money,
+    pub as_of: DateTime<Utc>,
+}
+
+/// A transfer imported from a `pain.001` file, waiting for its sender to
+/// sign it as a `Transaction` with `transaction_id` as its id and submit
+/// it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct PaymentInstruction {
+    pub transaction_id: String,
+    pub file_id: String,
+    pub payment_info_id: String,
+    pub end_to_end_id: String,
+    pub from_endpoint: String,
+    pub to_endpoint: String,
+    #[serde(with = "tx_core::money::as_major")]
+    pub amount: Money,
+    pub requested_date: Option<String>,
+    pub remittance: Option<String>,
+    /// `queued`, `released`, `declined` or `rejected`.
+    pub status: String,
+    pub reason: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// One page of a list, newest first.
+#[derive(Clone, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass as `TransactionQuery::before` for the next (older) page; `None`
+    /// on the last one.
+    pub next_cursor: Option<String>,
+}
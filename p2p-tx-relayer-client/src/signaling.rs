@@ -0,0 +1,151 @@
+//! A native peer on the signaling server: joins rooms and exchanges
+//! `SignalingMessage`s over its WebSocket, as the browser endpoints do.
+//! Transactions broadcast here reach room members directly; use
+//! `Client::submit` to have them recorded.
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tx_core::{Capabilities, Capability, Plugin, Plugins, RoomInfo, SignalingMessage, Transaction};
+
+use crate::Error;
+
+fn signaling_error(e: impl std::fmt::Display) -> Error {
+    Error::Signaling(e.to_string())
+}
+
+pub struct SignalingClient {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    peer_id: String,
+    negotiated: Capabilities,
+    plugins: Plugins,
+}
+
+impl SignalingClient {
+    /// `url` is the server's WebSocket address, e.g. `ws://localhost:8080`.
+    /// Offers `features` in the protocol handshake if the server's welcome
+    /// says it has one; see `negotiated`.
+    pub async fn connect(url: &str, peer_id: impl Into<String>, features: &[Capability]) -> Result<Self, Error> {
+        let (socket, _) = tokio_tungstenite::connect_async(url).await.map_err(signaling_error)?;
+        let mut client = SignalingClient {
+            socket,
+            peer_id: peer_id.into(),
+            negotiated: Capabilities::default(),
+            plugins: Plugins::new(),
+        };
+
+        let welcome = client.next_message().await?.ok_or_else(|| signaling_error("Connection closed"))?;
+        if welcome.protocol_version.is_some() {
+            client.send(&SignalingMessage::hello(features)).await?;
+            while let Some(message) = client.next_message().await? {
+                if message.message_type == "negotiated" {
+                    client.negotiated = Capabilities::from_message(&message);
+                    break;
+                }
+            }
+        }
+        Ok(client)
+    }
+
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+
+    /// What the server agreed to; version 1 with nothing for servers from
+    /// before the handshake. Peers' capabilities arrive on `peer-joined`
+    /// and `room-joined`.
+    pub fn negotiated(&self) -> &Capabilities {
+        &self.negotiated
+    }
+
+    /// Adds `plugin` to the hooks run on every message sent and received
+    /// from here on; see `tx_core::plugin`.
+    pub fn register_plugin(&mut self, plugin: impl Plugin + 'static) {
+        self.plugins.register(plugin);
+    }
+
+    /// Fails with `Error::Signaling` if a plugin drops `message`. What the
+    /// plugins queued goes first.
+    pub async fn send(&mut self, message: &SignalingMessage) -> Result<(), Error> {
+        let mut message = message.clone();
+        let sent = self.plugins.before_send(&mut message);
+        self.send_plugin_messages().await?;
+        sent.map_err(signaling_error)?;
+        self.write(&message).await
+    }
+
+    async fn write(&mut self, message: &SignalingMessage) -> Result<(), Error> {
+        let text = serde_json::to_string(message).map_err(signaling_error)?;
+        self.socket.send(Message::Text(text)).await.map_err(signaling_error)
+    }
+
+    /// Plugins' own messages, such as key exchanges, skip the plugins.
+    async fn send_plugin_messages(&mut self) -> Result<(), Error> {
+        for message in self.plugins.take_outgoing() {
+            self.write(&message).await?;
+        }
+        Ok(())
+    }
+
+    /// The server answers with `room-joined`, listing the peers present.
+    pub async fn join(&mut self, room_id: &str) -> Result<(), Error> {
+        let mut message = SignalingMessage::new("join");
+        message.room_id = Some(room_id.to_string());
+        message.peer_id = Some(self.peer_id.clone());
+        self.send(&message).await
+    }
+
+    pub async fn leave(&mut self, room_id: &str) -> Result<(), Error> {
+        let mut message = SignalingMessage::new("leave");
+        message.room_id = Some(room_id.to_string());
+        self.send(&message).await
+    }
+
+    /// Sends `list-rooms` and waits for the `rooms` reply. Other messages
+    /// arriving meanwhile are dropped, so call this before joining.
+    pub async fn rooms(&mut self) -> Result<Vec<RoomInfo>, Error> {
+        self.send(&SignalingMessage::new("list-rooms")).await?;
+        while let Some(message) = self.next_message().await? {
+            if message.message_type == "rooms" {
+                return Ok(message.rooms.unwrap_or_default());
+            }
+        }
+        Err(signaling_error("Connection closed"))
+    }
+
+    /// Broadcasts a signed transaction to the rooms joined.
+    pub async fn broadcast_transaction(&mut self, transaction: &Transaction) -> Result<(), Error> {
+        let mut message = SignalingMessage::new("transaction");
+        message.peer_id = Some(self.peer_id.clone());
+        message.transaction = Some(transaction.clone());
+        self.send(&message).await
+    }
+
+    /// The next message from the server, `None` once it has closed.
+    /// Messages that aren't `SignalingMessage`s, and ones a plugin drops,
+    /// are skipped.
+    pub async fn next_message(&mut self) -> Result<Option<SignalingMessage>, Error> {
+        while let Some(frame) = self.socket.next().await {
+            match frame.map_err(signaling_error)? {
+                Message::Text(text) => {
+                    if let Ok(mut message) = serde_json::from_str(&text) {
+                        let received = self.plugins.receive(&mut message);
+                        self.send_plugin_messages().await?;
+                        if received.is_ok() {
+                            return Ok(Some(message));
+                        }
+                    }
+                }
+                Message::Close(_) => return Ok(None),
+                // tungstenite answers pings itself
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.socket.close(None).await.map_err(signaling_error)
+    }
+}
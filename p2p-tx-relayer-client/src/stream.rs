@@ -0,0 +1,250 @@
+//! The gateway's live stream, `GET /api/transactions/stream` (SSE), as a
+//! `Stream` of typed events that survives dropped connections: it
+//! reconnects with the last event id seen, and the gateway replays what
+//! was missed while it still has it.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_util::stream::{self, Stream, StreamExt};
+use tx_core::Transaction;
+
+use crate::client::{self, Client};
+use crate::Error;
+
+const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Debug)]
+pub struct WatchOptions {
+    endpoint: Option<String>,
+    resume: Option<String>,
+    reconnect_delay: Duration,
+}
+
+impl Default for WatchOptions {
+    fn default() -> Self {
+        WatchOptions { endpoint: None, resume: None, reconnect_delay: DEFAULT_RECONNECT_DELAY }
+    }
+}
+
+impl WatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only changes to transactions `endpoint_id` sent or received.
+    pub fn endpoint(mut self, endpoint_id: impl Into<String>) -> Self {
+        self.endpoint = Some(endpoint_id.into());
+        self
+    }
+
+    /// Starts after the event with this id, e.g. one persisted by a
+    /// previous run.
+    pub fn resume(mut self, event_id: impl Into<String>) -> Self {
+        self.resume = Some(event_id.into());
+        self
+    }
+
+    pub fn reconnect_delay(mut self, delay: Duration) -> Self {
+        self.reconnect_delay = delay;
+        self
+    }
+}
+
+/// What happened to a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Created,
+    /// A chargeback was recorded; `transaction` is the chargeback.
+    Reversed,
+    StatusChanged,
+}
+
+impl Change {
+    fn from_event(name: &str) -> Option<Self> {
+        match name {
+            "transaction.created" => Some(Change::Created),
+            "transaction.reversed" => Some(Change::Reversed),
+            "transaction.status_changed" => Some(Change::StatusChanged),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Created => "transaction.created",
+            Change::Reversed => "transaction.reversed",
+            Change::StatusChanged => "transaction.status_changed",
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum LiveEvent {
+    Transaction {
+        change: Change,
+        /// `<partition>:<offset>`; persist it to `resume` from later.
+        id: String,
+        transaction: Box<Transaction>,
+    },
+    /// Events were dropped: the stream fell behind, or the resume point
+    /// is no longer buffered. Refetch what you show. `missed` is 0 when
+    /// the gateway can't tell how many.
+    Lagged { missed: u64 },
+}
+
+/// One SSE event as received.
+#[derive(Default)]
+struct SseEvent {
+    event: Option<String>,
+    id: Option<String>,
+    data: Vec<String>,
+}
+
+impl SseEvent {
+    fn parse(block: &str) -> Self {
+        let mut sse = SseEvent::default();
+        for line in block.lines() {
+            // Comments (`:heartbeat`) keep the connection open and
+            // nothing else
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "event" => sse.event = Some(value.to_string()),
+                "id" => sse.id = Some(value.to_string()),
+                "data" => sse.data.push(value.to_string()),
+                _ => {}
+            }
+        }
+        sse
+    }
+
+    fn into_live_event(self) -> Option<Result<LiveEvent, Error>> {
+        let name = self.event?;
+        let data = self.data.join("\n");
+        if name == "lagged" {
+            return Some(Ok(LiveEvent::Lagged { missed: data.parse().unwrap_or(0) }));
+        }
+        // Event kinds added after this client are skipped
+        let change = Change::from_event(&name)?;
+        let transaction: Box<Transaction> = match serde_json::from_str(&data) {
+            Ok(transaction) => transaction,
+            Err(e) => return Some(Err(Error::Decode(format!("{} event: {}", name, e)))),
+        };
+        Some(Ok(LiveEvent::Transaction { change, id: self.id.unwrap_or_default(), transaction }))
+    }
+}
+
+type Body = Pin<Box<dyn Stream<Item = reqwest::Result<bytes::Bytes>> + Send>>;
+
+struct Watch {
+    client: Client,
+    options: WatchOptions,
+    last_event_id: Option<String>,
+    body: Option<Body>,
+    buffer: Vec<u8>,
+    pending: VecDeque<Result<LiveEvent, Error>>,
+    connected_before: bool,
+    done: bool,
+}
+
+impl Watch {
+    async fn connect(&mut self) -> Result<(), Error> {
+        if self.connected_before {
+            tokio::time::sleep(self.options.reconnect_delay).await;
+        }
+        self.connected_before = true;
+
+        let mut request = self
+            .client
+            .http()
+            .get(self.client.url("/api/transactions/stream"))
+            .header("accept", "text/event-stream");
+        if let Some(endpoint) = &self.options.endpoint {
+            request = request.query(&[("endpoint", endpoint)]);
+        }
+        if let Some(last_event_id) = &self.last_event_id {
+            request = request.header("last-event-id", last_event_id);
+        }
+        let response = client::check(self.client.authorize(request).send().await?).await?;
+        self.buffer.clear();
+        self.body = Some(Box::pin(response.bytes_stream()));
+        Ok(())
+    }
+
+    /// Moves every complete event in `buffer` to `pending`.
+    fn drain_events(&mut self) {
+        // Line endings may be \r\n or \n; only \n matters here
+        self.buffer.retain(|&byte| byte != b'\r');
+        while let Some(end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+            let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+            let sse = SseEvent::parse(&String::from_utf8_lossy(&block));
+            if let Some(id) = &sse.id {
+                self.last_event_id = Some(id.clone());
+            }
+            if let Some(event) = sse.into_live_event() {
+                self.pending.push_back(event);
+            }
+        }
+    }
+
+    async fn next(&mut self) -> Option<Result<LiveEvent, Error>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            if self.done {
+                return None;
+            }
+            let Some(body) = &mut self.body else {
+                if let Err(e) = self.connect().await {
+                    // A 4xx won't fix itself; anything else is retried
+                    self.done = !e.is_retryable() && e.status().is_some();
+                    return Some(Err(e));
+                }
+                continue;
+            };
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    self.buffer.extend_from_slice(&chunk);
+                    self.drain_events();
+                }
+                Some(Err(e)) => {
+                    self.body = None;
+                    return Some(Err(e.into()));
+                }
+                None => self.body = None,
+            }
+        }
+    }
+}
+
+/// Errors are yielded and the stream carries on reconnecting, except
+/// after a client error (4xx), when it ends.
+pub(crate) fn watch(client: Client, options: WatchOptions) -> impl Stream<Item = Result<LiveEvent, Error>> + Send {
+    let watch = Watch {
+        client,
+        last_event_id: options.resume.clone(),
+        options,
+        body: None,
+        buffer: Vec::new(),
+        pending: VecDeque::new(),
+        connected_before: false,
+        done: false,
+    };
+    stream::unfold(watch, |mut watch| async move {
+        let event = watch.next().await?;
+        Some((event, watch))
+    })
+}
@@ -0,0 +1,43 @@
+from typing import Any, AsyncIterator, Dict, List, Optional
+
+__version__: str
+
+class RelayerError(Exception):
+    """A request to the gateway failed."""
+
+class ApiError(RelayerError):
+    """The gateway answered with an error; `args` is `(status, message)`."""
+
+Row = Dict[str, Any]
+
+class Watch(AsyncIterator[Row]):
+    def __aiter__(self) -> "Watch": ...
+    async def __anext__(self) -> Row: ...
+
+class Client:
+    def __init__(self, base_url: str, timeout: Optional[float] = None) -> None: ...
+    def transaction(self, id: str) -> Optional[Row]: ...
+    def transactions(
+        self,
+        endpoint: Optional[str] = None,
+        before: Optional[str] = None,
+        after: Optional[str] = None,
+        limit: Optional[int] = None,
+        as_of: Optional[str] = None,
+    ) -> Row: ...
+    def all_transactions(
+        self,
+        endpoint: Optional[str] = None,
+        as_of: Optional[str] = None,
+        page_size: Optional[int] = None,
+        max_pages: Optional[int] = None,
+    ) -> List[Row]: ...
+    def stats(self) -> Row: ...
+    def endpoint_stats(self, endpoint_id: str, as_of: Optional[str] = None) -> Row: ...
+    def balance(self, endpoint_id: str, as_of: Optional[str] = None) -> Row: ...
+    def watch(
+        self,
+        endpoint: Optional[str] = None,
+        resume: Optional[str] = None,
+        reconnect_delay: Optional[float] = None,
+    ) -> Watch: ...
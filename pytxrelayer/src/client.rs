@@ -0,0 +1,156 @@
+use std::future::Future;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use p2p_tx_relayer_client::{Client, TransactionQuery, WatchOptions};
+use pyo3::prelude::*;
+
+use crate::watch::Watch;
+use crate::{convert, to_py_err};
+
+/// Runs `future` on the shared runtime with the GIL released, so other
+/// Python threads keep going while a request is in flight.
+fn block_on<F>(py: Python<'_>, future: F) -> F::Output
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    py.allow_threads(|| pyo3_asyncio::tokio::get_runtime().block_on(future))
+}
+
+fn query(
+    endpoint: Option<String>,
+    before: Option<String>,
+    after: Option<String>,
+    limit: Option<u32>,
+    as_of: Option<DateTime<Utc>>,
+) -> TransactionQuery {
+    let mut query = TransactionQuery::new();
+    if let Some(endpoint) = endpoint {
+        query = query.endpoint(endpoint);
+    }
+    if let Some(before) = before {
+        query = query.before(before);
+    }
+    if let Some(after) = after {
+        query = query.after(after);
+    }
+    if let Some(limit) = limit {
+        query = query.limit(limit);
+    }
+    if let Some(as_of) = as_of {
+        query = query.as_of(as_of);
+    }
+    query
+}
+
+/// `Client(base_url, timeout=None)`: one gateway. `timeout` is per
+/// request, in seconds.
+#[pyclass(name = "Client", module = "pytxrelayer")]
+pub struct PyClient {
+    client: Client,
+}
+
+#[pymethods]
+impl PyClient {
+    #[new]
+    #[pyo3(signature = (base_url, timeout=None))]
+    fn new(base_url: String, timeout: Option<f64>) -> PyResult<Self> {
+        let mut builder = Client::builder(base_url).user_agent(concat!("pytxrelayer/", env!("CARGO_PKG_VERSION")));
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(Duration::from_secs_f64(timeout));
+        }
+        Ok(PyClient { client: builder.build().map_err(to_py_err)? })
+    }
+
+    /// One transaction, or `None` if the gateway doesn't know it.
+    fn transaction(&self, py: Python<'_>, id: &str) -> PyResult<Option<PyObject>> {
+        let tx = block_on(py, self.client.transaction(id)).map_err(to_py_err)?;
+        tx.map(|tx| convert::transaction(py, &tx)).transpose()
+    }
+
+    /// One page, newest first: `{"items": [...], "next_cursor": ...}`.
+    /// Pass `next_cursor` as `before` for the next (older) page.
+    #[pyo3(signature = (endpoint=None, before=None, after=None, limit=None, as_of=None))]
+    fn transactions(
+        &self,
+        py: Python<'_>,
+        endpoint: Option<String>,
+        before: Option<String>,
+        after: Option<String>,
+        limit: Option<u32>,
+        as_of: Option<&str>,
+    ) -> PyResult<PyObject> {
+        let query = query(endpoint, before, after, limit, convert::as_of(as_of)?);
+        let page = block_on(py, self.client.transactions(&query)).map_err(to_py_err)?;
+        convert::page(py, &page)
+    }
+
+    /// Every page from the newest back, or the newest `max_pages`, as one
+    /// list: what `pandas.DataFrame` takes.
+    #[pyo3(signature = (endpoint=None, as_of=None, page_size=None, max_pages=None))]
+    fn all_transactions(
+        &self,
+        py: Python<'_>,
+        endpoint: Option<String>,
+        as_of: Option<&str>,
+        page_size: Option<u32>,
+        max_pages: Option<usize>,
+    ) -> PyResult<Vec<PyObject>> {
+        let as_of = convert::as_of(as_of)?;
+        let mut rows = Vec::new();
+        let mut before = None;
+        for _ in 0..max_pages.unwrap_or(usize::MAX) {
+            let query = query(endpoint.clone(), before.take(), None, page_size, as_of);
+            let page = block_on(py, self.client.transactions(&query)).map_err(to_py_err)?;
+            rows.extend(convert::transactions(py, &page.items)?);
+            // Long pulls can be interrupted with Ctrl-C
+            py.check_signals()?;
+            match page.next_cursor {
+                Some(cursor) => before = Some(cursor),
+                None => break,
+            }
+        }
+        Ok(rows)
+    }
+
+    /// Totals across all endpoints, with per-endpoint rows in `endpoints`.
+    fn stats(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let stats = block_on(py, self.client.stats()).map_err(to_py_err)?;
+        convert::stats(py, &stats)
+    }
+
+    #[pyo3(signature = (endpoint_id, as_of=None))]
+    fn endpoint_stats(&self, py: Python<'_>, endpoint_id: &str, as_of: Option<&str>) -> PyResult<PyObject> {
+        let as_of = convert::as_of(as_of)?;
+        let stats = block_on(py, self.client.endpoint_stats(endpoint_id, as_of)).map_err(to_py_err)?;
+        convert::endpoint_stats(py, &stats)
+    }
+
+    /// Now unless `as_of` is given.
+    #[pyo3(signature = (endpoint_id, as_of=None))]
+    fn balance(&self, py: Python<'_>, endpoint_id: &str, as_of: Option<&str>) -> PyResult<PyObject> {
+        let as_of = convert::as_of(as_of)?;
+        let balance = block_on(py, self.client.balance(endpoint_id, as_of)).map_err(to_py_err)?;
+        convert::balance(py, &balance)
+    }
+
+    /// The live stream as an async iterator of event dicts. Reconnects by
+    /// itself; persist each event's `id` and pass it as `resume` to pick up
+    /// where a previous run stopped.
+    #[pyo3(signature = (endpoint=None, resume=None, reconnect_delay=None))]
+    fn watch(&self, endpoint: Option<String>, resume: Option<String>, reconnect_delay: Option<f64>) -> Watch {
+        let mut options = WatchOptions::new();
+        if let Some(endpoint) = endpoint {
+            options = options.endpoint(endpoint);
+        }
+        if let Some(resume) = resume {
+            options = options.resume(resume);
+        }
+        if let Some(delay) = reconnect_delay {
+            options = options.reconnect_delay(Duration::from_secs_f64(delay));
+        }
+        // Nothing runs until the first `__anext__`, on the shared runtime
+        Watch::new(self.client.watch(options))
+    }
+}
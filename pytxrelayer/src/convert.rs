@@ -0,0 +1,102 @@
+//! SDK types as Python dicts, keyed as in the REST API's JSON so code can
+//! move between the two. Amounts are floats in major units, timestamps
+//! RFC 3339 strings.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use p2p_tx_relayer_client::{EndpointBalance, EndpointStats, LiveEvent, Page, Transaction, TransactionStats};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyList};
+
+fn rfc3339(at: &DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// An `as_of` argument; `ValueError` unless it is RFC 3339.
+pub fn as_of(as_of: Option<&str>) -> PyResult<Option<DateTime<Utc>>> {
+    as_of
+        .map(|as_of| {
+            DateTime::parse_from_rfc3339(as_of)
+                .map(|at| at.with_timezone(&Utc))
+                .map_err(|e| PyValueError::new_err(format!("as_of is not an RFC 3339 timestamp: {}", e)))
+        })
+        .transpose()
+}
+
+pub fn transaction(py: Python<'_>, tx: &Transaction) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("id", &tx.id)?;
+    dict.set_item("from_endpoint", &tx.from_endpoint)?;
+    dict.set_item("to_endpoint", &tx.to_endpoint)?;
+    dict.set_item("amount", tx.amount.to_major())?;
+    dict.set_item("timestamp", rfc3339(&tx.timestamp))?;
+    dict.set_item("signature", &tx.signature)?;
+    dict.set_item("status", tx.status.as_str())?;
+    dict.set_item("kind", tx.kind.as_str())?;
+    dict.set_item("risk_score", tx.risk_score)?;
+    dict.set_item("parent_tx_id", &tx.parent_tx_id)?;
+    dict.set_item("sequence", tx.sequence)?;
+    dict.set_item("public_key", &tx.public_key)?;
+    Ok(dict.into())
+}
+
+pub fn transactions(py: Python<'_>, transactions: &[Transaction]) -> PyResult<Vec<PyObject>> {
+    transactions.iter().map(|tx| transaction(py, tx)).collect()
+}
+
+pub fn page(py: Python<'_>, page: &Page<Transaction>) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("items", PyList::new(py, transactions(py, &page.items)?))?;
+    dict.set_item("next_cursor", &page.next_cursor)?;
+    Ok(dict.into())
+}
+
+pub fn endpoint_stats(py: Python<'_>, stats: &EndpointStats) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("endpoint_id", &stats.endpoint_id)?;
+    dict.set_item("transaction_count", stats.transaction_count)?;
+    dict.set_item("total_sent", stats.total_sent.to_major())?;
+    dict.set_item("total_received", stats.total_received.to_major())?;
+    dict.set_item("balance_change", stats.balance_change.to_major())?;
+    Ok(dict.into())
+}
+
+pub fn stats(py: Python<'_>, stats: &TransactionStats) -> PyResult<PyObject> {
+    let endpoints = stats
+        .endpoints
+        .iter()
+        .map(|endpoint| endpoint_stats(py, endpoint))
+        .collect::<PyResult<Vec<_>>>()?;
+    let dict = PyDict::new(py);
+    dict.set_item("total_transactions", stats.total_transactions)?;
+    dict.set_item("total_volume", stats.total_volume.to_major())?;
+    dict.set_item("average_transaction", stats.average_transaction.to_major())?;
+    dict.set_item("endpoints", PyList::new(py, endpoints))?;
+    Ok(dict.into())
+}
+
+pub fn balance(py: Python<'_>, balance: &EndpointBalance) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("endpoint_id", &balance.endpoint_id)?;
+    dict.set_item("balance", balance.balance.to_major())?;
+    dict.set_item("as_of", rfc3339(&balance.as_of))?;
+    Ok(dict.into())
+}
+
+/// `event` is the SSE event name, or `"lagged"` when events were dropped
+/// and what's shown should be refetched.
+pub fn live_event(py: Python<'_>, event: &LiveEvent) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    match event {
+        LiveEvent::Transaction { change, id, transaction: tx } => {
+            dict.set_item("event", change.as_str())?;
+            dict.set_item("id", id)?;
+            dict.set_item("transaction", transaction(py, tx)?)?;
+        }
+        LiveEvent::Lagged { missed } => {
+            dict.set_item("event", "lagged")?;
+            dict.set_item("missed", missed)?;
+        }
+    }
+    Ok(dict.into())
+}
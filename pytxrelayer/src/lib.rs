@@ -0,0 +1,64 @@
+//! `pytxrelayer`: the client SDK's read APIs for Python, so analytics can
+//! pull transactions and stats straight into pandas.
+//!
+//! ```python
+//! import pandas as pd
+//! import pytxrelayer
+//!
+//! client = pytxrelayer.Client("http://localhost:3000")
+//! frame = pd.DataFrame(client.all_transactions(endpoint="alice"))
+//! totals = pd.DataFrame(client.stats()["endpoints"])
+//!
+//! async def follow():
+//!     async for event in client.watch(endpoint="alice"):
+//!         print(event["event"], event["transaction"]["id"])
+//! ```
+//!
+//! Results are plain dicts and lists with the REST API's snake_case keys,
+//! amounts as major-unit floats and timestamps as RFC 3339 strings, as
+//! the REST API sends them (`pd.to_datetime` reads them). `as_of` takes an
+//! RFC 3339 string too, e.g. `datetime.isoformat()` of an aware
+//! `datetime`: pyo3 can't convert `datetime`s under the stable ABI the
+//! wheel is built for. Calls block, without holding the GIL; `watch` is an
+//! async iterator and runs on the extension's own tokio runtime.
+//!
+//! `pip install ./pytxrelayer` builds and installs it (maturin), as does
+//! `maturin develop --release` inside a virtualenv.
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+
+// pyo3 0.20's `#[pymethods]` expands `#[new]` into impls nested in
+// functions, which newer compilers warn about
+#[allow(non_local_definitions)]
+mod client;
+mod convert;
+mod watch;
+
+use p2p_tx_relayer_client::Error;
+
+create_exception!(pytxrelayer, RelayerError, PyException, "A request to the gateway failed.");
+create_exception!(
+    pytxrelayer,
+    ApiError,
+    RelayerError,
+    "The gateway answered with an error; `args` is `(status, message)`."
+);
+
+pub(crate) fn to_py_err(e: Error) -> PyErr {
+    match e {
+        Error::Api { status, message } => ApiError::new_err((status, message)),
+        e => RelayerError::new_err(e.to_string()),
+    }
+}
+
+#[pymodule]
+fn pytxrelayer(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<client::PyClient>()?;
+    m.add_class::<watch::Watch>()?;
+    m.add("RelayerError", py.get_type::<RelayerError>())?;
+    m.add("ApiError", py.get_type::<ApiError>())?;
+    m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    Ok(())
+}
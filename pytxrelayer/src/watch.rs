@@ -0,0 +1,44 @@
+use std::sync::Arc;
+
+use futures_util::stream::{BoxStream, StreamExt};
+use p2p_tx_relayer_client::{Error, LiveEvent};
+use pyo3::exceptions::PyStopAsyncIteration;
+use pyo3::prelude::*;
+use tokio::sync::Mutex;
+
+use crate::{convert, to_py_err};
+
+type Events = BoxStream<'static, Result<LiveEvent, Error>>;
+
+/// `async for event in client.watch(...)`. Errors the stream can't
+/// recover from, like a 4xx on reconnect, are raised and end it.
+#[pyclass(module = "pytxrelayer")]
+pub struct Watch {
+    // Shared with the pending `__anext__` future, which outlives the call
+    events: Arc<Mutex<Events>>,
+}
+
+impl Watch {
+    pub fn new(events: Events) -> Self {
+        Watch { events: Arc::new(Mutex::new(events)) }
+    }
+}
+
+#[pymethods]
+impl Watch {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Option<&'py PyAny>> {
+        let events = self.events.clone();
+        let next = pyo3_asyncio::tokio::future_into_py(py, async move {
+            match events.lock().await.next().await {
+                Some(Ok(event)) => Python::with_gil(|py| convert::live_event(py, &event)),
+                Some(Err(e)) => Err(to_py_err(e)),
+                None => Err(PyStopAsyncIteration::new_err(())),
+            }
+        })?;
+        Ok(Some(next))
+    }
+}
@@ -0,0 +1,197 @@
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+use crate::hub::ConnId;
+use crate::AppState;
+
+/// Point-to-point message types, relayed to `targetPeer` untouched.
+const RELAYED_TYPES: &[&str] = &[
+    "offer",
+    "answer",
+    "ice-candidate",
+    "transaction-rejected",
+    "transaction-ack",
+    "channel-open",
+    "channel-update",
+    "channel-close",
+    "swap-offer",
+    "swap-accept",
+    "swap-decline",
+    "quote",
+    "memo",
+    "key-exchange",
+    "sender-key",
+];
+
+/// Signaling protocol versions, as in tx-core's `protocol` module.
+const PROTOCOL_VERSION: u32 = 2;
+const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Capabilities this server honours itself; the rest are between peers.
+/// Batching isn't one: rooms here are never settled by the server, nor is
+/// `permessage-deflate`, as these sockets aren't compressed.
+const SERVER_FEATURES: &[&str] = &["acks"];
+
+pub async fn handle_socket(socket: WebSocket, state: AppState) {
+    let (mut sink, mut stream) = socket.split();
+    let (outbox, mut queued) = mpsc::channel::<String>(state.config.max_queued_messages);
+    let conn = state.hub.register(outbox);
+
+    tokio::spawn(async move {
+        while let Some(text) = queued.recv().await {
+            if sink.send(Message::Text(text)).await.is_err() {
+                return;
+            }
+        }
+        // The hub let go of this connection: it left or fell too far behind
+        let _ = sink
+            .send(Message::Close(Some(CloseFrame {
+                code: 1008,
+                reason: "Connection closed by server".into(),
+            })))
+            .await;
+    });
+
+    info!("New peer connected");
+    state.hub.reply(
+        conn,
+        json!({
+            "type": "welcome",
+            "message": "Connected to signaling server",
+            "protocolVersion": PROTOCOL_VERSION,
+        }),
+    );
+
+    while let Some(Ok(message)) = stream.next().await {
+        match message {
+            Message::Text(text) => handle_message(&state, conn, &text).await,
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    info!("Peer disconnected");
+    state.hub.disconnect(conn);
+}
+
+async fn handle_message(state: &AppState, conn: ConnId, text: &str) {
+    let Ok(message) = serde_json::from_str::<Value>(text) else {
+        error!("Invalid message format");
+        state.hub.error(conn, "Invalid message format");
+        return;
+    };
+    let message_type = message.get("type").and_then(Value::as_str).unwrap_or_default().to_string();
+    info!("Received message type: {}", message_type);
+
+    match message_type.as_str() {
+        "hello" => negotiate(state, conn, &message),
+        // Sessions can't be handed over between instances here; resuming
+        // falls back to a plain join, as it does on an expired token
+        "join" | "resume" => {
+            let encrypted = message.get("encrypted").and_then(Value::as_bool).unwrap_or(false);
+            join(state, conn, field(&message, "roomId"), field(&message, "peerId"), encrypted).await
+        }
+        "leave" => state.hub.leave(conn, field(&message, "roomId").as_deref()),
+        "list-rooms" => state.hub.reply(conn, json!({ "type": "rooms", "rooms": state.hub.rooms() })),
+        t if RELAYED_TYPES.contains(&t) => state.hub.relay(conn, message),
+        "transaction" => {
+            let transaction = message.get("transaction").cloned().unwrap_or(Value::Null);
+            let sealed = message.get("sealed").cloned();
+            let epoch = message.get("epoch").cloned();
+            state.hub.broadcast_transaction(conn, transaction, sealed, epoch);
+        }
+        "quote-request" | "peer-map" => state.hub.broadcast(conn, message),
+        "resync" => {
+            let after_seq = message.get("afterSeq").and_then(Value::as_u64).unwrap_or(0);
+            state.hub.resync(conn, after_seq);
+        }
+        "ping" => state.hub.reply(conn, json!({ "type": "pong" })),
+        other => info!("Unknown message type: {}", other),
+    }
+}
+
+/// Answers a `hello` with the lower of the two protocol versions and the
+/// offered capabilities this server honours. Clients that never say
+/// hello speak version 1 with none.
+fn negotiate(state: &AppState, conn: ConnId, message: &Value) {
+    let version = message.get("protocolVersion").and_then(Value::as_u64).unwrap_or(1) as u32;
+    if version < MIN_PROTOCOL_VERSION {
+        state.hub.error(
+            conn,
+            &format!(
+                "Protocol version {} is no longer supported, {} or later required",
+                version, MIN_PROTOCOL_VERSION
+            ),
+        );
+        state.hub.disconnect(conn);
+        return;
+    }
+    let version = version.min(PROTOCOL_VERSION);
+    let features: Vec<String> = message
+        .get("features")
+        .and_then(Value::as_array)
+        .map(|features| features.iter().filter_map(Value::as_str).map(str::to_string).collect())
+        .unwrap_or_default();
+    let honoured: Vec<&String> = features.iter().filter(|f| SERVER_FEATURES.contains(&f.as_str())).collect();
+    state
+        .hub
+        .reply(conn, json!({ "type": "negotiated", "protocolVersion": version, "features": honoured }));
+    state.hub.set_protocol(conn, version, features);
+}
+
+fn field(message: &Value, name: &str) -> Option<String> {
+    message.get(name).and_then(Value::as_str).map(str::to_string)
+}
+
+async fn join(state: &AppState, conn: ConnId, room_id: Option<String>, peer_id: Option<String>, encrypted: bool) {
+    let (Some(room_id), Some(peer_id)) = (room_id, peer_id) else {
+        state.hub.error(conn, "Room ID and Peer ID required");
+        return;
+    };
+    if !valid_room_id(&room_id) {
+        state.hub.error(conn, "Room ID must be 1-64 letters, digits, dashes or underscores");
+        return;
+    }
+
+    if let Some(status) = endpoint_status(state, &peer_id).await {
+        if status == "suspended" || status == "closed" {
+            info!("Refused join for {} endpoint {}", status, peer_id);
+            state.hub.error(conn, &format!("Endpoint {} is {}", peer_id, status));
+            return;
+        }
+    }
+    state.hub.join(conn, &room_id, &peer_id, encrypted);
+}
+
+/// Rooms are created by whoever joins first, so their ids are kept to
+/// something safe to log and to put in gateway URLs.
+fn valid_room_id(room_id: &str) -> bool {
+    (1..=64).contains(&room_id.len())
+        && room_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Suspended or closed endpoints are refused at join time. Endpoints the
+/// gateway doesn't know about, or an unreachable gateway, fail open so the
+/// relay keeps working without a provisioning step.
+async fn endpoint_status(state: &AppState, peer_id: &str) -> Option<String> {
+    let mut url = reqwest::Url::parse(&state.config.api_gateway).ok()?;
+    url.path_segments_mut().ok()?.pop_if_empty().extend(["api", "endpoints", peer_id]);
+
+    let mut request = state.http.current().get(url);
+    if let Some(token) = &state.config.gateway_token {
+        request = request.bearer_auth(token);
+    }
+    let response = match request.send().await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(_) => return None,
+        Err(e) => {
+            error!("Endpoint status lookup failed for {}: {}", peer_id, e);
+            return None;
+        }
+    };
+    let endpoint: Value = response.json().await.ok()?;
+    endpoint.get("status").and_then(Value::as_str).map(str::to_string)
+}
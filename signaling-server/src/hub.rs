@@ -0,0 +1,486 @@
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{info, warn};
+
+pub type ConnId = u64;
+
+/// Capability a peer has to offer to join an encrypted room, as in
+/// tx-core's `protocol` module.
+const GROUP_ENCRYPTION: &str = "group-encryption";
+
+/// Outbound queue of one connection, drained by its writer task. Dropping
+/// the sender closes the socket.
+pub type Outbox = mpsc::Sender<String>;
+
+struct Connection {
+    outbox: Outbox,
+    peer_id: Option<String>,
+    room_id: Option<String>,
+    /// From the connection's `hello`; 1 and none until then.
+    protocol_version: u32,
+    features: Vec<String>,
+}
+
+#[derive(Default)]
+struct Room {
+    members: HashSet<ConnId>,
+    /// Last transaction broadcast sequence number.
+    seq: u64,
+    recent: VecDeque<Value>,
+    /// Opened with `encrypted`: only sealed broadcasts are relayed, and
+    /// only peers offering group encryption may join.
+    encrypted: bool,
+}
+
+#[derive(Default)]
+struct HubState {
+    next_id: ConnId,
+    connections: HashMap<ConnId, Connection>,
+    /// Peer id -> the connection it joined with.
+    peers: HashMap<String, ConnId>,
+    rooms: HashMap<String, Room>,
+    /// Connections whose outbox filled up, dropped once the current
+    /// operation is done.
+    overflowed: Vec<ConnId>,
+}
+
+/// Rooms and connected peers. A connection is in at most one room: joining
+/// another leaves the first.
+#[derive(Clone)]
+pub struct Hub {
+    state: Arc<Mutex<HubState>>,
+    history_size: usize,
+}
+
+impl HubState {
+    fn send(&mut self, conn: ConnId, message: &Value) -> bool {
+        let Some(connection) = self.connections.get(&conn) else {
+            return false;
+        };
+        match connection.outbox.try_send(message.to_string()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                warn!("Closing {}: outbound queue full", connection.peer_id.as_deref().unwrap_or("peer"));
+                self.overflowed.push(conn);
+                false
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    fn error(&mut self, conn: ConnId, message: &str) {
+        self.send(conn, &json!({ "type": "error", "message": message }));
+    }
+
+    fn leave(&mut self, conn: ConnId) {
+        let Some(connection) = self.connections.get_mut(&conn) else {
+            return;
+        };
+        let (room_id, peer_id) = (connection.room_id.take(), connection.peer_id.take());
+        // The peer id goes with the connection, room or not
+        if let Some(peer_id) = &peer_id {
+            if self.peers.get(peer_id) == Some(&conn) {
+                self.peers.remove(peer_id);
+            }
+        }
+        let Some(room_id) = room_id else {
+            return;
+        };
+
+        let Some(room) = self.rooms.get_mut(&room_id) else {
+            return;
+        };
+        room.members.remove(&conn);
+        let remaining: Vec<ConnId> = room.members.iter().copied().collect();
+        if remaining.is_empty() {
+            self.rooms.remove(&room_id);
+            info!("Room {} deleted (empty)", room_id);
+        }
+
+        if let Some(peer_id) = peer_id {
+            let notice = json!({ "type": "peer-left", "peerId": peer_id, "roomId": room_id });
+            for member in remaining {
+                self.send(member, &notice);
+            }
+        }
+    }
+
+    fn disconnect(&mut self, conn: ConnId) {
+        self.leave(conn);
+        self.connections.remove(&conn);
+    }
+
+    /// Drops connections that couldn't keep up. Telling their rooms may
+    /// overflow others in turn.
+    fn drop_overflowed(&mut self) {
+        while let Some(conn) = self.overflowed.pop() {
+            self.disconnect(conn);
+        }
+    }
+}
+
+impl Hub {
+    pub fn new(history_size: usize) -> Self {
+        Hub {
+            state: Arc::new(Mutex::new(HubState::default())),
+            history_size,
+        }
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut HubState) -> T) -> T {
+        let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let result = f(&mut state);
+        state.drop_overflowed();
+        result
+    }
+
+    pub fn register(&self, outbox: Outbox) -> ConnId {
+        self.with_state(|state| {
+            state.next_id += 1;
+            let conn = state.next_id;
+            state.connections.insert(
+                conn,
+                Connection { outbox, peer_id: None, room_id: None, protocol_version: 1, features: Vec::new() },
+            );
+            conn
+        })
+    }
+
+    pub fn disconnect(&self, conn: ConnId) {
+        self.with_state(|state| state.disconnect(conn));
+    }
+
+    pub fn reply(&self, conn: ConnId, message: Value) {
+        self.with_state(|state| {
+            state.send(conn, &message);
+        });
+    }
+
+    pub fn error(&self, conn: ConnId, message: &str) {
+        self.with_state(|state| state.error(conn, message));
+    }
+
+    /// Records what the connection's `hello` negotiated. `features` are
+    /// all it offered, announced to its room as they are.
+    pub fn set_protocol(&self, conn: ConnId, protocol_version: u32, features: Vec<String>) {
+        self.with_state(|state| {
+            if let Some(connection) = state.connections.get_mut(&conn) {
+                connection.protocol_version = protocol_version;
+                connection.features = features;
+            }
+        });
+    }
+
+    /// Puts the connection in `room_id`. `encrypted` opens a new room
+    /// end-to-end encrypted; an existing room keeps what it was opened as.
+    pub fn join(&self, conn: ConnId, room_id: &str, peer_id: &str, encrypted: bool) {
+        self.with_state(|state| {
+            let offers_encryption = state
+                .connections
+                .get(&conn)
+                .is_some_and(|c| c.features.iter().any(|f| f == GROUP_ENCRYPTION));
+            match state.rooms.get(room_id).map(|room| room.encrypted) {
+                Some(true) if !offers_encryption => {
+                    state.error(conn, &format!("Room {} is end-to-end encrypted: group encryption required", room_id));
+                    return;
+                }
+                Some(false) if encrypted => {
+                    state.error(conn, &format!("Room {} is already open without encryption", room_id));
+                    return;
+                }
+                None if encrypted && !offers_encryption => {
+                    state.error(conn, "Encrypted rooms need group encryption, offered in hello");
+                    return;
+                }
+                _ => {}
+            }
+            state.leave(conn);
+
+            let room = state
+                .rooms
+                .entry(room_id.to_string())
+                .or_insert_with(|| Room { encrypted, ..Room::default() });
+            let room_encrypted = room.encrypted;
+            let existing: Vec<ConnId> = room.members.iter().copied().collect();
+            room.members.insert(conn);
+            let size = room.members.len();
+
+            let (protocol_version, features) = state
+                .connections
+                .get(&conn)
+                .map(|c| (c.protocol_version, c.features.clone()))
+                .unwrap_or((1, Vec::new()));
+            let notice = json!({
+                "type": "peer-joined",
+                "peerId": peer_id,
+                "roomId": room_id,
+                "protocolVersion": protocol_version,
+                "features": features,
+            });
+            let mut existing_peers = Vec::new();
+            let mut peer_features = serde_json::Map::new();
+            for member in existing {
+                state.send(member, &notice);
+                if let Some(connection) = state.connections.get(&member) {
+                    if let Some(peer) = connection.peer_id.clone() {
+                        peer_features.insert(peer.clone(), json!(connection.features));
+                        existing_peers.push(peer);
+                    }
+                }
+            }
+
+            if let Some(connection) = state.connections.get_mut(&conn) {
+                connection.peer_id = Some(peer_id.to_string());
+                connection.room_id = Some(room_id.to_string());
+            }
+            state.peers.insert(peer_id.to_string(), conn);
+
+            state.send(
+                conn,
+                &json!({
+                    "type": "room-joined",
+                    "roomId": room_id,
+                    "peerId": peer_id,
+                    "peers": existing_peers,
+                    "peerFeatures": peer_features,
+                    "encrypted": room_encrypted,
+                }),
+            );
+            info!("Peer {} joined room {}. Room size: {}", peer_id, room_id, size);
+        });
+    }
+
+    /// Leaves the connection's room; `room_id`, when given, has to match it.
+    pub fn leave(&self, conn: ConnId, room_id: Option<&str>) {
+        self.with_state(|state| {
+            let current = state.connections.get(&conn).and_then(|c| c.room_id.as_deref());
+            if room_id.is_none() || room_id == current {
+                state.leave(conn);
+            }
+        });
+    }
+
+    /// Forwards a point-to-point message to `targetPeer` as-is, stamped with
+    /// the sender's peer id. Both have to be in the named room.
+    pub fn relay(&self, conn: ConnId, mut message: Value) {
+        self.with_state(|state| {
+            let target = message.get("targetPeer").and_then(Value::as_str).map(str::to_string);
+            let room_id = message.get("roomId").and_then(Value::as_str).map(str::to_string);
+            let (Some(target), Some(room_id)) = (target, room_id) else {
+                state.error(conn, "Target peer and room ID required for signaling");
+                return;
+            };
+
+            let from_peer = state.connections.get(&conn).and_then(|c| c.peer_id.clone());
+            let target_conn = state
+                .peers
+                .get(&target)
+                .copied()
+                .filter(|t| state.connections.get(t).and_then(|c| c.room_id.as_deref()) == Some(room_id.as_str()));
+            let Some(target_conn) = target_conn else {
+                state.error(conn, &format!("Peer {} not found or not in same room", target));
+                return;
+            };
+
+            message["fromPeer"] = json!(from_peer);
+            if state.send(target_conn, &message) {
+                let message_type = message.get("type").and_then(Value::as_str).unwrap_or_default();
+                info!("Relayed {} from {} to {}", message_type, from_peer.unwrap_or_default(), target);
+            }
+        });
+    }
+
+    /// Sends a transaction to everyone in the sender's room, the sender
+    /// included, under the room's next sequence number. `sealed` is an
+    /// end-to-end encrypted transaction, relayed unread, and `epoch` the
+    /// sender key it is under in an encrypted room.
+    pub fn broadcast_transaction(
+        &self,
+        conn: ConnId,
+        transaction: Value,
+        sealed: Option<Value>,
+        epoch: Option<Value>,
+    ) {
+        let history_size = self.history_size;
+        self.with_state(|state| {
+            let Some(connection) = state.connections.get(&conn) else {
+                return;
+            };
+            let (peer_id, room_id) = (connection.peer_id.clone(), connection.room_id.clone());
+            let Some(room) = room_id.as_ref().and_then(|id| state.rooms.get_mut(id)) else {
+                state.error(conn, "Not in a room");
+                return;
+            };
+            if room.encrypted && (!transaction.is_null() || sealed.is_none() || epoch.is_none()) {
+                state.error(conn, "Room is end-to-end encrypted: send sealed transactions only");
+                return;
+            }
+
+            room.seq += 1;
+            let mut broadcast = json!({
+                "type": "transaction-broadcast",
+                "transaction": transaction,
+                "fromPeer": peer_id,
+                "roomId": room_id,
+                "seq": room.seq,
+                "timestamp": chrono::Utc::now().timestamp_millis(),
+            });
+            if let Some(sealed) = sealed {
+                broadcast["sealed"] = sealed;
+            }
+            if let Some(epoch) = epoch {
+                broadcast["epoch"] = epoch;
+            }
+            room.recent.push_back(broadcast.clone());
+            if room.recent.len() > history_size {
+                room.recent.pop_front();
+            }
+
+            let members: Vec<ConnId> = room.members.iter().copied().collect();
+            for member in &members {
+                state.send(*member, &broadcast);
+            }
+            info!("Broadcasted transaction from {} to {} peers", peer_id.unwrap_or_default(), members.len());
+        });
+    }
+
+    /// Fans a message out to the rest of the sender's room as-is, without
+    /// a sequence number or history.
+    pub fn broadcast(&self, conn: ConnId, mut message: Value) {
+        self.with_state(|state| {
+            let Some(connection) = state.connections.get(&conn) else {
+                return;
+            };
+            let (peer_id, room_id) = (connection.peer_id.clone(), connection.room_id.clone());
+            let Some(room) = room_id.as_ref().and_then(|id| state.rooms.get(id)) else {
+                state.error(conn, "Not in a room");
+                return;
+            };
+
+            let members: Vec<ConnId> = room.members.iter().copied().filter(|m| *m != conn).collect();
+            message["fromPeer"] = json!(peer_id);
+            message["roomId"] = json!(room_id);
+            for member in members {
+                state.send(member, &message);
+            }
+        });
+    }
+
+    /// Replays the room's broadcasts after `after_seq`. `complete` is false
+    /// when the history no longer reaches back that far.
+    pub fn resync(&self, conn: ConnId, after_seq: u64) {
+        self.with_state(|state| {
+            let room_id = state.connections.get(&conn).and_then(|c| c.room_id.clone());
+            let Some(room) = room_id.as_ref().and_then(|id| state.rooms.get(id)) else {
+                state.send(conn, &json!({ "type": "resync-complete", "roomId": room_id, "seq": 0, "complete": true }));
+                return;
+            };
+
+            let oldest = room
+                .recent
+                .front()
+                .and_then(|b| b.get("seq").and_then(Value::as_u64))
+                .unwrap_or(room.seq + 1);
+            let seq = room.seq;
+            let pending: Vec<Value> = room
+                .recent
+                .iter()
+                .filter(|b| b.get("seq").and_then(Value::as_u64).unwrap_or(0) > after_seq)
+                .cloned()
+                .collect();
+
+            for broadcast in &pending {
+                if !state.send(conn, broadcast) {
+                    return;
+                }
+            }
+            state.send(
+                conn,
+                &json!({ "type": "resync-complete", "roomId": room_id, "seq": seq, "complete": oldest <= after_seq + 1 }),
+            );
+        });
+    }
+
+    /// Open rooms and how many are in each, for `list-rooms`.
+    pub fn rooms(&self) -> Vec<Value> {
+        self.with_state(|state| {
+            state
+                .rooms
+                .iter()
+                .map(|(room_id, room)| {
+                    json!({ "roomId": room_id, "peerCount": room.members.len(), "encrypted": room.encrypted })
+                })
+                .collect()
+        })
+    }
+
+    pub fn stats(&self) -> Value {
+        self.with_state(|state| {
+            let rooms: Vec<Value> = state
+                .rooms
+                .iter()
+                .map(|(room_id, room)| {
+                    let peers: Vec<&str> = room
+                        .members
+                        .iter()
+                        .filter_map(|m| state.connections.get(m).and_then(|c| c.peer_id.as_deref()))
+                        .collect();
+                    json!({ "roomId": room_id, "peerCount": room.members.len(), "peers": peers })
+                })
+                .collect();
+
+            json!({
+                "totalConnections": state.connections.len(),
+                "totalRooms": state.rooms.len(),
+                "rooms": rooms,
+            })
+        })
+    }
+
+    pub fn counts(&self) -> (usize, usize) {
+        self.with_state(|state| (state.connections.len(), state.rooms.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connect(hub: &Hub) -> (ConnId, mpsc::Receiver<String>) {
+        let (outbox, inbox) = mpsc::channel(16);
+        (hub.register(outbox), inbox)
+    }
+
+    fn peer_count(hub: &Hub) -> usize {
+        hub.with_state(|state| state.peers.len())
+    }
+
+    #[test]
+    fn disconnect_forgets_the_peer() {
+        let hub = Hub::new(10);
+        let (alice, _alice_inbox) = connect(&hub);
+        let (bob, _bob_inbox) = connect(&hub);
+        hub.join(alice, "room", "alice", false);
+        hub.join(bob, "room", "bob", false);
+        assert_eq!(peer_count(&hub), 2);
+
+        hub.disconnect(alice);
+        assert_eq!(peer_count(&hub), 1);
+        hub.disconnect(bob);
+        assert_eq!(peer_count(&hub), 0);
+        assert!(hub.rooms().is_empty());
+    }
+
+    #[test]
+    fn disconnect_forgets_a_peer_whose_room_is_gone() {
+        let hub = Hub::new(10);
+        let (alice, _inbox) = connect(&hub);
+        hub.join(alice, "room", "alice", false);
+        hub.with_state(|state| state.rooms.clear());
+
+        hub.disconnect(alice);
+        assert_eq!(peer_count(&hub), 0);
+    }
+}
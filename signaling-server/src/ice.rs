@@ -0,0 +1,64 @@
+//! ICE servers for WebRTC endpoints, served at `GET /ice-servers` so peers
+//! behind symmetric NATs can fall back to relaying through TURN. Same
+//! variables as the Node server: `STUN_URLS`, `TURN_URLS`, and either
+//! `TURN_SECRET` (TURN REST API credentials, valid for
+//! `TURN_CREDENTIAL_TTL_SECS`) or fixed `TURN_USERNAME`/`TURN_CREDENTIAL`.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha1::Sha1;
+
+const DEFAULT_STUN_URL: &str = "stun:stun.l.google.com:19302";
+
+pub struct IceConfig {
+    stun_urls: Vec<String>,
+    turn_urls: Vec<String>,
+    turn_secret: Option<String>,
+    turn_username: String,
+    turn_credential: String,
+    turn_credential_ttl_secs: i64,
+}
+
+fn csv(value: &str) -> Vec<String> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect()
+}
+
+impl IceConfig {
+    pub fn from_env() -> Self {
+        let var = |name: &str| std::env::var(name).ok();
+        IceConfig {
+            stun_urls: var("STUN_URLS").map_or_else(|| vec![DEFAULT_STUN_URL.to_string()], |urls| csv(&urls)),
+            turn_urls: var("TURN_URLS").map(|urls| csv(&urls)).unwrap_or_default(),
+            turn_secret: var("TURN_SECRET").filter(|secret| !secret.is_empty()),
+            turn_username: var("TURN_USERNAME").unwrap_or_default(),
+            turn_credential: var("TURN_CREDENTIAL").unwrap_or_default(),
+            turn_credential_ttl_secs: var("TURN_CREDENTIAL_TTL_SECS").and_then(|ttl| ttl.parse().ok()).unwrap_or(86400),
+        }
+    }
+
+    /// `RTCPeerConnection` `iceServers` for `peer_id` (may be empty).
+    pub fn servers(&self, peer_id: &str) -> Vec<Value> {
+        let mut servers = Vec::new();
+        if !self.stun_urls.is_empty() {
+            servers.push(json!({ "urls": self.stun_urls }));
+        }
+        if self.turn_urls.is_empty() {
+            return servers;
+        }
+        let (username, credential) = match &self.turn_secret {
+            // `<expiry>:<peer>` signed with the shared secret
+            Some(secret) => {
+                let expiry = chrono::Utc::now().timestamp() + self.turn_credential_ttl_secs;
+                let username = if peer_id.is_empty() { expiry.to_string() } else { format!("{}:{}", expiry, peer_id) };
+                let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes()).expect("HMAC takes any key length");
+                mac.update(username.as_bytes());
+                (username, STANDARD.encode(mac.finalize().into_bytes()))
+            }
+            None => (self.turn_username.clone(), self.turn_credential.clone()),
+        };
+        servers.push(json!({ "urls": self.turn_urls, "username": username, "credential": credential }));
+        servers
+    }
+}
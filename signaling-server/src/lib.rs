@@ -0,0 +1,129 @@
+//! Rust port of the WebSocket signaling server (see ws-signaling-server):
+//! rooms, join/leave notifications, point-to-point relaying of offers,
+//! answers, ICE candidates and the payment protocol messages, and room
+//! transaction broadcasts. Messages are handled as raw JSON so relayed
+//! payloads reach the other peer exactly as sent.
+//!
+//! The `signaling-server` binary serves `router` on its own port; the
+//! gateway's all-in-one dev mode mounts it next to the API.
+
+use axum::{
+    extract::{Query, State, WebSocketUpgrade},
+    http::Method,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tower_http::cors::{Any, CorsLayer};
+
+mod connection;
+mod hub;
+mod ice;
+pub mod tls;
+
+use hub::Hub;
+
+pub struct Config {
+    pub port: u16,
+    pub api_gateway: String,
+    /// Bearer token for gateways that require a JWT (`GATEWAY_TOKEN`).
+    pub gateway_token: Option<String>,
+    /// Largest inbound message accepted.
+    pub max_message_bytes: usize,
+    /// Messages buffered for a client before it is disconnected.
+    pub max_queued_messages: usize,
+    /// Transaction broadcasts kept per room for `resync`.
+    pub room_history_size: usize,
+    pub ice: ice::IceConfig,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        Config {
+            port: var("PORT", 8080),
+            api_gateway: std::env::var("API_GATEWAY").unwrap_or_else(|_| "http://localhost:3001".to_string()),
+            gateway_token: std::env::var("GATEWAY_TOKEN").ok().filter(|token| !token.is_empty()),
+            max_message_bytes: var("MAX_MESSAGE_BYTES", 65536),
+            max_queued_messages: var("MAX_QUEUED_MESSAGES", 1024),
+            room_history_size: var("ROOM_HISTORY_SIZE", 500),
+            ice: ice::IceConfig::from_env(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    hub: Hub,
+    http: tls::Reloading<reqwest::Client>,
+    config: Arc<Config>,
+}
+
+impl AppState {
+    pub fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(AppState {
+            hub: Hub::new(config.room_history_size),
+            http: tls::gateway_client()?,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Keeps the gateway client's certificates current.
+    pub async fn run_reload(self) {
+        self.http.run_reload().await
+    }
+}
+
+/// The WebSocket at `/` and the HTTP routes next to it.
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/", get(upgrade))
+        .route("/health", get(health_check))
+        .route("/rooms", get(get_rooms))
+        .route("/ice-servers", get(get_ice_servers))
+        .route("/stats", get(get_stats))
+        .layer(
+            CorsLayer::new()
+                .allow_origin(Any)
+                .allow_methods([Method::GET])
+                .allow_headers(Any),
+        )
+        .with_state(state)
+}
+
+
+async fn upgrade(State(state): State<AppState>, ws: WebSocketUpgrade) -> Response {
+    ws.max_message_size(state.config.max_message_bytes)
+        .on_upgrade(move |socket| connection::handle_socket(socket, state))
+        .into_response()
+}
+
+async fn health_check(State(state): State<AppState>) -> Json<Value> {
+    let (connections, rooms) = state.hub.counts();
+    Json(json!({
+        "status": "healthy",
+        "connections": connections,
+        "rooms": rooms,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    }))
+}
+
+async fn get_rooms(State(state): State<AppState>) -> Json<Value> {
+    Json(json!({ "rooms": state.hub.rooms() }))
+}
+
+/// `GET /ice-servers[?peerId=]`
+async fn get_ice_servers(State(state): State<AppState>, Query(params): Query<HashMap<String, String>>) -> Json<Value> {
+    let peer_id = params.get("peerId").map(String::as_str).unwrap_or_default();
+    Json(json!({ "iceServers": state.config.ice.servers(peer_id) }))
+}
+
+async fn get_stats(State(state): State<AppState>) -> Json<Value> {
+    Json(state.hub.stats())
+}
@@ -0,0 +1,45 @@
+//! The signaling server on `PORT`, with its TLS and mTLS listeners.
+
+use tracing::info;
+
+use signaling_server::{router, tls, AppState, Config};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter("signaling_server=debug,info")
+        .init();
+
+    info!("Starting P2P Signaling Server...");
+
+    let config = Config::from_env();
+    let port = config.port;
+    let public_tls = tls::public_from_env()?;
+    let internal_tls = tls::internal_from_env()?;
+    let state = AppState::new(config)?;
+    tokio::spawn(state.clone().run_reload());
+
+    let app = router(state);
+
+    if let Some(internal) = internal_tls {
+        let listener = tokio::net::TcpListener::bind(("0.0.0.0", internal.port)).await?;
+        info!("🔒 Internal mTLS listener on port {}", internal.port);
+        tokio::spawn(internal.config.clone().run_reload());
+        tokio::spawn(tls::serve(listener, app.clone(), internal.config));
+    }
+
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port)).await?;
+    let scheme = if public_tls.is_some() { "https" } else { "http" };
+    info!("🚀 Signaling server running on port {}", port);
+    info!("📊 Health check: {}://localhost:{}/health", scheme, port);
+    info!("📈 Stats: {}://localhost:{}/stats", scheme, port);
+
+    match public_tls {
+        Some(config) => {
+            tokio::spawn(config.clone().run_reload());
+            tls::serve(listener, app, config).await;
+        }
+        None => axum::serve(listener, app).await?,
+    }
+    Ok(())
+}
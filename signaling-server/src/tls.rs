@@ -0,0 +1,345 @@
+//! TLS for the server's listeners and its gateway client.
+//!
+//! - TLS_CERT, TLS_KEY: certificate chain and PKCS#8 key (PEM); the public
+//!   port then serves `wss://` instead of `ws://`
+//! - MTLS_CERT, MTLS_KEY, MTLS_CA: this server's SPIFFE certificate, its
+//!   key and the internal CA. Gateway calls present the certificate (see
+//!   `gateway_client`); with MTLS_ALLOWED_IDS (comma-separated SPIFFE IDs)
+//!   also set, MTLS_PORT (8443) serves the same routes to those services
+//!   only, checking their client certificates against the CA and the list.
+//!
+//! Every `TLS_RELOAD_SECS` (30) the files are checked, and when one has
+//! changed on disk the listeners and client are rebuilt for new
+//! connections. A set that doesn't load, e.g. a key written after its
+//! certificate, is logged and the current one stays in use until the files
+//! change again.
+
+use axum::Router;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use rustls::{
+    client::danger::HandshakeSignatureValid,
+    crypto::CryptoProvider,
+    pki_types::{CertificateDer, PrivateKeyDer, UnixTime},
+    server::{
+        danger::{ClientCertVerified, ClientCertVerifier},
+        WebPkiClientVerifier,
+    },
+    DigitallySignedStruct, DistinguishedName, RootCertStore, ServerConfig, SignatureScheme,
+};
+use std::collections::HashSet;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tracing::{debug, info, warn};
+use x509_parser::extensions::GeneralName;
+
+type Build<T> = dyn Fn() -> Result<T, String> + Send + Sync;
+
+/// A value built from files on disk and rebuilt when any of them changes.
+pub struct Reloading<T> {
+    what: &'static str,
+    files: Arc<[PathBuf]>,
+    build: Arc<Build<T>>,
+    current: Arc<RwLock<Arc<T>>>,
+    modified: Arc<Mutex<Vec<Option<SystemTime>>>>,
+}
+
+impl<T> Clone for Reloading<T> {
+    fn clone(&self) -> Self {
+        Reloading {
+            what: self.what,
+            files: self.files.clone(),
+            build: self.build.clone(),
+            current: self.current.clone(),
+            modified: self.modified.clone(),
+        }
+    }
+}
+
+fn modified(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files.iter().map(|file| std::fs::metadata(file).and_then(|m| m.modified()).ok()).collect()
+}
+
+impl<T: Send + Sync + 'static> Reloading<T> {
+    pub fn new(
+        what: &'static str,
+        files: Vec<PathBuf>,
+        build: impl Fn() -> Result<T, String> + Send + Sync + 'static,
+    ) -> Result<Self, String> {
+        let stamps = modified(&files);
+        let value = build()?;
+        Ok(Reloading {
+            what,
+            files: files.into(),
+            build: Arc::new(build),
+            current: Arc::new(RwLock::new(Arc::new(value))),
+            modified: Arc::new(Mutex::new(stamps)),
+        })
+    }
+
+    pub fn current(&self) -> Arc<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Rebuilds the value whenever its files change; returns at once if
+    /// it wasn't built from any.
+    pub async fn run_reload(self) {
+        if self.files.is_empty() {
+            return;
+        }
+        let interval = Duration::from_secs(std::env::var("TLS_RELOAD_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(30));
+        loop {
+            tokio::time::sleep(interval).await;
+            let stamps = modified(&self.files);
+            if *self.modified.lock().unwrap() == stamps {
+                continue;
+            }
+            match (self.build)() {
+                Ok(value) => {
+                    info!("🔄 Reloaded the {}", self.what);
+                    *self.current.write().unwrap() = Arc::new(value);
+                }
+                Err(e) => warn!("Keeping the current {}: {}", self.what, e),
+            }
+            // Not retried until the files change again
+            *self.modified.lock().unwrap() = stamps;
+        }
+    }
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+fn certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid certificate in {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificate in {}", path.display()));
+    }
+    Ok(certs)
+}
+
+fn private_key(path: &Path) -> Result<PrivateKeyDer<'static>, String> {
+    let file = std::fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| format!("Invalid key in {}: {}", path.display(), e))?
+        .ok_or_else(|| format!("No private key in {}", path.display()))
+}
+
+/// MTLS_CERT, MTLS_KEY and MTLS_CA, `None` unless MTLS_CERT is set.
+fn mtls_files() -> Result<Option<[PathBuf; 3]>, Box<dyn std::error::Error>> {
+    let Ok(cert) = std::env::var("MTLS_CERT") else {
+        return Ok(None);
+    };
+    let key = std::env::var("MTLS_KEY").map_err(|_| "MTLS_CERT is set but MTLS_KEY isn't")?;
+    let ca = std::env::var("MTLS_CA").map_err(|_| "MTLS_CERT is set but MTLS_CA isn't")?;
+    Ok(Some([cert.into(), key.into(), ca.into()]))
+}
+
+/// Client for gateway calls. With MTLS_CERT set it presents this server's
+/// SPIFFE certificate and trusts only the internal CA; `API_GATEWAY` then
+/// points at the gateway's mTLS port.
+pub fn gateway_client() -> Result<Reloading<reqwest::Client>, Box<dyn std::error::Error>> {
+    let builder = || reqwest::Client::builder().timeout(Duration::from_secs(2));
+    let Some([cert, key, ca]) = mtls_files()? else {
+        return Ok(Reloading::new("gateway client", Vec::new(), move || builder().build().map_err(|e| e.to_string()))?);
+    };
+
+    info!("Calling the gateway over mTLS");
+    let files = vec![cert.clone(), key.clone(), ca.clone()];
+    let client = Reloading::new("gateway client certificate", files, move || {
+        let read = |path: &Path| std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e));
+        let mut identity = read(&cert)?;
+        identity.extend(read(&key)?);
+        let identity = reqwest::Identity::from_pem(&identity).map_err(|e| e.to_string())?;
+        let ca_cert = reqwest::Certificate::from_pem(&read(&ca)?).map_err(|e| e.to_string())?;
+        builder()
+            .identity(identity)
+            .add_root_certificate(ca_cert)
+            .tls_built_in_root_certs(false)
+            .https_only(true)
+            .build()
+            .map_err(|e| e.to_string())
+    })?;
+    Ok(client)
+}
+
+/// The public listener's config, `None` unless TLS_CERT is set.
+pub fn public_from_env() -> Result<Option<Reloading<ServerConfig>>, Box<dyn std::error::Error>> {
+    let Ok(cert_path) = std::env::var("TLS_CERT") else {
+        return Ok(None);
+    };
+    let key_path = std::env::var("TLS_KEY").map_err(|_| "TLS_CERT is set but TLS_KEY isn't")?;
+    let (cert_path, key_path) = (PathBuf::from(cert_path), PathBuf::from(key_path));
+
+    let files = vec![cert_path.clone(), key_path.clone()];
+    let config = Reloading::new("TLS certificate", files, move || {
+        let mut config = ServerConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| e.to_string())?
+            .with_no_client_auth()
+            .with_single_cert(certs(&cert_path)?, private_key(&key_path)?)
+            .map_err(|e| e.to_string())?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    })?;
+    Ok(Some(config))
+}
+
+pub struct InternalTls {
+    pub port: u16,
+    pub config: Reloading<ServerConfig>,
+}
+
+/// The internal listener, `None` unless both MTLS_CERT and
+/// MTLS_ALLOWED_IDS are set.
+pub fn internal_from_env() -> Result<Option<InternalTls>, Box<dyn std::error::Error>> {
+    let allowed: HashSet<String> = std::env::var("MTLS_ALLOWED_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+    if allowed.is_empty() {
+        return Ok(None);
+    }
+    if let Some(id) = allowed.iter().find(|id| !id.starts_with("spiffe://")) {
+        return Err(format!("Not a SPIFFE ID: {}", id).into());
+    }
+    let [cert_path, key_path, ca_path] = mtls_files()?.ok_or("MTLS_ALLOWED_IDS is set but MTLS_CERT isn't")?;
+
+    let files = vec![cert_path.clone(), key_path.clone(), ca_path.clone()];
+    let config = Reloading::new("mTLS certificate", files, move || {
+        internal_config(&cert_path, &key_path, &ca_path, &allowed).map_err(|e| e.to_string())
+    })?;
+    let port = std::env::var("MTLS_PORT").ok().and_then(|v| v.parse().ok()).unwrap_or(8443);
+    Ok(Some(InternalTls { port, config }))
+}
+
+fn internal_config(
+    cert_path: &Path,
+    key_path: &Path,
+    ca_path: &Path,
+    allowed: &HashSet<String>,
+) -> Result<ServerConfig, Box<dyn std::error::Error>> {
+    let mut roots = RootCertStore::empty();
+    for ca in certs(ca_path)? {
+        roots.add(ca)?;
+    }
+
+    let provider = provider();
+    let verifier = Arc::new(SpiffeClientVerifier {
+        inner: WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider.clone()).build()?,
+        allowed: allowed.clone(),
+    });
+    Ok(ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(certs(cert_path)?, private_key(key_path)?)?)
+}
+
+/// The URI SAN that names the certificate's holder, if it has one.
+fn spiffe_id(cert: &CertificateDer<'_>) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let san = cert.subject_alternative_name().ok()??;
+    san.value.general_names.iter().find_map(|name| match name {
+        GeneralName::URI(uri) if uri.starts_with("spiffe://") => Some(uri.to_string()),
+        _ => None,
+    })
+}
+
+/// Chain validation against the internal CA, then the SPIFFE ID against the
+/// allow list, so an unlisted service fails the handshake.
+#[derive(Debug)]
+struct SpiffeClientVerifier {
+    inner: Arc<dyn ClientCertVerifier>,
+    allowed: HashSet<String>,
+}
+
+impl ClientCertVerifier for SpiffeClientVerifier {
+    fn root_hint_subjects(&self) -> &[DistinguishedName] {
+        self.inner.root_hint_subjects()
+    }
+
+    fn verify_client_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        self.inner.verify_client_cert(end_entity, intermediates, now)?;
+        match spiffe_id(end_entity) {
+            Some(id) if self.allowed.contains(&id) => Ok(ClientCertVerified::assertion()),
+            Some(id) => Err(rustls::Error::General(format!("SPIFFE ID {} is not allowed", id))),
+            None => Err(rustls::Error::General("client certificate has no SPIFFE ID".to_string())),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// Serves `app` over TLS on `listener`, with the config current when each
+/// connection arrives.
+pub async fn serve(listener: TcpListener, app: Router, config: Reloading<ServerConfig>) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                warn!("TLS accept failed: {}", e);
+                continue;
+            }
+        };
+        let acceptor = TlsAcceptor::from(config.current());
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match acceptor.accept(stream).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    debug!("TLS handshake with {} failed: {}", addr, e);
+                    return;
+                }
+            };
+            if let Some(identity) = stream.get_ref().1.peer_certificates().and_then(|certs| certs.first()).and_then(spiffe_id) {
+                debug!("mTLS connection from {} as {}", addr, identity);
+            }
+
+            let service = TowerToHyperService::new(app);
+            if let Err(e) = auto::Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!("TLS connection from {} ended: {}", addr, e);
+            }
+        });
+    }
+}
@@ -0,0 +1,54 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{verify_transaction, Money, MoneyError, Transaction, TransactionKind, TransactionStatus, NATIVE_ASSET};
+
+/// One step of a streaming payment channel.
+///
+/// The payer reserves `reserved` when opening the channel and then keeps
+/// raising `paid`. Each update is signed by the payer as the transfer that
+/// would settle the channel right now: id `channel_id`, amount `paid`,
+/// timestamp `opened_at`. Whoever closes the channel posts the latest
+/// update's `settlement()` to the gateway; since every update is the same
+/// transaction at a higher amount, the channel settles at most once.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ChannelUpdate {
+    pub channel_id: String,
+    pub payer: String,
+    pub payee: String,
+    pub reserved: f64,
+    pub paid: f64,
+    pub opened_at: DateTime<Utc>,
+    pub signature: String,
+    pub public_key: String,
+}
+
+impl ChannelUpdate {
+    /// The transfer this update commits the payer to. `signature` is only
+    /// valid once the payer has signed it. Fails if `paid` isn't an amount.
+    pub fn settlement(&self) -> Result<Transaction, MoneyError> {
+        Ok(Transaction {
+            id: self.channel_id.clone(),
+            from_endpoint: self.payer.clone(),
+            to_endpoint: self.payee.clone(),
+            amount: Money::from_major(self.paid, NATIVE_ASSET)?,
+            timestamp: self.opened_at,
+            signature: self.signature.clone(),
+            status: TransactionStatus::Pending,
+            kind: TransactionKind::Transfer,
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: None,
+            public_key: Some(self.public_key.clone()),
+        })
+    }
+
+    /// Whether this update is signed by its payer and stays within the
+    /// reservation. Whether the key belongs to the payer is up to the caller.
+    pub fn verify(&self) -> bool {
+        self.paid >= 0.0
+            && self.paid <= self.reserved
+            && self.settlement().is_ok_and(|settlement| verify_transaction(&settlement))
+    }
+}
@@ -0,0 +1,86 @@
+//! The gateway's published service keys, in JWKS form (`OKP`/`Ed25519`,
+//! RFC 8037). During a rotation the next key is published before it signs
+//! anything and the previous one keeps signing until after that, so a
+//! client that refreshes within the overlap never sees an unknown key.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct PublishedKey {
+    pub kid: String,
+    pub kty: String,
+    pub crv: String,
+    /// The raw public key, base64url without padding.
+    pub x: String,
+    #[serde(rename = "use")]
+    pub key_use: String,
+    pub alg: String,
+    /// Unix seconds from which the key signs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub nbf: Option<i64>,
+    /// Unix seconds from which the key no longer signs. It stays published
+    /// so what it signed before then still verifies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub exp: Option<i64>,
+}
+
+impl PublishedKey {
+    pub fn ed25519(kid: String, public_key: &[u8; 32], nbf: Option<DateTime<Utc>>, exp: Option<DateTime<Utc>>) -> Self {
+        PublishedKey {
+            kid,
+            kty: "OKP".to_string(),
+            crv: "Ed25519".to_string(),
+            x: URL_SAFE_NO_PAD.encode(public_key),
+            key_use: "sig".to_string(),
+            alg: "EdDSA".to_string(),
+            nbf: nbf.map(|t| t.timestamp()),
+            exp: exp.map(|t| t.timestamp()),
+        }
+    }
+
+    /// The key in hex, as receipts carry it.
+    pub fn public_key_hex(&self) -> Option<String> {
+        let bytes = URL_SAFE_NO_PAD.decode(&self.x).ok()?;
+        (self.kty == "OKP" && self.crv == "Ed25519" && bytes.len() == 32).then(|| hex::encode(bytes))
+    }
+
+    /// Whether the key was in service at `at`.
+    pub fn valid_at(&self, at: DateTime<Utc>) -> bool {
+        let after = |secs: i64| Utc.timestamp_opt(secs, 0).single().is_some_and(|t| at >= t);
+        self.nbf.is_none_or(after) && !self.exp.is_some_and(after)
+    }
+}
+
+/// `GET /api/.well-known/keys`
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct KeySet {
+    pub keys: Vec<PublishedKey>,
+}
+
+impl KeySet {
+    /// A set of one key given in hex, e.g. pinned by hand.
+    pub fn single(public_key_hex: &str) -> Option<Self> {
+        let bytes = <[u8; 32]>::try_from(hex::decode(public_key_hex.trim()).ok()?).ok()?;
+        let kid = key_id(&bytes);
+        Some(KeySet { keys: vec![PublishedKey::ed25519(kid, &bytes, None, None)] })
+    }
+
+    /// The published key matching `public_key_hex`.
+    pub fn find(&self, public_key_hex: &str) -> Option<&PublishedKey> {
+        self.keys
+            .iter()
+            .find(|key| key.public_key_hex().is_some_and(|hex| hex.eq_ignore_ascii_case(public_key_hex)))
+    }
+}
+
+/// Key id: the first eight bytes of the public key, in hex.
+pub fn key_id(public_key: &[u8; 32]) -> String {
+    hex::encode(&public_key[..8])
+}
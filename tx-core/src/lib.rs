@@ -0,0 +1,40 @@
+//! Types shared by the api-gateway and the transaction endpoints: the
+//! canonical `Transaction`, the signaling protocol's `SignalingMessage`,
+//! and the signing payload both sides verify against.
+
+mod channel;
+mod keys;
+pub mod money;
+pub mod plugin;
+mod protocol;
+mod receipt;
+mod rfq;
+pub mod rules;
+mod signaling;
+mod signing;
+mod swap;
+mod transaction;
+mod verification;
+
+pub use channel::ChannelUpdate;
+pub use keys::{key_id, KeySet, PublishedKey};
+pub use money::{Money, MoneyError};
+pub use plugin::{Plugin, Plugins};
+pub use protocol::{Capabilities, Capability, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+pub use receipt::{transaction_hash, Receipt};
+pub use rfq::{Quote, QuoteRequest, Side};
+pub use rules::Rule;
+pub use signaling::{AckResult, IceCandidate, RoomInfo, SignalingMessage};
+pub use signing::{canonical_bytes, verify_signature, verify_transaction};
+pub use swap::{SwapCommitment, SwapLeg, SwapState, SwapStatus, SwapTerms, NATIVE_ASSET};
+pub use transaction::{Transaction, TransactionKind, TransactionStatus};
+pub use verification::{
+    checkpoint_root, verify_receipt, ChainLink, Check, InclusionProof, ReceiptReport, TRANSACTION_CREATED,
+};
+
+/// Version of the wire schema in this crate.
+///
+/// 1: endpoints sent transactions as `from`/`to` with millisecond
+/// timestamps, the gateway as `from_endpoint`/`to_endpoint` with RFC 3339.
+/// 2: everyone sends the gateway's form; both forms are still accepted.
+pub const SCHEMA_VERSION: u32 = 2;
@@ -0,0 +1,322 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::NATIVE_ASSET;
+
+/// Decimal places kept; channel payments accrue in fractions of a cent.
+pub const DECIMALS: u32 = 6;
+
+/// Minor units in one major unit.
+pub const MINOR_PER_MAJOR: i64 = 10i64.pow(DECIMALS);
+
+/// An exact amount of one currency, in millionths of its major unit.
+/// Arithmetic is checked: mixing currencies or overflowing is an error
+/// rather than a wrong balance.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Money {
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    minor: i64,
+    currency: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MoneyError {
+    CurrencyMismatch(String, String),
+    Overflow,
+    Invalid(String),
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::CurrencyMismatch(a, b) => write!(f, "cannot combine {} with {}", a, b),
+            MoneyError::Overflow => write!(f, "amount out of range"),
+            MoneyError::Invalid(reason) => write!(f, "invalid amount: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+impl Money {
+    pub fn new(minor: i64, currency: &str) -> Self {
+        Money { minor, currency: currency.to_string() }
+    }
+
+    pub fn zero(currency: &str) -> Self {
+        Money::new(0, currency)
+    }
+
+    /// From a floating-point amount in major units, rounded to the nearest
+    /// minor unit. For amounts that arrive as `f64` on the wire.
+    pub fn from_major(amount: f64, currency: &str) -> Result<Self, MoneyError> {
+        let minor = (amount * MINOR_PER_MAJOR as f64).round();
+        if !minor.is_finite() {
+            return Err(MoneyError::Invalid(amount.to_string()));
+        }
+        if minor < i64::MIN as f64 || minor >= i64::MAX as f64 {
+            return Err(MoneyError::Overflow);
+        }
+        Ok(Money::new(minor as i64, currency))
+    }
+
+    pub fn minor(&self) -> i64 {
+        self.minor
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// The amount as `f64` in major units, for the wire and display only.
+    pub fn to_major(&self) -> f64 {
+        self.minor as f64 / MINOR_PER_MAJOR as f64
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.minor == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.minor < 0
+    }
+
+    pub fn checked_add(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let minor = self.minor.checked_add(other.minor).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(minor, &self.currency))
+    }
+
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, MoneyError> {
+        self.same_currency(other)?;
+        let minor = self.minor.checked_sub(other.minor).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(minor, &self.currency))
+    }
+
+    pub fn checked_neg(&self) -> Result<Money, MoneyError> {
+        let minor = self.minor.checked_neg().ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(minor, &self.currency))
+    }
+
+    /// Divides into `parts`, rounding toward zero. For averages.
+    pub fn checked_div(&self, parts: i64) -> Result<Money, MoneyError> {
+        let minor = self.minor.checked_div(parts).ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(minor, &self.currency))
+    }
+
+    fn same_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch(self.currency.clone(), other.currency.clone()));
+        }
+        Ok(())
+    }
+}
+
+/// Amounts of one currency order by value; amounts of different ones
+/// don't compare, so every comparison between them is false.
+impl PartialOrd for Money {
+    fn partial_cmp(&self, other: &Money) -> Option<std::cmp::Ordering> {
+        (self.currency == other.currency).then(|| self.minor.cmp(&other.minor))
+    }
+}
+
+/// `12.50 USD`: at least two decimals, more only where the amount has them.
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.minor < 0 { "-" } else { "" };
+        let abs = self.minor.unsigned_abs();
+        let per_major = MINOR_PER_MAJOR as u64;
+        let fraction = format!("{:0width$}", abs % per_major, width = DECIMALS as usize);
+        let fraction = fraction.trim_end_matches('0');
+        write!(f, "{}{}.{:0<2} {}", sign, abs / per_major, fraction, self.currency)
+    }
+}
+
+/// Parses `12.5 USD`, or a bare `12.5` in the native asset.
+impl FromStr for Money {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let amount = parts.next().ok_or_else(|| MoneyError::Invalid(s.to_string()))?;
+        let currency = parts.next().unwrap_or(NATIVE_ASSET);
+        if parts.next().is_some() {
+            return Err(MoneyError::Invalid(s.to_string()));
+        }
+
+        let (negative, digits) = match amount.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, amount),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let valid = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if whole.is_empty() || !valid(whole) || !valid(fraction) || fraction.len() > DECIMALS as usize {
+            return Err(MoneyError::Invalid(s.to_string()));
+        }
+
+        let whole: i64 = whole.parse().map_err(|_| MoneyError::Overflow)?;
+        let fraction: i64 = format!("{:0<width$}", fraction, width = DECIMALS as usize)
+            .parse()
+            .map_err(|_| MoneyError::Invalid(s.to_string()))?;
+        let minor = whole
+            .checked_mul(MINOR_PER_MAJOR)
+            .and_then(|minor| minor.checked_add(fraction))
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(if negative { -minor } else { minor }, currency))
+    }
+}
+
+/// Serializes native-asset `Money` as a plain number of major units, for
+/// fields that were `f64` before and whose readers still expect one.
+/// Reads that number, or an exact decimal string (`"12.50"`, `"12.50 USD"`)
+/// for clients that would rather not round through a float.
+pub mod as_major {
+    use serde::{Deserializer, Serializer};
+
+    use super::Money;
+    use crate::NATIVE_ASSET;
+
+    pub fn serialize<S: Serializer>(money: &Money, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(money.to_major())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        super::deserialize_major(deserializer, NATIVE_ASSET)
+    }
+}
+
+/// `as_major` for an optional amount: `null` for `None`, which is also
+/// what an absent field reads as under `#[serde(default)]`.
+pub mod as_major_opt {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Money;
+
+    pub fn serialize<S: Serializer>(money: &Option<Money>, serializer: S) -> Result<S::Ok, S::Error> {
+        match money {
+            Some(money) => serializer.serialize_some(&money.to_major()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Money>, D::Error> {
+        #[derive(Deserialize)]
+        struct Major(#[serde(with = "super::as_major")] Money);
+
+        Ok(Option::<Major>::deserialize(deserializer)?.map(|Major(money)| money))
+    }
+}
+
+/// An amount of `currency` as `as_major` reads it. A string naming another
+/// currency is rejected rather than converted.
+pub(crate) fn deserialize_major<'de, D: Deserializer<'de>>(deserializer: D, currency: &str) -> Result<Money, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Major {
+        Number(f64),
+        Text(String),
+    }
+
+    let money = match Major::deserialize(deserializer)? {
+        Major::Number(amount) => Money::from_major(amount, currency),
+        Major::Text(text) => match text.split_whitespace().count() {
+            1 => format!("{} {}", text.trim(), currency).parse(),
+            _ => text.parse(),
+        },
+    }
+    .map_err(serde::de::Error::custom)?;
+    if money.currency() != currency {
+        return Err(serde::de::Error::custom(MoneyError::CurrencyMismatch(money.currency, currency.to_string())));
+    }
+    Ok(money)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_up_to_six_decimals() {
+        assert_eq!("12.5".parse(), Ok(Money::new(12_500_000, NATIVE_ASSET)));
+        assert_eq!("0.000001 EUR".parse(), Ok(Money::new(1, "EUR")));
+        assert_eq!("-3".parse(), Ok(Money::new(-3_000_000, NATIVE_ASSET)));
+        for invalid in ["", "1.0000001", "1.2.3", "abc", "1e3", ".5", "1 USD extra"] {
+            assert!(invalid.parse::<Money>().is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn formats_at_least_two_decimals() {
+        assert_eq!(Money::new(12_500_000, "USD").to_string(), "12.50 USD");
+        assert_eq!(Money::new(1, "USD").to_string(), "0.000001 USD");
+        assert_eq!(Money::new(-1_230_000, "EUR").to_string(), "-1.23 EUR");
+        let money = Money::new(987_654_321, "USD");
+        assert_eq!(money.to_string().parse(), Ok(money));
+    }
+
+    #[test]
+    fn overflow_is_an_error() {
+        let max = Money::new(i64::MAX, NATIVE_ASSET);
+        assert_eq!(max.checked_add(&Money::new(1, NATIVE_ASSET)), Err(MoneyError::Overflow));
+        assert_eq!(Money::new(i64::MIN, NATIVE_ASSET).checked_neg(), Err(MoneyError::Overflow));
+        // i64::MAX millionths is 9223372036854.775807 major units
+        assert_eq!("9223372036855".parse::<Money>(), Err(MoneyError::Overflow));
+        assert_eq!("9223372036854.775807".parse(), Ok(max));
+        assert_eq!(Money::from_major(1e13, NATIVE_ASSET), Err(MoneyError::Overflow));
+        assert!(Money::from_major(f64::NAN, NATIVE_ASSET).is_err());
+    }
+
+    #[test]
+    fn mixing_currencies_is_an_error() {
+        let usd = Money::new(1, "USD");
+        let eur = Money::new(1, "EUR");
+        assert_eq!(usd.checked_add(&eur), Err(MoneyError::CurrencyMismatch("USD".to_string(), "EUR".to_string())));
+    }
+
+    #[test]
+    fn compares_within_a_currency_only() {
+        assert!(Money::new(2, "USD") > Money::new(1, "USD"));
+        assert!(Money::new(1, "USD") <= Money::new(1, "USD"));
+        assert_eq!(Money::new(2, "USD").partial_cmp(&Money::new(1, "EUR")), None);
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Amount {
+        #[serde(with = "as_major")]
+        amount: Money,
+    }
+
+    #[test]
+    fn as_major_reads_numbers_and_decimal_strings() {
+        let read = |json: &str| serde_json::from_str::<Amount>(json).map(|amount| amount.amount);
+        assert_eq!(read(r#"{"amount": 12.5}"#).unwrap(), Money::new(12_500_000, NATIVE_ASSET));
+        assert_eq!(read(r#"{"amount": "12.50"}"#).unwrap(), Money::new(12_500_000, NATIVE_ASSET));
+        assert_eq!(read(r#"{"amount": "0.1 USD"}"#).unwrap(), Money::new(100_000, NATIVE_ASSET));
+        assert!(read(r#"{"amount": "12.50 EUR"}"#).is_err());
+        assert!(read(r#"{"amount": "0.0000001"}"#).is_err());
+
+        let written = serde_json::to_string(&Amount { amount: Money::new(12_500_000, NATIVE_ASSET) }).unwrap();
+        assert_eq!(written, r#"{"amount":12.5}"#);
+    }
+
+    #[derive(Debug, Deserialize, Serialize)]
+    struct Limit {
+        #[serde(default, with = "as_major_opt")]
+        limit: Option<Money>,
+    }
+
+    #[test]
+    fn as_major_opt_reads_null_and_absent_as_none() {
+        let read = |json: &str| serde_json::from_str::<Limit>(json).map(|limit| limit.limit);
+        assert_eq!(read("{}").unwrap(), None);
+        assert_eq!(read(r#"{"limit": null}"#).unwrap(), None);
+        assert_eq!(read(r#"{"limit": "2.5"}"#).unwrap(), Some(Money::new(2_500_000, NATIVE_ASSET)));
+        assert!(read(r#"{"limit": "1 EUR"}"#).is_err());
+
+        let written = serde_json::to_string(&Limit { limit: Some(Money::new(2_500_000, NATIVE_ASSET)) }).unwrap();
+        assert_eq!(written, r#"{"limit":2.5}"#);
+        assert_eq!(serde_json::to_string(&Limit { limit: None }).unwrap(), r#"{"limit":null}"#);
+    }
+}
@@ -0,0 +1,189 @@
+//! Hooks into the signaling protocol engine, so applications can add
+//! validation, logging or enrichment without forking the connection
+//! modules. A connection runs its `Plugins`:
+//!
+//! - `on_before_send` on every message it is about to write, in
+//!   registration order;
+//! - `on_receive` on every message it read, in reverse order, so a plugin
+//!   registered after another sees its own output first (encryption
+//!   registered last seals last and opens first);
+//! - `on_peer_change` when room membership changes, after the message
+//!   that says so has been received.
+//!
+//! Either message hook may rewrite the message or drop it, and a plugin
+//! may queue messages of its own, which the connection sends ahead of the
+//! next one it writes, or after what it just read. `Dedup`, `Encryption`,
+//! `PeerEncryption` and `GroupEncryption` are built on this interface.
+
+mod dedup;
+mod encryption;
+mod group_encryption;
+mod peer_encryption;
+
+use std::fmt;
+
+use crate::{Capabilities, SignalingMessage, PROTOCOL_VERSION};
+
+pub use dedup::Dedup;
+pub use encryption::Encryption;
+pub use group_encryption::{GroupEncryption, SENDER_KEY_TYPE};
+pub use peer_encryption::{PeerEncryption, RatchetState, KEY_EXCHANGE_TYPE};
+
+/// What a message hook decided.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Pass,
+    /// Not sent, or not delivered, with why.
+    Drop(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PeerChange {
+    /// Also reported for every peer already present when we join.
+    Joined { peer_id: String, capabilities: Capabilities },
+    Left { peer_id: String },
+}
+
+/// Every hook defaults to doing nothing. `Send` so native clients holding
+/// plugins can move between threads.
+pub trait Plugin: Send {
+    /// Shown when the plugin drops a message.
+    fn name(&self) -> &str;
+
+    fn on_before_send(&mut self, _message: &mut SignalingMessage) -> Verdict {
+        Verdict::Pass
+    }
+
+    fn on_receive(&mut self, _message: &mut SignalingMessage) -> Verdict {
+        Verdict::Pass
+    }
+
+    fn on_peer_change(&mut self, _change: &PeerChange) {}
+
+    /// Messages the plugin wants sent, e.g. to answer a peer. These skip
+    /// the plugins.
+    fn take_outgoing(&mut self) -> Vec<SignalingMessage> {
+        Vec::new()
+    }
+}
+
+/// A sealed transaction a plugin opened, or failed to, for a key-usage
+/// log; see `PeerEncryption::log_decryptions`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Decryption {
+    pub plugin: String,
+    pub peer: String,
+    /// The session key's id, or the sender key's epoch.
+    pub key: String,
+    /// Of the transaction, once opened.
+    pub transaction_id: Option<String>,
+    pub error: Option<String>,
+}
+
+impl Decryption {
+    fn new(plugin: &str, peer: &str, key: String, opened: &Result<crate::Transaction, String>) -> Self {
+        Decryption {
+            plugin: plugin.to_string(),
+            peer: peer.to_string(),
+            key,
+            transaction_id: opened.as_ref().ok().map(|tx| tx.id.clone()),
+            error: opened.as_ref().err().cloned(),
+        }
+    }
+}
+
+/// A message a plugin dropped.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Dropped {
+    pub plugin: String,
+    pub reason: String,
+}
+
+impl fmt::Display for Dropped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "dropped by {}: {}", self.plugin, self.reason)
+    }
+}
+
+impl std::error::Error for Dropped {}
+
+/// The plugins a connection runs.
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+impl Plugins {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, plugin: impl Plugin + 'static) {
+        self.plugins.push(Box::new(plugin));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// Runs `on_before_send`; `Err` means don't send `message`.
+    pub fn before_send(&mut self, message: &mut SignalingMessage) -> Result<(), Dropped> {
+        for plugin in self.plugins.iter_mut() {
+            if let Verdict::Drop(reason) = plugin.on_before_send(message) {
+                return Err(Dropped { plugin: plugin.name().to_string(), reason });
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `on_receive`, then `on_peer_change` for any membership change
+    /// `message` reports; `Err` means don't deliver it.
+    pub fn receive(&mut self, message: &mut SignalingMessage) -> Result<(), Dropped> {
+        for plugin in self.plugins.iter_mut().rev() {
+            if let Verdict::Drop(reason) = plugin.on_receive(message) {
+                return Err(Dropped { plugin: plugin.name().to_string(), reason });
+            }
+        }
+        for change in peer_changes(message) {
+            for plugin in self.plugins.iter_mut() {
+                plugin.on_peer_change(&change);
+            }
+        }
+        Ok(())
+    }
+
+    /// What the plugins queued to send, in registration order.
+    pub fn take_outgoing(&mut self) -> Vec<SignalingMessage> {
+        self.plugins.iter_mut().flat_map(|plugin| plugin.take_outgoing()).collect()
+    }
+}
+
+fn peer_changes(message: &SignalingMessage) -> Vec<PeerChange> {
+    match message.message_type.as_str() {
+        "room-joined" => {
+            let features = message.peer_features.as_ref();
+            message
+                .peers
+                .iter()
+                .flatten()
+                .map(|peer_id| PeerChange::Joined {
+                    peer_id: peer_id.clone(),
+                    capabilities: features
+                        .and_then(|features| features.get(peer_id))
+                        .map(|names| Capabilities::from_names(PROTOCOL_VERSION, names))
+                        .unwrap_or_default(),
+                })
+                .collect()
+        }
+        "peer-joined" => message
+            .peer_id
+            .iter()
+            .map(|peer_id| PeerChange::Joined {
+                peer_id: peer_id.clone(),
+                capabilities: Capabilities::from_message(message),
+            })
+            .collect(),
+        "peer-left" => message.peer_id.iter().map(|peer_id| PeerChange::Left { peer_id: peer_id.clone() }).collect(),
+        _ => Vec::new(),
+    }
+}
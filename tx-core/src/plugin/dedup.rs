@@ -0,0 +1,50 @@
+use std::collections::{HashSet, VecDeque};
+
+use super::{Plugin, Verdict};
+use crate::SignalingMessage;
+
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Drops a transaction message already delivered: the same transaction
+/// in a message of the same type, e.g. a broadcast replayed by a resync
+/// or relayed over two paths. Remembers the last `capacity` of them.
+pub struct Dedup {
+    seen: HashSet<(String, String)>,
+    order: VecDeque<(String, String)>,
+    capacity: usize,
+}
+
+impl Default for Dedup {
+    fn default() -> Self {
+        Dedup::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl Dedup {
+    pub fn new(capacity: usize) -> Self {
+        Dedup { seen: HashSet::new(), order: VecDeque::new(), capacity: capacity.max(1) }
+    }
+}
+
+impl Plugin for Dedup {
+    fn name(&self) -> &str {
+        "dedup"
+    }
+
+    fn on_receive(&mut self, message: &mut SignalingMessage) -> Verdict {
+        let Some(tx) = &message.transaction else {
+            return Verdict::Pass;
+        };
+        let key = (message.message_type.clone(), tx.id.clone());
+        if !self.seen.insert(key.clone()) {
+            return Verdict::Drop(format!("{} {} already delivered", key.0, key.1));
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        Verdict::Pass
+    }
+}
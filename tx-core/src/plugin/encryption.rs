@@ -0,0 +1,92 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+use super::{Plugin, Verdict};
+use crate::{SignalingMessage, Transaction};
+
+/// Binds ciphertexts to this use, and to this layout.
+const AAD: &[u8] = b"tx-core sealed transaction v1";
+const NONCE_LEN: usize = 12;
+
+/// End-to-end encryption of transactions on the signaling channel, under
+/// a key every member of the room shares, distributed out of band. The
+/// server relays `sealed` in place of `transaction` and can no longer read
+/// amounts or parties; the gateway still gets them from each endpoint's
+/// own submission.
+///
+/// Rooms that settle in batches through the server can't use it: the
+/// server would have nothing to record. Messages from peers without the
+/// key arrive unsealed and are passed through as they are.
+pub struct Encryption {
+    cipher: ChaCha20Poly1305,
+}
+
+impl Encryption {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Encryption { cipher: ChaCha20Poly1305::new(Key::from_slice(key)) }
+    }
+
+    /// From 64 hex digits.
+    pub fn from_hex(key: &str) -> Result<Self, String> {
+        let bytes = hex::decode(key.trim()).map_err(|e| format!("Invalid room key: {}", e))?;
+        let key: [u8; 32] = bytes.try_into().map_err(|_| "Room key must be 32 bytes".to_string())?;
+        Ok(Encryption::new(&key))
+    }
+
+    fn seal(&self, tx: &Transaction) -> Result<String, String> {
+        let plaintext = serde_json::to_vec(tx).map_err(|e| e.to_string())?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: AAD })
+            .map_err(|_| "encryption failed".to_string())?;
+        Ok(STANDARD.encode([nonce.as_slice(), &ciphertext].concat()))
+    }
+
+    fn open(&self, sealed: &str) -> Result<Transaction, String> {
+        let bytes = STANDARD.decode(sealed).map_err(|e| format!("invalid sealed transaction: {}", e))?;
+        if bytes.len() < NONCE_LEN {
+            return Err("sealed transaction too short".to_string());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: AAD })
+            .map_err(|_| "sealed under another key, or tampered with".to_string())?;
+        serde_json::from_slice(&plaintext).map_err(|e| format!("invalid sealed transaction: {}", e))
+    }
+}
+
+impl Plugin for Encryption {
+    fn name(&self) -> &str {
+        "encryption"
+    }
+
+    fn on_before_send(&mut self, message: &mut SignalingMessage) -> Verdict {
+        let Some(tx) = message.transaction.take() else {
+            return Verdict::Pass;
+        };
+        match self.seal(&tx) {
+            Ok(sealed) => {
+                message.sealed = Some(sealed);
+                Verdict::Pass
+            }
+            Err(e) => Verdict::Drop(e),
+        }
+    }
+
+    fn on_receive(&mut self, message: &mut SignalingMessage) -> Verdict {
+        let Some(sealed) = message.sealed.take() else {
+            return Verdict::Pass;
+        };
+        match self.open(&sealed) {
+            Ok(tx) => {
+                message.transaction = Some(tx);
+                Verdict::Pass
+            }
+            Err(e) => Verdict::Drop(e),
+        }
+    }
+}
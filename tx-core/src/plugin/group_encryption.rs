@@ -0,0 +1,508 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use ed25519_dalek::{Signer, SigningKey};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::{Decryption, PeerChange, Plugin, Verdict};
+use crate::{verify_signature, Capability, SignalingMessage, Transaction};
+
+/// Carries one member's exchange key, and its sender key once it knows
+/// ours, to another, relayed point-to-point.
+pub const SENDER_KEY_TYPE: &str = "sender-key";
+
+/// What an endpoint's identity key signs to vouch for its exchange key.
+const KEY_CONTEXT: &str = "tx-core sender-key v1";
+/// HKDF info for wrapping sender keys, followed by sender and recipient.
+const WRAP_INFO: &str = "tx-core sender-key wrap v1";
+/// Binds ciphertexts to this use, followed by sender and epoch.
+const AAD: &str = "tx-core group-sealed transaction v1";
+const NONCE_LEN: usize = 12;
+/// Sender keys kept per member, newest first, for broadcasts sealed just
+/// before a rekey or replayed by a resync.
+const KEPT_EPOCHS: usize = 2;
+
+/// Checks a peer's identity key; see `GroupEncryption::with_trust`.
+type Trust = Box<dyn Fn(&str, &str) -> bool + Send>;
+/// See `GroupEncryption::log_decryptions`.
+type Log = Box<dyn FnMut(&Decryption) + Send>;
+
+struct SenderKey {
+    epoch: u64,
+    key: Key,
+}
+
+impl SenderKey {
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(&self.key)
+    }
+}
+
+#[derive(Default)]
+struct Member {
+    /// From its last `sender-key`.
+    exchange_key: Option<PublicKey>,
+    sender_keys: VecDeque<SenderKey>,
+}
+
+/// End-to-end encryption of room broadcasts with sender keys, for rooms
+/// joined with `encrypted`: the server relays only ciphertext there, and
+/// every member, but nobody else, can read every broadcast.
+///
+/// Each member seals its broadcasts with ChaCha20-Poly1305 under a sender
+/// key of its own, tagged with the key's `epoch`, and hands the key to
+/// every other member in a `sender-key`. That carries its X25519 exchange
+/// key, signed with its ed25519 identity key, and the sender key wrapped
+/// under one derived from the two members' exchange keys through
+/// HKDF-SHA256. A member that doesn't know the other's exchange key yet
+/// sends it alone, and the answer completes the exchange.
+///
+/// Every member moves to a new sender key whenever someone joins or
+/// leaves, so a newcomer can't read what was said before it came and a
+/// leaver can't read what is said after. The previous key is kept for
+/// what was already in flight. A broadcast sealed before a member has
+/// our key can't be opened there and is dropped.
+///
+/// In other rooms it does nothing. Register it before `PeerEncryption`,
+/// which would otherwise seal broadcasts for their recipient alone, and
+/// not alongside the room-key `Encryption`, which can't tell the two
+/// ciphertexts apart.
+pub struct GroupEncryption {
+    endpoint_id: String,
+    identity: SigningKey,
+    trust: Trust,
+    secret: StaticSecret,
+    public: PublicKey,
+    epoch: u64,
+    /// Ours, newest first.
+    sender_keys: VecDeque<SenderKey>,
+    members: HashMap<String, Member>,
+    /// Members sent our current sender key.
+    distributed: HashSet<String>,
+    /// The room is encrypted, from its `room-joined`.
+    encrypted: bool,
+    log: Option<Log>,
+    outgoing: Vec<SignalingMessage>,
+}
+
+impl GroupEncryption {
+    /// `identity` is the endpoint's transaction signing key.
+    pub fn new(endpoint_id: &str, identity: SigningKey) -> Self {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        GroupEncryption {
+            endpoint_id: endpoint_id.to_string(),
+            identity,
+            trust: Box::new(|_, _| true),
+            public: PublicKey::from(&secret),
+            secret,
+            epoch: 0,
+            sender_keys: VecDeque::new(),
+            members: HashMap::new(),
+            distributed: HashSet::new(),
+            encrypted: false,
+            log: None,
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// `trust(peer, public_key_hex)` decides whether an identity key is
+    /// really the peer's, e.g. against the key pinned for it. By default
+    /// any key whose signature checks out is taken.
+    pub fn with_trust(mut self, trust: impl Fn(&str, &str) -> bool + Send + 'static) -> Self {
+        self.trust = Box::new(trust);
+        self
+    }
+
+    /// `log` hears of every sealed broadcast we open, or fail to.
+    pub fn log_decryptions(mut self, log: impl FnMut(&Decryption) + Send + 'static) -> Self {
+        self.log = Some(Box::new(log));
+        self
+    }
+
+    /// Our exchange key to `peer`, signed, with nothing else yet.
+    fn announcement(&self, peer: &str) -> SignalingMessage {
+        let exchange_key = STANDARD.encode(self.public.as_bytes());
+        let signed = format!("{}\n{}\n{}\n{}", KEY_CONTEXT, self.endpoint_id, peer, exchange_key);
+        SignalingMessage {
+            peer_id: Some(self.endpoint_id.clone()),
+            target_peer: Some(peer.to_string()),
+            exchange_key: Some(exchange_key),
+            public_key: Some(hex::encode(self.identity.verifying_key().to_bytes())),
+            signature: Some(hex::encode(self.identity.sign(signed.as_bytes()).to_bytes())),
+            ..SignalingMessage::new(SENDER_KEY_TYPE)
+        }
+    }
+
+    fn wrapping_key(&self, their_key: &PublicKey, from: &str, to: &str) -> Result<ChaCha20Poly1305, String> {
+        let peer = if from == self.endpoint_id { to } else { from };
+        let shared = self.secret.diffie_hellman(their_key);
+        if !shared.was_contributory() {
+            return Err(format!("weak exchange key from {}", peer));
+        }
+        let info = format!("{}\n{}\n{}", WRAP_INFO, from, to);
+        let mut key = [0u8; 32];
+        Hkdf::<Sha256>::new(None, shared.as_bytes())
+            .expand(info.as_bytes(), &mut key)
+            .map_err(|_| "key derivation failed".to_string())?;
+        Ok(ChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    /// Our current sender key, wrapped for `peer`.
+    fn send_key(&mut self, peer: &str) -> Result<(), String> {
+        let (Some(their_key), Some(current)) =
+            (self.members.get(peer).and_then(|member| member.exchange_key), self.sender_keys.front())
+        else {
+            return Ok(());
+        };
+        let aad = format!("{}\n{}\n{}\n{}", WRAP_INFO, self.endpoint_id, peer, current.epoch);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let wrapped = self
+            .wrapping_key(&their_key, &self.endpoint_id, peer)?
+            .encrypt(&nonce, Payload { msg: current.key.as_slice(), aad: aad.as_bytes() })
+            .map_err(|_| "encryption failed".to_string())?;
+        let message = SignalingMessage {
+            epoch: Some(current.epoch),
+            sealed: Some(STANDARD.encode([nonce.as_slice(), &wrapped].concat())),
+            ..self.announcement(peer)
+        };
+        self.outgoing.push(message);
+        self.distributed.insert(peer.to_string());
+        Ok(())
+    }
+
+    /// A new sender key, sent to every member whose exchange key we know;
+    /// the rest get it when they answer.
+    fn rekey(&mut self) {
+        self.epoch += 1;
+        self.sender_keys.push_front(SenderKey {
+            epoch: self.epoch,
+            key: ChaCha20Poly1305::generate_key(&mut OsRng),
+        });
+        self.sender_keys.truncate(KEPT_EPOCHS);
+        self.distributed.clear();
+
+        let peers: Vec<String> = self.members.keys().cloned().collect();
+        for peer in peers {
+            if self.send_key(&peer).is_err() {
+                self.members.remove(&peer);
+            }
+        }
+    }
+
+    fn unwrap_key(&self, wrapped: &str, epoch: u64, their_key: &PublicKey, peer: &str) -> Result<Key, String> {
+        let bytes = STANDARD.decode(wrapped).map_err(|e| format!("invalid sender key from {}: {}", peer, e))?;
+        if bytes.len() < NONCE_LEN {
+            return Err(format!("sender key from {} too short", peer));
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let aad = format!("{}\n{}\n{}\n{}", WRAP_INFO, peer, self.endpoint_id, epoch);
+        let key = self
+            .wrapping_key(their_key, peer, &self.endpoint_id)?
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: aad.as_bytes() })
+            .map_err(|_| format!("sender key from {} was wrapped for someone else, or tampered with", peer))?;
+        if key.len() != 32 {
+            return Err(format!("invalid sender key from {}", peer));
+        }
+        Ok(*Key::from_slice(&key))
+    }
+
+    /// Takes a peer's `sender-key`, answering with ours if it hasn't had
+    /// it.
+    fn accept(&mut self, message: &SignalingMessage) -> Result<String, String> {
+        let peer = message.from_peer.clone().ok_or("sender-key without a sender")?;
+        let (Some(exchange_key), Some(public_key), Some(signature)) =
+            (&message.exchange_key, &message.public_key, &message.signature)
+        else {
+            return Err(format!("incomplete sender-key from {}", peer));
+        };
+        let signed = format!("{}\n{}\n{}\n{}", KEY_CONTEXT, peer, self.endpoint_id, exchange_key);
+        if !verify_signature(public_key, signed.as_bytes(), signature) || !(self.trust)(&peer, public_key) {
+            return Err(format!("sender-key from {} isn't signed by its key", peer));
+        }
+        let bytes: [u8; 32] = STANDARD
+            .decode(exchange_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("invalid exchange key from {}", peer))?;
+        let their_key = PublicKey::from(bytes);
+
+        let sender_key = match (&message.sealed, message.epoch) {
+            (Some(wrapped), Some(epoch)) => {
+                Some(SenderKey { epoch, key: self.unwrap_key(wrapped, epoch, &their_key, &peer)? })
+            }
+            _ => None,
+        };
+        let member = self.members.entry(peer.clone()).or_default();
+        member.exchange_key = Some(their_key);
+        if let Some(sender_key) = sender_key {
+            // A rekey can overtake the key before it
+            if member.sender_keys.front().is_none_or(|newest| newest.epoch < sender_key.epoch) {
+                member.sender_keys.push_front(sender_key);
+                member.sender_keys.truncate(KEPT_EPOCHS);
+            }
+        }
+        if !self.distributed.contains(&peer) {
+            self.send_key(&peer)?;
+        }
+        Ok(peer)
+    }
+
+    fn seal(&self, tx: &Transaction) -> Result<(String, u64), String> {
+        let current = self.sender_keys.front().ok_or("no sender key yet")?;
+        let plaintext = serde_json::to_vec(tx).map_err(|e| e.to_string())?;
+        let aad = format!("{}\n{}\n{}", AAD, self.endpoint_id, current.epoch);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = current
+            .cipher()
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: aad.as_bytes() })
+            .map_err(|_| "encryption failed".to_string())?;
+        Ok((STANDARD.encode([nonce.as_slice(), &ciphertext].concat()), current.epoch))
+    }
+
+    fn open(&self, sealed: &str, epoch: u64, peer: &str) -> Result<Transaction, String> {
+        let keys = if peer == self.endpoint_id {
+            Some(&self.sender_keys)
+        } else {
+            self.members.get(peer).map(|member| &member.sender_keys)
+        };
+        let key = keys
+            .into_iter()
+            .flatten()
+            .find(|key| key.epoch == epoch)
+            .ok_or_else(|| format!("no sender key from {} for epoch {}", peer, epoch))?;
+
+        let bytes = STANDARD.decode(sealed).map_err(|e| format!("invalid sealed transaction: {}", e))?;
+        if bytes.len() < NONCE_LEN {
+            return Err("sealed transaction too short".to_string());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let aad = format!("{}\n{}\n{}", AAD, peer, epoch);
+        let plaintext = key
+            .cipher()
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: aad.as_bytes() })
+            .map_err(|_| format!("sealed broadcast from {} was tampered with", peer))?;
+        serde_json::from_slice(&plaintext).map_err(|e| format!("invalid sealed transaction: {}", e))
+    }
+}
+
+impl Plugin for GroupEncryption {
+    fn name(&self) -> &str {
+        "group-encryption"
+    }
+
+    fn on_before_send(&mut self, message: &mut SignalingMessage) -> Verdict {
+        if !self.encrypted || message.message_type != "transaction" {
+            return Verdict::Pass;
+        }
+        let Some(tx) = message.transaction.take() else {
+            return Verdict::Pass;
+        };
+        match self.seal(&tx) {
+            Ok((sealed, epoch)) => {
+                message.sealed = Some(sealed);
+                message.epoch = Some(epoch);
+                Verdict::Pass
+            }
+            Err(e) => Verdict::Drop(e),
+        }
+    }
+
+    fn on_receive(&mut self, message: &mut SignalingMessage) -> Verdict {
+        match message.message_type.as_str() {
+            "room-joined" => {
+                // A new room, or the same one after a reconnect: start over
+                self.encrypted = message.encrypted == Some(true);
+                self.members.clear();
+                self.secret = StaticSecret::random_from_rng(OsRng);
+                self.public = PublicKey::from(&self.secret);
+                self.rekey();
+                return Verdict::Pass;
+            }
+            // Before the newcomer is a member, so it doesn't get this key
+            "peer-joined" if self.encrypted => {
+                self.rekey();
+                return Verdict::Pass;
+            }
+            SENDER_KEY_TYPE => {
+                // Ours alone; nothing for the application
+                return match self.accept(message) {
+                    Ok(peer) => Verdict::Drop(format!("took the sender key from {}", peer)),
+                    Err(e) => Verdict::Drop(e),
+                };
+            }
+            _ => {}
+        }
+
+        let (Some(_), Some(epoch)) = (&message.sealed, message.epoch) else {
+            return Verdict::Pass;
+        };
+        let Some(peer) = message.from_peer.clone() else {
+            return Verdict::Drop("sealed broadcast without a sender".to_string());
+        };
+        let sealed = message.sealed.take().unwrap_or_default();
+        let opened = self.open(&sealed, epoch, &peer);
+        if self.log.is_some() {
+            let decryption = Decryption::new(self.name(), &peer, epoch.to_string(), &opened);
+            if let Some(log) = self.log.as_mut() {
+                log(&decryption);
+            }
+        }
+        match opened {
+            Ok(tx) => {
+                message.transaction = Some(tx);
+                Verdict::Pass
+            }
+            Err(e) => Verdict::Drop(e),
+        }
+    }
+
+    fn on_peer_change(&mut self, change: &PeerChange) {
+        if !self.encrypted {
+            return;
+        }
+        match change {
+            PeerChange::Joined { peer_id, capabilities }
+                if *peer_id != self.endpoint_id && capabilities.supports(Capability::GroupEncryption) =>
+            {
+                self.members.entry(peer_id.clone()).or_default();
+                let announcement = self.announcement(peer_id);
+                self.outgoing.push(announcement);
+            }
+            PeerChange::Joined { .. } => {}
+            PeerChange::Left { peer_id } => {
+                if self.members.remove(peer_id).is_some() {
+                    self.rekey();
+                }
+            }
+        }
+    }
+
+    fn take_outgoing(&mut self) -> Vec<SignalingMessage> {
+        std::mem::take(&mut self.outgoing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Capabilities, Money, TransactionStatus, PROTOCOL_VERSION};
+    use chrono::Utc;
+
+    fn transaction(id: &str) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            from_endpoint: "alice".to_string(),
+            to_endpoint: "bob".to_string(),
+            amount: Money::new(1_500_000, crate::NATIVE_ASSET),
+            timestamp: Utc::now(),
+            signature: String::new(),
+            status: TransactionStatus::Pending,
+            kind: Default::default(),
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: None,
+            public_key: None,
+        }
+    }
+
+    fn relay(alice: &mut GroupEncryption, bob: &mut GroupEncryption) {
+        loop {
+            let (to_bob, to_alice) = (alice.take_outgoing(), bob.take_outgoing());
+            if to_bob.is_empty() && to_alice.is_empty() {
+                break;
+            }
+            for mut message in to_bob {
+                message.from_peer = Some("alice".to_string());
+                bob.on_receive(&mut message);
+            }
+            for mut message in to_alice {
+                message.from_peer = Some("bob".to_string());
+                alice.on_receive(&mut message);
+            }
+        }
+    }
+
+    /// Two members of an encrypted room, after handing each other keys.
+    fn room() -> (GroupEncryption, GroupEncryption) {
+        let (mut alice, mut bob) = (
+            GroupEncryption::new("alice", SigningKey::from_bytes(&[1; 32])),
+            GroupEncryption::new("bob", SigningKey::from_bytes(&[2; 32])),
+        );
+        let capabilities = Capabilities::new(PROTOCOL_VERSION, &[Capability::GroupEncryption]);
+        for (member, other) in [(&mut alice, "bob"), (&mut bob, "alice")] {
+            let mut joined = SignalingMessage { encrypted: Some(true), ..SignalingMessage::new("room-joined") };
+            member.on_receive(&mut joined);
+            let change = PeerChange::Joined { peer_id: other.to_string(), capabilities: capabilities.clone() };
+            member.on_peer_change(&change);
+        }
+        relay(&mut alice, &mut bob);
+        (alice, bob)
+    }
+
+    fn broadcast(alice: &mut GroupEncryption, id: &str) -> SignalingMessage {
+        let mut message =
+            SignalingMessage { transaction: Some(transaction(id)), ..SignalingMessage::new("transaction") };
+        assert_eq!(alice.on_before_send(&mut message), Verdict::Pass);
+        assert!(message.transaction.is_none() && message.sealed.is_some());
+        message.from_peer = Some("alice".to_string());
+        message
+    }
+
+    fn opened(bob: &mut GroupEncryption, mut message: SignalingMessage) -> Result<String, Verdict> {
+        match bob.on_receive(&mut message) {
+            Verdict::Pass => Ok(message.transaction.expect("opened").id),
+            dropped => Err(dropped),
+        }
+    }
+
+    #[test]
+    fn round_trips_for_every_member() {
+        let (mut alice, mut bob) = room();
+        let message = broadcast(&mut alice, "tx-1");
+        assert_eq!(opened(&mut bob, message), Ok("tx-1".to_string()));
+
+        let mut reply =
+            SignalingMessage { transaction: Some(transaction("tx-2")), ..SignalingMessage::new("transaction") };
+        assert_eq!(bob.on_before_send(&mut reply), Verdict::Pass);
+        reply.from_peer = Some("bob".to_string());
+        assert_eq!(opened(&mut alice, reply), Ok("tx-2".to_string()));
+    }
+
+    #[test]
+    fn opens_out_of_order_across_a_rekey() {
+        let (mut alice, mut bob) = room();
+        let first = broadcast(&mut alice, "tx-1");
+        let second = broadcast(&mut alice, "tx-2");
+
+        // Someone joins: alice moves to a new sender key, handed to bob
+        alice.on_receive(&mut SignalingMessage::new("peer-joined"));
+        relay(&mut alice, &mut bob);
+        let third = broadcast(&mut alice, "tx-3");
+        assert_ne!(third.epoch, first.epoch);
+
+        assert_eq!(opened(&mut bob, third), Ok("tx-3".to_string()));
+        assert_eq!(opened(&mut bob, second), Ok("tx-2".to_string()));
+        assert_eq!(opened(&mut bob, first), Ok("tx-1".to_string()));
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertext() {
+        let (mut alice, mut bob) = room();
+        let message = broadcast(&mut alice, "tx-1");
+        let mut bytes = STANDARD.decode(message.sealed.as_deref().unwrap()).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = SignalingMessage { sealed: Some(STANDARD.encode(bytes)), ..message.clone() };
+        assert!(opened(&mut bob, tampered).is_err());
+
+        // Another member's name is bound to the ciphertext too
+        let spoofed = SignalingMessage { from_peer: Some("mallory".to_string()), ..message.clone() };
+        assert!(opened(&mut bob, spoofed).is_err());
+
+        assert_eq!(opened(&mut bob, message), Ok("tx-1".to_string()));
+    }
+}
@@ -0,0 +1,660 @@
+use std::collections::{HashMap, HashSet};
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signer, SigningKey};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use super::{Decryption, PeerChange, Plugin, Verdict};
+use crate::{verify_signature, SignalingMessage, Transaction};
+
+/// Carries one side's key agreement key to the other, relayed
+/// point-to-point.
+pub const KEY_EXCHANGE_TYPE: &str = "key-exchange";
+
+/// Our key agreement key is replaced after sealing this many transactions,
+/// or after `ROTATE_AFTER_MINUTES`, whichever comes first, unless
+/// `PeerEncryption::with_rotation` says otherwise.
+pub const ROTATE_AFTER_MESSAGES: u32 = 1_000;
+pub const ROTATE_AFTER_MINUTES: i64 = 60;
+
+/// What an endpoint's identity key signs to vouch for its exchange key.
+/// Earlier clients signed v1 and didn't ratchet, so their keys aren't taken.
+const KEY_CONTEXT: &str = "tx-core key-exchange v2";
+/// HKDF info for a session's root key, followed by both endpoint ids in order.
+const KDF_INFO: &str = "tx-core peer session v2";
+/// HKDF info for one direction's chain, followed by sender and recipient.
+const CHAIN_INFO: &str = "tx-core peer chain v2";
+/// HKDF info for a step along a chain: a message key, then the next chain key.
+const STEP_INFO: &str = "tx-core peer chain step v2";
+/// Binds ciphertexts to this use, followed by sender, recipient and index.
+const AAD: &str = "tx-core peer-sealed transaction v2";
+const NONCE_LEN: usize = 12;
+/// Message keys kept per session for indexes skipped on the way, for
+/// transactions that arrive late; older ones are forgotten first.
+const MAX_SKIPPED: u32 = 100;
+
+/// Checks a peer's identity key; see `PeerEncryption::with_trust`.
+type Trust = Box<dyn Fn(&str, &str) -> bool + Send>;
+/// Saves the ratchet; see `PeerEncryption::persist_with`.
+type Persist = Box<dyn FnMut(&RatchetState) + Send>;
+/// See `PeerEncryption::log_decryptions`.
+type Log = Box<dyn FnMut(&Decryption) + Send>;
+
+/// One direction of a session. Every step hands out a message key and
+/// replaces the chain key, so the keys of transactions already sealed or
+/// opened can't be derived from what's kept.
+#[derive(Clone, Serialize, Deserialize)]
+struct Chain {
+    key: [u8; 32],
+    /// Of the next message.
+    index: u32,
+}
+
+impl Chain {
+    fn step(&mut self) -> Result<(u32, [u8; 32]), String> {
+        let mut okm = [0u8; 64];
+        Hkdf::<Sha256>::from_prk(&self.key)
+            .map_err(|_| "invalid chain key".to_string())?
+            .expand(STEP_INFO.as_bytes(), &mut okm)
+            .map_err(|_| "key derivation failed".to_string())?;
+        let mut message_key = [0u8; 32];
+        message_key.copy_from_slice(&okm[..32]);
+        self.key.copy_from_slice(&okm[32..]);
+        let index = self.index;
+        self.index += 1;
+        Ok((index, message_key))
+    }
+}
+
+/// The keys shared with one peer under a pair of exchange keys, with an
+/// id so either side can tell which a ciphertext is under.
+#[derive(Clone, Serialize, Deserialize)]
+struct SessionKey {
+    id: String,
+    sending: Chain,
+    receiving: Chain,
+    /// Message keys of indexes `receiving` stepped past unopened.
+    skipped: HashMap<u32, [u8; 32]>,
+}
+
+impl SessionKey {
+    /// The key the peer sealed message `index` under. Each is handed out
+    /// once, so a replayed ciphertext can't be opened.
+    fn message_key(&mut self, index: u32) -> Result<[u8; 32], String> {
+        if index < self.receiving.index {
+            return self
+                .skipped
+                .remove(&index)
+                .ok_or_else(|| format!("message {} was opened already, or is too old", index));
+        }
+        if index - self.receiving.index > MAX_SKIPPED {
+            return Err(format!("message {} is too far ahead", index));
+        }
+        let key = loop {
+            let (next, key) = self.receiving.step()?;
+            if next == index {
+                break key;
+            }
+            self.skipped.insert(next, key);
+        };
+        while self.skipped.len() > MAX_SKIPPED as usize {
+            let Some(oldest) = self.skipped.keys().min().copied() else {
+                break;
+            };
+            self.skipped.remove(&oldest);
+        }
+        Ok(key)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Session {
+    /// The peer's exchange key, as last announced.
+    their_key: [u8; 32],
+    current: SessionKey,
+    /// Kept across one rotation, for what was sealed before it.
+    previous: Option<SessionKey>,
+}
+
+/// What `PeerEncryption` needs to carry on after a reload: our exchange
+/// key and how far each session's chains have stepped. It holds secrets,
+/// so keep it where the identity key is kept.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RatchetState {
+    endpoint_id: String,
+    secret: [u8; 32],
+    rotated_at: DateTime<Utc>,
+    sealed_since_rotation: u32,
+    sessions: HashMap<String, Session>,
+}
+
+/// End-to-end encryption of transactions with a key per pair of peers,
+/// so a transaction sealed for its recipient can't be read by the
+/// signaling server or by anyone else in the room.
+///
+/// On meeting a peer, each side sends a `key-exchange` carrying an X25519
+/// public key, signed with its ed25519 identity key, the one it signs
+/// transactions with. Both derive the same session key from the X25519
+/// agreement through HKDF-SHA256, and transactions to that peer travel as
+/// ChaCha20-Poly1305 `sealed` ciphertext, tagged with the session key's
+/// id and the transaction's index.
+///
+/// Each direction of a session is a chain: every transaction is sealed
+/// under a message key of its own, and the chain key steps forward past
+/// it, so a key taken from an endpoint opens nothing it had already sent
+/// or received. Each side also replaces its exchange key now and then and
+/// announces the new one, which starts fresh chains no earlier key leads
+/// to; the session key before it is kept for what was already in flight.
+/// If both sides rotate at once, a transaction sealed in between can't be
+/// opened and is dropped, as is one that arrives twice.
+///
+/// Sessions outlast reconnects: a peer that leaves keeps its session until
+/// our next rotation, so both sides carry on where they were if it comes
+/// back, and `persist_with` and `restore` carry them across reloads.
+///
+/// Peers without a session, older clients among them, get transactions
+/// in the clear unless `require_encryption` is set. Rooms the server
+/// settles in batches are sent broadcasts in the clear too, since the
+/// server has to read them. Sealed strings carry a `:` after the key id,
+/// so a room-key `Encryption` registered alongside leaves them alone.
+pub struct PeerEncryption {
+    endpoint_id: String,
+    identity: SigningKey,
+    trust: Trust,
+    require: bool,
+    secret: StaticSecret,
+    public: PublicKey,
+    rotate_after_messages: u32,
+    rotate_after: Duration,
+    rotated_at: DateTime<Utc>,
+    sealed_since_rotation: u32,
+    sessions: HashMap<String, Session>,
+    /// Peers with a session that aren't in the room, until they're back.
+    departed: HashSet<String>,
+    /// Peers sent our current exchange key.
+    announced: HashSet<String>,
+    /// The room settles in batches, from its `room-joined`.
+    batched: bool,
+    persist: Option<Persist>,
+    log: Option<Log>,
+    outgoing: Vec<SignalingMessage>,
+}
+
+impl PeerEncryption {
+    /// `identity` is the endpoint's transaction signing key.
+    pub fn new(endpoint_id: &str, identity: SigningKey) -> Self {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        PeerEncryption {
+            endpoint_id: endpoint_id.to_string(),
+            identity,
+            trust: Box::new(|_, _| true),
+            require: false,
+            public: PublicKey::from(&secret),
+            secret,
+            rotate_after_messages: ROTATE_AFTER_MESSAGES,
+            rotate_after: Duration::minutes(ROTATE_AFTER_MINUTES),
+            rotated_at: Utc::now(),
+            sealed_since_rotation: 0,
+            sessions: HashMap::new(),
+            departed: HashSet::new(),
+            announced: HashSet::new(),
+            batched: false,
+            persist: None,
+            log: None,
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// `trust(peer, public_key_hex)` decides whether an identity key is
+    /// really the peer's, e.g. against the key pinned for it. By default
+    /// any key whose signature checks out is taken.
+    pub fn with_trust(mut self, trust: impl Fn(&str, &str) -> bool + Send + 'static) -> Self {
+        self.trust = Box::new(trust);
+        self
+    }
+
+    /// Drop transactions to peers we have no session with, rather than
+    /// sending them in the clear.
+    pub fn require_encryption(mut self) -> Self {
+        self.require = true;
+        self
+    }
+
+    /// Replace our exchange key after sealing `messages` transactions or
+    /// once it is `every` old, whichever comes first.
+    pub fn with_rotation(mut self, messages: u32, every: Duration) -> Self {
+        self.rotate_after_messages = messages;
+        self.rotate_after = every;
+        self
+    }
+
+    /// Carry on from a state `persist_with` saved: our exchange key, and
+    /// the sessions of peers that kept theirs. Another endpoint's is
+    /// ignored.
+    pub fn restore(mut self, state: RatchetState) -> Self {
+        if state.endpoint_id != self.endpoint_id {
+            return self;
+        }
+        self.secret = StaticSecret::from(state.secret);
+        self.public = PublicKey::from(&self.secret);
+        self.rotated_at = state.rotated_at;
+        self.sealed_since_rotation = state.sealed_since_rotation;
+        // Until the room says they're here
+        self.departed = state.sessions.keys().cloned().collect();
+        self.sessions = state.sessions;
+        self
+    }
+
+    /// `save` gets the whole state whenever it changes, i.e. with every
+    /// transaction sealed or opened.
+    pub fn persist_with(mut self, save: impl FnMut(&RatchetState) + Send + 'static) -> Self {
+        self.persist = Some(Box::new(save));
+        self
+    }
+
+    /// `log` hears of every sealed transaction we open, or fail to, e.g.
+    /// to keep a record of what our keys were used for.
+    pub fn log_decryptions(mut self, log: impl FnMut(&Decryption) + Send + 'static) -> Self {
+        self.log = Some(Box::new(log));
+        self
+    }
+
+    fn persist(&mut self) {
+        if self.persist.is_none() {
+            return;
+        }
+        let state = RatchetState {
+            endpoint_id: self.endpoint_id.clone(),
+            secret: self.secret.to_bytes(),
+            rotated_at: self.rotated_at,
+            sealed_since_rotation: self.sealed_since_rotation,
+            sessions: self.sessions.clone(),
+        };
+        if let Some(save) = self.persist.as_mut() {
+            save(&state);
+        }
+    }
+
+    /// Our current exchange key to `peer`, signed.
+    fn announce(&mut self, peer: &str) {
+        let exchange_key = STANDARD.encode(self.public.as_bytes());
+        let signed = format!("{}\n{}\n{}\n{}", KEY_CONTEXT, self.endpoint_id, peer, exchange_key);
+        self.outgoing.push(SignalingMessage {
+            peer_id: Some(self.endpoint_id.clone()),
+            target_peer: Some(peer.to_string()),
+            exchange_key: Some(exchange_key),
+            public_key: Some(hex::encode(self.identity.verifying_key().to_bytes())),
+            signature: Some(hex::encode(self.identity.sign(signed.as_bytes()).to_bytes())),
+            ..SignalingMessage::new(KEY_EXCHANGE_TYPE)
+        });
+        self.announced.insert(peer.to_string());
+    }
+
+    fn derive(&self, their_key: &PublicKey, peer: &str) -> Result<SessionKey, String> {
+        let shared = self.secret.diffie_hellman(their_key);
+        if !shared.was_contributory() {
+            return Err(format!("weak exchange key from {}", peer));
+        }
+        let (first, second) = if self.endpoint_id.as_str() < peer {
+            (self.endpoint_id.as_str(), peer)
+        } else {
+            (peer, self.endpoint_id.as_str())
+        };
+        let info = format!("{}\n{}\n{}", KDF_INFO, first, second);
+        let mut root = [0u8; 32];
+        Hkdf::<Sha256>::new(None, shared.as_bytes())
+            .expand(info.as_bytes(), &mut root)
+            .map_err(|_| "key derivation failed".to_string())?;
+
+        let chain = |from: &str, to: &str| {
+            let info = format!("{}\n{}\n{}", CHAIN_INFO, from, to);
+            let mut key = [0u8; 32];
+            Hkdf::<Sha256>::new(None, &root)
+                .expand(info.as_bytes(), &mut key)
+                .map_err(|_| "key derivation failed".to_string())?;
+            Ok::<_, String>(Chain { key, index: 0 })
+        };
+        Ok(SessionKey {
+            id: hex::encode(&Sha256::digest(root)[..8]),
+            sending: chain(&self.endpoint_id, peer)?,
+            receiving: chain(peer, &self.endpoint_id)?,
+            skipped: HashMap::new(),
+        })
+    }
+
+    /// A new exchange key once the current one is old or used enough,
+    /// announced to every peer we share a session with. Sessions of peers
+    /// that left end here, as they aren't around to take it.
+    fn rotate_if_due(&mut self) {
+        let due = self.sealed_since_rotation >= self.rotate_after_messages
+            || Utc::now() - self.rotated_at >= self.rotate_after;
+        if !due {
+            return;
+        }
+        let departed = std::mem::take(&mut self.departed);
+        self.sessions.retain(|peer, _| !departed.contains(peer));
+        if self.sessions.is_empty() {
+            return;
+        }
+        self.secret = StaticSecret::random_from_rng(OsRng);
+        self.public = PublicKey::from(&self.secret);
+        self.rotated_at = Utc::now();
+        self.sealed_since_rotation = 0;
+        self.announced.clear();
+
+        let peers: Vec<String> = self.sessions.keys().cloned().collect();
+        for peer in peers {
+            let their_key = PublicKey::from(self.sessions[&peer].their_key);
+            match self.derive(&their_key, &peer) {
+                Ok(key) => {
+                    let session = self.sessions.get_mut(&peer).expect("listed above");
+                    session.previous = Some(std::mem::replace(&mut session.current, key));
+                    self.announce(&peer);
+                }
+                Err(_) => {
+                    self.sessions.remove(&peer);
+                }
+            }
+        }
+        self.persist();
+    }
+
+    /// Takes a peer's `key-exchange`, answering with ours if it hasn't
+    /// had it.
+    fn accept_key(&mut self, message: &SignalingMessage) -> Result<String, String> {
+        let peer = message.from_peer.clone().ok_or("key-exchange without a sender")?;
+        let (Some(exchange_key), Some(public_key), Some(signature)) =
+            (&message.exchange_key, &message.public_key, &message.signature)
+        else {
+            return Err(format!("incomplete key-exchange from {}", peer));
+        };
+        let signed = format!("{}\n{}\n{}\n{}", KEY_CONTEXT, peer, self.endpoint_id, exchange_key);
+        if !verify_signature(public_key, signed.as_bytes(), signature) || !(self.trust)(&peer, public_key) {
+            return Err(format!("key-exchange from {} isn't signed by its key", peer));
+        }
+        let bytes: [u8; 32] = STANDARD
+            .decode(exchange_key)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| format!("invalid exchange key from {}", peer))?;
+        let their_key = PublicKey::from(bytes);
+
+        if self.sessions.get(&peer).map(|session| session.their_key) != Some(bytes) {
+            let key = self.derive(&their_key, &peer)?;
+            let previous = self.sessions.remove(&peer).map(|session| session.current);
+            self.sessions.insert(peer.clone(), Session { their_key: bytes, current: key, previous });
+            self.persist();
+        }
+        if !self.announced.contains(&peer) {
+            self.announce(&peer);
+        }
+        Ok(peer)
+    }
+
+    fn seal(&mut self, tx: &Transaction, peer: &str) -> Result<Option<String>, String> {
+        self.rotate_if_due();
+        let Some(session) = self.sessions.get_mut(peer) else {
+            return Ok(None);
+        };
+        let plaintext = serde_json::to_vec(tx).map_err(|e| e.to_string())?;
+        let (index, key) = session.current.sending.step()?;
+        let aad = format!("{}\n{}\n{}\n{}", AAD, self.endpoint_id, peer, index);
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: aad.as_bytes() })
+            .map_err(|_| "encryption failed".to_string())?;
+        let body = STANDARD.encode([nonce.as_slice(), &ciphertext].concat());
+        let sealed = format!("{}:{}:{}", session.current.id, index, body);
+        self.sealed_since_rotation += 1;
+        self.persist();
+        Ok(Some(sealed))
+    }
+
+    fn open(&mut self, sealed: &str, peer: &str) -> Result<Transaction, String> {
+        let mut parts = sealed.splitn(3, ':');
+        let (Some(key_id), Some(Ok(index)), Some(body)) =
+            (parts.next(), parts.next().map(str::parse::<u32>), parts.next())
+        else {
+            return Err(format!("sealed by {} in an unknown format", peer));
+        };
+        let bytes = STANDARD.decode(body).map_err(|e| format!("invalid sealed transaction: {}", e))?;
+        if bytes.len() < NONCE_LEN {
+            return Err("sealed transaction too short".to_string());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+
+        let session = self.sessions.get_mut(peer).ok_or_else(|| format!("no key shared with {}", peer))?;
+        let session_key = [Some(&mut session.current), session.previous.as_mut()]
+            .into_iter()
+            .flatten()
+            .find(|key| key.id == key_id)
+            .ok_or_else(|| format!("sealed by {} for another peer, or under a retired key", peer))?;
+        // Stepped on a copy, kept only if the ciphertext is genuine
+        let mut stepped = session_key.clone();
+        let key = stepped.message_key(index)?;
+        let aad = format!("{}\n{}\n{}\n{}", AAD, peer, self.endpoint_id, index);
+        let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: aad.as_bytes() })
+            .map_err(|_| format!("sealed transaction from {} was tampered with", peer))?;
+        *session_key = stepped;
+        self.persist();
+        serde_json::from_slice(&plaintext).map_err(|e| format!("invalid sealed transaction: {}", e))
+    }
+}
+
+impl Plugin for PeerEncryption {
+    fn name(&self) -> &str {
+        "peer-encryption"
+    }
+
+    fn on_before_send(&mut self, message: &mut SignalingMessage) -> Verdict {
+        let Some(tx) = &message.transaction else {
+            return Verdict::Pass;
+        };
+        if self.batched && message.message_type == "transaction" {
+            return Verdict::Pass;
+        }
+        let peer = message.target_peer.clone().unwrap_or_else(|| tx.to_endpoint.clone());
+        if peer == self.endpoint_id {
+            return Verdict::Pass;
+        }
+        match self.seal(tx, &peer) {
+            Ok(Some(sealed)) => {
+                message.transaction = None;
+                message.sealed = Some(sealed);
+                Verdict::Pass
+            }
+            Ok(None) if self.require => Verdict::Drop(format!("no key shared with {} yet", peer)),
+            Ok(None) => Verdict::Pass,
+            Err(e) => Verdict::Drop(e),
+        }
+    }
+
+    fn on_receive(&mut self, message: &mut SignalingMessage) -> Verdict {
+        match message.message_type.as_str() {
+            "room-joined" => self.batched = message.batch_window_ms.is_some(),
+            KEY_EXCHANGE_TYPE => {
+                // Ours alone; nothing for the application
+                return match self.accept_key(message) {
+                    Ok(peer) => Verdict::Drop(format!("took the exchange key from {}", peer)),
+                    Err(e) => Verdict::Drop(e),
+                };
+            }
+            _ => {}
+        }
+
+        // Room-key ciphertexts have no key id
+        if !message.sealed.as_deref().is_some_and(|sealed| sealed.contains(':')) {
+            return Verdict::Pass;
+        }
+        let Some(peer) = message.from_peer.clone() else {
+            return Verdict::Drop("sealed transaction without a sender".to_string());
+        };
+        if peer == self.endpoint_id {
+            return Verdict::Drop("our own transaction, sealed for its recipient".to_string());
+        }
+        let sealed = message.sealed.take().unwrap_or_default();
+        let opened = self.open(&sealed, &peer);
+        if self.log.is_some() {
+            let key = sealed.split(':').next().unwrap_or_default().to_string();
+            let decryption = Decryption::new(self.name(), &peer, key, &opened);
+            if let Some(log) = self.log.as_mut() {
+                log(&decryption);
+            }
+        }
+        match opened {
+            Ok(tx) => {
+                message.transaction = Some(tx);
+                Verdict::Pass
+            }
+            Err(e) => Verdict::Drop(e),
+        }
+    }
+
+    fn on_peer_change(&mut self, change: &PeerChange) {
+        match change {
+            PeerChange::Joined { peer_id, .. } if *peer_id != self.endpoint_id => {
+                self.departed.remove(peer_id);
+                self.announce(peer_id);
+            }
+            PeerChange::Joined { .. } => {}
+            PeerChange::Left { peer_id } => {
+                // Kept for its return, until our next rotation
+                if self.sessions.contains_key(peer_id) {
+                    self.departed.insert(peer_id.clone());
+                }
+                self.announced.remove(peer_id);
+            }
+        }
+    }
+
+    fn take_outgoing(&mut self) -> Vec<SignalingMessage> {
+        std::mem::take(&mut self.outgoing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Capabilities, Money, TransactionStatus};
+
+    fn endpoint(id: &str, seed: u8) -> PeerEncryption {
+        PeerEncryption::new(id, SigningKey::from_bytes(&[seed; 32]))
+    }
+
+    fn transaction(id: &str) -> Transaction {
+        Transaction {
+            id: id.to_string(),
+            from_endpoint: "alice".to_string(),
+            to_endpoint: "bob".to_string(),
+            amount: Money::new(1_500_000, crate::NATIVE_ASSET),
+            timestamp: Utc::now(),
+            signature: String::new(),
+            status: TransactionStatus::Pending,
+            kind: Default::default(),
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: None,
+            public_key: None,
+        }
+    }
+
+    /// Relays what each side queued to the other until neither has more.
+    fn relay(alice: &mut PeerEncryption, bob: &mut PeerEncryption) {
+        loop {
+            let (to_bob, to_alice) = (alice.take_outgoing(), bob.take_outgoing());
+            if to_bob.is_empty() && to_alice.is_empty() {
+                break;
+            }
+            for mut message in to_bob {
+                message.from_peer = Some("alice".to_string());
+                bob.on_receive(&mut message);
+            }
+            for mut message in to_alice {
+                message.from_peer = Some("bob".to_string());
+                alice.on_receive(&mut message);
+            }
+        }
+    }
+
+    /// Both sides, after exchanging keys.
+    fn session() -> (PeerEncryption, PeerEncryption) {
+        let (mut alice, mut bob) = (endpoint("alice", 1), endpoint("bob", 2));
+        alice.on_peer_change(&PeerChange::Joined { peer_id: "bob".to_string(), capabilities: Capabilities::default() });
+        relay(&mut alice, &mut bob);
+        (alice, bob)
+    }
+
+    fn sealed(alice: &mut PeerEncryption, id: &str) -> SignalingMessage {
+        let mut message = SignalingMessage {
+            transaction: Some(transaction(id)),
+            target_peer: Some("bob".to_string()),
+            ..SignalingMessage::new("transaction")
+        };
+        assert_eq!(alice.on_before_send(&mut message), Verdict::Pass);
+        assert!(message.transaction.is_none() && message.sealed.is_some());
+        message.from_peer = Some("alice".to_string());
+        message
+    }
+
+    fn opened(bob: &mut PeerEncryption, mut message: SignalingMessage) -> Result<String, Verdict> {
+        match bob.on_receive(&mut message) {
+            Verdict::Pass => Ok(message.transaction.expect("opened").id),
+            dropped => Err(dropped),
+        }
+    }
+
+    #[test]
+    fn round_trips_for_the_recipient() {
+        let (mut alice, mut bob) = session();
+        for id in ["tx-1", "tx-2"] {
+            let message = sealed(&mut alice, id);
+            assert_eq!(opened(&mut bob, message), Ok(id.to_string()));
+        }
+    }
+
+    #[test]
+    fn opens_out_of_order_but_only_once() {
+        let (mut alice, mut bob) = session();
+        let messages: Vec<_> = ["tx-1", "tx-2", "tx-3"].iter().map(|id| sealed(&mut alice, id)).collect();
+        assert_eq!(opened(&mut bob, messages[2].clone()), Ok("tx-3".to_string()));
+        assert_eq!(opened(&mut bob, messages[0].clone()), Ok("tx-1".to_string()));
+        assert_eq!(opened(&mut bob, messages[1].clone()), Ok("tx-2".to_string()));
+        assert!(opened(&mut bob, messages[1].clone()).is_err());
+    }
+
+    #[test]
+    fn rejects_tampered_ciphertext() {
+        let (mut alice, mut bob) = session();
+        let message = sealed(&mut alice, "tx-1");
+        let sealed_body = message.sealed.clone().unwrap();
+        let (prefix, body) = sealed_body.rsplit_once(':').unwrap();
+        let mut bytes = STANDARD.decode(body).unwrap();
+        *bytes.last_mut().unwrap() ^= 1;
+        let tampered = SignalingMessage {
+            sealed: Some(format!("{}:{}", prefix, STANDARD.encode(bytes))),
+            ..message.clone()
+        };
+        assert!(opened(&mut bob, tampered).is_err());
+
+        // Under another index, the ciphertext no longer matches its data
+        let (key_id, index) = prefix.split_once(':').unwrap();
+        let moved_index = index.parse::<u32>().unwrap() + 1;
+        let moved = SignalingMessage {
+            sealed: Some(format!("{}:{}:{}", key_id, moved_index, body)),
+            ..message.clone()
+        };
+        assert!(opened(&mut bob, moved).is_err());
+
+        // The failures didn't use up the genuine one's key
+        assert_eq!(opened(&mut bob, message), Ok("tx-1".to_string()));
+    }
+}
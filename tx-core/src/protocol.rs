@@ -0,0 +1,140 @@
+//! Signaling protocol versions and capabilities.
+//!
+//! A client opens with `hello`, giving the highest protocol version it
+//! speaks and the capabilities it supports. The server answers
+//! `negotiated` with the version both speak and the capabilities it
+//! honours itself, and tells room members what each other advertised
+//! (`features` on `peer-joined`, `peerFeatures` on `room-joined`).
+//! Clients that never say hello speak version 1 and support none of them.
+//!
+//! Message types newer than version 1 are gated on a capability: check
+//! the peer's (or, for `Batching`, the server's) before relying on it.
+
+use std::collections::HashSet;
+
+use crate::SignalingMessage;
+
+/// Version of the signaling protocol in this crate.
+///
+/// 1: no handshake; every peer is assumed to speak the full message set.
+/// 2: `hello`/`negotiated`, with capabilities.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest version servers still accept a `hello` for.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Reads CBOR and MessagePack data channel frames after a
+    /// `codec-hello`.
+    BinaryCodec,
+    /// Sends and understands `transaction-ack`.
+    Acks,
+    /// Knows that rooms with a `batchWindowMs` are settled by the server,
+    /// and doesn't submit their transactions itself.
+    Batching,
+    /// Reads `transaction-batch` data channel messages.
+    TransactionBatches,
+    /// Reads `transaction-gossip` data channel messages and forwards them.
+    Gossip,
+    /// Forwards `relay-to` envelopes toward their recipient and
+    /// `relay-receipt`s back to their sender.
+    Relay,
+    /// Exchanges `sender-key`s and reads group-sealed broadcasts; required
+    /// to join an encrypted room.
+    GroupEncryption,
+    /// Its signaling socket was upgraded with permessage-deflate. Offered
+    /// by clients whose socket took the extension, and honoured by servers
+    /// that compress, so both sides know the connection is compressed.
+    PermessageDeflate,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 8] = [
+        Capability::BinaryCodec,
+        Capability::Acks,
+        Capability::Batching,
+        Capability::TransactionBatches,
+        Capability::Gossip,
+        Capability::Relay,
+        Capability::GroupEncryption,
+        Capability::PermessageDeflate,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Capability::BinaryCodec => "binary-codec",
+            Capability::Acks => "acks",
+            Capability::Batching => "batching",
+            Capability::TransactionBatches => "transaction-batch",
+            Capability::Gossip => "gossip",
+            Capability::Relay => "relay",
+            Capability::GroupEncryption => "group-encryption",
+            Capability::PermessageDeflate => "permessage-deflate",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Capability::ALL.into_iter().find(|capability| capability.as_str() == s)
+    }
+}
+
+/// What a peer advertised, or what was agreed with the server.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub protocol_version: u32,
+    features: HashSet<Capability>,
+}
+
+impl Default for Capabilities {
+    /// A peer that never said hello.
+    fn default() -> Self {
+        Capabilities { protocol_version: 1, features: HashSet::new() }
+    }
+}
+
+impl Capabilities {
+    pub fn new(protocol_version: u32, features: &[Capability]) -> Self {
+        Capabilities { protocol_version, features: features.iter().copied().collect() }
+    }
+
+    /// From wire names; ones this crate doesn't know are dropped.
+    pub fn from_names(protocol_version: u32, names: &[String]) -> Self {
+        Capabilities {
+            protocol_version,
+            features: names.iter().filter_map(|name| Capability::parse(name)).collect(),
+        }
+    }
+
+    /// From a `hello`, `negotiated` or `peer-joined` message.
+    pub fn from_message(message: &SignalingMessage) -> Self {
+        match &message.features {
+            Some(names) => Capabilities::from_names(message.protocol_version.unwrap_or(PROTOCOL_VERSION), names),
+            None => Capabilities::default(),
+        }
+    }
+
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.features.contains(&capability)
+    }
+
+    /// Wire names, in a stable order.
+    pub fn names(&self) -> Vec<String> {
+        Capability::ALL
+            .into_iter()
+            .filter(|capability| self.supports(*capability))
+            .map(|capability| capability.as_str().to_string())
+            .collect()
+    }
+}
+
+impl SignalingMessage {
+    /// Opens the handshake, offering `features` at this crate's version.
+    pub fn hello(features: &[Capability]) -> Self {
+        SignalingMessage {
+            protocol_version: Some(PROTOCOL_VERSION),
+            features: Some(Capabilities::new(PROTOCOL_VERSION, features).names()),
+            ..SignalingMessage::new("hello")
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{canonical_bytes, verify_signature, InclusionProof, Transaction, TransactionStatus};
+
+/// The gateway's signed statement that a transaction settled with
+/// `status`. It can be checked offline with the gateway's public key, and
+/// tied to a transaction by recomputing `transaction_hash`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Receipt {
+    pub transaction_id: String,
+    pub transaction_hash: String,
+    pub status: TransactionStatus,
+    pub issued_at: DateTime<Utc>,
+    pub signature: String,
+    /// The gateway's receipt key. Compare it with the one you trust, the
+    /// receipt only proves it was signed by whoever holds this one.
+    pub public_key: String,
+    /// Once a checkpoint of the gateway's event log covers the transaction.
+    /// The signature covers the checkpoint, the proof leads to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inclusion: Option<InclusionProof>,
+}
+
+/// Hex SHA-256 of the transaction's canonical signing bytes.
+pub fn transaction_hash(tx: &Transaction) -> String {
+    hex::encode(Sha256::digest(canonical_bytes(tx)))
+}
+
+impl Receipt {
+    /// The bytes the gateway signs: everything but the signature, the key
+    /// and the proof's path, which is checked against the signed root.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let mut signed = serde_json::json!({
+            "transaction_id": self.transaction_id,
+            "transaction_hash": self.transaction_hash,
+            "status": self.status,
+            "issued_at": self.issued_at.timestamp_millis(),
+        });
+        // Absent from receipts issued without one, so they still verify
+        if let Some(proof) = &self.inclusion {
+            signed["checkpoint"] = serde_json::json!({ "snapshot_id": proof.snapshot_id, "root": proof.root });
+        }
+        let mut bytes = b"receipt|".to_vec();
+        bytes.extend(serde_json::to_vec(&signed).unwrap_or_default());
+        bytes
+    }
+
+    pub fn verify(&self) -> bool {
+        verify_signature(&self.public_key, &self.signing_bytes(), &self.signature)
+    }
+
+    /// Whether this is a receipt for `tx` as it stands.
+    pub fn matches(&self, tx: &Transaction) -> bool {
+        self.transaction_id == tx.id && self.transaction_hash == transaction_hash(tx) && self.status == tx.status
+    }
+}
@@ -0,0 +1,105 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{verify_signature, Money, MoneyError, SwapLeg, SwapTerms};
+
+/// Which way the requester of a quote wants to trade the base asset.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// A request for quotes, broadcast to the room: `requester` wants to buy
+/// or sell `size` of `base_asset` against `quote_asset`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct QuoteRequest {
+    pub rfq_id: String,
+    pub requester: String,
+    pub side: Side,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub size: f64,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A peer's signed answer to a `QuoteRequest`: it will trade the
+/// requested size at `price` units of the quote asset per unit of base
+/// until `expires_at`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Quote {
+    pub quote_id: String,
+    pub request: QuoteRequest,
+    pub quoter: String,
+    pub price: f64,
+    pub expires_at: DateTime<Utc>,
+    pub signature: String,
+    pub public_key: String,
+}
+
+impl Quote {
+    /// The bytes the quoter signs: everything but the signature and key.
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        let request = &self.request;
+        let mut bytes = b"quote|".to_vec();
+        bytes.extend(
+            serde_json::to_vec(&serde_json::json!({
+                "quote_id": self.quote_id,
+                "rfq_id": request.rfq_id,
+                "requester": request.requester,
+                "side": request.side,
+                "base_asset": request.base_asset,
+                "quote_asset": request.quote_asset,
+                "size": request.size,
+                "quoter": self.quoter,
+                "price": self.price,
+                "expires_at": self.expires_at.timestamp_millis(),
+            }))
+            .unwrap_or_default(),
+        );
+        bytes
+    }
+
+    pub fn verify(&self) -> bool {
+        self.price > 0.0
+            && self.request.size > 0.0
+            && self.quoter != self.request.requester
+            && self.request.base_asset != self.request.quote_asset
+            && verify_signature(&self.public_key, &self.signing_bytes(), &self.signature)
+    }
+
+    /// The atomic swap accepting this quote creates. The requester is the
+    /// maker, the swap reuses the quote's id and lapses with the quote, so
+    /// the quoter can check an incoming offer against what it signed.
+    /// Fails if the size or the price don't make amounts.
+    pub fn swap_terms(&self) -> Result<SwapTerms, MoneyError> {
+        let request = &self.request;
+        let base = Money::from_major(request.size, &request.base_asset)?;
+        let quote = Money::from_major(request.size * self.price, &request.quote_asset)?;
+        let (give, ask) = match request.side {
+            Side::Buy => (quote, base),
+            Side::Sell => (base, quote),
+        };
+
+        Ok(SwapTerms {
+            swap_id: self.quote_id.clone(),
+            offer: SwapLeg {
+                from_endpoint: request.requester.clone(),
+                to_endpoint: self.quoter.clone(),
+                asset: give.currency().to_string(),
+                amount: give,
+            },
+            ask: SwapLeg {
+                from_endpoint: self.quoter.clone(),
+                to_endpoint: request.requester.clone(),
+                asset: ask.currency().to_string(),
+                amount: ask,
+            },
+            expires_at: self.expires_at,
+        })
+    }
+}
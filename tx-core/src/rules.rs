@@ -0,0 +1,186 @@
+//! Automation rules an endpoint runs on what it receives: "when I receive
+//! over 100 from alice, send her a thank-you memo", "accept swap offers
+//! costing me under 5 from trusted peers". A rule is plain data, stored
+//! and shared as JSON: one trigger, conditions that must all hold, and
+//! one action from a fixed set. Nothing in a rule is executed, so rules
+//! pasted from elsewhere can do no more than those actions allow.
+//!
+//! ```json
+//! {"name": "thanks", "when": "received",
+//!  "conditions": [{"type": "amount-over", "amount": 100}, {"type": "from", "peer": "alice"}],
+//!  "then": {"type": "send-memo", "text": "Thank you!"}}
+//! ```
+//!
+//! Amounts are in the native asset; a swap paid in anything else matches
+//! no amount condition.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::Money;
+
+/// Longest memo a rule may send.
+pub const MEMO_LIMIT: usize = 280;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trigger {
+    /// A transfer to us was applied.
+    Received,
+    /// A peer offered us a swap.
+    SwapOffered,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Condition {
+    AmountOver {
+        #[serde(with = "crate::money::as_major")]
+        amount: Money,
+    },
+    AmountUnder {
+        #[serde(with = "crate::money::as_major")]
+        amount: Money,
+    },
+    From { peer: String },
+    /// The peer is on our allowlist.
+    Trusted,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum Action {
+    /// A `memo` to the peer.
+    SendMemo { text: String },
+    /// Only on `swap-offered`.
+    Accept,
+    /// Only on `swap-offered`.
+    Decline,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub name: String,
+    #[serde(default = "enabled")]
+    pub enabled: bool,
+    pub when: Trigger,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    pub then: Action,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuleError {
+    MissingName,
+    InvalidAmount(String),
+    InvalidMemo(String),
+    /// The action doesn't apply to the trigger.
+    Mismatch { when: Trigger, then: &'static str },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::MissingName => write!(f, "Rule needs a name"),
+            RuleError::InvalidAmount(amount) => write!(f, "Invalid amount: {}", amount),
+            RuleError::InvalidMemo(reason) => write!(f, "Invalid memo: {}", reason),
+            RuleError::Mismatch { when, then } => write!(f, "Can't {} on {:?}", then, when),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+/// Something that happened, for rules to match.
+#[derive(Clone, Copy, Debug)]
+pub struct Event<'a> {
+    pub trigger: Trigger,
+    /// The other party.
+    pub peer: &'a str,
+    /// What the transfer paid us, or what the swap would cost us, in
+    /// whatever asset it was.
+    pub amount: &'a Money,
+}
+
+impl Rule {
+    pub fn validate(&self) -> Result<(), RuleError> {
+        if self.name.trim().is_empty() {
+            return Err(RuleError::MissingName);
+        }
+        for condition in &self.conditions {
+            if let Condition::AmountOver { amount } | Condition::AmountUnder { amount } = condition {
+                if amount.is_negative() {
+                    return Err(RuleError::InvalidAmount(amount.to_string()));
+                }
+            }
+        }
+        match (&self.then, self.when) {
+            (Action::SendMemo { text }, _) if text.trim().is_empty() => {
+                Err(RuleError::InvalidMemo("empty".to_string()))
+            }
+            (Action::SendMemo { text }, _) if text.chars().count() > MEMO_LIMIT => {
+                Err(RuleError::InvalidMemo(format!("over {} characters", MEMO_LIMIT)))
+            }
+            (Action::Accept, Trigger::Received) => Err(RuleError::Mismatch { when: self.when, then: "accept" }),
+            (Action::Decline, Trigger::Received) => Err(RuleError::Mismatch { when: self.when, then: "decline" }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the rule fires on `event`; `trusted` says whether a peer is
+    /// on our allowlist.
+    pub fn matches(&self, event: &Event, trusted: impl Fn(&str) -> bool) -> bool {
+        self.enabled
+            && self.when == event.trigger
+            && self.conditions.iter().all(|condition| match condition {
+                Condition::AmountOver { amount } => {
+                    event.amount.currency() == amount.currency() && event.amount.minor() > amount.minor()
+                }
+                Condition::AmountUnder { amount } => {
+                    event.amount.currency() == amount.currency() && event.amount.minor() < amount.minor()
+                }
+                Condition::From { peer } => event.peer == peer,
+                Condition::Trusted => trusted(event.peer),
+            })
+    }
+}
+
+/// Accepting or declining a swap offer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Accept,
+    Decline,
+}
+
+/// What the rules that fired on an event ask for.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Outcome {
+    /// From the first rule that decides; later ones are ignored.
+    pub decision: Option<Decision>,
+    /// Every memo, in rule order.
+    pub memos: Vec<String>,
+    /// Names of the rules that fired.
+    pub fired: Vec<String>,
+}
+
+/// Runs `rules` over `event`. Rules that don't validate never fire.
+pub fn evaluate(rules: &[Rule], event: &Event, trusted: impl Fn(&str) -> bool) -> Outcome {
+    let mut outcome = Outcome::default();
+    for rule in rules {
+        if rule.validate().is_err() || !rule.matches(event, &trusted) {
+            continue;
+        }
+        match &rule.then {
+            Action::SendMemo { text } => outcome.memos.push(text.clone()),
+            Action::Accept if outcome.decision.is_none() => outcome.decision = Some(Decision::Accept),
+            Action::Decline if outcome.decision.is_none() => outcome.decision = Some(Decision::Decline),
+            Action::Accept | Action::Decline => continue,
+        }
+        outcome.fired.push(rule.name.clone());
+    }
+    outcome
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::{ChannelUpdate, Quote, QuoteRequest, SwapCommitment, Transaction, SCHEMA_VERSION};
+
+/// A message on the signaling channel. One struct covers every message
+/// type; only the fields relevant to `message_type` are set. Field names
+/// follow the signaling server's camelCase wire format.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct SignalingMessage {
+    #[serde(rename = "type")]
+    pub message_type: String,
+    /// Schema the sender speaks; absent on schema 1 messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub schema_version: Option<u32>,
+    /// Signaling protocol version: the highest the sender speaks on
+    /// `hello`, the agreed one on `negotiated` (see `protocol`). Distinct
+    /// from `schema_version`, which covers the shape of the fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protocol_version: Option<u32>,
+    /// Capability names: offered on `hello`, honoured by the server on
+    /// `negotiated`, the joining peer's on `peer-joined`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<Vec<String>>,
+    /// Each present peer's capability names, on `room-joined`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peer_features: Option<HashMap<String, Vec<String>>>,
+    pub room_id: Option<String>,
+    pub peer_id: Option<String>,
+    pub target_peer: Option<String>,
+    /// Set by the server on relayed messages.
+    #[serde(default)]
+    pub from_peer: Option<String>,
+    pub transaction: Option<Transaction>,
+    /// Several transactions to one peer, on a `transaction-batch`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transactions: Option<Vec<Transaction>>,
+    /// `transaction`, encrypted for the room or for its recipient, in its
+    /// place; see `plugin::Encryption`, `plugin::PeerEncryption` and
+    /// `plugin::GroupEncryption`. On a `sender-key`, the sender key wrapped
+    /// for its recipient.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sealed: Option<String>,
+    /// Which of its sender's keys a group-sealed broadcast is under, or a
+    /// `sender-key` carries; see `plugin::GroupEncryption`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub epoch: Option<u64>,
+    /// Asked for on `join` to open the room end-to-end encrypted; on
+    /// `room-joined`, whether it is. The server relays only sealed
+    /// broadcasts in such a room.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encrypted: Option<bool>,
+    /// An X25519 public key, base64, on `key-exchange` and `sender-key`;
+    /// see `plugin::PeerEncryption` and `plugin::GroupEncryption`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exchange_key: Option<String>,
+    /// Hops a `transaction-gossip` or `relay-to` may still be forwarded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u8>,
+    /// Final recipient of a `relay-to`, or the sender a `relay-receipt`
+    /// returns to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub destination: Option<String>,
+    /// Peers a `relay-to` has passed through, its sender first; on a
+    /// `relay-receipt`, the whole path, which it retraces.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub route: Option<Vec<String>>,
+    /// The message a `relay-to` carries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "ts", ts(type = "SignalingMessage | null"))]
+    pub payload: Option<Box<SignalingMessage>>,
+    pub peers: Option<Vec<String>>,
+    /// Open rooms, on a `rooms` reply to `list-rooms`.
+    #[serde(default)]
+    pub rooms: Option<Vec<RoomInfo>>,
+    /// Set on `room-joined` when the room settles in batches: the server
+    /// records its transactions with the gateway every this many ms.
+    #[serde(default)]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub batch_window_ms: Option<u64>,
+    /// Receiver's signature on a `transaction-rejected` or
+    /// `transaction-ack` message, and the key it verifies under.
+    #[serde(default)]
+    pub signature: Option<String>,
+    #[serde(default)]
+    pub public_key: Option<String>,
+    /// Whether the receiver applied the transaction, on a `transaction-ack`
+    /// message.
+    #[serde(default)]
+    pub result: Option<AckResult>,
+    /// Why it wasn't applied, on a failed `transaction-ack`.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Instance to reconnect to, on a `redirect` message.
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub resume_token: Option<String>,
+    /// Room broadcast sequence number (gap-resync protocol).
+    #[serde(default)]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub seq: Option<u64>,
+    /// Broadcasts skipped, on a `lagged` message.
+    #[serde(default)]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub missed: Option<u64>,
+    /// Whether a `resync` could replay everything that was missed.
+    #[serde(default)]
+    pub complete: Option<bool>,
+    /// Streaming payment state, on `channel-*` messages.
+    #[serde(default)]
+    pub channel: Option<ChannelUpdate>,
+    /// Sender's signed commitment, on `swap-*` messages.
+    #[serde(default)]
+    pub swap: Option<SwapCommitment>,
+    /// Request for quotes, on `quote-request` broadcasts.
+    #[serde(default)]
+    pub rfq: Option<QuoteRequest>,
+    /// Signed answer to a request, on `quote` messages.
+    #[serde(default)]
+    pub quote: Option<Quote>,
+    /// Free text to one peer, on `memo` messages.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+    /// WebRTC negotiation (tx-endpoint-v2).
+    #[serde(default)]
+    pub offer: Option<String>,
+    #[serde(default)]
+    pub answer: Option<String>,
+    #[serde(default)]
+    pub ice_candidate: Option<IceCandidate>,
+}
+
+impl SignalingMessage {
+    /// An otherwise empty message of `message_type`, tagged with this
+    /// crate's schema version.
+    pub fn new(message_type: &str) -> Self {
+        SignalingMessage {
+            message_type: message_type.to_string(),
+            schema_version: Some(SCHEMA_VERSION),
+            ..Default::default()
+        }
+    }
+}
+
+/// A room as listed by the signaling server. Rooms exist while someone
+/// is in them; joining an unknown room id creates it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct RoomInfo {
+    pub room_id: String,
+    pub peer_count: usize,
+    #[serde(default)]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub batch_window_ms: Option<u64>,
+    /// Relays only sealed broadcasts; see `SignalingMessage::encrypted`.
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+/// Outcome a receiver reports in a `transaction-ack`: the sender moves the
+/// transaction to `Confirmed` on `Applied` and to `Failed` otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum AckResult {
+    Applied,
+    Failed,
+}
+
+impl AckResult {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AckResult::Applied => "applied",
+            AckResult::Failed => "failed",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct IceCandidate {
+    pub candidate: String,
+    pub sdp_mid: Option<String>,
+    pub sdp_m_line_index: Option<u16>,
+}
@@ -0,0 +1,58 @@
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::Serialize;
+
+use crate::Transaction;
+
+// Fields covered by the signature, in canonical order. `signature`,
+// `status` and `public_key` are excluded; the first two change after the
+// transaction is created and the key is what the signature is checked with.
+// The payload keeps its schema 1 field names, and the amount the `f64` of
+// major units it was before `Money`, so existing signatures verify.
+#[derive(Serialize)]
+struct SigningPayload<'a> {
+    id: &'a str,
+    from: &'a str,
+    to: &'a str,
+    amount: f64,
+    timestamp: i64,
+    // Omitted when absent so transactions signed before sequencing still verify
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sequence: Option<u64>,
+}
+
+pub fn canonical_bytes(tx: &Transaction) -> Vec<u8> {
+    serde_json::to_vec(&SigningPayload {
+        id: &tx.id,
+        from: &tx.from_endpoint,
+        to: &tx.to_endpoint,
+        amount: tx.amount.to_major(),
+        timestamp: tx.timestamp.timestamp_millis(),
+        sequence: tx.sequence,
+    })
+    .unwrap_or_default()
+}
+
+/// Checks a hex ed25519 `signature` over `message` under a hex public key.
+pub fn verify_signature(public_key: &str, message: &[u8], signature: &str) -> bool {
+    let key = hex::decode(public_key)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok());
+    let signature = hex::decode(signature)
+        .ok()
+        .and_then(|bytes| <[u8; 64]>::try_from(bytes).ok())
+        .map(|bytes| Signature::from_bytes(&bytes));
+
+    match (key, signature) {
+        (Some(key), Some(signature)) => key.verify(message, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+/// Checks the signature against the key the transaction carries. Whether
+/// that key belongs to the sender is up to the caller.
+pub fn verify_transaction(tx: &Transaction) -> bool {
+    tx.public_key
+        .as_deref()
+        .is_some_and(|public_key| verify_signature(public_key, &canonical_bytes(tx), &tx.signature))
+}
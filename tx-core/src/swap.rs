@@ -0,0 +1,203 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::money::{self, Money};
+use crate::verify_signature;
+
+/// The asset endpoint balances and ordinary transfers are denominated in.
+pub const NATIVE_ASSET: &str = "USD";
+
+/// One side of a swap: `from_endpoint` pays `amount`, in `asset`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[serde(try_from = "LegWire")]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct SwapLeg {
+    pub from_endpoint: String,
+    pub to_endpoint: String,
+    pub asset: String,
+    /// A number of major units of `asset` on the wire, like
+    /// `Transaction::amount`.
+    #[serde(serialize_with = "crate::money::as_major::serialize")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub amount: Money,
+}
+
+/// A leg as it is read: the amount's currency is the leg's asset.
+#[derive(Deserialize)]
+struct LegWire {
+    from_endpoint: String,
+    to_endpoint: String,
+    asset: String,
+    amount: serde_json::Value,
+}
+
+impl TryFrom<LegWire> for SwapLeg {
+    type Error = serde_json::Error;
+
+    fn try_from(leg: LegWire) -> Result<Self, Self::Error> {
+        let amount = money::deserialize_major(leg.amount, &leg.asset)?;
+        Ok(SwapLeg { from_endpoint: leg.from_endpoint, to_endpoint: leg.to_endpoint, asset: leg.asset, amount })
+    }
+}
+
+/// A maker's offer of `offer` in exchange for `ask`, open until
+/// `expires_at`. Both parties sign the same terms.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct SwapTerms {
+    pub swap_id: String,
+    pub offer: SwapLeg,
+    pub ask: SwapLeg,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl SwapTerms {
+    /// The two legs mirror each other between two distinct parties and
+    /// move positive amounts.
+    pub fn is_well_formed(&self) -> bool {
+        self.offer.from_endpoint == self.ask.to_endpoint
+            && self.offer.to_endpoint == self.ask.from_endpoint
+            && self.offer.from_endpoint != self.offer.to_endpoint
+            && !self.offer.amount.is_negative()
+            && !self.offer.amount.is_zero()
+            && !self.ask.amount.is_negative()
+            && !self.ask.amount.is_zero()
+            && self.offer.amount.currency() == self.offer.asset
+            && self.ask.amount.currency() == self.ask.asset
+            && !self.offer.asset.is_empty()
+            && !self.ask.asset.is_empty()
+    }
+
+    pub fn maker(&self) -> &str {
+        &self.offer.from_endpoint
+    }
+
+    pub fn taker(&self) -> &str {
+        &self.ask.from_endpoint
+    }
+
+    /// The leg `party` pays, if it is part of the swap.
+    pub fn leg_paid_by(&self, party: &str) -> Option<&SwapLeg> {
+        [&self.offer, &self.ask].into_iter().find(|leg| leg.from_endpoint == party)
+    }
+
+    /// Amounts go in as the `f64` they were signed as before `SwapLeg`
+    /// held `Money`, so earlier commitments still verify.
+    fn commitment_bytes(&self, party: &str) -> Vec<u8> {
+        let (offer, ask) = (&self.offer, &self.ask);
+        let mut bytes = serde_json::to_vec(&serde_json::json!({
+            "swap_id": self.swap_id,
+            "offer": [&offer.from_endpoint, &offer.to_endpoint, &offer.asset, offer.amount.to_major()],
+            "ask": [&ask.from_endpoint, &ask.to_endpoint, &ask.asset, ask.amount.to_major()],
+            "expires_at": self.expires_at.timestamp_millis(),
+        }))
+        .unwrap_or_default();
+        bytes.extend_from_slice(b"|committed-by|");
+        bytes.extend_from_slice(party.as_bytes());
+        bytes
+    }
+}
+
+/// A party's signed agreement to `terms`: lock its leg and settle both
+/// legs together, or neither.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct SwapCommitment {
+    pub terms: SwapTerms,
+    pub party: String,
+    pub signature: String,
+    pub public_key: String,
+}
+
+impl SwapCommitment {
+    /// The bytes `party` signs to commit to `terms`.
+    pub fn signing_bytes(terms: &SwapTerms, party: &str) -> Vec<u8> {
+        terms.commitment_bytes(party)
+    }
+
+    /// Whether the terms are sound, `party` is part of them and the
+    /// signature verifies. Whether the key belongs to `party` is up to the
+    /// caller.
+    pub fn verify(&self) -> bool {
+        self.terms.is_well_formed()
+            && self.terms.leg_paid_by(&self.party).is_some()
+            && verify_signature(&self.public_key, &self.terms.commitment_bytes(&self.party), &self.signature)
+    }
+}
+
+/// Two-phase commit states. A swap is `Prepared` while holds are being
+/// locked, `Committing` once both are and the decision to settle is
+/// recorded, then `Settled`. `Prepared` swaps past their expiry are
+/// `RolledBack`; `Committing` ones are always rolled forward.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum SwapStatus {
+    #[serde(rename = "prepared")]
+    Prepared,
+    #[serde(rename = "committing")]
+    Committing,
+    #[serde(rename = "settled")]
+    Settled,
+    #[serde(rename = "rolled_back")]
+    RolledBack,
+}
+
+impl SwapStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwapStatus::Prepared => "prepared",
+            SwapStatus::Committing => "committing",
+            SwapStatus::Settled => "settled",
+            SwapStatus::RolledBack => "rolled_back",
+        }
+    }
+}
+
+impl fmt::Display for SwapStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SwapStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prepared" => Ok(SwapStatus::Prepared),
+            "committing" => Ok(SwapStatus::Committing),
+            "settled" => Ok(SwapStatus::Settled),
+            "rolled_back" => Ok(SwapStatus::RolledBack),
+            other => Err(format!("Unknown swap status: {}", other)),
+        }
+    }
+}
+
+/// The gateway's view of a swap.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct SwapState {
+    pub terms: SwapTerms,
+    pub status: SwapStatus,
+    /// Parties whose hold is locked.
+    pub held_by: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leg_amounts_are_in_the_leg_asset() {
+        let leg: SwapLeg =
+            serde_json::from_str(r#"{"from_endpoint": "a", "to_endpoint": "b", "asset": "EUR", "amount": "2.5"}"#)
+                .unwrap();
+        assert_eq!(leg.amount, Money::new(2_500_000, "EUR"));
+        assert_eq!(serde_json::to_value(&leg).unwrap()["amount"], serde_json::json!(2.5));
+
+        let mismatched = r#"{"from_endpoint": "a", "to_endpoint": "b", "asset": "EUR", "amount": "2.5 USD"}"#;
+        assert!(serde_json::from_str::<SwapLeg>(mismatched).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::Money;
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct Transaction {
+    pub id: String,
+    #[serde(alias = "from")]
+    pub from_endpoint: String,
+    #[serde(alias = "to")]
+    pub to_endpoint: String,
+    /// In the native asset. A number of major units on the wire, as it was
+    /// when this was an `f64`; see `money::as_major`.
+    #[serde(with = "crate::money::as_major")]
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub amount: Money,
+    #[serde(deserialize_with = "deserialize_timestamp")]
+    pub timestamp: DateTime<Utc>,
+    pub signature: String,
+    pub status: TransactionStatus,
+    #[serde(default)]
+    pub kind: TransactionKind,
+    /// Assigned by the gateway on ingest; ignored if supplied by clients.
+    #[serde(default)]
+    pub risk_score: Option<u8>,
+    /// Original transaction a chargeback compensates, or the net transfer
+    /// a netted payment was settled through.
+    #[serde(default)]
+    pub parent_tx_id: Option<String>,
+    /// Sender-assigned, strictly increasing per sender. Covered by the
+    /// signature.
+    #[serde(default)]
+    #[cfg_attr(feature = "ts", ts(type = "number | null"))]
+    pub sequence: Option<u64>,
+    /// Sender's ed25519 public key (hex) the signature verifies under.
+    #[serde(default)]
+    pub public_key: Option<String>,
+}
+
+impl Transaction {
+    pub fn involves(&self, endpoint_id: &str) -> bool {
+        self.from_endpoint == endpoint_id || self.to_endpoint == endpoint_id
+    }
+}
+
+/// Schema 1 endpoints sent millisecond timestamps, everything else RFC 3339.
+fn deserialize_timestamp<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Timestamp {
+        Millis(i64),
+        Rfc3339(DateTime<Utc>),
+    }
+
+    match Timestamp::deserialize(deserializer)? {
+        Timestamp::Millis(millis) => DateTime::from_timestamp_millis(millis)
+            .ok_or_else(|| serde::de::Error::custom(format!("timestamp out of range: {}", millis))),
+        Timestamp::Rfc3339(timestamp) => Ok(timestamp),
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum TransactionKind {
+    #[default]
+    #[serde(rename = "transfer")]
+    Transfer,
+    #[serde(rename = "deposit")]
+    Deposit,
+    #[serde(rename = "withdraw")]
+    Withdrawal,
+    /// Compensating entry reversing a refunded dispute's transfer.
+    #[serde(rename = "chargeback")]
+    Chargeback,
+    /// A micro-payment settled as part of a net transfer (its parent).
+    /// Recorded for audit only, the parent carries the funds.
+    #[serde(rename = "netted")]
+    Netted,
+}
+
+impl TransactionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionKind::Transfer => "transfer",
+            TransactionKind::Deposit => "deposit",
+            TransactionKind::Withdrawal => "withdraw",
+            TransactionKind::Chargeback => "chargeback",
+            TransactionKind::Netted => "netted",
+        }
+    }
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "transfer" => Ok(TransactionKind::Transfer),
+            "deposit" => Ok(TransactionKind::Deposit),
+            "withdraw" => Ok(TransactionKind::Withdrawal),
+            "chargeback" => Ok(TransactionKind::Chargeback),
+            "netted" => Ok(TransactionKind::Netted),
+            other => Err(format!("Unknown transaction kind: {}", other)),
+        }
+    }
+}
+
+/// Where a transaction is in its lifecycle:
+///
+/// ```text
+/// pending → relayed → acknowledged → confirmed
+///    ⇅
+///   held
+/// ```
+///
+/// plus `failed` or `expired` from anything that isn't final.
+///
+/// A transaction only moves forward along the main line, possibly
+/// skipping steps nobody observed (a withdrawal is never relayed). Held
+/// ones go back to pending, or straight to an outcome, only through
+/// review. `confirmed`, `failed` and `expired` are final.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub enum TransactionStatus {
+    /// Created and signed, not yet delivered.
+    #[serde(rename = "pending")]
+    Pending,
+    /// Handed to the signaling server or a data channel.
+    #[serde(rename = "relayed")]
+    Relayed,
+    /// Applied by the receiver.
+    #[serde(rename = "acknowledged")]
+    Acknowledged,
+    #[serde(rename = "confirmed")]
+    Confirmed,
+    #[serde(rename = "failed")]
+    Failed,
+    /// Never completed in time.
+    #[serde(rename = "expired")]
+    Expired,
+    /// Held for manual review.
+    #[serde(rename = "held")]
+    Held,
+}
+
+impl TransactionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransactionStatus::Pending => "pending",
+            TransactionStatus::Relayed => "relayed",
+            TransactionStatus::Acknowledged => "acknowledged",
+            TransactionStatus::Confirmed => "confirmed",
+            TransactionStatus::Failed => "failed",
+            TransactionStatus::Expired => "expired",
+            TransactionStatus::Held => "held",
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(self, TransactionStatus::Confirmed | TransactionStatus::Failed | TransactionStatus::Expired)
+    }
+
+    /// Position on the main line, for statuses on it.
+    fn progress(&self) -> Option<u8> {
+        match self {
+            TransactionStatus::Pending => Some(0),
+            TransactionStatus::Relayed => Some(1),
+            TransactionStatus::Acknowledged => Some(2),
+            TransactionStatus::Confirmed => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Whether a transaction in this status may move to `next`. Staying
+    /// put is allowed, so repeated updates are harmless.
+    pub fn can_transition_to(&self, next: TransactionStatus) -> bool {
+        if *self == next {
+            return true;
+        }
+        if self.is_final() {
+            return false;
+        }
+        match (self, next) {
+            (_, TransactionStatus::Failed | TransactionStatus::Expired) => true,
+            (TransactionStatus::Pending, TransactionStatus::Held) => true,
+            (TransactionStatus::Held, TransactionStatus::Pending | TransactionStatus::Confirmed) => true,
+            (TransactionStatus::Held, _) | (_, TransactionStatus::Held) => false,
+            _ => self.progress() < next.progress(),
+        }
+    }
+
+    /// `next`, if the transition is legal.
+    pub fn transition_to(&self, next: TransactionStatus) -> Result<TransactionStatus, String> {
+        if self.can_transition_to(next) {
+            Ok(next)
+        } else {
+            Err(format!("Transaction can't go from {} to {}", self, next))
+        }
+    }
+
+    /// Maps legacy free-form status strings onto the enum. Used by the
+    /// gateway's schema migration to clean up rows written before the enum
+    /// existed.
+    pub fn from_legacy(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "pending" | "created" | "sent" => Some(TransactionStatus::Pending),
+            "confirmed" | "confrmed" | "complete" | "completed" | "success" => Some(TransactionStatus::Confirmed),
+            "failed" | "fail" | "error" | "rejected" => Some(TransactionStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TransactionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TransactionStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(TransactionStatus::Pending),
+            "relayed" => Ok(TransactionStatus::Relayed),
+            "acknowledged" => Ok(TransactionStatus::Acknowledged),
+            "confirmed" => Ok(TransactionStatus::Confirmed),
+            "failed" => Ok(TransactionStatus::Failed),
+            "expired" => Ok(TransactionStatus::Expired),
+            "held" => Ok(TransactionStatus::Held),
+            other => Err(format!("Unknown transaction status: {}", other)),
+        }
+    }
+}
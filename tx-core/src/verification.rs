@@ -0,0 +1,162 @@
+//! Offline receipt verification: the gateway's signature, the proof that
+//! the transaction is in a checkpoint of the gateway's event log, and the
+//! transaction hash against a local copy.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::{transaction_hash, KeySet, Receipt, Transaction};
+
+/// Event type of the log entry a transaction's inclusion is proven by.
+pub const TRANSACTION_CREATED: &str = "transaction.created";
+
+/// One event as it's folded into its partition's hash chain.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct ChainLink {
+    #[cfg_attr(feature = "ts", ts(type = "number"))]
+    pub offset: i64,
+    pub event_id: String,
+    pub event_type: String,
+    pub tx_hash: String,
+    /// The event payload exactly as hashed (compact JSON).
+    pub payload: String,
+}
+
+impl ChainLink {
+    /// The partition's chain value after this event, given the one before.
+    pub fn extend(&self, chain: &str) -> String {
+        let link = format!(
+            "{}|{}|{}|{}|{}|{}",
+            chain, self.offset, self.event_id, self.event_type, self.tx_hash, self.payload
+        );
+        hex::encode(Sha256::digest(link.as_bytes()))
+    }
+}
+
+/// Checkpoint root over each partition's chain head.
+pub fn checkpoint_root(chains: &BTreeMap<String, String>) -> String {
+    let mut root = Sha256::new();
+    for (partition_key, chain) in chains {
+        root.update(format!("{}:{}\n", partition_key, chain).as_bytes());
+    }
+    hex::encode(root.finalize())
+}
+
+/// Proof that a transaction's creation event is covered by a checkpoint:
+/// its partition's chain from just before the event up to the checkpoint,
+/// plus every other partition's head.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+#[cfg_attr(feature = "ts", derive(ts_rs::TS))]
+pub struct InclusionProof {
+    pub snapshot_id: String,
+    pub root: String,
+    pub partition_key: String,
+    /// Chain value before the transaction's event; empty if it's the first.
+    pub prev_chain: String,
+    /// The transaction's event first, then the partition's later events
+    /// up to the checkpoint.
+    pub links: Vec<ChainLink>,
+    pub other_chains: BTreeMap<String, String>,
+}
+
+impl InclusionProof {
+    /// Checks the proof leads to `root` from the event that created the
+    /// receipted transaction.
+    pub fn verify(&self, receipt: &Receipt) -> Result<(), String> {
+        let first = self.links.first().ok_or("Proof has no events")?;
+        if first.event_type != TRANSACTION_CREATED {
+            return Err(format!("Proof starts at a {} event", first.event_type));
+        }
+        let tx: Transaction =
+            serde_json::from_str(&first.payload).map_err(|e| format!("Unreadable event payload: {}", e))?;
+        if tx.id != receipt.transaction_id || transaction_hash(&tx) != receipt.transaction_hash {
+            return Err("Proof is for a different transaction".to_string());
+        }
+
+        let head = self.links.iter().fold(self.prev_chain.clone(), |chain, link| link.extend(&chain));
+        let mut chains = self.other_chains.clone();
+        chains.insert(self.partition_key.clone(), head);
+        if checkpoint_root(&chains) != self.root {
+            return Err("Proof does not lead to the checkpoint root".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Check {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Check::Passed => write!(f, "passed"),
+            Check::Failed(reason) => write!(f, "FAILED: {}", reason),
+            Check::Skipped(reason) => write!(f, "skipped: {}", reason),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceiptReport {
+    /// The receipt is signed by the key it carries.
+    pub signature: Check,
+    /// That key was one of the gateway's published keys when it signed.
+    pub key: Check,
+    /// The transaction is in a checkpoint of the gateway's log.
+    pub inclusion: Check,
+    /// The receipted transaction matches the local copy.
+    pub transaction: Check,
+}
+
+impl ReceiptReport {
+    /// A valid signature and nothing that failed; skipped checks are only
+    /// as good as the caller's reasons to accept them.
+    pub fn is_valid(&self) -> bool {
+        self.signature == Check::Passed
+            && ![&self.key, &self.inclusion, &self.transaction]
+                .iter()
+                .any(|check| matches!(check, Check::Failed(_)))
+    }
+}
+
+/// Runs every check `receipt` supports. `trusted_keys` are the gateway's
+/// published keys if known; `history` is where a local copy of the
+/// transaction is looked up.
+pub fn verify_receipt<'a>(
+    receipt: &Receipt,
+    trusted_keys: Option<&KeySet>,
+    history: impl IntoIterator<Item = &'a Transaction>,
+) -> ReceiptReport {
+    let signature = if receipt.verify() {
+        Check::Passed
+    } else {
+        Check::Failed("signature does not verify".to_string())
+    };
+
+    let key = match trusted_keys.map(|keys| keys.find(&receipt.public_key)) {
+        Some(Some(key)) if key.valid_at(receipt.issued_at) => Check::Passed,
+        Some(Some(key)) => Check::Failed(format!("issued outside the validity of gateway key {}", key.kid)),
+        Some(None) => Check::Failed("signed with a key the gateway does not publish".to_string()),
+        None => Check::Skipped("no trusted gateway keys".to_string()),
+    };
+
+    let inclusion = match &receipt.inclusion {
+        Some(proof) => proof.verify(receipt).map_or_else(Check::Failed, |()| Check::Passed),
+        None => Check::Skipped("issued before a checkpoint covered the transaction".to_string()),
+    };
+
+    let transaction = match history.into_iter().find(|tx| tx.id == receipt.transaction_id) {
+        Some(tx) if transaction_hash(tx) == receipt.transaction_hash => Check::Passed,
+        Some(_) => Check::Failed("local copy differs from the receipted transaction".to_string()),
+        None => Check::Skipped("not in local history".to_string()),
+    };
+
+    ReceiptReport { signature, key, inclusion, transaction }
+}
@@ -2,8 +2,32 @@ import React, { useState, useEffect } from 'react';
 import TxEndpointCard from './components/TxEndpointCard';
 import TransactionGraph from './components/TransactionGraph';
 import StatsPanel from './components/StatsPanel';
+import ReviewQueue from './components/ReviewQueue';
+import Disputes from './components/Disputes';
+import History from './components/History';
+import { graphql } from './graphql';
 import './App.css';
 
+// config.js, written by the gateway when it serves the dashboard, wins
+// over the build-time settings
+const runtimeConfig = window.RELAYER_CONFIG || {};
+const API_GATEWAY = runtimeConfig.apiGateway || import.meta.env.VITE_API_GATEWAY || 'http://localhost:3001';
+const SIGNALING_SERVER = runtimeConfig.signalingServer || import.meta.env.VITE_SIGNALING_SERVER || 'ws://localhost:8080';
+
+// Only what the log, the graph and chargeback links show
+const TRANSACTIONS_QUERY = `{
+  transactions(limit: 50) {
+    items { id from_endpoint to_endpoint amount timestamp status kind parent_tx_id }
+  }
+}`;
+
+const STATS_QUERY = `{
+  stats {
+    total_transactions total_volume average_transaction
+    endpoints { endpoint_id transaction_count total_sent total_received balance_change }
+  }
+}`;
+
 function App() {
   const [transactions, setTransactions] = useState([]);
   const [endpoints, setEndpoints] = useState([
@@ -16,17 +40,63 @@ function App() {
 
   useEffect(() => {
     initializeConnections();
+    const closeStream = subscribeToTransactions();
     fetchData();
     
-    // Set up polling for updates
+    // Stats are still polled; transactions arrive over the stream
     const interval = setInterval(fetchData, 2000);
-    return () => clearInterval(interval);
+    return () => {
+      clearInterval(interval);
+      closeStream();
+    };
   }, []);
 
+  // Live transactions from the gateway (server-sent events). The list is
+  // fetched once the stream first connects; on reconnect the browser sends
+  // the last event id and the gateway replays what was missed, and only
+  // when it can't (`lagged`) is the list refetched.
+  const subscribeToTransactions = () => {
+    const apiGateway = API_GATEWAY;
+    const source = new EventSource(`${apiGateway}/api/transactions/stream`);
+
+    const upsert = (event) => {
+      const transaction = JSON.parse(event.data);
+      setTransactions(prev => {
+        if (prev.some(tx => tx.id === transaction.id)) {
+          return prev.map(tx => (tx.id === transaction.id ? transaction : tx));
+        }
+        return [transaction, ...prev].slice(0, 100); // Keep last 100
+      });
+    };
+
+    source.addEventListener('transaction.created', upsert);
+    source.addEventListener('transaction.reversed', upsert);
+    source.addEventListener('transaction.status_changed', upsert);
+    source.addEventListener('lagged', fetchTransactions);
+    let opened = false;
+    source.onopen = () => {
+      if (!opened) {
+        opened = true;
+        fetchTransactions();
+      }
+    };
+    return () => source.close();
+  };
+
+  const fetchTransactions = async () => {
+    try {
+      const apiGateway = API_GATEWAY;
+      const data = await graphql(apiGateway, TRANSACTIONS_QUERY);
+      setTransactions(data.transactions.items);
+    } catch (err) {
+      console.error('Error fetching transactions:', err);
+    }
+  };
+
   const initializeConnections = () => {
     // Connect to signaling server for real-time updates
     try {
-      const signalingUrl = import.meta.env.VITE_SIGNALING_SERVER || 'ws://localhost:8080';
+      const signalingUrl = SIGNALING_SERVER;
       const ws = new WebSocket(signalingUrl);
       
       ws.onopen = () => {
@@ -92,28 +162,18 @@ function App() {
 
   const fetchData = async () => {
     try {
-      const apiGateway = import.meta.env.VITE_API_GATEWAY || 'http://localhost:3001';
-      
-      // Fetch transactions
-      const transactionsResponse = await fetch(`${apiGateway}/api/transactions?limit=50`);
-      if (transactionsResponse.ok) {
-        const transactionsData = await transactionsResponse.json();
-        setTransactions(transactionsData);
-      }
+      const apiGateway = API_GATEWAY;
       
       // Fetch stats
-      const statsResponse = await fetch(`${apiGateway}/api/stats`);
-      if (statsResponse.ok) {
-        const statsData = await statsResponse.json();
-        setStats(statsData);
-        
-        // Update endpoint balances from stats
-        setEndpoints(prev => prev.map(ep => {
-          const endpointStats = statsData.endpoints.find(es => es.endpoint_id === ep.id);
-          return endpointStats ? 
-            { ...ep, balance: 1000 + endpointStats.balance_change } : ep;
-        }));
-      }
+      const { stats: statsData } = await graphql(apiGateway, STATS_QUERY);
+      setStats(statsData);
+
+      // Update endpoint balances from stats
+      setEndpoints(prev => prev.map(ep => {
+        const endpointStats = statsData.endpoints.find(es => es.endpoint_id === ep.id);
+        return endpointStats ?
+          { ...ep, balance: 1000 + endpointStats.balance_change } : ep;
+      }));
       
       setLoading(false);
     } catch (err) {
@@ -123,6 +183,33 @@ function App() {
     }
   };
 
+  // Saves the gateway's signed receipt for a settled transaction
+  const downloadReceipt = async (txId) => {
+    const apiGateway = API_GATEWAY;
+    try {
+      const response = await fetch(`${apiGateway}/api/transactions/${txId}/receipt`);
+      if (!response.ok) {
+        setError(response.status === 409 ? 'No receipt yet, the transaction has not settled' : `Receipt request failed: HTTP ${response.status}`);
+        return;
+      }
+      const receipt = await response.json();
+      const url = URL.createObjectURL(new Blob([JSON.stringify(receipt, null, 2)], { type: 'application/json' }));
+      const link = document.createElement('a');
+      link.href = url;
+      link.download = `receipt-${txId}.json`;
+      link.click();
+      URL.revokeObjectURL(url);
+    } catch (err) {
+      console.error('Error fetching receipt:', err);
+      setError('Failed to fetch receipt');
+    }
+  };
+
+  // Original transaction id -> id of the chargeback reversing it
+  const chargedBack = Object.fromEntries(
+    transactions.filter(tx => tx.parent_tx_id).map(tx => [tx.parent_tx_id, tx.id])
+  );
+
   if (loading) {
     return (
       <div className="app-loading">
@@ -170,11 +257,25 @@ function App() {
           />
         </div>
         
+        <div className="transactions-section">
+          <h2>Review Queue</h2>
+          <ReviewQueue apiGateway={API_GATEWAY} />
+        </div>
+        
+        <div className="transactions-section">
+          <h2>Disputes</h2>
+          <Disputes apiGateway={API_GATEWAY} />
+        </div>
+        
         <div className="transactions-section">
           <h2>Recent Transactions ({transactions.length})</h2>
           <div className="transactions-list">
             {transactions.slice(0, 20).map(tx => (
-              <div key={tx.id} className="transaction-item">
+              <div
+                key={tx.id}
+                className="transaction-item"
+                style={tx.kind === 'chargeback' || chargedBack[tx.id] ? { borderLeft: '4px solid #6f42c1' } : undefined}
+              >
                 <div className="transaction-header">
                   <span className="transaction-amount">${tx.amount}</span>
                   <span className={`transaction-status status-${tx.status}`}>
@@ -191,6 +292,20 @@ function App() {
                 </div>
                 <div className="transaction-id">
                   ID: {tx.id.substring(0, 8)}...
+                  {tx.parent_tx_id && (
+                    <span style={{ marginLeft: '10px' }}>↩️ chargeback of {tx.parent_tx_id.substring(0, 8)}...</span>
+                  )}
+                  {chargedBack[tx.id] && (
+                    <span style={{ marginLeft: '10px' }}>↩️ charged back by {chargedBack[tx.id].substring(0, 8)}...</span>
+                  )}
+                  {['confirmed', 'failed', 'expired'].includes(tx.status) && (
+                    <button
+                      style={{ marginLeft: '10px', fontSize: '0.8rem', cursor: 'pointer' }}
+                      onClick={() => downloadReceipt(tx.id)}
+                    >
+                      🧾 Download receipt
+                    </button>
+                  )}
                 </div>
               </div>
             ))}
@@ -202,6 +317,11 @@ function App() {
             )}
           </div>
         </div>
+
+        <div className="transactions-section">
+          <h2>History</h2>
+          <History apiGateway={API_GATEWAY} />
+        </div>
       </div>
     </div>
   );
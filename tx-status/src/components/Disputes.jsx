@@ -0,0 +1,237 @@
+import React, { useState, useEffect } from 'react';
+
+const STATUS_CLASS = {
+  open: 'status-pending',
+  under_review: 'status-held',
+  refunded: 'status-confirmed',
+  rejected: 'status-failed'
+};
+
+const TIMELINE_LABELS = {
+  'dispute.opened': '⚖️ Dispute opened',
+  'dispute.under_review': '🔎 Moved to review',
+  'dispute.refunded': '💸 Resolved: refund',
+  'dispute.rejected': '✖️ Resolved: rejected',
+  'dispute.chargeback': '↩️ Chargeback issued',
+  evidence: '📎 Evidence'
+};
+
+function readFileAsBase64(file) {
+  return new Promise((resolve, reject) => {
+    const reader = new FileReader();
+    reader.onload = () => resolve(reader.result.split(',')[1]);
+    reader.onerror = reject;
+    reader.readAsDataURL(file);
+  });
+}
+
+function Disputes({ apiGateway }) {
+  const [disputes, setDisputes] = useState([]);
+  const [selected, setSelected] = useState(null);
+  const [party, setParty] = useState('');
+  const [message, setMessage] = useState('');
+  const [file, setFile] = useState(null);
+  const [error, setError] = useState('');
+
+  const fetchDisputes = async () => {
+    try {
+      const response = await fetch(`${apiGateway}/api/disputes`);
+      if (response.ok) {
+        setDisputes(await response.json());
+      }
+    } catch (err) {
+      console.error('Error fetching disputes:', err);
+    }
+  };
+
+  const fetchDetail = async (id) => {
+    try {
+      const response = await fetch(`${apiGateway}/api/disputes/${id}`);
+      if (response.ok) {
+        const detail = await response.json();
+        setSelected(detail);
+        setParty(prev => prev || detail.opened_by);
+      }
+    } catch (err) {
+      console.error('Error fetching dispute:', err);
+    }
+  };
+
+  useEffect(() => {
+    fetchDisputes();
+    const interval = setInterval(fetchDisputes, 5000);
+    return () => clearInterval(interval);
+  }, []);
+
+  const submitEvidence = async () => {
+    if (!message.trim() && !file) {
+      setError('Add a message or a file');
+      return;
+    }
+
+    try {
+      const attachment = file
+        ? { name: file.name, content_type: file.type || 'application/octet-stream', data: await readFileAsBase64(file) }
+        : null;
+      const response = await fetch(`${apiGateway}/api/disputes/${selected.transaction_id}/evidence`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json' },
+        body: JSON.stringify({ party, message, attachment })
+      });
+      if (!response.ok) {
+        setError(`Failed to add evidence (HTTP ${response.status})`);
+        return;
+      }
+      setError('');
+      setMessage('');
+      setFile(null);
+      fetchDetail(selected.transaction_id);
+    } catch (err) {
+      setError('Failed to add evidence');
+    }
+  };
+
+  const review = async (action, body) => {
+    const reviewer = localStorage.getItem('reviewer') || '';
+    if (!reviewer.trim()) {
+      setError('Set your reviewer name in the review queue first');
+      return;
+    }
+
+    try {
+      const response = await fetch(`${apiGateway}/api/disputes/${selected.transaction_id}/${action}`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json', 'X-Reviewer': reviewer },
+        body: JSON.stringify(body || {})
+      });
+      if (response.status === 412) {
+        setError('The disputing party has to submit evidence first');
+        return;
+      }
+      if (!response.ok) {
+        setError(`Failed to ${action} dispute (HTTP ${response.status})`);
+        return;
+      }
+      setError('');
+      fetchDetail(selected.transaction_id);
+      fetchDisputes();
+    } catch (err) {
+      setError(`Failed to ${action} dispute`);
+    }
+  };
+
+  const buttonStyle = (background) => ({
+    background,
+    color: 'white',
+    border: 'none',
+    padding: '6px 14px',
+    borderRadius: '6px',
+    cursor: 'pointer',
+    fontWeight: '600',
+    marginLeft: '8px'
+  });
+
+  const isFinal = selected && (selected.status === 'refunded' || selected.status === 'rejected');
+
+  return (
+    <div className="disputes">
+      {error && <div className="error-banner">⚠️ {error}</div>}
+
+      {disputes.length === 0 ? (
+        <div className="no-transactions">No disputes.</div>
+      ) : (
+        disputes.map(dispute => (
+          <div
+            key={dispute.transaction_id}
+            className="transaction-item"
+            style={{ cursor: 'pointer' }}
+            onClick={() => fetchDetail(dispute.transaction_id)}
+          >
+            <div className="transaction-header">
+              <span className="transaction-flow">
+                {dispute.opened_by} vs {dispute.counterparty}
+              </span>
+              <span className={`transaction-status ${STATUS_CLASS[dispute.status]}`}>
+                {dispute.status.replace('_', ' ')}
+              </span>
+            </div>
+            <div className="transaction-details">
+              <span>{dispute.reason}</span>
+              <span className="transaction-time">
+                {new Date(dispute.updated_at).toLocaleString()}
+              </span>
+            </div>
+            <span className="transaction-id">TX: {dispute.transaction_id.substring(0, 8)}...</span>
+          </div>
+        ))
+      )}
+
+      {selected && (
+        <div className="dispute-detail" style={{ marginTop: '20px' }}>
+          <h3>Timeline for {selected.transaction_id.substring(0, 8)}...</h3>
+          <ul style={{ listStyle: 'none', padding: 0 }}>
+            {selected.timeline.map((entry, index) => (
+              <li key={index} style={{ borderLeft: '3px solid #667eea', padding: '6px 12px', margin: '8px 0' }}>
+                <div>
+                  <strong>{TIMELINE_LABELS[entry.kind] || entry.kind}</strong> by {entry.actor}
+                  <span className="transaction-time" style={{ marginLeft: '10px' }}>
+                    {new Date(entry.at).toLocaleString()}
+                  </span>
+                </div>
+                {entry.message && <div>{entry.message}</div>}
+                {entry.attachment && (
+                  <a
+                    href={`${apiGateway}/api/disputes/${selected.transaction_id}/evidence/${entry.attachment.evidence_id}/attachment`}
+                    target="_blank"
+                    rel="noreferrer"
+                  >
+                    📄 {entry.attachment.name} ({Math.ceil(entry.attachment.size / 1024)} KB)
+                  </a>
+                )}
+              </li>
+            ))}
+          </ul>
+
+          {!isFinal && (
+            <>
+              <div style={{ display: 'flex', gap: '8px', alignItems: 'center', flexWrap: 'wrap' }}>
+                <select value={party} onChange={(e) => setParty(e.target.value)}>
+                  <option value={selected.opened_by}>{selected.opened_by}</option>
+                  <option value={selected.counterparty}>{selected.counterparty}</option>
+                </select>
+                <input
+                  value={message}
+                  onChange={(e) => setMessage(e.target.value)}
+                  placeholder="message"
+                  style={{ padding: '6px 10px', borderRadius: '6px', border: '1px solid #dee2e6', flex: 1 }}
+                />
+                <input type="file" onChange={(e) => setFile(e.target.files[0] || null)} />
+                <button style={buttonStyle('#667eea')} onClick={submitEvidence}>
+                  Add Evidence
+                </button>
+              </div>
+
+              <div style={{ marginTop: '12px', textAlign: 'right' }}>
+                {selected.status === 'open' && (
+                  <button style={buttonStyle('#fd7e14')} onClick={() => review('escalate')}>
+                    Move to Review
+                  </button>
+                )}
+                {selected.status === 'under_review' && (
+                  <button style={buttonStyle('#28a745')} onClick={() => review('resolve', { outcome: 'refund' })}>
+                    Refund
+                  </button>
+                )}
+                <button style={buttonStyle('#dc3545')} onClick={() => review('resolve', { outcome: 'reject' })}>
+                  Reject
+                </button>
+              </div>
+            </>
+          )}
+        </div>
+      )}
+    </div>
+  );
+}
+
+export default Disputes;
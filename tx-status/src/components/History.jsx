@@ -0,0 +1,73 @@
+import React, { useState } from 'react';
+
+const PAGE_SIZE = 50;
+const FIELDS = 'id,from_endpoint,to_endpoint,amount,timestamp,status';
+
+// Full transaction history, newest first, a page at a time. Pages follow
+// the gateway's `Next-Cursor` header, so transactions arriving meanwhile
+// don't shift or repeat what is already loaded.
+function History({ apiGateway }) {
+  const [transactions, setTransactions] = useState([]);
+  const [cursor, setCursor] = useState(null);
+  const [exhausted, setExhausted] = useState(false);
+  const [loading, setLoading] = useState(false);
+  const [error, setError] = useState('');
+
+  const loadPage = async () => {
+    setLoading(true);
+    try {
+      const before = cursor ? `&before=${encodeURIComponent(cursor)}` : '';
+      const response = await fetch(`${apiGateway}/api/transactions?limit=${PAGE_SIZE}&fields=${FIELDS}${before}`);
+      if (!response.ok) {
+        throw new Error(`HTTP ${response.status}`);
+      }
+      const page = await response.json();
+      const next = response.headers.get('Next-Cursor');
+      setTransactions(prev => [...prev, ...page]);
+      setCursor(next);
+      setExhausted(!next);
+      setError('');
+    } catch (err) {
+      setError(`Failed to load history: ${err.message}`);
+    } finally {
+      setLoading(false);
+    }
+  };
+
+  return (
+    <div>
+      {error && <div className="error-banner">⚠️ {error}</div>}
+      <div className="transactions-list">
+        {transactions.map(tx => (
+          <div key={tx.id} className="transaction-item">
+            <div className="transaction-header">
+              <span className="transaction-amount">${tx.amount}</span>
+              <span className={`transaction-status status-${tx.status}`}>
+                {tx.status}
+              </span>
+            </div>
+            <div className="transaction-details">
+              <span className="transaction-flow">
+                {tx.from_endpoint} → {tx.to_endpoint}
+              </span>
+              <span className="transaction-time">
+                {new Date(tx.timestamp).toLocaleString()}
+              </span>
+            </div>
+            <div className="transaction-id">ID: {tx.id.substring(0, 8)}...</div>
+          </div>
+        ))}
+      </div>
+      {!exhausted && (
+        <button style={{ marginTop: '10px', cursor: 'pointer' }} disabled={loading} onClick={loadPage}>
+          {loading ? 'Loading…' : transactions.length === 0 ? 'Load history' : 'Load older'}
+        </button>
+      )}
+      {exhausted && transactions.length > 0 && (
+        <div className="no-transactions">Start of history ({transactions.length} transactions)</div>
+      )}
+    </div>
+  );
+}
+
+export default History;
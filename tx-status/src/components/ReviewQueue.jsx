@@ -0,0 +1,113 @@
+import React, { useState, useEffect } from 'react';
+
+const FIELDS = 'id,from_endpoint,to_endpoint,amount,timestamp,risk_score';
+
+function ReviewQueue({ apiGateway }) {
+  const [queue, setQueue] = useState([]);
+  const [reviewer, setReviewer] = useState(localStorage.getItem('reviewer') || '');
+  const [error, setError] = useState('');
+
+  const fetchQueue = async () => {
+    try {
+      const response = await fetch(`${apiGateway}/api/review-queue?fields=${FIELDS}`);
+      if (response.ok) {
+        setQueue(await response.json());
+      }
+    } catch (err) {
+      console.error('Error fetching review queue:', err);
+    }
+  };
+
+  useEffect(() => {
+    fetchQueue();
+    const interval = setInterval(fetchQueue, 5000);
+    return () => clearInterval(interval);
+  }, []);
+
+  const decide = async (txId, action) => {
+    if (!reviewer.trim()) {
+      setError('Enter your reviewer name first');
+      return;
+    }
+    localStorage.setItem('reviewer', reviewer);
+
+    try {
+      const response = await fetch(`${apiGateway}/api/review-queue/${txId}/${action}`, {
+        method: 'POST',
+        headers: { 'Content-Type': 'application/json', 'X-Reviewer': reviewer },
+        body: JSON.stringify({})
+      });
+      if (!response.ok) {
+        setError(`Failed to ${action} transaction (HTTP ${response.status})`);
+        return;
+      }
+      setError('');
+      setQueue(prev => prev.filter(tx => tx.id !== txId));
+    } catch (err) {
+      setError(`Failed to ${action} transaction`);
+    }
+  };
+
+  const buttonStyle = (background) => ({
+    background,
+    color: 'white',
+    border: 'none',
+    padding: '6px 14px',
+    borderRadius: '6px',
+    cursor: 'pointer',
+    fontWeight: '600',
+    marginLeft: '8px'
+  });
+
+  return (
+    <div className="review-queue">
+      <div style={{ display: 'flex', alignItems: 'center', marginBottom: '15px' }}>
+        <label style={{ marginRight: '10px', fontWeight: '600' }}>Reviewer</label>
+        <input
+          value={reviewer}
+          onChange={(e) => setReviewer(e.target.value)}
+          placeholder="your name"
+          style={{ padding: '6px 10px', borderRadius: '6px', border: '1px solid #dee2e6' }}
+        />
+      </div>
+
+      {error && <div className="error-banner">⚠️ {error}</div>}
+
+      {queue.length === 0 ? (
+        <div className="no-transactions">No transactions awaiting review.</div>
+      ) : (
+        queue.map(tx => (
+          <div key={tx.id} className="transaction-item">
+            <div className="transaction-header">
+              <span className="transaction-amount">${tx.amount}</span>
+              <span className="transaction-status status-held">
+                risk {tx.risk_score ?? '?'}
+              </span>
+            </div>
+            <div className="transaction-details">
+              <span className="transaction-flow">
+                {tx.from_endpoint} → {tx.to_endpoint}
+              </span>
+              <span className="transaction-time">
+                {new Date(tx.timestamp).toLocaleString()}
+              </span>
+            </div>
+            <div style={{ display: 'flex', justifyContent: 'space-between', alignItems: 'center' }}>
+              <span className="transaction-id">ID: {tx.id.substring(0, 8)}...</span>
+              <div>
+                <button style={buttonStyle('#28a745')} onClick={() => decide(tx.id, 'approve')}>
+                  Approve
+                </button>
+                <button style={buttonStyle('#dc3545')} onClick={() => decide(tx.id, 'reject')}>
+                  Reject
+                </button>
+              </div>
+            </div>
+          </div>
+        ))
+      )}
+    </div>
+  );
+}
+
+export default ReviewQueue;
@@ -0,0 +1,17 @@
+// Runs a query against the gateway's GraphQL endpoint and returns its
+// data, throwing on transport or GraphQL errors.
+export async function graphql(apiGateway, query, variables = {}) {
+  const response = await fetch(`${apiGateway}/api/graphql`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json' },
+    body: JSON.stringify({ query, variables })
+  });
+  if (!response.ok) {
+    throw new Error(`HTTP ${response.status}`);
+  }
+  const { data, errors } = await response.json();
+  if (errors?.length) {
+    throw new Error(errors.map(e => e.message).join('; '));
+  }
+  return data;
+}
@@ -3,6 +3,8 @@ import react from '@vitejs/plugin-react'
 
 export default defineConfig({
   plugins: [react()],
+  // Relative asset URLs, so the build works under any base path
+  base: './',
   server: {
     port: 3000,
     host: true
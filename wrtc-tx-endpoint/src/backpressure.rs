@@ -0,0 +1,178 @@
+//! Flow control for data channel sends. `RTCDataChannel.send` never
+//! blocks: it appends to the channel's buffer, and once that passes the
+//! browser's limit (16 MiB in Chrome) the channel is closed and everything
+//! buffered is lost. A heavy sender has to watch `bufferedAmount` itself.
+//!
+//! Each channel gets a `SendQueue`. Frames go straight to the channel while
+//! its buffer is under the high-water mark; past it they wait here, and the
+//! channel's `bufferedamountlow` event (fired once the buffer drains to the
+//! low-water mark) sends them on, in order. The queue's depth is reported
+//! on every change so the page can show what's waiting.
+//!
+//! Marks can be set from the page's query string:
+//! `?buffer_high=1048576&buffer_low=262144`.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::{Rc, Weak};
+
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::codec::Frame;
+use crate::Transaction;
+
+/// Told `(peer_id, queued frames)` whenever a queue grows or drains.
+pub type OnBacklog = Rc<dyn Fn(&str, usize)>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watermarks {
+    /// Buffered bytes past which sends wait in the queue.
+    pub high: u32,
+    /// Buffered bytes at which the browser tells us to resume.
+    pub low: u32,
+}
+
+impl Default for Watermarks {
+    fn default() -> Self {
+        Watermarks { high: 1024 * 1024, low: 256 * 1024 }
+    }
+}
+
+impl Watermarks {
+    /// The defaults, with any marks the query string sets.
+    pub fn from_query(search: &str) -> Self {
+        let mut marks = Watermarks::default();
+        let Ok(params) = web_sys::UrlSearchParams::new_with_str(search) else {
+            return marks;
+        };
+        let param = |name: &str| params.get(name).and_then(|value| value.parse().ok());
+        if let Some(high) = param("buffer_high") {
+            marks.high = high;
+        }
+        if let Some(low) = param("buffer_low") {
+            marks.low = low;
+        }
+        // A low mark at or over the high one would never fire
+        marks.low = marks.low.min(marks.high / 2);
+        marks
+    }
+}
+
+struct Queued {
+    frame: Frame,
+    /// What the frame carries, for requeueing if the channel goes away.
+    transactions: Vec<Transaction>,
+}
+
+/// Sends for one peer's data channel.
+pub struct SendQueue {
+    peer_id: String,
+    channel: web_sys::RtcDataChannel,
+    marks: Watermarks,
+    pending: RefCell<VecDeque<Queued>>,
+    on_backlog: OnBacklog,
+    // Kept alive for as long as the channel may call it
+    on_low: RefCell<Option<Closure<dyn FnMut()>>>,
+}
+
+impl SendQueue {
+    /// Takes over `channel`'s `bufferedamountlow` handler.
+    pub fn attach(
+        peer_id: &str,
+        channel: &web_sys::RtcDataChannel,
+        marks: Watermarks,
+        on_backlog: OnBacklog,
+    ) -> Rc<Self> {
+        channel.set_buffered_amount_low_threshold(marks.low);
+        let queue = Rc::new(SendQueue {
+            peer_id: peer_id.to_string(),
+            channel: channel.clone(),
+            marks,
+            pending: RefCell::new(VecDeque::new()),
+            on_backlog,
+            on_low: RefCell::new(None),
+        });
+
+        let weak: Weak<SendQueue> = Rc::downgrade(&queue);
+        let on_low = Closure::<dyn FnMut()>::new(move || {
+            if let Some(queue) = weak.upgrade() {
+                if let Err(e) = queue.drain() {
+                    web_sys::console::error_1(&e);
+                }
+            }
+        });
+        channel.set_onbufferedamountlow(Some(on_low.as_ref().unchecked_ref()));
+        *queue.on_low.borrow_mut() = Some(on_low);
+        queue
+    }
+
+    /// Sends `frame` now if the channel has room and nothing is waiting
+    /// ahead of it, else queues it. An error means the channel rejected the
+    /// frame, usually because it is closing; the frame is dropped and its
+    /// transactions are the caller's to requeue.
+    pub fn send(&self, frame: Frame, transactions: Vec<Transaction>) -> Result<(), JsValue> {
+        if self.pending.borrow().is_empty() && self.has_room(&frame) {
+            return frame.send(&self.channel);
+        }
+        self.pending.borrow_mut().push_back(Queued { frame, transactions });
+        self.report();
+        Ok(())
+    }
+
+    /// Frames waiting for the channel to drain.
+    pub fn depth(&self) -> usize {
+        self.pending.borrow().len()
+    }
+
+    /// Empties the queue, e.g. when the channel closes, returning what the
+    /// frames carried so it can be replayed on the next channel.
+    pub fn take(&self) -> Vec<Transaction> {
+        let taken: Vec<Transaction> =
+            self.pending.borrow_mut().drain(..).flat_map(|queued| queued.transactions).collect();
+        self.report();
+        taken
+    }
+
+    /// Always lets a frame onto an empty buffer, even one bigger than the
+    /// high mark, so oversized frames can't wedge the queue.
+    fn has_room(&self, frame: &Frame) -> bool {
+        let buffered = self.channel.buffered_amount();
+        let len = u32::try_from(frame.byte_len()).unwrap_or(u32::MAX);
+        buffered == 0 || buffered.saturating_add(len) <= self.marks.high
+    }
+
+    /// Sends queued frames until the buffer is back at the high mark. A
+    /// failed frame stays at the front for `take`.
+    fn drain(&self) -> Result<(), JsValue> {
+        let mut sent = false;
+        let result = loop {
+            let mut pending = self.pending.borrow_mut();
+            let Some(next) = pending.front() else {
+                break Ok(());
+            };
+            if !self.has_room(&next.frame) {
+                break Ok(());
+            }
+            if let Err(e) = next.frame.send(&self.channel) {
+                break Err(e);
+            }
+            pending.pop_front();
+            sent = true;
+        };
+        if sent {
+            self.report();
+        }
+        result
+    }
+
+    fn report(&self) {
+        (self.on_backlog)(&self.peer_id, self.depth());
+    }
+}
+
+impl Drop for SendQueue {
+    fn drop(&mut self) {
+        self.channel.set_onbufferedamountlow(None);
+    }
+}
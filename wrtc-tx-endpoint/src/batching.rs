@@ -0,0 +1,204 @@
+//! Outbound transaction batching. Each data channel message has a fixed
+//! cost (framing, SCTP chunk headers, a JS event on the receiver), which
+//! dominates for small transactions sent in bulk. Transactions to a peer
+//! that reads `transaction-batch` are held for up to `flush_interval_ms`
+//! and sent together, in order; a batch goes out early once it reaches
+//! `max_transactions` or would pass `max_bytes`.
+//!
+//! Peers that didn't advertise the capability get one `transaction-p2p`
+//! per transaction, as before. The receiver unbatches into those same
+//! messages, so nothing downstream sees the difference.
+//!
+//! Limits can be set from the page's query string:
+//! `?batch_bytes=16384&batch_size=100&batch_ms=20` (`batch_ms=0` disables
+//! batching).
+
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use crate::{SignalingMessage, Transaction};
+
+pub const BATCH_TYPE: &str = "transaction-batch";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchConfig {
+    /// Upper bound on a batch's encoded transactions. Messages over 16 KiB
+    /// are fragmented unevenly across browsers, so that's the default.
+    pub max_bytes: usize,
+    pub max_transactions: usize,
+    /// How long the first transaction of a batch may wait for company.
+    pub flush_interval_ms: u32,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig { max_bytes: 16 * 1024, max_transactions: 100, flush_interval_ms: 20 }
+    }
+}
+
+impl BatchConfig {
+    /// The defaults, with any limits the query string sets.
+    pub fn from_query(search: &str) -> Self {
+        let mut config = BatchConfig::default();
+        let Ok(params) = web_sys::UrlSearchParams::new_with_str(search) else {
+            return config;
+        };
+        // Generic, unlike a closure, so each limit parses as its own type
+        fn param<T: FromStr>(params: &web_sys::UrlSearchParams, name: &str) -> Option<T> {
+            params.get(name).and_then(|value| value.parse().ok())
+        }
+        if let Some(max_bytes) = param(&params, "batch_bytes") {
+            config.max_bytes = max_bytes;
+        }
+        if let Some(max_transactions) = param(&params, "batch_size") {
+            config.max_transactions = max_transactions;
+        }
+        if let Some(flush_interval_ms) = param(&params, "batch_ms") {
+            config.flush_interval_ms = flush_interval_ms;
+        }
+        config
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.flush_interval_ms > 0 && self.max_transactions > 1
+    }
+}
+
+#[derive(Default)]
+struct Pending {
+    transactions: Vec<Transaction>,
+    bytes: usize,
+}
+
+/// What `Batcher::push` wants done with a transaction.
+#[derive(Debug)]
+pub enum Push {
+    /// Held; the first in its batch, so a flush should be scheduled.
+    Scheduled,
+    /// Held behind others, whose flush is already scheduled.
+    Held,
+    /// Send these now, in order: a full batch and possibly the
+    /// transaction on its own.
+    Send(Vec<SignalingMessage>),
+}
+
+/// Transactions waiting to go out, per peer.
+pub struct Batcher {
+    config: BatchConfig,
+    pending: HashMap<String, Pending>,
+    /// Peers that advertised `transaction-batch`.
+    batching_peers: HashSet<String>,
+}
+
+impl Batcher {
+    pub fn new(config: BatchConfig) -> Self {
+        Batcher { config, pending: HashMap::new(), batching_peers: HashSet::new() }
+    }
+
+    pub fn config(&self) -> BatchConfig {
+        self.config
+    }
+
+    /// From the peer's advertised capabilities on joining.
+    pub fn set_peer_batches(&mut self, peer_id: &str, batches: bool) {
+        if batches {
+            self.batching_peers.insert(peer_id.to_string());
+        } else {
+            self.batching_peers.remove(peer_id);
+        }
+    }
+
+    pub fn push(&mut self, tx: Transaction) -> Push {
+        let peer_id = tx.to_endpoint.clone();
+        if !self.config.enabled() || !self.batching_peers.contains(&peer_id) {
+            return Push::Send(vec![single(tx)]);
+        }
+
+        let bytes = serde_json::to_vec(&tx).map_or(0, |encoded| encoded.len());
+        let mut ready = Vec::new();
+        let pending = self.pending.entry(peer_id.clone()).or_default();
+        if !pending.transactions.is_empty() && pending.bytes + bytes > self.config.max_bytes {
+            let full = std::mem::take(pending);
+            ready.push(batch(&peer_id, full.transactions));
+        }
+        if pending.transactions.is_empty() && bytes > self.config.max_bytes {
+            // Too big to share a frame with anything
+            ready.push(single(tx));
+            return Push::Send(ready);
+        }
+
+        let first = pending.transactions.is_empty();
+        pending.transactions.push(tx);
+        pending.bytes += bytes;
+        if pending.transactions.len() >= self.config.max_transactions {
+            let full = std::mem::take(pending);
+            ready.push(batch(&peer_id, full.transactions));
+        }
+
+        match (ready.is_empty(), first) {
+            (false, _) => Push::Send(ready),
+            (true, true) => Push::Scheduled,
+            (true, false) => Push::Held,
+        }
+    }
+
+    /// `peer_id`'s held transactions as one message, if there are any.
+    pub fn flush(&mut self, peer_id: &str) -> Option<SignalingMessage> {
+        let pending = self.pending.remove(peer_id)?;
+        (!pending.transactions.is_empty()).then(|| batch(peer_id, pending.transactions))
+    }
+
+    /// Drops what's held for `peer_id`, e.g. when its channel closes,
+    /// returning it for requeueing.
+    pub fn take(&mut self, peer_id: &str) -> Vec<Transaction> {
+        self.pending.remove(peer_id).map(|pending| pending.transactions).unwrap_or_default()
+    }
+}
+
+fn single(tx: Transaction) -> SignalingMessage {
+    SignalingMessage {
+        target_peer: Some(tx.to_endpoint.clone()),
+        transaction: Some(tx),
+        ..SignalingMessage::new("transaction-p2p")
+    }
+}
+
+fn batch(peer_id: &str, transactions: Vec<Transaction>) -> SignalingMessage {
+    if transactions.len() == 1 {
+        return single(transactions.into_iter().next().expect("one transaction"));
+    }
+    SignalingMessage {
+        target_peer: Some(peer_id.to_string()),
+        transactions: Some(transactions),
+        ..SignalingMessage::new(BATCH_TYPE)
+    }
+}
+
+/// The transactions a message carries, in order: a batch's, or the one of
+/// any other message.
+pub fn transactions(message: &SignalingMessage) -> Vec<Transaction> {
+    match &message.transactions {
+        Some(transactions) if message.message_type == BATCH_TYPE => transactions.clone(),
+        _ => message.transaction.iter().cloned().collect(),
+    }
+}
+
+/// A received `transaction-batch` as the `transaction-p2p` messages it
+/// stands for; any other message as itself.
+pub fn unbatch(message: SignalingMessage) -> Vec<SignalingMessage> {
+    if message.message_type != BATCH_TYPE {
+        return vec![message];
+    }
+    let from_peer = message.from_peer.clone();
+    message
+        .transactions
+        .unwrap_or_default()
+        .into_iter()
+        .map(|tx| SignalingMessage {
+            from_peer: from_peer.clone(),
+            peer_id: from_peer.clone(),
+            transaction: Some(tx),
+            ..SignalingMessage::new("transaction-p2p")
+        })
+        .collect()
+}
@@ -0,0 +1,288 @@
+//! Wire format for data channel messages. Peers used to exchange JSON text
+//! only; binary frames now carry CBOR or MessagePack behind a one-byte
+//! format prefix, which is smaller and cheaper to parse at high relay
+//! rates.
+//!
+//! The format is negotiated per peer. When a channel opens each side
+//! sends a `codec-hello` text frame listing what it can read, if the peer
+//! advertised the `binary-codec` capability on joining, and from then on
+//! writes to that peer in the first of our `SUPPORTED` formats it listed.
+//! A peer that never says hello is an old one and keeps getting JSON
+//! text. Frames are decoded by their own prefix, so the two
+//! directions need not agree.
+//!
+//! The hello also lists the compression a peer reads for bulk frames. We
+//! read none: zstd, the one peers offer, is only available as the C
+//! library, which the wasm build can't link. A peer offering it is
+//! declined by our empty list and writes to us uncompressed, and a frame
+//! with the zstd prefix is refused rather than misread.
+//!
+//! Signatures cover canonical bytes rebuilt from the fields, not the
+//! frame, and amounts stay `f64` in every format, so a transaction
+//! verifies the same whichever way it travelled.
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use wasm_bindgen::{JsCast, JsValue};
+
+pub const HELLO_TYPE: &str = "codec-hello";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WireFormat {
+    Json,
+    Cbor,
+    MessagePack,
+}
+
+/// What we read, most preferred first.
+pub const SUPPORTED: [WireFormat; 3] = [WireFormat::Cbor, WireFormat::MessagePack, WireFormat::Json];
+
+/// The compression we read, as listed in our hello; see the module docs.
+pub const SUPPORTED_COMPRESSION: [&str; 0] = [];
+
+/// Prefix of a zstd-compressed frame, from peers whose hello offered it.
+const ZSTD_PREFIX: u8 = 0x10;
+
+impl WireFormat {
+    fn prefix(self) -> u8 {
+        match self {
+            WireFormat::Json => 0x00,
+            WireFormat::Cbor => 0x01,
+            WireFormat::MessagePack => 0x02,
+        }
+    }
+
+    fn from_prefix(prefix: u8) -> Option<Self> {
+        match prefix {
+            0x00 => Some(WireFormat::Json),
+            0x01 => Some(WireFormat::Cbor),
+            0x02 => Some(WireFormat::MessagePack),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WireFormat::Json => "json",
+            WireFormat::Cbor => "cbor",
+            WireFormat::MessagePack => "msgpack",
+        }
+    }
+}
+
+impl fmt::Display for WireFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for WireFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(WireFormat::Json),
+            "cbor" => Ok(WireFormat::Cbor),
+            "msgpack" => Ok(WireFormat::MessagePack),
+            other => Err(format!("Unknown wire format: {}", other)),
+        }
+    }
+}
+
+/// One data channel message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Frame {
+    /// From a `message` event's `data`; the channel must have its binary
+    /// type set to `arraybuffer` (see `prepare`).
+    pub fn from_message(data: &JsValue) -> Option<Self> {
+        if let Some(text) = data.as_string() {
+            return Some(Frame::Text(text));
+        }
+        let buffer = data.dyn_ref::<js_sys::ArrayBuffer>()?;
+        Some(Frame::Binary(js_sys::Uint8Array::new(buffer).to_vec()))
+    }
+
+    pub fn send(&self, channel: &web_sys::RtcDataChannel) -> Result<(), JsValue> {
+        match self {
+            Frame::Text(text) => channel.send_with_str(text),
+            Frame::Binary(bytes) => channel.send_with_u8_array(bytes),
+        }
+    }
+
+    /// Bytes on the channel, before SCTP framing.
+    pub fn byte_len(&self) -> usize {
+        match self {
+            Frame::Text(text) => text.len(),
+            Frame::Binary(bytes) => bytes.len(),
+        }
+    }
+}
+
+/// Has binary messages delivered as `ArrayBuffer`s rather than `Blob`s,
+/// which can't be read synchronously.
+pub fn prepare(channel: &web_sys::RtcDataChannel) {
+    channel.set_binary_type(web_sys::RtcDataChannelType::Arraybuffer);
+}
+
+#[derive(Serialize, Deserialize)]
+struct Hello {
+    #[serde(rename = "type")]
+    message_type: String,
+    formats: Vec<String>,
+    /// Older peers send none.
+    #[serde(default)]
+    compression: Vec<String>,
+}
+
+/// What a peer's `codec-hello` said it reads.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PeerHello {
+    /// Formats we don't know are skipped.
+    pub formats: Vec<WireFormat>,
+    pub compression: Vec<String>,
+}
+
+/// Our `codec-hello`, sent as the first frame on a new channel.
+pub fn hello() -> Frame {
+    let hello = Hello {
+        message_type: HELLO_TYPE.to_string(),
+        formats: SUPPORTED.iter().map(|format| format.as_str().to_string()).collect(),
+        compression: SUPPORTED_COMPRESSION.iter().map(|name| name.to_string()).collect(),
+    };
+    Frame::Text(serde_json::to_string(&hello).expect("hello serializes"))
+}
+
+/// Our `codec-hello` for a peer with `capabilities`, or `None` if it
+/// didn't advertise `binary-codec` and would read the hello as a
+/// malformed message.
+pub fn hello_for(capabilities: &tx_core::Capabilities) -> Option<Frame> {
+    capabilities.supports(tx_core::Capability::BinaryCodec).then(hello)
+}
+
+/// A peer's `codec-hello`, or `None` if `frame` isn't one.
+pub fn parse_hello(frame: &Frame) -> Option<PeerHello> {
+    let Frame::Text(text) = frame else {
+        return None;
+    };
+    let hello: Hello = serde_json::from_str(text).ok()?;
+    if hello.message_type != HELLO_TYPE {
+        return None;
+    }
+    Some(PeerHello {
+        formats: hello.formats.iter().filter_map(|format| format.parse().ok()).collect(),
+        compression: hello.compression,
+    })
+}
+
+pub fn encode<T: Serialize>(format: WireFormat, value: &T) -> Result<Frame, String> {
+    let encode_error = |e: &dyn fmt::Display| format!("Could not encode as {}: {}", format, e);
+    match format {
+        // Text, not prefixed bytes: it's what old peers read
+        WireFormat::Json => serde_json::to_string(value).map(Frame::Text).map_err(|e| encode_error(&e)),
+        WireFormat::Cbor => {
+            let mut bytes = vec![format.prefix()];
+            ciborium::into_writer(value, &mut bytes).map_err(|e| encode_error(&e))?;
+            Ok(Frame::Binary(bytes))
+        }
+        WireFormat::MessagePack => {
+            // Named fields: optional fields are skipped when unset, which
+            // would shift positional ones
+            let mut bytes = vec![format.prefix()];
+            rmp_serde::encode::write_named(&mut bytes, value).map_err(|e| encode_error(&e))?;
+            Ok(Frame::Binary(bytes))
+        }
+    }
+}
+
+pub fn decode<T: DeserializeOwned>(frame: &Frame) -> Result<T, String> {
+    let bytes = match frame {
+        Frame::Text(text) => return serde_json::from_str(text).map_err(|e| format!("Invalid JSON frame: {}", e)),
+        Frame::Binary(bytes) => bytes,
+    };
+    let (&prefix, body) = bytes.split_first().ok_or("Empty binary frame")?;
+    match WireFormat::from_prefix(prefix) {
+        Some(WireFormat::Json) => serde_json::from_slice(body).map_err(|e| format!("Invalid JSON frame: {}", e)),
+        Some(WireFormat::Cbor) => ciborium::from_reader(body).map_err(|e| format!("Invalid CBOR frame: {}", e)),
+        Some(WireFormat::MessagePack) => {
+            rmp_serde::from_slice(body).map_err(|e| format!("Invalid MessagePack frame: {}", e))
+        }
+        None if prefix == ZSTD_PREFIX => {
+            Err("zstd frames are not supported; our codec-hello offers no compression".to_string())
+        }
+        None => Err(format!("Unknown frame format 0x{:02x}", prefix)),
+    }
+}
+
+/// Frames written in one format.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+impl FrameStats {
+    pub fn bytes_per_message(&self) -> Option<f64> {
+        (self.messages > 0).then(|| self.bytes as f64 / self.messages as f64)
+    }
+}
+
+/// The format negotiated with each peer, and what was written in each.
+#[derive(Clone, Debug, Default)]
+pub struct Codecs {
+    formats: HashMap<String, WireFormat>,
+    written: HashMap<WireFormat, FrameStats>,
+}
+
+impl Codecs {
+    /// Records `peer_id`'s hello and returns the format we'll write to it.
+    /// Frames go uncompressed whatever compression it offered.
+    pub fn negotiate(&mut self, peer_id: &str, theirs: &PeerHello) -> WireFormat {
+        let format = SUPPORTED
+            .into_iter()
+            .find(|format| theirs.formats.contains(format))
+            .unwrap_or(WireFormat::Json);
+        if !theirs.compression.is_empty() {
+            web_sys::console::log_1(
+                &format!("Declined {} from {}, writing uncompressed", theirs.compression.join(", "), peer_id).into(),
+            );
+        }
+        self.formats.insert(peer_id.to_string(), format);
+        format
+    }
+
+    /// JSON until the peer has said hello.
+    pub fn format(&self, peer_id: &str) -> WireFormat {
+        self.formats.get(peer_id).copied().unwrap_or(WireFormat::Json)
+    }
+
+    pub fn encode<T: Serialize>(&mut self, peer_id: &str, value: &T) -> Result<Frame, String> {
+        let format = self.format(peer_id);
+        let frame = encode(format, value)?;
+        let stats = self.written.entry(format).or_default();
+        stats.messages += 1;
+        stats.bytes += frame.byte_len() as u64;
+        Ok(frame)
+    }
+
+    /// What was written in each format, in `SUPPORTED` order.
+    pub fn stats(&self) -> Vec<(WireFormat, FrameStats)> {
+        SUPPORTED
+            .into_iter()
+            .filter_map(|format| self.written.get(&format).map(|stats| (format, *stats)))
+            .collect()
+    }
+
+    /// When the channel closes; the next one negotiates afresh, possibly
+    /// with an upgraded peer.
+    pub fn forget(&mut self, peer_id: &str) {
+        self.formats.remove(peer_id);
+    }
+}
@@ -0,0 +1,89 @@
+//! Transaction gossip across the room's mesh. Data channels are
+//! point-to-point, so a transfer only ever reached its recipient and the
+//! rest of the room never saw it. Now every endpoint that learns of a
+//! transaction it hasn't seen, directly or by gossip, forwards it as a
+//! `transaction-gossip` to its other peers, and the room converges on one
+//! log without a server broadcast.
+//!
+//! Loops are cut two ways: each endpoint forwards a transaction at most
+//! once (the seen set), and each message carries a `ttl`, one lower per
+//! hop, after which it isn't forwarded at all. The seen set is bounded;
+//! by the time an id is evicted, its gossip has long died out.
+//!
+//! Only peers that advertised `gossip` get gossip; older ones would log
+//! it as a transfer addressed to someone else.
+
+use std::collections::{HashSet, VecDeque};
+
+use crate::{SignalingMessage, Transaction};
+
+pub const GOSSIP_TYPE: &str = "transaction-gossip";
+
+/// Hops from the recipient: enough to cross any room the signaling
+/// server will hold as a sparse mesh.
+pub const DEFAULT_TTL: u8 = 6;
+
+/// Transaction ids remembered.
+const SEEN_CAPACITY: usize = 10_000;
+
+#[derive(Default)]
+pub struct Gossip {
+    seen: HashSet<String>,
+    /// `seen` in insertion order, for eviction.
+    order: VecDeque<String>,
+    /// Peers that advertised `gossip`.
+    gossiping_peers: HashSet<String>,
+}
+
+impl Gossip {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// From the peer's advertised capabilities on joining.
+    pub fn set_peer_gossips(&mut self, peer_id: &str, gossips: bool) {
+        if gossips {
+            self.gossiping_peers.insert(peer_id.to_string());
+        } else {
+            self.gossiping_peers.remove(peer_id);
+        }
+    }
+
+    /// Records `tx_id`; false if it was already seen, in which case the
+    /// transaction must not be forwarded again.
+    pub fn observe(&mut self, tx_id: &str) -> bool {
+        if !self.seen.insert(tx_id.to_string()) {
+            return false;
+        }
+        self.order.push_back(tx_id.to_string());
+        if self.order.len() > SEEN_CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// Gossip for `tx`, newly seen from `message`, to each of
+    /// `connected_peers` that gossips, except where it came from and its
+    /// sender. None once the hops run out.
+    pub fn forward(&self, tx: &Transaction, message: &SignalingMessage, connected_peers: &[String]) -> Vec<SignalingMessage> {
+        // A direct `transaction-p2p` has travelled no hops yet
+        let ttl = message.ttl.unwrap_or(DEFAULT_TTL);
+        if ttl <= 1 {
+            return Vec::new();
+        }
+        let from_peer = message.from_peer.as_deref();
+        connected_peers
+            .iter()
+            .filter(|peer| self.gossiping_peers.contains(*peer))
+            .filter(|peer| Some(peer.as_str()) != from_peer && **peer != tx.from_endpoint)
+            .map(|peer| SignalingMessage {
+                target_peer: Some(peer.clone()),
+                transaction: Some(tx.clone()),
+                ttl: Some(ttl - 1),
+                ..SignalingMessage::new(GOSSIP_TYPE)
+            })
+            .collect()
+    }
+}
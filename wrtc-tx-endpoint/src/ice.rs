@@ -0,0 +1,177 @@
+//! ICE server configuration for peer connections. Browsers only gather
+//! host and STUN candidates by default, which fails between peers behind
+//! symmetric NATs; a TURN server relays for them instead.
+//!
+//! Servers come from the signaling server's `/ice-servers`, or from the
+//! page's query parameters, which take precedence for testing a setup:
+//! `?stun=stun:host:3478&turn=turn:host:3478&turn_user=u&turn_pass=p`
+//! (`stun` and `turn` may repeat or be comma-separated).
+
+use gloo_net::http::Request;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+
+const DEFAULT_STUN_URL: &str = "stun:stun.l.google.com:19302";
+
+/// One entry of `RTCConfiguration.iceServers`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct IceServer {
+    pub urls: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IceConfig {
+    pub ice_servers: Vec<IceServer>,
+}
+
+impl Default for IceConfig {
+    fn default() -> Self {
+        IceConfig {
+            ice_servers: vec![IceServer { urls: vec![DEFAULT_STUN_URL.to_string()], username: None, credential: None }],
+        }
+    }
+}
+
+impl IceConfig {
+    /// Whether any server can relay, so a failed direct path isn't final.
+    pub fn has_turn(&self) -> bool {
+        self.ice_servers
+            .iter()
+            .flat_map(|server| &server.urls)
+            .any(|url| url.starts_with("turn:") || url.starts_with("turns:"))
+    }
+
+    /// Servers named in the page's query string, if any.
+    pub fn from_query(search: &str) -> Option<Self> {
+        let params = web_sys::UrlSearchParams::new_with_str(search).ok()?;
+        let urls = |name: &str| -> Vec<String> {
+            params
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.as_string())
+                .flat_map(|value| value.split(',').map(|url| url.trim().to_string()).collect::<Vec<_>>())
+                .filter(|url| !url.is_empty())
+                .collect()
+        };
+
+        let mut ice_servers = Vec::new();
+        let stun = urls("stun");
+        if !stun.is_empty() {
+            ice_servers.push(IceServer { urls: stun, username: None, credential: None });
+        }
+        let turn = urls("turn");
+        if !turn.is_empty() {
+            ice_servers.push(IceServer {
+                urls: turn,
+                username: params.get("turn_user"),
+                credential: params.get("turn_pass"),
+            });
+        }
+        (!ice_servers.is_empty()).then_some(IceConfig { ice_servers })
+    }
+
+    pub fn rtc_configuration(&self) -> web_sys::RtcConfiguration {
+        let config = web_sys::RtcConfiguration::new();
+        match serde_json::to_string(&self.ice_servers).map(|json| js_sys::JSON::parse(&json)) {
+            Ok(Ok(servers)) => config.set_ice_servers(&servers),
+            _ => web_sys::console::error_1(&"Could not build ICE server list".into()),
+        }
+        config
+    }
+}
+
+fn signaling_http_url() -> String {
+    crate::webrtc_connection::signaling_url().replacen("ws", "http", 1)
+}
+
+async fn fetch(peer_id: &str) -> Result<IceConfig, String> {
+    let url = format!("{}/ice-servers", signaling_http_url());
+    let response = Request::get(&url)
+        .query([("peerId", peer_id)])
+        .send()
+        .await
+        .map_err(|e| format!("ICE server request failed: {}", e))?;
+
+    if !response.ok() {
+        return Err(format!("ICE server request failed: HTTP {}", response.status()));
+    }
+
+    response
+        .json::<IceConfig>()
+        .await
+        .map_err(|e| format!("Invalid ICE server response: {}", e))
+}
+
+/// The query string's servers, else the signaling server's, else public
+/// STUN only.
+pub async fn load(peer_id: &str) -> IceConfig {
+    let search = web_sys::window().and_then(|w| w.location().search().ok()).unwrap_or_default();
+    if let Some(config) = IceConfig::from_query(&search) {
+        return config;
+    }
+    match fetch(peer_id).await {
+        Ok(config) => config,
+        Err(e) => {
+            web_sys::console::warn_1(&format!("{}; using public STUN only", e).into());
+            IceConfig::default()
+        }
+    }
+}
+
+/// How a peer connection's selected candidate pair reaches the peer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Route {
+    Direct,
+    /// Through a TURN server on either side.
+    Relayed,
+}
+
+impl Route {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Route::Direct => "direct",
+            Route::Relayed => "relayed via TURN",
+        }
+    }
+}
+
+fn get(object: &JsValue, key: &str) -> JsValue {
+    js_sys::Reflect::get(object, &key.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// Reads the route from `getStats()`: the transport's selected candidate
+/// pair (or, in browsers without it, the nominated pair that succeeded)
+/// is relayed if either of its candidates is a `relay` candidate.
+pub async fn selected_route(peer_connection: &web_sys::RtcPeerConnection) -> Option<Route> {
+    let report: js_sys::Map = JsFuture::from(peer_connection.get_stats()).await.ok()?.unchecked_into();
+
+    let mut selected_pair_id = None;
+    let mut nominated_pair_id = None;
+    report.for_each(&mut |stats, id| match get(&stats, "type").as_string().as_deref() {
+        Some("transport") => {
+            if let Some(pair_id) = get(&stats, "selectedCandidatePairId").as_string() {
+                selected_pair_id = Some(pair_id);
+            }
+        }
+        Some("candidate-pair")
+            if get(&stats, "nominated").is_truthy()
+                && get(&stats, "state").as_string().as_deref() == Some("succeeded") =>
+        {
+            nominated_pair_id = id.as_string();
+        }
+        _ => {}
+    });
+
+    let pair = report.get(&selected_pair_id.or(nominated_pair_id)?.into());
+    let relayed = ["localCandidateId", "remoteCandidateId"].iter().any(|side| {
+        let candidate = report.get(&get(&pair, side));
+        get(&candidate, "candidateType").as_string().as_deref() == Some("relay")
+    });
+    Some(if relayed { Route::Relayed } else { Route::Direct })
+}
@@ -1,45 +1,39 @@
+use chrono::{DateTime, Utc};
 use dioxus::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
+mod backpressure;
+mod batching;
+mod codec;
+mod gossip;
+mod ice;
+mod relay;
+mod topology;
 mod tx_endpoint;
 mod webrtc_connection;
 
+use backpressure::Watermarks;
+use batching::{BatchConfig, Batcher, Push};
+use gossip::Gossip;
+use ice::{IceConfig, Route};
+use relay::Step;
+use topology::Topology;
 use tx_endpoint::TxEndpoint;
 use webrtc_connection::WebRTCConnection;
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-pub struct Transaction {
-    pub id: String,
-    pub from: String,
-    pub to: String,
-    pub amount: f64,
-    pub timestamp: u64,
-    pub signature: String,
-    pub status: String,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct SignalingMessage {
-    pub message_type: String,
-    pub room_id: Option<String>,
-    pub peer_id: Option<String>,
-    pub target_peer: Option<String>,
-    pub from_peer: Option<String>,
-    pub transaction: Option<Transaction>,
-    pub peers: Option<Vec<String>>,
-    pub offer: Option<String>,
-    pub answer: Option<String>,
-    pub ice_candidate: Option<IceCandidate>,
-}
+pub use tx_core::{AckResult, Capabilities, Capability, IceCandidate, RoomInfo, SignalingMessage, Transaction, TransactionKind, TransactionStatus};
+use tx_core::money::MINOR_PER_MAJOR;
+use tx_core::{Money, NATIVE_ASSET};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct IceCandidate {
-    pub candidate: String,
-    pub sdp_mid: Option<String>,
-    pub sdp_m_line_index: Option<u16>,
-}
+/// What we advertise in `hello`; `webrtc_connection` sends it.
+pub const FEATURES: [Capability; 5] = [
+    Capability::BinaryCodec,
+    Capability::Acks,
+    Capability::TransactionBatches,
+    Capability::Gossip,
+    Capability::Relay,
+];
 
 fn main() {
     console_error_panic_hook::set_once();
@@ -47,17 +41,12 @@ fn main() {
 }
 
 fn app(cx: Scope) -> Element {
-    // Get endpoint ID from URL or default
+    // Get endpoint ID from URL or default; other parameters configure ICE
     let endpoint_id = use_state(cx, || {
         web_sys::window()
             .and_then(|w| w.location().search().ok())
-            .and_then(|search| {
-                if search.starts_with("?id=") {
-                    Some(search[4..].to_string())
-                } else {
-                    None
-                }
-            })
+            .and_then(|search| web_sys::UrlSearchParams::new_with_str(&search).ok())
+            .and_then(|params| params.get("id"))
             .unwrap_or_else(|| "endpoint-1".to_string())
     });
 
@@ -65,54 +54,137 @@ fn app(cx: Scope) -> Element {
     let connection = use_state(cx, || WebRTCConnection::new());
     let transactions = use_state(cx, HashMap::<String, Transaction>::new);
     let connected_peers = use_state(cx, Vec::<String>::new);
+    // How each connected peer is reached, from the selected candidate pair
+    let peer_routes = use_state(cx, HashMap::<String, Route>::new);
+    // What each peer in the room advertised when it joined
+    let peer_capabilities = use_state(cx, HashMap::<String, Capabilities>::new);
+    // Fetched up front so going online doesn't wait on it; never fails,
+    // falling back to public STUN
+    let ice_config = use_future(cx, (), |_| {
+        let endpoint_id = endpoint_id.get().clone();
+        async move { ice::load(&endpoint_id).await }
+    });
+    // Peers whose data channel dropped and is being recovered by an ICE
+    // restart; they stay listed and sends to them are queued.
+    let recovering_peers = use_state(cx, HashSet::<String>::new);
+    let outbound = use_state(cx, Vec::<Transaction>::new);
+    // Transactions held briefly to go out together
+    let batcher = use_ref(cx, || {
+        let search = web_sys::window().and_then(|w| w.location().search().ok()).unwrap_or_default();
+        Batcher::new(BatchConfig::from_query(&search))
+    });
+    // Transactions seen, and which peers forward them
+    let gossip = use_ref(cx, Gossip::new);
+    // Who in the room has a channel to whom, for the graph and for relays
+    let topology = use_ref(cx, Topology::new);
+    // The path each relayed transfer of ours took, from its receipt
+    let delivery_routes = use_state(cx, HashMap::<String, Vec<String>>::new);
+    // Frames waiting for each peer's data channel buffer to drain
+    let send_backlog = use_state(cx, HashMap::<String, usize>::new);
+    let current_room = use_state(cx, || "transaction-room".to_string());
+    let rooms = use_state(cx, Vec::<RoomInfo>::new);
+    let room_input = use_state(cx, String::new);
     let connection_status = use_state(cx, || "Disconnected".to_string());
     let webrtc_status = use_state(cx, || "Not Connected".to_string());
     let error_message = use_state(cx, || "".to_string());
 
-    // Auto-connect on component mount
-    use_effect(cx, (), {
-        let connection = connection.clone();
-        let endpoint_id = endpoint_id.get().clone();
-        let connection_status = connection_status.clone();
-        let webrtc_status = webrtc_status.clone();
-        let connected_peers = connected_peers.clone();
-        let transactions = transactions.clone();
-        let error_message = error_message.clone();
-        
-        move |_| {
-            async move {
-                web_sys::console::log_1(&"Initializing WebRTC connection...".into());
-                
-                let result = connection.with_mut(|conn| {
-                    conn.connect(
-                        &endpoint_id,
-                        Box::new({
-                            let connection_status = connection_status.clone();
-                            let webrtc_status = webrtc_status.clone();
-                            let connected_peers = connected_peers.clone();
-                            let transactions = transactions.clone();
-                            let error_message = error_message.clone();
-                            
-                            move |msg: SignalingMessage| {
-                                handle_signaling_message(
-                                    msg,
-                                    &connection_status,
-                                    &webrtc_status,
-                                    &connected_peers,
-                                    &transactions,
-                                    &error_message,
-                                );
+    // The room's mesh, drawn once someone else is in it
+    let mesh_positions = topology.read().layout(endpoint_id.get(), 240.0);
+    let mesh_edges = topology.read().edges();
+    // Peers we have no channel to that others can pass a transfer on to
+    let relay_targets = topology.read().reachable(endpoint_id.get());
+
+    // Peer connections are deferred until the user goes online or opens the
+    // send panel, so viewing history doesn't trigger connection setup.
+    let online = use_state(cx, || false);
+    let send_panel_open = use_state(cx, || false);
+
+    let go_online = move || {
+        if *online.get() {
+            return;
+        }
+        let Some(ice) = ice_config.value() else {
+            error_message.set("Still loading ICE servers, try again in a moment".to_string());
+            return;
+        };
+        online.set(true);
+        web_sys::console::log_1(&"Initializing WebRTC connection...".into());
+
+        let search = web_sys::window().and_then(|w| w.location().search().ok()).unwrap_or_default();
+        let result = connection.with_mut(|conn| {
+            // Used for every peer connection created from here on
+            conn.set_rtc_configuration(ice.rtc_configuration());
+            // Each data channel sends through a `backpressure::SendQueue`
+            conn.set_backpressure(
+                Watermarks::from_query(&search),
+                std::rc::Rc::new({
+                    let send_backlog = send_backlog.clone();
+                    move |peer_id: &str, depth: usize| {
+                        send_backlog.with_mut(|backlog| {
+                            if depth == 0 {
+                                backlog.remove(peer_id);
+                            } else {
+                                backlog.insert(peer_id.to_string(), depth);
                             }
-                        }),
-                    )
-                });
+                        });
+                    }
+                }),
+            );
+            conn.connect(
+                endpoint_id.get(),
+                Box::new({
+                    let connection_status = connection_status.clone();
+                    let webrtc_status = webrtc_status.clone();
+                    let connected_peers = connected_peers.clone();
+                    let peer_routes = peer_routes.clone();
+                    let peer_capabilities = peer_capabilities.clone();
+                    let recovering_peers = recovering_peers.clone();
+                    let outbound = outbound.clone();
+                    let batcher = batcher.clone();
+                    let gossip = gossip.clone();
+                    let topology = topology.clone();
+                    let delivery_routes = delivery_routes.clone();
+                    let rooms = rooms.clone();
+                    let transactions = transactions.clone();
+                    let tx_endpoint = tx_endpoint.clone();
+                    let connection = connection.clone();
+                    let endpoint_id = endpoint_id.get().clone();
+                    let error_message = error_message.clone();
 
-                if let Err(e) = result {
-                    error_message.set(format!("Connection failed: {:?}", e));
-                }
-            }
+                    move |msg: SignalingMessage| {
+                        // A batch is handled as the transactions in it
+                        for msg in batching::unbatch(msg) {
+                            handle_signaling_message(
+                                msg,
+                                &endpoint_id,
+                                &connection_status,
+                                &webrtc_status,
+                                &connected_peers,
+                                &peer_routes,
+                                &peer_capabilities,
+                                &recovering_peers,
+                                &outbound,
+                                &batcher,
+                                &gossip,
+                                &topology,
+                                &delivery_routes,
+                                &rooms,
+                                &transactions,
+                                &tx_endpoint,
+                                &connection,
+                                &error_message,
+                            );
+                        }
+                    }
+                }),
+            )
+        });
+
+        if let Err(e) = result {
+            online.set(false);
+            error_message.set(format!("Connection failed: {:?}", e));
         }
-    });
+    };
 
     render! {
         div {
@@ -169,6 +241,15 @@ fn app(cx: Scope) -> Element {
                             "{connection_status}"
                         }
                     }
+                    
+                    if !*online.get() {
+                        button {
+                            style: "background: #28a745; color: white; border: none; padding: 8px 16px; border-radius: 6px; cursor: pointer; font-weight: 600;",
+                            disabled: ice_config.value().is_none(),
+                            onclick: move |_| go_online(),
+                            "🌐 Go Online"
+                        }
+                    }
                 }
                 
                 // WebRTC Status Panel
@@ -202,6 +283,27 @@ fn app(cx: Scope) -> Element {
                         style: "margin: 5px 0; color: #2d5a2d;",
                         "P2P Peers: {connected_peers.len()}" 
                     }
+
+                    if ice_config.value().is_some() {
+                        p {
+                            style: "margin: 5px 0; color: #6c757d; font-size: 0.85rem;",
+                            "{turn_availability(ice_config.value())}"
+                        }
+                    }
+
+                    if !outbound.is_empty() {
+                        p {
+                            style: "margin: 5px 0; color: #856404;",
+                            "⏳ {outbound.len()} queued until peers reconnect"
+                        }
+                    }
+
+                    if !send_backlog.is_empty() {
+                        p {
+                            style: "margin: 5px 0; color: #856404;",
+                            "📦 {send_backlog.values().sum::<usize>()} waiting for data channels to drain"
+                        }
+                    }
                     
                     if !connected_peers.is_empty() {
                         ul {
@@ -210,11 +312,60 @@ fn app(cx: Scope) -> Element {
                                 li { 
                                     key: "{peer}",
                                     style: "margin: 5px 0;",
-                                    "🤝 {peer}"
+                                    if recovering_peers.contains(peer) {
+                                        "🔄 {peer} (reconnecting)"
+                                    } else {
+                                        "🤝 {peer}{route_suffix(peer_routes.get(peer))}{backlog_suffix(send_backlog.get(peer))}"
+                                    }
                                 }
                             })
                         }
                     }
+
+                    if *online.get() {
+                        div {
+                            style: "margin-top: 15px; padding-top: 10px; border-top: 1px solid #c3e6c3;",
+                            p {
+                                style: "margin: 5px 0; color: #2d5a2d;",
+                                "Room: {current_room}"
+                            }
+                            div {
+                                style: "display: flex; gap: 8px;",
+                                input {
+                                    r#type: "text",
+                                    placeholder: "Room to create or join",
+                                    value: "{room_input}",
+                                    style: "padding: 6px; border: 1px solid #c3e6c3; border-radius: 6px; flex: 1;",
+                                    oninput: move |evt| room_input.set(evt.value.clone()),
+                                }
+                                button {
+                                    style: "background: #4CAF50; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                                    onclick: move |_| {
+                                        switch_room(room_input.get().trim(), current_room, connection, connected_peers, peer_routes, topology, recovering_peers, send_backlog, webrtc_status, error_message);
+                                        room_input.set(String::new());
+                                    },
+                                    "Join"
+                                }
+                            }
+                            ul {
+                                style: "margin: 10px 0; padding-left: 0; list-style: none;",
+                                rooms.iter().filter(|room| room.room_id != *current_room.get()).map(|room| {
+                                    let room_id = room.room_id.clone();
+                                    render! {
+                                        li {
+                                            key: "{room.room_id}",
+                                            style: "margin: 4px 0;",
+                                            button {
+                                                style: "background: none; border: none; color: #2d5a2d; cursor: pointer; padding: 0;",
+                                                onclick: move |_| switch_room(&room_id, current_room, connection, connected_peers, peer_routes, topology, recovering_peers, send_backlog, webrtc_status, error_message),
+                                                "🚪 {room.room_id} ({room.peer_count} peers)"
+                                            }
+                                        }
+                                    }
+                                })
+                            }
+                        }
+                    }
                 }
                 
                 // Endpoint Info Panel
@@ -236,140 +387,231 @@ fn app(cx: Scope) -> Element {
                 }
             }
             
-            // Transaction Controls
-            div {
-                class: "transaction-controls",
-                style: "background: linear-gradient(135deg, #FF9800 0%, #F57C00 100%); color: white; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
-                
-                h3 { 
-                    style: "margin-top: 0;",
-                    "💸 Send P2P Transaction" 
-                }
-                
-                p {
-                    style: "margin: 0 0 15px 0; opacity: 0.9; font-size: 0.9rem;",
-                    "Transactions sent directly via WebRTC data channels - no server intermediary!"
-                }
-                
+            // Room mesh
+            if *online.get() && mesh_positions.len() > 1 {
                 div {
-                    style: "display: flex; gap: 10px; align-items: center; flex-wrap: wrap;",
-                    
-                    select {
-                        style: "padding: 10px; border: none; border-radius: 6px; font-size: 1rem;",
-                        option { value: "", "Select P2P Peer" }
-                        connected_peers.iter().map(|peer| render! {
-                            option { 
+                    style: "background: #f8f9fa; border: 1px solid #dee2e6; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
+
+                    h3 {
+                        style: "margin-top: 0; color: #495057;",
+                        "🕸️ Room Mesh"
+                    }
+
+                    svg {
+                        width: "240",
+                        height: "240",
+                        view_box: "0 0 240 240",
+                        mesh_edges.iter().filter_map(|(a, b)| Some((a, b, mesh_positions.get(a)?, mesh_positions.get(b)?))).map(|(a, b, (x1, y1), (x2, y2))| render! {
+                            line {
+                                key: "{a}-{b}",
+                                x1: "{x1}",
+                                y1: "{y1}",
+                                x2: "{x2}",
+                                y2: "{y2}",
+                                stroke: "#adb5bd",
+                                stroke_width: "2",
+                            }
+                        })
+                        mesh_positions.iter().map(|(peer, (x, y))| render! {
+                            g {
                                 key: "{peer}",
-                                value: "{peer}",
-                                "{peer}"
+                                circle {
+                                    cx: "{x}",
+                                    cy: "{y}",
+                                    r: "8",
+                                    fill: if peer == endpoint_id.get() { "#FF9800" } else if connected_peers.contains(peer) { "#4CAF50" } else { "#adb5bd" },
+                                }
+                                text {
+                                    x: "{x}",
+                                    y: "{y + 20.0}",
+                                    text_anchor: "middle",
+                                    font_size: "10",
+                                    fill: "#495057",
+                                    "{peer}"
+                                }
                             }
                         })
                     }
-                    
-                    input {
-                        r#type: "number",
-                        placeholder: "Amount",
-                        step: "0.01",
-                        min: "0.01",
-                        style: "padding: 10px; border: none; border-radius: 6px; font-size: 1rem; width: 120px;",
+
+                    p {
+                        style: "margin: 5px 0 0 0; color: #6c757d; font-size: 0.85rem;",
+                        "🟠 this endpoint · 🟢 direct channel · ⚪ reached through other peers"
                     }
+                }
+            }
+
+            // Transaction Controls
+            if *send_panel_open.get() {
+                div {
+                    class: "transaction-controls",
+                    style: "background: linear-gradient(135deg, #FF9800 0%, #F57C00 100%); color: white; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
+                
+                    h3 { 
+                        style: "margin-top: 0;",
+                        "💸 Send P2P Transaction" 
+                    }
+                
+                    p {
+                        style: "margin: 0 0 15px 0; opacity: 0.9; font-size: 0.9rem;",
+                        "Transactions sent directly via WebRTC data channels - no server intermediary!"
+                    }
+                
+                    div {
+                        style: "display: flex; gap: 10px; align-items: center; flex-wrap: wrap;",
                     
-                    button {
-                        style: "background: rgba(255,255,255,0.2); color: white; border: 1px solid rgba(255,255,255,0.3); padding: 10px 20px; border-radius: 6px; cursor: pointer; font-size: 1rem; font-weight: 600;",
-                        disabled: connected_peers.is_empty(),
-                        onclick: move |event| {
-                            if let Some(form) = event.target().and_then(|t| t.closest("div")) {
-                                if let Ok(form_elem) = form.dyn_into::<web_sys::HtmlElement>() {
-                                    let select = form_elem.query_selector("select").unwrap().unwrap();
-                                    let input = form_elem.query_selector("input").unwrap().unwrap();
+                        select {
+                            style: "padding: 10px; border: none; border-radius: 6px; font-size: 1rem;",
+                            option { value: "", "Select P2P Peer" }
+                            connected_peers.iter().map(|peer| render! {
+                                option { 
+                                    key: "{peer}",
+                                    value: "{peer}",
+                                    "{peer}"
+                                }
+                            })
+                            relay_targets.iter().map(|(peer, hop)| render! {
+                                option {
+                                    key: "{peer}",
+                                    value: "{peer}",
+                                    "{peer} (via {hop})"
+                                }
+                            })
+                        }
+                    
+                        input {
+                            r#type: "number",
+                            placeholder: "Amount",
+                            step: "0.01",
+                            min: "0.01",
+                            style: "padding: 10px; border: none; border-radius: 6px; font-size: 1rem; width: 120px;",
+                        }
+                    
+                        button {
+                            style: "background: rgba(255,255,255,0.2); color: white; border: 1px solid rgba(255,255,255,0.3); padding: 10px 20px; border-radius: 6px; cursor: pointer; font-size: 1rem; font-weight: 600;",
+                            disabled: connected_peers.is_empty(),
+                            onclick: move |event| {
+                                if let Some(form) = event.target().and_then(|t| t.closest("div")) {
+                                    if let Ok(form_elem) = form.dyn_into::<web_sys::HtmlElement>() {
+                                        let select = form_elem.query_selector("select").unwrap().unwrap();
+                                        let input = form_elem.query_selector("input").unwrap().unwrap();
                                     
-                                    let select_elem = select.dyn_into::<web_sys::HtmlSelectElement>().unwrap();
-                                    let input_elem = input.dyn_into::<web_sys::HtmlInputElement>().unwrap();
+                                        let select_elem = select.dyn_into::<web_sys::HtmlSelectElement>().unwrap();
+                                        let input_elem = input.dyn_into::<web_sys::HtmlInputElement>().unwrap();
                                     
-                                    let to_peer = select_elem.value();
-                                    let amount_str = input_elem.value();
+                                        let to_peer = select_elem.value();
+                                        let amount_str = input_elem.value();
                                     
-                                    if !to_peer.is_empty() && !amount_str.is_empty() {
-                                        if let Ok(amount) = amount_str.parse::<f64>() {
-                                            if amount > 0.0 && amount <= tx_endpoint.balance {
-                                                let tx = Transaction {
-                                                    id: Uuid::new_v4().to_string(),
-                                                    from: endpoint_id.get().clone(),
-                                                    to: to_peer,
-                                                    amount,
-                                                    timestamp: js_sys::Date::now() as u64,
-                                                    signature: format!("webrtc_sig_{}", tx_endpoint.transaction_count),
-                                                    status: "confirmed".to_string(),
-                                                };
+                                        if !to_peer.is_empty() && !amount_str.is_empty() {
+                                            if let Ok(amount) = amount_str.trim().parse::<Money>() {
+                                                let major = amount.to_major();
+                                                if amount.currency() == NATIVE_ASSET && major > 0.0 && major <= tx_endpoint.balance {
+                                                    let tx = Transaction {
+                                                        id: Uuid::new_v4().to_string(),
+                                                        from_endpoint: endpoint_id.get().clone(),
+                                                        to_endpoint: to_peer,
+                                                        amount,
+                                                        timestamp: Utc::now(),
+                                                        signature: format!("webrtc_sig_{}", tx_endpoint.transaction_count),
+                                                        status: TransactionStatus::Pending,
+                                                        kind: TransactionKind::Transfer,
+                                                        risk_score: None,
+                                                        parent_tx_id: None,
+                                                        sequence: None,
+                                                        public_key: None,
+                                                    };
                                                 
-                                                // Update local endpoint state
-                                                tx_endpoint.with_mut(|ep| {
-                                                    let _ = ep.process_transaction(&tx);
-                                                });
+                                                    // Update local endpoint state
+                                                    tx_endpoint.with_mut(|ep| {
+                                                        let _ = ep.process_transaction(&tx);
+                                                    });
                                                 
-                                                // Add to local transactions
-                                                transactions.with_mut(|txs| {
-                                                    txs.insert(tx.id.clone(), tx.clone());
-                                                });
+                                                    // Add to local transactions
+                                                    transactions.with_mut(|txs| {
+                                                        txs.insert(tx.id.clone(), tx.clone());
+                                                    });
                                                 
-                                                // Send via WebRTC
-                                                connection.with_mut(|conn| {
-                                                    if let Err(e) = conn.send_transaction(&tx) {
-                                                        error_message.set(format!("Failed to send via WebRTC: {:?}", e));
+                                                    // Send via WebRTC, or hold it for the peer's recovery;
+                                                    // peers we have no channel to are reached through others
+                                                    let sent = if connected_peers.contains(&tx.to_endpoint) {
+                                                        send_or_queue(&tx, connection, recovering_peers, outbound, batcher)
+                                                    } else {
+                                                        send_relayed(&tx, endpoint_id.get(), topology, connection, outbound)
+                                                    };
+                                                    if sent {
+                                                        mark_relayed(&tx.id, transactions);
+                                                    } else {
+                                                        error_message.set(format!("{} is unreachable, transaction queued", tx.to_endpoint));
                                                     }
-                                                });
                                                 
-                                                // Clear form
-                                                select_elem.set_value("");
-                                                input_elem.set_value("");
-                                            } else {
-                                                error_message.set("Invalid amount or insufficient balance".to_string());
+                                                    // Clear form
+                                                    select_elem.set_value("");
+                                                    input_elem.set_value("");
+                                                } else {
+                                                    error_message.set("Invalid amount or insufficient balance".to_string());
+                                                }
                                             }
                                         }
                                     }
                                 }
-                            }
-                        },
-                        "Send Direct P2P"
-                    }
+                            },
+                            "Send Direct P2P"
+                        }
                     
-                    button {
-                        style: "background: rgba(255,255,255,0.2); color: white; border: 1px solid rgba(255,255,255,0.3); padding: 10px 20px; border-radius: 6px; cursor: pointer; font-size: 1rem;",
-                        disabled: connected_peers.is_empty(),
-                        onclick: move |_| {
-                            if !connected_peers.is_empty() {
-                                let random_peer = &connected_peers[0];
-                                let tx = Transaction {
-                                    id: Uuid::new_v4().to_string(),
-                                    from: endpoint_id.get().clone(),
-                                    to: random_peer.clone(),
-                                    amount: 25.0,
-                                    timestamp: js_sys::Date::now() as u64,
-                                    signature: format!("webrtc_test_{}", tx_endpoint.transaction_count),
-                                    status: "confirmed".to_string(),
-                                };
+                        button {
+                            style: "background: rgba(255,255,255,0.2); color: white; border: 1px solid rgba(255,255,255,0.3); padding: 10px 20px; border-radius: 6px; cursor: pointer; font-size: 1rem;",
+                            disabled: connected_peers.is_empty(),
+                            onclick: move |_| {
+                                if !connected_peers.is_empty() {
+                                    let random_peer = &connected_peers[0];
+                                    let tx = Transaction {
+                                        id: Uuid::new_v4().to_string(),
+                                        from_endpoint: endpoint_id.get().clone(),
+                                        to_endpoint: random_peer.clone(),
+                                        amount: Money::new(25 * MINOR_PER_MAJOR, NATIVE_ASSET),
+                                        timestamp: Utc::now(),
+                                        signature: format!("webrtc_test_{}", tx_endpoint.transaction_count),
+                                        status: TransactionStatus::Pending,
+                                        kind: TransactionKind::Transfer,
+                                        risk_score: None,
+                                        parent_tx_id: None,
+                                        sequence: None,
+                                        public_key: None,
+                                    };
                                 
-                                tx_endpoint.with_mut(|ep| {
-                                    let _ = ep.process_transaction(&tx);
-                                });
+                                    tx_endpoint.with_mut(|ep| {
+                                        let _ = ep.process_transaction(&tx);
+                                    });
                                 
-                                transactions.with_mut(|txs| {
-                                    txs.insert(tx.id.clone(), tx.clone());
-                                });
+                                    transactions.with_mut(|txs| {
+                                        txs.insert(tx.id.clone(), tx.clone());
+                                    });
                                 
-                                connection.with_mut(|conn| {
-                                    let _ = conn.send_transaction(&tx);
-                                });
-                            }
-                        },
-                        "Test $25 P2P"
+                                    if send_or_queue(&tx, connection, recovering_peers, outbound, batcher) {
+                                        mark_relayed(&tx.id, transactions);
+                                    }
+                                }
+                            },
+                            "Test $25 P2P"
+                        }
                     }
-                }
                 
-                if connected_peers.is_empty() {
-                    p {
-                        style: "margin: 10px 0 0 0; opacity: 0.8; font-size: 0.9rem;",
-                        "⏳ Waiting for WebRTC peer connections..."
+                    if connected_peers.is_empty() {
+                        p {
+                            style: "margin: 10px 0 0 0; opacity: 0.8; font-size: 0.9rem;",
+                            "⏳ Waiting for WebRTC peer connections..."
+                        }
+                    }
+                }
+            } else {
+                div {
+                    style: "text-align: center; margin-bottom: 20px;",
+                    button {
+                        style: "background: linear-gradient(135deg, #FF9800 0%, #F57C00 100%); color: white; border: none; padding: 12px 24px; border-radius: 8px; cursor: pointer; font-size: 1rem; font-weight: 600;",
+                        onclick: move |_| {
+                            send_panel_open.set(true);
+                            go_online();
+                        },
+                        "💸 Open Send Panel"
                     }
                 }
             }
@@ -398,33 +640,42 @@ fn app(cx: Scope) -> Element {
                                 key: "{id}",
                                 style: format!(
                                     "border-left: 4px solid {}; background: linear-gradient(90deg, {}, #f8f9fa); margin: 10px 0; padding: 15px; border-radius: 0 8px 8px 0;",
-                                    if tx.from == *endpoint_id.get() { "#FF9800" } else { "#4CAF50" },
-                                    if tx.from == *endpoint_id.get() { "rgba(255, 152, 0, 0.1)" } else { "rgba(76, 175, 80, 0.1)" }
+                                    if tx.from_endpoint == *endpoint_id.get() { "#FF9800" } else { "#4CAF50" },
+                                    if tx.from_endpoint == *endpoint_id.get() { "rgba(255, 152, 0, 0.1)" } else { "rgba(76, 175, 80, 0.1)" }
                                 ),
                                 
                                 div {
                                     style: "display: flex; justify-content: space-between; align-items: center; margin-bottom: 8px;",
                                     strong {
                                         style: "color: #495057;",
-                                        if tx.from == *endpoint_id.get() { "🚀 Sent via WebRTC" } else { "📥 Received via WebRTC" }
+                                        if tx.from_endpoint == *endpoint_id.get() { "🚀 Sent via WebRTC" }
+                                        else if tx.to_endpoint == *endpoint_id.get() { "📥 Received via WebRTC" }
+                                        else { "🛰️ Heard via gossip" }
                                     }
                                     span {
-                                        style: "background: #4CAF50; color: white; padding: 2px 8px; border-radius: 12px; font-size: 0.8rem;",
-                                        "✓ P2P Direct"
+                                        style: format!(
+                                            "background: {}; color: white; padding: 2px 8px; border-radius: 12px; font-size: 0.8rem;",
+                                            match tx.status {
+                                                TransactionStatus::Confirmed | TransactionStatus::Acknowledged => "#4CAF50",
+                                                TransactionStatus::Failed | TransactionStatus::Expired => "#dc3545",
+                                                _ => "#FF9800",
+                                            }
+                                        ),
+                                        "{delivery_badge(tx, endpoint_id.get())}"
                                     }
                                 }
                                 
                                 p { 
                                     style: "margin: 5px 0; color: #495057;",
-                                    "💰 Amount: ${tx.amount:.2}" 
+                                    "💰 Amount: {tx.amount}" 
                                 }
                                 p { 
                                     style: "margin: 5px 0; color: #6c757d; font-size: 0.9rem;",
-                                    "🔗 {tx.from} ↔ {tx.to}" 
+                                    "🔗 {tx.from_endpoint} ↔ {tx.to_endpoint}{via_suffix(delivery_routes.get().get(id))}" 
                                 }
                                 p { 
                                     style: "margin: 5px 0; color: #6c757d; font-size: 0.8rem;",
-                                    "🕐 {format_timestamp(tx.timestamp)}"
+                                    "🕐 {format_timestamp(&tx.timestamp)}"
                                 }
                                 p { 
                                     style: "margin: 5px 0; color: #6c757d; font-size: 0.8rem; font-family: monospace;",
@@ -445,10 +696,22 @@ fn app(cx: Scope) -> Element {
 
 fn handle_signaling_message(
     msg: SignalingMessage,
+    endpoint_id: &str,
     connection_status: &UseState<String>,
     webrtc_status: &UseState<String>,
     connected_peers: &UseState<Vec<String>>,
+    peer_routes: &UseState<HashMap<String, Route>>,
+    peer_capabilities: &UseState<HashMap<String, Capabilities>>,
+    recovering_peers: &UseState<HashSet<String>>,
+    outbound: &UseState<Vec<Transaction>>,
+    batcher: &UseRef<Batcher>,
+    gossip: &UseRef<Gossip>,
+    topology: &UseRef<Topology>,
+    delivery_routes: &UseState<HashMap<String, Vec<String>>>,
+    rooms: &UseState<Vec<RoomInfo>>,
     transactions: &UseState<HashMap<String, Transaction>>,
+    tx_endpoint: &UseState<TxEndpoint>,
+    connection: &UseState<WebRTCConnection>,
     error_message: &UseState<String>,
 ) {
     web_sys::console::log_1(&format!("Handling WebRTC message: {:?}", msg.message_type).into());
@@ -459,15 +722,57 @@ fn handle_signaling_message(
         },
         "room-joined" => {
             connection_status.set("Connected".to_string());
+            let capabilities: HashMap<String, Capabilities> = msg
+                .peer_features
+                .iter()
+                .flatten()
+                .map(|(peer_id, features)| {
+                    (peer_id.clone(), Capabilities::from_names(tx_core::PROTOCOL_VERSION, features))
+                })
+                .collect();
+            for (peer_id, peer) in &capabilities {
+                batcher.write_silent().set_peer_batches(peer_id, peer.supports(Capability::TransactionBatches));
+                gossip.write_silent().set_peer_gossips(peer_id, peer.supports(Capability::Gossip));
+                topology.write_silent().set_peer_relays(peer_id, peer.supports(Capability::Relay));
+            }
+            peer_capabilities.set(capabilities);
             if let Some(peers) = msg.peers {
                 // WebRTC connection establishment will happen via signaling
                 webrtc_status.set("Establishing P2P...".to_string());
             }
+            if let Err(e) = connection.get().list_rooms() {
+                web_sys::console::error_1(&format!("Listing rooms failed: {:?}", e).into());
+            }
+        },
+        "rooms" => {
+            let mut listed = msg.rooms.unwrap_or_default();
+            listed.sort_by(|a, b| b.peer_count.cmp(&a.peer_count).then_with(|| a.room_id.cmp(&b.room_id)));
+            rooms.set(listed);
         },
         "peer-joined" => {
+            let capabilities = Capabilities::from_message(&msg);
             if let Some(peer_id) = msg.peer_id {
+                batcher.write_silent().set_peer_batches(&peer_id, capabilities.supports(Capability::TransactionBatches));
+                gossip.write_silent().set_peer_gossips(&peer_id, capabilities.supports(Capability::Gossip));
+                topology.write_silent().set_peer_relays(&peer_id, capabilities.supports(Capability::Relay));
+                peer_capabilities.with_mut(|peers| {
+                    peers.insert(peer_id.clone(), capabilities);
+                });
                 webrtc_status.set(format!("Connecting to {}...", peer_id));
                 // WebRTC connection logic handled in webrtc_connection.rs
+                // Our map for the newcomer, which has none yet
+                announce_links(endpoint_id, connected_peers, topology, connection);
+            }
+        },
+        "peer-left" => {
+            if let Some(peer_id) = msg.peer_id {
+                topology.write().remove_peer(&peer_id);
+            }
+        },
+        topology::PEER_MAP_TYPE => {
+            // Set by the server, so a peer can only speak for itself
+            if let Some(peer_id) = msg.from_peer {
+                topology.write().set_links(&peer_id, &msg.peers.unwrap_or_default());
             }
         },
         "webrtc-connected" => {
@@ -477,24 +782,186 @@ fn handle_signaling_message(
                         peers.push(peer_id.clone());
                     }
                 });
+                recovering_peers.with_mut(|recovering| {
+                    recovering.remove(&peer_id);
+                });
                 webrtc_status.set("Connected".to_string());
+                announce_links(endpoint_id, connected_peers, topology, connection);
+                // Also after an ICE restart, which may settle on another path
+                refresh_route(&peer_id, connection, peer_routes);
+                replay_outbound(&peer_id, connection, recovering_peers, outbound, batcher, transactions);
             }
         },
         "webrtc-disconnected" => {
+            // ICE went `disconnected` or `failed`, usually a network change.
+            // Keep the peer and renegotiate with an ICE restart over the
+            // signaling channel; `webrtc-connected` follows on success.
+            if let Some(peer_id) = msg.peer_id {
+                recovering_peers.with_mut(|recovering| {
+                    recovering.insert(peer_id.clone());
+                });
+                // Ones waiting on the old channel's buffer, then held
+                // ones, wait for the replay with the rest
+                let mut backlog = Vec::new();
+                connection.with_mut(|conn| backlog = conn.take_backlog(&peer_id));
+                let held = batcher.write_silent().take(&peer_id);
+                outbound.with_mut(|queue| {
+                    queue.extend(backlog);
+                    queue.extend(held);
+                });
+                webrtc_status.set(format!("Reconnecting to {}...", peer_id));
+                connection.with_mut(|conn| {
+                    if let Err(e) = conn.restart_ice(&peer_id) {
+                        web_sys::console::error_1(&format!("ICE restart for {} failed: {:?}", peer_id, e).into());
+                    }
+                });
+            }
+        },
+        "webrtc-failed" => {
+            // Recovery gave up; transactions queued for the peer stay queued
+            // until it connects again.
             if let Some(peer_id) = msg.peer_id {
                 connected_peers.with_mut(|peers| {
                     peers.retain(|p| p != &peer_id);
                 });
+                peer_routes.with_mut(|routes| {
+                    routes.remove(&peer_id);
+                });
+                recovering_peers.with_mut(|recovering| {
+                    recovering.remove(&peer_id);
+                });
+                // Including ones the closed channel's queue still held
+                let mut backlog = Vec::new();
+                connection.with_mut(|conn| backlog = conn.take_backlog(&peer_id));
+                outbound.with_mut(|queue| queue.extend(backlog));
                 if connected_peers.is_empty() {
                     webrtc_status.set("Not Connected".to_string());
                 }
+                announce_links(endpoint_id, connected_peers, topology, connection);
+            }
+        },
+        // Gossip is handled as if it came directly: applied if it's ours,
+        // logged if not
+        "transaction-p2p" | gossip::GOSSIP_TYPE => {
+            if let Some(tx) = msg.transaction.clone() {
+                let fresh = gossip.write_silent().observe(&tx.id);
+                if !fresh || transactions.get().contains_key(&tx.id) {
+                    return;
+                }
+                let forwards = gossip.read().forward(&tx, &msg, connected_peers.get());
+                send_gossip(forwards, connection);
+                if tx.to_endpoint == endpoint_id {
+                    let mut applied = Ok(());
+                    tx_endpoint.with_mut(|ep| applied = ep.process_transaction(&tx));
+                    let (result, reason) = match &applied {
+                        Ok(()) => (AckResult::Applied, None),
+                        Err(e) => (AckResult::Failed, Some(e.clone())),
+                    };
+                    // Senders from before acks would take it for garbage
+                    let acks = peer_capabilities.get().get(&tx.from_endpoint).is_some_and(|peer| peer.supports(Capability::Acks));
+                    if acks && connected_peers.current().contains(&tx.from_endpoint) {
+                        connection.with_mut(|conn| {
+                            if let Err(e) = conn.send_ack(&tx, result, reason) {
+                                web_sys::console::error_1(&e);
+                            }
+                        });
+                    } else if acks {
+                        // Back the way a relayed transfer came
+                        let ack = SignalingMessage {
+                            target_peer: Some(tx.from_endpoint.clone()),
+                            transaction: Some(tx.clone()),
+                            result: Some(result),
+                            reason,
+                            ..SignalingMessage::new("transaction-ack")
+                        };
+                        let wrapped = relay::wrap(endpoint_id, &tx.from_endpoint, ack, &topology.read());
+                        match wrapped {
+                            Some(envelope) => send_relay(envelope, connection),
+                            None => web_sys::console::warn_1(&format!("No route to ack {}", tx.from_endpoint).into()),
+                        }
+                    }
+                    let status = match result {
+                        AckResult::Applied => TransactionStatus::Acknowledged,
+                        AckResult::Failed => TransactionStatus::Failed,
+                    };
+                    let tx = Transaction { status, ..tx };
+                    transactions.with_mut(|txs| {
+                        txs.insert(tx.id.clone(), tx);
+                    });
+                } else {
+                    transactions.with_mut(|txs| {
+                        txs.insert(tx.id.clone(), tx);
+                    });
+                }
             }
         },
-        "transaction-p2p" => {
-            if let Some(tx) = msg.transaction {
+        "transaction-ack" => {
+            if let (Some(tx), Some(result)) = (msg.transaction, msg.result) {
+                // Data channels are point-to-point, so only the recipient can answer
+                let expected = tx.from_endpoint == endpoint_id
+                    && msg.from_peer.as_deref().is_none_or(|peer| peer == tx.to_endpoint);
+                if !expected {
+                    return;
+                }
+                let status = match result {
+                    AckResult::Applied => TransactionStatus::Confirmed,
+                    AckResult::Failed => TransactionStatus::Failed,
+                };
+                let mut advanced = false;
                 transactions.with_mut(|txs| {
-                    txs.insert(tx.id.clone(), tx);
+                    if let Some(ours) = txs.get_mut(&tx.id) {
+                        if let Ok(next) = ours.status.transition_to(status) {
+                            advanced = next != ours.status;
+                            ours.status = next;
+                        }
+                    }
                 });
+                if advanced && result == AckResult::Failed {
+                    tx_endpoint.with_mut(|ep| ep.refund_transaction(&tx));
+                    error_message.set(format!(
+                        "{} could not apply transaction {}: {}",
+                        tx.to_endpoint,
+                        &tx.id[..8],
+                        msg.reason.as_deref().unwrap_or("no reason given")
+                    ));
+                }
+            }
+        },
+        relay::RELAY_TYPE | relay::RECEIPT_TYPE => {
+            // Not matched on directly: the borrow would outlive the handling below
+            let step = relay::step(endpoint_id, msg, &topology.read());
+            match step {
+                Step::Deliver { message, receipt } => {
+                    if let Some(receipt) = receipt {
+                        send_relay(receipt, connection);
+                    }
+                    if message.message_type == relay::RECEIPT_TYPE {
+                        record_delivery(&message, endpoint_id, delivery_routes);
+                        return;
+                    }
+                    handle_signaling_message(
+                        message,
+                        endpoint_id,
+                        connection_status,
+                        webrtc_status,
+                        connected_peers,
+                        peer_routes,
+                        peer_capabilities,
+                        recovering_peers,
+                        outbound,
+                        batcher,
+                        gossip,
+                        topology,
+                        delivery_routes,
+                        rooms,
+                        transactions,
+                        tx_endpoint,
+                        connection,
+                        error_message,
+                    );
+                }
+                Step::Forward(next) => send_relay(next, connection),
+                Step::Drop(reason) => web_sys::console::warn_1(&format!("Dropped relay: {}", reason).into()),
             }
         },
         "error" => {
@@ -506,7 +973,287 @@ fn handle_signaling_message(
     }
 }
 
-fn format_timestamp(timestamp: u64) -> String {
-    let date = js_sys::Date::new(&(timestamp.into()));
+/// Leaves the current room for `room_id`, creating it if it's empty. The
+/// connection closes every peer connection and data channel from the old
+/// room before joining; transactions queued for those peers stay queued
+/// until they are reachable again.
+#[allow(clippy::too_many_arguments)]
+fn switch_room(
+    room_id: &str,
+    current_room: &UseState<String>,
+    connection: &UseState<WebRTCConnection>,
+    connected_peers: &UseState<Vec<String>>,
+    peer_routes: &UseState<HashMap<String, Route>>,
+    topology: &UseRef<Topology>,
+    recovering_peers: &UseState<HashSet<String>>,
+    send_backlog: &UseState<HashMap<String, usize>>,
+    webrtc_status: &UseState<String>,
+    error_message: &UseState<String>,
+) {
+    if room_id.is_empty() || room_id == current_room.get().as_str() {
+        return;
+    }
+    let mut switched = Ok(());
+    connection.with_mut(|conn| switched = conn.switch_room(room_id));
+    match switched {
+        Ok(()) => {
+            current_room.set(room_id.to_string());
+            connected_peers.set(Vec::new());
+            peer_routes.set(HashMap::new());
+            topology.write().clear();
+            recovering_peers.set(HashSet::new());
+            send_backlog.set(HashMap::new());
+            webrtc_status.set("Not Connected".to_string());
+        }
+        Err(e) => error_message.set(format!("Could not switch rooms: {:?}", e)),
+    }
+}
+
+/// Looks up which path `peer_id`'s connection settled on, for the peer list.
+fn refresh_route(
+    peer_id: &str,
+    connection: &UseState<WebRTCConnection>,
+    peer_routes: &UseState<HashMap<String, Route>>,
+) {
+    let Some(peer_connection) = connection.get().peer_connection(peer_id) else {
+        return;
+    };
+    let peer_id = peer_id.to_string();
+    let peer_routes = peer_routes.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Some(route) = ice::selected_route(&peer_connection).await {
+            peer_routes.with_mut(|routes| {
+                routes.insert(peer_id, route);
+            });
+        }
+    });
+}
+
+fn route_suffix(route: Option<&Route>) -> String {
+    route.map(|route| format!(" · {}", route.label())).unwrap_or_default()
+}
+
+/// The peers a relayed transfer passed through, between its ends.
+fn via_suffix(route: Option<&Vec<String>>) -> String {
+    match route {
+        Some(route) if route.len() > 2 => format!(" via {}", route[1..route.len() - 1].join(" → ")),
+        _ => String::new(),
+    }
+}
+
+fn backlog_suffix(depth: Option<&usize>) -> String {
+    depth.map(|depth| format!(" · {} waiting to send", depth)).unwrap_or_default()
+}
+
+fn turn_availability(ice: Option<&IceConfig>) -> &'static str {
+    if ice.is_some_and(IceConfig::has_turn) {
+        "TURN relay available"
+    } else {
+        "No TURN relay configured; peers behind strict NATs may not connect"
+    }
+}
+
+/// Sends `tx` over its peer's data channel, or holds it for the peer's
+/// next batch. Returns false, with `tx` queued for replay, if the channel
+/// is recovering or the send fails.
+fn send_or_queue(
+    tx: &Transaction,
+    connection: &UseState<WebRTCConnection>,
+    recovering_peers: &UseState<HashSet<String>>,
+    outbound: &UseState<Vec<Transaction>>,
+    batcher: &UseRef<Batcher>,
+) -> bool {
+    if recovering_peers.contains(&tx.to_endpoint) {
+        outbound.with_mut(|queue| queue.push(tx.clone()));
+        return false;
+    }
+    // Not matched on directly: the borrow would outlive the flush below
+    let push = batcher.write_silent().push(tx.clone());
+    match push {
+        Push::Held => true,
+        Push::Scheduled => {
+            schedule_flush(&tx.to_endpoint, connection, outbound, batcher);
+            true
+        }
+        Push::Send(messages) => send_messages(messages, connection, outbound),
+    }
+}
+
+/// Sends whatever is held for `peer_id` once the flush interval is up.
+fn schedule_flush(
+    peer_id: &str,
+    connection: &UseState<WebRTCConnection>,
+    outbound: &UseState<Vec<Transaction>>,
+    batcher: &UseRef<Batcher>,
+) {
+    let delay_ms = batcher.read().config().flush_interval_ms;
+    let peer_id = peer_id.to_string();
+    let connection = connection.clone();
+    let outbound = outbound.clone();
+    let batcher = batcher.clone();
+    gloo_timers::callback::Timeout::new(delay_ms, move || {
+        let flushed = batcher.write_silent().flush(&peer_id);
+        if let Some(message) = flushed {
+            send_messages(vec![message], &connection, &outbound);
+        }
+    })
+    .forget();
+}
+
+/// Sends `messages` in order. On a failure, the transactions in that
+/// message and every later one are queued for replay.
+fn send_messages(
+    messages: Vec<SignalingMessage>,
+    connection: &UseState<WebRTCConnection>,
+    outbound: &UseState<Vec<Transaction>>,
+) -> bool {
+    let mut messages = messages.into_iter();
+    while let Some(message) = messages.next() {
+        let mut sent = false;
+        connection.with_mut(|conn| {
+            sent = match &message.transaction {
+                Some(tx) => conn.send_transaction(tx).is_ok(),
+                None => conn.send_batch(&message).is_ok(),
+            }
+        });
+        if !sent {
+            outbound.with_mut(|queue| {
+                queue.extend(batching::transactions(&message));
+                queue.extend(messages.flat_map(|message| batching::transactions(&message)));
+            });
+            return false;
+        }
+    }
+    true
+}
+
+/// Resends, in order, what was queued for `peer_id` while it was away.
+/// Stops at the first failure so later transactions don't overtake it.
+fn replay_outbound(
+    peer_id: &str,
+    connection: &UseState<WebRTCConnection>,
+    recovering_peers: &UseState<HashSet<String>>,
+    outbound: &UseState<Vec<Transaction>>,
+    batcher: &UseRef<Batcher>,
+    transactions: &UseState<HashMap<String, Transaction>>,
+) {
+    let mut queued = Vec::new();
+    outbound.with_mut(|queue| {
+        let (for_peer, rest): (Vec<_>, Vec<_>) = queue.drain(..).partition(|tx| tx.to_endpoint == peer_id);
+        queued = for_peer;
+        *queue = rest;
+    });
+    let mut queued = queued.into_iter();
+    while let Some(tx) = queued.next() {
+        if !send_or_queue(&tx, connection, recovering_peers, outbound, batcher) {
+            outbound.with_mut(|queue| queue.extend(queued));
+            return;
+        }
+        mark_relayed(&tx.id, transactions);
+    }
+}
+
+/// Sends `tx` toward a recipient we have no channel to, through the peers
+/// that do. Returns false, with `tx` queued until the recipient connects
+/// directly, if the room's maps show no route or the send fails.
+fn send_relayed(
+    tx: &Transaction,
+    endpoint_id: &str,
+    topology: &UseRef<Topology>,
+    connection: &UseState<WebRTCConnection>,
+    outbound: &UseState<Vec<Transaction>>,
+) -> bool {
+    let wrapped = relay::wrap_transaction(endpoint_id, tx, &topology.read());
+    let mut sent = false;
+    if let Some(envelope) = wrapped {
+        connection.with_mut(|conn| sent = conn.send_message(&envelope).is_ok());
+    }
+    if !sent {
+        outbound.with_mut(|queue| queue.push(tx.clone()));
+    }
+    sent
+}
+
+/// Best effort, like gossip: an envelope lost on the way leaves its sender
+/// without a receipt or an ack.
+fn send_relay(message: SignalingMessage, connection: &UseState<WebRTCConnection>) {
+    connection.with_mut(|conn| {
+        if let Err(e) = conn.send_message(&message) {
+            web_sys::console::warn_1(&e);
+        }
+    });
+}
+
+/// Notes the route a relayed transfer of ours took to its recipient.
+fn record_delivery(receipt: &SignalingMessage, endpoint_id: &str, delivery_routes: &UseState<HashMap<String, Vec<String>>>) {
+    let (Some(tx), Some(route)) = (&receipt.transaction, &receipt.route) else {
+        return;
+    };
+    // Receipts for relayed acks come back to the transfer's recipient
+    if tx.from_endpoint != endpoint_id {
+        return;
+    }
+    delivery_routes.with_mut(|routes| {
+        routes.insert(tx.id.clone(), route.clone());
+    });
+}
+
+/// Records which peers we have a channel to and tells the room, so
+/// everyone's routes take them into account.
+fn announce_links(
+    endpoint_id: &str,
+    connected_peers: &UseState<Vec<String>>,
+    topology: &UseRef<Topology>,
+    connection: &UseState<WebRTCConnection>,
+) {
+    // The handler's copy of the state is as old as the connection
+    let peers = connected_peers.current();
+    topology.write().set_links(endpoint_id, &peers);
+    if let Err(e) = connection.get().send_signaling(&Topology::announcement(&peers)) {
+        web_sys::console::warn_1(&e);
+    }
+}
+
+/// Best effort: a peer that misses gossip hears it from another, or not
+/// at all, which only leaves a gap in its view of the room.
+fn send_gossip(messages: Vec<SignalingMessage>, connection: &UseState<WebRTCConnection>) {
+    if messages.is_empty() {
+        return;
+    }
+    connection.with_mut(|conn| {
+        for message in &messages {
+            if let Err(e) = conn.send_message(message) {
+                web_sys::console::warn_1(&e);
+            }
+        }
+    });
+}
+
+/// Our copy of a transaction once it is on the data channel.
+fn mark_relayed(tx_id: &str, transactions: &UseState<HashMap<String, Transaction>>) {
+    transactions.with_mut(|txs| {
+        if let Some(tx) = txs.get_mut(tx_id) {
+            if let Ok(next) = tx.status.transition_to(TransactionStatus::Relayed) {
+                tx.status = next;
+            }
+        }
+    });
+}
+
+/// Badge text for a logged transaction: delivery state for ones we sent.
+fn delivery_badge(tx: &Transaction, endpoint_id: &str) -> String {
+    if tx.from_endpoint != endpoint_id {
+        return "✓ P2P Direct".to_string();
+    }
+    match tx.status {
+        TransactionStatus::Confirmed => "✓ Applied".to_string(),
+        TransactionStatus::Failed => "✗ Not applied".to_string(),
+        TransactionStatus::Relayed => "⏳ Awaiting ack".to_string(),
+        status => status.to_string(),
+    }
+}
+
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    let date = js_sys::Date::new(&(timestamp.timestamp_millis() as f64).into());
     date.to_locale_string("en-US", &js_sys::Object::new()).as_string().unwrap_or_default()
 }
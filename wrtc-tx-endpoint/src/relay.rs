@@ -0,0 +1,128 @@
+//! Routed delivery to peers we have no data channel to. The message goes
+//! in a `relay-to` envelope, over the channels that do exist, to the next
+//! hop `Topology` picks; each endpoint on the way picks the next from its
+//! own view of the mesh. The recipient opens the envelope, handles what
+//! is inside as if it had come directly, and sends a `relay-receipt` back
+//! the way the envelope came, so the sender learns it arrived and by which
+//! route.
+//!
+//! The envelope records the peers it has passed through. An endpoint never
+//! hands it to one of them, and drops it if it finds itself on the list,
+//! so stale maps can't send it round in circles; its `ttl`, as on gossip,
+//! caps the hops.
+
+use crate::topology::Topology;
+use crate::{SignalingMessage, Transaction};
+
+pub const RELAY_TYPE: &str = "relay-to";
+pub const RECEIPT_TYPE: &str = "relay-receipt";
+
+/// Hops an envelope may take, as many as gossip's.
+pub const DEFAULT_TTL: u8 = crate::gossip::DEFAULT_TTL;
+
+/// What to do with a `relay-to` or `relay-receipt` that reached us.
+#[derive(Debug)]
+pub enum Step {
+    /// It's for us: handle `message`, and send `receipt`, if there is one,
+    /// back along the route.
+    Deliver { message: SignalingMessage, receipt: Option<SignalingMessage> },
+    /// Pass it on, to its `target_peer`.
+    Forward(SignalingMessage),
+    Drop(String),
+}
+
+/// `payload` in an envelope for `destination`, addressed to its first hop
+/// from us. None if the room's maps show no route.
+pub fn wrap(me: &str, destination: &str, payload: SignalingMessage, topology: &Topology) -> Option<SignalingMessage> {
+    let hop = topology.next_hop(me, destination, &[])?;
+    Some(SignalingMessage {
+        target_peer: Some(hop),
+        destination: Some(destination.to_string()),
+        route: Some(vec![me.to_string()]),
+        ttl: Some(DEFAULT_TTL),
+        payload: Some(Box::new(payload)),
+        ..SignalingMessage::new(RELAY_TYPE)
+    })
+}
+
+/// A transfer in an envelope, as `send_transaction` would have sent it.
+pub fn wrap_transaction(me: &str, tx: &Transaction, topology: &Topology) -> Option<SignalingMessage> {
+    let payload = SignalingMessage {
+        target_peer: Some(tx.to_endpoint.clone()),
+        transaction: Some(tx.clone()),
+        ..SignalingMessage::new("transaction-p2p")
+    };
+    wrap(me, &tx.to_endpoint, payload, topology)
+}
+
+pub fn step(me: &str, message: SignalingMessage, topology: &Topology) -> Step {
+    match message.message_type.as_str() {
+        RELAY_TYPE => step_envelope(me, message, topology),
+        RECEIPT_TYPE => step_receipt(me, message),
+        other => Step::Drop(format!("{} is not relayed", other)),
+    }
+}
+
+fn step_envelope(me: &str, mut envelope: SignalingMessage, topology: &Topology) -> Step {
+    let (Some(destination), Some(mut route), Some(payload)) =
+        (envelope.destination.take(), envelope.route.take(), envelope.payload.take())
+    else {
+        return Step::Drop("incomplete relay-to".to_string());
+    };
+    let Some(origin) = route.first().cloned() else {
+        return Step::Drop("relay-to without a sender".to_string());
+    };
+    if route.iter().any(|peer| peer == me) {
+        return Step::Drop(format!("relay from {} looped back to us", origin));
+    }
+
+    if destination == me {
+        if matches!(payload.message_type.as_str(), RELAY_TYPE | RECEIPT_TYPE) {
+            return Step::Drop("nested relay".to_string());
+        }
+        let previous = route.last().cloned();
+        route.push(me.to_string());
+        let receipt = SignalingMessage {
+            target_peer: previous,
+            destination: Some(origin.clone()),
+            route: Some(route),
+            transaction: payload.transaction.clone(),
+            ..SignalingMessage::new(RECEIPT_TYPE)
+        };
+        // As if it came straight from the sender
+        let message = SignalingMessage { from_peer: Some(origin), ..*payload };
+        return Step::Deliver { message, receipt: Some(receipt) };
+    }
+
+    let ttl = envelope.ttl.unwrap_or(DEFAULT_TTL);
+    if ttl <= 1 {
+        return Step::Drop(format!("relay from {} to {} ran out of hops", origin, destination));
+    }
+    let Some(hop) = topology.next_hop(me, &destination, &route) else {
+        return Step::Drop(format!("no route on to {}", destination));
+    };
+    route.push(me.to_string());
+    Step::Forward(SignalingMessage {
+        target_peer: Some(hop),
+        destination: Some(destination),
+        route: Some(route),
+        ttl: Some(ttl - 1),
+        payload: Some(payload),
+        ..SignalingMessage::new(RELAY_TYPE)
+    })
+}
+
+/// Receipts retrace the route recorded in them, so they need no map.
+fn step_receipt(me: &str, mut receipt: SignalingMessage) -> Step {
+    if receipt.destination.as_deref() == Some(me) {
+        return Step::Deliver { message: receipt, receipt: None };
+    }
+    let route = receipt.route.as_deref().unwrap_or_default();
+    let previous = match route.iter().position(|peer| peer == me) {
+        Some(i) if i > 0 => route[i - 1].clone(),
+        _ => return Step::Drop("relay-receipt off its route".to_string()),
+    };
+    receipt.target_peer = Some(previous);
+    receipt.from_peer = None;
+    Step::Forward(receipt)
+}
@@ -0,0 +1,151 @@
+//! The room's mesh as its endpoints see it. Not every pair of peers gets a
+//! data channel (strict NATs, no TURN), so a transfer to a peer we have no
+//! channel to used to fail. Now each endpoint announces the peers it has
+//! an open channel to in a `peer-map`, which the signaling server hands to
+//! the rest of the room, whenever that set changes or someone joins.
+//!
+//! From those maps the `Topology` builds a graph of the room, drawn in the
+//! UI, and a routing table: the next hop toward a peer out of reach, on
+//! the shortest path. `relay` carries messages along it.
+//!
+//! A link counts once either end has reported it; a map replaces the last
+//! one from the same peer, so closed channels drop out with it. Only peers
+//! that advertised `relay` are routed through.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use crate::SignalingMessage;
+
+pub const PEER_MAP_TYPE: &str = "peer-map";
+
+#[derive(Default)]
+pub struct Topology {
+    /// Each endpoint's open channels, as it last reported them; ours too.
+    links: BTreeMap<String, BTreeSet<String>>,
+    /// Peers that advertised `relay`.
+    relaying_peers: HashSet<String>,
+}
+
+impl Topology {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// From the peer's advertised capabilities on joining.
+    pub fn set_peer_relays(&mut self, peer_id: &str, relays: bool) {
+        if relays {
+            self.relaying_peers.insert(peer_id.to_string());
+        } else {
+            self.relaying_peers.remove(peer_id);
+        }
+    }
+
+    /// Replaces the channels `peer_id` has open, from its `peer-map` or,
+    /// for us, our own connections.
+    pub fn set_links(&mut self, peer_id: &str, peers: &[String]) {
+        let peers = peers.iter().filter(|peer| *peer != peer_id).cloned().collect();
+        self.links.insert(peer_id.to_string(), peers);
+    }
+
+    /// Forgets a peer that left the room, and every link to it.
+    pub fn remove_peer(&mut self, peer_id: &str) {
+        self.links.remove(peer_id);
+        for peers in self.links.values_mut() {
+            peers.remove(peer_id);
+        }
+        self.relaying_peers.remove(peer_id);
+    }
+
+    /// On leaving the room.
+    pub fn clear(&mut self) {
+        self.links.clear();
+        self.relaying_peers.clear();
+    }
+
+    /// Everyone in the graph, in order.
+    pub fn nodes(&self) -> Vec<String> {
+        let mut nodes: BTreeSet<&String> = self.links.keys().collect();
+        nodes.extend(self.links.values().flatten());
+        nodes.into_iter().cloned().collect()
+    }
+
+    /// Each link once, its ends in order.
+    pub fn edges(&self) -> Vec<(String, String)> {
+        let mut edges = BTreeSet::new();
+        for (peer, peers) in &self.links {
+            for other in peers {
+                let edge = if peer < other { (peer, other) } else { (other, peer) };
+                edges.insert(edge);
+            }
+        }
+        edges.into_iter().map(|(a, b)| (a.clone(), b.clone())).collect()
+    }
+
+    fn neighbours<'a>(&'a self, peer_id: &'a str) -> BTreeSet<&'a str> {
+        let mut neighbours: BTreeSet<&str> =
+            self.links.get(peer_id).into_iter().flatten().map(String::as_str).collect();
+        neighbours.extend(
+            self.links.iter().filter(|(_, peers)| peers.contains(peer_id)).map(|(peer, _)| peer.as_str()),
+        );
+        neighbours
+    }
+
+    /// The first hop from `from` on a shortest path to `to`, through peers
+    /// that relay and aren't in `avoid`; `to` itself if they're linked.
+    /// None if there's no such path.
+    pub fn next_hop(&self, from: &str, to: &str, avoid: &[String]) -> Option<String> {
+        // Each peer reached, with the first hop that reached it
+        let mut first_hops: HashMap<&str, &str> = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(peer) = queue.pop_front() {
+            // Peers that don't relay are ends, never waypoints
+            if peer != from && !self.relaying_peers.contains(peer) {
+                continue;
+            }
+            for next in self.neighbours(peer) {
+                if next == from || first_hops.contains_key(next) || avoid.iter().any(|peer| peer == next) {
+                    continue;
+                }
+                let first_hop = if peer == from { next } else { first_hops[peer] };
+                if next == to {
+                    return Some(first_hop.to_string());
+                }
+                first_hops.insert(next, first_hop);
+                queue.push_back(next);
+            }
+        }
+        None
+    }
+
+    /// Peers `me` has no channel to but can reach, each with its first hop.
+    pub fn reachable(&self, me: &str) -> Vec<(String, String)> {
+        let neighbours = self.neighbours(me);
+        self.nodes()
+            .into_iter()
+            .filter(|peer| peer != me && !neighbours.contains(peer.as_str()))
+            .filter_map(|peer| self.next_hop(me, &peer, &[]).map(|hop| (peer, hop)))
+            .collect()
+    }
+
+    /// Our `peer-map`, for the signaling server to hand to the room.
+    pub fn announcement(connected_peers: &[String]) -> SignalingMessage {
+        SignalingMessage {
+            peers: Some(connected_peers.to_vec()),
+            ..SignalingMessage::new(PEER_MAP_TYPE)
+        }
+    }
+
+    /// Where to draw each peer in a `size`-wide square: `me` in the
+    /// middle, the others round it in order.
+    pub fn layout(&self, me: &str, size: f64) -> BTreeMap<String, (f64, f64)> {
+        let centre = size / 2.0;
+        let radius = size * 0.38;
+        let others: Vec<String> = self.nodes().into_iter().filter(|peer| peer != me).collect();
+        let mut positions = BTreeMap::from([(me.to_string(), (centre, centre))]);
+        for (i, peer) in others.iter().enumerate() {
+            let angle = std::f64::consts::TAU * i as f64 / others.len() as f64 - std::f64::consts::FRAC_PI_2;
+            positions.insert(peer.clone(), (centre + radius * angle.cos(), centre + radius * angle.sin()));
+        }
+        positions
+    }
+}
@@ -0,0 +1,710 @@
+//! The signaling socket, and a WebRTC peer connection to every other
+//! member of the room, each with one data channel transactions travel
+//! over. Whoever joins offers to the peers already there; offers, answers
+//! and ICE candidates go through the signaling server, and everything
+//! else between peers over the channels.
+//!
+//! What the application hears, through the handler given to `connect`:
+//! every signaling message except the negotiation, every message read from
+//! a data channel (with `from_peer` set to the channel's peer; a
+//! `transaction-batch` arrives whole, for `batching::unbatch`), and
+//!
+//! - `webrtc-connected` when a peer's channel opens, and again when its
+//!   connection comes back after an ICE restart;
+//! - `webrtc-disconnected` when a peer's connection goes `disconnected` or
+//!   `failed`, usually a network change. The channel is kept, and the
+//!   application answers with `restart_ice`;
+//! - `webrtc-failed` when recovery gives up after `MAX_ICE_RESTARTS`, the
+//!   channel closes, or the peer leaves the room.
+//!
+//! Each of these carries the peer in `peer_id`.
+//!
+//! An ICE restart renegotiates the transport of the existing connection,
+//! so the data channel, and anything the browser buffered on it, survives.
+//! Both sides see the drop, but only the one with the lower endpoint id
+//! sends the restart offer, so the two don't collide; the other answers
+//! it. Either gives a restart `RESTART_TIMEOUT_MS` to bring the connection
+//! back before trying again.
+//!
+//! Peer connections are created with the `RTCConfiguration` last given to
+//! `set_rtc_configuration`, so the ICE servers (STUN, and TURN to relay
+//! when no direct path works) are the ones `ice::load` found.
+//!
+//! Every channel sends through a `backpressure::SendQueue`, with the marks
+//! and backlog callback last given to `set_backpressure`. What a queue still
+//! holds when its peer is dropped is kept, with what a live queue holds,
+//! for `take_backlog` to hand back.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    MessageEvent, RtcConfiguration, RtcDataChannel, RtcDataChannelEvent, RtcDataChannelState, RtcIceCandidateInit,
+    RtcOfferOptions, RtcPeerConnection, RtcPeerConnectionIceEvent, RtcPeerConnectionState, RtcSdpType,
+    RtcSessionDescriptionInit, WebSocket,
+};
+
+use crate::backpressure::{OnBacklog, SendQueue, Watermarks};
+use crate::batching;
+use crate::codec::{self, Codecs, Frame};
+use crate::{AckResult, Capabilities, IceCandidate, SignalingMessage, Transaction, FEATURES};
+
+/// Told every message for the application; see the module docs.
+pub type MessageHandler = Box<dyn Fn(SignalingMessage)>;
+
+/// Joined on `connect`; `switch_room` moves elsewhere.
+const DEFAULT_ROOM: &str = "transaction-room";
+const DATA_CHANNEL_LABEL: &str = "transactions";
+/// ICE restarts tried for a peer before it's given up.
+const MAX_ICE_RESTARTS: u32 = 3;
+/// How long one ICE restart has to bring a connection back.
+const RESTART_TIMEOUT_MS: u32 = 10_000;
+
+/// Where the signaling server is: `SIGNALING_SERVER`, else a local one.
+pub fn signaling_url() -> String {
+    std::env::var("SIGNALING_SERVER").unwrap_or_else(|_| "ws://localhost:8080".to_string())
+}
+
+type Callback = Closure<dyn FnMut(JsValue)>;
+
+struct Peer {
+    connection: RtcPeerConnection,
+    channel: Option<RtcDataChannel>,
+    /// Sends on `channel`, once there is one.
+    queue: Option<Rc<SendQueue>>,
+    /// ICE restarts since the connection was last up.
+    restarts: u32,
+    /// Between `webrtc-disconnected` and the connection coming back, or
+    /// being given up.
+    recovering: bool,
+    // Kept alive for as long as the connection and channel may call them
+    callbacks: Vec<Callback>,
+}
+
+impl Peer {
+    /// Stops the connection and its channel calling back, and closes both.
+    fn close(&self) {
+        self.connection.set_onicecandidate(None);
+        self.connection.set_onconnectionstatechange(None);
+        self.connection.set_ondatachannel(None);
+        if let Some(channel) = &self.channel {
+            channel.set_onopen(None);
+            channel.set_onmessage(None);
+            channel.set_onclose(None);
+            channel.set_onbufferedamountlow(None);
+            channel.close();
+        }
+        self.connection.close();
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    endpoint_id: RefCell<String>,
+    room_id: RefCell<String>,
+    /// For every peer connection; the browser's defaults until set.
+    rtc_configuration: RefCell<Option<RtcConfiguration>>,
+    socket: RefCell<Option<WebSocket>>,
+    handler: RefCell<Option<Rc<dyn Fn(SignalingMessage)>>>,
+    peers: RefCell<HashMap<String, Peer>>,
+    /// What each member of the room advertised on joining.
+    capabilities: RefCell<HashMap<String, Capabilities>>,
+    codecs: RefCell<Codecs>,
+    /// For every channel's `SendQueue`; the defaults, unreported, until set.
+    backpressure: RefCell<Option<(Watermarks, OnBacklog)>>,
+    /// What the queues of peers since dropped still held, by peer.
+    stranded: RefCell<HashMap<String, Vec<Transaction>>>,
+}
+
+#[derive(Default)]
+pub struct WebRTCConnection {
+    shared: Rc<Shared>,
+}
+
+impl WebRTCConnection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Opens the signaling socket and joins the default room as
+    /// `endpoint_id`; peer connections follow from the room's messages.
+    pub fn connect(&mut self, endpoint_id: &str, handler: MessageHandler) -> Result<(), JsValue> {
+        *self.shared.endpoint_id.borrow_mut() = endpoint_id.to_string();
+        *self.shared.room_id.borrow_mut() = DEFAULT_ROOM.to_string();
+        *self.shared.handler.borrow_mut() = Some(Rc::from(handler));
+
+        let ws = WebSocket::new(&signaling_url())?;
+
+        let shared = self.shared.clone();
+        let onmessage = Closure::wrap(Box::new(move |e: MessageEvent| {
+            let Some(text) = e.data().as_string() else {
+                return;
+            };
+            match serde_json::from_str::<SignalingMessage>(&text) {
+                Ok(message) => on_signaling(&shared, message),
+                Err(e) => web_sys::console::error_1(&format!("Invalid signaling message: {}", e).into()),
+            }
+        }) as Box<dyn FnMut(_)>);
+        ws.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+        onmessage.forget();
+
+        let shared = self.shared.clone();
+        let onopen = Closure::wrap(Box::new(move |_: JsValue| {
+            // Servers from before the handshake ignore it
+            let _ = send_signaling(&shared, SignalingMessage::hello(&FEATURES));
+            let join = SignalingMessage {
+                peer_id: Some(shared.endpoint_id.borrow().clone()),
+                ..SignalingMessage::new("join")
+            };
+            if let Err(e) = send_signaling(&shared, join) {
+                web_sys::console::error_1(&e);
+            }
+        }) as Box<dyn FnMut(_)>);
+        ws.set_onopen(Some(onopen.as_ref().unchecked_ref()));
+        onopen.forget();
+
+        let shared = self.shared.clone();
+        let onclose = Closure::wrap(Box::new(move |_: JsValue| {
+            web_sys::console::warn_1(&"Signaling connection closed".into());
+            shared.socket.borrow_mut().take();
+        }) as Box<dyn FnMut(_)>);
+        ws.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+        onclose.forget();
+
+        *self.shared.socket.borrow_mut() = Some(ws);
+        Ok(())
+    }
+
+    /// Used for every peer connection created from here on; those already
+    /// open keep theirs.
+    pub fn set_rtc_configuration(&mut self, configuration: RtcConfiguration) {
+        *self.shared.rtc_configuration.borrow_mut() = Some(configuration);
+    }
+
+    /// Used for every channel attached from here on: sends wait in its
+    /// `SendQueue` past `marks`, and `on_backlog` hears each queue's depth.
+    pub fn set_backpressure(&mut self, marks: Watermarks, on_backlog: OnBacklog) {
+        *self.shared.backpressure.borrow_mut() = Some((marks, on_backlog));
+    }
+
+    /// Empties `peer_id`'s send queue, returning the transactions it held,
+    /// and any its dropped connections left behind, oldest first; e.g. to
+    /// replay them once the peer is back.
+    pub fn take_backlog(&mut self, peer_id: &str) -> Vec<Transaction> {
+        let mut backlog = self.shared.stranded.borrow_mut().remove(peer_id).unwrap_or_default();
+        let queue = self.shared.peers.borrow().get(peer_id).and_then(|peer| peer.queue.clone());
+        if let Some(queue) = queue {
+            backlog.extend(queue.take());
+        }
+        backlog
+    }
+
+    /// `peer_id`'s connection, e.g. to read which path it settled on.
+    pub fn peer_connection(&self, peer_id: &str) -> Option<RtcPeerConnection> {
+        self.shared.peers.borrow().get(peer_id).map(|peer| peer.connection.clone())
+    }
+
+    /// Sends `message` to the signaling server, in the current room.
+    pub fn send_signaling(&self, message: &SignalingMessage) -> Result<(), JsValue> {
+        send_signaling(&self.shared, message.clone())
+    }
+
+    pub fn list_rooms(&self) -> Result<(), JsValue> {
+        send_signaling(&self.shared, SignalingMessage::new("list-rooms"))
+    }
+
+    /// Leaves the room, closing every peer connection without telling the
+    /// application of each, and joins `room_id`.
+    pub fn switch_room(&mut self, room_id: &str) -> Result<(), JsValue> {
+        send_signaling(&self.shared, SignalingMessage::new("leave"))?;
+        let peers: Vec<(String, Peer)> = self.shared.peers.borrow_mut().drain().collect();
+        for (peer_id, peer) in peers {
+            retire(&self.shared, &peer_id, peer);
+        }
+        self.shared.capabilities.borrow_mut().clear();
+        *self.shared.codecs.borrow_mut() = Codecs::default();
+        *self.shared.room_id.borrow_mut() = room_id.to_string();
+        let join = SignalingMessage {
+            peer_id: Some(self.shared.endpoint_id.borrow().clone()),
+            ..SignalingMessage::new("join")
+        };
+        send_signaling(&self.shared, join)
+    }
+
+    /// Renegotiates `peer_id`'s transport after `webrtc-disconnected`; see
+    /// the module docs. Past `MAX_ICE_RESTARTS` the peer is given up with
+    /// `webrtc-failed`.
+    pub fn restart_ice(&mut self, peer_id: &str) -> Result<(), JsValue> {
+        restart_ice(&self.shared, peer_id)
+    }
+
+    pub fn send_transaction(&mut self, tx: &Transaction) -> Result<(), JsValue> {
+        let message = SignalingMessage {
+            target_peer: Some(tx.to_endpoint.clone()),
+            transaction: Some(tx.clone()),
+            ..SignalingMessage::new("transaction-p2p")
+        };
+        self.send_message(&message)
+    }
+
+    /// Sends a `transaction-batch` from `Batcher` as one data channel
+    /// message, to a peer that advertised it reads them.
+    pub fn send_batch(&mut self, batch: &SignalingMessage) -> Result<(), JsValue> {
+        if batch.message_type != batching::BATCH_TYPE {
+            return Err(format!("Not a batch: {}", batch.message_type).into());
+        }
+        self.send_message(batch)
+    }
+
+    /// Tells `tx`'s sender whether we applied it.
+    pub fn send_ack(&mut self, tx: &Transaction, result: AckResult, reason: Option<String>) -> Result<(), JsValue> {
+        let message = SignalingMessage {
+            target_peer: Some(tx.from_endpoint.clone()),
+            transaction: Some(tx.clone()),
+            result: Some(result),
+            reason,
+            ..SignalingMessage::new("transaction-ack")
+        };
+        self.send_message(&message)
+    }
+
+    /// Sends `message` through its `target_peer`'s send queue, in the
+    /// format negotiated with that peer.
+    pub fn send_message(&mut self, message: &SignalingMessage) -> Result<(), JsValue> {
+        let peer_id = message.target_peer.as_deref().ok_or("Message has no target peer")?;
+        let queue = self.open_queue(peer_id)?;
+        let frame = self.shared.codecs.borrow_mut().encode(peer_id, message)?;
+        queue.send(frame, batching::transactions(message))
+    }
+
+    fn open_queue(&self, peer_id: &str) -> Result<Rc<SendQueue>, JsValue> {
+        self.shared
+            .peers
+            .borrow()
+            .get(peer_id)
+            .filter(|peer| peer.channel.as_ref().is_some_and(|c| c.ready_state() == RtcDataChannelState::Open))
+            .and_then(|peer| peer.queue.clone())
+            .ok_or_else(|| format!("No open data channel to {}", peer_id).into())
+    }
+}
+
+fn emit(shared: &Shared, message: SignalingMessage) {
+    // Not borrowed across the call: the handler calls back in
+    let handler = shared.handler.borrow().clone();
+    if let Some(handler) = handler {
+        handler(message);
+    }
+}
+
+fn emit_peer_event(shared: &Shared, message_type: &str, peer_id: &str) {
+    emit(shared, SignalingMessage { peer_id: Some(peer_id.to_string()), ..SignalingMessage::new(message_type) });
+}
+
+fn send_signaling(shared: &Shared, mut message: SignalingMessage) -> Result<(), JsValue> {
+    if message.room_id.is_none() && message.message_type != "hello" {
+        message.room_id = Some(shared.room_id.borrow().clone());
+    }
+    let socket = shared.socket.borrow().clone().ok_or("Not connected to the signaling server")?;
+    let text = serde_json::to_string(&message).map_err(|e| e.to_string())?;
+    socket.send_with_str(&text)
+}
+
+/// Closes `peer` now, keeping what its queue held for `take_backlog`, and
+/// drops its callbacks once the one running, which may be one of them, has
+/// returned.
+fn retire(shared: &Shared, peer_id: &str, peer: Peer) {
+    if let Some(queue) = &peer.queue {
+        let held = queue.take();
+        if !held.is_empty() {
+            shared.stranded.borrow_mut().entry(peer_id.to_string()).or_default().extend(held);
+        }
+    }
+    peer.close();
+    wasm_bindgen_futures::spawn_local(async move { drop(peer) });
+}
+
+fn on_signaling(shared: &Rc<Shared>, message: SignalingMessage) {
+    let me = shared.endpoint_id.borrow().clone();
+    match message.message_type.as_str() {
+        "room-joined" => {
+            let features = message.peer_features.clone().unwrap_or_default();
+            shared.capabilities.borrow_mut().extend(features.iter().map(|(peer_id, names)| {
+                (peer_id.clone(), Capabilities::from_names(tx_core::PROTOCOL_VERSION, names))
+            }));
+            // Newcomers offer to those already here
+            for peer_id in message.peers.iter().flatten().filter(|peer_id| **peer_id != me) {
+                if let Err(e) = offer(shared, peer_id) {
+                    web_sys::console::error_1(&format!("Could not connect to {}: {:?}", peer_id, e).into());
+                }
+            }
+        }
+        "peer-joined" => {
+            if let Some(peer_id) = &message.peer_id {
+                shared.capabilities.borrow_mut().insert(peer_id.clone(), Capabilities::from_message(&message));
+            }
+        }
+        "peer-left" => {
+            if let Some(peer_id) = &message.peer_id {
+                shared.capabilities.borrow_mut().remove(peer_id);
+                let departed = shared.peers.borrow_mut().remove(peer_id);
+                if let Some(peer) = departed {
+                    retire(shared, peer_id, peer);
+                    shared.codecs.borrow_mut().forget(peer_id);
+                    emit_peer_event(shared, "webrtc-failed", peer_id);
+                }
+            }
+        }
+        // Negotiation is ours alone
+        "offer" => {
+            if let Err(e) = answer(shared, message) {
+                web_sys::console::error_1(&format!("Could not answer an offer: {:?}", e).into());
+            }
+            return;
+        }
+        "answer" => {
+            accept_answer(shared, message);
+            return;
+        }
+        "ice-candidate" => {
+            add_candidate(shared, message);
+            return;
+        }
+        _ => {}
+    }
+    emit(shared, message);
+}
+
+/// A peer connection to `peer_id`, its callbacks registered.
+fn open_peer(shared: &Rc<Shared>, peer_id: &str) -> Result<RtcPeerConnection, JsValue> {
+    let connection = match shared.rtc_configuration.borrow().as_ref() {
+        Some(configuration) => RtcPeerConnection::new_with_configuration(configuration)?,
+        None => RtcPeerConnection::new()?,
+    };
+    let mut callbacks = Vec::new();
+
+    let on_candidate: Callback = {
+        let shared = shared.clone();
+        let peer_id = peer_id.to_string();
+        Closure::new(move |e: JsValue| {
+            let Some(candidate) = e.unchecked_into::<RtcPeerConnectionIceEvent>().candidate() else {
+                return;
+            };
+            let message = SignalingMessage {
+                target_peer: Some(peer_id.clone()),
+                ice_candidate: Some(IceCandidate {
+                    candidate: candidate.candidate(),
+                    sdp_mid: candidate.sdp_mid(),
+                    sdp_m_line_index: candidate.sdp_m_line_index(),
+                }),
+                ..SignalingMessage::new("ice-candidate")
+            };
+            if let Err(e) = send_signaling(&shared, message) {
+                web_sys::console::warn_1(&e);
+            }
+        })
+    };
+    connection.set_onicecandidate(Some(on_candidate.as_ref().unchecked_ref()));
+    callbacks.push(on_candidate);
+
+    let on_state: Callback = {
+        let shared = shared.clone();
+        let peer_id = peer_id.to_string();
+        Closure::new(move |_: JsValue| on_connection_state(&shared, &peer_id))
+    };
+    connection.set_onconnectionstatechange(Some(on_state.as_ref().unchecked_ref()));
+    callbacks.push(on_state);
+
+    let on_channel: Callback = {
+        let shared = shared.clone();
+        let peer_id = peer_id.to_string();
+        Closure::new(move |e: JsValue| {
+            let channel = e.unchecked_into::<RtcDataChannelEvent>().channel();
+            attach_channel(&shared, &peer_id, channel);
+        })
+    };
+    connection.set_ondatachannel(Some(on_channel.as_ref().unchecked_ref()));
+    callbacks.push(on_channel);
+
+    let replaced = shared.peers.borrow_mut().insert(
+        peer_id.to_string(),
+        Peer { connection: connection.clone(), channel: None, queue: None, restarts: 0, recovering: false, callbacks },
+    );
+    if let Some(replaced) = replaced {
+        retire(shared, peer_id, replaced);
+    }
+    Ok(connection)
+}
+
+/// Takes over `channel` as `peer_id`'s, once it is the one we created or
+/// the one the peer opened.
+fn attach_channel(shared: &Rc<Shared>, peer_id: &str, channel: RtcDataChannel) {
+    codec::prepare(&channel);
+    let mut callbacks = Vec::new();
+
+    let on_open: Callback = {
+        let shared = shared.clone();
+        let peer_id = peer_id.to_string();
+        let channel = channel.clone();
+        Closure::new(move |_: JsValue| {
+            let capabilities = shared.capabilities.borrow().get(&peer_id).cloned().unwrap_or_default();
+            if let Some(hello) = codec::hello_for(&capabilities) {
+                if let Err(e) = hello.send(&channel) {
+                    web_sys::console::warn_1(&e);
+                }
+            }
+            if let Some(peer) = shared.peers.borrow_mut().get_mut(&peer_id) {
+                peer.restarts = 0;
+                peer.recovering = false;
+            }
+            emit_peer_event(&shared, "webrtc-connected", &peer_id);
+        })
+    };
+    channel.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+    callbacks.push(on_open);
+
+    let on_message: Callback = {
+        let shared = shared.clone();
+        let peer_id = peer_id.to_string();
+        Closure::new(move |e: JsValue| {
+            let Some(frame) = Frame::from_message(&e.unchecked_into::<MessageEvent>().data()) else {
+                return;
+            };
+            if let Some(hello) = codec::parse_hello(&frame) {
+                let format = shared.codecs.borrow_mut().negotiate(&peer_id, &hello);
+                web_sys::console::log_1(&format!("Writing {} to {}", format, peer_id).into());
+                return;
+            }
+            match codec::decode::<SignalingMessage>(&frame) {
+                // The channel is the peer's alone, whatever the message says
+                Ok(message) => emit(&shared, SignalingMessage { from_peer: Some(peer_id.clone()), ..message }),
+                Err(e) => web_sys::console::warn_1(&format!("From {}: {}", peer_id, e).into()),
+            }
+        })
+    };
+    channel.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    callbacks.push(on_message);
+
+    let on_close: Callback = {
+        let shared = shared.clone();
+        let peer_id = peer_id.to_string();
+        Closure::new(move |_: JsValue| {
+            shared.codecs.borrow_mut().forget(&peer_id);
+            let closed = shared.peers.borrow_mut().remove(&peer_id);
+            if let Some(peer) = closed {
+                retire(&shared, &peer_id, peer);
+                emit_peer_event(&shared, "webrtc-failed", &peer_id);
+            }
+        })
+    };
+    channel.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+    callbacks.push(on_close);
+
+    let backpressure = shared.backpressure.borrow().clone();
+    let (marks, on_backlog) = match backpressure {
+        Some(backpressure) => backpressure,
+        None => (Watermarks::default(), Rc::new(|_: &str, _: usize| {}) as OnBacklog),
+    };
+    let mut peers = shared.peers.borrow_mut();
+    match peers.get_mut(peer_id) {
+        Some(peer) => {
+            peer.queue = Some(SendQueue::attach(peer_id, &channel, marks, on_backlog));
+            peer.channel = Some(channel);
+            peer.callbacks.extend(callbacks);
+        }
+        // Gone while the channel was on its way
+        None => {
+            channel.set_onopen(None);
+            channel.set_onmessage(None);
+            channel.set_onclose(None);
+            channel.close();
+        }
+    }
+}
+
+fn on_connection_state(shared: &Rc<Shared>, peer_id: &str) {
+    let mut event = None;
+    if let Some(peer) = shared.peers.borrow_mut().get_mut(peer_id) {
+        match peer.connection.connection_state() {
+            RtcPeerConnectionState::Connected if peer.recovering => {
+                peer.recovering = false;
+                peer.restarts = 0;
+                event = Some("webrtc-connected");
+            }
+            RtcPeerConnectionState::Disconnected | RtcPeerConnectionState::Failed if !peer.recovering => {
+                peer.recovering = true;
+                event = Some("webrtc-disconnected");
+            }
+            _ => {}
+        }
+    }
+    if let Some(event) = event {
+        emit_peer_event(shared, event, peer_id);
+    }
+}
+
+fn restart_ice(shared: &Rc<Shared>, peer_id: &str) -> Result<(), JsValue> {
+    let me = shared.endpoint_id.borrow().clone();
+    let (connection, attempt) = {
+        let mut peers = shared.peers.borrow_mut();
+        let peer = peers.get_mut(peer_id).ok_or_else(|| format!("No connection to {}", peer_id))?;
+        if !peer.recovering {
+            return Ok(());
+        }
+        if peer.restarts >= MAX_ICE_RESTARTS {
+            let given_up = peers.remove(peer_id).expect("found above");
+            drop(peers);
+            retire(shared, peer_id, given_up);
+            shared.codecs.borrow_mut().forget(peer_id);
+            emit_peer_event(shared, "webrtc-failed", peer_id);
+            return Ok(());
+        }
+        peer.restarts += 1;
+        (peer.connection.clone(), peer.restarts)
+    };
+
+    // The other side answers ours
+    if me.as_str() < peer_id {
+        let options = RtcOfferOptions::new();
+        options.set_ice_restart(true);
+        send_offer(shared, peer_id, &connection, Some(options));
+    }
+
+    // Tried again, or given up, unless this attempt brought it back
+    let shared = shared.clone();
+    let peer_id = peer_id.to_string();
+    gloo_timers::callback::Timeout::new(RESTART_TIMEOUT_MS, move || {
+        let stalled = shared
+            .peers
+            .borrow()
+            .get(&peer_id)
+            .is_some_and(|peer| peer.recovering && peer.restarts == attempt);
+        if stalled {
+            if let Err(e) = restart_ice(&shared, &peer_id) {
+                web_sys::console::error_1(&e);
+            }
+        }
+    })
+    .forget();
+    Ok(())
+}
+
+/// Opens a connection and channel to `peer_id` and sends our offer.
+fn offer(shared: &Rc<Shared>, peer_id: &str) -> Result<(), JsValue> {
+    let connection = open_peer(shared, peer_id)?;
+    let channel = connection.create_data_channel(DATA_CHANNEL_LABEL);
+    attach_channel(shared, peer_id, channel);
+    send_offer(shared, peer_id, &connection, None);
+    Ok(())
+}
+
+fn send_offer(shared: &Rc<Shared>, peer_id: &str, connection: &RtcPeerConnection, options: Option<RtcOfferOptions>) {
+    let shared = shared.clone();
+    let peer_id = peer_id.to_string();
+    let connection = connection.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        let created = match &options {
+            Some(options) => connection.create_offer_with_rtc_offer_options(options),
+            None => connection.create_offer(),
+        };
+        let result = async {
+            let offer = JsFuture::from(created).await?;
+            let sdp = sdp_of(&offer)?;
+            let description = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
+            description.set_sdp(&sdp);
+            JsFuture::from(connection.set_local_description(&description)).await?;
+            let message = SignalingMessage {
+                target_peer: Some(peer_id.clone()),
+                offer: Some(sdp),
+                ..SignalingMessage::new("offer")
+            };
+            send_signaling(&shared, message)
+        }
+        .await;
+        if let Err(e) = result {
+            web_sys::console::error_1(&format!("Offer to {} failed: {:?}", peer_id, e).into());
+        }
+    });
+}
+
+/// Answers an offer: a new peer's, or an ICE restart on a connection we
+/// already have.
+fn answer(shared: &Rc<Shared>, message: SignalingMessage) -> Result<(), JsValue> {
+    let (Some(peer_id), Some(sdp)) = (message.from_peer, message.offer) else {
+        return Err("Offer without a sender or description".into());
+    };
+    let existing = shared.peers.borrow().get(&peer_id).map(|peer| peer.connection.clone());
+    let connection = match existing {
+        Some(connection) => connection,
+        None => open_peer(shared, &peer_id)?,
+    };
+
+    let shared = shared.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        let result = async {
+            let remote = RtcSessionDescriptionInit::new(RtcSdpType::Offer);
+            remote.set_sdp(&sdp);
+            JsFuture::from(connection.set_remote_description(&remote)).await?;
+            let answer = JsFuture::from(connection.create_answer()).await?;
+            let sdp = sdp_of(&answer)?;
+            let local = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
+            local.set_sdp(&sdp);
+            JsFuture::from(connection.set_local_description(&local)).await?;
+            let message = SignalingMessage {
+                target_peer: Some(peer_id.clone()),
+                answer: Some(sdp),
+                ..SignalingMessage::new("answer")
+            };
+            send_signaling(&shared, message)
+        }
+        .await;
+        if let Err(e) = result {
+            web_sys::console::error_1(&format!("Answer to {} failed: {:?}", peer_id, e).into());
+        }
+    });
+    Ok(())
+}
+
+fn accept_answer(shared: &Shared, message: SignalingMessage) {
+    let (Some(peer_id), Some(sdp)) = (message.from_peer, message.answer) else {
+        return;
+    };
+    let Some(connection) = shared.peers.borrow().get(&peer_id).map(|peer| peer.connection.clone()) else {
+        return;
+    };
+    wasm_bindgen_futures::spawn_local(async move {
+        let remote = RtcSessionDescriptionInit::new(RtcSdpType::Answer);
+        remote.set_sdp(&sdp);
+        if let Err(e) = JsFuture::from(connection.set_remote_description(&remote)).await {
+            web_sys::console::error_1(&format!("Answer from {} rejected: {:?}", peer_id, e).into());
+        }
+    });
+}
+
+fn add_candidate(shared: &Shared, message: SignalingMessage) {
+    let (Some(peer_id), Some(candidate)) = (message.from_peer, message.ice_candidate) else {
+        return;
+    };
+    let Some(connection) = shared.peers.borrow().get(&peer_id).map(|peer| peer.connection.clone()) else {
+        return;
+    };
+    let init = RtcIceCandidateInit::new(&candidate.candidate);
+    init.set_sdp_mid(candidate.sdp_mid.as_deref());
+    init.set_sdp_m_line_index(candidate.sdp_m_line_index);
+    wasm_bindgen_futures::spawn_local(async move {
+        let added = connection.add_ice_candidate_with_opt_rtc_ice_candidate_init(Some(&init));
+        if let Err(e) = JsFuture::from(added).await {
+            web_sys::console::warn_1(&format!("ICE candidate from {} rejected: {:?}", peer_id, e).into());
+        }
+    });
+}
+
+/// The SDP of what `createOffer` or `createAnswer` resolved to.
+fn sdp_of(description: &JsValue) -> Result<String, JsValue> {
+    js_sys::Reflect::get(description, &"sdp".into())?
+        .as_string()
+        .ok_or_else(|| "Session description without SDP".into())
+}
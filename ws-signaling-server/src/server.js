@@ -1,23 +1,319 @@
 import { WebSocketServer } from 'ws';
+import { createHmac, randomUUID } from 'crypto';
 import express from 'express';
 import { createServer } from 'http';
+import { createServer as createTlsServer } from 'https';
+import { readFileSync } from 'fs';
+import { Agent, setGlobalDispatcher } from 'undici';
 import cors from 'cors';
 
 const app = express();
 app.use(cors());
 app.use(express.json());
 
+// Per-connection quotas, so one greedy client can't exhaust the relay:
+// - MAX_MESSAGE_BYTES (65536): largest inbound message accepted
+// - MAX_QUEUED_BYTES (1048576): outbound bytes buffered for a client
+//   before it is disconnected
+// - MAX_MAILBOX_ITEMS (100): messages held for a peer being handed over
+// A connection is in at most one room: joining another leaves the first.
+const MAX_MESSAGE_BYTES = parseInt(process.env.MAX_MESSAGE_BYTES || '65536', 10);
+const MAX_QUEUED_BYTES = parseInt(process.env.MAX_QUEUED_BYTES || '1048576', 10);
+const MAX_MAILBOX_ITEMS = parseInt(process.env.MAX_MAILBOX_ITEMS || '100', 10);
+
+// Slow consumers. Transaction broadcasts carry a per-room `seq`. While a
+// client has more than LAG_THRESHOLD_BYTES (262144) queued, broadcasts to it
+// are skipped rather than queued; once it drains it gets `lagged` with the
+// number missed and catches up with `resync`, replayed from the room's last
+// ROOM_HISTORY_SIZE (500) broadcasts.
+const LAG_THRESHOLD_BYTES = parseInt(process.env.LAG_THRESHOLD_BYTES || '262144', 10);
+const ROOM_HISTORY_SIZE = parseInt(process.env.ROOM_HISTORY_SIZE || '500', 10);
+
+// roomId -> { seq, recent: [broadcast, ...] }
+const roomHistory = new Map();
+let laggedNotifications = 0;
+
+const quotaRejections = {
+    messageBytes: 0,
+    queuedBytes: 0,
+    mailbox: 0
+};
+
+// Compression. With WS_PERMESSAGE_DEFLATE=true the server offers
+// permessage-deflate during the WebSocket upgrade; browsers accept it
+// transparently. Messages under WS_DEFLATE_THRESHOLD bytes (256) are sent
+// as-is, compressing them costs more than it saves. A client whose socket
+// took the extension offers `permessage-deflate` in its `hello`, and the
+// `negotiated` answer echoes it only if this end compresses as well.
+const PERMESSAGE_DEFLATE = process.env.WS_PERMESSAGE_DEFLATE === 'true';
+const DEFLATE_THRESHOLD = parseInt(process.env.WS_DEFLATE_THRESHOLD || '256', 10);
+
+// Outbound messages and their bytes before (payload) and after (wire)
+// compression, including connections already closed
+const compressionTotals = { messages: 0, payloadBytes: 0, wireBytes: 0 };
+
 const server = createServer(app);
-const wss = new WebSocketServer({ server });
+const wss = new WebSocketServer({
+    server,
+    maxPayload: MAX_MESSAGE_BYTES,
+    perMessageDeflate: PERMESSAGE_DEFLATE && {
+        threshold: DEFLATE_THRESHOLD,
+        zlibDeflateOptions: { level: 6 },
+        // Per-message contexts keep memory per connection flat
+        serverNoContextTakeover: true,
+        clientNoContextTakeover: true
+    }
+});
+
+function quotaExceeded(ws, quota, message) {
+    quotaRejections[quota]++;
+    if (ws.readyState === ws.OPEN) {
+        ws.send(JSON.stringify({ type: 'quota-exceeded', quota, message }));
+    }
+}
+
+// Sends to one client, disconnecting it once its outbound buffer passes
+// MAX_QUEUED_BYTES rather than buffering without bound
+function send(ws, message) {
+    if (ws.readyState !== ws.OPEN) return false;
+
+    const payload = JSON.stringify(message);
+    if (ws.bufferedAmount + payload.length > MAX_QUEUED_BYTES) {
+        quotaRejections.queuedBytes++;
+        console.log(`Closing ${ws.peerId || 'peer'}: ${ws.bufferedAmount} bytes queued`);
+        ws.close(1008, 'Outbound queue quota exceeded');
+        return false;
+    }
+    ws.send(payload);
+    ws.messagesOut = (ws.messagesOut || 0) + 1;
+    ws.payloadBytesOut = (ws.payloadBytesOut || 0) + Buffer.byteLength(payload);
+    return true;
+}
+
+function compressionStats() {
+    let messages = compressionTotals.messages;
+    let payloadBytes = compressionTotals.payloadBytes;
+    let wireBytes = compressionTotals.wireBytes;
+    let compressed = 0;
+    wss.clients.forEach(client => {
+        messages += client.messagesOut || 0;
+        payloadBytes += client.payloadBytesOut || 0;
+        wireBytes += client.rawSocket ? client.rawSocket.bytesWritten : 0;
+        if (client.extensions.includes('permessage-deflate')) compressed++;
+    });
+    return {
+        permessageDeflate: PERMESSAGE_DEFLATE,
+        compressedConnections: compressed,
+        messages,
+        payloadBytes,
+        wireBytes,
+        payloadBytesPerMessage: messages > 0 ? +(payloadBytes / messages).toFixed(1) : null,
+        wireBytesPerMessage: messages > 0 ? +(wireBytes / messages).toFixed(1) : null,
+        ratio: payloadBytes > 0 ? +(wireBytes / payloadBytes).toFixed(3) : null
+    };
+}
+
+// Batch settlement. Rooms the gateway has a batch window for
+// (GET /api/rooms/:id/batching) don't have endpoints record each transfer:
+// the server collects the room's transactions and posts each window as one
+// batch at its boundary. Windows that fail to post are retried in order.
+// The room's setting is re-read every BATCH_CONFIG_TTL_MS.
+const BATCH_CONFIG_TTL_MS = 60000;
+// roomId -> { windowMs, fetchedAt, windowStart, transactions, pending }
+const roomBatches = new Map();
 
 // Store connected peers
 const peers = new Map();
+// Rooms are created by the first join and removed when the last peer leaves
 const rooms = new Map();
+// Rooms opened with `encrypted`: only sealed broadcasts are relayed there,
+// and only peers offering group encryption may join
+const encryptedRooms = new Set();
+const GROUP_ENCRYPTION = 'group-encryption';
+const ROOM_ID_PATTERN = /^[A-Za-z0-9_-]{1,64}$/;
+
+const API_GATEWAY = process.env.API_GATEWAY || 'http://localhost:3001';
+// Bearer token for gateways that require a JWT; a reader token will do
+// unless batches are posted over the plain port
+const GATEWAY_TOKEN = process.env.GATEWAY_TOKEN;
+
+function gatewayHeaders(headers = {}) {
+    return GATEWAY_TOKEN ? { ...headers, Authorization: `Bearer ${GATEWAY_TOKEN}` } : headers;
+}
+
+// Pod lifecycle. On preStop (or SIGTERM) the server drains: /ready fails so
+// no new traffic is routed here, new joins are refused, connected peers get
+// a `server-draining` notice, and shutdown waits (up to DRAIN_TIMEOUT_MS)
+// for in-flight relays -- joins being authorized and offers still awaiting
+// an answer -- so active signaling sessions aren't cut mid-negotiation.
+const DRAIN_TIMEOUT_MS = parseInt(process.env.DRAIN_TIMEOUT_MS || '25000', 10);
+// Readiness gates: comma-separated checks /ready requires besides not
+// draining. Supported: `gateway` (API gateway /health answers).
+const READINESS_GATES = (process.env.READINESS_GATES || '')
+    .split(',')
+    .map(gate => gate.trim())
+    .filter(Boolean);
+
+let draining = false;
+let drainPromise = null;
+let pendingJoins = 0;
+// Offers relayed without an answer yet, keyed `${offerer}->${answerer}`.
+// Offers never answered stop counting after NEGOTIATION_TTL_MS.
+const pendingNegotiations = new Map();
+const NEGOTIATION_TTL_MS = 30000;
+
+function inFlightCount() {
+    const cutoff = Date.now() - NEGOTIATION_TTL_MS;
+    for (const [key, startedAt] of pendingNegotiations) {
+        if (startedAt < cutoff) {
+            pendingNegotiations.delete(key);
+        }
+    }
+    return pendingJoins + pendingNegotiations.size;
+}
+
+// Session handoff. A draining instance moves its clients to a sibling from
+// HANDOFF_PEERS (base HTTP URLs): the sibling issues a resumption token per
+// session, the client is sent a `redirect` to the sibling's PUBLIC_URL, and
+// resumes there with its room membership intact. Messages addressed to a
+// peer in transit are forwarded into its mailbox on the new instance and
+// delivered on resume. HANDOFF_SECRET, when set, guards the internal
+// /handoff endpoints.
+const PUBLIC_URL = process.env.PUBLIC_URL || '';
+const HANDOFF_PEERS = (process.env.HANDOFF_PEERS || '')
+    .split(',')
+    .map(peer => peer.trim().replace(/\/$/, ''))
+    .filter(Boolean);
+const HANDOFF_SECRET = process.env.HANDOFF_SECRET || '';
+
+// Mutual TLS between internal services. With MTLS_CERT, MTLS_KEY and
+// MTLS_CA (PEM paths) set, every outbound call (the gateway, siblings)
+// presents this server's certificate and trusts only the internal CA, and
+// siblings hand off over MTLS_PORT (8443), where callers must present a
+// certificate whose SPIFFE ID (URI SAN) is in MTLS_ALLOWED_IDS. The
+// /handoff endpoints then refuse the plain port; API_GATEWAY and
+// HANDOFF_PEERS point at https:// mTLS ports.
+const MTLS = process.env.MTLS_CERT ? {
+    cert: readFileSync(process.env.MTLS_CERT),
+    key: readFileSync(process.env.MTLS_KEY),
+    ca: readFileSync(process.env.MTLS_CA)
+} : null;
+const MTLS_PORT = parseInt(process.env.MTLS_PORT || '8443', 10);
+const MTLS_ALLOWED_IDS = new Set((process.env.MTLS_ALLOWED_IDS || '')
+    .split(',')
+    .map(id => id.trim())
+    .filter(Boolean));
+if (MTLS && MTLS_ALLOWED_IDS.size === 0) {
+    throw new Error('MTLS_ALLOWED_IDS must list the SPIFFE IDs allowed to connect');
+}
+if (MTLS) {
+    setGlobalDispatcher(new Agent({ connect: MTLS }));
+}
+
+// ICE servers for WebRTC endpoints, served at /ice-servers, so peers
+// behind symmetric NATs can fall back to relaying through TURN:
+// - STUN_URLS: comma-separated (default stun:stun.l.google.com:19302)
+// - TURN_URLS: comma-separated turn:/turns: URLs; none means no relay
+// - TURN_SECRET: secret shared with the TURN server (coturn's
+//   `use-auth-secret`); each response gets credentials that expire after
+//   TURN_CREDENTIAL_TTL_SECS (86400)
+// - TURN_USERNAME, TURN_CREDENTIAL: fixed credentials instead
+const csv = value => (value || '').split(',').map(item => item.trim()).filter(Boolean);
+const STUN_URLS = process.env.STUN_URLS === undefined
+    ? ['stun:stun.l.google.com:19302']
+    : csv(process.env.STUN_URLS);
+const TURN_URLS = csv(process.env.TURN_URLS);
+const TURN_SECRET = process.env.TURN_SECRET || '';
+const TURN_CREDENTIAL_TTL_SECS = parseInt(process.env.TURN_CREDENTIAL_TTL_SECS || '86400', 10);
+
+function iceServers(peerId) {
+    const servers = [];
+    if (STUN_URLS.length > 0) {
+        servers.push({ urls: STUN_URLS });
+    }
+    if (TURN_URLS.length > 0) {
+        if (TURN_SECRET) {
+            // TURN REST API credentials: `<expiry>:<peer>` signed with the secret
+            const expiry = Math.floor(Date.now() / 1000) + TURN_CREDENTIAL_TTL_SECS;
+            const username = peerId ? `${expiry}:${peerId}` : `${expiry}`;
+            const credential = createHmac('sha1', TURN_SECRET).update(username).digest('base64');
+            servers.push({ urls: TURN_URLS, username, credential });
+        } else {
+            servers.push({
+                urls: TURN_URLS,
+                username: process.env.TURN_USERNAME || '',
+                credential: process.env.TURN_CREDENTIAL || ''
+            });
+        }
+    }
+    return servers;
+}
+
+function spiffeId(cert) {
+    const uri = (cert?.subjectaltname || '')
+        .split(', ')
+        .find(name => name.startsWith('URI:spiffe://'));
+    return uri ? uri.slice('URI:'.length) : null;
+}
+
+// Protocol versions (see tx-core's protocol module). Clients open with
+// `hello` and are answered `negotiated`: the lower of the two versions, and
+// of the capabilities offered, those this server honours itself. The rest
+// are between peers and are passed on to room members as advertised.
+// Clients that never say hello speak version 1 with no capabilities.
+const PROTOCOL_VERSION = 2;
+const MIN_PROTOCOL_VERSION = 1;
+// Relays `transaction-ack`; settles rooms with a batch window itself
+const SERVER_FEATURES = ['acks', 'batching'];
+const PERMESSAGE_DEFLATE_FEATURE = 'permessage-deflate';
+
+// Whether this server honours `feature` on `ws`
+function honours(ws, feature) {
+    if (feature === PERMESSAGE_DEFLATE_FEATURE) {
+        return ws.extensions.includes('permessage-deflate');
+    }
+    return SERVER_FEATURES.includes(feature);
+}
+
+function negotiate(ws, data) {
+    const version = Number.isInteger(data.protocolVersion) ? data.protocolVersion : 1;
+    if (version < MIN_PROTOCOL_VERSION) {
+        send(ws, {
+            type: 'error',
+            message: `Protocol version ${version} is no longer supported, ${MIN_PROTOCOL_VERSION} or later required`
+        });
+        ws.close(1002, 'Unsupported protocol version');
+        return;
+    }
+    ws.protocolVersion = Math.min(version, PROTOCOL_VERSION);
+    ws.features = Array.isArray(data.features)
+        ? data.features.filter(feature => typeof feature === 'string')
+        : [];
+    send(ws, {
+        type: 'negotiated',
+        protocolVersion: ws.protocolVersion,
+        features: ws.features.filter(feature => honours(ws, feature))
+    });
+}
+
+const RESUME_TTL_MS = 60000;
+
+// Sessions handed to this instance, by resumption token
+const resumptions = new Map();
+// Peers this instance redirected elsewhere: peerId -> { target, token, roomId }
+const redirected = new Map();
 
 console.log('Starting P2P Signaling Server...');
 
 wss.on('connection', (ws, req) => {
+    if (draining) {
+        send(ws, { type: 'server-draining', message: 'Server is shutting down' });
+        ws.close(1012, 'Server draining');
+        return;
+    }
     console.log(`New peer connected from ${req.socket.remoteAddress}`);
+    ws.protocolVersion = 1;
+    ws.features = [];
     
     ws.on('message', (message) => {
         try {
@@ -26,59 +322,220 @@ wss.on('connection', (ws, req) => {
             handleMessage(ws, data);
         } catch (error) {
             console.error('Invalid message format:', error);
-            ws.send(JSON.stringify({ 
+            send(ws, { 
                 type: 'error', 
                 message: 'Invalid message format' 
-            }));
+            });
         }
     });
 
+    ws.rawSocket = req.socket;
+
     ws.on('close', () => {
         console.log('Peer disconnected');
-        cleanupPeer(ws);
+        compressionTotals.messages += ws.messagesOut || 0;
+        compressionTotals.payloadBytes += ws.payloadBytesOut || 0;
+        compressionTotals.wireBytes += ws.rawSocket.bytesWritten;
+        // Handed-off peers live on elsewhere; don't tell the room they left
+        cleanupPeer(ws, !ws.handedOff);
     });
 
     ws.on('error', (error) => {
+        // ws rejects messages over maxPayload with a 1009 close
+        if (error.code === 'WS_ERR_UNSUPPORTED_MESSAGE_LENGTH') {
+            quotaRejections.messageBytes++;
+        }
         console.error('WebSocket error:', error);
     });
 
     // Send welcome message
-    ws.send(JSON.stringify({
+    send(ws, {
         type: 'welcome',
-        message: 'Connected to signaling server'
-    }));
+        message: 'Connected to signaling server',
+        protocolVersion: PROTOCOL_VERSION
+    });
 });
 
 function handleMessage(ws, data) {
     switch (data.type) {
+        case 'hello':
+            negotiate(ws, data);
+            break;
         case 'join':
-            joinRoom(ws, data.roomId, data.peerId);
+            authorizeAndJoin(ws, data.roomId, data.peerId, data.encrypted === true);
+            break;
+        case 'resume':
+            resumeSession(ws, data);
             break;
         case 'leave':
             leaveRoom(ws, data.roomId);
             break;
+        case 'list-rooms':
+            send(ws, { type: 'rooms', rooms: listRooms() });
+            break;
         case 'offer':
         case 'answer':
         case 'ice-candidate':
+        case 'transaction-rejected':
+        case 'transaction-ack':
+        case 'channel-open':
+        case 'channel-update':
+        case 'channel-close':
+        case 'swap-offer':
+        case 'swap-accept':
+        case 'swap-decline':
+        case 'quote':
+        case 'memo':
+        case 'key-exchange':
+        case 'sender-key':
             relaySignalingMessage(ws, data);
             break;
         case 'transaction':
             broadcastTransaction(ws, data);
             break;
+        case 'quote-request':
+        case 'peer-map':
+            broadcastToRoom(ws, data);
+            break;
+        case 'resync':
+            resync(ws, data.afterSeq);
+            break;
         case 'ping':
-            ws.send(JSON.stringify({ type: 'pong' }));
+            send(ws, { type: 'pong' });
             break;
         default:
             console.log(`Unknown message type: ${data.type}`);
     }
 }
 
-function joinRoom(ws, roomId, peerId) {
+// Suspended or closed endpoints are refused at join time. Endpoints the
+// gateway doesn't know about (404) or an unreachable gateway fail open so
+// the relay keeps working without a provisioning step.
+async function checkEndpointStatus(peerId) {
+    try {
+        const response = await fetch(`${API_GATEWAY}/api/endpoints/${encodeURIComponent(peerId)}`, {
+            headers: gatewayHeaders()
+        });
+        if (!response.ok) {
+            return null;
+        }
+        const endpoint = await response.json();
+        return endpoint.status;
+    } catch (error) {
+        console.error(`Endpoint status lookup failed for ${peerId}:`, error.message);
+        return null;
+    }
+}
+
+async function authorizeAndJoin(ws, roomId, peerId, encrypted = false) {
+    if (draining) {
+        send(ws, {
+            type: 'server-draining',
+            message: 'Server is shutting down, reconnect to join'
+        });
+        return;
+    }
+
+    pendingJoins++;
+    try {
+        await checkAndJoin(ws, roomId, peerId, encrypted);
+    } finally {
+        pendingJoins--;
+    }
+}
+
+async function refreshBatching(roomId) {
+    let batch = roomBatches.get(roomId);
+    if (batch && Date.now() - batch.fetchedAt < BATCH_CONFIG_TTL_MS) return;
+
+    let windowMs = null;
+    try {
+        const response = await fetch(`${API_GATEWAY}/api/rooms/${encodeURIComponent(roomId)}/batching`, {
+            headers: gatewayHeaders()
+        });
+        if (response.ok) {
+            windowMs = (await response.json()).window_ms || null;
+        }
+    } catch (error) {
+        console.error(`Batching lookup failed for room ${roomId}:`, error.message);
+        // Keep the last known setting while the gateway is away
+        if (batch) windowMs = batch.windowMs;
+    }
+
+    if (!batch) {
+        batch = { windowStart: Date.now(), transactions: [], pending: [] };
+        roomBatches.set(roomId, batch);
+    }
+    batch.windowMs = windowMs;
+    batch.fetchedAt = Date.now();
+}
+
+function batchWindowMs(roomId) {
+    const batch = roomBatches.get(roomId);
+    return batch ? batch.windowMs : null;
+}
+
+async function checkAndJoin(ws, roomId, peerId, encrypted) {
+    if (roomId) {
+        await refreshBatching(roomId);
+    }
+    if (peerId) {
+        const status = await checkEndpointStatus(peerId);
+        if (status === 'suspended' || status === 'closed') {
+            send(ws, {
+                type: 'error',
+                message: `Endpoint ${peerId} is ${status}`
+            });
+            console.log(`Refused join for ${status} endpoint ${peerId}`);
+            return;
+        }
+    }
+    joinRoom(ws, roomId, peerId, encrypted);
+}
+
+// Why `ws` can't join `roomId`, or open it `encrypted`, if it can't
+function encryptionRefusal(ws, roomId, encrypted) {
+    const offersEncryption = (ws.features || []).includes(GROUP_ENCRYPTION);
+    if (rooms.has(roomId)) {
+        if (encryptedRooms.has(roomId) && !offersEncryption) {
+            return `Room ${roomId} is end-to-end encrypted: group encryption required`;
+        }
+        if (encrypted && !encryptedRooms.has(roomId)) {
+            return `Room ${roomId} is already open without encryption`;
+        }
+        return null;
+    }
+    if (encrypted && !offersEncryption) {
+        return 'Encrypted rooms need group encryption, offered in hello';
+    }
+    if (encrypted && batchWindowMs(roomId)) {
+        // The server would have nothing to record
+        return `Room ${roomId} settles in batches and can't be end-to-end encrypted`;
+    }
+    return null;
+}
+
+// `encrypted` opens a new room end-to-end encrypted; an existing room
+// keeps what it was opened as
+function joinRoom(ws, roomId, peerId, encrypted = false) {
     if (!roomId || !peerId) {
-        ws.send(JSON.stringify({ 
+        send(ws, { 
             type: 'error', 
             message: 'Room ID and Peer ID required' 
-        }));
+        });
+        return;
+    }
+    if (!ROOM_ID_PATTERN.test(roomId)) {
+        send(ws, {
+            type: 'error',
+            message: 'Room ID must be 1-64 letters, digits, dashes or underscores'
+        });
+        return;
+    }
+
+    const refusal = encryptionRefusal(ws, roomId, encrypted);
+    if (refusal) {
+        send(ws, { type: 'error', message: refusal });
         return;
     }
 
@@ -89,6 +546,7 @@ function joinRoom(ws, roomId, peerId) {
 
     if (!rooms.has(roomId)) {
         rooms.set(roomId, new Set());
+        if (encrypted) encryptedRooms.add(roomId);
     }
     
     const room = rooms.get(roomId);
@@ -97,30 +555,46 @@ function joinRoom(ws, roomId, peerId) {
     
     // Notify existing peers about new peer
     const existingPeers = [];
+    const peerFeatures = {};
     room.forEach(peer => {
-        peer.send(JSON.stringify({
+        send(peer, {
             type: 'peer-joined',
             peerId: peerId,
-            roomId: roomId
-        }));
+            roomId: roomId,
+            protocolVersion: ws.protocolVersion,
+            features: ws.features
+        });
         existingPeers.push(peer.peerId);
+        peerFeatures[peer.peerId] = peer.features;
     });
     
     room.add(ws);
     peers.set(peerId, ws);
     
     // Send room state to new peer
-    ws.send(JSON.stringify({
+    send(ws, {
         type: 'room-joined',
         roomId: roomId,
         peerId: peerId,
-        peers: existingPeers
-    }));
+        peers: existingPeers,
+        peerFeatures,
+        batchWindowMs: batchWindowMs(roomId),
+        encrypted: encryptedRooms.has(roomId)
+    });
 
     console.log(`Peer ${peerId} joined room ${roomId}. Room size: ${room.size}`);
 }
 
-function leaveRoom(ws, roomId) {
+function listRooms() {
+    return Array.from(rooms.entries()).map(([roomId, members]) => ({
+        roomId,
+        peerCount: members.size,
+        batchWindowMs: batchWindowMs(roomId),
+        encrypted: encryptedRooms.has(roomId)
+    }));
+}
+
+function leaveRoom(ws, roomId, notify = true) {
     if (!roomId || !rooms.has(roomId)) return;
     
     const room = rooms.get(roomId);
@@ -131,78 +605,271 @@ function leaveRoom(ws, roomId) {
         
         // Notify remaining peers
         room.forEach(peer => {
-            peer.send(JSON.stringify({
+            if (!notify) return;
+            send(peer, {
                 type: 'peer-left',
                 peerId: ws.peerId,
                 roomId: roomId
-            }));
+            });
         });
     }
     
     // Clean up empty room
     if (room.size === 0) {
         rooms.delete(roomId);
+        encryptedRooms.delete(roomId);
+        roomHistory.delete(roomId);
         console.log(`Room ${roomId} deleted (empty)`);
     }
     
+    clearNegotiations(ws.peerId);
     ws.roomId = null;
     ws.peerId = null;
 }
 
+function clearNegotiations(peerId) {
+    if (!peerId) return;
+    for (const key of pendingNegotiations.keys()) {
+        const [offerer, answerer] = key.split('->');
+        if (offerer === peerId || answerer === peerId) {
+            pendingNegotiations.delete(key);
+        }
+    }
+}
+
+function trackNegotiation(data, fromPeer) {
+    if (data.type === 'offer') {
+        pendingNegotiations.set(`${fromPeer}->${data.targetPeer}`, Date.now());
+    } else if (data.type === 'answer') {
+        pendingNegotiations.delete(`${data.targetPeer}->${fromPeer}`);
+    }
+}
+
 function relaySignalingMessage(ws, data) {
     const { targetPeer, roomId } = data;
     
     if (!targetPeer || !roomId) {
-        ws.send(JSON.stringify({ 
+        send(ws, { 
             type: 'error', 
             message: 'Target peer and room ID required for signaling' 
-        }));
+        });
         return;
     }
 
     const targetWs = peers.get(targetPeer);
+    if ((!targetWs || targetWs.handedOff) && redirected.has(targetPeer)) {
+        data.fromPeer = ws.peerId;
+        forwardToMailbox(targetPeer, data, ws);
+        trackNegotiation(data, ws.peerId);
+        return;
+    }
     if (targetWs && targetWs.roomId === roomId) {
         // Add sender info
         data.fromPeer = ws.peerId;
-        targetWs.send(JSON.stringify(data));
+        send(targetWs, data);
+        trackNegotiation(data, ws.peerId);
         console.log(`Relayed ${data.type} from ${ws.peerId} to ${targetPeer}`);
     } else {
-        ws.send(JSON.stringify({
+        send(ws, {
             type: 'error',
             message: `Peer ${targetPeer} not found or not in same room`
-        }));
+        });
     }
 }
 
 function broadcastTransaction(ws, data) {
     if (!ws.roomId || !rooms.has(ws.roomId)) {
-        ws.send(JSON.stringify({ 
+        send(ws, { 
             type: 'error', 
             message: 'Not in a room' 
-        }));
+        });
+        return;
+    }
+
+    if (encryptedRooms.has(ws.roomId) && (data.transaction || !data.sealed || data.epoch === undefined)) {
+        send(ws, { type: 'error', message: 'Room is end-to-end encrypted: send sealed transactions only' });
         return;
     }
 
     const room = rooms.get(ws.roomId);
+    if (!roomHistory.has(ws.roomId)) {
+        roomHistory.set(ws.roomId, { seq: 0, recent: [] });
+    }
+    const history = roomHistory.get(ws.roomId);
     const broadcastData = {
         type: 'transaction-broadcast',
         transaction: data.transaction,
+        // An end-to-end encrypted transaction, relayed unread, and the
+        // sender key it is under in an encrypted room
+        sealed: data.sealed,
+        epoch: data.epoch,
         fromPeer: ws.peerId,
         roomId: ws.roomId,
+        seq: ++history.seq,
         timestamp: Date.now()
     };
+    history.recent.push(broadcastData);
+    if (history.recent.length > ROOM_HISTORY_SIZE) {
+        history.recent.shift();
+    }
+
+    // Broadcast to all peers in room (including sender for confirmation),
+    // skipping clients too far behind to take more
+    const batch = roomBatches.get(ws.roomId);
+    if (batch && batch.windowMs && data.transaction) {
+        batch.transactions.push(data.transaction);
+    }
 
-    // Broadcast to all peers in room (including sender for confirmation)
     room.forEach(peer => {
-        peer.send(JSON.stringify(broadcastData));
+        if (peer.handedOff) return;
+        if (peer.bufferedAmount > LAG_THRESHOLD_BYTES) {
+            peer.missed = (peer.missed || 0) + 1;
+            return;
+        }
+        notifyLagged(peer);
+        send(peer, broadcastData);
+    });
+    redirected.forEach((session, peerId) => {
+        if (session.roomId === ws.roomId) {
+            forwardToMailbox(peerId, broadcastData, ws);
+        }
     });
 
     console.log(`Broadcasted transaction from ${ws.peerId} to ${room.size} peers`);
 }
 
-function cleanupPeer(ws) {
+// Fans a message out to the rest of the sender's room as-is. Unlike
+// transactions these aren't sequenced or kept for resync: requests for
+// quotes are short-lived.
+function broadcastToRoom(ws, data) {
+    if (!ws.roomId || !rooms.has(ws.roomId)) {
+        send(ws, { type: 'error', message: 'Not in a room' });
+        return;
+    }
+
+    data.fromPeer = ws.peerId;
+    data.roomId = ws.roomId;
+    rooms.get(ws.roomId).forEach(peer => {
+        if (peer !== ws && !peer.handedOff) send(peer, data);
+    });
+    console.log(`Broadcasted ${data.type} from ${ws.peerId}`);
+}
+
+// Tells a client that has drained its queue how many broadcasts it missed
+function notifyLagged(ws) {
+    if (!ws.missed || ws.bufferedAmount > LAG_THRESHOLD_BYTES) return;
+
+    const history = roomHistory.get(ws.roomId);
+    send(ws, {
+        type: 'lagged',
+        roomId: ws.roomId,
+        missed: ws.missed,
+        seq: history ? history.seq : 0
+    });
+    console.log(`${ws.peerId} lagged, missed ${ws.missed} broadcasts`);
+    laggedNotifications++;
+    ws.missed = 0;
+}
+
+// Replays the room's broadcasts after `afterSeq`. `complete` is false when
+// the history no longer reaches back that far, and the client has to fall
+// back to the gateway for the rest.
+function resync(ws, afterSeq) {
+    const history = ws.roomId && roomHistory.get(ws.roomId);
+    if (!history) {
+        send(ws, { type: 'resync-complete', roomId: ws.roomId, seq: 0, complete: true });
+        return;
+    }
+
+    const after = Number(afterSeq) || 0;
+    const oldest = history.recent.length > 0 ? history.recent[0].seq : history.seq + 1;
+    const pending = history.recent.filter(broadcast => broadcast.seq > after);
+    for (let i = 0; i < pending.length; i++) {
+        // Replaying can't outrun the client either; the rest comes with the
+        // next `lagged`
+        if (ws.bufferedAmount > LAG_THRESHOLD_BYTES) {
+            ws.missed = (ws.missed || 0) + pending.length - i;
+            return;
+        }
+        if (!send(ws, pending[i])) return;
+    }
+    send(ws, {
+        type: 'resync-complete',
+        roomId: ws.roomId,
+        seq: history.seq,
+        complete: oldest <= after + 1
+    });
+}
+
+// Clients that stopped receiving broadcasts learn about it as soon as their
+// queue drains, even if the room has gone quiet
+setInterval(() => {
+    wss.clients.forEach(client => {
+        if (client.missed) notifyLagged(client);
+    });
+}, 500).unref();
+
+async function postBatch(roomId, window) {
+    const response = await fetch(`${API_GATEWAY}/api/rooms/${encodeURIComponent(roomId)}/batches`, {
+        method: 'POST',
+        headers: gatewayHeaders({ 'Content-Type': 'application/json' }),
+        body: JSON.stringify(window)
+    });
+    if (!response.ok) {
+        throw new Error(`HTTP ${response.status}`);
+    }
+    const report = await response.json();
+    console.log(`Settled ${report.transaction_count} transactions of room ${roomId} as ${report.settlements.length} transfers`);
+}
+
+let batchFlushRunning = false;
+
+// Closes windows that have ended and posts them, oldest first
+async function flushBatches() {
+    if (batchFlushRunning) return;
+    batchFlushRunning = true;
+    try {
+        const now = Date.now();
+        for (const [roomId, batch] of roomBatches) {
+            const windowEnded = batch.windowMs ? now - batch.windowStart >= batch.windowMs : true;
+            if (windowEnded) {
+                if (batch.transactions.length > 0) {
+                    batch.pending.push({
+                        window_start: new Date(batch.windowStart).toISOString(),
+                        window_end: new Date(now).toISOString(),
+                        transactions: batch.transactions
+                    });
+                }
+                batch.windowStart = now;
+                batch.transactions = [];
+            }
+
+            while (batch.pending.length > 0) {
+                try {
+                    await postBatch(roomId, batch.pending[0]);
+                    batch.pending.shift();
+                } catch (error) {
+                    console.error(`Batch settlement for room ${roomId} failed, will retry:`, error.message);
+                    break;
+                }
+            }
+
+            if (rooms.has(roomId)) {
+                await refreshBatching(roomId);
+            } else if (batch.pending.length === 0 && batch.transactions.length === 0) {
+                roomBatches.delete(roomId);
+            }
+        }
+    } finally {
+        batchFlushRunning = false;
+    }
+}
+
+setInterval(flushBatches, 1000).unref();
+
+function cleanupPeer(ws, notify = true) {
     if (ws.roomId) {
-        leaveRoom(ws, ws.roomId);
+        leaveRoom(ws, ws.roomId, notify);
     }
     if (ws.peerId) {
         peers.delete(ws.peerId);
@@ -219,6 +886,236 @@ app.get('/health', (req, res) => {
     });
 });
 
+async function gatewayHealthy() {
+    try {
+        const response = await fetch(`${API_GATEWAY}/health`, { signal: AbortSignal.timeout(2000) });
+        return response.ok;
+    } catch (error) {
+        return false;
+    }
+}
+
+// Readiness probe: fails while draining or while a configured gate is down
+app.get('/ready', async (req, res) => {
+    const failing = [];
+    if (draining) {
+        failing.push('draining');
+    }
+    if (READINESS_GATES.includes('gateway') && !(await gatewayHealthy())) {
+        failing.push('gateway');
+    }
+
+    res.status(failing.length === 0 ? 200 : 503).json({
+        ready: failing.length === 0,
+        failing,
+        connections: wss.clients.size,
+        inFlight: inFlightCount()
+    });
+});
+
+function handoffHeaders() {
+    const headers = { 'Content-Type': 'application/json' };
+    if (HANDOFF_SECRET) {
+        headers['X-Handoff-Secret'] = HANDOFF_SECRET;
+    }
+    return headers;
+}
+
+function requireInternalPeer(req, res, next) {
+    if (!MTLS) return next();
+    const id = req.socket.authorized ? spiffeId(req.socket.getPeerCertificate()) : null;
+    if (!id || !MTLS_ALLOWED_IDS.has(id)) {
+        return res.status(403).json({ error: 'mTLS required' });
+    }
+    next();
+}
+
+function requireHandoffSecret(req, res, next) {
+    if (HANDOFF_SECRET && req.get('X-Handoff-Secret') !== HANDOFF_SECRET) {
+        return res.status(401).json({ error: 'Invalid handoff secret' });
+    }
+    next();
+}
+
+function forwardToMailbox(peerId, message, fromWs) {
+    const { target, token } = redirected.get(peerId);
+    fetch(`${target}/handoff/mailbox`, {
+        method: 'POST',
+        headers: handoffHeaders(),
+        body: JSON.stringify({ token, message })
+    }).then(response => {
+        if (response.status === 507) {
+            quotaExceeded(fromWs, 'mailbox', `Mailbox for ${peerId} is full, message dropped`);
+        }
+    }).catch(error => console.error(`Mailbox forward to ${target} for ${peerId} failed:`, error.message));
+}
+
+// Moves every session in a room to the first ready sibling. Returns the
+// clients that were redirected.
+async function handOffSessions() {
+    const sessions = [];
+    wss.clients.forEach(client => {
+        if (client.peerId && client.roomId) {
+            sessions.push(client);
+        }
+    });
+    if (sessions.length === 0) return new Set();
+
+    for (const target of HANDOFF_PEERS) {
+        try {
+            const ready = await fetch(`${target}/ready`, { signal: AbortSignal.timeout(2000) });
+            if (!ready.ok) continue;
+
+            const response = await fetch(`${target}/handoff`, {
+                method: 'POST',
+                headers: handoffHeaders(),
+                body: JSON.stringify({
+                    sessions: sessions.map(client => ({ peerId: client.peerId, roomId: client.roomId }))
+                })
+            });
+            if (!response.ok) continue;
+
+            const { wsUrl, tokens } = await response.json();
+            const handedOff = new Set();
+            sessions.forEach(client => {
+                const token = tokens[client.peerId];
+                if (!token) return;
+                redirected.set(client.peerId, { target, token, roomId: client.roomId });
+                client.handedOff = true;
+                send(client, {
+                    type: 'redirect',
+                    url: wsUrl,
+                    resumeToken: token,
+                    roomId: client.roomId,
+                    peerId: client.peerId
+                });
+                handedOff.add(client);
+            });
+            console.log(`Handed off ${handedOff.size} sessions to ${target}`);
+            return handedOff;
+        } catch (error) {
+            console.error(`Handoff to ${target} failed:`, error.message);
+        }
+    }
+    console.log('No sibling accepted the handoff, clients will reconnect on their own');
+    return new Set();
+}
+
+function drain() {
+    if (drainPromise) return drainPromise;
+
+    draining = true;
+    console.log(`Draining: notifying ${wss.clients.size} peers, waiting up to ${DRAIN_TIMEOUT_MS}ms for in-flight relays`);
+
+    const deadline = Date.now() + DRAIN_TIMEOUT_MS;
+    drainPromise = handOffSessions().then(handedOff => new Promise(resolve => {
+        wss.clients.forEach(client => {
+            if (handedOff.has(client)) return;
+            send(client, {
+                type: 'server-draining',
+                message: 'Server is restarting, reconnect shortly'
+            });
+        });
+
+        const check = () => {
+            const inFlight = inFlightCount();
+            if (inFlight === 0 || Date.now() >= deadline) {
+                if (inFlight > 0) {
+                    console.log(`Drain timeout with ${inFlight} relays still in flight`);
+                }
+                resolve();
+                return;
+            }
+            setTimeout(check, 250);
+        };
+        check();
+    }));
+    return drainPromise;
+}
+
+// Internal: a draining sibling hands over its sessions
+app.post('/handoff', requireInternalPeer, requireHandoffSecret, (req, res) => {
+    if (draining || !PUBLIC_URL) {
+        return res.status(503).json({ error: 'Not accepting handoffs' });
+    }
+
+    const tokens = {};
+    (req.body.sessions || []).forEach(({ peerId, roomId }) => {
+        if (!peerId || !roomId) return;
+        const token = randomUUID();
+        resumptions.set(token, { peerId, roomId, mailbox: [], expiresAt: Date.now() + RESUME_TTL_MS });
+        tokens[peerId] = token;
+    });
+
+    console.log(`Accepted handoff of ${Object.keys(tokens).length} sessions`);
+    res.json({ wsUrl: PUBLIC_URL, tokens });
+});
+
+// Internal: a message for a peer that was handed to this instance
+app.post('/handoff/mailbox', requireInternalPeer, requireHandoffSecret, (req, res) => {
+    const { token, message } = req.body;
+    const session = resumptions.get(token);
+    if (!session) {
+        return res.status(404).json({ error: 'Unknown resumption token' });
+    }
+
+    const ws = peers.get(session.peerId);
+    if (session.resumed && ws) {
+        send(ws, message);
+    } else if (session.mailbox.length < MAX_MAILBOX_ITEMS) {
+        session.mailbox.push(message);
+    } else {
+        quotaRejections.mailbox++;
+        return res.status(507).json({ error: 'Mailbox full' });
+    }
+    res.json({ queued: !session.resumed });
+});
+
+// A client following a `redirect`. Unknown or expired tokens fall back to a
+// regular join, so the client ends up in its room either way.
+async function resumeSession(ws, data) {
+    const session = resumptions.get(data.resumeToken);
+    if (!session || session.expiresAt < Date.now() || session.peerId !== data.peerId) {
+        console.log(`Resumption failed for ${data.peerId}, joining normally`);
+        await authorizeAndJoin(ws, data.roomId, data.peerId, data.encrypted === true);
+        return;
+    }
+
+    await authorizeAndJoin(ws, session.roomId, session.peerId, data.encrypted === true);
+    if (ws.peerId !== session.peerId) return;
+
+    session.resumed = true;
+    session.mailbox.forEach(message => send(ws, message));
+    console.log(`Resumed ${session.peerId} with ${session.mailbox.length} mailbox items`);
+    session.mailbox = [];
+}
+
+// Forget resumption tokens once they can't be used
+setInterval(() => {
+    const now = Date.now();
+    resumptions.forEach((session, token) => {
+        if (session.expiresAt < now) resumptions.delete(token);
+    });
+}, RESUME_TTL_MS).unref();
+
+// Kubernetes preStop hook (httpGet): returns once draining is done, so the
+// pod only receives SIGTERM after in-flight relays have settled
+app.get('/prestop', async (req, res) => {
+    await drain();
+    res.json({ drained: true, connections: wss.clients.size });
+});
+
+// Rooms a client can join, as also answered to `list-rooms`
+app.get('/rooms', (req, res) => {
+    res.json({ rooms: listRooms() });
+});
+
+// RTCPeerConnection `iceServers` for `?peerId=`
+app.get('/ice-servers', (req, res) => {
+    const peerId = typeof req.query.peerId === 'string' ? req.query.peerId : '';
+    res.json({ iceServers: iceServers(peerId) });
+});
+
 // Stats endpoint
 app.get('/stats', (req, res) => {
     const roomStats = Array.from(rooms.entries()).map(([roomId, peers]) => ({
@@ -228,8 +1125,20 @@ app.get('/stats', (req, res) => {
     }));
 
     res.json({
+        draining,
+        quotaRejections,
+        laggedNotifications,
+        compression: compressionStats(),
         totalConnections: wss.clients.size,
         totalRooms: rooms.size,
+        batching: Array.from(roomBatches.entries())
+            .filter(([, batch]) => batch.windowMs)
+            .map(([roomId, batch]) => ({
+                roomId,
+                windowMs: batch.windowMs,
+                buffered: batch.transactions.length,
+                pendingWindows: batch.pending.length
+            })),
         rooms: roomStats
     });
 });
@@ -241,9 +1150,21 @@ server.listen(PORT, () => {
     console.log(`📈 Stats: http://localhost:${PORT}/stats`);
 });
 
+// Internal listener for siblings; the handshake already rejects
+// certificates the internal CA didn't issue
+const internalServer = MTLS && createTlsServer({ ...MTLS, requestCert: true, rejectUnauthorized: true }, app);
+if (internalServer) {
+    internalServer.listen(MTLS_PORT, () => {
+        console.log(`🔒 Internal mTLS listener on port ${MTLS_PORT}`);
+    });
+}
+
 // Graceful shutdown
-process.on('SIGTERM', () => {
+process.on('SIGTERM', async () => {
     console.log('SIGTERM received, shutting down gracefully');
+    await drain();
+    wss.clients.forEach(client => client.close(1012, 'Server restarting'));
+    if (internalServer) internalServer.close();
     server.close(() => {
         console.log('Server closed');
         process.exit(0);
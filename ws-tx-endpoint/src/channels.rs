@@ -0,0 +1,79 @@
+use chrono::Utc;
+use tx_core::ChannelUpdate;
+
+use crate::crypto::{self, EndpointKeys};
+
+/// How often an outgoing stream pays out and sends a fresh update.
+pub const CHANNEL_TICK_MS: u32 = 250;
+
+/// Defaults for the "Stream" button.
+pub const STREAM_RATE_PER_SEC: f64 = 0.10;
+pub const STREAM_RESERVE: f64 = 10.0;
+
+/// A streaming payment channel this endpoint is part of, as payer or
+/// payee. `rate_per_sec` is only set on channels we are paying into.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpenChannel {
+    pub update: ChannelUpdate,
+    pub rate_per_sec: Option<f64>,
+}
+
+/// Leaves the signature alone if `paid` isn't an amount, which whole cents
+/// under the reservation always are.
+fn sign(update: &mut ChannelUpdate, keys: &EndpointKeys) {
+    if let Ok(settlement) = update.settlement() {
+        update.signature = crypto::sign_transaction(&settlement, keys);
+    }
+}
+
+/// Opens a channel to `payee`, with nothing paid yet.
+pub fn open(keys: &EndpointKeys, payer: &str, payee: &str, reserved: f64, rate_per_sec: f64) -> OpenChannel {
+    let mut update = ChannelUpdate {
+        channel_id: uuid::Uuid::new_v4().to_string(),
+        payer: payer.to_string(),
+        payee: payee.to_string(),
+        reserved,
+        paid: 0.0,
+        opened_at: Utc::now(),
+        signature: String::new(),
+        public_key: keys.public_key_hex(),
+    };
+    sign(&mut update, keys);
+    OpenChannel {
+        update,
+        rate_per_sec: Some(rate_per_sec),
+    }
+}
+
+impl OpenChannel {
+    /// Pays out one tick's worth, capped at the reservation, and re-signs.
+    /// Returns whether anything changed.
+    pub fn advance(&mut self, keys: &EndpointKeys) -> bool {
+        let Some(rate) = self.rate_per_sec else {
+            return false;
+        };
+        let update = &mut self.update;
+        if update.paid >= update.reserved {
+            return false;
+        }
+
+        let paid = update.paid + rate * CHANNEL_TICK_MS as f64 / 1000.0;
+        // Whole cents keep the signed amount stable across serializations
+        update.paid = ((paid * 100.0).round() / 100.0).min(update.reserved);
+        sign(update, keys);
+        true
+    }
+
+    /// Whether `next` may replace our copy of an incoming channel: same
+    /// channel terms, validly signed, and never paying less than before.
+    pub fn accepts(&self, next: &ChannelUpdate) -> bool {
+        let current = &self.update;
+        next.verify()
+            && next.public_key == current.public_key
+            && next.payer == current.payer
+            && next.payee == current.payee
+            && next.reserved == current.reserved
+            && next.opened_at == current.opened_at
+            && next.paid >= current.paid
+    }
+}
@@ -0,0 +1,16 @@
+//! Settings supplied by the page at runtime, as `window.RELAYER_CONFIG`.
+//! The gateway writes it to `config.js` when it serves the app, so one
+//! build can be pointed at any gateway and signaling server; without it
+//! the compile-time defaults apply.
+
+use wasm_bindgen::JsValue;
+
+/// `RELAYER_CONFIG[key]`, if the page set it to a non-empty string.
+pub fn get(key: &str) -> Option<String> {
+    let window: JsValue = web_sys::window()?.into();
+    let config = js_sys::Reflect::get(&window, &"RELAYER_CONFIG".into()).ok()?;
+    if config.is_undefined() || config.is_null() {
+        return None;
+    }
+    js_sys::Reflect::get(&config, &key.into()).ok()?.as_string().filter(|value| !value.is_empty())
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Per-endpoint block and allow lists. The gateway is authoritative; a copy
+/// is kept in localStorage so the lists apply before the gateway answers.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CounterpartyLists {
+    pub blocked: Vec<String>,
+    pub allowed: Vec<String>,
+}
+
+impl CounterpartyLists {
+    /// An empty allowlist means "allow everyone not blocked".
+    pub fn accepts(&self, peer: &str) -> bool {
+        if self.is_blocked(peer) {
+            return false;
+        }
+        self.allowed.is_empty() || self.allowed.iter().any(|p| p == peer)
+    }
+
+    /// Explicitly allowed, unlike `accepts`, which an empty allowlist
+    /// opens to everyone.
+    pub fn is_trusted(&self, peer: &str) -> bool {
+        !self.is_blocked(peer) && self.allowed.iter().any(|p| p == peer)
+    }
+
+    pub fn is_blocked(&self, peer: &str) -> bool {
+        self.blocked.iter().any(|p| p == peer)
+    }
+
+    pub fn load_cached(endpoint_id: &str) -> Self {
+        storage()
+            .and_then(|s| s.get_item(&cache_key(endpoint_id)).ok().flatten())
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn store_cached(&self, endpoint_id: &str) {
+        if let (Some(storage), Ok(json)) = (storage(), serde_json::to_string(self)) {
+            let _ = storage.set_item(&cache_key(endpoint_id), &json);
+        }
+    }
+}
+
+fn cache_key(endpoint_id: &str) -> String {
+    format!("counterparties:{}", endpoint_id)
+}
+
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window().and_then(|w| w.local_storage().ok().flatten())
+}
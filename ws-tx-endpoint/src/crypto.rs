@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use ed25519_dalek::{Signer, SigningKey};
+use rand_core::OsRng;
+use tx_core::plugin::{GroupEncryption, PeerEncryption, RatchetState};
+use tx_core::{canonical_bytes, verify_signature, AckResult};
+use wasm_bindgen::prelude::*;
+use crate::{key_usage, Transaction};
+
+// Verification lives in tx-core so the gateway checks the same payload
+pub use tx_core::verify_transaction;
+
+/// An endpoint's ed25519 keypair. Generated on first use and kept in
+/// localStorage, so the endpoint keeps its identity across reloads. Every
+/// signature goes on the endpoint's key-usage log; keys loaded from a
+/// bare secret, as in the worker, have no endpoint and log nothing.
+#[derive(Clone)]
+pub struct EndpointKeys {
+    signing_key: SigningKey,
+    endpoint_id: Option<String>,
+}
+
+impl EndpointKeys {
+    pub fn load_or_generate(endpoint_id: &str) -> Self {
+        let key = format!("signing-key:{}", endpoint_id);
+        if let Some(keys) = storage()
+            .and_then(|s| s.get_item(&key).ok().flatten())
+            .and_then(|secret| Self::from_secret_hex(&secret))
+        {
+            return EndpointKeys { endpoint_id: Some(endpoint_id.to_string()), ..keys };
+        }
+
+        let keys = EndpointKeys {
+            signing_key: SigningKey::generate(&mut OsRng),
+            endpoint_id: Some(endpoint_id.to_string()),
+        };
+        if let Some(storage) = storage() {
+            let _ = storage.set_item(&key, &keys.secret_hex());
+        }
+        keys
+    }
+
+    pub fn from_secret_hex(secret: &str) -> Option<Self> {
+        let bytes: [u8; 32] = hex::decode(secret).ok()?.try_into().ok()?;
+        Some(EndpointKeys {
+            signing_key: SigningKey::from_bytes(&bytes),
+            endpoint_id: None,
+        })
+    }
+
+    pub fn secret_hex(&self) -> String {
+        hex::encode(self.signing_key.to_bytes())
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.signing_key.verifying_key().to_bytes())
+    }
+
+    /// `subject` says what `message` is, for the key-usage log.
+    pub fn sign(&self, subject: &str, message: &[u8]) -> String {
+        self.log_signature(subject);
+        hex::encode(self.signing_key.sign(message).to_bytes())
+    }
+
+    /// For signatures made elsewhere with this key, i.e. by the worker.
+    pub fn log_signature(&self, subject: &str) {
+        if let Some(endpoint_id) = &self.endpoint_id {
+            key_usage::signed(endpoint_id, subject.to_string());
+        }
+    }
+}
+
+/// Transactions to each peer sealed under a key only the two of us share,
+/// vouched for by our signing key; a peer's identity key has to match the
+/// one pinned for it. The ratchet is kept in localStorage next to the
+/// signing key, so sessions carry on across reloads.
+pub fn peer_encryption(endpoint_id: &str, keys: &EndpointKeys) -> PeerEncryption {
+    let owner = endpoint_id.to_string();
+    let key = format!("peer-ratchet:{}", endpoint_id);
+    let logger = endpoint_id.to_string();
+    let encryption = PeerEncryption::new(endpoint_id, keys.signing_key.clone())
+        .with_trust(move |peer, public_key| pin_peer_key(&owner, peer, public_key))
+        .log_decryptions(move |decryption| key_usage::decrypted(&logger, decryption));
+    let encryption = match storage()
+        .and_then(|s| s.get_item(&key).ok().flatten())
+        .and_then(|json| serde_json::from_str::<RatchetState>(&json).ok())
+    {
+        Some(state) => encryption.restore(state),
+        None => encryption,
+    };
+    encryption.persist_with(move |state| {
+        if let (Some(storage), Ok(json)) = (storage(), serde_json::to_string(state)) {
+            let _ = storage.set_item(&key, &json);
+        }
+    })
+}
+
+/// Room broadcasts sealed for the room's members alone, in rooms opened
+/// encrypted, with sender keys handed out under the same pinned identity.
+pub fn group_encryption(endpoint_id: &str, keys: &EndpointKeys) -> GroupEncryption {
+    let owner = endpoint_id.to_string();
+    let logger = endpoint_id.to_string();
+    GroupEncryption::new(endpoint_id, keys.signing_key.clone())
+        .with_trust(move |peer, public_key| pin_peer_key(&owner, peer, public_key))
+        .log_decryptions(move |decryption| key_usage::decrypted(&logger, decryption))
+}
+
+pub fn sign_transaction(tx: &Transaction, keys: &EndpointKeys) -> String {
+    keys.sign(&format!("transaction {}", tx.id), &canonical_bytes(tx))
+}
+
+// Entry points used by tx-worker.js, which loads this same wasm module
+// inside a Web Worker so hashing and serialization stay off the UI thread.
+// Workers have no localStorage, so the key travels with the request.
+
+#[wasm_bindgen]
+pub fn worker_sign(tx_json: &str, secret_key: &str) -> Result<String, JsValue> {
+    let tx: Transaction = serde_json::from_str(tx_json)
+        .map_err(|e| JsValue::from_str(&format!("Invalid transaction: {}", e)))?;
+    let keys = EndpointKeys::from_secret_hex(secret_key)
+        .ok_or_else(|| JsValue::from_str("Invalid signing key"))?;
+    Ok(sign_transaction(&tx, &keys))
+}
+
+#[wasm_bindgen]
+pub fn worker_verify(tx_json: &str) -> Result<bool, JsValue> {
+    let tx: Transaction = serde_json::from_str(tx_json)
+        .map_err(|e| JsValue::from_str(&format!("Invalid transaction: {}", e)))?;
+    Ok(verify_transaction(&tx))
+}
+
+// A rejection binds the receiver's decision to the transaction's canonical
+// payload so the sender can tell it came from the intended recipient.
+fn rejection_bytes(tx: &Transaction, rejected_by: &str) -> Vec<u8> {
+    let mut bytes = canonical_bytes(tx);
+    bytes.extend_from_slice(b"|rejected-by|");
+    bytes.extend_from_slice(rejected_by.as_bytes());
+    bytes
+}
+
+pub fn sign_rejection(tx: &Transaction, rejected_by: &str, keys: &EndpointKeys) -> String {
+    keys.sign(&format!("rejection of transaction {}", tx.id), &rejection_bytes(tx, rejected_by))
+}
+
+pub fn verify_rejection(tx: &Transaction, rejected_by: &str, public_key: &str, signature: &str) -> bool {
+    verify_signature(public_key, &rejection_bytes(tx, rejected_by), signature)
+}
+
+// An ack binds the outcome the same way, so a relay can't turn a failure
+// into a confirmation.
+fn ack_bytes(tx: &Transaction, acked_by: &str, result: AckResult) -> Vec<u8> {
+    let mut bytes = canonical_bytes(tx);
+    bytes.extend_from_slice(b"|acked-by|");
+    bytes.extend_from_slice(acked_by.as_bytes());
+    bytes.extend_from_slice(b"|result|");
+    bytes.extend_from_slice(result.as_str().as_bytes());
+    bytes
+}
+
+pub fn sign_ack(tx: &Transaction, acked_by: &str, result: AckResult, keys: &EndpointKeys) -> String {
+    keys.sign(&format!("{} ack of transaction {}", result.as_str(), tx.id), &ack_bytes(tx, acked_by, result))
+}
+
+pub fn verify_ack(tx: &Transaction, acked_by: &str, result: AckResult, public_key: &str, signature: &str) -> bool {
+    verify_signature(public_key, &ack_bytes(tx, acked_by, result), signature)
+}
+
+/// Trust on first use: remembers the first key seen for `peer` and returns
+/// whether `public_key` matches it. Kept per local endpoint in localStorage.
+pub fn pin_peer_key(endpoint_id: &str, peer: &str, public_key: &str) -> bool {
+    let key = format!("peer-keys:{}", endpoint_id);
+    let storage = storage();
+    let mut pinned: HashMap<String, String> = storage
+        .as_ref()
+        .and_then(|s| s.get_item(&key).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default();
+
+    if let Some(known) = pinned.get(peer) {
+        return known == public_key;
+    }
+    pinned.insert(peer.to_string(), public_key.to_string());
+    if let (Some(storage), Ok(json)) = (storage, serde_json::to_string(&pinned)) {
+        let _ = storage.set_item(&key, &json);
+    }
+    true
+}
+
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window().and_then(|w| w.local_storage().ok().flatten())
+}
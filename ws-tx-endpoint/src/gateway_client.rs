@@ -0,0 +1,424 @@
+use gloo_net::http::{Request, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use crate::counterparties::CounterpartyLists;
+use tx_core::{ChannelUpdate, KeySet, Receipt, SwapCommitment, SwapState};
+use crate::{Transaction, TransactionStatus};
+
+fn api_gateway_url() -> String {
+    crate::config::get("apiGateway")
+        .or_else(|| std::env::var("API_GATEWAY").ok())
+        .unwrap_or_else(|| "http://localhost:3001".to_string())
+}
+
+/// A JWT for gateways that require one, put in localStorage under
+/// `gateway-token` by whoever deploys the endpoint.
+fn gateway_token() -> Option<String> {
+    web_sys::window()
+        .and_then(|w| w.local_storage().ok().flatten())
+        .and_then(|s| s.get_item("gateway-token").ok().flatten())
+        .filter(|token| !token.is_empty())
+}
+
+/// `request` with our bearer token, if we have one.
+fn authorized(request: RequestBuilder) -> RequestBuilder {
+    match gateway_token() {
+        Some(token) => request.header("Authorization", &format!("Bearer {}", token)),
+        None => request,
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IngestResult {
+    pub risk_score: u8,
+    pub held: bool,
+}
+
+pub enum IngestError {
+    /// The gateway refused the transaction; it must not be sent.
+    Refused(String),
+    /// The gateway could not be reached or answered unexpectedly.
+    Unavailable(String),
+}
+
+/// Keyed by the transaction id, so a retry (say, from the outbox after a
+/// lost response) gets the first result instead of a duplicate.
+pub async fn submit_transaction(tx: &Transaction) -> Result<IngestResult, IngestError> {
+    let url = format!("{}/api/transactions", api_gateway_url());
+    submit(&url, tx, Some(&tx.id)).await
+}
+
+/// Records a net transfer together with the micro-payments it settles.
+pub async fn submit_net(net: &Transaction, components: &[Transaction]) -> Result<IngestResult, IngestError> {
+    let url = format!("{}/api/transactions/net", api_gateway_url());
+    let body = serde_json::json!({ "transaction": net, "components": components });
+    submit(&url, &body, None).await
+}
+
+async fn submit<T: Serialize>(url: &str, body: &T, idempotency_key: Option<&str>) -> Result<IngestResult, IngestError> {
+    let mut request = authorized(Request::post(url));
+    if let Some(key) = idempotency_key {
+        request = request.header("Idempotency-Key", key);
+    }
+    let response = request
+        .json(body)
+        .map_err(|e| IngestError::Unavailable(format!("Failed to build request: {}", e)))?
+        .send()
+        .await
+        .map_err(|e| IngestError::Unavailable(format!("Ingest request failed: {}", e)))?;
+
+    let status = response.status();
+    if (400..500).contains(&status) {
+        // Cool-downs and other refusals may explain themselves
+        let reason = response
+            .json::<serde_json::Value>()
+            .await
+            .ok()
+            .and_then(|body| {
+                // `error` before the gateway's structured errors
+                let reason = body.get("message").or_else(|| body.get("error"));
+                reason.and_then(|e| e.as_str()).map(str::to_string)
+            })
+            .unwrap_or_else(|| format!("Ingest rejected: HTTP {}", status));
+        return Err(IngestError::Refused(reason));
+    }
+    if !response.ok() {
+        return Err(IngestError::Unavailable(format!("Ingest failed: HTTP {}", status)));
+    }
+
+    response
+        .json::<IngestResult>()
+        .await
+        .map_err(|e| IngestError::Unavailable(format!("Invalid ingest response: {}", e)))
+}
+
+/// Posts a channel's final update. `Ok(false)` means the other side already
+/// settled it.
+pub async fn settle_channel(update: &ChannelUpdate) -> Result<bool, IngestError> {
+    let url = format!("{}/api/channels/settle", api_gateway_url());
+
+    let response = authorized(Request::post(&url))
+        .json(update)
+        .map_err(|e| IngestError::Unavailable(format!("Failed to build request: {}", e)))?
+        .send()
+        .await
+        .map_err(|e| IngestError::Unavailable(format!("Settlement request failed: {}", e)))?;
+
+    match response.status() {
+        409 => Ok(false),
+        status if (400..500).contains(&status) => {
+            Err(IngestError::Refused(format!("Channel settlement rejected: HTTP {}", status)))
+        }
+        _ if response.ok() => Ok(true),
+        status => Err(IngestError::Unavailable(format!("Channel settlement failed: HTTP {}", status))),
+    }
+}
+
+/// Posts our signed commitment to a swap, locking our leg.
+pub async fn hold_swap(commitment: &SwapCommitment) -> Result<SwapState, String> {
+    let url = format!("{}/api/swaps/hold", api_gateway_url());
+
+    let response = authorized(Request::post(&url))
+        .json(commitment)
+        .map_err(|e| format!("Failed to build request: {}", e))?
+        .send()
+        .await
+        .map_err(|e| format!("Swap hold failed: {}", e))?;
+
+    match response.status() {
+        410 => return Err("Swap expired".to_string()),
+        422 => return Err("Insufficient funds for swap".to_string()),
+        _ if !response.ok() => return Err(format!("Swap hold rejected: HTTP {}", response.status())),
+        _ => {}
+    }
+
+    response
+        .json::<SwapState>()
+        .await
+        .map_err(|e| format!("Invalid swap response: {}", e))
+}
+
+pub async fn fetch_swap(swap_id: &str) -> Result<SwapState, String> {
+    let url = format!("{}/api/swaps/{}", api_gateway_url(), swap_id);
+
+    let response = authorized(Request::get(&url))
+        .send()
+        .await
+        .map_err(|e| format!("Swap lookup failed: {}", e))?;
+
+    if !response.ok() {
+        return Err(format!("Swap lookup failed: HTTP {}", response.status()));
+    }
+
+    response
+        .json::<SwapState>()
+        .await
+        .map_err(|e| format!("Invalid swap response: {}", e))
+}
+
+/// The gateway's signed receipt, once the transaction has settled.
+pub async fn fetch_receipt(tx_id: &str) -> Result<Receipt, String> {
+    let url = format!("{}/api/transactions/{}/receipt", api_gateway_url(), tx_id);
+
+    let response = authorized(Request::get(&url))
+        .send()
+        .await
+        .map_err(|e| format!("Receipt request failed: {}", e))?;
+
+    match response.status() {
+        200 => response
+            .json::<Receipt>()
+            .await
+            .map_err(|e| format!("Invalid receipt response: {}", e)),
+        404 => Err("The gateway has no record of this transaction".to_string()),
+        409 => Err("No receipt yet, the transaction hasn't settled".to_string()),
+        status => Err(format!("Receipt request failed: HTTP {}", status)),
+    }
+}
+
+const KEY_SET_CACHE: &str = "gateway:keys";
+
+/// How long a cached key set is trusted before asking again; well inside
+/// the gateway's rotation overlap.
+const KEY_SET_MAX_AGE_MS: f64 = 3_600_000.0;
+
+#[derive(Serialize, Deserialize)]
+struct CachedKeySet {
+    fetched_at: f64,
+    keys: KeySet,
+}
+
+/// The gateway's published keys, cached in localStorage. Refetched once
+/// the cache is an hour old or doesn't know `expected` (a key in hex), so
+/// a rotation is picked up as soon as something signed with it shows up.
+/// Offline, the cache is used however old it is.
+pub async fn fetch_gateway_keys(expected: Option<&str>) -> Result<KeySet, String> {
+    let storage = web_sys::window().and_then(|w| w.local_storage().ok().flatten());
+    let cached = storage
+        .as_ref()
+        .and_then(|s| s.get_item(KEY_SET_CACHE).ok().flatten())
+        .and_then(|json| serde_json::from_str::<CachedKeySet>(&json).ok());
+
+    if let Some(cached) = &cached {
+        let fresh = js_sys::Date::now() - cached.fetched_at < KEY_SET_MAX_AGE_MS;
+        if fresh && expected.map_or(true, |key| cached.keys.find(key).is_some()) {
+            return Ok(cached.keys.clone());
+        }
+    }
+
+    let url = format!("{}/api/.well-known/keys", api_gateway_url());
+    let fetched = match authorized(Request::get(&url)).send().await {
+        Ok(response) if response.ok() => response
+            .json::<KeySet>()
+            .await
+            .map_err(|e| format!("Invalid key set response: {}", e)),
+        Ok(response) => Err(format!("Key set request failed: HTTP {}", response.status())),
+        Err(e) => Err(format!("Key set request failed: {}", e)),
+    };
+
+    match fetched {
+        Ok(keys) => {
+            let entry = CachedKeySet { fetched_at: js_sys::Date::now(), keys };
+            if let (Some(storage), Ok(json)) = (&storage, serde_json::to_string(&entry)) {
+                let _ = storage.set_item(KEY_SET_CACHE, &json);
+            }
+            Ok(entry.keys)
+        }
+        Err(e) => cached.map(|cached| cached.keys).ok_or(e),
+    }
+}
+
+pub async fn fetch_transaction(id: &str) -> Result<Transaction, String> {
+    let url = format!("{}/api/transactions/{}", api_gateway_url(), id);
+
+    let response = authorized(Request::get(&url))
+        .send()
+        .await
+        .map_err(|e| format!("Lookup failed: {}", e))?;
+
+    if !response.ok() {
+        return Err(format!("Lookup failed: HTTP {}", response.status()));
+    }
+
+    response
+        .json::<Transaction>()
+        .await
+        .map_err(|e| format!("Invalid transaction response: {}", e))
+}
+
+/// Reports how far a transaction got. The gateway refusing with 409 means
+/// it already knows a later status (the receiver's acknowledgement can
+/// overtake the sender's relay report), which is fine.
+pub async fn report_status(tx_id: &str, status: TransactionStatus) -> Result<(), String> {
+    let url = format!("{}/api/transactions/{}/status", api_gateway_url(), tx_id);
+
+    let response = authorized(Request::patch(&url))
+        .json(&serde_json::json!({ "status": status }))
+        .map_err(|e| format!("Failed to build request: {}", e))?
+        .send()
+        .await
+        .map_err(|e| format!("Status report failed: {}", e))?;
+
+    if response.ok() || response.status() == 409 {
+        Ok(())
+    } else {
+        Err(format!("Status report failed: HTTP {}", response.status()))
+    }
+}
+
+/// A top-up asked for; nothing is credited until an admin approves it.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TopUpRequest {
+    pub id: String,
+    pub amount: f64,
+    pub status: String,
+}
+
+pub async fn request_top_up(endpoint_id: &str, amount: f64) -> Result<TopUpRequest, String> {
+    let url = format!("{}/api/endpoints/{}/top-up-requests", api_gateway_url(), endpoint_id);
+
+    let response = authorized(Request::post(&url))
+        .json(&serde_json::json!({ "amount": amount }))
+        .map_err(|e| format!("Failed to build request: {}", e))?
+        .send()
+        .await
+        .map_err(|e| format!("Top-up request failed: {}", e))?;
+
+    if !response.ok() {
+        return Err(format!("Top-up rejected: HTTP {}", response.status()));
+    }
+
+    response
+        .json::<TopUpRequest>()
+        .await
+        .map_err(|e| format!("Invalid top-up response: {}", e))
+}
+
+pub async fn fetch_counterparties(endpoint_id: &str) -> Result<CounterpartyLists, String> {
+    let url = format!("{}/api/endpoints/{}/counterparties", api_gateway_url(), endpoint_id);
+
+    let response = authorized(Request::get(&url))
+        .send()
+        .await
+        .map_err(|e| format!("Counterparty lookup failed: {}", e))?;
+
+    if !response.ok() {
+        return Err(format!("Counterparty lookup failed: HTTP {}", response.status()));
+    }
+
+    response
+        .json::<CounterpartyLists>()
+        .await
+        .map_err(|e| format!("Invalid counterparty response: {}", e))
+}
+
+/// Adds `peer` to the endpoint's `list` ("blocked" or "allowed"), or removes
+/// it when `add` is false. Returns the updated lists.
+pub async fn update_counterparty(
+    endpoint_id: &str,
+    list: &str,
+    peer: &str,
+    add: bool,
+) -> Result<CounterpartyLists, String> {
+    let url = format!(
+        "{}/api/endpoints/{}/counterparties/{}/{}",
+        api_gateway_url(),
+        endpoint_id,
+        list,
+        peer
+    );
+
+    let request = authorized(if add { Request::put(&url) } else { Request::delete(&url) });
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Counterparty update failed: {}", e))?;
+
+    if !response.ok() {
+        return Err(format!("Counterparty update rejected: HTTP {}", response.status()));
+    }
+
+    response
+        .json::<CounterpartyLists>()
+        .await
+        .map_err(|e| format!("Invalid counterparty response: {}", e))
+}
+
+/// Chargebacks the gateway recorded for disputes involving this endpoint.
+/// Recent transactions the endpoint sent or received, newest first.
+/// Enough to replay an endpoint's whole history.
+pub const HISTORY_LIMIT: u32 = 10_000;
+
+/// Largest page the gateway serves; longer histories follow `Next-Cursor`.
+const PAGE_LIMIT: u32 = 1000;
+
+pub async fn fetch_endpoint_transactions(endpoint_id: &str, limit: u32) -> Result<Vec<Transaction>, String> {
+    let mut transactions = Vec::new();
+    let mut cursor: Option<String> = None;
+
+    while (transactions.len() as u32) < limit {
+        let page_limit = (limit - transactions.len() as u32).min(PAGE_LIMIT);
+        let mut url = format!("{}/api/transactions?endpoint={}&limit={}", api_gateway_url(), endpoint_id, page_limit);
+        if let Some(cursor) = &cursor {
+            url.push_str(&format!("&before={}", cursor));
+        }
+
+        let response = authorized(Request::get(&url))
+            .send()
+            .await
+            .map_err(|e| format!("Transaction lookup failed: {}", e))?;
+
+        if !response.ok() {
+            return Err(format!("Transaction lookup failed: HTTP {}", response.status()));
+        }
+
+        cursor = response.headers().get("next-cursor");
+        let page = response
+            .json::<Vec<Transaction>>()
+            .await
+            .map_err(|e| format!("Invalid transaction response: {}", e))?;
+        transactions.extend(page);
+        if cursor.is_none() {
+            break;
+        }
+    }
+    Ok(transactions)
+}
+
+pub async fn fetch_chargebacks(endpoint_id: &str) -> Result<Vec<Transaction>, String> {
+    let url = format!("{}/api/endpoints/{}/chargebacks", api_gateway_url(), endpoint_id);
+
+    let response = authorized(Request::get(&url))
+        .send()
+        .await
+        .map_err(|e| format!("Chargeback lookup failed: {}", e))?;
+
+    if !response.ok() {
+        return Err(format!("Chargeback lookup failed: HTTP {}", response.status()));
+    }
+
+    response
+        .json::<Vec<Transaction>>()
+        .await
+        .map_err(|e| format!("Invalid chargeback response: {}", e))
+}
+
+pub async fn fetch_last_sequence(endpoint_id: &str) -> Result<u64, String> {
+    let url = format!("{}/api/endpoints/{}/sequence", api_gateway_url(), endpoint_id);
+
+    let response = authorized(Request::get(&url))
+        .send()
+        .await
+        .map_err(|e| format!("Sequence lookup failed: {}", e))?;
+
+    if !response.ok() {
+        return Err(format!("Sequence lookup failed: HTTP {}", response.status()));
+    }
+
+    response
+        .json::<serde_json::Value>()
+        .await
+        .map_err(|e| format!("Invalid sequence response: {}", e))?
+        .get("last_sequence")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| "Invalid sequence response".to_string())
+}
@@ -0,0 +1,102 @@
+//! What the endpoint's keys were used for, kept in localStorage under
+//! `key-usage:{id}` for incident forensics: every signature made with the
+//! signing key and every sealed transaction opened, or refused, by the
+//! encryption plugins. The keys never leave the browser, so the gateway
+//! can't keep this record; it registers keys only. The newest
+//! `MAX_ENTRIES` are kept and `export` renders them for download.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tx_core::plugin::Decryption;
+
+const MAX_ENTRIES: usize = 1000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Sign,
+    Decrypt,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyUse {
+    pub at: DateTime<Utc>,
+    pub operation: Operation,
+    /// What was signed, or which plugin opened what from whom.
+    pub subject: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn storage_key(endpoint_id: &str) -> String {
+    format!("key-usage:{}", endpoint_id)
+}
+
+pub fn load(endpoint_id: &str) -> Vec<KeyUse> {
+    storage()
+        .and_then(|s| s.get_item(&storage_key(endpoint_id)).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn append(endpoint_id: &str, entry: KeyUse) {
+    let Some(storage) = storage() else {
+        return;
+    };
+    let mut entries = load(endpoint_id);
+    entries.push(entry);
+    if entries.len() > MAX_ENTRIES {
+        entries.drain(..entries.len() - MAX_ENTRIES);
+    }
+    if let Ok(json) = serde_json::to_string(&entries) {
+        let _ = storage.set_item(&storage_key(endpoint_id), &json);
+    }
+}
+
+/// `subject` says what was signed, e.g. `transaction <id>`.
+pub fn signed(endpoint_id: &str, subject: String) {
+    append(
+        endpoint_id,
+        KeyUse {
+            at: Utc::now(),
+            operation: Operation::Sign,
+            subject,
+            error: None,
+        },
+    );
+}
+
+pub fn decrypted(endpoint_id: &str, decryption: &Decryption) {
+    let opened = decryption.transaction_id.as_deref().unwrap_or("-");
+    append(
+        endpoint_id,
+        KeyUse {
+            at: Utc::now(),
+            operation: Operation::Decrypt,
+            subject: format!(
+                "{}: transaction {} from {} under key {}",
+                decryption.plugin, opened, decryption.peer, decryption.key
+            ),
+            error: decryption.error.clone(),
+        },
+    );
+}
+
+/// The whole log as a JSON report.
+pub fn export(endpoint_id: &str) -> Result<String, serde_json::Error> {
+    #[derive(Serialize)]
+    struct Report {
+        endpoint_id: String,
+        exported_at: DateTime<Utc>,
+        entries: Vec<KeyUse>,
+    }
+    serde_json::to_string_pretty(&Report {
+        endpoint_id: endpoint_id.to_string(),
+        exported_at: Utc::now(),
+        entries: load(endpoint_id),
+    })
+}
+
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window().and_then(|w| w.local_storage().ok().flatten())
+}
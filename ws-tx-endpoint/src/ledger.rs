@@ -0,0 +1,136 @@
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tx_core::{Money, NATIVE_ASSET};
+
+/// What every endpoint starts with, as on the gateway.
+pub const OPENING_BALANCE: f64 = 1000.0;
+
+/// A native-asset amount as typed: `12.50`, or `12.50 USD`.
+pub fn parse_amount(input: &str) -> Option<Money> {
+    input.trim().parse::<Money>().ok().filter(|amount| amount.currency() == NATIVE_ASSET)
+}
+
+/// Funds come from here when the ledger is opened.
+const OPENING_ACCOUNT: &str = "system:opening";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Money leaving the account.
+    Debit,
+    /// Money arriving in it.
+    Credit,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Entry {
+    /// The posting this entry belongs to; each has one debit and one credit.
+    pub key: String,
+    pub account: String,
+    pub direction: Direction,
+    pub amount: Money,
+    pub recorded_at: DateTime<Utc>,
+}
+
+impl Entry {
+    /// `balance` after this entry. Amounts that would overflow are left out
+    /// and reported, rather than wrapping.
+    fn apply_to(&self, balance: &Money) -> Money {
+        let applied = match self.direction {
+            Direction::Credit => balance.checked_add(&self.amount),
+            Direction::Debit => balance.checked_sub(&self.amount),
+        };
+        applied.unwrap_or_else(|e| {
+            web_sys::console::warn_1(&format!("Skipping ledger entry {}: {}", self.key, e).into());
+            balance.clone()
+        })
+    }
+}
+
+/// Append-only double-entry ledger. Every movement is posted once under a
+/// key (a transaction id, or a derived one such as `refund:<id>`) as a
+/// debit of one account and an equal credit of another; balances are
+/// derived from the entries, never stored.
+///
+/// Accounts are endpoint ids, `system:opening`, and `reserved:<ref>` for
+/// funds set aside for a channel or swap.
+#[derive(Clone, Debug, Default)]
+pub struct Ledger {
+    entries: Vec<Entry>,
+    posted: HashSet<String>,
+}
+
+impl Ledger {
+    pub fn opened(account: &str, amount: f64) -> Self {
+        let mut ledger = Ledger::default();
+        ledger.post_major(&format!("opening:{}", account), OPENING_ACCOUNT, account, amount);
+        ledger
+    }
+
+    /// `post` for an amount in major units, as the balance is shown. Also
+    /// false if `amount` isn't a usable amount.
+    pub fn post_major(&mut self, key: &str, from: &str, to: &str, amount: f64) -> bool {
+        match Money::from_major(amount, NATIVE_ASSET) {
+            Ok(amount) => self.post(key, from, to, &amount),
+            Err(e) => {
+                web_sys::console::warn_1(&format!("Not posting {}: {}", key, e).into());
+                false
+            }
+        }
+    }
+
+    /// Moves `amount` from `from` to `to`. Returns false, changing nothing,
+    /// if `key` has been posted before.
+    pub fn post(&mut self, key: &str, from: &str, to: &str, amount: &Money) -> bool {
+        if !self.posted.insert(key.to_string()) {
+            return false;
+        }
+        let recorded_at = Utc::now();
+        for (account, direction) in [(from, Direction::Debit), (to, Direction::Credit)] {
+            self.entries.push(Entry {
+                key: key.to_string(),
+                account: account.to_string(),
+                direction,
+                amount: amount.clone(),
+                recorded_at,
+            });
+        }
+        true
+    }
+
+    pub fn is_posted(&self, key: &str) -> bool {
+        self.posted.contains(key)
+    }
+
+    pub fn balance(&self, account: &str) -> f64 {
+        self.money_of(account).to_major()
+    }
+
+    fn money_of(&self, account: &str) -> Money {
+        self.entries
+            .iter()
+            .filter(|entry| entry.account == account)
+            .fold(Money::zero(NATIVE_ASSET), |balance, entry| entry.apply_to(&balance))
+    }
+
+    /// Reservations still holding funds, by reference.
+    pub fn open_reserves(&self) -> BTreeMap<String, Money> {
+        let mut reserves: BTreeMap<String, Money> = BTreeMap::new();
+        for entry in &self.entries {
+            if let Some(reference) = entry.account.strip_prefix("reserved:") {
+                let reserve = reserves.entry(reference.to_string()).or_insert_with(|| Money::zero(NATIVE_ASSET));
+                *reserve = entry.apply_to(reserve);
+            }
+        }
+        reserves
+            .into_iter()
+            .filter(|(_, reserved)| !reserved.is_negative() && !reserved.is_zero())
+            .collect()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+}
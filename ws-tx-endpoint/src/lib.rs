@@ -1,41 +1,65 @@
+use chrono::{DateTime, Utc};
 use dioxus::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 use wasm_bindgen::prelude::*;
 
+mod channels;
+mod config;
+mod counterparties;
+mod crypto;
+mod gateway_client;
+mod key_usage;
+mod ledger;
+mod netting;
+mod outbox;
+mod rfq;
+mod rules;
+mod sequence;
+mod swaps;
 mod tx_endpoint;
+mod tx_worker;
 mod websocket_connection;
+mod widget;
 
+use channels::OpenChannel;
+use counterparties::CounterpartyLists;
+use netting::NettingBuffer;
+use outbox::Outbox;
+use rfq::RfqBook;
+use sequence::SequenceAllocator;
+use swaps::PendingSwap;
 use tx_endpoint::TxEndpoint;
-use websocket_connection::WebSocketConnection;
-
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-pub struct Transaction {
-    pub id: String,
-    pub from: String,
-    pub to: String,
-    pub amount: f64,
-    pub timestamp: u64,
-    pub signature: String,
-    pub status: String,
-}
+use tx_worker::TxWorker;
+use websocket_connection::{ReconnectEvent, WebSocketConnection};
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct SignalingMessage {
-    pub message_type: String,
-    pub room_id: Option<String>,
-    pub peer_id: Option<String>,
-    pub target_peer: Option<String>,
-    pub transaction: Option<Transaction>,
-    pub peers: Option<Vec<String>>,
-}
+pub use tx_core::{
+    ChannelUpdate, Side, SignalingMessage, SwapStatus, Transaction, TransactionKind, TransactionStatus, NATIVE_ASSET,
+};
+use tx_core::rules::{Decision, Event, Trigger};
+use tx_core::money::MINOR_PER_MAJOR;
+use tx_core::{AckResult, Check, Money, Receipt, ReceiptReport, RoomInfo, Rule};
+
+const TOP_UP_AMOUNT: f64 = 100.0;
+
+const CHARGEBACK_POLL_MS: u32 = 30_000;
+
+/// Incoming payments scored at or above this are held for manual accept.
+/// Matches the gateway's default `RISK_HOLD_THRESHOLD`.
+const RISK_HOLD_THRESHOLD: u8 = 70;
 
 fn main() {
     console_error_panic_hook::set_once();
     dioxus_web::launch(app);
 }
 
+/// Entry point of `widget.html`, the compact UI for iframes; see `widget`.
+#[wasm_bindgen]
+pub fn widget() {
+    console_error_panic_hook::set_once();
+    dioxus_web::launch(widget::widget_app);
+}
+
 fn app(cx: Scope) -> Element {
     // Get endpoint ID from URL or default
     let endpoint_id = use_state(cx, || {
@@ -53,10 +77,39 @@ fn app(cx: Scope) -> Element {
 
     let tx_endpoint = use_state(cx, || TxEndpoint::new(&endpoint_id.get()));
     let connection = use_state(cx, || WebSocketConnection::new());
+    let tx_worker = use_state(cx, || TxWorker::new(tx_endpoint.keys.clone()));
+    let sequence = use_state(cx, SequenceAllocator::default);
+    let netting = use_state(cx, NettingBuffer::default);
+    let netting_enabled = use_state(cx, || false);
     let transactions = use_state(cx, HashMap::<String, Transaction>::new);
+    let held_transactions = use_state(cx, HashSet::<String>::new);
+    let channels = use_state(cx, HashMap::<String, OpenChannel>::new);
+    let swaps = use_state(cx, HashMap::<String, PendingSwap>::new);
+    let swap_give_asset = use_state(cx, || NATIVE_ASSET.to_string());
+    let swap_give_amount = use_state(cx, || "10".to_string());
+    let swap_ask_asset = use_state(cx, || "EUR".to_string());
+    let swap_ask_amount = use_state(cx, || "9".to_string());
+    let rfq_book = use_state(cx, RfqBook::default);
+    let rfq_side = use_state(cx, || Side::Buy);
+    let rfq_size = use_state(cx, || "10".to_string());
+    let rfq_base = use_state(cx, || "EUR".to_string());
+    let rfq_quote_asset = use_state(cx, || NATIVE_ASSET.to_string());
+    let quote_price = use_state(cx, || "1.10".to_string());
+    let counterparty_lists = use_state(cx, || CounterpartyLists::load_cached(endpoint_id.get()));
+    let counterparty_input = use_state(cx, String::new);
+    let automation_rules = use_state(cx, || rules::load_cached(endpoint_id.get()));
+    let rule_draft = use_state(cx, rules::RuleDraft::default);
+    let rules_json = use_state(cx, String::new);
+    // (from, text), newest first
+    let memos = use_state(cx, Vec::<(String, String)>::new);
     let connected_peers = use_state(cx, Vec::<String>::new);
+    let rooms = use_state(cx, Vec::<RoomInfo>::new);
+    let room_input = use_state(cx, String::new);
+    let encrypt_room = use_state(cx, || false);
     let connection_status = use_state(cx, || "Disconnected".to_string());
     let error_message = use_state(cx, || "".to_string());
+    let receipt_report = use_state(cx, || None::<(String, ReceiptReport)>);
+    let top_up_status = use_state(cx, String::new);
 
     // Auto-connect on component mount
     use_effect(cx, (), {
@@ -64,29 +117,99 @@ fn app(cx: Scope) -> Element {
         let endpoint_id = endpoint_id.get().clone();
         let connection_status = connection_status.clone();
         let connected_peers = connected_peers.clone();
+        let rooms = rooms.clone();
         let transactions = transactions.clone();
+        let held_transactions = held_transactions.clone();
+        let channels = channels.clone();
+        let swaps = swaps.clone();
+        let rfq_book = rfq_book.clone();
+        let tx_endpoint = tx_endpoint.clone();
         let error_message = error_message.clone();
+        let tx_worker = tx_worker.get().clone();
+        let counterparty_lists = counterparty_lists.clone();
+        let automation_rules = automation_rules.clone();
+        let memos = memos.clone();
+        let sequence = sequence.get().clone();
         
         move |_| {
             async move {
                 web_sys::console::log_1(&"Initializing connection...".into());
+
+                sequence.sync(&endpoint_id).await;
+
+                // Refresh the cached lists from the gateway
+                match gateway_client::fetch_counterparties(&endpoint_id).await {
+                    Ok(lists) => {
+                        lists.store_cached(&endpoint_id);
+                        counterparty_lists.set(lists);
+                    }
+                    Err(e) => web_sys::console::warn_1(&e.into()),
+                }
                 
                 let result = connection.with_mut(|conn| {
+                    // Ahead of peer encryption, so it seals broadcasts in encrypted rooms first
+                    conn.register_plugin(crypto::group_encryption(&endpoint_id, tx_worker.keys()));
+                    conn.register_plugin(crypto::peer_encryption(&endpoint_id, tx_worker.keys()));
+                    conn.on_reconnect(Box::new({
+                        let connection_status = connection_status.clone();
+                        let connected_peers = connected_peers.clone();
+                        let error_message = error_message.clone();
+
+                        move |event| match event {
+                            ReconnectEvent::Reconnecting { attempt, .. } => {
+                                // Peers stay listed so sends to them can be
+                                // queued; room-joined replaces the list
+                                connection_status.set(format!("Reconnecting (attempt {})…", attempt));
+                            }
+                            ReconnectEvent::Reconnected { .. } => connection_status.set("Connected".to_string()),
+                            ReconnectEvent::GaveUp { attempts } => {
+                                connection_status.set("Disconnected".to_string());
+                                error_message.set(format!(
+                                    "Lost connection to the signaling server, gave up after {} attempts",
+                                    attempts
+                                ));
+                            }
+                        }
+                    }));
                     conn.connect(
                         &endpoint_id,
                         Box::new({
                             let connection_status = connection_status.clone();
                             let connected_peers = connected_peers.clone();
+                            let rooms = rooms.clone();
                             let transactions = transactions.clone();
+                            let held_transactions = held_transactions.clone();
+                            let channels = channels.clone();
+                            let swaps = swaps.clone();
+                            let rfq_book = rfq_book.clone();
+                            let tx_endpoint = tx_endpoint.clone();
                             let error_message = error_message.clone();
+                            let tx_worker = tx_worker.clone();
+                            let endpoint_id = endpoint_id.clone();
+                            let counterparty_lists = counterparty_lists.clone();
+                            let automation_rules = automation_rules.clone();
+                            let memos = memos.clone();
+                            let connection = connection.clone();
                             
                             move |msg: SignalingMessage| {
                                 handle_signaling_message(
                                     msg,
+                                    &endpoint_id,
                                     &connection_status,
                                     &connected_peers,
+                                    &rooms,
                                     &transactions,
+                                    &held_transactions,
+                                    &channels,
+                                    &swaps,
+                                    &rfq_book,
+                                    &tx_endpoint,
                                     &error_message,
+                                    &tx_worker,
+                                    &counterparty_lists,
+                                    &automation_rules,
+                                    &memos,
+                                    &connection,
                                 );
                             }
                         }),
@@ -96,6 +219,170 @@ fn app(cx: Scope) -> Element {
                 if let Err(e) = result {
                     error_message.set(format!("Connection failed: {:?}", e));
                 }
+
+                // Chargebacks are decided on the gateway, pick them up periodically
+                loop {
+                    match gateway_client::fetch_chargebacks(&endpoint_id).await {
+                        Ok(chargebacks) => {
+                            for chargeback in chargebacks {
+                                if transactions.get().contains_key(&chargeback.id) {
+                                    continue;
+                                }
+                                tx_endpoint.with_mut(|ep| {
+                                    let _ = ep.process_transaction(&chargeback);
+                                });
+                                transactions.with_mut(|txs| {
+                                    txs.insert(chargeback.id.clone(), chargeback);
+                                });
+                            }
+                        }
+                        Err(e) => web_sys::console::warn_1(&e.into()),
+                    }
+                    gloo_timers::future::TimeoutFuture::new(CHARGEBACK_POLL_MS).await;
+                }
+            }
+        }
+    });
+
+    // Pay out open streams and send the peer a fresh signed update
+    use_effect(cx, (), {
+        let channels = channels.clone();
+        let connection = connection.clone();
+        let keys = tx_worker.keys().clone();
+
+        move |_| {
+            async move {
+                loop {
+                    gloo_timers::future::TimeoutFuture::new(channels::CHANNEL_TICK_MS).await;
+                    if !channels.current().values().any(|channel| channel.rate_per_sec.is_some()) {
+                        continue;
+                    }
+
+                    let mut updates = Vec::new();
+                    channels.with_mut(|channels| {
+                        for channel in channels.values_mut() {
+                            if channel.advance(&keys) {
+                                updates.push(channel.update.clone());
+                            }
+                        }
+                    });
+                    for update in updates {
+                        connection.with_mut(|conn| {
+                            if let Err(e) = conn.send_channel("channel-update", &update) {
+                                web_sys::console::error_1(&e);
+                            }
+                        });
+                    }
+                }
+            }
+        }
+    });
+
+    // Follow pending swaps on the gateway until they settle or roll back
+    use_effect(cx, (), {
+        let swaps = swaps.clone();
+        let rfq_book = rfq_book.clone();
+        let endpoint_id = endpoint_id.get().clone();
+        let tx_endpoint = tx_endpoint.clone();
+        let transactions = transactions.clone();
+
+        move |_| {
+            async move {
+                loop {
+                    gloo_timers::future::TimeoutFuture::new(swaps::SWAP_POLL_MS).await;
+                    rfq_book.with_mut(RfqBook::prune);
+                    let pending: Vec<PendingSwap> =
+                        swaps.current().values().filter(|swap| !swap.is_final()).cloned().collect();
+
+                    for swap in pending {
+                        let status = if swap.committed {
+                            match gateway_client::fetch_swap(&swap.terms.swap_id).await {
+                                Ok(state) => state.status,
+                                Err(e) => {
+                                    web_sys::console::warn_1(&e.into());
+                                    continue;
+                                }
+                            }
+                        } else if swap.terms.expires_at <= Utc::now() {
+                            // Never committed to it, nothing to undo
+                            SwapStatus::RolledBack
+                        } else {
+                            continue;
+                        };
+                        if status != swap.status {
+                            finalize_swap(&swap, status, &endpoint_id, &tx_endpoint, &transactions);
+                            swaps.with_mut(|swaps| {
+                                if let Some(swap) = swaps.get_mut(&swap.terms.swap_id) {
+                                    swap.status = status;
+                                }
+                            });
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    // Restore the outbox from the last session, then send it whenever the
+    // connection is up
+    use_effect(cx, (), {
+        let endpoint_id = endpoint_id.get().clone();
+        let sequence = sequence.get().clone();
+        let tx_endpoint = tx_endpoint.clone();
+        let transactions = transactions.clone();
+        let connection = connection.clone();
+        let error_message = error_message.clone();
+
+        move |_| {
+            async move {
+                let restored = Outbox::load(&endpoint_id).await;
+                transactions.with_mut(|txs| {
+                    for queued in restored.iter() {
+                        if let Some(seq) = queued.tx.sequence {
+                            sequence.observe(seq);
+                        }
+                        txs.entry(queued.tx.id.clone()).or_insert_with(|| queued.tx.clone());
+                    }
+                });
+                tx_endpoint.with_mut(|ep| ep.restore_outbox(restored));
+
+                loop {
+                    flush_outbox(&sequence, &tx_endpoint, &transactions, &connection, &error_message).await;
+                    gloo_timers::future::TimeoutFuture::new(outbox::OUTBOX_RETRY_MS).await;
+                }
+            }
+        }
+    });
+
+    // Settle netted payments whose window has closed
+    use_effect(cx, (), {
+        let netting = netting.get().clone();
+        let endpoint_id = endpoint_id.get().clone();
+        let sequence = sequence.get().clone();
+        let tx_worker = tx_worker.get().clone();
+        let tx_endpoint = tx_endpoint.clone();
+        let transactions = transactions.clone();
+        let connection = connection.clone();
+        let error_message = error_message.clone();
+
+        move |_| {
+            async move {
+                loop {
+                    gloo_timers::future::TimeoutFuture::new(netting::NETTING_POLL_MS).await;
+                    for peer in netting.due() {
+                        settle_net(
+                            &peer,
+                            &endpoint_id,
+                            netting.clone(),
+                            sequence.clone(),
+                            tx_worker.clone(),
+                            tx_endpoint.clone(),
+                            transactions.clone(),
+                            connection.clone(),
+                            error_message.clone(),
+                        );
+                    }
+                }
             }
         }
     });
@@ -174,6 +461,72 @@ fn app(cx: Scope) -> Element {
                             })
                         }
                     }
+
+                    div {
+                        style: "margin-top: 15px; padding-top: 10px; border-top: 1px solid #dee2e6;",
+                        p {
+                            style: "margin: 5px 0; color: #6c757d;",
+                            "Room: {connection.get().room_id()}",
+                            if connection.get().room_encrypted() { " 🔒 end-to-end encrypted" } else { "" }
+                        }
+                        div {
+                            style: "display: flex; gap: 8px;",
+                            input {
+                                r#type: "text",
+                                placeholder: "Room to create or join",
+                                value: "{room_input}",
+                                style: "padding: 6px; border: 1px solid #ced4da; border-radius: 6px; flex: 1;",
+                                oninput: move |evt| room_input.set(evt.value.clone()),
+                            }
+                            label {
+                                style: "display: flex; align-items: center; gap: 4px; color: #495057;",
+                                title: "Open a new room end-to-end encrypted",
+                                input {
+                                    r#type: "checkbox",
+                                    checked: "{encrypt_room}",
+                                    onchange: move |evt| encrypt_room.set(evt.value == "true"),
+                                }
+                                "🔒"
+                            }
+                            button {
+                                style: "background: #667eea; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                                onclick: move |_| {
+                                    connection.with_mut(|conn| conn.set_open_encrypted(*encrypt_room.get()));
+                                    switch_room(room_input.get().trim(), connection, connection_status, connected_peers, channels, swaps, rfq_book, error_message);
+                                    room_input.set(String::new());
+                                },
+                                "Join"
+                            }
+                            button {
+                                style: "background: none; border: 1px solid #ced4da; color: #495057; padding: 6px 10px; border-radius: 6px; cursor: pointer;",
+                                title: "Refresh rooms",
+                                onclick: move |_| {
+                                    if let Err(e) = connection.get().list_rooms() {
+                                        error_message.set(format!("Could not list rooms: {:?}", e));
+                                    }
+                                },
+                                "↻"
+                            }
+                        }
+                        ul {
+                            style: "margin: 10px 0; padding-left: 0; list-style: none;",
+                            rooms.iter().filter(|room| room.room_id != connection.get().room_id()).map(|room| {
+                                let room_id = room.room_id.clone();
+                                render! {
+                                    li {
+                                        key: "{room.room_id}",
+                                        style: "margin: 4px 0;",
+                                        button {
+                                            style: "background: none; border: none; color: #667eea; cursor: pointer; padding: 0;",
+                                            onclick: move |_| switch_room(&room_id, connection, connection_status, connected_peers, channels, swaps, rfq_book, error_message),
+                                            "🚪 {room.room_id} ({room.peer_count} peers)",
+                                            if room.encrypted { " 🔒" } else { "" }
+                                        }
+                                    }
+                                }
+                            })
+                        }
+                    }
                 }
                 
                 // Endpoint Info Panel
@@ -187,12 +540,127 @@ fn app(cx: Scope) -> Element {
                     }
                     p { 
                         style: "margin: 5px 0; font-size: 1.2rem; font-weight: 600; color: #1976d2;",
-                        "Balance: ${tx_endpoint.balance:.2}" 
+                        "Balance: ${tx_endpoint.balance():.2}" 
                     }
                     p { 
                         style: "margin: 5px 0; color: #1565c0;",
                         "Total Transactions: {tx_endpoint.transaction_count}" 
                     }
+                    button {
+                        style: "margin-top: 10px; background: #1976d2; color: white; border: none; padding: 8px 16px; border-radius: 6px; cursor: pointer; font-weight: 600;",
+                        onclick: move |_| {
+                            let endpoint_id = endpoint_id.get().clone();
+                            let top_up_status = top_up_status.clone();
+                            let error_message = error_message.clone();
+                            wasm_bindgen_futures::spawn_local(async move {
+                                match gateway_client::request_top_up(&endpoint_id, TOP_UP_AMOUNT).await {
+                                    // Credited by a deposit once an admin approves it
+                                    Ok(request) => top_up_status.set(format!(
+                                        "⏳ Top-up of ${:.2} {} ({})",
+                                        request.amount, request.status, request.id
+                                    )),
+                                    Err(e) => error_message.set(e),
+                                }
+                            });
+                        },
+                        "💳 Request Top-up (${TOP_UP_AMOUNT:.0})"
+                    }
+                    if !top_up_status.is_empty() {
+                        p {
+                            style: "margin: 5px 0; color: #1565c0; font-size: 0.85rem;",
+                            "{top_up_status}"
+                        }
+                    }
+                    p {
+                        style: "margin: 10px 0 5px 0; color: #1565c0; font-size: 0.85rem;",
+                        "Ledger: {tx_endpoint.ledger.entries().len()} entries"
+                    }
+                    div {
+                        style: "display: flex; gap: 8px;",
+                        button {
+                            style: "background: none; color: #1976d2; border: 1px solid #1976d2; padding: 4px 10px; border-radius: 6px; cursor: pointer; font-size: 0.8rem;",
+                            onclick: move |_| rebuild_ledger(endpoint_id.get().clone(), tx_endpoint.clone(), transactions.clone(), error_message.clone()),
+                            "🔄 Rebuild from gateway"
+                        }
+                        button {
+                            style: "background: none; color: #1976d2; border: 1px solid #1976d2; padding: 4px 10px; border-radius: 6px; cursor: pointer; font-size: 0.8rem;",
+                            onclick: move |_| {
+                                let result = serde_json::to_string_pretty(tx_endpoint.ledger.entries())
+                                    .map_err(|e| JsValue::from_str(&e.to_string()))
+                                    .and_then(|json| save_file(&format!("ledger-{}.json", endpoint_id.get()), &json));
+                                if let Err(e) = result {
+                                    error_message.set(format!("Failed to export ledger: {:?}", e));
+                                }
+                            },
+                            "⬇️ Export ledger"
+                        }
+                        button {
+                            style: "background: none; color: #1976d2; border: 1px solid #1976d2; padding: 4px 10px; border-radius: 6px; cursor: pointer; font-size: 0.8rem;",
+                            onclick: move |_| {
+                                let result = key_usage::export(endpoint_id.get())
+                                    .map_err(|e| JsValue::from_str(&e.to_string()))
+                                    .and_then(|json| save_file(&format!("key-usage-{}.json", endpoint_id.get()), &json));
+                                if let Err(e) = result {
+                                    error_message.set(format!("Failed to export key usage: {:?}", e));
+                                }
+                            },
+                            "🔑 Export key usage"
+                        }
+                    }
+                    label {
+                        style: "display: block; margin-top: 12px; padding: 12px; border: 2px dashed #90caf9; border-radius: 8px; color: #1565c0; font-size: 0.85rem; text-align: center; cursor: pointer;",
+                        "🧾 Drop a receipt here to verify it"
+                        input {
+                            r#type: "file",
+                            accept: ".json,application/json",
+                            style: "display: block; width: 100%; margin-top: 6px;",
+                            onchange: move |evt| {
+                                let Some(files) = evt.files.clone() else { return };
+                                let transactions = transactions.clone();
+                                let receipt_report = receipt_report.clone();
+                                let error_message = error_message.clone();
+                                wasm_bindgen_futures::spawn_local(async move {
+                                    for name in files.files() {
+                                        match files.read_file_to_string(&name).await {
+                                            Some(json) => verify_receipt_file(name, json, &transactions, &receipt_report).await,
+                                            None => error_message.set(format!("Could not read {}", name)),
+                                        }
+                                    }
+                                });
+                            }
+                        }
+                    }
+                    if let Some((name, report)) = receipt_report.get() {
+                        rsx! {
+                            div {
+                                style: "margin-top: 8px; font-size: 0.8rem; color: #1565c0;",
+                                p {
+                                    style: "margin: 0 0 4px 0; font-weight: 600;",
+                                    if report.is_valid() {
+                                        rsx! { "✅ {name} is valid" }
+                                    } else {
+                                        rsx! { "❌ {name} did not verify" }
+                                    }
+                                }
+                                [("Signature", &report.signature), ("Gateway key", &report.key), ("Checkpoint inclusion", &report.inclusion), ("Transaction", &report.transaction)]
+                                    .into_iter()
+                                    .map(|(label, check)| {
+                                        let color = match check {
+                                            Check::Passed => "#2e7d32",
+                                            Check::Failed(_) => "#c62828",
+                                            Check::Skipped(_) => "#757575",
+                                        };
+                                        render! {
+                                            div {
+                                                key: "{label}",
+                                                style: "color: {color};",
+                                                "{label}: {check}"
+                                            }
+                                        }
+                                    })
+                            }
+                        }
+                    }
                 }
             }
             
@@ -212,7 +680,7 @@ fn app(cx: Scope) -> Element {
                     select {
                         style: "padding: 10px; border: none; border-radius: 6px; font-size: 1rem;",
                         option { value: "", "Select Peer" }
-                        connected_peers.iter().map(|peer| render! {
+                        connected_peers.iter().filter(|peer| !counterparty_lists.is_blocked(peer)).map(|peer| render! {
                             option { 
                                 key: "{peer}",
                                 value: "{peer}",
@@ -244,34 +712,49 @@ fn app(cx: Scope) -> Element {
                                     let amount_str = input_elem.value();
                                     
                                     if !to_peer.is_empty() && !amount_str.is_empty() {
-                                        if let Ok(amount) = amount_str.parse::<f64>() {
-                                            if amount > 0.0 && amount <= tx_endpoint.balance {
+                                        if let Some(amount) = ledger::parse_amount(&amount_str) {
+                                            let major = amount.to_major();
+                                            if major > 0.0 && major <= tx_endpoint.balance() && *netting_enabled.get() && major <= netting::NETTING_MAX_AMOUNT {
+                                                queue_netted(
+                                                    tx_endpoint.create_transaction(&to_peer, amount),
+                                                    endpoint_id.get(),
+                                                    netting.get().clone(),
+                                                    sequence.get().clone(),
+                                                    tx_worker.get().clone(),
+                                                    tx_endpoint.clone(),
+                                                    transactions.clone(),
+                                                    connection.clone(),
+                                                    error_message.clone(),
+                                                );
+
+                                                select_elem.set_value("");
+                                                input_elem.set_value("");
+                                            } else if major > 0.0 && major <= tx_endpoint.balance() {
                                                 let tx = Transaction {
                                                     id: Uuid::new_v4().to_string(),
-                                                    from: endpoint_id.get().clone(),
-                                                    to: to_peer,
+                                                    from_endpoint: endpoint_id.get().clone(),
+                                                    to_endpoint: to_peer,
                                                     amount,
-                                                    timestamp: js_sys::Date::now() as u64,
-                                                    signature: format!("sig_{}", tx_endpoint.transaction_count),
-                                                    status: "pending".to_string(),
+                                                    timestamp: Utc::now(),
+                                                    signature: String::new(),
+                                                    status: TransactionStatus::Pending,
+                                                    kind: TransactionKind::Transfer,
+                                                    risk_score: None,
+                                                    parent_tx_id: None,
+                                                    sequence: Some(sequence.get().next()),
+                                                    public_key: None,
                                                 };
                                                 
-                                                // Update local endpoint state
-                                                tx_endpoint.with_mut(|ep| {
-                                                    let _ = ep.process_transaction(&tx);
-                                                });
-                                                
-                                                // Add to local transactions
-                                                transactions.with_mut(|txs| {
-                                                    txs.insert(tx.id.clone(), tx.clone());
-                                                });
-                                                
-                                                // Send via WebSocket
-                                                connection.with_mut(|conn| {
-                                                    if let Err(e) = conn.send_transaction(&tx) {
-                                                        error_message.set(format!("Failed to send transaction: {:?}", e));
-                                                    }
-                                                });
+                                                // Sign in the worker, then apply and send
+                                                send_signed_transaction(
+                                                    tx,
+                                                    sequence.get().clone(),
+                                                    tx_worker.get().clone(),
+                                                    tx_endpoint.clone(),
+                                                    transactions.clone(),
+                                                    connection.clone(),
+                                                    error_message.clone(),
+                                                );
                                                 
                                                 // Clear form
                                                 select_elem.set_value("");
@@ -290,118 +773,855 @@ fn app(cx: Scope) -> Element {
                     button {
                         style: "background: rgba(255,255,255,0.2); color: white; border: 1px solid rgba(255,255,255,0.3); padding: 10px 20px; border-radius: 6px; cursor: pointer; font-size: 1rem;",
                         onclick: move |_| {
-                            if !connected_peers.is_empty() {
-                                let random_peer = &connected_peers[0]; // Use first peer for demo
+                            // Use first selectable peer for demo
+                            if let Some(random_peer) = connected_peers.iter().find(|p| !counterparty_lists.is_blocked(p)) {
                                 let tx = Transaction {
                                     id: Uuid::new_v4().to_string(),
-                                    from: endpoint_id.get().clone(),
-                                    to: random_peer.clone(),
-                                    amount: 10.0,
-                                    timestamp: js_sys::Date::now() as u64,
-                                    signature: format!("sig_{}", tx_endpoint.transaction_count),
-                                    status: "pending".to_string(),
+                                    from_endpoint: endpoint_id.get().clone(),
+                                    to_endpoint: random_peer.clone(),
+                                    amount: Money::new(10 * MINOR_PER_MAJOR, NATIVE_ASSET),
+                                    timestamp: Utc::now(),
+                                    signature: String::new(),
+                                    status: TransactionStatus::Pending,
+                                    kind: TransactionKind::Transfer,
+                                    risk_score: None,
+                                    parent_tx_id: None,
+                                    sequence: Some(sequence.get().next()),
+                                    public_key: None,
                                 };
                                 
-                                tx_endpoint.with_mut(|ep| {
-                                    let _ = ep.process_transaction(&tx);
-                                });
-                                
-                                transactions.with_mut(|txs| {
-                                    txs.insert(tx.id.clone(), tx.clone());
-                                });
-                                
-                                connection.with_mut(|conn| {
-                                    let _ = conn.send_transaction(&tx);
-                                });
+                                send_signed_transaction(
+                                    tx,
+                                    sequence.get().clone(),
+                                    tx_worker.get().clone(),
+                                    tx_endpoint.clone(),
+                                    transactions.clone(),
+                                    connection.clone(),
+                                    error_message.clone(),
+                                );
                             }
                         },
                         "Send Test $10"
                     }
+
+                    button {
+                        style: "background: rgba(255,255,255,0.2); color: white; border: 1px solid rgba(255,255,255,0.3); padding: 10px 20px; border-radius: 6px; cursor: pointer; font-size: 1rem;",
+                        onclick: move |_| {
+                            if let Some(peer) = connected_peers.iter().find(|p| !counterparty_lists.is_blocked(p)) {
+                                open_channel(peer, endpoint_id.get(), tx_worker.get(), tx_endpoint, channels, connection, error_message);
+                            }
+                        },
+                        "Stream ${channels::STREAM_RATE_PER_SEC:.2}/s"
+                    }
+
+                    label {
+                        style: "display: flex; align-items: center; gap: 6px; font-size: 0.9rem;",
+                        input {
+                            r#type: "checkbox",
+                            checked: "{netting_enabled}",
+                            onchange: move |evt| netting_enabled.set(evt.value == "true"),
+                        }
+                        "Net payments ≤ ${netting::NETTING_MAX_AMOUNT:.2} ({netting.pending_count()} pending)"
+                    }
                 }
             }
-            
-            // Transaction Log
+
+            // Outbox
+            if !tx_endpoint.outbox.is_empty() {
+                rsx! {
+                    div {
+                        class: "outbox",
+                        style: "background: #fff8e1; border: 1px solid #ffe082; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
+
+                        h3 {
+                            style: "margin-top: 0; color: #8d6e00;",
+                            "📤 Outbox ({tx_endpoint.outbox.len()} waiting)"
+                        }
+                        ul {
+                            style: "margin: 0; padding-left: 20px; color: #5d4037;",
+                            tx_endpoint.outbox.iter().map(|queued| {
+                                let status = match (&queued.last_error, queued.attempts) {
+                                    (Some(error), attempts) => format!("retrying after {} failed attempts: {}", attempts, error),
+                                    (None, _) if queued.recorded => "recorded, waiting to broadcast".to_string(),
+                                    (None, _) => "waiting for connection".to_string(),
+                                };
+                                render! {
+                                    li {
+                                        key: "{queued.tx.id}",
+                                        style: "margin: 5px 0;",
+                                        "{queued.tx.amount} to {queued.tx.to_endpoint} — {status}"
+                                    }
+                                }
+                            })
+                        }
+                    }
+                }
+            }
+
+            // Counterparty Lists
             div {
-                class: "transaction-log",
-                style: "background: white; border: 1px solid #dee2e6; border-radius: 12px; padding: 20px;",
-                
-                h3 { 
+                class: "counterparty-lists",
+                style: "background: #f8f9fa; border: 1px solid #dee2e6; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
+
+                h3 {
                     style: "margin-top: 0; color: #495057;",
-                    "Transaction Log ({transactions.len()})" 
+                    "Counterparties"
                 }
-                
+
                 div {
-                    style: "max-height: 400px; overflow-y: auto;",
-                    
-                    if transactions.is_empty() {
-                        div {
-                            style: "text-align: center; color: #6c757d; padding: 40px;",
-                            "No transactions yet. Send one to get started!"
-                        }
-                    } else {
-                        transactions.iter().rev().take(10).map(|(id, tx)| render! {
+                    style: "display: flex; gap: 10px; align-items: center; margin-bottom: 15px;",
+                    input {
+                        r#type: "text",
+                        placeholder: "Peer ID",
+                        value: "{counterparty_input}",
+                        style: "padding: 8px; border: 1px solid #ced4da; border-radius: 6px; flex: 1;",
+                        oninput: move |evt| counterparty_input.set(evt.value.clone()),
+                    }
+                    button {
+                        style: "background: #dc3545; color: white; border: none; padding: 8px 16px; border-radius: 6px; cursor: pointer;",
+                        onclick: move |_| {
+                            update_counterparty_list(
+                                endpoint_id.get().clone(),
+                                "blocked",
+                                counterparty_input.get().trim().to_string(),
+                                true,
+                                counterparty_lists.clone(),
+                                error_message.clone(),
+                            );
+                            counterparty_input.set(String::new());
+                        },
+                        "Block"
+                    }
+                    button {
+                        style: "background: #28a745; color: white; border: none; padding: 8px 16px; border-radius: 6px; cursor: pointer;",
+                        onclick: move |_| {
+                            update_counterparty_list(
+                                endpoint_id.get().clone(),
+                                "allowed",
+                                counterparty_input.get().trim().to_string(),
+                                true,
+                                counterparty_lists.clone(),
+                                error_message.clone(),
+                            );
+                            counterparty_input.set(String::new());
+                        },
+                        "Allow"
+                    }
+                }
+
+                div {
+                    style: "display: grid; grid-template-columns: 1fr 1fr; gap: 20px;",
+                    [("blocked", "🚫 Blocked", &counterparty_lists.blocked), ("allowed", "✅ Allowed", &counterparty_lists.allowed)]
+                        .into_iter()
+                        .map(|(list, label, peers)| render! {
                             div {
-                                key: "{id}",
-                                style: format!(
-                                    "border-left: 4px solid {}; background: #f8f9fa; margin: 10px 0; padding: 15px; border-radius: 0 8px 8px 0;",
-                                    if tx.from == *endpoint_id.get() { "#dc3545" } else { "#28a745" }
-                                ),
-                                
-                                div {
-                                    style: "display: flex; justify-content: space-between; align-items: center; margin-bottom: 8px;",
-                                    strong {
-                                        style: "color: #495057;",
-                                        if tx.from == *endpoint_id.get() { "📤 Sent" } else { "📥 Received" }
-                                    }
-                                    span {
-                                        style: format!(
-                                            "background: {}; color: white; padding: 2px 8px; border-radius: 12px; font-size: 0.8rem;",
-                                            match tx.status.as_str() {
-                                                "confirmed" => "#28a745",
-                                                "pending" => "#ffc107",
-                                                "failed" => "#dc3545",
-                                                _ => "#6c757d"
-                                            }
-                                        ),
-                                        "{tx.status}"
+                                key: "{list}",
+                                strong { style: "color: #495057;", "{label} ({peers.len()})" }
+                                if list == "allowed" && peers.is_empty() {
+                                    rsx! {
+                                        p {
+                                            style: "margin: 5px 0; color: #6c757d; font-size: 0.85rem;",
+                                            "Empty — anyone not blocked may send"
+                                        }
                                     }
                                 }
-                                
-                                p { 
-                                    style: "margin: 5px 0; color: #495057;",
-                                    "Amount: ${tx.amount:.2}" 
-                                }
-                                p { 
-                                    style: "margin: 5px 0; color: #6c757d; font-size: 0.9rem;",
-                                    "{tx.from} → {tx.to}" 
-                                }
-                                p { 
-                                    style: "margin: 5px 0; color: #6c757d; font-size: 0.8rem;",
-                                    "{format_timestamp(tx.timestamp)}"
-                                }
-                                p { 
-                                    style: "margin: 5px 0; color: #6c757d; font-size: 0.8rem; font-family: monospace;",
-                                    "{tx.id[..8]}..."
+                                ul {
+                                    style: "margin: 10px 0; padding-left: 20px; color: #495057;",
+                                    peers.iter().map(|peer| render! {
+                                        li {
+                                            key: "{peer}",
+                                            style: "margin: 5px 0;",
+                                            "{peer} "
+                                            button {
+                                                style: "background: none; border: none; color: #6c757d; cursor: pointer;",
+                                                onclick: move |_| {
+                                                    update_counterparty_list(
+                                                        endpoint_id.get().clone(),
+                                                        list,
+                                                        peer.clone(),
+                                                        false,
+                                                        counterparty_lists.clone(),
+                                                        error_message.clone(),
+                                                    );
+                                                },
+                                                "×"
+                                            }
+                                        }
+                                    })
                                 }
                             }
                         })
-                    }
                 }
             }
-        }
-    }
-}
 
-fn handle_signaling_message(
-    msg: SignalingMessage,
-    connection_status: &UseState<String>,
-    connected_peers: &UseState<Vec<String>>,
-    transactions: &UseState<HashMap<String, Transaction>>,
-    error_message: &UseState<String>,
-) {
-    web_sys::console::log_1(&format!("Handling message: {:?}", msg.message_type).into());
-    
-    match msg.message_type.as_str() {
+            // Automation Rules
+            div {
+                class: "automation-rules",
+                style: "background: #f8f9fa; border: 1px solid #dee2e6; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
+
+                h3 {
+                    style: "margin-top: 0; color: #495057;",
+                    "Automation Rules"
+                }
+
+                div {
+                    style: "display: flex; gap: 8px; align-items: center; flex-wrap: wrap; margin-bottom: 15px;",
+                    input {
+                        style: "width: 120px; padding: 6px;",
+                        placeholder: "Name",
+                        value: "{rule_draft.name}",
+                        oninput: move |evt| rule_draft.with_mut(|draft| draft.name = evt.value.clone()),
+                    }
+                    select {
+                        style: "padding: 6px;",
+                        onchange: move |evt| rule_draft.with_mut(|draft| {
+                            draft.when = if evt.value == "swap-offered" { Trigger::SwapOffered } else { Trigger::Received };
+                        }),
+                        option { value: "received", "On receiving" }
+                        option { value: "swap-offered", "On a swap offer" }
+                    }
+                    input {
+                        style: "width: 70px; padding: 6px;",
+                        placeholder: "Over $",
+                        value: "{rule_draft.over}",
+                        oninput: move |evt| rule_draft.with_mut(|draft| draft.over = evt.value.clone()),
+                    }
+                    input {
+                        style: "width: 70px; padding: 6px;",
+                        placeholder: "Under $",
+                        value: "{rule_draft.under}",
+                        oninput: move |evt| rule_draft.with_mut(|draft| draft.under = evt.value.clone()),
+                    }
+                    input {
+                        style: "width: 100px; padding: 6px;",
+                        placeholder: "From peer",
+                        value: "{rule_draft.from}",
+                        oninput: move |evt| rule_draft.with_mut(|draft| draft.from = evt.value.clone()),
+                    }
+                    label {
+                        style: "display: flex; align-items: center; gap: 4px; font-size: 0.9rem;",
+                        input {
+                            r#type: "checkbox",
+                            checked: "{rule_draft.trusted_only}",
+                            onchange: move |evt| rule_draft.with_mut(|draft| draft.trusted_only = evt.value == "true"),
+                        }
+                        "Trusted only"
+                    }
+                    select {
+                        style: "padding: 6px;",
+                        onchange: move |evt| rule_draft.with_mut(|draft| draft.then = evt.value.clone()),
+                        option { value: "send-memo", "Send memo" }
+                        option { value: "accept", "Accept" }
+                        option { value: "decline", "Decline" }
+                    }
+                    if rule_draft.then == "send-memo" {
+                        rsx! {
+                            input {
+                                style: "flex: 1; min-width: 120px; padding: 6px;",
+                                placeholder: "Memo",
+                                value: "{rule_draft.memo}",
+                                oninput: move |evt| rule_draft.with_mut(|draft| draft.memo = evt.value.clone()),
+                            }
+                        }
+                    }
+                    button {
+                        style: "background: #007bff; color: white; border: none; padding: 6px 14px; border-radius: 6px; cursor: pointer;",
+                        onclick: move |_| match rule_draft.get().to_rule() {
+                            Ok(rule) => {
+                                let mut updated = automation_rules.get().clone();
+                                updated.push(rule);
+                                save_rules(endpoint_id.get(), updated, automation_rules);
+                                rule_draft.set(rules::RuleDraft { when: rule_draft.when, then: rule_draft.then.clone(), ..Default::default() });
+                            }
+                            Err(e) => error_message.set(e),
+                        },
+                        "Add rule"
+                    }
+                }
+
+                if automation_rules.is_empty() {
+                    rsx! {
+                        p {
+                            style: "margin: 5px 0; color: #6c757d; font-size: 0.85rem;",
+                            "No rules — everything waits for you"
+                        }
+                    }
+                }
+                ul {
+                    style: "margin: 10px 0; padding-left: 0; list-style: none; color: #495057;",
+                    automation_rules.iter().enumerate().map(|(i, rule)| render! {
+                        li {
+                            key: "{i}-{rule.name}",
+                            style: "margin: 5px 0; display: flex; align-items: center; gap: 8px;",
+                            input {
+                                r#type: "checkbox",
+                                checked: "{rule.enabled}",
+                                onchange: move |evt| {
+                                    let mut updated = automation_rules.get().clone();
+                                    updated[i].enabled = evt.value == "true";
+                                    save_rules(endpoint_id.get(), updated, automation_rules);
+                                },
+                            }
+                            strong { "{rule.name}" }
+                            span { style: "font-size: 0.9rem;", "{rules::describe(rule)}" }
+                            button {
+                                style: "background: none; border: none; color: #6c757d; cursor: pointer;",
+                                onclick: move |_| {
+                                    let mut updated = automation_rules.get().clone();
+                                    updated.remove(i);
+                                    save_rules(endpoint_id.get(), updated, automation_rules);
+                                },
+                                "×"
+                            }
+                        }
+                    })
+                }
+
+                details {
+                    summary {
+                        style: "cursor: pointer; color: #6c757d; font-size: 0.85rem;",
+                        onclick: move |_| rules_json.set(serde_json::to_string_pretty(automation_rules.get()).unwrap_or_default()),
+                        "Edit as JSON"
+                    }
+                    textarea {
+                        style: "display: block; width: 100%; height: 140px; margin: 8px 0; font-family: monospace; font-size: 0.8rem;",
+                        value: "{rules_json}",
+                        oninput: move |evt| rules_json.set(evt.value.clone()),
+                    }
+                    button {
+                        style: "background: #6c757d; color: white; border: none; padding: 6px 14px; border-radius: 6px; cursor: pointer;",
+                        onclick: move |_| match rules::parse(rules_json.get()) {
+                            Ok(parsed) => save_rules(endpoint_id.get(), parsed, automation_rules),
+                            Err(e) => error_message.set(e),
+                        },
+                        "Apply"
+                    }
+                }
+
+                if !memos.is_empty() {
+                    rsx! {
+                        strong { style: "display: block; margin-top: 15px; color: #495057;", "📝 Memos" }
+                        ul {
+                            style: "margin: 10px 0; padding-left: 20px; color: #495057;",
+                            memos.iter().enumerate().map(|(i, (from, text))| render! {
+                                li {
+                                    key: "{i}",
+                                    style: "margin: 5px 0;",
+                                    "{from}: {text}"
+                                }
+                            })
+                        }
+                    }
+                }
+            }
+            
+            // Payment Channels
+            if !channels.is_empty() {
+                div {
+                    class: "payment-channels",
+                    style: "background: #f8f9fa; border: 1px solid #dee2e6; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
+
+                    h3 {
+                        style: "margin-top: 0; color: #495057;",
+                        "Payment Channels ({channels.len()})"
+                    }
+
+                    channels.iter().map(|(id, channel)| {
+                        let update = &channel.update;
+                        let (direction, peer) = if channel.rate_per_sec.is_some() {
+                            ("🌊 Streaming to", update.payee.clone())
+                        } else {
+                            ("🌊 Receiving from", update.payer.clone())
+                        };
+                        let channel_id = id.clone();
+                        render! {
+                            div {
+                                key: "{id}",
+                                style: "display: flex; justify-content: space-between; align-items: center; margin: 8px 0;",
+                                span { "{direction} {peer}: ${update.paid:.2} of ${update.reserved:.2}" }
+                                button {
+                                    style: "background: #6c757d; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                                    onclick: move |_| close_channel(
+                                        channel_id.clone(),
+                                        endpoint_id.get().clone(),
+                                        channels.clone(),
+                                        tx_endpoint.clone(),
+                                        transactions.clone(),
+                                        connection.clone(),
+                                        error_message.clone(),
+                                    ),
+                                    "Close"
+                                }
+                            }
+                        }
+                    })
+                }
+            }
+
+            // Atomic Swaps
+            div {
+                class: "swaps",
+                style: "background: #f8f9fa; border: 1px solid #dee2e6; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
+
+                h3 {
+                    style: "margin-top: 0; color: #495057;",
+                    "Swaps"
+                }
+
+                div {
+                    style: "display: flex; gap: 8px; align-items: center; flex-wrap: wrap;",
+                    "Give"
+                    input {
+                        style: "width: 80px; padding: 6px;",
+                        value: "{swap_give_amount}",
+                        oninput: move |evt| swap_give_amount.set(evt.value.clone()),
+                    }
+                    input {
+                        style: "width: 60px; padding: 6px;",
+                        value: "{swap_give_asset}",
+                        oninput: move |evt| swap_give_asset.set(evt.value.to_uppercase()),
+                    }
+                    "for"
+                    input {
+                        style: "width: 80px; padding: 6px;",
+                        value: "{swap_ask_amount}",
+                        oninput: move |evt| swap_ask_amount.set(evt.value.clone()),
+                    }
+                    input {
+                        style: "width: 60px; padding: 6px;",
+                        value: "{swap_ask_asset}",
+                        oninput: move |evt| swap_ask_asset.set(evt.value.to_uppercase()),
+                    }
+                    button {
+                        style: "background: #17a2b8; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                        onclick: move |_| {
+                            let give = format!("{} {}", swap_give_amount.trim(), swap_give_asset.get()).parse::<Money>();
+                            let ask = format!("{} {}", swap_ask_amount.trim(), swap_ask_asset.get()).parse::<Money>();
+                            let (Ok(give_amount), Ok(ask_amount)) = (give, ask) else {
+                                error_message.set("Invalid swap amounts".to_string());
+                                return;
+                            };
+                            if let Some(peer) = connected_peers.iter().find(|p| !counterparty_lists.is_blocked(p)) {
+                                let terms = swaps::propose(
+                                    endpoint_id.get(),
+                                    peer,
+                                    give_amount,
+                                    ask_amount,
+                                );
+                                commit_swap(
+                                    PendingSwap { terms, status: SwapStatus::Prepared, committed: false },
+                                    "swap-offer",
+                                    endpoint_id.get().clone(),
+                                    tx_worker.get().clone(),
+                                    swaps.clone(),
+                                    tx_endpoint.clone(),
+                                    connection.clone(),
+                                    error_message.clone(),
+                                );
+                            }
+                        },
+                        "Offer Swap"
+                    }
+                }
+
+                swaps.iter().map(|(id, swap)| {
+                    let terms = &swap.terms;
+                    let incoming = terms.taker() == endpoint_id.get().as_str();
+                    let peer = if incoming { terms.maker() } else { terms.taker() };
+                    let awaiting_us = incoming && !swap.committed && !swap.is_final();
+                    let accept = swap.clone();
+                    let decline = swap.clone();
+                    render! {
+                        div {
+                            key: "{id}",
+                            style: "display: flex; justify-content: space-between; align-items: center; margin: 8px 0;",
+                            span {
+                                "🔁 {terms.offer.amount} ⇄ {terms.ask.amount} with {peer} ({swap.status})"
+                            }
+                            if awaiting_us {
+                                rsx! {
+                                    div {
+                                        style: "display: flex; gap: 6px;",
+                                        button {
+                                            style: "background: #28a745; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                                            onclick: move |_| commit_swap(
+                                                accept.clone(),
+                                                "swap-accept",
+                                                endpoint_id.get().clone(),
+                                                tx_worker.get().clone(),
+                                                swaps.clone(),
+                                                tx_endpoint.clone(),
+                                                connection.clone(),
+                                                error_message.clone(),
+                                            ),
+                                            "Accept"
+                                        }
+                                        button {
+                                            style: "background: #6c757d; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                                            onclick: move |_| decline_swap(
+                                                &decline,
+                                                endpoint_id.get(),
+                                                tx_worker.get(),
+                                                swaps,
+                                                connection,
+                                            ),
+                                            "Decline"
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                })
+            }
+
+            // OTC Quotes
+            div {
+                class: "otc-quotes",
+                style: "background: #f8f9fa; border: 1px solid #dee2e6; padding: 20px; border-radius: 12px; margin-bottom: 20px;",
+
+                h3 {
+                    style: "margin-top: 0; color: #495057;",
+                    "OTC Quotes"
+                }
+
+                div {
+                    style: "display: flex; gap: 8px; align-items: center; flex-wrap: wrap;",
+                    select {
+                        style: "padding: 6px;",
+                        onchange: move |evt| rfq_side.set(if evt.value == "sell" { Side::Sell } else { Side::Buy }),
+                        option { value: "buy", "Buy" }
+                        option { value: "sell", "Sell" }
+                    }
+                    input {
+                        style: "width: 80px; padding: 6px;",
+                        value: "{rfq_size}",
+                        oninput: move |evt| rfq_size.set(evt.value.clone()),
+                    }
+                    input {
+                        style: "width: 60px; padding: 6px;",
+                        value: "{rfq_base}",
+                        oninput: move |evt| rfq_base.set(evt.value.to_uppercase()),
+                    }
+                    "for"
+                    input {
+                        style: "width: 60px; padding: 6px;",
+                        value: "{rfq_quote_asset}",
+                        oninput: move |evt| rfq_quote_asset.set(evt.value.to_uppercase()),
+                    }
+                    button {
+                        style: "background: #17a2b8; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                        onclick: move |_| {
+                            let Ok(size) = rfq_size.parse::<f64>() else {
+                                error_message.set("Invalid quote request size".to_string());
+                                return;
+                            };
+                            if size <= 0.0 || rfq_base.get() == rfq_quote_asset.get() {
+                                error_message.set("Invalid quote request".to_string());
+                                return;
+                            }
+                            let request = rfq::request(endpoint_id.get(), *rfq_side.get(), rfq_base.get(), rfq_quote_asset.get(), size);
+                            connection.with_mut(|conn| {
+                                if let Err(e) = conn.send_rfq(&request) {
+                                    error_message.set(format!("Failed to request quotes: {:?}", e));
+                                }
+                            });
+                            rfq_book.with_mut(|book| {
+                                book.requested.insert(request.rfq_id.clone(), request);
+                            });
+                        },
+                        "Request Quotes"
+                    }
+                }
+
+                // Quotes drawn by our requests
+                rfq_book.received.values().map(|quote| {
+                    let request = &quote.request;
+                    let side = match request.side { Side::Buy => "buy", Side::Sell => "sell" };
+                    let quote = quote.clone();
+                    let quote_id = quote.quote_id.clone();
+                    render! {
+                        div {
+                            key: "{quote_id}",
+                            style: "display: flex; justify-content: space-between; align-items: center; margin: 8px 0;",
+                            span {
+                                "💬 {quote.quoter}: {side} {request.size:.2} {request.base_asset} at {quote.price:.4} {request.quote_asset}"
+                            }
+                            button {
+                                style: "background: #28a745; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                                onclick: move |_| {
+                                    let terms = match quote.swap_terms() {
+                                        Ok(terms) => terms,
+                                        Err(e) => {
+                                            error_message.set(format!("Invalid quote: {}", e));
+                                            return;
+                                        }
+                                    };
+                                    let rfq_id = quote.request.rfq_id.clone();
+                                    rfq_book.with_mut(|book| {
+                                        book.requested.remove(&rfq_id);
+                                        book.received.retain(|_, q| q.request.rfq_id != rfq_id);
+                                    });
+                                    commit_swap(
+                                        PendingSwap { terms, status: SwapStatus::Prepared, committed: false },
+                                        "swap-offer",
+                                        endpoint_id.get().clone(),
+                                        tx_worker.get().clone(),
+                                        swaps.clone(),
+                                        tx_endpoint.clone(),
+                                        connection.clone(),
+                                        error_message.clone(),
+                                    );
+                                },
+                                "Accept"
+                            }
+                        }
+                    }
+                })
+
+                // Other peers' requests we can quote on
+                if !rfq_book.incoming.is_empty() {
+                    rsx! {
+                        div {
+                            style: "display: flex; gap: 8px; align-items: center; margin-top: 10px;",
+                            "Quote price"
+                            input {
+                                style: "width: 80px; padding: 6px;",
+                                value: "{quote_price}",
+                                oninput: move |evt| quote_price.set(evt.value.clone()),
+                            }
+                        }
+                    }
+                }
+                rfq_book.incoming.values().map(|request| {
+                    let side = match request.side { Side::Buy => "buy", Side::Sell => "sell" };
+                    let request = request.clone();
+                    let rfq_id = request.rfq_id.clone();
+                    render! {
+                        div {
+                            key: "{rfq_id}",
+                            style: "display: flex; justify-content: space-between; align-items: center; margin: 8px 0;",
+                            span {
+                                "📣 {request.requester} wants to {side} {request.size:.2} {request.base_asset} for {request.quote_asset}"
+                            }
+                            button {
+                                style: "background: #17a2b8; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer;",
+                                onclick: move |_| {
+                                    let Some(price) = quote_price.parse::<f64>().ok().filter(|p| *p > 0.0) else {
+                                        error_message.set("Invalid quote price".to_string());
+                                        return;
+                                    };
+                                    let quote = rfq::quote(&request, endpoint_id.get(), price, tx_worker.keys());
+                                    connection.with_mut(|conn| {
+                                        if let Err(e) = conn.send_quote(&quote) {
+                                            error_message.set(format!("Failed to send quote: {:?}", e));
+                                        }
+                                    });
+                                    rfq_book.with_mut(|book| {
+                                        book.incoming.remove(&request.rfq_id);
+                                        book.issued.insert(quote.quote_id.clone(), quote);
+                                    });
+                                },
+                                "Quote"
+                            }
+                        }
+                    }
+                })
+            }
+
+            // Transaction Log
+            div {
+                class: "transaction-log",
+                style: "background: white; border: 1px solid #dee2e6; border-radius: 12px; padding: 20px;",
+                
+                h3 { 
+                    style: "margin-top: 0; color: #495057;",
+                    "Transaction Log ({transactions.len()})" 
+                }
+                
+                div {
+                    style: "max-height: 400px; overflow-y: auto;",
+                    
+                    if transactions.is_empty() {
+                        div {
+                            style: "text-align: center; color: #6c757d; padding: 40px;",
+                            "No transactions yet. Send one to get started!"
+                        }
+                    } else {
+                        transactions.iter().rev().take(10).map(|(id, tx)| render! {
+                            div {
+                                key: "{id}",
+                                style: format!(
+                                    "border-left: 4px solid {}; background: #f8f9fa; margin: 10px 0; padding: 15px; border-radius: 0 8px 8px 0;",
+                                    if tx.kind == TransactionKind::Deposit { "#1976d2" }
+                                    else if tx.kind == TransactionKind::Chargeback { "#6f42c1" }
+                                    else if tx.kind == TransactionKind::Netted { "#adb5bd" }
+                                    else if tx.from_endpoint == *endpoint_id.get() { "#dc3545" } else { "#28a745" }
+                                ),
+                                
+                                div {
+                                    style: "display: flex; justify-content: space-between; align-items: center; margin-bottom: 8px;",
+                                    strong {
+                                        style: "color: #495057;",
+                                        if tx.kind == TransactionKind::Deposit { "💳 Deposit" }
+                                        else if tx.kind == TransactionKind::Chargeback { "↩️ Chargeback" }
+                                        else if tx.kind == TransactionKind::Netted { "🧮 Netted" }
+                                        else if tx.from_endpoint == *endpoint_id.get() { "📤 Sent" } else { "📥 Received" }
+                                    }
+                                    span {
+                                        style: format!(
+                                            "background: {}; color: white; padding: 2px 8px; border-radius: 12px; font-size: 0.8rem;",
+                                            match tx.status {
+                                                TransactionStatus::Confirmed => "#28a745",
+                                                TransactionStatus::Pending => "#ffc107",
+                                                TransactionStatus::Relayed | TransactionStatus::Acknowledged => "#17a2b8",
+                                                TransactionStatus::Failed => "#dc3545",
+                                                TransactionStatus::Expired => "#6c757d",
+                                                TransactionStatus::Held => "#fd7e14",
+                                            }
+                                        ),
+                                        "{tx.status}"
+                                    }
+                                }
+                                
+                                if tx.kind == TransactionKind::Transfer && tx.from_endpoint == *endpoint_id.get() {
+                                    rsx! {
+                                        p {
+                                            style: "margin: 5px 0; color: #6c757d; font-size: 0.85rem;",
+                                            "{delivery_state(tx)}"
+                                        }
+                                    }
+                                }
+
+                                if let Some(parent) = &tx.parent_tx_id {
+                                    rsx! {
+                                        p {
+                                            style: "margin: 5px 0; color: #6f42c1; font-size: 0.85rem;",
+                                            "Reverses {parent.get(..8).unwrap_or(parent)}..."
+                                        }
+                                    }
+                                }
+
+                                if let Some(chargeback) = transactions.values().find(|t| t.parent_tx_id.as_deref() == Some(id.as_str())) {
+                                    rsx! {
+                                        p {
+                                            style: "margin: 5px 0; color: #6f42c1; font-size: 0.85rem;",
+                                            "↩️ Charged back by {chargeback.id[..8]}..."
+                                        }
+                                    }
+                                }
+                                
+                                if let Some(score) = tx.risk_score {
+                                    rsx! {
+                                        span {
+                                            style: format!(
+                                                "display: inline-block; margin: 5px 0; background: {}; color: white; padding: 2px 8px; border-radius: 12px; font-size: 0.75rem;",
+                                                if score >= RISK_HOLD_THRESHOLD { "#dc3545" } else if score >= 40 { "#fd7e14" } else { "#6c757d" }
+                                            ),
+                                            "Risk {score}"
+                                        }
+                                    }
+                                }
+                                
+                                button {
+                                    style: "display: block; margin: 5px 0; background: none; border: 1px solid #ced4da; color: #495057; padding: 2px 10px; border-radius: 4px; cursor: pointer; font-size: 0.8rem;",
+                                    onclick: move |_| download_receipt(tx.clone(), error_message.clone()),
+                                    "🧾 Download receipt"
+                                }
+
+                                if held_transactions.contains(id) {
+                                    rsx! {
+                                        div {
+                                            style: "display: flex; gap: 10px; align-items: center; margin: 8px 0; padding: 8px; background: #fff3cd; border-radius: 6px;",
+                                            span {
+                                                style: "color: #856404; font-size: 0.9rem;",
+                                                "⏸ Held for review"
+                                            }
+                                            button {
+                                                style: "background: #28a745; color: white; border: none; padding: 4px 12px; border-radius: 4px; cursor: pointer;",
+                                                onclick: move |_| {
+                                                    let mut applied = Ok(());
+                                                    tx_endpoint.with_mut(|ep| applied = ep.process_transaction(tx));
+                                                    held_transactions.with_mut(|held| {
+                                                        held.remove(&tx.id);
+                                                    });
+                                                    acknowledge(tx, &applied, endpoint_id.get(), tx_worker.get(), connection);
+                                                    if applied.is_ok() {
+                                                        run_rules(&received(tx), automation_rules, counterparty_lists, connection);
+                                                    }
+                                                    match applied {
+                                                        Ok(()) => advance_status(&tx.id, TransactionStatus::Acknowledged, true, transactions),
+                                                        Err(_) => advance_status(&tx.id, TransactionStatus::Failed, false, transactions),
+                                                    }
+                                                },
+                                                "Accept"
+                                            }
+                                            button {
+                                                style: "background: #dc3545; color: white; border: none; padding: 4px 12px; border-radius: 4px; cursor: pointer;",
+                                                onclick: move |_| {
+                                                    held_transactions.with_mut(|held| {
+                                                        held.remove(&tx.id);
+                                                    });
+                                                    acknowledge(tx, &Err("Declined after review".to_string()), endpoint_id.get(), tx_worker.get(), connection);
+                                                    transactions.with_mut(|txs| {
+                                                        if let Some(declined) = txs.get_mut(&tx.id) {
+                                                            declined.status = TransactionStatus::Failed;
+                                                        }
+                                                    });
+                                                },
+                                                "Decline"
+                                            }
+                                        }
+                                    }
+                                }
+                                
+                                p { 
+                                    style: "margin: 5px 0; color: #495057;",
+                                    "Amount: {tx.amount}" 
+                                }
+                                p { 
+                                    style: "margin: 5px 0; color: #6c757d; font-size: 0.9rem;",
+                                    "{tx.from_endpoint} → {tx.to_endpoint}" 
+                                }
+                                p { 
+                                    style: "margin: 5px 0; color: #6c757d; font-size: 0.8rem;",
+                                    "{format_timestamp(&tx.timestamp)}"
+                                }
+                                p { 
+                                    style: "margin: 5px 0; color: #6c757d; font-size: 0.8rem; font-family: monospace;",
+                                    "{tx.id[..8]}..."
+                                }
+                            }
+                        })
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn handle_signaling_message(
+    msg: SignalingMessage,
+    endpoint_id: &str,
+    connection_status: &UseState<String>,
+    connected_peers: &UseState<Vec<String>>,
+    rooms: &UseState<Vec<RoomInfo>>,
+    transactions: &UseState<HashMap<String, Transaction>>,
+    held_transactions: &UseState<HashSet<String>>,
+    channels: &UseState<HashMap<String, OpenChannel>>,
+    swaps: &UseState<HashMap<String, PendingSwap>>,
+    rfq_book: &UseState<RfqBook>,
+    tx_endpoint: &UseState<TxEndpoint>,
+    error_message: &UseState<String>,
+    tx_worker: &TxWorker,
+    counterparty_lists: &UseState<CounterpartyLists>,
+    automation_rules: &UseState<Vec<Rule>>,
+    memos: &UseState<Vec<(String, String)>>,
+    connection: &UseState<WebSocketConnection>,
+) {
+    web_sys::console::log_1(&format!("Handling message: {:?}", msg.message_type).into());
+    
+    match msg.message_type.as_str() {
         "welcome" => {
             connection_status.set("Connected".to_string());
         },
@@ -410,6 +1630,14 @@ fn handle_signaling_message(
             if let Some(peers) = msg.peers {
                 connected_peers.set(peers);
             }
+            if let Err(e) = connection.get().list_rooms() {
+                web_sys::console::error_1(&e);
+            }
+        },
+        "rooms" => {
+            let mut listed = msg.rooms.unwrap_or_default();
+            listed.sort_by(|a, b| b.peer_count.cmp(&a.peer_count).then_with(|| a.room_id.cmp(&b.room_id)));
+            rooms.set(listed);
         },
         "peer-joined" => {
             if let Some(peer_id) = msg.peer_id {
@@ -429,22 +1657,1143 @@ fn handle_signaling_message(
         },
         "transaction-broadcast" => {
             if let Some(tx) = msg.transaction {
-                transactions.with_mut(|txs| {
-                    txs.insert(tx.id.clone(), tx);
-                });
-            }
-        },
-        "error" => {
-            error_message.set("Connection error occurred".to_string());
-        },
-        _ => {
-            web_sys::console::log_1(&format!("Unknown message type: {}", msg.message_type).into());
-        }
+                // Already seen, e.g. relayed to us over another path too
+                if tx_endpoint.has_applied(&tx.id) || transactions.get().contains_key(&tx.id) {
+                    return;
+                }
+                let transactions = transactions.clone();
+                let held_transactions = held_transactions.clone();
+                let tx_endpoint = tx_endpoint.clone();
+                let tx_worker = tx_worker.clone();
+                let endpoint_id = endpoint_id.to_string();
+                let counterparty_lists = counterparty_lists.clone();
+                let automation_rules = automation_rules.clone();
+                let connection = connection.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    // A valid signature only counts under the key first seen for the sender
+                    let verified = tx_worker.verify(&tx).await.map(|valid| {
+                        valid && crypto::pin_peer_key(&endpoint_id, &tx.from_endpoint, tx.public_key.as_deref().unwrap_or_default())
+                    });
+                    match verified {
+                        Ok(true) if tx.to_endpoint == endpoint_id
+                            && tx.from_endpoint != endpoint_id
+                            && !counterparty_lists.get().accepts(&tx.from_endpoint) =>
+                        {
+                            let signature = crypto::sign_rejection(&tx, &endpoint_id, tx_worker.keys());
+                            let tx = Transaction { status: TransactionStatus::Failed, ..tx };
+                            connection.with_mut(|conn| {
+                                if let Err(e) = conn.send_rejection(&tx, signature, tx_worker.keys().public_key_hex()) {
+                                    web_sys::console::error_1(&e);
+                                }
+                            });
+                            transactions.with_mut(|txs| {
+                                txs.insert(tx.id.clone(), tx);
+                            });
+                        },
+                        Ok(true) if tx.to_endpoint == endpoint_id && tx.from_endpoint != endpoint_id => {
+                            // Prefer the gateway's score over whatever the sender attached
+                            let risk_score = gateway_client::fetch_transaction(&tx.id)
+                                .await
+                                .ok()
+                                .and_then(|stored| stored.risk_score)
+                                .or(tx.risk_score);
+                            let tx = Transaction { risk_score, ..tx };
+
+                            if risk_score.unwrap_or(0) >= RISK_HOLD_THRESHOLD {
+                                held_transactions.with_mut(|held| {
+                                    held.insert(tx.id.clone());
+                                });
+                                transactions.with_mut(|txs| {
+                                    txs.insert(tx.id.clone(), tx);
+                                });
+                            } else {
+                                let mut applied = Ok(());
+                                tx_endpoint.with_mut(|ep| applied = ep.process_transaction(&tx));
+                                acknowledge(&tx, &applied, &endpoint_id, &tx_worker, &connection);
+                                if applied.is_ok() {
+                                    run_rules(&received(&tx), &automation_rules, &counterparty_lists, &connection);
+                                }
+                                let tx_id = tx.id.clone();
+                                transactions.with_mut(|txs| {
+                                    txs.insert(tx.id.clone(), tx);
+                                });
+                                match applied {
+                                    Ok(()) => advance_status(&tx_id, TransactionStatus::Acknowledged, true, &transactions),
+                                    Err(_) => advance_status(&tx_id, TransactionStatus::Failed, false, &transactions),
+                                }
+                            }
+                        },
+                        Ok(true) => transactions.with_mut(|txs| {
+                            txs.insert(tx.id.clone(), tx);
+                        }),
+                        Ok(false) => web_sys::console::warn_1(
+                            &format!("Dropping transaction {} with invalid signature", tx.id).into()
+                        ),
+                        Err(e) => web_sys::console::error_1(&e),
+                    }
+                });
+            }
+        },
+        "transaction-rejected" => {
+            if let (Some(tx), Some(signature), Some(public_key)) = (msg.transaction, msg.signature, msg.public_key) {
+                let is_ours = tx.from_endpoint == endpoint_id
+                    && crypto::verify_rejection(&tx, &tx.to_endpoint, &public_key, &signature)
+                    && crypto::pin_peer_key(endpoint_id, &tx.to_endpoint, &public_key)
+                    && transactions.get().get(&tx.id).map_or(false, |t| t.status != TransactionStatus::Failed);
+                if is_ours {
+                    tx_endpoint.with_mut(|ep| ep.refund_transaction(&tx));
+                    transactions.with_mut(|txs| {
+                        if let Some(rejected) = txs.get_mut(&tx.id) {
+                            rejected.status = TransactionStatus::Failed;
+                        }
+                    });
+                    error_message.set(format!("{} rejected transaction {}", tx.to_endpoint, &tx.id[..8]));
+                } else {
+                    web_sys::console::warn_1(
+                        &format!("Ignoring unverifiable rejection for {}", tx.id).into()
+                    );
+                }
+            }
+        },
+        "transaction-ack" => {
+            if let (Some(tx), Some(result), Some(signature), Some(public_key)) = (msg.transaction, msg.result, msg.signature, msg.public_key) {
+                let is_ours = tx.from_endpoint == endpoint_id
+                    && crypto::verify_ack(&tx, &tx.to_endpoint, result, &public_key, &signature)
+                    && crypto::pin_peer_key(endpoint_id, &tx.to_endpoint, &public_key)
+                    && transactions.get().get(&tx.id).is_some_and(|t| !t.status.is_final());
+                if !is_ours {
+                    web_sys::console::warn_1(
+                        &format!("Ignoring unverifiable ack for {}", tx.id).into()
+                    );
+                    return;
+                }
+                let report = !connection.get().room_batches();
+                match result {
+                    AckResult::Applied => advance_status(&tx.id, TransactionStatus::Confirmed, report, transactions),
+                    AckResult::Failed => {
+                        tx_endpoint.with_mut(|ep| ep.refund_transaction(&tx));
+                        advance_status(&tx.id, TransactionStatus::Failed, report, transactions);
+                        error_message.set(format!(
+                            "{} could not apply transaction {}: {}",
+                            tx.to_endpoint,
+                            &tx.id[..8],
+                            msg.reason.as_deref().unwrap_or("no reason given")
+                        ));
+                    }
+                }
+            }
+        },
+        "channel-open" => {
+            if let Some(update) = msg.channel {
+                let acceptable = update.payee == endpoint_id
+                    && update.paid == 0.0
+                    && update.verify()
+                    && counterparty_lists.get().accepts(&update.payer)
+                    && crypto::pin_peer_key(endpoint_id, &update.payer, &update.public_key);
+                if acceptable {
+                    channels.with_mut(|channels| {
+                        channels.insert(update.channel_id.clone(), OpenChannel { update, rate_per_sec: None });
+                    });
+                } else {
+                    web_sys::console::warn_1(&format!("Ignoring channel {} from {}", update.channel_id, update.payer).into());
+                }
+            }
+        },
+        "channel-update" => {
+            if let Some(update) = msg.channel {
+                channels.with_mut(|channels| {
+                    match channels.get_mut(&update.channel_id) {
+                        Some(channel) if channel.rate_per_sec.is_none() && channel.accepts(&update) => {
+                            channel.update = update;
+                        }
+                        _ => web_sys::console::warn_1(
+                            &format!("Dropping invalid update for channel {}", update.channel_id).into()
+                        ),
+                    }
+                });
+            }
+        },
+        "channel-close" => {
+            let closed = msg.channel.and_then(|update| {
+                let mut closed = None;
+                channels.with_mut(|channels| closed = channels.remove(&update.channel_id));
+                closed
+            });
+            if let Some(channel) = closed {
+                let endpoint_id = endpoint_id.to_string();
+                let tx_endpoint = tx_endpoint.clone();
+                let transactions = transactions.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    // The closer settled with the gateway; its record is what counts
+                    let paid = match gateway_client::fetch_transaction(&channel.update.channel_id).await {
+                        Ok(settlement) => settlement.amount.to_major(),
+                        Err(e) => {
+                            web_sys::console::warn_1(&format!("Channel settlement not found: {}", e).into());
+                            0.0
+                        }
+                    };
+                    let update = ChannelUpdate { paid, ..channel.update };
+                    finalize_channel(&update, &endpoint_id, &tx_endpoint, &transactions);
+                });
+            }
+        },
+        "swap-offer" => {
+            if let Some(commitment) = msg.swap {
+                let terms = &commitment.terms;
+                let acceptable = commitment.verify()
+                    && commitment.party == terms.maker()
+                    && terms.taker() == endpoint_id
+                    && terms.expires_at > Utc::now()
+                    && counterparty_lists.get().accepts(terms.maker())
+                    && crypto::pin_peer_key(endpoint_id, terms.maker(), &commitment.public_key);
+                if acceptable {
+                    let swap = PendingSwap {
+                        terms: commitment.terms.clone(),
+                        status: SwapStatus::Prepared,
+                        committed: false,
+                    };
+                    swaps.with_mut(|swaps| {
+                        swaps.entry(swap.terms.swap_id.clone()).or_insert_with(|| swap.clone());
+                    });
+                    // We already agreed to these terms when we quoted them
+                    if rfq_book.get().honours(&swap.terms) {
+                        rfq_book.with_mut(|book| {
+                            book.issued.remove(&swap.terms.swap_id);
+                        });
+                        commit_swap(
+                            swap,
+                            "swap-accept",
+                            endpoint_id.to_string(),
+                            tx_worker.clone(),
+                            swaps.clone(),
+                            tx_endpoint.clone(),
+                            connection.clone(),
+                            error_message.clone(),
+                        );
+                    } else if let Some(leg) = swap.terms.leg_paid_by(endpoint_id) {
+                        let event = Event { trigger: Trigger::SwapOffered, peer: terms.maker(), amount: &leg.amount };
+                        match run_rules(&event, automation_rules, counterparty_lists, connection) {
+                            Some(Decision::Accept) => commit_swap(
+                                swap.clone(),
+                                "swap-accept",
+                                endpoint_id.to_string(),
+                                tx_worker.clone(),
+                                swaps.clone(),
+                                tx_endpoint.clone(),
+                                connection.clone(),
+                                error_message.clone(),
+                            ),
+                            Some(Decision::Decline) => decline_swap(&swap, endpoint_id, tx_worker, swaps, connection),
+                            None => {}
+                        }
+                    }
+                } else {
+                    web_sys::console::warn_1(&format!("Ignoring swap offer {} from {}", terms.swap_id, terms.maker()).into());
+                }
+            }
+        },
+        "quote-request" => {
+            if let Some(request) = msg.rfq {
+                let acceptable = request.requester != endpoint_id
+                    && request.size > 0.0
+                    && request.expires_at > Utc::now()
+                    && counterparty_lists.get().accepts(&request.requester);
+                if acceptable {
+                    rfq_book.with_mut(|book| {
+                        book.incoming.insert(request.rfq_id.clone(), request);
+                    });
+                }
+            }
+        },
+        "quote" => {
+            if let Some(quote) = msg.quote {
+                let requested = rfq_book.get().requested.get(&quote.request.rfq_id) == Some(&quote.request);
+                let acceptable = requested
+                    && quote.verify()
+                    && quote.expires_at > Utc::now()
+                    && counterparty_lists.get().accepts(&quote.quoter)
+                    && crypto::pin_peer_key(endpoint_id, &quote.quoter, &quote.public_key);
+                if acceptable {
+                    rfq_book.with_mut(|book| {
+                        book.received.insert(quote.quote_id.clone(), quote);
+                    });
+                } else {
+                    web_sys::console::warn_1(&format!("Ignoring quote {} from {}", quote.quote_id, quote.quoter).into());
+                }
+            }
+        },
+        "memo" => {
+            if let (Some(from), Some(text)) = (msg.from_peer, msg.memo) {
+                if text.trim().is_empty() || counterparty_lists.get().is_blocked(&from) {
+                    return;
+                }
+                let text: String = text.chars().take(tx_core::rules::MEMO_LIMIT).collect();
+                memos.with_mut(|memos| {
+                    memos.insert(0, (from, text));
+                    memos.truncate(rules::MEMOS_SHOWN);
+                });
+            }
+        },
+        "swap-accept" => {
+            // Settlement is driven by the gateway; the poll loop picks it up
+            if let Some(commitment) = msg.swap {
+                web_sys::console::log_1(&format!("{} accepted swap {}", commitment.party, commitment.terms.swap_id).into());
+            }
+        },
+        "swap-decline" => {
+            if let Some(commitment) = msg.swap {
+                if commitment.verify() && swaps.get().contains_key(&commitment.terms.swap_id) {
+                    // Our hold stays until the gateway rolls the swap back at expiry
+                    error_message.set(format!(
+                        "{} declined the swap; funds are released when it expires",
+                        commitment.party
+                    ));
+                }
+            }
+        },
+        "redirect" => {
+            if let (Some(url), Some(resume_token)) = (msg.url, msg.resume_token) {
+                connection_status.set("Reconnecting".to_string());
+                connection.with_mut(|conn| {
+                    if let Err(e) = conn.resume(&url, &resume_token) {
+                        web_sys::console::error_1(&e);
+                        error_message.set("Failed to follow signaling server redirect".to_string());
+                    }
+                });
+            }
+        },
+        "lagged" => {
+            web_sys::console::warn_1(
+                &format!("Fell behind, {} broadcasts missed; resyncing", msg.missed.unwrap_or(0)).into()
+            );
+        },
+        "resync-complete" if msg.complete == Some(false) => {
+            // The relay's history didn't reach back far enough, the gateway
+            // has the rest
+            let transactions = transactions.clone();
+            let tx_endpoint = tx_endpoint.clone();
+            let endpoint_id = endpoint_id.to_string();
+            wasm_bindgen_futures::spawn_local(async move {
+                match gateway_client::fetch_endpoint_transactions(&endpoint_id, 100).await {
+                    Ok(recovered) => {
+                        for tx in recovered {
+                            if transactions.get().contains_key(&tx.id) {
+                                continue;
+                            }
+                            if tx.to_endpoint == endpoint_id && tx.from_endpoint != endpoint_id {
+                                tx_endpoint.with_mut(|ep| {
+                                    let _ = ep.process_transaction(&tx);
+                                });
+                            }
+                            transactions.with_mut(|txs| {
+                                txs.insert(tx.id.clone(), tx);
+                            });
+                        }
+                    }
+                    Err(e) => web_sys::console::warn_1(&e.into()),
+                }
+            });
+        },
+        "resync-complete" => {},
+        "quota-exceeded" => {
+            error_message.set("Signaling server refused a message: relay quota exceeded".to_string());
+        },
+        "server-draining" => {
+            connection_status.set("Server restarting".to_string());
+            error_message.set("Signaling server is restarting, reconnect in a moment".to_string());
+        },
+        "error" => {
+            error_message.set("Connection error occurred".to_string());
+        },
+        _ => {
+            web_sys::console::log_1(&format!("Unknown message type: {}", msg.message_type).into());
+        }
+    }
+}
+
+fn send_signed_transaction(
+    tx: Transaction,
+    sequence: SequenceAllocator,
+    tx_worker: TxWorker,
+    tx_endpoint: UseState<TxEndpoint>,
+    transactions: UseState<HashMap<String, Transaction>>,
+    connection: UseState<WebSocketConnection>,
+    error_message: UseState<String>,
+) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let signature = match tx_worker.sign(&tx).await {
+            Ok(signature) => signature,
+            Err(e) => {
+                error_message.set(format!("Failed to sign transaction: {:?}", e));
+                return;
+            }
+        };
+        let mut tx = Transaction {
+            signature,
+            public_key: Some(tx_worker.keys().public_key_hex()),
+            ..tx
+        };
+
+        // While offline, and behind anything still waiting, hold it for
+        // the outbox to send in order
+        if !connection.current().is_open() || !tx_endpoint.current().outbox.is_empty() {
+            let mut queued = Ok(());
+            tx_endpoint.with_mut(|ep| queued = ep.enqueue(tx.clone()));
+            match queued {
+                Ok(()) => transactions.with_mut(|txs| {
+                    txs.insert(tx.id.clone(), tx);
+                }),
+                Err(e) => error_message.set(e),
+            }
+            return;
+        }
+
+        // Record with the gateway first so receivers can look up its risk
+        // score, unless the room settles in batches through the server
+        if !connection.get().room_batches() {
+            match gateway_client::submit_transaction(&tx).await {
+                Ok(result) => tx.risk_score = Some(result.risk_score),
+                Err(gateway_client::IngestError::Refused(reason)) => {
+                    // Another device may have used this sequence number
+                    sequence.sync(&tx.from_endpoint).await;
+                    error_message.set(reason);
+                    return;
+                }
+                Err(gateway_client::IngestError::Unavailable(e)) => {
+                    web_sys::console::warn_1(&format!("Gateway ingest failed: {}", e).into())
+                }
+            }
+        }
+
+        // Update local endpoint state
+        tx_endpoint.with_mut(|ep| {
+            let _ = ep.process_transaction(&tx);
+        });
+
+        // Add to local transactions
+        transactions.with_mut(|txs| {
+            txs.insert(tx.id.clone(), tx.clone());
+        });
+
+        // Send via WebSocket
+        let mut sent = Ok(());
+        connection.with_mut(|conn| sent = conn.send_transaction(&tx));
+        match sent {
+            Ok(()) => advance_status(&tx.id, TransactionStatus::Relayed, !connection.get().room_batches(), &transactions),
+            Err(e) => error_message.set(format!("Failed to send transaction: {:?}", e)),
+        }
+    });
+}
+
+/// Leaves the current room for `room_id`, creating it if it's empty.
+/// Channels and swaps are relayed within the room, so they have to be
+/// closed or finished first; peers and quotes from the old room are
+/// dropped.
+#[allow(clippy::too_many_arguments)]
+fn switch_room(
+    room_id: &str,
+    connection: &UseState<WebSocketConnection>,
+    connection_status: &UseState<String>,
+    connected_peers: &UseState<Vec<String>>,
+    channels: &UseState<HashMap<String, OpenChannel>>,
+    swaps: &UseState<HashMap<String, PendingSwap>>,
+    rfq_book: &UseState<RfqBook>,
+    error_message: &UseState<String>,
+) {
+    if room_id.is_empty() || room_id == connection.get().room_id() {
+        return;
+    }
+    let swapping = swaps
+        .get()
+        .values()
+        .any(|swap| matches!(swap.status, SwapStatus::Prepared | SwapStatus::Committing));
+    if !channels.get().is_empty() || swapping {
+        error_message.set("Close open channels and finish swaps before switching rooms".to_string());
+        return;
+    }
+
+    let mut switched = Ok(());
+    connection.with_mut(|conn| switched = conn.switch_room(room_id));
+    match switched {
+        Ok(()) => {
+            connection_status.set(format!("Joining {}…", room_id));
+            connected_peers.set(Vec::new());
+            rfq_book.set(RfqBook::default());
+        }
+        Err(e) => error_message.set(format!("Could not switch rooms: {:?}", e)),
+    }
+}
+
+/// Tells the sender of `tx` whether we applied it. The ack is signed over
+/// the outcome, so the sender can confirm or refund on our word alone.
+fn acknowledge(
+    tx: &Transaction,
+    applied: &Result<(), String>,
+    endpoint_id: &str,
+    tx_worker: &TxWorker,
+    connection: &UseState<WebSocketConnection>,
+) {
+    let (result, reason) = match applied {
+        Ok(()) => (AckResult::Applied, None),
+        Err(e) => (AckResult::Failed, Some(e.clone())),
+    };
+    let signature = crypto::sign_ack(tx, endpoint_id, result, tx_worker.keys());
+    connection.with_mut(|conn| {
+        if let Err(e) = conn.send_ack(tx, result, reason, signature, tx_worker.keys().public_key_hex()) {
+            web_sys::console::error_1(&e);
+        }
+    });
+}
+
+/// Moves our copy of a transaction to `status` if its lifecycle allows
+/// it, and reports the change to the gateway when it holds a record of
+/// the transaction.
+fn advance_status(
+    tx_id: &str,
+    status: TransactionStatus,
+    report: bool,
+    transactions: &UseState<HashMap<String, Transaction>>,
+) {
+    let mut advanced = false;
+    transactions.with_mut(|txs| {
+        if let Some(tx) = txs.get_mut(tx_id) {
+            if let Ok(next) = tx.status.transition_to(status) {
+                advanced = next != tx.status;
+                tx.status = next;
+            }
+        }
+    });
+    if advanced && report {
+        let tx_id = tx_id.to_string();
+        wasm_bindgen_futures::spawn_local(async move {
+            if let Err(e) = gateway_client::report_status(&tx_id, status).await {
+                web_sys::console::warn_1(&format!("Reporting {} as {} failed: {}", tx_id, status, e).into());
+            }
+        });
+    }
+}
+
+/// Sends queued transactions in order while the connection is up. Stops at
+/// the first that can't be broadcast so later ones don't overtake it; one
+/// the gateway refuses is dropped and refunded.
+async fn flush_outbox(
+    sequence: &SequenceAllocator,
+    tx_endpoint: &UseState<TxEndpoint>,
+    transactions: &UseState<HashMap<String, Transaction>>,
+    connection: &UseState<WebSocketConnection>,
+    error_message: &UseState<String>,
+) {
+    loop {
+        if !connection.current().is_open() {
+            return;
+        }
+        let Some(queued) = tx_endpoint.current().outbox.front().cloned() else {
+            return;
+        };
+        let mut tx = queued.tx;
+
+        if !queued.recorded && !connection.current().room_batches() {
+            match gateway_client::submit_transaction(&tx).await {
+                Ok(result) => {
+                    tx.risk_score = Some(result.risk_score);
+                    tx_endpoint.with_mut(|ep| ep.mark_recorded(tx.clone()));
+                }
+                Err(gateway_client::IngestError::Refused(reason)) => {
+                    sequence.sync(&tx.from_endpoint).await;
+                    tx_endpoint.with_mut(|ep| {
+                        if ep.dequeue(&tx.id).is_some() {
+                            ep.refund_transaction(&tx);
+                        }
+                    });
+                    transactions.with_mut(|txs| {
+                        if let Some(existing) = txs.get_mut(&tx.id) {
+                            existing.status = TransactionStatus::Failed;
+                        }
+                    });
+                    error_message.set(format!("Queued transaction to {} refused: {}", tx.to_endpoint, reason));
+                    continue;
+                }
+                Err(gateway_client::IngestError::Unavailable(e)) => {
+                    web_sys::console::warn_1(&format!("Gateway ingest failed: {}", e).into())
+                }
+            }
+        }
+
+        let mut sent = Ok(());
+        connection.with_mut(|conn| sent = conn.send_transaction(&tx));
+        if let Err(e) = sent {
+            tx_endpoint.with_mut(|ep| ep.record_send_failure(&tx.id, format!("{:?}", e)));
+            return;
+        }
+        tx_endpoint.with_mut(|ep| {
+            ep.dequeue(&tx.id);
+        });
+        let tx_id = tx.id.clone();
+        transactions.with_mut(|txs| {
+            txs.insert(tx.id.clone(), tx);
+        });
+        advance_status(&tx_id, TransactionStatus::Relayed, !connection.current().room_batches(), transactions);
+    }
+}
+
+/// Takes a small payment out of the balance now and queues it for the next
+/// net settlement with its peer.
+#[allow(clippy::too_many_arguments)]
+fn queue_netted(
+    tx: Transaction,
+    endpoint_id: &str,
+    netting: NettingBuffer,
+    sequence: SequenceAllocator,
+    tx_worker: TxWorker,
+    tx_endpoint: UseState<TxEndpoint>,
+    transactions: UseState<HashMap<String, Transaction>>,
+    connection: UseState<WebSocketConnection>,
+    error_message: UseState<String>,
+) {
+    let mut applied = Ok(());
+    tx_endpoint.with_mut(|ep| applied = ep.process_transaction(&tx));
+    if let Err(e) = applied {
+        error_message.set(e);
+        return;
+    }
+
+    let peer = tx.to_endpoint.clone();
+    transactions.with_mut(|txs| {
+        txs.insert(tx.id.clone(), tx.clone());
+    });
+    if netting.add(tx) >= netting::NETTING_MAX_ITEMS {
+        settle_net(&peer, endpoint_id, netting, sequence, tx_worker, tx_endpoint, transactions, connection, error_message);
+    }
+}
+
+/// Sends one transfer for everything pending to `peer`. The gateway records
+/// the pending payments as its children; if it refuses the net they are
+/// refunded and marked failed.
+#[allow(clippy::too_many_arguments)]
+fn settle_net(
+    peer: &str,
+    endpoint_id: &str,
+    netting: NettingBuffer,
+    sequence: SequenceAllocator,
+    tx_worker: TxWorker,
+    tx_endpoint: UseState<TxEndpoint>,
+    transactions: UseState<HashMap<String, Transaction>>,
+    connection: UseState<WebSocketConnection>,
+    error_message: UseState<String>,
+) {
+    let components = netting.take(peer);
+    if components.is_empty() {
+        return;
+    }
+    let total = components.iter().try_fold(Money::zero(NATIVE_ASSET), |total, tx| total.checked_add(&tx.amount));
+    let amount = match total {
+        Ok(amount) => amount,
+        Err(e) => {
+            let reason = format!("Failed to net payments: {}", e);
+            return refund_netted(&components, &tx_endpoint, &transactions, &error_message, reason);
+        }
+    };
+
+    let net = Transaction {
+        id: Uuid::new_v4().to_string(),
+        from_endpoint: endpoint_id.to_string(),
+        to_endpoint: peer.to_string(),
+        amount,
+        timestamp: Utc::now(),
+        signature: String::new(),
+        status: TransactionStatus::Pending,
+        kind: TransactionKind::Transfer,
+        risk_score: None,
+        parent_tx_id: None,
+        sequence: Some(sequence.next()),
+        public_key: Some(tx_worker.keys().public_key_hex()),
+    };
+
+    wasm_bindgen_futures::spawn_local(async move {
+        let fail = |reason: String| refund_netted(&components, &tx_endpoint, &transactions, &error_message, reason);
+
+        let signature = match tx_worker.sign(&net).await {
+            Ok(signature) => signature,
+            Err(e) => return fail(format!("Failed to sign net transaction: {:?}", e)),
+        };
+        let mut net = Transaction { signature, ..net };
+
+        let mut component_status = TransactionStatus::Pending;
+        match gateway_client::submit_net(&net, &components).await {
+            Ok(result) => {
+                net.risk_score = Some(result.risk_score);
+                component_status = if result.held { TransactionStatus::Held } else { TransactionStatus::Confirmed };
+            }
+            Err(gateway_client::IngestError::Refused(reason)) => {
+                sequence.sync(&net.from_endpoint).await;
+                return fail(reason);
+            }
+            // Same as single sends: without the gateway the peer still gets paid
+            Err(gateway_client::IngestError::Unavailable(e)) => {
+                web_sys::console::warn_1(&format!("Gateway net ingest failed: {}", e).into())
+            }
+        }
+
+        // The components already left the balance, so the net is only recorded
+        transactions.with_mut(|txs| {
+            for component in &components {
+                if let Some(tx) = txs.get_mut(&component.id) {
+                    tx.kind = TransactionKind::Netted;
+                    tx.parent_tx_id = Some(net.id.clone());
+                    tx.status = component_status;
+                }
+            }
+            txs.insert(net.id.clone(), net.clone());
+        });
+        tx_endpoint.with_mut(|ep| ep.transaction_count += 1);
+
+        connection.with_mut(|conn| {
+            if let Err(e) = conn.send_transaction(&net) {
+                error_message.set(format!("Failed to send net transaction: {:?}", e));
+            }
+        });
+    });
+}
+
+/// Gives back pending payments a net transfer didn't go through for and
+/// marks them failed.
+fn refund_netted(
+    components: &[Transaction],
+    tx_endpoint: &UseState<TxEndpoint>,
+    transactions: &UseState<HashMap<String, Transaction>>,
+    error_message: &UseState<String>,
+    reason: String,
+) {
+    for component in components {
+        tx_endpoint.with_mut(|ep| ep.refund_transaction(component));
+    }
+    transactions.with_mut(|txs| {
+        for component in components {
+            if let Some(tx) = txs.get_mut(&component.id) {
+                tx.status = TransactionStatus::Failed;
+            }
+        }
+    });
+    error_message.set(reason);
+}
+
+/// Reserves funds and starts streaming to `payee`.
+fn open_channel(
+    payee: &str,
+    endpoint_id: &str,
+    tx_worker: &TxWorker,
+    tx_endpoint: &UseState<TxEndpoint>,
+    channels: &UseState<HashMap<String, OpenChannel>>,
+    connection: &UseState<WebSocketConnection>,
+    error_message: &UseState<String>,
+) {
+    let channel = channels::open(
+        tx_worker.keys(),
+        endpoint_id,
+        payee,
+        channels::STREAM_RESERVE,
+        channels::STREAM_RATE_PER_SEC,
+    );
+    let mut reserved = Ok(());
+    tx_endpoint.with_mut(|ep| reserved = ep.reserve(&channel.update.channel_id, channels::STREAM_RESERVE));
+    if let Err(e) = reserved {
+        error_message.set(e);
+        return;
+    }
+    connection.with_mut(|conn| {
+        if let Err(e) = conn.send_channel("channel-open", &channel.update) {
+            error_message.set(format!("Failed to open channel: {:?}", e));
+        }
+    });
+    channels.with_mut(|channels| {
+        channels.insert(channel.update.channel_id.clone(), channel);
+    });
+}
+
+/// Settles the channel with the gateway at its latest update and tells
+/// the peer. Either side may close.
+fn close_channel(
+    channel_id: String,
+    endpoint_id: String,
+    channels: UseState<HashMap<String, OpenChannel>>,
+    tx_endpoint: UseState<TxEndpoint>,
+    transactions: UseState<HashMap<String, Transaction>>,
+    connection: UseState<WebSocketConnection>,
+    error_message: UseState<String>,
+) {
+    let mut closed = None;
+    channels.with_mut(|channels| closed = channels.remove(&channel_id));
+    let Some(channel) = closed else {
+        return;
+    };
+
+    wasm_bindgen_futures::spawn_local(async move {
+        let update = channel.update.clone();
+        let settled = if update.paid > 0.0 {
+            gateway_client::settle_channel(&update).await.map(|_| update.paid)
+        } else {
+            Ok(0.0)
+        };
+
+        let paid = match settled {
+            Ok(paid) => paid,
+            Err(gateway_client::IngestError::Refused(reason)) => {
+                error_message.set(reason);
+                0.0
+            }
+            Err(gateway_client::IngestError::Unavailable(e)) => {
+                // Keep the channel so closing can be retried
+                error_message.set(format!("Could not settle channel: {}", e));
+                channels.with_mut(|channels| {
+                    channels.insert(channel_id, channel);
+                });
+                return;
+            }
+        };
+
+        finalize_channel(&ChannelUpdate { paid, ..update.clone() }, &endpoint_id, &tx_endpoint, &transactions);
+        connection.with_mut(|conn| {
+            if let Err(e) = conn.send_channel("channel-close", &update) {
+                web_sys::console::error_1(&e);
+            }
+        });
+    });
+}
+
+/// Books a closed channel locally: the payer gets back what was reserved
+/// but not paid, the payee is credited what was paid.
+fn finalize_channel(
+    update: &ChannelUpdate,
+    endpoint_id: &str,
+    tx_endpoint: &UseState<TxEndpoint>,
+    transactions: &UseState<HashMap<String, Transaction>>,
+) {
+    tx_endpoint.with_mut(|ep| {
+        if update.payer == endpoint_id {
+            ep.release(&update.channel_id, update.reserved - update.paid);
+        }
+    });
+    if update.paid <= 0.0 {
+        return;
+    }
+    let Ok(settlement) = update.settlement() else {
+        web_sys::console::warn_1(&format!("Channel {} paid an unusable amount", update.channel_id).into());
+        return;
+    };
+    let settlement = Transaction { status: TransactionStatus::Confirmed, ..settlement };
+
+    tx_endpoint.with_mut(|ep| {
+        if update.payer == endpoint_id {
+            ep.pay_from_reserve(&update.channel_id, &settlement);
+        } else {
+            let _ = ep.process_transaction(&settlement);
+        }
+    });
+    transactions.with_mut(|txs| {
+        txs.insert(settlement.id.clone(), settlement);
+    });
+}
+
+/// Signs our side of `swap`, locks our leg with the gateway and tells the
+/// other party. Native funds are reserved locally until the swap settles
+/// or rolls back.
+#[allow(clippy::too_many_arguments)]
+fn commit_swap(
+    swap: PendingSwap,
+    message_type: &'static str,
+    endpoint_id: String,
+    tx_worker: TxWorker,
+    swaps: UseState<HashMap<String, PendingSwap>>,
+    tx_endpoint: UseState<TxEndpoint>,
+    connection: UseState<WebSocketConnection>,
+    error_message: UseState<String>,
+) {
+    let Some(leg) = swap.terms.leg_paid_by(&endpoint_id).cloned() else {
+        return;
+    };
+    if !swap.terms.is_well_formed() {
+        error_message.set("Invalid swap terms".to_string());
+        return;
+    }
+    if leg.asset == NATIVE_ASSET {
+        let mut reserved = Ok(());
+        tx_endpoint.with_mut(|ep| reserved = ep.reserve(&swap.terms.swap_id, leg.amount.to_major()));
+        if let Err(e) = reserved {
+            error_message.set(e);
+            return;
+        }
+    }
+
+    let commitment = swaps::commit(&swap.terms, &endpoint_id, tx_worker.keys());
+    let swap_id = swap.terms.swap_id.clone();
+    wasm_bindgen_futures::spawn_local(async move {
+        match gateway_client::hold_swap(&commitment).await {
+            Ok(state) => {
+                swaps.with_mut(|swaps| {
+                    swaps.insert(
+                        state.terms.swap_id.clone(),
+                        PendingSwap { committed: true, ..swap },
+                    );
+                });
+                connection.with_mut(|conn| {
+                    if let Err(e) = conn.send_swap(message_type, &commitment) {
+                        web_sys::console::error_1(&e);
+                    }
+                });
+            }
+            Err(e) => {
+                if leg.asset == NATIVE_ASSET {
+                    tx_endpoint.with_mut(|ep| ep.release(&swap_id, leg.amount.to_major()));
+                }
+                error_message.set(e);
+            }
+        }
+    });
+}
+
+fn decline_swap(
+    swap: &PendingSwap,
+    endpoint_id: &str,
+    tx_worker: &TxWorker,
+    swaps: &UseState<HashMap<String, PendingSwap>>,
+    connection: &UseState<WebSocketConnection>,
+) {
+    let commitment = swaps::commit(&swap.terms, endpoint_id, tx_worker.keys());
+    connection.with_mut(|conn| {
+        if let Err(e) = conn.send_swap("swap-decline", &commitment) {
+            web_sys::console::error_1(&e);
+        }
+    });
+    swaps.with_mut(|swaps| {
+        swaps.remove(&swap.terms.swap_id);
+    });
+}
+
+/// Books the outcome of a swap we committed to. Only native legs touch
+/// the local balance; other assets live on the gateway.
+fn finalize_swap(
+    swap: &PendingSwap,
+    status: SwapStatus,
+    endpoint_id: &str,
+    tx_endpoint: &UseState<TxEndpoint>,
+    transactions: &UseState<HashMap<String, Transaction>>,
+) {
+    let terms = &swap.terms;
+    match status {
+        SwapStatus::Settled => {
+            for leg in [&terms.offer, &terms.ask] {
+                if leg.asset != NATIVE_ASSET {
+                    continue;
+                }
+                // Local record of the leg; the gateway books it under its own id
+                let transfer = Transaction {
+                    id: Uuid::new_v4().to_string(),
+                    from_endpoint: leg.from_endpoint.clone(),
+                    to_endpoint: leg.to_endpoint.clone(),
+                    amount: leg.amount.clone(),
+                    timestamp: Utc::now(),
+                    signature: String::new(),
+                    status: TransactionStatus::Confirmed,
+                    kind: TransactionKind::Transfer,
+                    risk_score: None,
+                    parent_tx_id: None,
+                    sequence: None,
+                    public_key: None,
+                };
+                tx_endpoint.with_mut(|ep| {
+                    if leg.from_endpoint == endpoint_id {
+                        // Paid out of what we reserved when we committed
+                        ep.pay_from_reserve(&terms.swap_id, &transfer);
+                    } else {
+                        let _ = ep.process_transaction(&transfer);
+                    }
+                });
+                transactions.with_mut(|txs| {
+                    txs.insert(transfer.id.clone(), transfer);
+                });
+            }
+        }
+        SwapStatus::RolledBack if swap.committed => {
+            if let Some(leg) = terms.leg_paid_by(endpoint_id) {
+                if leg.asset == NATIVE_ASSET {
+                    tx_endpoint.with_mut(|ep| ep.release(&terms.swap_id, leg.amount.to_major()));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn update_counterparty_list(
+    endpoint_id: String,
+    list: &'static str,
+    peer: String,
+    add: bool,
+    counterparty_lists: UseState<CounterpartyLists>,
+    error_message: UseState<String>,
+) {
+    if peer.is_empty() || peer == endpoint_id {
+        return;
+    }
+    wasm_bindgen_futures::spawn_local(async move {
+        match gateway_client::update_counterparty(&endpoint_id, list, &peer, add).await {
+            Ok(lists) => {
+                lists.store_cached(&endpoint_id);
+                counterparty_lists.set(lists);
+            }
+            Err(e) => error_message.set(e),
+        }
+    });
+}
+
+/// A transfer to us, applied, for the automation rules.
+fn received(tx: &Transaction) -> Event<'_> {
+    Event { trigger: Trigger::Received, peer: &tx.from_endpoint, amount: &tx.amount }
+}
+
+/// Runs the automation rules on `event` and sends the memos they ask for.
+/// A swap decision is returned for the caller to act on.
+fn run_rules(
+    event: &Event,
+    automation_rules: &UseState<Vec<Rule>>,
+    counterparty_lists: &UseState<CounterpartyLists>,
+    connection: &UseState<WebSocketConnection>,
+) -> Option<Decision> {
+    let lists = counterparty_lists.current();
+    let outcome = tx_core::rules::evaluate(&automation_rules.current(), event, |peer| lists.is_trusted(peer));
+    if outcome.fired.is_empty() {
+        return None;
+    }
+    web_sys::console::log_1(&format!("Rules fired for {}: {}", event.peer, outcome.fired.join(", ")).into());
+    for memo in &outcome.memos {
+        connection.with_mut(|conn| {
+            if let Err(e) = conn.send_memo(event.peer, memo) {
+                web_sys::console::error_1(&e);
+            }
+        });
+    }
+    outcome.decision
+}
+
+/// Replaces the automation rules and keeps them for next time.
+fn save_rules(endpoint_id: &str, updated: Vec<Rule>, automation_rules: &UseState<Vec<Rule>>) {
+    rules::store_cached(endpoint_id, &updated);
+    automation_rules.set(updated);
+}
+
+/// Replays the endpoint's ledger from the gateway's transaction history,
+/// picking up any transactions we hadn't seen.
+fn rebuild_ledger(
+    endpoint_id: String,
+    tx_endpoint: UseState<TxEndpoint>,
+    transactions: UseState<HashMap<String, Transaction>>,
+    error_message: UseState<String>,
+) {
+    wasm_bindgen_futures::spawn_local(async move {
+        match gateway_client::fetch_endpoint_transactions(&endpoint_id, gateway_client::HISTORY_LIMIT).await {
+            Ok(history) => {
+                tx_endpoint.with_mut(|ep| ep.rebuild_from_history(&history));
+                transactions.with_mut(|txs| {
+                    for tx in history {
+                        txs.entry(tx.id.clone()).or_insert(tx);
+                    }
+                });
+            }
+            Err(e) => error_message.set(e),
+        }
+    });
+}
+
+/// Fetches the gateway's signed receipt for `tx` and, once it checks out
+/// against the transaction we hold and the gateway's published keys, saves
+/// it as a JSON file.
+fn download_receipt(tx: Transaction, error_message: UseState<String>) {
+    wasm_bindgen_futures::spawn_local(async move {
+        let receipt = match gateway_client::fetch_receipt(&tx.id).await {
+            Ok(receipt) => receipt,
+            Err(e) => {
+                error_message.set(e);
+                return;
+            }
+        };
+        if !receipt.verify() || receipt.transaction_hash != tx_core::transaction_hash(&tx) {
+            error_message.set("The gateway's receipt does not match this transaction".to_string());
+            return;
+        }
+        if let Ok(keys) = gateway_client::fetch_gateway_keys(Some(&receipt.public_key)).await {
+            if let Check::Failed(reason) = tx_core::verify_receipt(&receipt, Some(&keys), [&tx]).key {
+                error_message.set(format!("The gateway's receipt is not trusted: {}", reason));
+                return;
+            }
+        }
+
+        let result = serde_json::to_string_pretty(&receipt)
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+            .and_then(|json| save_file(&format!("receipt-{}.json", tx.id), &json));
+        if let Err(e) = result {
+            error_message.set(format!("Failed to save receipt: {:?}", e));
+        }
+    });
+}
+
+/// Checks a receipt file against the gateway's keys and the transactions
+/// held locally. Works offline with the last key set seen.
+async fn verify_receipt_file(
+    name: String,
+    json: String,
+    transactions: &UseState<HashMap<String, Transaction>>,
+    receipt_report: &UseState<Option<(String, ReceiptReport)>>,
+) {
+    let receipt: Receipt = match serde_json::from_str(&json) {
+        Ok(receipt) => receipt,
+        Err(e) => {
+            let report = ReceiptReport {
+                signature: Check::Failed(format!("not a receipt: {}", e)),
+                key: Check::Skipped("unreadable receipt".to_string()),
+                inclusion: Check::Skipped("unreadable receipt".to_string()),
+                transaction: Check::Skipped("unreadable receipt".to_string()),
+            };
+            receipt_report.set(Some((name, report)));
+            return;
+        }
+    };
+
+    let trusted_keys = gateway_client::fetch_gateway_keys(Some(&receipt.public_key)).await.ok();
+    let report = tx_core::verify_receipt(&receipt, trusted_keys.as_ref(), transactions.get().values());
+    receipt_report.set(Some((name, report)));
+}
+
+fn save_file(name: &str, contents: &str) -> Result<(), JsValue> {
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let mut options = web_sys::BlobPropertyBag::new();
+    options.type_("application/json");
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+    let document = web_sys::window().and_then(|w| w.document()).ok_or("No document")?;
+    let anchor = document
+        .create_element("a")?
+        .dyn_into::<web_sys::HtmlAnchorElement>()
+        .map_err(JsValue::from)?;
+    anchor.set_href(&url);
+    anchor.set_download(name);
+    anchor.click();
+    web_sys::Url::revoke_object_url(&url)
+}
+
+/// Where a transfer we sent stands with its recipient.
+fn delivery_state(tx: &Transaction) -> String {
+    match tx.status {
+        TransactionStatus::Pending | TransactionStatus::Held => "⏳ Not sent yet".to_string(),
+        TransactionStatus::Relayed | TransactionStatus::Acknowledged => format!("📨 Sent, waiting for {} to apply it", tx.to_endpoint),
+        TransactionStatus::Confirmed => format!("✅ Applied by {}", tx.to_endpoint),
+        TransactionStatus::Failed => format!("❌ Not applied by {}", tx.to_endpoint),
+        TransactionStatus::Expired => "⌛ Expired before delivery".to_string(),
     }
 }
 
-fn format_timestamp(timestamp: u64) -> String {
-    let date = js_sys::Date::new(&(timestamp.into()));
+fn format_timestamp(timestamp: &DateTime<Utc>) -> String {
+    let date = js_sys::Date::new(&(timestamp.timestamp_millis() as f64).into());
     date.to_locale_string("en-US", &js_sys::Object::new()).as_string().unwrap_or_default()
 }
 
@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use chrono::Utc;
+
+use crate::Transaction;
+
+/// Payments up to this amount are netted when netting mode is on.
+pub const NETTING_MAX_AMOUNT: f64 = 5.0;
+
+/// A peer's pending payments are settled once this many have piled up...
+pub const NETTING_MAX_ITEMS: usize = 20;
+
+/// ...or once the oldest has waited this long.
+pub const NETTING_WINDOW_MS: i64 = 5_000;
+
+pub const NETTING_POLL_MS: u32 = 1_000;
+
+/// Small payments to the same peer, waiting to be settled as one net
+/// transfer. The funds are already deducted locally; the components are
+/// sent to the gateway with the net so each stays on record as its child.
+#[derive(Clone, Default)]
+pub struct NettingBuffer {
+    pending: Rc<RefCell<HashMap<String, Vec<Transaction>>>>,
+}
+
+impl NettingBuffer {
+    /// Queues a payment and returns how many are now pending for its peer.
+    pub fn add(&self, tx: Transaction) -> usize {
+        let mut pending = self.pending.borrow_mut();
+        let components = pending.entry(tx.to_endpoint.clone()).or_default();
+        components.push(tx);
+        components.len()
+    }
+
+    pub fn take(&self, peer: &str) -> Vec<Transaction> {
+        self.pending.borrow_mut().remove(peer).unwrap_or_default()
+    }
+
+    /// Peers whose oldest pending payment has outlived the window.
+    pub fn due(&self) -> Vec<String> {
+        let now = Utc::now().timestamp_millis();
+        self.pending
+            .borrow()
+            .iter()
+            .filter(|(_, components)| {
+                components
+                    .first()
+                    .map_or(false, |tx| now - tx.timestamp.timestamp_millis() >= NETTING_WINDOW_MS)
+            })
+            .map(|(peer, _)| peer.clone())
+            .collect()
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.borrow().values().map(Vec::len).sum()
+    }
+}
@@ -0,0 +1,174 @@
+use std::cell::RefCell;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{IdbDatabase, IdbOpenDbRequest, IdbRequest, IdbTransactionMode};
+
+use crate::Transaction;
+
+const DB_NAME: &str = "tx-endpoint";
+const STORE: &str = "outbox";
+
+/// How often a non-empty outbox is retried while connected.
+pub const OUTBOX_RETRY_MS: u32 = 2_000;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueuedTransaction {
+    pub tx: Transaction,
+    /// Failed sends so far.
+    pub attempts: u32,
+    pub last_error: Option<String>,
+    /// Already accepted by the gateway, only the broadcast is outstanding.
+    #[serde(default)]
+    pub recorded: bool,
+}
+
+/// Signed transactions waiting for the connection, sent in the order they
+/// were made. Their funds have already left the local balance. A copy is
+/// kept in IndexedDB under the endpoint id so a reload doesn't lose them.
+#[derive(Clone, Debug, Default)]
+pub struct Outbox {
+    queued: Vec<QueuedTransaction>,
+}
+
+impl Outbox {
+    pub fn push(&mut self, tx: Transaction) {
+        self.queued.push(QueuedTransaction { tx, attempts: 0, last_error: None, recorded: false });
+    }
+
+    pub fn front(&self) -> Option<&QueuedTransaction> {
+        self.queued.first()
+    }
+
+    pub fn remove(&mut self, tx_id: &str) -> Option<QueuedTransaction> {
+        let index = self.queued.iter().position(|q| q.tx.id == tx_id)?;
+        Some(self.queued.remove(index))
+    }
+
+    /// Swaps in the transaction as the gateway recorded it.
+    pub fn mark_recorded(&mut self, tx: Transaction) {
+        if let Some(queued) = self.queued.iter_mut().find(|q| q.tx.id == tx.id) {
+            queued.tx = tx;
+            queued.recorded = true;
+        }
+    }
+
+    pub fn record_failure(&mut self, tx_id: &str, error: String) {
+        if let Some(queued) = self.queued.iter_mut().find(|q| q.tx.id == tx_id) {
+            queued.attempts += 1;
+            queued.last_error = Some(error);
+        }
+    }
+
+    /// Puts transactions restored from storage ahead of any queued since.
+    pub fn restore(&mut self, restored: Outbox) {
+        let mut queued = restored.queued;
+        queued.retain(|q| !self.queued.iter().any(|existing| existing.tx.id == q.tx.id));
+        queued.append(&mut self.queued);
+        self.queued = queued;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &QueuedTransaction> {
+        self.queued.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.queued.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queued.is_empty()
+    }
+
+    /// Reads the stored outbox. Opens the database on first use; until then
+    /// `store` is a no-op, so nothing overwrites the copy before it's read.
+    pub async fn load(endpoint_id: &str) -> Outbox {
+        let queued = match read(endpoint_id).await {
+            Ok(Some(json)) => serde_json::from_str(&json).unwrap_or_else(|e| {
+                web_sys::console::warn_1(&format!("Discarding unreadable outbox: {}", e).into());
+                Vec::new()
+            }),
+            Ok(None) => Vec::new(),
+            Err(e) => {
+                web_sys::console::warn_1(&e);
+                Vec::new()
+            }
+        };
+        Outbox { queued }
+    }
+
+    pub fn store(&self, endpoint_id: &str) {
+        let Some(db) = DATABASE.with(|db| db.borrow().clone()) else {
+            return;
+        };
+        let Ok(json) = serde_json::to_string(&self.queued) else {
+            return;
+        };
+        // Requests on one store run in the order they're made, so the last
+        // write wins without waiting for earlier ones
+        let result = db
+            .transaction_with_str_and_mode(STORE, IdbTransactionMode::Readwrite)
+            .and_then(|t| t.object_store(STORE))
+            .and_then(|store| store.put_with_key(&JsValue::from_str(&json), &JsValue::from_str(endpoint_id)));
+        if let Err(e) = result {
+            web_sys::console::warn_1(&e);
+        }
+    }
+}
+
+thread_local! {
+    static DATABASE: RefCell<Option<IdbDatabase>> = RefCell::new(None);
+}
+
+async fn read(endpoint_id: &str) -> Result<Option<String>, JsValue> {
+    let db = open().await?;
+    DATABASE.with(|cached| *cached.borrow_mut() = Some(db.clone()));
+
+    let request = db
+        .transaction_with_str(STORE)?
+        .object_store(STORE)?
+        .get(&JsValue::from_str(endpoint_id))?;
+    Ok(completed(&request).await?.as_string())
+}
+
+async fn open() -> Result<IdbDatabase, JsValue> {
+    let factory = web_sys::window()
+        .ok_or_else(|| JsValue::from_str("No window"))?
+        .indexed_db()?
+        .ok_or_else(|| JsValue::from_str("IndexedDB unavailable"))?;
+    let request: IdbOpenDbRequest = factory.open_with_u32(DB_NAME, 1)?;
+
+    let onupgradeneeded = Closure::once_into_js(move |e: web_sys::Event| {
+        let db = e
+            .target()
+            .and_then(|t| t.dyn_into::<IdbOpenDbRequest>().ok())
+            .and_then(|r| r.result().ok())
+            .and_then(|db| db.dyn_into::<IdbDatabase>().ok());
+        if let Some(db) = db {
+            if !db.object_store_names().contains(STORE) {
+                let _ = db.create_object_store(STORE);
+            }
+        }
+    });
+    request.set_onupgradeneeded(Some(onupgradeneeded.unchecked_ref()));
+
+    completed(&request).await?.dyn_into::<IdbDatabase>()
+}
+
+/// Resolves with the request's result once it succeeds.
+async fn completed(request: &IdbRequest) -> Result<JsValue, JsValue> {
+    let promise = js_sys::Promise::new(&mut |resolve, reject| {
+        let request_for_success = request.clone();
+        let onsuccess = Closure::once_into_js(move |_: web_sys::Event| {
+            let result = request_for_success.result().unwrap_or(JsValue::UNDEFINED);
+            let _ = resolve.call1(&JsValue::NULL, &result);
+        });
+        let onerror = Closure::once_into_js(move |_: web_sys::Event| {
+            let _ = reject.call1(&JsValue::NULL, &JsValue::from_str("IndexedDB request failed"));
+        });
+        request.set_onsuccess(Some(onsuccess.unchecked_ref()));
+        request.set_onerror(Some(onerror.unchecked_ref()));
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await
+}
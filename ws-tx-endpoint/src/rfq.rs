@@ -0,0 +1,68 @@
+use chrono::{Duration, Utc};
+use std::collections::HashMap;
+use tx_core::{Quote, QuoteRequest, Side};
+
+use crate::crypto::EndpointKeys;
+
+/// How long a request for quotes is shown to other peers.
+pub const RFQ_TTL_SECS: i64 = 30;
+
+/// How long a quote is binding. Accepting it opens a swap that expires
+/// with the quote.
+pub const QUOTE_TTL_SECS: i64 = 60;
+
+/// Requests for quotes and quotes this endpoint has seen: its own
+/// requests and the quotes they drew, other peers' requests, and the
+/// quotes it issued (which it honours when the requester accepts).
+#[derive(Clone, Debug, Default)]
+pub struct RfqBook {
+    pub requested: HashMap<String, QuoteRequest>,
+    pub received: HashMap<String, Quote>,
+    pub incoming: HashMap<String, QuoteRequest>,
+    pub issued: HashMap<String, Quote>,
+}
+
+impl RfqBook {
+    /// Drops requests and quotes that can no longer be acted on.
+    pub fn prune(&mut self) {
+        let now = Utc::now();
+        self.requested.retain(|_, request| request.expires_at > now);
+        self.incoming.retain(|_, request| request.expires_at > now);
+        self.received.retain(|_, quote| quote.expires_at > now);
+        self.issued.retain(|_, quote| quote.expires_at > now);
+    }
+
+    /// Whether an incoming swap offer is the one a quote we issued commits
+    /// us to.
+    pub fn honours(&self, terms: &tx_core::SwapTerms) -> bool {
+        self.issued
+            .get(&terms.swap_id)
+            .is_some_and(|quote| quote.swap_terms().is_ok_and(|quoted| quoted == *terms))
+    }
+}
+
+pub fn request(requester: &str, side: Side, base_asset: &str, quote_asset: &str, size: f64) -> QuoteRequest {
+    QuoteRequest {
+        rfq_id: uuid::Uuid::new_v4().to_string(),
+        requester: requester.to_string(),
+        side,
+        base_asset: base_asset.to_string(),
+        quote_asset: quote_asset.to_string(),
+        size,
+        expires_at: Utc::now() + Duration::seconds(RFQ_TTL_SECS),
+    }
+}
+
+pub fn quote(request: &QuoteRequest, quoter: &str, price: f64, keys: &EndpointKeys) -> Quote {
+    let mut quote = Quote {
+        quote_id: uuid::Uuid::new_v4().to_string(),
+        request: request.clone(),
+        quoter: quoter.to_string(),
+        price,
+        expires_at: Utc::now() + Duration::seconds(QUOTE_TTL_SECS),
+        signature: String::new(),
+        public_key: keys.public_key_hex(),
+    };
+    quote.signature = keys.sign(&format!("quote {}", quote.quote_id), &quote.signing_bytes());
+    quote
+}
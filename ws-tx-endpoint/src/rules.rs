@@ -0,0 +1,120 @@
+use tx_core::rules::{Action, Condition, Trigger};
+use tx_core::{Money, Rule};
+
+use crate::ledger;
+
+/// Memos shown; older ones drop off.
+pub const MEMOS_SHOWN: usize = 20;
+
+/// The automation rules panel's form, as typed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RuleDraft {
+    pub name: String,
+    pub when: Trigger,
+    pub over: String,
+    pub under: String,
+    pub from: String,
+    pub trusted_only: bool,
+    /// `send-memo`, `accept` or `decline`.
+    pub then: String,
+    pub memo: String,
+}
+
+impl Default for RuleDraft {
+    fn default() -> Self {
+        RuleDraft {
+            name: String::new(),
+            when: Trigger::Received,
+            over: String::new(),
+            under: String::new(),
+            from: String::new(),
+            trusted_only: false,
+            then: "send-memo".to_string(),
+            memo: String::new(),
+        }
+    }
+}
+
+impl RuleDraft {
+    pub fn to_rule(&self) -> Result<Rule, String> {
+        let amount = |field: &str| -> Result<Option<Money>, String> {
+            match field.trim() {
+                "" => Ok(None),
+                value => ledger::parse_amount(value).map(Some).ok_or_else(|| format!("Invalid amount: {}", value)),
+            }
+        };
+        let mut conditions = Vec::new();
+        if let Some(amount) = amount(&self.over)? {
+            conditions.push(Condition::AmountOver { amount });
+        }
+        if let Some(amount) = amount(&self.under)? {
+            conditions.push(Condition::AmountUnder { amount });
+        }
+        if !self.from.trim().is_empty() {
+            conditions.push(Condition::From { peer: self.from.trim().to_string() });
+        }
+        if self.trusted_only {
+            conditions.push(Condition::Trusted);
+        }
+        let then = match self.then.as_str() {
+            "accept" => Action::Accept,
+            "decline" => Action::Decline,
+            _ => Action::SendMemo { text: self.memo.trim().to_string() },
+        };
+
+        let rule = Rule { name: self.name.trim().to_string(), enabled: true, when: self.when, conditions, then };
+        rule.validate().map_err(|e| e.to_string())?;
+        Ok(rule)
+    }
+}
+
+/// One line for the rules list.
+pub fn describe(rule: &Rule) -> String {
+    let mut parts = vec![match rule.when {
+        Trigger::Received => "on receiving".to_string(),
+        Trigger::SwapOffered => "on a swap offer".to_string(),
+    }];
+    parts.extend(rule.conditions.iter().map(|condition| match condition {
+        Condition::AmountOver { amount } => format!("over {}", amount),
+        Condition::AmountUnder { amount } => format!("under {}", amount),
+        Condition::From { peer } => format!("from {}", peer),
+        Condition::Trusted => "from a trusted peer".to_string(),
+    }));
+    let then = match &rule.then {
+        Action::SendMemo { text } => format!("send “{}”", text),
+        Action::Accept => "accept".to_string(),
+        Action::Decline => "decline".to_string(),
+    };
+    format!("{} → {}", parts.join(" · "), then)
+}
+
+/// Rules pasted as JSON, each checked.
+pub fn parse(json: &str) -> Result<Vec<Rule>, String> {
+    let rules: Vec<Rule> = serde_json::from_str(json).map_err(|e| format!("Invalid rules: {}", e))?;
+    for rule in &rules {
+        rule.validate().map_err(|e| format!("{}: {}", rule.name, e))?;
+    }
+    Ok(rules)
+}
+
+/// Rules stay in this browser; the gateway never sees them.
+pub fn load_cached(endpoint_id: &str) -> Vec<Rule> {
+    storage()
+        .and_then(|s| s.get_item(&cache_key(endpoint_id)).ok().flatten())
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+pub fn store_cached(endpoint_id: &str, rules: &[Rule]) {
+    if let (Some(storage), Ok(json)) = (storage(), serde_json::to_string(rules)) {
+        let _ = storage.set_item(&cache_key(endpoint_id), &json);
+    }
+}
+
+fn cache_key(endpoint_id: &str) -> String {
+    format!("rules:{}", endpoint_id)
+}
+
+fn storage() -> Option<web_sys::Storage> {
+    web_sys::window().and_then(|w| w.local_storage().ok().flatten())
+}
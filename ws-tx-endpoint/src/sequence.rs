@@ -0,0 +1,32 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::gateway_client;
+
+/// Hands out the per-sender sequence numbers the gateway uses to refuse
+/// double spends. Seeded from the gateway so several devices (or tabs)
+/// sharing an endpoint id don't reuse numbers; a refused send resyncs it.
+#[derive(Clone, Default)]
+pub struct SequenceAllocator {
+    last: Rc<Cell<u64>>,
+}
+
+impl SequenceAllocator {
+    pub fn next(&self) -> u64 {
+        let next = self.last.get() + 1;
+        self.last.set(next);
+        next
+    }
+
+    /// Accounts for a number handed out before a reload.
+    pub fn observe(&self, sequence: u64) {
+        self.last.set(self.last.get().max(sequence));
+    }
+
+    pub async fn sync(&self, endpoint_id: &str) {
+        match gateway_client::fetch_last_sequence(endpoint_id).await {
+            Ok(last) => self.last.set(self.last.get().max(last)),
+            Err(e) => web_sys::console::warn_1(&format!("Sequence sync failed: {}", e).into()),
+        }
+    }
+}
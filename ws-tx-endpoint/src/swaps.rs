@@ -0,0 +1,58 @@
+use chrono::{Duration, Utc};
+use tx_core::{Money, SwapCommitment, SwapLeg, SwapStatus, SwapTerms};
+
+use crate::crypto::EndpointKeys;
+
+/// How long a swap offer stays open before the gateway rolls it back.
+pub const SWAP_TTL_SECS: i64 = 60;
+
+/// How often pending swaps are checked with the gateway.
+pub const SWAP_POLL_MS: u32 = 2000;
+
+/// A swap this endpoint offered or was offered. `committed` once our own
+/// commitment is held by the gateway.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PendingSwap {
+    pub terms: SwapTerms,
+    pub status: SwapStatus,
+    pub committed: bool,
+}
+
+impl PendingSwap {
+    pub fn is_final(&self) -> bool {
+        matches!(self.status, SwapStatus::Settled | SwapStatus::RolledBack)
+    }
+}
+
+/// Terms for giving `give` to `peer` in exchange for `ask`, each leg in
+/// its amount's currency.
+pub fn propose(maker: &str, peer: &str, give: Money, ask: Money) -> SwapTerms {
+    SwapTerms {
+        swap_id: uuid::Uuid::new_v4().to_string(),
+        offer: SwapLeg {
+            from_endpoint: maker.to_string(),
+            to_endpoint: peer.to_string(),
+            asset: give.currency().to_string(),
+            amount: give,
+        },
+        ask: SwapLeg {
+            from_endpoint: peer.to_string(),
+            to_endpoint: maker.to_string(),
+            asset: ask.currency().to_string(),
+            amount: ask,
+        },
+        expires_at: Utc::now() + Duration::seconds(SWAP_TTL_SECS),
+    }
+}
+
+pub fn commit(terms: &SwapTerms, party: &str, keys: &EndpointKeys) -> SwapCommitment {
+    SwapCommitment {
+        terms: terms.clone(),
+        party: party.to_string(),
+        signature: keys.sign(
+            &format!("commitment to swap {}", terms.swap_id),
+            &SwapCommitment::signing_bytes(terms, party),
+        ),
+        public_key: keys.public_key_hex(),
+    }
+}
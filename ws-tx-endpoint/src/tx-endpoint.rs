@@ -1,45 +1,187 @@
-use serde::{Deserialize, Serialize};
-use crate::Transaction;
+use chrono::Utc;
+use std::collections::HashSet;
+use crate::crypto::{self, EndpointKeys};
+use crate::ledger::{Ledger, OPENING_BALANCE};
+use crate::outbox::Outbox;
+use crate::{Transaction, TransactionKind, TransactionStatus};
+use tx_core::Money;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone)]
 pub struct TxEndpoint {
     pub id: String,
-    pub balance: f64,
+    /// Every movement of funds; the balance is derived from it.
+    pub ledger: Ledger,
     pub transaction_count: u64,
+    /// Every transaction applied, whether or not it moved our funds. The
+    /// same one can arrive over the socket, a data channel and recovery.
+    applied: HashSet<String>,
+    pub keys: EndpointKeys,
+    /// Sends waiting for the connection to come back.
+    pub outbox: Outbox,
 }
 
 impl TxEndpoint {
     pub fn new(id: &str) -> Self {
         Self {
             id: id.to_string(),
-            balance: 1000.0, // Starting balance
+            ledger: Ledger::opened(id, OPENING_BALANCE),
             transaction_count: 0,
+            applied: HashSet::new(),
+            keys: EndpointKeys::load_or_generate(id),
+            outbox: Outbox::default(),
         }
     }
 
+    pub fn balance(&self) -> f64 {
+        self.ledger.balance(&self.id)
+    }
+
+    pub fn has_applied(&self, tx_id: &str) -> bool {
+        self.applied.contains(tx_id)
+    }
+
+    /// Books a transaction. One that was applied before, or that already
+    /// failed or expired, is rejected and changes nothing.
     pub fn process_transaction(&mut self, tx: &Transaction) -> Result<(), String> {
-        if tx.from == self.id {
-            if self.balance < tx.amount {
-                return Err("Insufficient balance".to_string());
-            }
-            self.balance -= tx.amount;
-        } else if tx.to == self.id {
-            self.balance += tx.amount;
+        if self.has_applied(&tx.id) {
+            return Err(format!("Transaction {} was already applied", tx.id));
+        }
+        if matches!(tx.status, TransactionStatus::Failed | TransactionStatus::Expired) {
+            return Err(format!("Transaction {} is {} and can't be applied", tx.id, tx.status));
         }
-        
+        if tx.from_endpoint == self.id && self.balance() < tx.amount.to_major() {
+            return Err("Insufficient balance".to_string());
+        }
+        if tx.from_endpoint == self.id || tx.to_endpoint == self.id {
+            self.ledger.post(&tx.id, &tx.from_endpoint, &tx.to_endpoint, &tx.amount);
+        }
+
+        self.applied.insert(tx.id.clone());
         self.transaction_count += 1;
         Ok(())
     }
 
-    pub fn create_transaction(&self, to: &str, amount: f64) -> Transaction {
-        Transaction {
+    /// Sets funds aside for a payment channel or swap, under its id.
+    pub fn reserve(&mut self, reference: &str, amount: f64) -> Result<(), String> {
+        if self.ledger.is_posted(&format!("reserve:{}", reference)) {
+            return Ok(());
+        }
+        if self.balance() < amount {
+            return Err("Insufficient balance".to_string());
+        }
+        self.ledger.post_major(&format!("reserve:{}", reference), &self.id, &reserve_account(reference), amount);
+        Ok(())
+    }
+
+    /// Returns reserved funds that weren't paid out.
+    pub fn release(&mut self, reference: &str, amount: f64) {
+        self.ledger.post_major(&format!("release:{}", reference), &reserve_account(reference), &self.id, amount);
+    }
+
+    /// Books a payment made out of reserved funds.
+    pub fn pay_from_reserve(&mut self, reference: &str, tx: &Transaction) {
+        if self.applied.insert(tx.id.clone()) {
+            self.ledger.post(&tx.id, &reserve_account(reference), &tx.to_endpoint, &tx.amount);
+            self.transaction_count += 1;
+        }
+    }
+
+    /// Returns the funds of an outgoing transaction the receiver rejected.
+    pub fn refund_transaction(&mut self, tx: &Transaction) {
+        if tx.from_endpoint == self.id {
+            self.ledger.post(&format!("refund:{}", tx.id), &tx.to_endpoint, &self.id, &tx.amount);
+        }
+    }
+
+    /// Replaces the ledger with one replayed from the gateway's record of
+    /// this endpoint's transactions. Funds still reserved, and sends still
+    /// in the outbox, aren't on the gateway yet and are carried over.
+    pub fn rebuild_from_history(&mut self, recorded: &[Transaction]) {
+        let mut history: Vec<&Transaction> = recorded
+            .iter()
+            // Netted payments are on record through their net transfer
+            .filter(|tx| !matches!(tx.status, TransactionStatus::Failed | TransactionStatus::Expired) && tx.kind != TransactionKind::Netted)
+            .collect();
+        history.sort_by_key(|tx| tx.timestamp);
+
+        let mut ledger = Ledger::opened(&self.id, OPENING_BALANCE);
+        for tx in &history {
+            ledger.post(&tx.id, &tx.from_endpoint, &tx.to_endpoint, &tx.amount);
+        }
+        for (reference, amount) in self.ledger.open_reserves() {
+            ledger.post(&format!("reserve:{}", reference), &self.id, &reserve_account(&reference), &amount);
+        }
+        for queued in self.outbox.iter() {
+            ledger.post(&queued.tx.id, &queued.tx.from_endpoint, &queued.tx.to_endpoint, &queued.tx.amount);
+        }
+
+        self.applied = recorded
+            .iter()
+            .map(|tx| tx.id.clone())
+            .chain(self.outbox.iter().map(|queued| queued.tx.id.clone()))
+            .collect();
+        self.transaction_count = history.len() as u64;
+        self.ledger = ledger;
+    }
+
+    /// Takes a signed transaction out of the balance and holds it until it
+    /// can be sent.
+    pub fn enqueue(&mut self, tx: Transaction) -> Result<(), String> {
+        self.process_transaction(&tx)?;
+        self.outbox.push(tx);
+        self.outbox.store(&self.id);
+        Ok(())
+    }
+
+    /// Takes back the outbox persisted by an earlier session. Its
+    /// transactions left a balance that has since been reset, so they're
+    /// deducted again.
+    pub fn restore_outbox(&mut self, restored: Outbox) {
+        for queued in restored.iter() {
+            if !self.outbox.iter().any(|q| q.tx.id == queued.tx.id) {
+                let _ = self.process_transaction(&queued.tx);
+            }
+        }
+        self.outbox.restore(restored);
+        self.outbox.store(&self.id);
+    }
+
+    pub fn dequeue(&mut self, tx_id: &str) -> Option<Transaction> {
+        let queued = self.outbox.remove(tx_id)?;
+        self.outbox.store(&self.id);
+        Some(queued.tx)
+    }
+
+    pub fn mark_recorded(&mut self, tx: Transaction) {
+        self.outbox.mark_recorded(tx);
+        self.outbox.store(&self.id);
+    }
+
+    pub fn record_send_failure(&mut self, tx_id: &str, error: String) {
+        self.outbox.record_failure(tx_id, error);
+        self.outbox.store(&self.id);
+    }
+
+    pub fn create_transaction(&self, to: &str, amount: Money) -> Transaction {
+        let mut tx = Transaction {
             id: uuid::Uuid::new_v4().to_string(),
-            from: self.id.clone(),
-            to: to.to_string(),
+            from_endpoint: self.id.clone(),
+            to_endpoint: to.to_string(),
             amount,
-            timestamp: js_sys::Date::now() as u64,
-            signature: format!("sig_{}", self.transaction_count),
-            status: "pending".to_string(),
-        }
+            timestamp: Utc::now(),
+            signature: String::new(),
+            status: TransactionStatus::Pending,
+            kind: TransactionKind::Transfer,
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: None,
+            public_key: Some(self.keys.public_key_hex()),
+        };
+        tx.signature = crypto::sign_transaction(&tx, &self.keys);
+        tx
     }
 }
+
+fn reserve_account(reference: &str) -> String {
+    format!("reserved:{}", reference)
+}
@@ -0,0 +1,135 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use futures::channel::oneshot;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{MessageEvent, Worker, WorkerOptions, WorkerType};
+use crate::crypto::{self, EndpointKeys};
+use crate::Transaction;
+
+const WORKER_SCRIPT: &str = "./tx-worker.js";
+
+type PendingMap = HashMap<u32, oneshot::Sender<Result<JsValue, JsValue>>>;
+
+/// Runs signing and verification in a dedicated Web Worker. Falls back to
+/// running inline when workers are unavailable (e.g. file:// origins).
+#[derive(Clone)]
+pub struct TxWorker {
+    worker: Option<Worker>,
+    keys: EndpointKeys,
+    next_id: Rc<Cell<u32>>,
+    pending: Rc<RefCell<PendingMap>>,
+}
+
+impl TxWorker {
+    pub fn new(keys: EndpointKeys) -> Self {
+        let pending: Rc<RefCell<PendingMap>> = Rc::new(RefCell::new(HashMap::new()));
+
+        let mut options = WorkerOptions::new();
+        options.type_(WorkerType::Module);
+
+        let worker = match Worker::new_with_options(WORKER_SCRIPT, &options) {
+            Ok(worker) => {
+                let pending = pending.clone();
+                let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
+                    let data = e.data();
+                    let id = js_sys::Reflect::get(&data, &"id".into())
+                        .ok()
+                        .and_then(|v| v.as_f64())
+                        .map(|v| v as u32);
+                    let ok = js_sys::Reflect::get(&data, &"ok".into())
+                        .ok()
+                        .and_then(|v| v.as_bool())
+                        .unwrap_or(false);
+
+                    if let Some(sender) = id.and_then(|id| pending.borrow_mut().remove(&id)) {
+                        let result = if ok {
+                            Ok(js_sys::Reflect::get(&data, &"result".into()).unwrap_or(JsValue::NULL))
+                        } else {
+                            Err(js_sys::Reflect::get(&data, &"error".into())
+                                .unwrap_or_else(|_| JsValue::from_str("Worker error")))
+                        };
+                        let _ = sender.send(result);
+                    }
+                }) as Box<dyn FnMut(_)>);
+
+                worker.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+                onmessage_callback.forget();
+                Some(worker)
+            }
+            Err(e) => {
+                web_sys::console::warn_1(&format!("Web Worker unavailable, running crypto inline: {:?}", e).into());
+                None
+            }
+        };
+
+        Self {
+            worker,
+            keys,
+            next_id: Rc::new(Cell::new(0)),
+            pending,
+        }
+    }
+
+    pub fn keys(&self) -> &EndpointKeys {
+        &self.keys
+    }
+
+    pub async fn sign(&self, tx: &Transaction) -> Result<String, JsValue> {
+        if self.worker.is_none() {
+            return Ok(crypto::sign_transaction(tx, &self.keys));
+        }
+
+        let signature = self
+            .call("sign", tx)
+            .await?
+            .as_string()
+            .ok_or_else(|| JsValue::from_str("Worker returned a non-string signature"))?;
+        // The worker has no localStorage to log it in
+        self.keys.log_signature(&format!("transaction {}", tx.id));
+        Ok(signature)
+    }
+
+    pub async fn verify(&self, tx: &Transaction) -> Result<bool, JsValue> {
+        if self.worker.is_none() {
+            return Ok(crypto::verify_transaction(tx));
+        }
+
+        self.call("verify", tx)
+            .await?
+            .as_bool()
+            .ok_or_else(|| JsValue::from_str("Worker returned a non-boolean result"))
+    }
+
+    async fn call(&self, op: &str, tx: &Transaction) -> Result<JsValue, JsValue> {
+        let worker = self.worker.as_ref().ok_or_else(|| JsValue::from_str("No worker"))?;
+
+        let id = self.next_id.get();
+        self.next_id.set(id.wrapping_add(1));
+
+        let payload = serde_json::to_string(tx)
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))?;
+
+        let request = js_sys::Object::new();
+        js_sys::Reflect::set(&request, &"id".into(), &JsValue::from(id))?;
+        js_sys::Reflect::set(&request, &"op".into(), &JsValue::from_str(op))?;
+        js_sys::Reflect::set(&request, &"payload".into(), &JsValue::from_str(&payload))?;
+        if op == "sign" {
+            js_sys::Reflect::set(&request, &"key".into(), &JsValue::from_str(&self.keys.secret_hex()))?;
+        }
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.borrow_mut().insert(id, sender);
+
+        if let Err(e) = worker.post_message(&request) {
+            self.pending.borrow_mut().remove(&id);
+            return Err(e);
+        }
+
+        receiver
+            .await
+            .map_err(|_| JsValue::from_str("Worker request cancelled"))?
+    }
+}
@@ -0,0 +1,455 @@
+//! Compact endpoint UI for embedding in other sites: a send form and the
+//! last few transactions, nothing else. `widget.html` loads it in an
+//! iframe:
+//!
+//! ```html
+//! <iframe src="https://relayer.example/widget.html?id=shop-42&origin=https://shop.example"></iframe>
+//! ```
+//!
+//! It runs the same signaling, signing and outbox code as the full app,
+//! so payments made from a widget are indistinguishable from any other.
+//!
+//! Configuration comes from the query string (`room`, `theme`, `recipient`)
+//! and can be changed afterwards by the embedding page with `postMessage`:
+//!
+//! ```js
+//! frame.contentWindow.postMessage(
+//!   { type: 'relayer-widget:configure', room: 'checkout', theme: 'dark', recipient: 'merchant-7' },
+//!   'https://relayer.example',
+//! );
+//! ```
+//!
+//! Only messages from the origin named by `?origin=` are accepted, and
+//! only that origin is told about the widget's `relayer-widget:ready` and
+//! `relayer-widget:sent` (`{ id, to, amount }`); without it the widget
+//! keeps to its query string. A `recipient` locks the form to that peer.
+
+use chrono::Utc;
+use dioxus::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+use wasm_bindgen::prelude::*;
+
+use crate::channels::OpenChannel;
+use crate::counterparties::CounterpartyLists;
+use crate::crypto;
+use crate::ledger;
+use crate::outbox::{self, Outbox};
+use crate::rfq::RfqBook;
+use crate::rules;
+use crate::sequence::SequenceAllocator;
+use crate::swaps::PendingSwap;
+use crate::tx_endpoint::TxEndpoint;
+use crate::tx_worker::TxWorker;
+use crate::websocket_connection::{WebSocketConnection, DEFAULT_ROOM};
+use crate::{
+    delivery_state, flush_outbox, handle_signaling_message, send_signed_transaction, Transaction, TransactionKind,
+    TransactionStatus,
+};
+use tx_core::{Money, RoomInfo};
+
+const MESSAGE_PREFIX: &str = "relayer-widget:";
+
+/// How many transactions the widget lists.
+const RECENT: usize = 5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    #[default]
+    Light,
+    Dark,
+}
+
+impl Theme {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "light" => Some(Theme::Light),
+            "dark" => Some(Theme::Dark),
+            _ => None,
+        }
+    }
+
+    /// (background, text, muted, border)
+    fn colors(&self) -> (&'static str, &'static str, &'static str, &'static str) {
+        match self {
+            Theme::Light => ("#ffffff", "#212529", "#6c757d", "#dee2e6"),
+            Theme::Dark => ("#1e1f24", "#f1f3f5", "#adb5bd", "#373a40"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WidgetConfig {
+    pub room: Option<String>,
+    pub theme: Theme,
+    /// Locks the form to one payee.
+    pub recipient: Option<String>,
+    /// The embedding page's origin, the only one we take messages from.
+    pub parent_origin: Option<String>,
+}
+
+/// A `relayer-widget:configure` message; fields left out are unchanged.
+#[derive(Deserialize)]
+struct Configure {
+    #[serde(rename = "type")]
+    message_type: String,
+    room: Option<String>,
+    theme: Option<Theme>,
+    recipient: Option<String>,
+}
+
+impl WidgetConfig {
+    pub fn from_query(search: &str) -> Self {
+        let Ok(params) = web_sys::UrlSearchParams::new_with_str(search) else {
+            return WidgetConfig::default();
+        };
+        let non_empty = |name: &str| params.get(name).filter(|value| !value.is_empty());
+        WidgetConfig {
+            room: non_empty("room"),
+            theme: non_empty("theme").and_then(|theme| Theme::parse(&theme)).unwrap_or_default(),
+            recipient: non_empty("recipient"),
+            parent_origin: non_empty("origin"),
+        }
+    }
+
+    /// Applies `event` if it is a configure message from the parent
+    /// origin, returning whether anything changed.
+    fn apply(&mut self, event: &web_sys::MessageEvent) -> bool {
+        if self.parent_origin.as_deref() != Some(event.origin().as_str()) {
+            return false;
+        }
+        let configure = js_sys::JSON::stringify(&event.data())
+            .ok()
+            .and_then(|json| json.as_string())
+            .and_then(|json| serde_json::from_str::<Configure>(&json).ok());
+        let Some(configure) = configure.filter(|c| c.message_type == format!("{}configure", MESSAGE_PREFIX)) else {
+            return false;
+        };
+        let before = self.clone();
+        if let Some(room) = configure.room.filter(|room| !room.is_empty()) {
+            self.room = Some(room);
+        }
+        if let Some(theme) = configure.theme {
+            self.theme = theme;
+        }
+        if let Some(recipient) = configure.recipient {
+            // An empty recipient unlocks the form
+            self.recipient = (!recipient.is_empty()).then_some(recipient);
+        }
+        *self != before
+    }
+
+    /// Tells the embedding page `event` happened, if we know its origin.
+    fn notify(&self, event: &str, detail: serde_json::Value) {
+        let Some(origin) = &self.parent_origin else {
+            return;
+        };
+        let Some(parent) = web_sys::window().and_then(|w| w.parent().ok().flatten()) else {
+            return;
+        };
+        let mut message = serde_json::json!({ "type": format!("{}{}", MESSAGE_PREFIX, event) });
+        if let (Some(message), serde_json::Value::Object(detail)) = (message.as_object_mut(), detail) {
+            message.extend(detail);
+        }
+        match js_sys::JSON::parse(&message.to_string()) {
+            Ok(message) => {
+                if let Err(e) = parent.post_message(&message, origin) {
+                    web_sys::console::warn_1(&e);
+                }
+            }
+            Err(e) => web_sys::console::warn_1(&e),
+        }
+    }
+}
+
+pub fn widget_app(cx: Scope) -> Element {
+    let search = web_sys::window().and_then(|w| w.location().search().ok()).unwrap_or_default();
+    let config = use_state(cx, || WidgetConfig::from_query(&search));
+    let endpoint_id = use_state(cx, || {
+        web_sys::UrlSearchParams::new_with_str(&search)
+            .ok()
+            .and_then(|params| params.get("id"))
+            .unwrap_or_else(|| "endpoint-1".to_string())
+    });
+
+    let tx_endpoint = use_state(cx, || TxEndpoint::new(endpoint_id.get()));
+    let connection = use_state(cx, WebSocketConnection::new);
+    let tx_worker = use_state(cx, || TxWorker::new(tx_endpoint.keys.clone()));
+    let sequence = use_state(cx, SequenceAllocator::default);
+    let transactions = use_state(cx, HashMap::<String, Transaction>::new);
+    let connected_peers = use_state(cx, Vec::<String>::new);
+    let connection_status = use_state(cx, || "Connecting".to_string());
+    let error_message = use_state(cx, String::new);
+    let counterparty_lists = use_state(cx, || CounterpartyLists::load_cached(endpoint_id.get()));
+    let recipient_input = use_state(cx, String::new);
+    let amount_input = use_state(cx, String::new);
+    // Not shown, but the shared message handler keeps them
+    let rooms = use_state(cx, Vec::<RoomInfo>::new);
+    let held_transactions = use_state(cx, HashSet::<String>::new);
+    let channels = use_state(cx, HashMap::<String, OpenChannel>::new);
+    let swaps = use_state(cx, HashMap::<String, PendingSwap>::new);
+    let rfq_book = use_state(cx, RfqBook::default);
+    // The endpoint's rules run here too; memos aren't shown
+    let automation_rules = use_state(cx, || rules::load_cached(endpoint_id.get()));
+    let memos = use_state(cx, Vec::<(String, String)>::new);
+
+    // Connect to the configured room and take configuration from the
+    // embedding page
+    use_effect(cx, (), {
+        let config = config.clone();
+        let endpoint_id = endpoint_id.get().clone();
+        let connection = connection.clone();
+        let connection_status = connection_status.clone();
+        let connected_peers = connected_peers.clone();
+        let rooms = rooms.clone();
+        let transactions = transactions.clone();
+        let held_transactions = held_transactions.clone();
+        let channels = channels.clone();
+        let swaps = swaps.clone();
+        let rfq_book = rfq_book.clone();
+        let tx_endpoint = tx_endpoint.clone();
+        let error_message = error_message.clone();
+        let tx_worker = tx_worker.get().clone();
+        let counterparty_lists = counterparty_lists.clone();
+        let automation_rules = automation_rules.clone();
+        let memos = memos.clone();
+        let sequence = sequence.get().clone();
+
+        move |_| {
+            async move {
+                sequence.sync(&endpoint_id).await;
+
+                let result = connection.with_mut(|conn| {
+                    conn.register_plugin(crypto::group_encryption(&endpoint_id, tx_worker.keys()));
+                    conn.register_plugin(crypto::peer_encryption(&endpoint_id, tx_worker.keys()));
+                    conn.set_room(config.get().room.as_deref().unwrap_or(DEFAULT_ROOM));
+                    conn.connect(
+                        &endpoint_id,
+                        Box::new({
+                            let connection = connection.clone();
+                            let endpoint_id = endpoint_id.clone();
+                            move |msg| {
+                                handle_signaling_message(
+                                    msg,
+                                    &endpoint_id,
+                                    &connection_status,
+                                    &connected_peers,
+                                    &rooms,
+                                    &transactions,
+                                    &held_transactions,
+                                    &channels,
+                                    &swaps,
+                                    &rfq_book,
+                                    &tx_endpoint,
+                                    &error_message,
+                                    &tx_worker,
+                                    &counterparty_lists,
+                                    &automation_rules,
+                                    &memos,
+                                    &connection,
+                                );
+                            }
+                        }),
+                    )
+                });
+                if let Err(e) = result {
+                    error_message.set(format!("Connection failed: {:?}", e));
+                }
+
+                let on_message = Closure::<dyn FnMut(web_sys::MessageEvent)>::new({
+                    let config = config.clone();
+                    let connection = connection.clone();
+                    let error_message = error_message.clone();
+                    move |event: web_sys::MessageEvent| {
+                        let mut updated = config.get().clone();
+                        if !updated.apply(&event) {
+                            return;
+                        }
+                        let room = updated.room.clone().unwrap_or_else(|| DEFAULT_ROOM.to_string());
+                        config.set(updated);
+                        let mut switched = Ok(());
+                        connection.with_mut(|conn| switched = conn.switch_room(&room));
+                        if let Err(e) = switched {
+                            error_message.set(format!("Could not switch rooms: {:?}", e));
+                        }
+                    }
+                });
+                if let Some(window) = web_sys::window() {
+                    let _ = window.add_event_listener_with_callback("message", on_message.as_ref().unchecked_ref());
+                }
+                // Listens for as long as the frame is open
+                on_message.forget();
+
+                config.get().notify("ready", serde_json::json!({ "endpointId": endpoint_id }));
+            }
+        }
+    });
+
+    // The same outbox as the full app, so a send made while offline goes
+    // out on reconnect
+    use_effect(cx, (), {
+        let endpoint_id = endpoint_id.get().clone();
+        let sequence = sequence.get().clone();
+        let tx_endpoint = tx_endpoint.clone();
+        let transactions = transactions.clone();
+        let connection = connection.clone();
+        let error_message = error_message.clone();
+
+        move |_| {
+            async move {
+                let restored = Outbox::load(&endpoint_id).await;
+                transactions.with_mut(|txs| {
+                    for queued in restored.iter() {
+                        if let Some(seq) = queued.tx.sequence {
+                            sequence.observe(seq);
+                        }
+                        txs.entry(queued.tx.id.clone()).or_insert_with(|| queued.tx.clone());
+                    }
+                });
+                tx_endpoint.with_mut(|ep| ep.restore_outbox(restored));
+
+                loop {
+                    flush_outbox(&sequence, &tx_endpoint, &transactions, &connection, &error_message).await;
+                    gloo_timers::future::TimeoutFuture::new(outbox::OUTBOX_RETRY_MS).await;
+                }
+            }
+        }
+    });
+
+    let (background, text, muted, border) = config.theme.colors();
+    let locked_recipient = config.recipient.clone();
+    let mut recent: Vec<&Transaction> = transactions.values().collect();
+    recent.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    recent.truncate(RECENT);
+
+    let send = move || {
+        let to_peer = locked_recipient.clone().unwrap_or_else(|| recipient_input.get().trim().to_string());
+        let amount = ledger::parse_amount(amount_input.get());
+        if to_peer.is_empty() || to_peer == *endpoint_id.get() {
+            error_message.set("Choose who to pay".to_string());
+            return;
+        }
+        if !counterparty_lists.accepts(&to_peer) {
+            error_message.set(format!("{} is blocked", to_peer));
+            return;
+        }
+        let usable = |amount: &Money| !amount.is_negative() && !amount.is_zero() && amount.to_major() <= tx_endpoint.balance();
+        let Some(amount) = amount.filter(usable) else {
+            error_message.set("Invalid amount or insufficient balance".to_string());
+            return;
+        };
+
+        let tx = Transaction {
+            id: Uuid::new_v4().to_string(),
+            from_endpoint: endpoint_id.get().clone(),
+            to_endpoint: to_peer,
+            amount,
+            timestamp: Utc::now(),
+            signature: String::new(),
+            status: TransactionStatus::Pending,
+            kind: TransactionKind::Transfer,
+            risk_score: None,
+            parent_tx_id: None,
+            sequence: Some(sequence.get().next()),
+            public_key: None,
+        };
+        config.get().notify("sent", serde_json::json!({ "id": tx.id, "to": tx.to_endpoint, "amount": tx.amount.to_major() }));
+        send_signed_transaction(
+            tx,
+            sequence.get().clone(),
+            tx_worker.get().clone(),
+            tx_endpoint.clone(),
+            transactions.clone(),
+            connection.clone(),
+            error_message.clone(),
+        );
+        amount_input.set(String::new());
+        error_message.set(String::new());
+    };
+
+    render! {
+        div {
+            class: "tx-widget",
+            style: "background: {background}; color: {text}; border: 1px solid {border}; border-radius: 10px; padding: 12px; font-family: 'Segoe UI', system-ui, sans-serif; font-size: 0.9rem;",
+
+            div {
+                style: "display: flex; justify-content: space-between; margin-bottom: 8px;",
+                strong { "Balance: ${tx_endpoint.balance():.2}" }
+                span {
+                    style: "color: {muted};",
+                    "{connection_status}"
+                }
+            }
+
+            div {
+                style: "display: flex; gap: 6px;",
+                if let Some(recipient) = &config.recipient {
+                    rsx! {
+                        span {
+                            style: "flex: 1; padding: 6px; border: 1px solid {border}; border-radius: 6px; color: {muted};",
+                            "To {recipient}"
+                        }
+                    }
+                } else {
+                    rsx! {
+                        input {
+                            r#type: "text",
+                            placeholder: "Recipient",
+                            list: "widget-peers",
+                            value: "{recipient_input}",
+                            style: "flex: 1; min-width: 0; padding: 6px; border: 1px solid {border}; border-radius: 6px; background: {background}; color: {text};",
+                            oninput: move |evt| recipient_input.set(evt.value.clone()),
+                        }
+                        datalist {
+                            id: "widget-peers",
+                            connected_peers.iter().filter(|peer| !counterparty_lists.is_blocked(peer)).map(|peer| render! {
+                                option { key: "{peer}", value: "{peer}" }
+                            })
+                        }
+                    }
+                }
+                input {
+                    r#type: "number",
+                    placeholder: "Amount",
+                    step: "0.01",
+                    min: "0.01",
+                    value: "{amount_input}",
+                    style: "width: 90px; padding: 6px; border: 1px solid {border}; border-radius: 6px; background: {background}; color: {text};",
+                    oninput: move |evt| amount_input.set(evt.value.clone()),
+                }
+                button {
+                    style: "background: #667eea; color: white; border: none; padding: 6px 12px; border-radius: 6px; cursor: pointer; font-weight: 600;",
+                    onclick: move |_| send(),
+                    "Pay"
+                }
+            }
+
+            if !error_message.is_empty() {
+                p {
+                    style: "margin: 6px 0 0 0; color: #dc3545;",
+                    "{error_message}"
+                }
+            }
+
+            ul {
+                style: "list-style: none; margin: 10px 0 0 0; padding: 0;",
+                recent.into_iter().map(|tx| {
+                    let sent = tx.from_endpoint == *endpoint_id.get();
+                    let counterparty = if sent { &tx.to_endpoint } else { &tx.from_endpoint };
+                    let state = if sent && tx.kind == TransactionKind::Transfer { delivery_state(tx) } else { tx.status.to_string() };
+                    render! {
+                        li {
+                            key: "{tx.id}",
+                            style: "display: flex; justify-content: space-between; padding: 4px 0; border-top: 1px solid {border};",
+                            span { if sent { "📤 " } else { "📥 " } "{counterparty}" }
+                            span { "{tx.amount}" }
+                            span { style: "color: {muted}; font-size: 0.8rem;", "{state}" }
+                        }
+                    }
+                })
+            }
+        }
+    }
+}
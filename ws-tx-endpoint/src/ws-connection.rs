@@ -1,151 +1,654 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use web_sys::{WebSocket, MessageEvent, CloseEvent, ErrorEvent};
+use std::collections::HashMap;
+use tx_core::{AckResult, Capabilities, Capability, ChannelUpdate, Plugin, Plugins, Quote, QuoteRequest, SwapCommitment};
 use crate::{Transaction, SignalingMessage};
 
+/// Gap-resync protocol. Room broadcasts carry a sequence number; a gap, or
+/// a `lagged` notice from the server after it skipped broadcasts to us,
+/// triggers a `resync` from the last contiguous one. Broadcasts out of
+/// order are dropped until the replay catches up.
+#[derive(Default)]
+struct BroadcastCursor {
+    last_seq: Cell<u64>,
+    resyncing: Cell<bool>,
+}
+
+impl BroadcastCursor {
+    fn request_resync(&self, ws: &WebSocket) {
+        if self.resyncing.replace(true) {
+            return;
+        }
+        let resync = serde_json::json!({ "type": "resync", "afterSeq": self.last_seq.get() });
+        let _ = ws.send_with_str(&resync.to_string());
+        web_sys::console::log_1(&format!("Resyncing after broadcast {}", self.last_seq.get()).into());
+    }
+
+    /// Whether `msg` should reach the application.
+    fn admit(&self, msg: &SignalingMessage, ws: &WebSocket) -> bool {
+        match msg.message_type.as_str() {
+            "transaction-broadcast" => {
+                let Some(seq) = msg.seq else {
+                    return true;
+                };
+                let last = self.last_seq.get();
+                // The first broadcast on a connection sets the baseline
+                if last == 0 || seq == last + 1 {
+                    self.last_seq.set(seq);
+                    true
+                } else {
+                    if seq > last + 1 {
+                        self.request_resync(ws);
+                    }
+                    false
+                }
+            }
+            "lagged" => {
+                // Supersedes any replay the server cut short
+                self.resyncing.set(false);
+                self.request_resync(ws);
+                true
+            }
+            "resync-complete" => {
+                self.resyncing.set(false);
+                // Whatever the server could no longer replay is skipped here
+                // and recovered from the gateway by the application
+                if let Some(seq) = msg.seq {
+                    if seq > self.last_seq.get() {
+                        self.last_seq.set(seq);
+                    }
+                }
+                true
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Reconnection schedule after the socket closes unexpectedly: the delay
+/// doubles from `base_delay_ms` up to `max_delay_ms`, randomized to half
+/// to full length so peers dropped together don't return together, and
+/// reconnecting stops after `max_attempts` failures in a row.
+#[derive(Clone, Copy, Debug)]
+pub struct ReconnectPolicy {
+    pub base_delay_ms: u32,
+    pub max_delay_ms: u32,
+    pub max_attempts: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+            max_attempts: 10,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    fn delay_ms(&self, attempt: u32) -> u32 {
+        let exponential = self
+            .base_delay_ms
+            .saturating_mul(1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX))
+            .min(self.max_delay_ms);
+        let jitter = js_sys::Math::random() * f64::from(exponential) / 2.0;
+        exponential / 2 + jitter as u32
+    }
+}
+
+/// Reported through the `on_reconnect` callback.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReconnectEvent {
+    /// The socket closed; attempt `attempt` starts after `delay_ms`.
+    Reconnecting { attempt: u32, delay_ms: u32 },
+    /// A new socket is open and has rejoined the room.
+    Reconnected { attempts: u32 },
+    /// `attempts` reconnects failed in a row; no more will be made.
+    GaveUp { attempts: u32 },
+}
+
+/// State shared with the socket callbacks, which outlive any borrow of the
+/// connection.
+struct Link {
+    ws: RefCell<Option<WebSocket>>,
+    url: RefCell<String>,
+    join_message: RefCell<serde_json::Value>,
+    message_handler: RefCell<Option<Rc<dyn Fn(SignalingMessage)>>>,
+    on_reconnect: RefCell<Option<Rc<dyn Fn(ReconnectEvent)>>>,
+    policy: Cell<ReconnectPolicy>,
+    /// Failed attempts since the last successful open.
+    attempt: Cell<u32>,
+    /// The room's batch window from the last `room-joined`, if it settles
+    /// in batches.
+    batch_window_ms: Cell<Option<u64>>,
+    /// Whether the room relays only sealed broadcasts, from the last
+    /// `room-joined`.
+    room_encrypted: Cell<bool>,
+    /// Ask for new rooms to be opened end-to-end encrypted.
+    open_encrypted: Cell<bool>,
+    /// Room joined on connect and on every reconnect.
+    room_id: RefCell<String>,
+    /// What the server agreed to in answer to our `hello`.
+    negotiated: RefCell<Capabilities>,
+    /// What each peer in the room advertised.
+    peer_capabilities: RefCell<HashMap<String, Capabilities>>,
+    /// Run on everything we send and receive after the handshake.
+    plugins: RefCell<Plugins>,
+}
+
+impl Link {
+    fn notify(&self, event: ReconnectEvent) {
+        let callback = self.on_reconnect.borrow().clone();
+        if let Some(callback) = callback {
+            callback(event);
+        }
+    }
+
+    fn schedule_reconnect(self: &Rc<Self>) {
+        let attempt = self.attempt.get() + 1;
+        let policy = self.policy.get();
+        if attempt > policy.max_attempts {
+            web_sys::console::error_1(&format!("Giving up after {} reconnect attempts", attempt - 1).into());
+            self.notify(ReconnectEvent::GaveUp { attempts: attempt - 1 });
+            return;
+        }
+        self.attempt.set(attempt);
+
+        let delay_ms = policy.delay_ms(attempt);
+        web_sys::console::log_1(&format!("Reconnecting in {}ms (attempt {})", delay_ms, attempt).into());
+        self.notify(ReconnectEvent::Reconnecting { attempt, delay_ms });
+
+        let link = self.clone();
+        gloo_timers::callback::Timeout::new(delay_ms, move || {
+            let url = link.url.borrow().clone();
+            let join_message = link.join_message.borrow().clone();
+            if let Err(e) = open(&link, &url, join_message) {
+                web_sys::console::error_1(&e);
+                link.schedule_reconnect();
+            }
+        })
+        .forget();
+    }
+
+    /// Follows the handshake and the room's membership.
+    fn track_capabilities(&self, msg: &SignalingMessage) {
+        match msg.message_type.as_str() {
+            "negotiated" => *self.negotiated.borrow_mut() = Capabilities::from_message(msg),
+            "room-joined" => {
+                let mut peers = self.peer_capabilities.borrow_mut();
+                peers.clear();
+                for (peer_id, features) in msg.peer_features.iter().flatten() {
+                    peers.insert(peer_id.clone(), Capabilities::from_names(tx_core::PROTOCOL_VERSION, features));
+                }
+            }
+            "peer-joined" => {
+                if let Some(peer_id) = &msg.peer_id {
+                    self.peer_capabilities.borrow_mut().insert(peer_id.clone(), Capabilities::from_message(msg));
+                }
+            }
+            "peer-left" => {
+                if let Some(peer_id) = &msg.peer_id {
+                    self.peer_capabilities.borrow_mut().remove(peer_id);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Writes what the plugins queued, e.g. key exchanges, as it is.
+    fn send_plugin_messages(&self, ws: &WebSocket) {
+        let outgoing = self.plugins.borrow_mut().take_outgoing();
+        for message in outgoing {
+            if let Ok(text) = serde_json::to_string(&message) {
+                if let Err(e) = ws.send_with_str(&text) {
+                    web_sys::console::error_1(&e);
+                }
+            }
+        }
+    }
+
+    /// Detaches the current socket and closes it without reconnecting.
+    fn close_current(&self) {
+        if let Some(old) = self.ws.borrow_mut().take() {
+            old.set_onmessage(None);
+            old.set_onclose(None);
+            let _ = old.close();
+        }
+    }
+}
+
+/// Room joined until the user picks another.
+pub const DEFAULT_ROOM: &str = "transaction-room";
+
 pub struct WebSocketConnection {
-    ws: Option<WebSocket>,
+    link: Rc<Link>,
     endpoint_id: String,
-    message_handler: Option<Box<dyn Fn(SignalingMessage)>>,
 }
 
 impl WebSocketConnection {
     pub fn new() -> Self {
         Self {
-            ws: None,
+            link: Rc::new(Link {
+                ws: RefCell::new(None),
+                url: RefCell::new(String::new()),
+                join_message: RefCell::new(serde_json::Value::Null),
+                message_handler: RefCell::new(None),
+                on_reconnect: RefCell::new(None),
+                policy: Cell::new(ReconnectPolicy::default()),
+                attempt: Cell::new(0),
+                batch_window_ms: Cell::new(None),
+                room_encrypted: Cell::new(false),
+                open_encrypted: Cell::new(false),
+                room_id: RefCell::new(DEFAULT_ROOM.to_string()),
+                negotiated: RefCell::new(Capabilities::default()),
+                peer_capabilities: RefCell::new(HashMap::new()),
+                plugins: RefCell::new(Plugins::new()),
+            }),
             endpoint_id: String::new(),
-            message_handler: None,
         }
     }
 
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.link.policy.set(policy);
+    }
+
+    /// Called as the connection drops and recovers, so the UI can show
+    /// progress instead of silently going quiet.
+    pub fn on_reconnect(&mut self, callback: Box<dyn Fn(ReconnectEvent)>) {
+        *self.link.on_reconnect.borrow_mut() = Some(Rc::from(callback));
+    }
+
+    /// Adds `plugin` to the hooks run on every message; see
+    /// `tx_core::plugin`.
+    pub fn register_plugin(&mut self, plugin: impl Plugin + 'static) {
+        self.link.plugins.borrow_mut().register(plugin);
+    }
+
     pub fn connect(
         &mut self,
         endpoint_id: &str,
         message_handler: Box<dyn Fn(SignalingMessage)>,
     ) -> Result<(), JsValue> {
         self.endpoint_id = endpoint_id.to_string();
-        self.message_handler = Some(message_handler);
-
-        let signaling_url = std::env::var("SIGNALING_SERVER")
-            .unwrap_or_else(|_| "ws://localhost:8080".to_string());
-
-        web_sys::console::log_1(&format!("Connecting to {}", signaling_url).into());
-
-        let ws = WebSocket::new(&signaling_url)?;
-        
-        // Set up message handler
-        let message_handler_clone = self.message_handler.as_ref().unwrap();
-        let onmessage_callback = {
-            let handler = unsafe {
-                std::mem::transmute::<&dyn Fn(SignalingMessage), &'static dyn Fn(SignalingMessage)>(
-                    message_handler_clone.as_ref()
-                )
-            };
-            
-            Closure::wrap(Box::new(move |e: MessageEvent| {
-                if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
-                    let message_str: String = txt.into();
-                    web_sys::console::log_1(&format!("Received: {}", message_str).into());
-                    
-                    if let Ok(msg) = serde_json::from_str::<SignalingMessage>(&message_str) {
-                        handler(msg);
-                    } else {
-                        web_sys::console::error_1(&"Failed to parse message".into());
-                    }
-                }
-            }) as Box<dyn FnMut(_)>)
-        };
-        
-        ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
-        onmessage_callback.forget();
-
-        // Set up open handler
-        let endpoint_id_clone = self.endpoint_id.clone();
-        let onopen_callback = Closure::wrap(Box::new(move |_| {
-            web_sys::console::log_1(&"WebSocket connected".into());
-            
-            // Join the transaction room
-            let join_message = SignalingMessage {
-                message_type: "join".to_string(),
-                room_id: Some("transaction-room".to_string()),
-                peer_id: Some(endpoint_id_clone.clone()),
-                target_peer: None,
-                transaction: None,
-                peers: None,
-            };
+        *self.link.message_handler.borrow_mut() = Some(Rc::from(message_handler));
 
-            if let Ok(msg_str) = serde_json::to_string(&join_message) {
-                // We need access to ws here, but it's moved
-                web_sys::console::log_1(&format!("Would send: {}", msg_str).into());
-            }
-        }) as Box<dyn FnMut(_)>);
-        
-        ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
-        
-        // Store websocket reference for sending join message
-        let ws_for_join = ws.clone();
-        let endpoint_id_for_join = self.endpoint_id.clone();
-        
-        // Set timeout to send join message after connection opens
-        let join_callback = Closure::wrap(Box::new(move || {
-            let join_message = serde_json::json!({
-                "type": "join",
-                "roomId": "transaction-room",
-                "peerId": endpoint_id_for_join
-            });
-            
-            if let Ok(msg_str) = serde_json::to_string(&join_message) {
-                let _ = ws_for_join.send_with_str(&msg_str);
-                web_sys::console::log_1(&"Sent join message".into());
-            }
-        }) as Box<dyn FnMut()>);
-        
-        web_sys::window()
-            .unwrap()
-            .set_timeout_with_callback_and_timeout_and_arguments_0(
-                join_callback.as_ref().unchecked_ref(),
-                100,
-            )?;
-        join_callback.forget();
-        onopen_callback.forget();
-
-        // Set up close handler
-        let onclose_callback = Closure::wrap(Box::new(move |e: CloseEvent| {
-            web_sys::console::log_1(&format!("WebSocket closed: {}", e.code()).into());
-        }) as Box<dyn FnMut(_)>);
-        
-        ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
-        onclose_callback.forget();
-
-        // Set up error handler
-        let onerror_callback = Closure::wrap(Box::new(move |e: ErrorEvent| {
-            web_sys::console::error_1(&format!("WebSocket error: {:?}", e).into());
-        }) as Box<dyn FnMut(_)>);
-        
-        ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
-        onerror_callback.forget();
-
-        self.ws = Some(ws);
+        let signaling_url = crate::config::get("signalingServer")
+            .or_else(|| std::env::var("SIGNALING_SERVER").ok())
+            .unwrap_or_else(|| "ws://localhost:8080".to_string());
+
+        let join_message = self.join_message();
+        *self.link.join_message.borrow_mut() = join_message.clone();
+        open(&self.link, &signaling_url, join_message)
+    }
+
+    fn join_message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "join",
+            "roomId": self.room_id(),
+            "peerId": self.endpoint_id,
+            "encrypted": self.link.open_encrypted.get()
+        })
+    }
+
+    /// Whether rooms we create from here on are opened end-to-end
+    /// encrypted, for `GroupEncryption`. Rooms others opened stay as they
+    /// are.
+    pub fn set_open_encrypted(&mut self, encrypted: bool) {
+        self.link.open_encrypted.set(encrypted);
+    }
+
+    /// Whether the current room relays only sealed broadcasts.
+    pub fn room_encrypted(&self) -> bool {
+        self.link.room_encrypted.get()
+    }
+
+    pub fn room_id(&self) -> String {
+        self.link.room_id.borrow().clone()
+    }
+
+    /// The room `connect` will join, for embedders that pick it up front;
+    /// once connected, use `switch_room`.
+    pub fn set_room(&mut self, room_id: &str) {
+        *self.link.room_id.borrow_mut() = room_id.to_string();
+    }
+
+    /// Moves to `room_id`, creating it if nobody is in it yet. The current
+    /// socket is closed, which takes us out of the old room and drops its
+    /// relays, and a new one joins the new room; broadcast sequence
+    /// numbers and the batch window start over with it.
+    pub fn switch_room(&mut self, room_id: &str) -> Result<(), JsValue> {
+        if room_id == self.room_id() {
+            return Ok(());
+        }
+        self.link.close_current();
+        self.link.batch_window_ms.set(None);
+        self.link.room_encrypted.set(false);
+        self.link.attempt.set(0);
+        *self.link.room_id.borrow_mut() = room_id.to_string();
+
+        let url = self.link.url.borrow().clone();
+        let join_message = self.join_message();
+        *self.link.join_message.borrow_mut() = join_message.clone();
+        open(&self.link, &url, join_message)
+    }
+
+    /// Asks the server for its open rooms; the answer arrives as a `rooms`
+    /// message.
+    pub fn list_rooms(&self) -> Result<(), JsValue> {
+        if let Some(ws) = self.socket() {
+            ws.send_with_str(&serde_json::json!({ "type": "list-rooms" }).to_string())?;
+        }
         Ok(())
     }
 
+    /// Follows a `redirect` from a draining signaling server: reconnects to
+    /// `url` and resumes the session there instead of joining afresh, so
+    /// room membership and queued messages carry over.
+    pub fn resume(&mut self, url: &str, resume_token: &str) -> Result<(), JsValue> {
+        self.link.close_current();
+
+        let resume_message = serde_json::json!({
+            "type": "resume",
+            "resumeToken": resume_token,
+            "roomId": self.room_id(),
+            "peerId": self.endpoint_id,
+            "encrypted": self.link.open_encrypted.get()
+        });
+        open(&self.link, url, resume_message)
+    }
+
+    /// Whether the signaling server records this room's transactions with
+    /// the gateway itself, in batches, so endpoints shouldn't submit them.
+    /// Only trusted from a server that negotiated batching.
+    pub fn room_batches(&self) -> bool {
+        self.link.batch_window_ms.get().is_some() && self.link.negotiated.borrow().supports(Capability::Batching)
+    }
+
+    /// Whether `peer_id` advertised `capability` when it joined; peers
+    /// that didn't say hello support none.
+    pub fn peer_supports(&self, peer_id: &str, capability: Capability) -> bool {
+        self.link
+            .peer_capabilities
+            .borrow()
+            .get(peer_id)
+            .is_some_and(|capabilities| capabilities.supports(capability))
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.socket().map_or(false, |ws| ws.ready_state() == WebSocket::OPEN)
+    }
+
+    fn socket(&self) -> Option<WebSocket> {
+        self.link.ws.borrow().clone()
+    }
+
+    /// Runs the plugins over `message` and sends what they leave, after
+    /// anything they queued; an error if one of them dropped it.
+    fn send_message(&self, ws: &WebSocket, mut message: SignalingMessage) -> Result<(), JsValue> {
+        let sent = self.link.plugins.borrow_mut().before_send(&mut message);
+        // A rotated key has to reach the peer before what's sealed under it
+        self.link.send_plugin_messages(ws);
+        sent.map_err(|dropped| JsValue::from_str(&dropped.to_string()))?;
+
+        let message_str = serde_json::to_string(&message)
+            .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))?;
+        ws.send_with_str(&message_str)
+    }
+
     pub fn send_transaction(&mut self, tx: &Transaction) -> Result<(), JsValue> {
-        if let Some(ws) = &self.ws {
+        if let Some(ws) = self.socket() {
             let message = SignalingMessage {
-                message_type: "transaction".to_string(),
-                room_id: Some("transaction-room".to_string()),
+                room_id: Some(self.room_id()),
                 peer_id: Some(self.endpoint_id.clone()),
-                target_peer: None,
                 transaction: Some(tx.clone()),
-                peers: None,
+                ..SignalingMessage::new("transaction")
             };
 
-            let message_str = serde_json::to_string(&message)
-                .map_err(|e| JsValue::from_str(&format!("Serialization error: {}", e)))?;
-            
-            ws.send_with_str(&message_str)?;
+            self.send_message(&ws, message)?;
             web_sys::console::log_1(&format!("Sent transaction: {}", tx.id).into());
         }
         Ok(())
     }
+
+    /// Sends a `channel-open`, `channel-update` or `channel-close` to the
+    /// other side of the channel, relayed point-to-point.
+    pub fn send_channel(&mut self, message_type: &str, update: &ChannelUpdate) -> Result<(), JsValue> {
+        if let Some(ws) = self.socket() {
+            let peer = if update.payer == self.endpoint_id { &update.payee } else { &update.payer };
+            let message = SignalingMessage {
+                room_id: Some(self.room_id()),
+                peer_id: Some(self.endpoint_id.clone()),
+                target_peer: Some(peer.clone()),
+                channel: Some(update.clone()),
+                ..SignalingMessage::new(message_type)
+            };
+
+            self.send_message(&ws, message)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a swap offer, acceptance or decline to the other party.
+    pub fn send_swap(&mut self, message_type: &str, commitment: &SwapCommitment) -> Result<(), JsValue> {
+        if let Some(ws) = self.socket() {
+            let terms = &commitment.terms;
+            let peer = if terms.maker() == self.endpoint_id { terms.taker() } else { terms.maker() };
+            let message = SignalingMessage {
+                room_id: Some(self.room_id()),
+                peer_id: Some(self.endpoint_id.clone()),
+                target_peer: Some(peer.to_string()),
+                swap: Some(commitment.clone()),
+                ..SignalingMessage::new(message_type)
+            };
+
+            self.send_message(&ws, message)?;
+        }
+        Ok(())
+    }
+
+    /// Asks the rest of the room for quotes.
+    pub fn send_rfq(&mut self, request: &QuoteRequest) -> Result<(), JsValue> {
+        if let Some(ws) = self.socket() {
+            let message = SignalingMessage {
+                room_id: Some(self.room_id()),
+                peer_id: Some(self.endpoint_id.clone()),
+                rfq: Some(request.clone()),
+                ..SignalingMessage::new("quote-request")
+            };
+
+            self.send_message(&ws, message)?;
+        }
+        Ok(())
+    }
+
+    /// Answers a request for quotes, relayed to the requester only.
+    pub fn send_quote(&mut self, quote: &Quote) -> Result<(), JsValue> {
+        if let Some(ws) = self.socket() {
+            let message = SignalingMessage {
+                room_id: Some(self.room_id()),
+                peer_id: Some(self.endpoint_id.clone()),
+                target_peer: Some(quote.request.requester.clone()),
+                quote: Some(quote.clone()),
+                ..SignalingMessage::new("quote")
+            };
+
+            self.send_message(&ws, message)?;
+        }
+        Ok(())
+    }
+
+    /// Free text to `peer`, relayed point-to-point.
+    pub fn send_memo(&mut self, peer: &str, text: &str) -> Result<(), JsValue> {
+        if let Some(ws) = self.socket() {
+            let message = SignalingMessage {
+                room_id: Some(self.room_id()),
+                peer_id: Some(self.endpoint_id.clone()),
+                target_peer: Some(peer.to_string()),
+                memo: Some(text.to_string()),
+                ..SignalingMessage::new("memo")
+            };
+
+            self.send_message(&ws, message)?;
+        }
+        Ok(())
+    }
+
+    /// Tells the sender of `tx` it was refused, relayed point-to-point.
+    pub fn send_rejection(&mut self, tx: &Transaction, signature: String, public_key: String) -> Result<(), JsValue> {
+        if let Some(ws) = self.socket() {
+            let message = SignalingMessage {
+                room_id: Some(self.room_id()),
+                peer_id: Some(self.endpoint_id.clone()),
+                target_peer: Some(tx.from_endpoint.clone()),
+                transaction: Some(tx.clone()),
+                signature: Some(signature),
+                public_key: Some(public_key),
+                ..SignalingMessage::new("transaction-rejected")
+            };
+
+            self.send_message(&ws, message)?;
+            web_sys::console::log_1(&format!("Rejected transaction: {}", tx.id).into());
+        }
+        Ok(())
+    }
+
+    /// Tells the sender of `tx` whether we applied it, relayed
+    /// point-to-point. Skipped for senders that don't understand acks.
+    pub fn send_ack(
+        &mut self,
+        tx: &Transaction,
+        result: AckResult,
+        reason: Option<String>,
+        signature: String,
+        public_key: String,
+    ) -> Result<(), JsValue> {
+        if !self.peer_supports(&tx.from_endpoint, Capability::Acks) {
+            web_sys::console::log_1(&format!("Not acking {}: {} doesn't support acks", tx.id, tx.from_endpoint).into());
+            return Ok(());
+        }
+        if let Some(ws) = self.socket() {
+            let message = SignalingMessage {
+                room_id: Some(self.room_id()),
+                peer_id: Some(self.endpoint_id.clone()),
+                target_peer: Some(tx.from_endpoint.clone()),
+                transaction: Some(tx.clone()),
+                result: Some(result),
+                reason,
+                signature: Some(signature),
+                public_key: Some(public_key),
+                ..SignalingMessage::new("transaction-ack")
+            };
+
+            self.send_message(&ws, message)?;
+            web_sys::console::log_1(&format!("Acked transaction {}: {}", tx.id, result.as_str()).into());
+        }
+        Ok(())
+    }
+}
+
+/// Capabilities offered in our `hello`.
+const FEATURES: [Capability; 3] = [Capability::Acks, Capability::Batching, Capability::GroupEncryption];
+
+/// Opens a socket to `url` and, once it's open, sends our `hello` and then
+/// `first_message`. Later reconnects go back to the same `url` with a plain
+/// join; resumption tokens are single use.
+fn open(link: &Rc<Link>, url: &str, first_message: serde_json::Value) -> Result<(), JsValue> {
+    web_sys::console::log_1(&format!("Connecting to {}", url).into());
+
+    let ws = WebSocket::new(url)?;
+    *link.url.borrow_mut() = url.to_string();
+
+    // Sequence numbers and negotiated capabilities are per server instance
+    *link.negotiated.borrow_mut() = Capabilities::default();
+    link.peer_capabilities.borrow_mut().clear();
+    let cursor = Rc::new(BroadcastCursor::default());
+    let ws_for_resync = ws.clone();
+
+    // Set up message handler
+    let handler = link.message_handler.borrow().clone();
+    let link_for_message = link.clone();
+    let onmessage_callback = Closure::wrap(Box::new(move |e: MessageEvent| {
+        if let Ok(txt) = e.data().dyn_into::<js_sys::JsString>() {
+            let message_str: String = txt.into();
+            web_sys::console::log_1(&format!("Received: {}", message_str).into());
+
+            if let Ok(mut msg) = serde_json::from_str::<SignalingMessage>(&message_str) {
+                link_for_message.track_capabilities(&msg);
+                if msg.message_type == "room-joined" {
+                    link_for_message.batch_window_ms.set(msg.batch_window_ms);
+                    link_for_message.room_encrypted.set(msg.encrypted == Some(true));
+                }
+                if let Some(handler) = &handler {
+                    if cursor.admit(&msg, &ws_for_resync) {
+                        // Released before the handler, which may send
+                        let received = link_for_message.plugins.borrow_mut().receive(&mut msg);
+                        link_for_message.send_plugin_messages(&ws_for_resync);
+                        match received {
+                            Ok(()) => handler(msg),
+                            Err(dropped) => web_sys::console::log_1(&dropped.to_string().into()),
+                        }
+                    }
+                }
+            } else {
+                web_sys::console::error_1(&"Failed to parse message".into());
+            }
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    ws.set_onmessage(Some(onmessage_callback.as_ref().unchecked_ref()));
+    onmessage_callback.forget();
+
+    // Join (or resume) once the socket is open
+    let ws_for_join = ws.clone();
+    let link_for_open = link.clone();
+    let onopen_callback = Closure::wrap(Box::new(move |_: JsValue| {
+        web_sys::console::log_1(&"WebSocket connected".into());
+        // Servers from before the handshake ignore it. Whether the socket
+        // is compressed is only known once it's open.
+        let mut features = FEATURES.to_vec();
+        if ws_for_join.extensions().contains("permessage-deflate") {
+            features.push(Capability::PermessageDeflate);
+        }
+        if let Ok(hello) = serde_json::to_string(&SignalingMessage::hello(&features)) {
+            let _ = ws_for_join.send_with_str(&hello);
+        }
+        let _ = ws_for_join.send_with_str(&first_message.to_string());
+        web_sys::console::log_1(&format!("Sent {} message", first_message["type"]).into());
+
+        let attempts = link_for_open.attempt.replace(0);
+        if attempts > 0 {
+            link_for_open.notify(ReconnectEvent::Reconnected { attempts });
+        }
+    }) as Box<dyn FnMut(_)>);
+
+    ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
+    onopen_callback.forget();
+
+    // Unexpected closes (server restart, network loss, a failed attempt)
+    // schedule the next reconnect; sockets we close ourselves are detached
+    // first and never get here
+    let link_for_close = link.clone();
+    let onclose_callback = Closure::wrap(Box::new(move |e: CloseEvent| {
+        web_sys::console::log_1(&format!("WebSocket closed: {}", e.code()).into());
+        link_for_close.ws.borrow_mut().take();
+        link_for_close.schedule_reconnect();
+    }) as Box<dyn FnMut(_)>);
+
+    ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+    onclose_callback.forget();
+
+    // Set up error handler
+    let onerror_callback = Closure::wrap(Box::new(move |e: ErrorEvent| {
+        web_sys::console::error_1(&format!("WebSocket error: {:?}", e).into());
+    }) as Box<dyn FnMut(_)>);
+
+    ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+    onerror_callback.forget();
+
+    *link.ws.borrow_mut() = Some(ws);
+    Ok(())
 }
@@ -0,0 +1,32 @@
+// Web Worker hosting the crypto module off the UI thread.
+// Loads the same wasm package as the app and answers { id, op, payload }
+// requests with { id, ok, result } or { id, ok: false, error }. Sign
+// requests also carry the endpoint's secret `key`, since workers have no
+// localStorage.
+import init, { worker_sign, worker_verify } from './pkg/tx_endpoint_v1.js';
+
+const ready = init();
+
+self.onmessage = async (event) => {
+    const { id, op, payload, key } = event.data;
+
+    try {
+        await ready;
+
+        let result;
+        switch (op) {
+            case 'sign':
+                result = worker_sign(payload, key);
+                break;
+            case 'verify':
+                result = worker_verify(payload);
+                break;
+            default:
+                throw new Error(`Unknown worker op: ${op}`);
+        }
+
+        self.postMessage({ id, ok: true, result });
+    } catch (error) {
+        self.postMessage({ id, ok: false, error: String(error) });
+    }
+};